@@ -1,9 +1,15 @@
-cargo build
+# the commit reported by `GET /version`
+SUNNY_GIT_HASH=$(git rev-parse --short HEAD) cargo build
 rm -rf local_build
 mkdir -p local_build
 cp target/debug/sunny* local_build/
 
 cd frontend/sunny-ui
 npx vite build --base=/ .
+# pre-compress the frontend for [assets] precompressed = true
+find dist -type f \( -name '*.js' -o -name '*.css' -o -name '*.html' -o -name '*.svg' \) | while read -r f; do
+    command -v gzip > /dev/null && gzip -k -9 -f "$f"
+    command -v brotli > /dev/null && brotli -k -f "$f"
+done
 cp -r dist/* ../../local_build/
 cp dist/index.html ../../local_build
\ No newline at end of file
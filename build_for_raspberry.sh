@@ -8,11 +8,16 @@
 #     cargo build --release --target=armv7-unknown-linux-gnueabihf"
 
 docker build -f Dockerfile-build -t sunny-build-raspberry . && \
-docker run --user "$(id -u)":"$(id -g)" -v "$PWD":/usr/src/sunny -w /usr/src/sunny sunny-build-raspberry cargo build --release --target=armv7-unknown-linux-gnueabihf
+docker run --user "$(id -u)":"$(id -g)" -e SUNNY_GIT_HASH="$(git rev-parse --short HEAD)" -v "$PWD":/usr/src/sunny -w /usr/src/sunny sunny-build-raspberry cargo build --release --target=armv7-unknown-linux-gnueabihf
 
 # build frontend
 cd frontend/sunny-ui
 npx vite build --base=/ .
+# pre-compress the frontend for [assets] precompressed = true
+find dist -type f \( -name '*.js' -o -name '*.css' -o -name '*.html' -o -name '*.svg' \) | while read -r f; do
+    command -v gzip > /dev/null && gzip -k -9 -f "$f"
+    command -v brotli > /dev/null && brotli -k -f "$f"
+done
 
 cd ../../
 
@@ -36,6 +36,10 @@ const forceLightTheme = createTheme({
 // Create the query client
 const queryClient = new QueryClient()
 
+// base URL of the sunny API; defaults to the origin the frontend is served from and the
+// default API prefix
+const apiBase = (import.meta.env.VITE_API_BASE ?? "") + (import.meta.env.VITE_API_PREFIX ?? "/api/v1");
+
 // color settings
 const colorPV = "#F4840B";
 const colorFromGrid = "#FD5F3D";
@@ -51,7 +55,7 @@ function App() {
         <div>
           <img src={sunnyLogo} className="logo" alt="Sunny logo" height="200"/>
         </div>
-        <h2>Welcome to Sunny!</h2>
+        <Welcome />
 
       <MainBody />
 
@@ -65,6 +69,22 @@ function App() {
 export default App
 
 
+function Welcome() {
+  const query = useQuery({ queryKey: ['frontendSettings'], queryFn: fetchFrontendSettings })
+  let siteName = query.isSuccess ? query.data.site_name : "Sunny";
+
+  return (
+    <h2>Welcome to {siteName}!</h2>
+  )
+}
+
+function fetchFrontendSettings() {
+  let url = `${apiBase}/config/frontend`
+  return fetch(url)
+    .then((response) => response.json())
+}
+
+
 function MainBody() {
   let endOfToday = dayjs().endOf('day');
   let startOfToday = dayjs().startOf('day');
@@ -92,7 +112,7 @@ function MainBody() {
   // query was successful
   let data = query.data;
   let values = data.values;
-  let currentValues = values[values.length - 1][1];
+  let currentValues = values.length > 0 ? values[values.length - 1][1] : null;
   let energyValues = data.energy_kwh;
   let maxes = data.maxes;
 
@@ -158,6 +178,10 @@ function MainBody() {
     </div>
     </LocalizationProvider>
 
+    { currentValues === null ? (
+    <div>No values in the selected range.</div>
+    ) : (
+    <>
     <PowerValueChart
       values={ values }
     />
@@ -173,6 +197,8 @@ function MainBody() {
         currentValues={ maxes }
       />
     </Stack>
+    </>
+    ) }
 
   </Stack>
   );
@@ -180,9 +206,15 @@ function MainBody() {
 }
 
 function fetchDataAndStats(timeRange: { start: number, end: number }) {
-  let url = `http://192.168.178.40:3000/values-with-stats/${timeRange.start}/${timeRange.end}`
+  let url = `${apiBase}/values-with-stats/${timeRange.start}/${timeRange.end}`
   return fetch(url)
-    .then((response) => response.json())
+    .then((response) => {
+      // depending on the server's config, ranges without values may come without a body
+      if (response.status === 204 || response.status === 404) {
+        return { values: [], average: null, maxes: null, energy_kwh: null }
+      }
+      return response.json()
+    })
     .then((jsonResponse) => {
       return jsonResponse
     })
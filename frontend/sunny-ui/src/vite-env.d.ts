@@ -1 +1,10 @@
 /// <reference types="vite/client" />
+
+interface ImportMetaEnv {
+  readonly VITE_API_BASE?: string
+  readonly VITE_API_PREFIX?: string
+}
+
+interface ImportMeta {
+  readonly env: ImportMetaEnv
+}
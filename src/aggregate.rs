@@ -0,0 +1,111 @@
+use chrono_tz::Tz;
+use serde::Deserialize;
+use sunny_db::statistics::{BucketAggregator, BucketInterval, Bucketize};
+use sunny_db::timeseries::TimeSeriesView;
+
+use crate::PowerValues;
+
+/// Buckets of `GET /aggregate/:bucket/:start_time/:end_time`, in the configured timezone
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AggregateBucket {
+    Hour,
+    Day,
+    Week,
+    Month,
+}
+
+impl From<AggregateBucket> for BucketInterval {
+    fn from(bucket: AggregateBucket) -> Self {
+        match bucket {
+            AggregateBucket::Hour => BucketInterval::Hour,
+            AggregateBucket::Day => BucketInterval::Day,
+            AggregateBucket::Week => BucketInterval::Week,
+            AggregateBucket::Month => BucketInterval::Month,
+        }
+    }
+}
+
+/// What's computed per bucket, e.g. `?aggregate=max`
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Aggregate {
+    /// in kWh
+    #[default]
+    Energy,
+    Average,
+    Max,
+}
+
+#[derive(Deserialize)]
+pub struct AggregateParams {
+    #[serde(default)]
+    pub aggregate: Aggregate,
+}
+
+/// the aggregate of the values per bucket as `(bucket start in ms, values)` pairs
+pub fn aggregate(
+    series: TimeSeriesView<'_, PowerValues>,
+    bucket: AggregateBucket,
+    aggregate: Aggregate,
+    timezone: Tz,
+) -> Vec<(u64, PowerValues)> {
+    let resolution = series.get_resolution();
+    let aggregator = match aggregate {
+        Aggregate::Energy => BucketAggregator::Integral,
+        Aggregate::Average => BucketAggregator::Average,
+        Aggregate::Max => BucketAggregator::Max,
+    };
+    // the integral comes out in W times the timestamp unit
+    let to_kwh = 1e-3 / 3600.0 / resolution.per_second() as f64;
+    series
+        .bucketize(bucket.into(), &timezone, aggregator)
+        .into_iter()
+        .map(|bucket| {
+            let value = match aggregate {
+                Aggregate::Energy => bucket.value * to_kwh,
+                _ => bucket.value,
+            };
+            (resolution.to_millis(bucket.start), value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sunny_db::timeseries::TimeSeries;
+
+    #[test]
+    fn test_aggregate() {
+        let berlin: Tz = "Europe/Berlin".parse().unwrap();
+        let power = |power_pv| PowerValues {
+            power_pv,
+            power_to_grid: 0.0,
+            power_from_grid: 0.0,
+            power_used: 0.0,
+        };
+        let hour = 3_600_000;
+        // 2024-10-27 00:00 CEST to 04:00 CET, when the clocks go back from 03:00 to 02:00
+        let start = 1729980000000;
+        let mut series = TimeSeries::<PowerValues>::new(10);
+        for i in 0..6 {
+            series.insert_value_at_time(start + i * hour, power(1000.0));
+        }
+
+        // the repeated hour is a bucket of its own
+        let hourly = aggregate(
+            series.view(),
+            AggregateBucket::Hour,
+            Aggregate::Energy,
+            berlin,
+        );
+        assert_eq!(hourly.len(), 5);
+        assert!(hourly.iter().all(|(_, energy)| energy.power_pv == 1.0));
+        assert_eq!(hourly[3].0 - hourly[2].0, hour);
+
+        // all of them fall into the day starting at midnight CEST
+        let daily = aggregate(series.view(), AggregateBucket::Day, Aggregate::Max, berlin);
+        assert_eq!(daily, vec![(start, power(1000.0))]);
+    }
+}
@@ -0,0 +1,62 @@
+use axum::{
+    extract::Request,
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+
+/// Assets whose name contains a hash of their content never change, so browsers can cache
+/// them for good
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+/// Everything else (most importantly index.html, which references the hashed assets) has
+/// to be revalidated on every load; thanks to Last-Modified that's cheap
+const REVALIDATE: &str = "no-cache";
+
+/// whether the file name contains a content hash as added by vite, e.g. `index-BlLwO1xV.js`
+/// or `index.3f2a1b9c.js`
+pub fn is_content_hashed(path: &str) -> bool {
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    let Some((stem, _extension)) = file_name.rsplit_once('.') else {
+        return false;
+    };
+    let Some((_name, hash)) = stem.rsplit_once(['-', '.']) else {
+        return false;
+    };
+    // hashes have at least 8 characters and, unlike ordinary words, digits or capitals
+    hash.len() >= 8
+        && hash.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && hash
+            .chars()
+            .any(|c| c.is_ascii_digit() || c.is_ascii_uppercase())
+}
+
+/// middleware setting the Cache-Control header of the frontend's files
+pub async fn cache_headers(request: Request, next: Next) -> Response {
+    let immutable = is_content_hashed(request.uri().path());
+    let mut response = next.run(request).await;
+    if response.status().is_success() || response.status().is_redirection() {
+        let cache_control = if immutable { IMMUTABLE } else { REVALIDATE };
+        response.headers_mut().insert(
+            header::CACHE_CONTROL,
+            HeaderValue::from_static(cache_control),
+        );
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_content_hashed() {
+        assert!(is_content_hashed("/assets/index-BlLwO1xV.js"));
+        assert!(is_content_hashed("/assets/index-DiwrgTda.css"));
+        assert!(is_content_hashed("/assets/sunny.3f2a1b9c.svg"));
+        assert!(!is_content_hashed("/"));
+        assert!(!is_content_hashed("/index.html"));
+        assert!(!is_content_hashed("/assets/sunny.svg"));
+        assert!(!is_content_hashed("/assets/background-gradient.png"));
+        assert!(!is_content_hashed("/assets/index-BlLwO1xV"));
+    }
+}
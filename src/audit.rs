@@ -0,0 +1,183 @@
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// How many entries `GET /admin/audit` returns unless asked for something else
+pub const DEFAULT_LIMIT: usize = 100;
+
+/// Bytes read at a time from the end of the log, so only its newest entries are read
+const TAIL_BLOCK_BYTES: u64 = 64 * 1024;
+
+/// A change made to the data, as recorded in the audit log
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    /// in ms
+    pub time: u64,
+    /// who made the change, `cli:` and the user
+    pub actor: String,
+    /// e.g. `import-influx`
+    pub action: String,
+    pub parameters: serde_json::Value,
+    /// "ok", or what went wrong
+    pub outcome: String,
+}
+
+impl AuditEntry {
+    pub fn new(
+        actor: String,
+        action: String,
+        parameters: serde_json::Value,
+        outcome: String,
+    ) -> Self {
+        AuditEntry {
+            time: chrono::Utc::now().timestamp_millis() as u64,
+            actor,
+            action,
+            parameters,
+            outcome,
+        }
+    }
+}
+
+/// Append-only file of JSON lines, one per change made to the data
+#[derive(Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+    /// so concurrent entries don't interleave
+    lock: Arc<Mutex<()>>,
+}
+
+impl AuditLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        AuditLog {
+            path: path.into(),
+            lock: Arc::default(),
+        }
+    }
+
+    /// the audit log of `<sunny-home>` given the database directory `<sunny-home>/db`
+    pub fn next_to(data_dir: &str) -> Self {
+        let home = Path::new(data_dir).parent().unwrap_or(Path::new(""));
+        AuditLog::new(home.join("audit.log"))
+    }
+
+    pub fn record(&self, entry: &AuditEntry) -> anyhow::Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        let _guard = self.lock.lock().unwrap();
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .with_context(|| format!("Couldn't write to {}", self.path.display()))
+    }
+
+    /// the newest `limit` entries, oldest first; only the end of the log is read
+    pub fn read(&self, limit: usize) -> anyhow::Result<Vec<AuditEntry>> {
+        let tail = match File::open(&self.path).and_then(|mut file| tail(&mut file, limit)) {
+            Ok(tail) => tail,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e).with_context(|| format!("Couldn't read {}", self.path.display()))
+            }
+        };
+        // the first line may be cut off, which doesn't parse
+        let entries: Vec<AuditEntry> = String::from_utf8_lossy(&tail)
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect();
+        let skipped = entries.len().saturating_sub(limit);
+        Ok(entries.into_iter().skip(skipped).collect())
+    }
+
+    /// records an import run from the command line
+    pub fn record_import<T>(
+        &self,
+        action: &str,
+        parameters: serde_json::Value,
+        result: &anyhow::Result<T>,
+    ) {
+        let user = std::env::var("USER").unwrap_or_default();
+        let outcome = match result {
+            Ok(_) => String::from("ok"),
+            Err(e) => format!("{:#}", e),
+        };
+        let entry = AuditEntry::new(
+            format!("cli:{}", user),
+            action.to_owned(),
+            parameters,
+            outcome,
+        );
+        if let Err(e) = self.record(&entry) {
+            println!("Error while writing the audit log: {:#}", e);
+        }
+    }
+}
+
+/// the end of the file holding at least its last `lines` complete lines, read backwards a block
+/// at a time; the whole file if it has fewer
+fn tail(file: &mut File, lines: usize) -> std::io::Result<Vec<u8>> {
+    let mut start = file.seek(SeekFrom::End(0))?;
+    let mut tail = Vec::new();
+    let mut newlines = 0;
+    // every line ends with a newline, so one more is needed for the first line to be complete
+    while start > 0 && newlines <= lines {
+        let block_start = start.saturating_sub(TAIL_BLOCK_BYTES);
+        let mut block = vec![0; (start - block_start) as usize];
+        file.seek(SeekFrom::Start(block_start))?;
+        file.read_exact(&mut block)?;
+        newlines += block.iter().filter(|byte| **byte == b'\n').count();
+        block.extend_from_slice(&tail);
+        tail = block;
+        start = block_start;
+    }
+    Ok(tail)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audit_log() {
+        let dir = std::env::temp_dir().join(format!("sunny-audit-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("db")).unwrap();
+        let audit_log = AuditLog::next_to(dir.join("db").to_str().unwrap());
+        assert_eq!(audit_log.read(10).unwrap(), vec![]);
+
+        for i in 0..3 {
+            let parameters = serde_json::json!({ "file": format!("export-{}.csv", i) });
+            audit_log.record_import("import-fronius", parameters, &anyhow::Ok(i));
+        }
+        audit_log.record_import::<()>(
+            "import-influx",
+            serde_json::Value::Null,
+            &Err(anyhow::anyhow!("Unknown precision")),
+        );
+
+        let entries = audit_log.read(2).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].parameters["file"], "export-2.csv");
+        assert_eq!(entries[0].outcome, "ok");
+        assert!(entries[0].actor.starts_with("cli:"));
+        assert_eq!(entries[1].action, "import-influx");
+        assert_eq!(entries[1].outcome, "Unknown precision");
+        assert_eq!(audit_log.read(10).unwrap().len(), 4);
+        assert!(dir.join("audit.log").exists());
+
+        // more entries than are read from the end at once
+        for i in 0..2000 {
+            let parameters = serde_json::json!({ "file": format!("export-{}.csv", i) });
+            audit_log.record_import("import-fronius", parameters, &anyhow::Ok(i));
+        }
+        let newest = audit_log.read(3).unwrap();
+        assert_eq!(newest.len(), 3);
+        assert_eq!(newest[2].parameters["file"], "export-1999.csv");
+        assert_eq!(audit_log.read(usize::MAX).unwrap().len(), 2004);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,463 @@
+use anyhow::Context;
+use axum::extract::{Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use openssl::bn::BigNum;
+use openssl::ec::{EcGroup, EcKey};
+use openssl::ecdsa::EcdsaSig;
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{Id, PKey, Public};
+use openssl::rsa::Rsa;
+use openssl::sign::Verifier;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::config::{AuthMode, AuthSettings};
+
+/// Clock skew in seconds tolerated when checking when tokens expire or become valid
+const LEEWAY_SECS: i64 = 60;
+/// How soon fetching the keys is retried after it failed
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How requests to the data routes are authorized, see `[auth]`
+pub enum Authenticator {
+    None,
+    /// any of the keys as bearer token
+    ApiKeys(Vec<String>),
+    /// JWTs issued by an identity provider, e.g. Keycloak or Authelia, as bearer token
+    Jwt(JwtValidator),
+}
+
+/// Who made a request: `api-key:` and the start of the key's SHA-256 hash, `jwt:` and the
+/// token's subject, or `anonymous`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Actor(pub String);
+
+impl Actor {
+    pub fn anonymous() -> Self {
+        Actor(String::from("anonymous"))
+    }
+}
+
+impl Authenticator {
+    /// sets up the configured backend; the keys JWTs are validated with are fetched in the
+    /// background
+    pub fn start(settings: &AuthSettings) -> Arc<Self> {
+        Arc::new(match settings.mode {
+            AuthMode::None => Authenticator::None,
+            AuthMode::ApiKey => Authenticator::ApiKeys(settings.api_keys.clone()),
+            AuthMode::Jwt => Authenticator::Jwt(JwtValidator::start(settings)),
+        })
+    }
+
+    /// checks the bearer token of a request; returns who made it
+    fn authorize(&self, token: Option<&str>) -> anyhow::Result<Actor> {
+        let missing = || anyhow::anyhow!("Missing bearer token");
+        match self {
+            Authenticator::None => Ok(Actor::anonymous()),
+            Authenticator::ApiKeys(keys) => {
+                let token = token.ok_or_else(missing)?;
+                let known = keys.iter().any(|key| tokens_match(key, token));
+                if !known {
+                    anyhow::bail!("Unknown API key");
+                }
+                let hash = openssl::sha::sha256(token.as_bytes());
+                let id: String = hash[..4].iter().map(|b| format!("{:02x}", b)).collect();
+                Ok(Actor(format!("api-key:{}", id)))
+            }
+            Authenticator::Jwt(validator) => {
+                validator.validate(token.ok_or_else(missing)?, chrono::Utc::now().timestamp())
+            }
+        }
+    }
+}
+
+/// whether the token is the known one, compared in constant time so it can't be guessed byte
+/// by byte
+pub fn tokens_match(known: &str, token: &str) -> bool {
+    known.len() == token.len() && openssl::memcmp::eq(known.as_bytes(), token.as_bytes())
+}
+
+/// middleware rejecting requests that aren't authorized with 401
+pub async fn require_auth(
+    State(authenticator): State<Arc<Authenticator>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match authenticator.authorize(token) {
+        Ok(_) => next.run(request).await,
+        Err(e) => (
+            StatusCode::UNAUTHORIZED,
+            [(header::WWW_AUTHENTICATE, "Bearer")],
+            format!("{:#}", e),
+        )
+            .into_response(),
+    }
+}
+
+/// Validates JWTs against the keys published by the identity provider (its JWKS), which are
+/// fetched periodically so rotated keys are picked up
+pub struct JwtValidator {
+    issuer: String,
+    audience: String,
+    /// the keys by their ID
+    keys: Arc<RwLock<HashMap<String, PKey<Public>>>>,
+}
+
+#[derive(Deserialize)]
+struct JwtHeader {
+    alg: String,
+    kid: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Claims {
+    iss: Option<String>,
+    sub: Option<String>,
+    aud: Option<Audience>,
+    exp: Option<i64>,
+    nbf: Option<i64>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+/// A public key as published in a JWKS, see RFC 7517
+#[derive(Deserialize)]
+struct Jwk {
+    kty: String,
+    #[serde(default)]
+    kid: String,
+    #[serde(rename = "use")]
+    usage: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+    crv: Option<String>,
+    x: Option<String>,
+    y: Option<String>,
+}
+
+impl JwtValidator {
+    pub fn start(settings: &AuthSettings) -> Self {
+        let validator = JwtValidator {
+            issuer: settings.issuer.clone(),
+            audience: settings.audience.clone(),
+            keys: Arc::default(),
+        };
+
+        let keys = Arc::clone(&validator.keys);
+        let url = settings.jwks_url.clone();
+        let interval = Duration::from_secs(settings.jwks_refresh_minutes * 60);
+        tokio::spawn(async move {
+            let client = reqwest::Client::new();
+            loop {
+                let pause = match fetch_keys(&client, &url).await {
+                    Ok(fetched) => {
+                        *keys.write().unwrap() = fetched;
+                        interval
+                    }
+                    Err(e) => {
+                        println!("Error while fetching the keys from {}: {:#}", url, e);
+                        interval.min(RETRY_INTERVAL)
+                    }
+                };
+                tokio::time::sleep(pause).await;
+            }
+        });
+        validator
+    }
+
+    /// checks the token's signature, issuer, audience and validity at `now` (in s)
+    fn validate(&self, token: &str, now: i64) -> anyhow::Result<Actor> {
+        let [encoded_header, payload, signature]: [&str; 3] = token
+            .split('.')
+            .collect::<Vec<_>>()
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Malformed token"))?;
+        let header: JwtHeader = serde_json::from_slice(&decode(encoded_header)?)?;
+
+        // the curve of EC keys and the size of its field in bytes
+        let (digest, key_type, curve) = match header.alg.as_str() {
+            "RS256" => (MessageDigest::sha256(), Id::RSA, None),
+            "RS384" => (MessageDigest::sha384(), Id::RSA, None),
+            "RS512" => (MessageDigest::sha512(), Id::RSA, None),
+            "ES256" => (
+                MessageDigest::sha256(),
+                Id::EC,
+                Some((Nid::X9_62_PRIME256V1, 32)),
+            ),
+            "ES384" => (MessageDigest::sha384(), Id::EC, Some((Nid::SECP384R1, 48))),
+            alg => anyhow::bail!("Unsupported algorithm '{}'", alg),
+        };
+        let key = {
+            let keys = self.keys.read().unwrap();
+            match &header.kid {
+                Some(kid) => keys.get(kid).cloned(),
+                // without an ID, the token can only be meant for the one key there is
+                None if keys.len() == 1 => keys.values().next().cloned(),
+                None => None,
+            }
+        }
+        .context("No key to validate the token with")?;
+        if key.id() != key_type {
+            anyhow::bail!("The key doesn't match the algorithm '{}'", header.alg);
+        }
+
+        let mut signature = decode(signature)?;
+        if let Some((curve, field_size)) = curve {
+            if key.ec_key()?.group().curve_name() != Some(curve) {
+                anyhow::bail!(
+                    "The key's curve doesn't match the algorithm '{}'",
+                    header.alg
+                );
+            }
+            // JWTs hold r and s padded to the field size and concatenated, openssl expects them
+            // DER encoded
+            if signature.len() != 2 * field_size {
+                anyhow::bail!("The signature is too short or long for '{}'", header.alg);
+            }
+            let (r, s) = signature.split_at(field_size);
+            signature =
+                EcdsaSig::from_private_components(BigNum::from_slice(r)?, BigNum::from_slice(s)?)?
+                    .to_der()?;
+        }
+        let mut verifier = Verifier::new(digest, &key)?;
+        // the signature covers the encoded header and payload
+        verifier.update(&token.as_bytes()[..encoded_header.len() + 1 + payload.len()])?;
+        if !verifier.verify(&signature).unwrap_or(false) {
+            anyhow::bail!("Invalid signature");
+        }
+
+        let claims: Claims = serde_json::from_slice(&decode(payload)?)?;
+        if claims.iss.as_deref() != Some(self.issuer.as_str()) {
+            anyhow::bail!("Token issued by someone else");
+        }
+        let audience_matches = match &claims.aud {
+            Some(Audience::One(aud)) => *aud == self.audience,
+            Some(Audience::Many(auds)) => auds.contains(&self.audience),
+            None => false,
+        };
+        if !audience_matches {
+            anyhow::bail!("Token meant for someone else");
+        }
+        if claims.exp.is_none_or(|exp| exp + LEEWAY_SECS <= now) {
+            anyhow::bail!("Token expired");
+        }
+        if claims.nbf.is_some_and(|nbf| nbf - LEEWAY_SECS > now) {
+            anyhow::bail!("Token not valid yet");
+        }
+        Ok(Actor(match claims.sub {
+            Some(sub) => format!("jwt:{}", sub),
+            None => String::from("jwt"),
+        }))
+    }
+}
+
+fn decode(part: &str) -> anyhow::Result<Vec<u8>> {
+    URL_SAFE_NO_PAD
+        .decode(part)
+        .context("Token isn't base64url encoded")
+}
+
+async fn fetch_keys(
+    client: &reqwest::Client,
+    url: &str,
+) -> anyhow::Result<HashMap<String, PKey<Public>>> {
+    let jwks = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<JwkSet>()
+        .await?;
+    Ok(parse_keys(jwks))
+}
+
+/// the signing keys of a JWKS by their ID; keys that can't be used are skipped
+fn parse_keys(jwks: JwkSet) -> HashMap<String, PKey<Public>> {
+    jwks.keys
+        .into_iter()
+        .filter(|jwk| jwk.usage.as_deref() != Some("enc"))
+        .filter_map(|jwk| match parse_key(&jwk) {
+            Ok(key) => Some((jwk.kid, key)),
+            Err(e) => {
+                println!("Skipping key '{}': {:#}", jwk.kid, e);
+                None
+            }
+        })
+        .collect()
+}
+
+fn parse_key(jwk: &Jwk) -> anyhow::Result<PKey<Public>> {
+    let number = |value: &Option<String>, name: &str| -> anyhow::Result<BigNum> {
+        let value = value
+            .as_deref()
+            .with_context(|| format!("Missing '{}'", name))?;
+        Ok(BigNum::from_slice(&decode(value)?)?)
+    };
+    match jwk.kty.as_str() {
+        "RSA" => {
+            let rsa = Rsa::from_public_components(number(&jwk.n, "n")?, number(&jwk.e, "e")?)?;
+            Ok(PKey::from_rsa(rsa)?)
+        }
+        "EC" => {
+            let curve = match jwk.crv.as_deref() {
+                Some("P-256") => Nid::X9_62_PRIME256V1,
+                Some("P-384") => Nid::SECP384R1,
+                crv => anyhow::bail!("Unsupported curve {:?}", crv),
+            };
+            let group = EcGroup::from_curve_name(curve)?;
+            let (x, y) = (number(&jwk.x, "x")?, number(&jwk.y, "y")?);
+            let ec = EcKey::from_public_key_affine_coordinates(&group, &x, &y)?;
+            Ok(PKey::from_ec_key(ec)?)
+        }
+        kty => anyhow::bail!("Unsupported key type '{}'", kty),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::pkey::Private;
+    use openssl::sign::Signer;
+
+    const NOW: i64 = 1717200000;
+
+    fn encode(bytes: &[u8]) -> String {
+        URL_SAFE_NO_PAD.encode(bytes)
+    }
+
+    fn sign(key: &PKey<Private>, alg: &str, kid: &str, claims: serde_json::Value) -> String {
+        let header = serde_json::json!({"alg": alg, "kid": kid, "typ": "JWT"});
+        let signed = format!(
+            "{}.{}",
+            encode(header.to_string().as_bytes()),
+            encode(claims.to_string().as_bytes())
+        );
+        let digest = match alg {
+            "ES384" => MessageDigest::sha384(),
+            _ => MessageDigest::sha256(),
+        };
+        let mut signer = Signer::new(digest, key).unwrap();
+        signer.update(signed.as_bytes()).unwrap();
+        let mut signature = signer.sign_to_vec().unwrap();
+        if key.id() == Id::EC {
+            let sig = EcdsaSig::from_der(&signature).unwrap();
+            let size = if alg == "ES384" { 48 } else { 32 };
+            signature = sig.r().to_vec_padded(size).unwrap();
+            signature.extend(sig.s().to_vec_padded(size).unwrap());
+        }
+        format!("{}.{}", signed, encode(&signature))
+    }
+
+    fn claims() -> serde_json::Value {
+        serde_json::json!({
+            "iss": "https://auth.example.com",
+            "aud": ["sunny", "other"],
+            "exp": NOW + 300,
+            "nbf": NOW - 300,
+        })
+    }
+
+    #[test]
+    fn test_validate_jwt() {
+        let rsa = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let ec_group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let ec = PKey::from_ec_key(EcKey::generate(&ec_group).unwrap()).unwrap();
+        let rsa_key = rsa.rsa().unwrap();
+        let (mut x, mut y) = (BigNum::new().unwrap(), BigNum::new().unwrap());
+        let mut ctx = openssl::bn::BigNumContext::new().unwrap();
+        ec.ec_key()
+            .unwrap()
+            .public_key()
+            .affine_coordinates(&ec_group, &mut x, &mut y, &mut ctx)
+            .unwrap();
+        let jwks: JwkSet = serde_json::from_value(serde_json::json!({"keys": [
+            {"kty": "RSA", "kid": "rsa", "use": "sig",
+             "n": encode(&rsa_key.n().to_vec()), "e": encode(&rsa_key.e().to_vec())},
+            {"kty": "EC", "kid": "ec", "crv": "P-256",
+             "x": encode(&x.to_vec()), "y": encode(&y.to_vec())},
+            {"kty": "oct", "kid": "symmetric", "k": "c2VjcmV0"},
+        ]}))
+        .unwrap();
+        let keys = parse_keys(jwks);
+        assert_eq!(keys.len(), 2);
+
+        let validator = JwtValidator {
+            issuer: "https://auth.example.com".to_owned(),
+            audience: "sunny".to_owned(),
+            keys: Arc::new(RwLock::new(keys)),
+        };
+        let validate = |token: &str| validator.validate(token, NOW);
+        let mut with_subject = claims();
+        with_subject["sub"] = "alice".into();
+        assert_eq!(
+            validate(&sign(&rsa, "RS256", "rsa", with_subject)).unwrap(),
+            Actor(String::from("jwt:alice"))
+        );
+        assert!(validate(&sign(&ec, "ES256", "ec", claims())).is_ok());
+        // ES384 needs a key on the P-384 curve, and r and s of its size
+        assert!(validate(&sign(&ec, "ES384", "ec", claims())).is_err());
+        let token = sign(&ec, "ES256", "ec", claims());
+        let (signed, signature) = token.rsplit_once('.').unwrap();
+        let truncated = &decode(signature).unwrap()[..63];
+        assert!(validate(&format!("{}.{}", signed, encode(truncated))).is_err());
+
+        // tampered with, or signed with a key that doesn't match
+        let token = sign(&rsa, "RS256", "rsa", claims());
+        let mut forged = claims();
+        forged["aud"] = serde_json::json!("sunny");
+        let forged_payload = encode(forged.to_string().as_bytes());
+        let parts: Vec<&str> = token.split('.').collect();
+        assert!(validate(&format!("{}.{}.{}", parts[0], forged_payload, parts[2])).is_err());
+        assert!(validate(&sign(&rsa, "RS256", "ec", claims())).is_err());
+        assert!(validate(&sign(&rsa, "RS256", "unknown", claims())).is_err());
+        assert!(validate("not a token").is_err());
+
+        let with = |field: &str, value: serde_json::Value| {
+            let mut claims = claims();
+            claims[field] = value;
+            sign(&rsa, "RS256", "rsa", claims)
+        };
+        assert!(validate(&with("iss", "https://evil.example.com".into())).is_err());
+        assert!(validate(&with("aud", "other".into())).is_err());
+        assert!(validate(&with("exp", (NOW - 120).into())).is_err());
+        assert!(validate(&with("nbf", (NOW + 120).into())).is_err());
+        // within the leeway
+        assert!(validate(&with("exp", (NOW - 30).into())).is_ok());
+        assert!(validate(&with("aud", "sunny".into())).is_ok());
+    }
+
+    #[test]
+    fn test_api_keys() {
+        let auth = Authenticator::ApiKeys(vec!["secret".to_owned()]);
+        // sha256("secret") = 2bb80d53...
+        assert_eq!(
+            auth.authorize(Some("secret")).unwrap(),
+            Actor(String::from("api-key:2bb80d53"))
+        );
+        assert!(auth.authorize(Some("secret2")).is_err());
+        assert!(auth.authorize(None).is_err());
+        assert!(Authenticator::None.authorize(None).is_ok());
+    }
+}
@@ -0,0 +1,141 @@
+use chrono::{NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::Serialize;
+use sunny_db::statistics::Quantiles;
+use sunny_db::timeseries::{Resolution, TimeSeries, TimeSeriesView};
+
+use crate::summary::day_range;
+use crate::PowerValues;
+
+/// PV power in W below which it's night, so the consumption isn't masked by self-consumed PV
+const NIGHT_PV_WATTS: f64 = 1.0;
+/// The share of the night-time consumption that stays below the baseline; low enough to
+/// ignore appliances switching on, high enough to not pick up single dips
+const BASELINE_PERCENTILE: f64 = 0.05;
+
+/// The consumption that's always on during a local day, e.g. to spot standby hogs in its trend
+#[derive(Serialize, Debug, PartialEq)]
+pub struct DailyBaseline {
+    pub date: NaiveDate,
+    pub start_time: u64,
+    /// the 5th percentile of the consumption at night in W
+    pub baseline_w: f64,
+    pub night_values: usize,
+}
+
+/// the baseline of the given values and how many of them were taken at night; None if none
+/// were. Each value counts for the time it stood for, see `Quantiles::quantile`
+pub fn baseline<'a>(
+    values: impl Iterator<Item = (u64, &'a PowerValues)>,
+    resolution: Resolution,
+) -> Option<(f64, usize)> {
+    // the consumption during the day is left out, but its times still bound those at night
+    let mut night = TimeSeries::<PowerValues>::with_resolution(0, resolution);
+    let mut night_values = 0;
+    for (time, value) in values {
+        let is_night = value.power_pv < NIGHT_PV_WATTS && !value.power_used.is_nan();
+        night_values += is_night as usize;
+        let power_used = if is_night { value.power_used } else { f64::NAN };
+        night.insert_value_at_time(
+            time,
+            PowerValues {
+                power_used,
+                ..*value
+            },
+        );
+    }
+    if night_values == 0 {
+        return None;
+    }
+    let baseline_w = night.quantile(BASELINE_PERCENTILE)?.power_used;
+    Some((baseline_w, night_values))
+}
+
+/// the baselines of the local days the values fall into
+pub fn daily_baselines(
+    series: TimeSeriesView<'_, PowerValues>,
+    timezone: Tz,
+) -> Vec<DailyBaseline> {
+    let resolution = series.get_resolution();
+    let mut days: Vec<(NaiveDate, Vec<(u64, PowerValues)>)> = Vec::new();
+    for (time, value) in series.iter() {
+        let Some(date) = Utc
+            .timestamp_millis_opt(resolution.to_millis(time) as i64)
+            .single()
+            .map(|t| t.with_timezone(&timezone).date_naive())
+        else {
+            continue;
+        };
+        match days.last_mut() {
+            Some((day, values)) if *day == date => values.push((time, *value)),
+            _ => days.push((date, vec![(time, *value)])),
+        }
+    }
+
+    days.into_iter()
+        .filter_map(|(date, values)| {
+            let values = values.iter().map(|(time, value)| (*time, value));
+            let (baseline_w, night_values) = baseline(values, resolution)?;
+            Some(DailyBaseline {
+                date,
+                start_time: day_range(date, timezone).0,
+                baseline_w,
+                night_values,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sunny_db::timeseries::TimeSeries;
+
+    #[test]
+    fn test_daily_baselines() {
+        let berlin: Tz = "Europe/Berlin".parse().unwrap();
+        let power = |power_pv, power_used| PowerValues {
+            power_pv,
+            power_to_grid: 0.0,
+            power_from_grid: 0.0,
+            power_used,
+        };
+        let minute = 60_000;
+        // 2024-06-01 00:00 CEST
+        let midnight = 1717192800000;
+        let mut series = TimeSeries::<PowerValues>::new(3000);
+        for i in 0..2 * 24 * 60 {
+            let time = midnight + i * minute;
+            let hour = (i / 60) % 24;
+            let value = match hour {
+                // the fridge adds 100 W every other 10 minutes, a kettle 2 kW once per night
+                0..=5 | 22..=23 if i % 60 == 30 => power(0.0, 2150.0),
+                0..=5 | 22..=23 if (i / 10) % 2 == 0 => power(0.0, 150.0),
+                0..=5 | 22..=23 => power(0.0, 50.0 + i as f64 / 1000.0),
+                _ => power(3000.0, 10.0),
+            };
+            series.insert_value_at_time(time, value);
+        }
+
+        let baselines = daily_baselines(series.view(), berlin);
+        assert_eq!(baselines.len(), 2);
+        assert_eq!(baselines[0].start_time, midnight);
+        assert_eq!(baselines[0].night_values, 8 * 60);
+        // the lowest consumption at night, not during the day
+        assert!((50.0..51.0).contains(&baselines[0].baseline_w));
+        assert!((51.0..52.0).contains(&baselines[1].baseline_w));
+
+        let day = [(0, power(100.0, 5.0))];
+        let values = || day.iter().map(|(time, value)| (*time, value));
+        assert_eq!(baseline(values(), Resolution::Milliseconds), None);
+
+        // a burst of samples counts for the little time it took, not for how many there are
+        let mut burst: Vec<(u64, PowerValues)> = (0..100).map(|i| (i, power(0.0, 20.0))).collect();
+        burst.extend((1..=20).map(|h| (h * 60 * minute, power(0.0, 100.0))));
+        let values = burst.iter().map(|(time, value)| (*time, value));
+        assert_eq!(
+            baseline(values, Resolution::Milliseconds),
+            Some((100.0, 120))
+        );
+    }
+}
@@ -0,0 +1,290 @@
+use serde::{Deserialize, Serialize};
+use sunny_db::timeseries::TimeSeriesView;
+
+use crate::kpi::Kpi;
+use crate::PowerValues;
+
+/// Round-trip efficiency of the battery unless asked for something else
+pub const DEFAULT_EFFICIENCY: f64 = 0.9;
+
+/// Query parameters of `GET /battery-simulation/:start_time/:end_time`, e.g.
+/// `?capacity_kwh=10&power_w=5000&efficiency=0.9`
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct BatteryParams {
+    pub capacity_kwh: f64,
+    /// most power the battery charges and discharges with; unlimited if it's missing
+    pub power_w: Option<f64>,
+    /// share of the charged energy that can be discharged again
+    pub efficiency: Option<f64>,
+}
+
+/// What's exchanged with the grid, given the PV production and consumption
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct GridExchange {
+    pub from_grid_kwh: f64,
+    pub to_grid_kwh: f64,
+    /// share of the consumption not drawn from the grid; null without consumption
+    pub autarky: Option<f64>,
+    /// share of the PV production not fed into the grid; null without production
+    pub self_consumption: Option<f64>,
+}
+
+impl GridExchange {
+    /// from the energies in Wh
+    pub fn from_energies(pv: f64, used: f64, from_grid: f64, to_grid: f64) -> Self {
+        let energy_kwh = PowerValues {
+            power_pv: pv / 1000.0,
+            power_to_grid: to_grid / 1000.0,
+            power_from_grid: from_grid / 1000.0,
+            power_used: used / 1000.0,
+        };
+        let kpi = Kpi::from_energy(&energy_kwh);
+        GridExchange {
+            from_grid_kwh: energy_kwh.power_from_grid,
+            to_grid_kwh: energy_kwh.power_to_grid,
+            autarky: kpi.and_then(|kpi| kpi.autarky),
+            self_consumption: kpi.and_then(|kpi| kpi.self_consumption),
+        }
+    }
+}
+
+/// Response of `GET /battery-simulation/:start_time/:end_time`
+#[derive(Serialize, Debug)]
+pub struct BatterySimulation {
+    pub capacity_kwh: f64,
+    pub power_w: Option<f64>,
+    pub efficiency: f64,
+    pub pv_kwh: f64,
+    pub used_kwh: f64,
+    pub without_battery: GridExchange,
+    pub with_battery: GridExchange,
+    /// the PV surplus that went into the battery
+    pub charged_kwh: f64,
+    /// what the battery supplied to the load
+    pub discharged_kwh: f64,
+    /// the discharged energy in multiples of the capacity
+    pub full_cycles: f64,
+    /// the energy stored at the end; the battery starts empty
+    pub final_charge_kwh: f64,
+}
+
+/// energies in Wh summed up over the intervals
+#[derive(Default)]
+struct Totals {
+    pv: f64,
+    used: f64,
+    from_grid: f64,
+    to_grid: f64,
+}
+
+impl Totals {
+    fn exchange(&self) -> GridExchange {
+        GridExchange::from_energies(self.pv, self.used, self.from_grid, self.to_grid)
+    }
+}
+
+/// An interval between two values, whose PV production and consumption in W are taken to
+/// change linearly
+#[derive(Clone, Copy, Debug)]
+pub struct Interval {
+    pub hours: f64,
+    pub pv: [f64; 2],
+    pub used: [f64; 2],
+}
+
+impl Interval {
+    pub fn mean_pv(&self) -> f64 {
+        (self.pv[0] + self.pv[1]) / 2.0
+    }
+
+    pub fn mean_used(&self) -> f64 {
+        (self.used[0] + self.used[1]) / 2.0
+    }
+
+    /// the parts of the interval with a PV surplus and with a deficit, given the PV production
+    /// scaled by `pv_scale`, as their length in hours and their mean surplus in W, which is
+    /// negative for a deficit. The surplus of each value is taken to change linearly, so an
+    /// interval in which it changes sign is split where it crosses zero
+    pub fn surpluses(&self, pv_scale: f64) -> impl Iterator<Item = (f64, f64)> {
+        let start = self.pv[0] * pv_scale - self.used[0];
+        let end = self.pv[1] * pv_scale - self.used[1];
+        let parts = if start * end < 0.0 {
+            let crossing = start / (start - end);
+            [
+                (self.hours * crossing, start / 2.0),
+                (self.hours * (1.0 - crossing), end / 2.0),
+            ]
+        } else {
+            [(self.hours, (start + end) / 2.0), (0.0, 0.0)]
+        };
+        parts.into_iter().filter(|(hours, _)| *hours > 0.0)
+    }
+}
+
+/// the intervals between the values; intervals with missing fields are skipped
+pub fn intervals<'a>(
+    series: TimeSeriesView<'a, PowerValues>,
+) -> impl Iterator<Item = Interval> + 'a {
+    let units_per_hour = 3600.0 * series.get_resolution().per_second() as f64;
+    series
+        .iter()
+        .zip(series.iter().skip(1))
+        .filter_map(move |((t_0, a), (t_1, b))| {
+            let interval = Interval {
+                hours: (t_1 - t_0) as f64 / units_per_hour,
+                pv: [a.power_pv, b.power_pv],
+                used: [a.power_used, b.power_used],
+            };
+            let missing = interval.mean_pv().is_nan() || interval.mean_used().is_nan();
+            if interval.hours <= 0.0 || missing {
+                return None;
+            }
+            Some(interval)
+        })
+}
+
+/// replays the PV production and consumption through a battery that charges with any PV
+/// surplus and discharges whenever the consumption exceeds the production, to see how much
+/// less would have been drawn from and fed into the grid with it. The grid exchange with and
+/// without the battery are both derived from the surplus of each value, which is taken to
+/// change linearly between values; intervals with missing fields are skipped
+pub fn simulate(
+    series: TimeSeriesView<'_, PowerValues>,
+    params: BatteryParams,
+) -> anyhow::Result<BatterySimulation> {
+    let efficiency = params.efficiency.unwrap_or(DEFAULT_EFFICIENCY);
+    if !(params.capacity_kwh > 0.0 && params.capacity_kwh.is_finite()) {
+        anyhow::bail!("The capacity has to be a positive number of kWh");
+    }
+    if params
+        .power_w
+        .is_some_and(|power_w| power_w.is_nan() || power_w <= 0.0)
+    {
+        anyhow::bail!("The power has to be a positive number of W");
+    }
+    if !(efficiency > 0.0 && efficiency <= 1.0) {
+        anyhow::bail!("The efficiency has to be within (0, 1]");
+    }
+    // the losses are split evenly between charging and discharging
+    let one_way = efficiency.sqrt();
+    let capacity = params.capacity_kwh * 1000.0;
+    let max_power = params.power_w.unwrap_or(f64::INFINITY);
+
+    let (mut without, mut with) = (Totals::default(), Totals::default());
+    let (mut charge, mut charged, mut discharged) = (0.0, 0.0, 0.0);
+    for interval in intervals(series) {
+        for totals in [&mut without, &mut with] {
+            totals.pv += interval.mean_pv() * interval.hours;
+            totals.used += interval.mean_used() * interval.hours;
+        }
+        for (hours, surplus) in interval.surpluses(1.0) {
+            if surplus >= 0.0 {
+                let charging = surplus
+                    .min(max_power)
+                    .min((capacity - charge) / (hours * one_way));
+                charge += charging * hours * one_way;
+                charged += charging * hours;
+                without.to_grid += surplus * hours;
+                with.to_grid += (surplus - charging) * hours;
+            } else {
+                let deficit = -surplus;
+                let discharging = deficit.min(max_power).min(charge * one_way / hours);
+                charge -= discharging * hours / one_way;
+                discharged += discharging * hours;
+                without.from_grid += deficit * hours;
+                with.from_grid += (deficit - discharging) * hours;
+            }
+        }
+    }
+
+    Ok(BatterySimulation {
+        capacity_kwh: params.capacity_kwh,
+        power_w: params.power_w,
+        efficiency,
+        pv_kwh: without.pv / 1000.0,
+        used_kwh: without.used / 1000.0,
+        without_battery: without.exchange(),
+        with_battery: with.exchange(),
+        charged_kwh: charged / 1000.0,
+        discharged_kwh: discharged / 1000.0,
+        full_cycles: discharged / capacity,
+        final_charge_kwh: charge / 1000.0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sunny_db::timeseries::TimeSeries;
+
+    #[test]
+    fn test_simulate() {
+        let hour = 3_600_000;
+        let mut series = TimeSeries::<PowerValues>::new(11);
+        // a 2 kW surplus for three hours, then 1 kW drawn for six
+        for h in 0..=10 {
+            let power_pv = if h <= 3 { 3000.0 } else { 0.0 };
+            let values = PowerValues {
+                power_pv,
+                power_to_grid: 0.0,
+                power_from_grid: 0.0,
+                power_used: 1000.0,
+            };
+            series.insert_value_at_time(h * hour, values);
+        }
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+
+        let lossless = BatteryParams {
+            capacity_kwh: 5.0,
+            power_w: None,
+            efficiency: Some(1.0),
+        };
+        let simulation = simulate(series.view(), lossless).unwrap();
+        assert!(close(simulation.pv_kwh, 10.5));
+        assert!(close(simulation.used_kwh, 10.0));
+        // it's full halfway through the third hour, the rest of the surplus is fed in
+        assert!(close(simulation.charged_kwh, 5.0));
+        assert!(close(simulation.discharged_kwh, 5.0));
+        assert!(close(simulation.full_cycles, 1.0));
+        assert!(close(simulation.final_charge_kwh, 0.0));
+        // in the fourth hour the surplus falls from 2 kW to a deficit of 1 kW, crossing zero
+        // after 40 minutes, rather than being a surplus of 500 W throughout
+        let (without, with) = (&simulation.without_battery, &simulation.with_battery);
+        assert!(close(without.from_grid_kwh, 6.0 + 1.0 / 6.0));
+        assert!(close(without.to_grid_kwh, 6.0 + 2.0 / 3.0));
+        assert!(close(with.from_grid_kwh, 1.0 + 1.0 / 6.0));
+        assert!(close(with.to_grid_kwh, 1.0 + 2.0 / 3.0));
+        assert!(close(
+            without.autarky.unwrap(),
+            1.0 - (6.0 + 1.0 / 6.0) / 10.0
+        ));
+        assert!(close(with.autarky.unwrap(), 1.0 - (1.0 + 1.0 / 6.0) / 10.0));
+        assert!(close(
+            with.self_consumption.unwrap(),
+            1.0 - (1.0 + 2.0 / 3.0) / 10.5
+        ));
+
+        // losses and a power limit of 1 kW
+        let limited = BatteryParams {
+            capacity_kwh: 5.0,
+            power_w: Some(1000.0),
+            efficiency: Some(0.81),
+        };
+        let simulation = simulate(series.view(), limited).unwrap();
+        assert!(close(simulation.charged_kwh, 3.0 + 2.0 / 3.0));
+        assert!(close(simulation.discharged_kwh, (3.0 + 2.0 / 3.0) * 0.81));
+        assert!(close(simulation.with_battery.to_grid_kwh, 3.0));
+        assert!(close(simulation.final_charge_kwh, 0.0));
+
+        let invalid = |capacity_kwh, power_w, efficiency| BatteryParams {
+            capacity_kwh,
+            power_w,
+            efficiency,
+        };
+        assert!(simulate(series.view(), invalid(0.0, None, None)).is_err());
+        assert!(simulate(series.view(), invalid(5.0, Some(-1.0), None)).is_err());
+        assert!(simulate(series.view(), invalid(5.0, None, Some(1.5))).is_err());
+        let empty = simulate(TimeSeries::empty().view(), lossless).unwrap();
+        assert_eq!(empty.with_battery, GridExchange::default());
+    }
+}
@@ -0,0 +1,230 @@
+use anyhow::{self, Context};
+use bitcode::{Decode, Encode};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use sunny_db::timeseries::{Resolution, TimeSeries};
+
+use crate::PowerValues;
+
+#[derive(clap::Args, Debug)]
+pub struct BenchArgs {
+    // Database directory (e.g. <sunny-home>/db) or its data directory
+    #[arg(long)]
+    data_dir: String,
+
+    // Maximum number of segments to use, evenly spread over all of them
+    #[arg(long, default_value_t = 50)]
+    samples: usize,
+
+    // zstd compression levels to compare
+    #[arg(long, value_delimiter = ',', default_values_t = [1, 2, 3, 9, 15, 19, 22])]
+    levels: Vec<i32>,
+}
+
+/// Values stored column by column with times as differences to the previous one, which
+/// usually compresses better than storing (time, value) rows
+#[derive(Encode, Decode)]
+struct Columns {
+    time_deltas: Vec<u64>,
+    power_pv: Vec<f64>,
+    power_to_grid: Vec<f64>,
+    power_from_grid: Vec<f64>,
+    power_used: Vec<f64>,
+}
+
+impl Columns {
+    fn from_series(ts: &TimeSeries<PowerValues>) -> Self {
+        let values = ts.get_current_values();
+        let mut previous = 0;
+        let time_deltas = values
+            .iter()
+            .map(|(t, _)| {
+                let delta = t - previous;
+                previous = *t;
+                delta
+            })
+            .collect();
+        Columns {
+            time_deltas,
+            power_pv: values.iter().map(|(_, v)| v.power_pv).collect(),
+            power_to_grid: values.iter().map(|(_, v)| v.power_to_grid).collect(),
+            power_from_grid: values.iter().map(|(_, v)| v.power_from_grid).collect(),
+            power_used: values.iter().map(|(_, v)| v.power_used).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Layout {
+    Rows,
+    Columns,
+    /// delta-of-delta timestamps and XOR compressed values, see sunny_db::gorilla
+    Gorilla,
+}
+
+impl Layout {
+    fn name(&self) -> &'static str {
+        match self {
+            Layout::Rows => "rows",
+            Layout::Columns => "columns",
+            Layout::Gorilla => "gorilla",
+        }
+    }
+
+    fn encode(&self, ts: &TimeSeries<PowerValues>) -> std::io::Result<Vec<u8>> {
+        match self {
+            Layout::Rows => ts.to_bytes(),
+            Layout::Columns => Ok(bitcode::encode(&Columns::from_series(ts))),
+            Layout::Gorilla => Ok(ts.to_gorilla_bytes()),
+        }
+    }
+
+    fn decode(&self, bytes: &[u8]) -> anyhow::Result<()> {
+        match self {
+            Layout::Rows => {
+                TimeSeries::<PowerValues>::from_bytes(bytes, Resolution::default())?;
+            }
+            Layout::Columns => {
+                bitcode::decode::<Columns>(bytes)?;
+            }
+            Layout::Gorilla => {
+                TimeSeries::<PowerValues>::from_gorilla_bytes(bytes, Resolution::default())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct BenchResult {
+    codec: String,
+    layout: Layout,
+    size: usize,
+    encode_time: Duration,
+    decode_time: Duration,
+}
+
+pub fn run(args: &BenchArgs) -> anyhow::Result<()> {
+    let data_dir = Path::new(&args.data_dir);
+    let data_dir = if data_dir.join("data").is_dir() {
+        data_dir.join("data")
+    } else {
+        data_dir.to_path_buf()
+    };
+
+    let mut files = segment_files(&data_dir)?;
+    files.sort();
+    let files = sample(&files, args.samples);
+    if files.is_empty() {
+        anyhow::bail!("Didn't find any segments in {}", data_dir.display());
+    }
+
+    let mut segments = Vec::new();
+    let mut stored_size = 0;
+    for file in &files {
+        let bytes = fs::read(file).with_context(|| format!("Couldn't read {}", file.display()))?;
+        stored_size += bytes.len();
+        let ts = TimeSeries::<PowerValues>::from_float_segment(&bytes, None)
+            .with_context(|| format!("Couldn't decode segment {}", file.display()))?;
+        segments.push(ts);
+    }
+    let value_count: usize = segments.iter().map(|ts| ts.len()).sum();
+    println!(
+        "Using {} segments with {} values ({} bytes as currently stored)\n",
+        segments.len(),
+        value_count,
+        stored_size
+    );
+
+    let mut results = Vec::new();
+    for layout in [Layout::Rows, Layout::Columns, Layout::Gorilla] {
+        results.push(bench(&segments, layout, None)?);
+        for level in &args.levels {
+            results.push(bench(&segments, layout, Some(*level))?);
+        }
+    }
+
+    let uncompressed = results[0].size;
+    println!(
+        "{:<10} {:<8} {:>12} {:>8} {:>12} {:>12}",
+        "codec", "layout", "bytes", "ratio", "encode ms", "decode ms"
+    );
+    for result in results {
+        println!(
+            "{:<10} {:<8} {:>12} {:>8.2} {:>12.2} {:>12.2}",
+            result.codec,
+            result.layout.name(),
+            result.size,
+            uncompressed as f64 / result.size as f64,
+            result.encode_time.as_secs_f64() * 1e3,
+            result.decode_time.as_secs_f64() * 1e3
+        );
+    }
+    Ok(())
+}
+
+/// encodes all segments with the given layout and, unless level is None, compresses
+/// them with zstd at that level
+fn bench(
+    segments: &[TimeSeries<PowerValues>],
+    layout: Layout,
+    level: Option<i32>,
+) -> anyhow::Result<BenchResult> {
+    let mut size = 0;
+    let mut encode_time = Duration::ZERO;
+    let mut decode_time = Duration::ZERO;
+
+    for ts in segments {
+        let start = Instant::now();
+        let mut bytes = layout.encode(ts)?;
+        if let Some(level) = level {
+            bytes = zstd::stream::encode_all(bytes.as_slice(), level)?;
+        }
+        encode_time += start.elapsed();
+        size += bytes.len();
+
+        let start = Instant::now();
+        if level.is_some() {
+            bytes = zstd::stream::decode_all(bytes.as_slice())?;
+        }
+        layout.decode(&bytes)?;
+        decode_time += start.elapsed();
+    }
+
+    Ok(BenchResult {
+        codec: match level {
+            Some(level) => format!("zstd-{}", level),
+            None => "none".to_owned(),
+        },
+        layout,
+        size,
+        encode_time,
+        decode_time,
+    })
+}
+
+fn segment_files(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(path).with_context(|| format!("Couldn't read {}", path.display()))? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if entry.file_type()?.is_dir() {
+            files.append(&mut segment_files(&entry.path())?);
+        } else if name
+            .split_once('-')
+            .is_some_and(|(start, end)| start.parse::<u64>().is_ok() && end.parse::<u64>().is_ok())
+        {
+            files.push(entry.path());
+        }
+    }
+    Ok(files)
+}
+
+/// picks at most n elements spread evenly over the slice
+fn sample<T: Clone>(items: &[T], n: usize) -> Vec<T> {
+    if items.len() <= n {
+        return items.to_vec();
+    }
+    (0..n).map(|i| items[i * items.len() / n].clone()).collect()
+}
@@ -0,0 +1,959 @@
+use anyhow::Context;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::time::Duration;
+use sunny_db::timeseries::TimestampBounds;
+
+/// Settings read from the optional TOML config file passed via `--config`
+/// every section falls back to its defaults if it's missing from the file
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub frontend: FrontendSettings,
+    pub schedule: ScheduleSettings,
+    pub archive: ArchiveSettings,
+    pub remote: RemoteSettings,
+    pub metrics: MetricsSettings,
+    pub api: ApiSettings,
+    pub assets: AssetSettings,
+    pub billing: BillingSettings,
+    pub storage: StorageSettings,
+    pub source: SourceSettings,
+    pub sampling: SamplingSettings,
+    pub server: ServerSettings,
+    pub updates: UpdateSettings,
+    pub live: LiveSettings,
+    pub auth: AuthSettings,
+    pub prices: PriceSettings,
+    pub timestamps: TimestampSettings,
+    pub status: StatusSettings,
+    pub health: HealthSettings,
+    /// derived series served via `GET /rollups/:name/:start_time/:end_time`, see rollups.rs
+    pub rollups: Vec<RollupRule>,
+}
+
+/// Non-secret runtime settings handed out to the frontend via `GET /config/frontend`
+/// so the bundled UI can adapt without having to be rebuilt
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct FrontendSettings {
+    /// IANA timezone name used to display times, e.g. "Europe/Berlin"
+    pub timezone: String,
+    /// ISO 4217 currency code, e.g. "EUR"
+    pub currency: String,
+    /// Name displayed in the frontend's title
+    pub site_name: String,
+    /// Optional frontend features that should be enabled
+    pub features: Vec<String>,
+}
+
+impl Default for FrontendSettings {
+    fn default() -> Self {
+        FrontendSettings {
+            timezone: String::from("UTC"),
+            currency: String::from("EUR"),
+            site_name: String::from("Sunny"),
+            features: Vec::new(),
+        }
+    }
+}
+
+/// Settings of the jobs that run at fixed local times every day, see scheduler.rs
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScheduleSettings {
+    /// IANA timezone defining the local energy day; defaults to the frontend's timezone
+    pub timezone: Option<String>,
+    /// local time (HH:MM) at which the values in memory are persisted so that a new
+    /// segment is started for the new day
+    pub new_segment_at: String,
+    /// local time (HH:MM) at which the summary of the previous day is computed
+    pub summary_at: String,
+    /// windows of local time (HH:MM-HH:MM) during which no jobs should run;
+    /// jobs that are due during a quiet time are postponed until it's over
+    pub quiet_times: Vec<String>,
+}
+
+impl Default for ScheduleSettings {
+    fn default() -> Self {
+        ScheduleSettings {
+            timezone: None,
+            new_segment_at: String::from("00:00"),
+            summary_at: String::from("00:05"),
+            quiet_times: Vec::new(),
+        }
+    }
+}
+
+/// Settings of the cold storage tier; archiving is disabled unless `after_months` is set
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArchiveSettings {
+    /// segments older than this many months get archived
+    pub after_months: Option<u32>,
+    /// zstd level used to re-compress archived segments
+    pub compression_level: i32,
+    /// directory to move archived segments to; without it, segments are re-compressed in place
+    pub cold_dir: Option<String>,
+    /// local time (HH:MM) at which old segments are archived every day
+    pub archive_at: String,
+}
+
+impl Default for ArchiveSettings {
+    fn default() -> Self {
+        ArchiveSettings {
+            after_months: None,
+            compression_level: 22,
+            cold_dir: None,
+            archive_at: String::from("03:00"),
+        }
+    }
+}
+
+/// Settings of the remote storage tier segments are offloaded to, either a directory (e.g. a
+/// mounted network share) or an S3-compatible bucket; offloading is disabled unless
+/// `after_months` is set. The S3 credentials are read from `AWS_ACCESS_KEY_ID` and
+/// `AWS_SECRET_ACCESS_KEY` so they don't end up in the config file
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RemoteSettings {
+    /// segments older than this many months get offloaded
+    pub after_months: Option<u32>,
+    /// local time (HH:MM) at which old segments are offloaded every day
+    pub offload_at: String,
+    /// directory to offload segments to
+    pub dir: Option<String>,
+    /// S3 endpoint, e.g. "https://s3.eu-central-1.amazonaws.com"; needs the `s3` feature
+    pub endpoint: Option<String>,
+    pub bucket: String,
+    pub region: String,
+    /// prepended to the keys of all objects, e.g. "sunny/"
+    pub prefix: String,
+}
+
+impl Default for RemoteSettings {
+    fn default() -> Self {
+        RemoteSettings {
+            after_months: None,
+            offload_at: String::from("03:30"),
+            dir: None,
+            endpoint: None,
+            bucket: String::new(),
+            region: String::from("us-east-1"),
+            prefix: String::new(),
+        }
+    }
+}
+
+/// Settings of the latency metrics served via `GET /metrics`
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsSettings {
+    /// queries of values taking longer than this many ms are logged; 0 disables the log
+    pub slow_query_ms: u64,
+}
+
+impl Default for MetricsSettings {
+    fn default() -> Self {
+        MetricsSettings {
+            slow_query_ms: 1000,
+        }
+    }
+}
+
+impl MetricsSettings {
+    pub fn slow_query_threshold(&self) -> Option<Duration> {
+        (self.slow_query_ms > 0).then(|| Duration::from_millis(self.slow_query_ms))
+    }
+}
+
+/// Settings of the routes serving data
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiSettings {
+    /// path under which all data routes are served, e.g. "/api/v1"; empty to serve them at
+    /// the root
+    pub prefix: String,
+    /// whether the data routes are also served at their old, unprefixed paths; their
+    /// responses carry `Warning` headers pointing to the prefixed paths
+    pub legacy_routes: bool,
+    /// date (YYYY-MM-DD) from which on the legacy routes may be gone, announced in the
+    /// `Sunset` header of their responses; empty to not announce one
+    pub legacy_sunset: String,
+    /// how queries of ranges without any values are answered
+    pub empty_response: EmptyResponse,
+    /// decimals of the fields of values in responses
+    pub precision: Precision,
+}
+
+/// Fields of the values whose precision can be configured
+const VALUE_FIELDS: [&str; 4] = ["power_pv", "power_to_grid", "power_from_grid", "power_used"];
+
+/// Number of decimals per field of the values in responses, e.g. `power_pv = 0`; energies in kWh
+/// get 3 more, so they're as precise in Wh as powers in W. Fields without one are served with
+/// full precision
+#[derive(Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(transparent)]
+pub struct Precision(BTreeMap<String, u32>);
+
+impl Precision {
+    fn validate(&self) -> anyhow::Result<()> {
+        for (field, decimals) in &self.0 {
+            if !VALUE_FIELDS.contains(&field.as_str()) {
+                anyhow::bail!(
+                    "api.precision.{} isn't a field of the values, expected one of {:?}",
+                    field,
+                    VALUE_FIELDS
+                );
+            }
+            if *decimals > 12 {
+                anyhow::bail!("api.precision.{} must not be more than 12 decimals", field);
+            }
+        }
+        Ok(())
+    }
+
+    /// rounds the fields of all values within a response
+    pub fn apply(&self, json: &mut serde_json::Value) {
+        if !self.0.is_empty() {
+            self.apply_with(json, 0);
+        }
+    }
+
+    fn apply_with(&self, json: &mut serde_json::Value, extra_decimals: u32) {
+        match json {
+            serde_json::Value::Array(items) => {
+                for item in items {
+                    self.apply_with(item, extra_decimals);
+                }
+            }
+            serde_json::Value::Object(fields) => {
+                for (name, value) in fields {
+                    let rounded = match (self.0.get(name), value.as_f64()) {
+                        (Some(decimals), Some(v)) if value.is_f64() => {
+                            let factor = 10f64.powi((decimals + extra_decimals) as i32);
+                            serde_json::Number::from_f64((v * factor).round() / factor)
+                        }
+                        _ => None,
+                    };
+                    match rounded {
+                        Some(rounded) => *value = serde_json::Value::Number(rounded),
+                        None if name == "energy_kwh" => self.apply_with(value, extra_decimals + 3),
+                        None => self.apply_with(value, extra_decimals),
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Response to queries of ranges without any values
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum EmptyResponse {
+    /// 200 with the usual structure, i.e. empty lists of values and null statistics
+    #[default]
+    Empty,
+    /// 204 without a body
+    NoContent,
+    /// 404
+    NotFound,
+}
+
+impl Default for ApiSettings {
+    fn default() -> Self {
+        ApiSettings {
+            prefix: String::from("/api/v1"),
+            legacy_routes: true,
+            legacy_sunset: String::new(),
+            empty_response: EmptyResponse::default(),
+            precision: Precision::default(),
+        }
+    }
+}
+
+impl ApiSettings {
+    /// the prefix with a leading but without a trailing slash; None if there's no prefix
+    pub fn prefix(&self) -> Option<String> {
+        let prefix = self.prefix.trim_matches('/');
+        (!prefix.is_empty()).then(|| format!("/{}", prefix))
+    }
+
+    pub fn legacy_sunset(&self) -> anyhow::Result<Option<NaiveDate>> {
+        if self.legacy_sunset.is_empty() {
+            return Ok(None);
+        }
+        NaiveDate::parse_from_str(&self.legacy_sunset, "%Y-%m-%d")
+            .map(Some)
+            .with_context(|| format!("api.legacy_sunset {} isn't a date", self.legacy_sunset))
+    }
+}
+
+/// Settings of how the frontend's files are served
+#[derive(Deserialize, Default, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct AssetSettings {
+    /// serve pre-compressed `.br`/`.gz` variants of the files next to them, if there are any,
+    /// to clients that accept them
+    pub precompressed: bool,
+}
+
+/// Settings of how the utility bills the energy taken from the grid
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct BillingSettings {
+    /// day of the month (1-28) on which billing periods start
+    pub period_start_day: u32,
+    /// length of the windows over which the grid import is averaged to find the peak demand
+    pub peak_window_minutes: u64,
+}
+
+impl Default for BillingSettings {
+    fn default() -> Self {
+        BillingSettings {
+            period_start_day: 1,
+            peak_window_minutes: 15,
+        }
+    }
+}
+
+/// Disk quotas of the stored series, see storage.rs; nothing is ever pruned unless a quota is
+/// configured for the series
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageSettings {
+    /// when less than this many MB are free on the disk, the series with quotas are pruned
+    /// oldest first in the order of their priority; 0 disables the check
+    pub min_free_mb: u64,
+    /// local time (HH:MM) at which the quotas are enforced every day
+    pub check_at: String,
+    pub quotas: Vec<SeriesQuota>,
+    /// keep an SQLite catalog of the segments, the manifest and the daily summaries in the
+    /// data directory, see sunny_db's catalog.rs
+    pub catalog: bool,
+}
+
+impl Default for StorageSettings {
+    fn default() -> Self {
+        StorageSettings {
+            min_free_mb: 0,
+            check_at: String::from("04:00"),
+            quotas: Vec::new(),
+            catalog: false,
+        }
+    }
+}
+
+/// Adaptive sampling, see sampling.rs: the source is polled every min_interval_ms while the
+/// values change by at least threshold_watts between samples and ever less often up to
+/// max_interval_ms while they're stable; without it, it's polled every --granularity seconds
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct SamplingSettings {
+    pub adaptive: bool,
+    pub min_interval_ms: u64,
+    pub max_interval_ms: u64,
+    /// change of any of the values in W that counts as activity
+    pub threshold_watts: f64,
+}
+
+impl Default for SamplingSettings {
+    fn default() -> Self {
+        SamplingSettings {
+            adaptive: false,
+            min_interval_ms: 1000,
+            max_interval_ms: 30_000,
+            threshold_watts: 100.0,
+        }
+    }
+}
+
+impl SamplingSettings {
+    /// how often values are stored at least when sampling every `granularity` (or adaptively)
+    /// and averaging over `average_over` samples
+    pub fn stored_interval(&self, granularity: Duration, average_over: usize) -> Duration {
+        let sample_interval = match self.adaptive {
+            true => Duration::from_millis(self.max_interval_ms),
+            false => granularity,
+        };
+        sample_interval * average_over as u32
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.min_interval_ms == 0 || self.min_interval_ms > self.max_interval_ms {
+            anyhow::bail!("sampling.min_interval_ms has to be between 1 and max_interval_ms");
+        }
+        if self.threshold_watts.is_nan() || self.threshold_watts < 0.0 {
+            anyhow::bail!("sampling.threshold_watts must not be negative");
+        }
+        Ok(())
+    }
+}
+
+/// Periodic check for new releases, reported via `GET /version` and `GET /metrics`; nothing
+/// is ever downloaded or installed
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct UpdateSettings {
+    pub check: bool,
+    pub interval_hours: u64,
+    /// the GitHub API endpoint of the latest release
+    pub url: String,
+}
+
+impl Default for UpdateSettings {
+    fn default() -> Self {
+        UpdateSettings {
+            check: false,
+            interval_hours: 24,
+            url: "https://api.github.com/repos/david-pl/sunny/releases/latest".to_owned(),
+        }
+    }
+}
+
+/// The samples kept in memory for `GET /live` at the rate they're fetched, before they're
+/// averaged for storage
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct LiveSettings {
+    /// how many of the most recent samples are kept
+    pub buffer_size: usize,
+}
+
+impl Default for LiveSettings {
+    fn default() -> Self {
+        LiveSettings { buffer_size: 600 }
+    }
+}
+
+/// How requests to the data routes are authorized, see auth.rs; the frontend's files are
+/// always served
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthSettings {
+    pub mode: AuthMode,
+    /// bearer tokens accepted in `api_key` mode
+    pub api_keys: Vec<String>,
+    /// `iss` that JWTs have to be issued by in `jwt` mode, e.g. the URL of a Keycloak realm
+    pub issuer: String,
+    /// `aud` that JWTs have to be meant for
+    pub audience: String,
+    /// where the identity provider publishes the keys JWTs are signed with
+    pub jwks_url: String,
+    pub jwks_refresh_minutes: u64,
+}
+
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMode {
+    /// every request is allowed, e.g. behind a proxy that authorizes them already
+    #[default]
+    None,
+    ApiKey,
+    Jwt,
+}
+
+impl Default for AuthSettings {
+    fn default() -> Self {
+        AuthSettings {
+            mode: AuthMode::default(),
+            api_keys: Vec::new(),
+            issuer: String::new(),
+            audience: String::new(),
+            jwks_url: String::new(),
+            jwks_refresh_minutes: 60,
+        }
+    }
+}
+
+impl AuthSettings {
+    fn validate(&self) -> anyhow::Result<()> {
+        match self.mode {
+            AuthMode::None => (),
+            AuthMode::ApiKey => {
+                if self.api_keys.iter().all(|key| key.is_empty()) {
+                    anyhow::bail!("auth.api_keys has to hold a key in api_key mode");
+                }
+            }
+            AuthMode::Jwt => {
+                if self.issuer.is_empty() || self.audience.is_empty() || self.jwks_url.is_empty() {
+                    anyhow::bail!(
+                        "auth.issuer, auth.audience and auth.jwks_url have to be set in jwt mode"
+                    );
+                }
+                if self.jwks_refresh_minutes == 0 {
+                    anyhow::bail!("auth.jwks_refresh_minutes must not be 0");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Spot prices of dynamic tariffs, fetched periodically and stored as their own series in
+/// `<sunny-home>/prices`, see prices.rs
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct PriceSettings {
+    pub source: PriceSource,
+    /// aWATTar's market data endpoint, e.g. https://api.awattar.at/v1/marketdata for Austria
+    pub awattar_url: String,
+    pub entsoe_url: String,
+    /// security token of the ENTSO-E transparency platform
+    pub entsoe_token: String,
+    /// EIC code of the bidding zone, e.g. 10Y1001A1001A82H for Germany and Luxembourg
+    pub entsoe_area: String,
+    pub fetch_interval_minutes: u64,
+    /// grid fees, taxes etc. in EUR per kWh added to the spot price of the energy from the grid
+    pub import_markup_per_kwh: f64,
+    /// added to the spot price of the energy fed into the grid, usually negative as the direct
+    /// marketer keeps a share
+    pub export_markup_per_kwh: f64,
+}
+
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PriceSource {
+    #[default]
+    None,
+    Awattar,
+    Entsoe,
+}
+
+impl Default for PriceSettings {
+    fn default() -> Self {
+        PriceSettings {
+            source: PriceSource::default(),
+            awattar_url: String::from("https://api.awattar.de/v1/marketdata"),
+            entsoe_url: String::from("https://web-api.tp.entsoe.eu/api"),
+            entsoe_token: String::new(),
+            entsoe_area: String::new(),
+            fetch_interval_minutes: 60,
+            import_markup_per_kwh: 0.0,
+            export_markup_per_kwh: 0.0,
+        }
+    }
+}
+
+impl PriceSettings {
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.source == PriceSource::Entsoe
+            && (self.entsoe_token.is_empty() || self.entsoe_area.is_empty())
+        {
+            anyhow::bail!("prices.entsoe_token and prices.entsoe_area have to be set for entsoe");
+        }
+        if self.fetch_interval_minutes == 0 {
+            anyhow::bail!("prices.fetch_interval_minutes must not be 0");
+        }
+        Ok(())
+    }
+}
+
+/// The UTC days values are accepted at when they're collected, imported or asked for; values
+/// outside of them most likely come from a clock that isn't set or are in the wrong unit
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimestampSettings {
+    pub earliest: NaiveDate,
+    /// values are accepted up to the start of this day
+    pub latest: NaiveDate,
+}
+
+impl Default for TimestampSettings {
+    fn default() -> Self {
+        let bounds = TimestampBounds::default();
+        let day = |ms: u64| {
+            chrono::DateTime::from_timestamp_millis(ms as i64)
+                .unwrap()
+                .date_naive()
+        };
+        TimestampSettings {
+            earliest: day(bounds.min_ms),
+            latest: day(bounds.max_ms),
+        }
+    }
+}
+
+impl TimestampSettings {
+    pub fn bounds(&self) -> anyhow::Result<TimestampBounds> {
+        let ms = |day: NaiveDate| {
+            u64::try_from(
+                day.and_time(Default::default())
+                    .and_utc()
+                    .timestamp_millis(),
+            )
+        };
+        let (Ok(min_ms), Ok(max_ms)) = (ms(self.earliest), ms(self.latest)) else {
+            anyhow::bail!("timestamps.earliest and timestamps.latest can't be before 1970");
+        };
+        if min_ms >= max_ms {
+            anyhow::bail!("timestamps.earliest has to be before timestamps.latest");
+        }
+        Ok(TimestampBounds { min_ms, max_ms })
+    }
+}
+
+/// The summary served via `GET /status.json` for embedding in other sites, see status.rs; it
+/// isn't subject to `[auth]`, so it's off unless enabled
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatusSettings {
+    pub enabled: bool,
+    /// if there are any, one of them has to be passed as `?token=`
+    pub share_tokens: Vec<String>,
+    /// requests answered per minute across all clients; further ones get 429
+    pub requests_per_minute: u32,
+    /// how long browsers and proxies may cache the summary
+    pub max_age_secs: u64,
+}
+
+impl Default for StatusSettings {
+    fn default() -> Self {
+        StatusSettings {
+            enabled: false,
+            share_tokens: Vec::new(),
+            requests_per_minute: 60,
+            max_age_secs: 30,
+        }
+    }
+}
+
+/// When `GET /health` reports the service as unhealthy, see health.rs
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthSettings {
+    /// seconds without a successful fetch from the inverter before it's answered with 503
+    pub max_failing_secs: u64,
+}
+
+impl Default for HealthSettings {
+    fn default() -> Self {
+        HealthSettings {
+            max_failing_secs: 300,
+        }
+    }
+}
+
+impl HealthSettings {
+    pub fn max_failing(&self) -> Duration {
+        Duration::from_secs(self.max_failing_secs)
+    }
+}
+
+/// Limits protecting the HTTP server from slow or idle clients, see server.rs; 0 disables a
+/// timeout
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSettings {
+    /// seconds a client has to send the headers of a request once it started sending it
+    pub header_read_timeout_secs: u64,
+    /// seconds after which connections without a request in flight are closed
+    pub idle_timeout_secs: u64,
+    /// maximum size of request bodies in bytes
+    pub max_body_bytes: usize,
+    /// maximum number of connections served at once; further ones wait to be accepted
+    pub max_connections: usize,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        ServerSettings {
+            header_read_timeout_secs: 10,
+            idle_timeout_secs: 60,
+            max_body_bytes: 64 * 1024,
+            max_connections: 256,
+        }
+    }
+}
+
+impl ServerSettings {
+    pub fn header_read_timeout(&self) -> Option<Duration> {
+        (self.header_read_timeout_secs > 0)
+            .then(|| Duration::from_secs(self.header_read_timeout_secs))
+    }
+
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        (self.idle_timeout_secs > 0).then(|| Duration::from_secs(self.idle_timeout_secs))
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SeriesQuota {
+    pub series: StoredSeries,
+    /// the oldest data of the series is pruned beyond this size
+    pub max_mb: Option<u64>,
+    /// series with a lower priority are pruned first when the disk is running full
+    #[serde(default)]
+    pub priority: u32,
+}
+
+/// The kinds of data sunny keeps on disk
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StoredSeries {
+    /// the segments of the measured values, including archived ones
+    Values,
+    /// the daily summaries
+    Summaries,
+}
+
+/// Units of the values the inverter reports in /status/powerflow; they're converted to W as
+/// soon as they're fetched
+#[derive(Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SourceSettings {
+    /// unit of P_PV
+    pub pv: FieldUnit,
+    /// unit of P_Grid
+    pub grid: FieldUnit,
+    /// unit of P_Load
+    pub load: FieldUnit,
+}
+
+impl SourceSettings {
+    fn validate(&self) -> anyhow::Result<()> {
+        self.pv.validate("pv")?;
+        self.grid.validate("grid")?;
+        self.load.validate("load")
+    }
+}
+
+/// Unit of a field of the source: a power like `"kW"` or a current together with the voltage
+/// it's measured at, like `{ unit = "A", voltage = 230 }`
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq)]
+#[serde(from = "FieldUnitSpec")]
+pub struct FieldUnit {
+    pub unit: Unit,
+    /// in V; only for currents
+    pub voltage: Option<f64>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FieldUnitSpec {
+    Unit(Unit),
+    WithVoltage(UnitWithVoltage),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UnitWithVoltage {
+    unit: Unit,
+    voltage: Option<f64>,
+}
+
+impl From<FieldUnitSpec> for FieldUnit {
+    fn from(spec: FieldUnitSpec) -> Self {
+        match spec {
+            FieldUnitSpec::Unit(unit) => FieldUnit {
+                unit,
+                voltage: None,
+            },
+            FieldUnitSpec::WithVoltage(UnitWithVoltage { unit, voltage }) => {
+                FieldUnit { unit, voltage }
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    #[default]
+    W,
+    #[serde(rename = "kW")]
+    KW,
+    MW,
+    A,
+}
+
+impl FieldUnit {
+    fn validate(&self, field: &str) -> anyhow::Result<()> {
+        match (self.unit, self.voltage) {
+            (Unit::A, None) => anyhow::bail!(
+                "source.{} is a current, so the voltage to convert it to W is needed as well",
+                field
+            ),
+            (Unit::A, Some(voltage)) if voltage <= 0.0 || !voltage.is_finite() => {
+                anyhow::bail!("source.{}.voltage has to be positive", field)
+            }
+            (Unit::A, Some(_)) => Ok(()),
+            (unit, Some(_)) => anyhow::bail!(
+                "source.{} is a power in {:?}, so it can't have a voltage",
+                field,
+                unit
+            ),
+            (_, None) => Ok(()),
+        }
+    }
+
+    /// converts a value of this unit to W
+    pub fn to_watts(self, value: f64) -> f64 {
+        match self.unit {
+            Unit::W => value,
+            Unit::KW => value * 1e3,
+            Unit::MW => value * 1e6,
+            Unit::A => value * self.voltage.unwrap_or(0.0),
+        }
+    }
+}
+
+/// A rollup as defined in the config, in the style of a Prometheus recording rule, e.g.
+/// `record = "pv_hourly"` and `expr = "avg_over_time(power_pv[1h])"`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RollupRule {
+    /// name under which the rollup is served
+    pub record: String,
+    /// `<aggregation>_over_time(<source expression>[<interval>])`
+    pub expr: String,
+}
+
+impl Config {
+    /// the timezone used to determine local days
+    pub fn timezone(&self) -> &str {
+        self.schedule
+            .timezone
+            .as_deref()
+            .unwrap_or(&self.frontend.timezone)
+    }
+
+    /// reads the config from the given path; without a path, the defaults are used
+    pub fn load(path: Option<&str>) -> anyhow::Result<Config> {
+        let Some(path) = path else {
+            return Ok(Config::default());
+        };
+
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Couldn't read config file {}", path))?;
+        Config::from_toml(&contents).with_context(|| format!("Invalid config file {}", path))
+    }
+
+    pub fn from_toml(contents: &str) -> anyhow::Result<Config> {
+        let config: Config = toml::from_str(contents)?;
+        if !(1..=28).contains(&config.billing.period_start_day) {
+            anyhow::bail!("billing.period_start_day has to be between 1 and 28");
+        }
+        if config.billing.peak_window_minutes == 0 {
+            anyhow::bail!("billing.peak_window_minutes must not be 0");
+        }
+        config.source.validate()?;
+        config.sampling.validate()?;
+        config.auth.validate()?;
+        config.prices.validate()?;
+        config.api.precision.validate()?;
+        config.api.legacy_sunset()?;
+        config.timestamps.bounds()?;
+        if config.remote.dir.is_some() && config.remote.endpoint.is_some() {
+            anyhow::bail!("remote.dir and remote.endpoint can't both be set");
+        }
+        if config.remote.endpoint.is_some() && config.remote.bucket.is_empty() {
+            anyhow::bail!("remote.bucket has to be set for remote.endpoint");
+        }
+        if config.storage.catalog && !cfg!(feature = "catalog") {
+            anyhow::bail!("storage.catalog needs sunny to be built with the catalog feature");
+        }
+        if config.updates.interval_hours == 0 {
+            anyhow::bail!("updates.interval_hours must not be 0");
+        }
+        if config.live.buffer_size == 0 {
+            anyhow::bail!("live.buffer_size must not be 0");
+        }
+        if config.server.max_connections == 0 {
+            anyhow::bail!("server.max_connections must not be 0");
+        }
+        if config.status.requests_per_minute == 0 {
+            anyhow::bail!("status.requests_per_minute must not be 0");
+        }
+        // it would be served to anyone, bypassing the authorization
+        if config.status.enabled
+            && config.auth.mode != AuthMode::None
+            && config.status.share_tokens.is_empty()
+        {
+            anyhow::bail!("status.share_tokens have to be set to enable the status with [auth]");
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source_units() {
+        let config = Config::from_toml("[source]\nload = \"MW\"").unwrap();
+        assert_eq!(config.source.load.to_watts(-0.5), -5e5);
+        assert_eq!(config.source.pv.to_watts(100.0), 100.0);
+
+        // currents need a voltage, powers mustn't have one
+        assert!(Config::from_toml("[source]\ngrid = \"A\"").is_err());
+        assert!(Config::from_toml("[source]\ngrid = { unit = \"A\", voltage = 0 }").is_err());
+        assert!(Config::from_toml("[source]\ngrid = { unit = \"kW\", voltage = 230 }").is_err());
+        assert!(Config::from_toml("[source]\ngrid = \"kWh\"").is_err());
+    }
+
+    #[test]
+    fn test_precision() {
+        let config = Config::from_toml("[api.precision]\npower_pv = 1\npower_used = 0").unwrap();
+        let mut json = serde_json::json!({
+            "values": [[1717200000000u64, {"power_pv": 1234.5678, "power_used": 99.5, "power_to_grid": 0.123456}]],
+            "energy_kwh": {"power_pv": 1.23456789, "power_used": null},
+        });
+        config.api.precision.apply(&mut json);
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "values": [[1717200000000u64, {"power_pv": 1234.6, "power_used": 100.0, "power_to_grid": 0.123456}]],
+                "energy_kwh": {"power_pv": 1.2346, "power_used": null},
+            })
+        );
+
+        assert!(Config::from_toml("[api.precision]\npower = 1").is_err());
+        assert!(Config::from_toml("[api.precision]\npower_pv = 20").is_err());
+    }
+
+    #[test]
+    fn test_sampling_settings() {
+        let config =
+            Config::from_toml("[sampling]\nadaptive = true\nmin_interval_ms = 500").unwrap();
+        assert!(config.sampling.adaptive);
+        assert_eq!(config.sampling.max_interval_ms, 30_000);
+
+        assert!(Config::from_toml("[sampling]\nmin_interval_ms = 0").is_err());
+        assert!(Config::from_toml("[sampling]\nmax_interval_ms = 500").is_err());
+        assert!(Config::from_toml("[sampling]\nthreshold_watts = -1.0").is_err());
+    }
+
+    #[test]
+    fn test_remote_settings() {
+        let config = Config::from_toml("[remote]\nafter_months = 12\ndir = \"/mnt/nas\"").unwrap();
+        assert_eq!(config.remote.after_months, Some(12));
+        assert_eq!(config.remote.offload_at, "03:30");
+
+        let both = "[remote]\ndir = \"/mnt/nas\"\nendpoint = \"http://minio:9000\"\nbucket = \"b\"";
+        assert!(Config::from_toml(both).is_err());
+        assert!(Config::from_toml("[remote]\nendpoint = \"http://minio:9000\"").is_err());
+    }
+
+    #[test]
+    fn test_timestamp_settings() {
+        let defaults = Config::default().timestamps;
+        assert_eq!(
+            defaults.earliest,
+            NaiveDate::from_ymd_opt(2000, 1, 1).unwrap()
+        );
+        assert_eq!(defaults.bounds().unwrap(), TimestampBounds::default());
+
+        let config = Config::from_toml("[timestamps]\nearliest = \"2020-01-01\"").unwrap();
+        let bounds = config.timestamps.bounds().unwrap();
+        assert_eq!(bounds.min_ms, 1577836800000);
+        assert_eq!(bounds.max_ms, TimestampBounds::default().max_ms);
+
+        assert!(Config::from_toml("[timestamps]\nearliest = \"1969-12-31\"").is_err());
+        assert!(Config::from_toml("[timestamps]\nlatest = \"1999-01-01\"").is_err());
+    }
+}
@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+use sunny_db::statistics::Correlation;
+use sunny_db::timeseries::{TimeSeries, TimeSeriesView};
+
+use crate::PowerValues;
+
+/// The fields of the values, e.g. `?a=power_pv&b=power_used`
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum PowerField {
+    #[serde(rename = "power_pv")]
+    Pv,
+    #[serde(rename = "power_to_grid")]
+    ToGrid,
+    #[serde(rename = "power_from_grid")]
+    FromGrid,
+    #[serde(rename = "power_used")]
+    Used,
+}
+
+impl PowerField {
+    fn of(self, values: &PowerValues) -> f64 {
+        match self {
+            PowerField::Pv => values.power_pv,
+            PowerField::ToGrid => values.power_to_grid,
+            PowerField::FromGrid => values.power_from_grid,
+            PowerField::Used => values.power_used,
+        }
+    }
+}
+
+/// Query parameters of `GET /correlation/:start_time/:end_time`
+#[derive(Deserialize)]
+pub struct CorrelationParams {
+    pub a: PowerField,
+    pub b: PowerField,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct FieldCorrelation {
+    pub a: PowerField,
+    pub b: PowerField,
+    /// Pearson's correlation coefficient between -1 and 1; null without enough values or if
+    /// either field is constant
+    pub correlation: Option<f64>,
+}
+
+/// the series of a single field
+pub fn field_series(series: TimeSeriesView<'_, PowerValues>, field: PowerField) -> TimeSeries<f64> {
+    let mut values = TimeSeries::<f64>::with_resolution(series.len(), series.get_resolution());
+    for (time, value) in series.iter() {
+        values.insert_value_at_time(time, field.of(value));
+    }
+    values
+}
+
+/// how the two fields of the values are correlated
+pub fn correlate(
+    series: TimeSeriesView<'_, PowerValues>,
+    params: CorrelationParams,
+) -> FieldCorrelation {
+    let a = field_series(series, params.a);
+    let b = field_series(series, params.b);
+    FieldCorrelation {
+        a: params.a,
+        b: params.b,
+        correlation: a.correlate(&b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_correlate() {
+        let hour = 3_600_000;
+        let mut series = TimeSeries::<PowerValues>::new(24);
+        for h in 0..24 {
+            let power_pv = match h {
+                6..=18 => 1000.0 * (1.0 - ((h as f64 - 12.0) / 6.0).powi(2)),
+                _ => 0.0,
+            };
+            // the grid covers what the PV doesn't
+            let power_from_grid = (500.0 - power_pv).max(0.0);
+            let values = PowerValues {
+                power_pv,
+                power_to_grid: (power_pv - 500.0).max(0.0),
+                power_from_grid,
+                power_used: 500.0,
+            };
+            series.insert_value_at_time(h * hour, values);
+        }
+
+        let params = |a, b| CorrelationParams { a, b };
+        let pv_grid = correlate(series.view(), params(PowerField::Pv, PowerField::FromGrid));
+        assert!(pv_grid.correlation.unwrap() < -0.9);
+        let pv_export = correlate(series.view(), params(PowerField::Pv, PowerField::ToGrid));
+        assert!(pv_export.correlation.unwrap() > 0.9);
+        // the consumption is constant
+        let pv_used = correlate(series.view(), params(PowerField::Pv, PowerField::Used));
+        assert_eq!(pv_used.correlation, None);
+        assert_eq!(
+            serde_json::to_value(&pv_used).unwrap(),
+            serde_json::json!({"a": "power_pv", "b": "power_used", "correlation": null})
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::HeaderValue,
+    middleware::Next,
+    response::Response,
+};
+use chrono::NaiveDate;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// Deprecation notices of the legacy, unprefixed paths of the data routes (see `[api]`),
+/// which also count how often each of them is still used
+pub struct Deprecation {
+    prefix: String,
+    sunset: Option<NaiveDate>,
+    usage: Mutex<BTreeMap<String, u64>>,
+}
+
+impl Deprecation {
+    pub fn new(prefix: String, sunset: Option<NaiveDate>) -> Self {
+        Deprecation {
+            prefix,
+            sunset,
+            usage: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// number of requests per legacy route, keyed by the route's path pattern
+    pub fn usage(&self) -> BTreeMap<String, u64> {
+        self.usage.lock().unwrap().clone()
+    }
+
+    fn record(&self, route: &str) {
+        *self
+            .usage
+            .lock()
+            .unwrap()
+            .entry(route.to_owned())
+            .or_default() += 1;
+    }
+
+    /// the headers telling clients to move to the prefixed path
+    fn headers(&self, path: &str) -> Vec<(&'static str, String)> {
+        let successor = format!("{}{}", self.prefix, path);
+        let mut headers = vec![
+            (
+                "warning",
+                format!(
+                    "299 - \"Deprecated path, use {} instead\"",
+                    successor.replace('"', "")
+                ),
+            ),
+            (
+                "link",
+                format!("<{}>; rel=\"successor-version\"", successor),
+            ),
+        ];
+        if let Some(sunset) = self.sunset {
+            // an HTTP-date, e.g. "Fri, 01 Jan 2027 00:00:00 GMT"
+            let sunset = sunset.format("%a, %d %b %Y 00:00:00 GMT").to_string();
+            headers.push(("sunset", sunset));
+        }
+        headers
+    }
+}
+
+/// middleware of the legacy routes, adding the deprecation headers to their responses
+pub async fn warn_legacy(
+    State(deprecation): State<Arc<Deprecation>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_owned())
+        .unwrap_or_else(|| request.uri().path().to_owned());
+    deprecation.record(&route);
+    let headers = deprecation.headers(request.uri().path());
+    let mut response = next.run(request).await;
+    for (name, value) in headers {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            response.headers_mut().insert(name, value);
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deprecation_headers() {
+        let sunset = NaiveDate::from_ymd_opt(2027, 1, 1);
+        let deprecation = Deprecation::new(String::from("/api/v1"), sunset);
+        assert_eq!(
+            deprecation.headers("/values/0/10"),
+            vec![
+                (
+                    "warning",
+                    String::from("299 - \"Deprecated path, use /api/v1/values/0/10 instead\"")
+                ),
+                (
+                    "link",
+                    String::from("</api/v1/values/0/10>; rel=\"successor-version\"")
+                ),
+                ("sunset", String::from("Fri, 01 Jan 2027 00:00:00 GMT")),
+            ]
+        );
+
+        deprecation.record("/values/:start_time/:end_time");
+        deprecation.record("/values/:start_time/:end_time");
+        assert_eq!(deprecation.usage()["/values/:start_time/:end_time"], 2);
+    }
+}
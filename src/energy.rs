@@ -0,0 +1,100 @@
+use chrono::{NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use sunny_db::aggregates::AggregateInterval;
+use sunny_db::error::SunnyDbError;
+use sunny_db::timeseries_db::SunnyDB;
+
+use crate::summary::day_range;
+use crate::PowerValues;
+
+/// Intervals of `GET /energy/:interval/:start_time/:end_time`
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum EnergyInterval {
+    Hourly,
+    /// local days
+    Daily,
+}
+
+/// adds up the hourly energies per local day; days in timezones whose offset isn't a whole
+/// number of hours are approximated by the hours starting within them
+fn sum_per_local_day(
+    hourly: &[(u64, PowerValues)],
+    timezone: Tz,
+) -> Vec<(NaiveDate, u64, PowerValues)> {
+    let mut daily: Vec<(NaiveDate, u64, PowerValues)> = Vec::new();
+    for (time, energy) in hourly {
+        let Some(date) = Utc
+            .timestamp_millis_opt(*time as i64)
+            .single()
+            .map(|t| t.with_timezone(&timezone).date_naive())
+        else {
+            continue;
+        };
+        match daily.last_mut() {
+            Some((day, _, sum)) if *day == date => *sum = *sum + *energy,
+            _ => daily.push((date, day_range(date, timezone).0, *energy)),
+        }
+    }
+    daily
+}
+
+/// the energy in Wh per hour, or per local day, of the intervals starting within
+/// [start_time, end_time) in ms, read from the aggregates maintained by the database
+pub fn energy(
+    db: &SunnyDB<PowerValues>,
+    interval: EnergyInterval,
+    start_time: u64,
+    end_time: u64,
+    timezone: Tz,
+) -> Result<Vec<(u64, PowerValues)>, SunnyDbError> {
+    let hourly = |start, end| db.get_energy(AggregateInterval::Hour, start, end);
+    match interval {
+        EnergyInterval::Hourly => hourly(start_time, end_time),
+        EnergyInterval::Daily => {
+            let first_day = Utc
+                .timestamp_millis_opt(start_time as i64)
+                .single()
+                .map(|t| t.with_timezone(&timezone).date_naive());
+            let start = first_day.map_or(start_time, |d| day_range(d, timezone).0);
+            let days = sum_per_local_day(&hourly(start, end_time)?, timezone);
+            Ok(days
+                .into_iter()
+                .filter(|(_, day_start, _)| *day_start >= start_time)
+                .map(|(_, day_start, energy)| (day_start, energy))
+                .collect())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sum_per_local_day() {
+        let berlin: Tz = "Europe/Berlin".parse().unwrap();
+        let energy = |power_pv| PowerValues {
+            power_pv,
+            power_to_grid: 0.0,
+            power_from_grid: 0.0,
+            power_used: 0.0,
+        };
+        let hour = 3_600_000;
+        // 2024-06-01 21:00 UTC, i.e. 23:00 CEST, to 01:00 CEST the next day
+        let start = 1717275600000;
+        let hourly = [
+            (start, energy(1.0)),
+            (start + hour, energy(2.0)),
+            (start + 2 * hour, energy(4.0)),
+        ];
+        let daily = sum_per_local_day(&hourly, berlin);
+        assert_eq!(daily.len(), 2);
+        assert_eq!(daily[0].0, NaiveDate::from_ymd_opt(2024, 6, 1).unwrap());
+        assert_eq!(daily[0].2.power_pv, 1.0);
+        // 2024-06-02 00:00 CEST
+        assert_eq!(daily[1].1, start + hour);
+        assert_eq!(daily[1].2.power_pv, 6.0);
+    }
+}
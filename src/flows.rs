@@ -0,0 +1,88 @@
+use serde::Serialize;
+use sunny_db::timeseries::TimeSeriesView;
+
+use crate::{compute_statistics, PowerValues};
+
+pub const PV: &str = "pv";
+pub const GRID: &str = "grid";
+pub const LOAD: &str = "load";
+
+/// The energy flows between PV, grid and load within a range, as nodes and links of a Sankey
+/// diagram; there's no battery node since no battery values are recorded
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct EnergyFlows {
+    pub nodes: Vec<&'static str>,
+    pub links: Vec<EnergyFlow>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct EnergyFlow {
+    pub source: &'static str,
+    pub target: &'static str,
+    pub energy_kwh: f64,
+}
+
+/// splits the energy of the values into flows: what's fed into the grid comes from PV, the
+/// rest of the PV production is used directly and whatever is purchased goes to the load;
+/// null if there are too few values to integrate
+pub fn energy_flows(values: TimeSeriesView<'_, PowerValues>) -> Option<EnergyFlows> {
+    let energy = compute_statistics(values).energy_kwh?;
+    let pv_to_grid = energy.power_to_grid.clamp(0.0, energy.power_pv.max(0.0));
+    let pv_to_load = energy.power_pv.max(0.0) - pv_to_grid;
+    let grid_to_load = energy.power_from_grid.max(0.0);
+    let link = |source, target, energy_kwh| EnergyFlow {
+        source,
+        target,
+        energy_kwh,
+    };
+    Some(EnergyFlows {
+        nodes: vec![PV, GRID, LOAD],
+        links: vec![
+            link(PV, LOAD, pv_to_load),
+            link(PV, GRID, pv_to_grid),
+            link(GRID, LOAD, grid_to_load),
+        ],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sunny_db::timeseries::TimeSeries;
+
+    #[test]
+    fn test_energy_flows() {
+        let mut series = TimeSeries::<PowerValues>::new(3);
+        let hour = 3_600_000;
+        // 3 kW of PV, of which 1 kW is fed into the grid, for an hour; then PV drops to
+        // nothing while purchases rise to 1 kW
+        let values = [
+            (3000.0, 1000.0, 0.0),
+            (3000.0, 1000.0, 0.0),
+            (0.0, 0.0, 1000.0),
+        ];
+        for (i, (pv, to_grid, from_grid)) in values.into_iter().enumerate() {
+            series.insert_value_at_time(
+                i as u64 * hour,
+                PowerValues {
+                    power_pv: pv,
+                    power_to_grid: to_grid,
+                    power_from_grid: from_grid,
+                    power_used: pv - to_grid + from_grid,
+                },
+            );
+        }
+
+        let flows = energy_flows(series.view()).unwrap();
+        assert_eq!(flows.nodes, vec![PV, GRID, LOAD]);
+        let expected = [(PV, LOAD, 3.0), (PV, GRID, 1.5), (GRID, LOAD, 0.5)];
+        assert_eq!(flows.links.len(), expected.len());
+        for (link, (source, target, energy_kwh)) in flows.links.iter().zip(expected) {
+            assert_eq!((link.source, link.target), (source, target));
+            assert!((link.energy_kwh - energy_kwh).abs() < 1e-9, "{:?}", link);
+        }
+
+        let single = TimeSeries::<PowerValues>::new(1);
+        assert_eq!(energy_flows(single.view()), None);
+    }
+}
@@ -0,0 +1,257 @@
+use anyhow::{self, Context};
+use chrono::{LocalResult, NaiveDateTime, TimeZone};
+use chrono_tz::Tz;
+use sunny_db::timeseries::{Quality, TimeSeries};
+
+use crate::config::Config;
+use crate::migrate::{audited_import, write_series, DatabaseArgs};
+use crate::scheduler::parse_timezone;
+use crate::PowerValues;
+
+/// Interval assumed if the export has too few rows to tell
+const DEFAULT_INTERVAL_MS: u64 = 5 * 60 * 1000;
+
+const TIME_FORMATS: [&str; 4] = [
+    "%d.%m.%Y %H:%M",
+    "%d.%m.%Y %H:%M:%S",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%d %H:%M:%S",
+];
+
+#[derive(clap::Args, Debug)]
+pub struct ImportArgs {
+    // CSV file exported from Solar.web
+    #[arg(long)]
+    file: String,
+
+    // Timezone of the export's timestamps; defaults to the one of the config file
+    #[arg(long)]
+    timezone: Option<String>,
+
+    // Path to the config file
+    #[arg(long)]
+    config: Option<String>,
+
+    #[command(flatten)]
+    database: DatabaseArgs,
+}
+
+/// The indices of the energy columns of an export; the first column holds the timestamps
+#[derive(Default, Debug)]
+struct Columns {
+    pv: Option<usize>,
+    to_grid: Option<usize>,
+    from_grid: Option<usize>,
+    used: Option<usize>,
+}
+
+impl Columns {
+    /// recognizes the columns by their (English or German) names
+    fn find(header: &[String]) -> anyhow::Result<Columns> {
+        let mut columns = Columns::default();
+        for (i, name) in header.iter().enumerate().skip(1) {
+            let name = name.to_lowercase();
+            let matches = |keywords: &[&str]| keywords.iter().any(|k| name.contains(k));
+            let column = if matches(&["production", "produktion"]) {
+                &mut columns.pv
+            } else if matches(&["fed into", "feed in", "feed-in", "einspeis", "eingespeist"]) {
+                &mut columns.to_grid
+            } else if matches(&["purchased", "from grid", "bezug", "bezogen"]) {
+                &mut columns.from_grid
+            } else if matches(&["consumption", "verbrauch"]) {
+                &mut columns.used
+            } else {
+                continue;
+            };
+            column.get_or_insert(i);
+        }
+        if columns.pv.is_none() || columns.to_grid.is_none() || columns.from_grid.is_none() {
+            anyhow::bail!(
+                "Expected columns for the PV production, the energy fed into the grid and the energy purchased from it, found {:?}",
+                header
+            );
+        }
+        Ok(columns)
+    }
+}
+
+/// splits a line into its fields, without the quotes around them
+fn fields(line: &str, delimiter: char) -> Vec<String> {
+    line.split(delimiter)
+        .map(|f| f.trim().trim_matches('"').trim().to_owned())
+        .collect()
+}
+
+/// parses numbers like `1234.5` or, in exports using `;` as delimiter, `1.234,5`
+fn parse_number(field: &str, delimiter: char) -> Option<f64> {
+    if delimiter == ';' && field.contains(',') {
+        return field.replace('.', "").replace(',', ".").parse().ok();
+    }
+    field.parse().ok()
+}
+
+fn parse_local_time(field: &str) -> Option<NaiveDateTime> {
+    TIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(field, format).ok())
+}
+
+/// converts a local time to ms; of the two times during the change back from summer time,
+/// the one after `previous` is used
+fn to_millis(local: NaiveDateTime, timezone: Tz, previous: Option<u64>) -> Option<u64> {
+    let time = match timezone.from_local_datetime(&local) {
+        LocalResult::Single(t) => t,
+        LocalResult::Ambiguous(earlier, later) => {
+            if previous.is_some_and(|p| earlier.timestamp_millis() as u64 <= p) {
+                later
+            } else {
+                earlier
+            }
+        }
+        LocalResult::None => return None,
+    };
+    u64::try_from(time.timestamp_millis()).ok()
+}
+
+/// parses a CSV export of Solar.web, which lists the energy produced, consumed, fed into and
+/// purchased from the grid per interval (usually 5 minutes) in Wh or kWh with local
+/// timestamps; the energies are converted to average powers at the start of each interval,
+/// flagged as backfilled. Rows with missing values are skipped
+pub fn parse_csv(contents: &str, timezone: Tz) -> anyhow::Result<TimeSeries<PowerValues>> {
+    let mut lines = contents
+        .lines()
+        .map(|l| l.trim_start_matches('\u{feff}'))
+        .filter(|l| !l.trim().is_empty())
+        .peekable();
+    let header_line = lines.next().context("The file is empty")?;
+    let delimiter = if header_line.contains(';') { ';' } else { ',' };
+    let header = fields(header_line, delimiter);
+    let columns = Columns::find(&header)?;
+
+    // an optional second header line holds the units
+    let mut scale = vec![1.0; header.len()];
+    if let Some(units) = lines.next_if(|l| parse_local_time(&fields(l, delimiter)[0]).is_none()) {
+        for (i, unit) in fields(units, delimiter)
+            .iter()
+            .enumerate()
+            .take(scale.len())
+        {
+            if unit.to_lowercase().contains("kwh") {
+                scale[i] = 1000.0;
+            }
+        }
+    }
+
+    let mut rows = Vec::new();
+    for line in lines {
+        let row = fields(line, delimiter);
+        let local =
+            parse_local_time(&row[0]).with_context(|| format!("Invalid timestamp '{}'", row[0]))?;
+        let previous = rows.last().map(|(time, _)| *time);
+        let Some(time) = to_millis(local, timezone, previous) else {
+            continue;
+        };
+        let energy = |column: Option<usize>| -> Option<f64> {
+            let column = column?;
+            Some(parse_number(row.get(column)?, delimiter)? * scale[column])
+        };
+        let (Some(pv), Some(to_grid), Some(from_grid)) = (
+            energy(columns.pv),
+            energy(columns.to_grid),
+            energy(columns.from_grid),
+        ) else {
+            continue;
+        };
+        let used = match columns.used {
+            Some(_) => energy(columns.used),
+            None => Some(pv - to_grid + from_grid),
+        };
+        let Some(used) = used else {
+            continue;
+        };
+        rows.push((
+            time,
+            PowerValues {
+                power_pv: pv,
+                power_to_grid: to_grid,
+                power_from_grid: from_grid,
+                power_used: used,
+            },
+        ));
+    }
+
+    // rows may be missing, so the shortest distance between them is the interval
+    let interval = rows
+        .windows(2)
+        .map(|w| w[1].0.saturating_sub(w[0].0))
+        .filter(|d| *d > 0)
+        .min()
+        .unwrap_or(DEFAULT_INTERVAL_MS);
+    // Wh per interval to W
+    let to_power = 3_600_000.0 / interval as f64;
+    let mut series = TimeSeries::<PowerValues>::new(rows.len());
+    for (time, energy) in rows {
+        series.insert_value_with_quality(time, energy * to_power, Quality::Backfilled);
+    }
+    Ok(series)
+}
+
+/// imports a Solar.web export into the database; returns the number of imported values
+pub fn run(args: &ImportArgs) -> anyhow::Result<usize> {
+    audited_import(&args.database, "import-fronius", &args.file, || {
+        let timezone = match &args.timezone {
+            Some(timezone) => parse_timezone(timezone)?,
+            None => parse_timezone(Config::load(args.config.as_deref())?.timezone())?,
+        };
+        let contents = std::fs::read_to_string(&args.file)
+            .with_context(|| format!("Couldn't read {}", args.file))?;
+        let series = parse_csv(&contents, timezone)?;
+        write_series(&args.database, &series, &args.file)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv() {
+        let berlin: Tz = "Europe/Berlin".parse().unwrap();
+        let export = "\u{feff}\"Datum und Uhrzeit\";\"Energie | Symo 8.2-3-M (1)\";\"PV Produktion\";\"Verbrauch\";\"Energie ins Netz eingespeist\";\"Energie vom Netz bezogen\"\n\
+            \"[dd.MM.yyyy HH:mm]\";\"[Wh]\";\"[Wh]\";\"[Wh]\";\"[Wh]\";\"[Wh]\"\n\
+            \"01.06.2024 12:00\";\"250\";\"250\";\"100\";\"150\";\"0\"\n\
+            \"01.06.2024 12:05\";\"1.000,5\";\"1.000,5\";\"100\";\"900,5\";\"0\"\n\
+            \"01.06.2024 12:10\";\"\";\"\";\"\";\"\";\"\"\n\
+            \"01.06.2024 12:15\";\"0\";\"0\";\"50\";\"0\";\"50\"\n";
+        let series = parse_csv(export, berlin).unwrap();
+        assert_eq!(series.len(), 3);
+        // 2024-06-01 10:00 UTC
+        assert_eq!(series.get_start_time(), Some(1717236000000));
+        let values = series.get_current_values();
+        assert_eq!(values[0].1.power_pv, 3000.0);
+        assert_eq!(values[1].1.power_to_grid, 10806.0);
+        assert_eq!(values[2].1.power_from_grid, 600.0);
+        let qualities: Vec<Quality> = series
+            .view()
+            .iter_with_quality()
+            .map(|(_, _, q)| q)
+            .collect();
+        assert_eq!(qualities, vec![Quality::Backfilled; 3]);
+
+        // English exports in kWh without consumption column; 2024-10-27 02:30 happens twice
+        let export =
+            "Date and time,PV production,Energy fed into grid,Energy purchased from grid\n\
+            ,[kWh],[kWh],[kWh]\n\
+            27.10.2024 02:30,0,0,0.1\n\
+            27.10.2024 02:45,0,0,0.2\n\
+            27.10.2024 02:30,0,0,0.1\n";
+        let series = parse_csv(export, berlin).unwrap();
+        let values = series.get_current_values();
+        let times: Vec<u64> = values.iter().map(|(t, _)| *t).collect();
+        assert_eq!(times, vec![1729989000000, 1729989900000, 1729992600000]);
+        assert_eq!(values[0].1.power_from_grid, 400.0);
+        assert_eq!(values[0].1.power_used, 400.0);
+
+        assert!(parse_csv("Date and time,PV production\n", berlin).is_err());
+    }
+}
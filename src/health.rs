@@ -0,0 +1,104 @@
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Outcome of the latest fetches from the inverter, updated by the fetcher and reported via
+/// `GET /health`
+#[derive(Clone)]
+pub struct FetcherHealth {
+    started: Instant,
+    state: Arc<Mutex<FetcherState>>,
+}
+
+#[derive(Default)]
+struct FetcherState {
+    last_success: Option<Instant>,
+    consecutive_errors: u64,
+}
+
+/// Response of `GET /health`
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Health {
+    /// false if no fetch has succeeded for longer than `[health] max_failing_secs`, whether
+    /// they've been failing or the fetcher got stuck
+    pub healthy: bool,
+    pub uptime_secs: u64,
+    /// null if no fetch has succeeded yet
+    pub secs_since_last_fetch: Option<u64>,
+    pub consecutive_fetch_errors: u64,
+    /// values not written to a segment yet
+    pub in_memory_points: usize,
+}
+
+impl FetcherHealth {
+    pub fn new(started: Instant) -> Self {
+        FetcherHealth {
+            started,
+            state: Arc::default(),
+        }
+    }
+
+    pub fn record_success(&self, now: Instant) {
+        let mut state = self.state.lock().unwrap();
+        state.last_success = Some(now);
+        state.consecutive_errors = 0;
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_errors += 1;
+    }
+
+    pub fn report(&self, now: Instant, in_memory_points: usize, max_failing: Duration) -> Health {
+        let state = self.state.lock().unwrap();
+        let elapsed = |since: Instant| now.saturating_duration_since(since);
+        // a fetch that never returns is neither a success nor a failure, so it's the time
+        // without a success that counts rather than the failures
+        let succeeded = state.last_success.unwrap_or(self.started);
+        Health {
+            healthy: elapsed(succeeded) <= max_failing,
+            uptime_secs: elapsed(self.started).as_secs(),
+            secs_since_last_fetch: state.last_success.map(|time| elapsed(time).as_secs()),
+            consecutive_fetch_errors: state.consecutive_errors,
+            in_memory_points,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health() {
+        let start = Instant::now();
+        let minutes = |m: u64| start + Duration::from_secs(60 * m);
+        let max_failing = Duration::from_secs(300);
+        let health = FetcherHealth::new(start);
+        let report = health.report(minutes(1), 3, max_failing);
+        assert!(report.healthy);
+        assert_eq!(report.secs_since_last_fetch, None);
+        assert_eq!(report.in_memory_points, 3);
+
+        health.record_success(minutes(1));
+        health.record_failure();
+        health.record_failure();
+        let report = health.report(minutes(6), 0, max_failing);
+        assert!(report.healthy);
+        assert_eq!(report.uptime_secs, 6 * 60);
+        assert_eq!(report.secs_since_last_fetch, Some(5 * 60));
+        assert_eq!(report.consecutive_fetch_errors, 2);
+        // more than five minutes since the last successful fetch
+        assert!(!health.report(minutes(7), 0, max_failing).healthy);
+
+        health.record_success(minutes(9));
+        let report = health.report(minutes(9), 0, max_failing);
+        assert!(report.healthy);
+        assert_eq!(report.consecutive_fetch_errors, 0);
+        // without any fetches finishing, e.g. since the fetcher hangs
+        assert!(health.report(minutes(14), 0, max_failing).healthy);
+        assert!(!health.report(minutes(15), 0, max_failing).healthy);
+        let stuck = FetcherHealth::new(start);
+        assert!(!stuck.report(minutes(6), 0, max_failing).healthy);
+    }
+}
@@ -0,0 +1,133 @@
+use serde::{Deserialize, Serialize};
+use sunny_db::fields::FloatFields;
+use sunny_db::statistics::{ComponentwiseMinMax, Histogram};
+use sunny_db::timeseries::TimeSeriesView;
+
+use crate::PowerValues;
+
+/// Width of the bins in W unless asked for something else
+pub const DEFAULT_BIN_WIDTH: f64 = 500.0;
+/// Most bins a histogram is split into, so a tiny width doesn't blow up the response
+const MAX_BINS: usize = 1000;
+
+/// Query parameters of `GET /histogram/:start_time/:end_time`, e.g. `?bin_width=250`
+#[derive(Deserialize)]
+pub struct HistogramParams {
+    pub bin_width: Option<f64>,
+}
+
+/// How many hours each field was within [low, high) W
+#[derive(Serialize, Debug, PartialEq)]
+pub struct PowerHistogramBin {
+    pub low: f64,
+    pub high: f64,
+    pub hours: PowerValues,
+}
+
+/// bins of `bin_width` W from a multiple of it up to the lowest value of any field, up to the
+/// highest value; at least one bin. Bins too narrow to cover the values with `MAX_BINS` are
+/// widened to a multiple of the width
+fn edges(min: f64, max: f64, bin_width: f64) -> Vec<f64> {
+    let bins = |width: f64| {
+        let first = (min / width).floor().min(0.0);
+        let last = (max / width).ceil().max(first + 1.0);
+        (first, (last - first) as usize)
+    };
+    let mut factor = bins(bin_width).1.div_ceil(MAX_BINS).max(1) as f64;
+    // rounding to the wider bins can take one more of them
+    while bins(bin_width * factor).1 > MAX_BINS {
+        factor += 1.0;
+    }
+    let width = bin_width * factor;
+    let (first, bins) = bins(width);
+    (0..=bins).map(|i| (first + i as f64) * width).collect()
+}
+
+/// the time-weighted histogram of the values in bins of `bin_width` W, e.g. to size a heat
+/// pump or a battery; empty without values
+pub fn histogram(
+    series: TimeSeriesView<'_, PowerValues>,
+    bin_width: f64,
+) -> anyhow::Result<Vec<PowerHistogramBin>> {
+    if !(bin_width > 0.0 && bin_width.is_finite()) {
+        anyhow::bail!(
+            "The bin width has to be a positive number, not {}",
+            bin_width
+        );
+    }
+    let Some(extrema) = series.extrema() else {
+        return Ok(Vec::new());
+    };
+    // NaN is skipped by f64::min and f64::max
+    let fields = 0..PowerValues::COUNT;
+    let min = fields
+        .clone()
+        .map(|i| extrema.min.field(i))
+        .fold(f64::NAN, f64::min);
+    let max = fields
+        .map(|i| extrema.max.field(i))
+        .fold(f64::NAN, f64::max);
+    if min.is_nan() || max.is_nan() {
+        return Ok(Vec::new());
+    }
+
+    let to_hours = 1.0 / 3600.0 / series.get_resolution().per_second() as f64;
+    Ok(series
+        .histogram(&edges(min, max, bin_width))
+        .into_iter()
+        .map(|bin| PowerHistogramBin {
+            low: bin.low,
+            high: bin.high,
+            hours: bin.duration * to_hours,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sunny_db::timeseries::TimeSeries;
+
+    #[test]
+    fn test_histogram() {
+        let used = |power_used| PowerValues {
+            power_pv: 0.0,
+            power_to_grid: 0.0,
+            power_from_grid: power_used,
+            power_used,
+        };
+        let hour = 3_600_000;
+        let mut series = TimeSeries::<PowerValues>::new(10);
+        // 300 W for two hours, then 1.8 kW for one
+        for (h, power) in [(0, 300.0), (2, 300.0), (3, 1800.0), (4, 1800.0)] {
+            series.insert_value_at_time(h * hour, used(power));
+        }
+
+        let bins = histogram(series.view(), DEFAULT_BIN_WIDTH).unwrap();
+        assert_eq!(bins.len(), 4);
+        assert_eq!((bins[0].low, bins[3].high), (0.0, 2000.0));
+        let hours: Vec<f64> = bins.iter().map(|bin| bin.hours.power_used).collect();
+        // the ramp from 300 W to 1.8 kW takes an hour
+        let expected = [2.0 + 0.2 / 1.5, 1.0 / 3.0, 1.0 / 3.0, 1.2];
+        for (hours, expected) in hours.iter().zip(expected) {
+            assert!((hours - expected).abs() < 1e-9, "{:?}", bins);
+        }
+        // never producing anything puts all of the time into the lowest bin
+        assert!((bins[0].hours.power_pv - 4.0).abs() < 1e-9);
+
+        assert_eq!(edges(-120.0, 0.0, 100.0), vec![-200.0, -100.0, 0.0]);
+        assert_eq!(edges(0.0, 0.0, 100.0), vec![0.0, 100.0]);
+        // too many bins are widened rather than cut off at the highest values
+        let widened = edges(0.0, 1e9, 1.0);
+        assert_eq!(widened.len(), MAX_BINS + 1);
+        assert_eq!(widened[MAX_BINS], 1e9);
+        let widened = edges(-1e9, 1e9, 3.0);
+        assert!(widened.len() <= MAX_BINS + 1);
+        assert!(widened[0] <= -1e9 && widened[widened.len() - 1] >= 1e9);
+        assert_eq!((widened[1] - widened[0]) % 3.0, 0.0);
+        assert!(histogram(series.view(), 0.0).is_err());
+        assert!(histogram(TimeSeries::empty().view(), 1.0)
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,382 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use sunny_db::fields::FloatFields;
+use sunny_db::statistics::{
+    squared_trapezoid, ComponentwiseMinMax, QualityOfSeries, QualitySummary, TrapezoidalIntegral,
+};
+use sunny_db::timeseries::TimeSeriesView;
+
+use crate::kpi::Kpi;
+use crate::{DatabaseReadLock, PowerStatistics, PowerValues};
+
+/// Number of values a statistics job reads at once; the database is only locked while a
+/// chunk is read, so jobs don't hold up the collector or other queries
+const CHUNK_VALUES: usize = 50_000;
+/// How long the results of finished jobs are kept around to be fetched
+const RETENTION: Duration = Duration::from_secs(3600);
+/// Maximum number of jobs running at once, to keep a Pi responsive
+pub const MAX_RUNNING: usize = 2;
+
+/// Body of `POST /jobs/stats`
+#[derive(Deserialize, Debug)]
+pub struct StatsJobRequest {
+    pub start_time: u64,
+    pub end_time: u64,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(tag = "state", rename_all = "lowercase")]
+pub enum JobState {
+    /// `progress` is the share of the range processed so far
+    Running {
+        progress: f64,
+    },
+    Done {
+        result: Box<PowerStatistics>,
+    },
+}
+
+struct Job {
+    state: JobState,
+    finished_at: Option<Instant>,
+}
+
+#[derive(Default)]
+struct JobTable {
+    next_id: u64,
+    jobs: BTreeMap<u64, Job>,
+}
+
+/// Jobs computing statistics over ranges too large to be answered within a request, started
+/// via `POST /jobs/stats` and polled via `GET /jobs/:id`
+#[derive(Clone, Default)]
+pub struct Jobs {
+    table: Arc<Mutex<JobTable>>,
+}
+
+impl Jobs {
+    /// starts a job computing the statistics of the values in (start_time, end_time] and
+    /// returns its id; None if too many jobs are running already
+    pub fn start_stats(&self, db: DatabaseReadLock, start_time: u64, end_time: u64) -> Option<u64> {
+        let mut table = self.table.lock().unwrap();
+        table.jobs.retain(|_, job| {
+            job.finished_at
+                .is_none_or(|finished| finished.elapsed() < RETENTION)
+        });
+        let running = table.jobs.values().filter(|job| job.finished_at.is_none());
+        if running.count() >= MAX_RUNNING {
+            return None;
+        }
+
+        let id = table.next_id;
+        table.next_id += 1;
+        let job = Job {
+            state: JobState::Running { progress: 0.0 },
+            finished_at: None,
+        };
+        table.jobs.insert(id, job);
+
+        let jobs = self.clone();
+        tokio::spawn(async move {
+            let (start_time, end_time) = (start_time.min(end_time), start_time.max(end_time));
+            let result = Box::new(compute_in_chunks(&db, start_time, end_time, &jobs, id).await);
+            jobs.update(id, JobState::Done { result }, Some(Instant::now()));
+        });
+        Some(id)
+    }
+
+    /// the state of the job serialized as JSON, None if there's no such job (anymore)
+    pub fn state(&self, id: u64) -> Option<serde_json::Result<serde_json::Value>> {
+        let table = self.table.lock().unwrap();
+        let job = table.jobs.get(&id)?;
+        Some(serde_json::to_value(&job.state))
+    }
+
+    fn update(&self, id: u64, state: JobState, finished_at: Option<Instant>) {
+        if let Some(job) = self.table.lock().unwrap().jobs.get_mut(&id) {
+            job.state = state;
+            job.finished_at = finished_at;
+        }
+    }
+}
+
+async fn compute_in_chunks(
+    db: &DatabaseReadLock,
+    start_time: u64,
+    end_time: u64,
+    jobs: &Jobs,
+    id: u64,
+) -> PowerStatistics {
+    let mut statistics = StatisticsInChunks::default();
+    let mut cursor = None;
+    loop {
+        let page = {
+            let reader = db.read().await;
+            reader.get_values_in_range_paged(start_time, end_time, CHUNK_VALUES, cursor)
+        };
+        let Some(page) = page else {
+            break;
+        };
+        statistics.add(page.values.view());
+        let Some(next) = page.next else {
+            break;
+        };
+        cursor = Some(next);
+
+        let progress = (next - start_time) as f64 / (end_time - start_time) as f64;
+        jobs.update(id, JobState::Running { progress }, None);
+        tokio::task::yield_now().await;
+    }
+    statistics.finish()
+}
+
+/// The statistics of a series added chunk by chunk, which come out the same as those of
+/// `compute_statistics` over the whole series
+#[derive(Default)]
+struct StatisticsInChunks {
+    integral: Option<PowerValues>,
+    /// of each field, for the standard deviation
+    moments: [CentredMoments; PowerValues::COUNT],
+    mins: Option<PowerValues>,
+    nonzero_mins: Option<PowerValues>,
+    maxes: Option<PowerValues>,
+    quality: QualitySummary,
+    first_time: Option<u64>,
+    last: Option<(u64, PowerValues)>,
+    units_per_second: u64,
+}
+
+impl StatisticsInChunks {
+    /// adds the values of a chunk, which have to follow the ones added before
+    fn add(&mut self, chunk: TimeSeriesView<'_, PowerValues>) {
+        let Some((first_time, first)) = chunk.iter().next() else {
+            return;
+        };
+        self.units_per_second = chunk.get_resolution().per_second();
+        self.first_time.get_or_insert(first_time);
+
+        // the trapezoid between the last value of the previous chunk and the first of this one
+        let seam = self
+            .last
+            .map(|(time, value)| (value + *first) * ((first_time - time) as f64 * 0.5));
+        for integral in [seam, chunk.integrate()].into_iter().flatten() {
+            self.integral = Some(self.integral.map_or(integral, |sum| sum + integral));
+        }
+
+        // the intervals of the chunk, including the one from the previous chunk's last value
+        let intervals: Vec<_> = self
+            .last
+            .into_iter()
+            .chain(chunk.iter().map(|(time, value)| (time, *value)))
+            .collect();
+        for (i, moments) in self.moments.iter_mut().enumerate() {
+            moments.merge(CentredMoments::of_intervals(&intervals, i));
+        }
+
+        let extrema = chunk.extrema();
+        self.mins = combine_fields(self.mins, extrema.map(|e| e.min), f64::min);
+        self.nonzero_mins =
+            combine_fields(self.nonzero_mins, extrema.map(|e| e.nonzero_min), f64::min);
+        self.maxes = combine_fields(self.maxes, extrema.map(|e| e.max), f64::max);
+
+        let quality = chunk.quality_summary();
+        self.quality.measured += quality.measured;
+        self.quality.interpolated += quality.interpolated;
+        self.quality.backfilled += quality.backfilled;
+        self.quality.suspect += quality.suspect;
+
+        self.last = chunk.iter().next_back().map(|(time, value)| (time, *value));
+    }
+
+    fn finish(self) -> PowerStatistics {
+        let duration = match (self.first_time, self.last) {
+            (Some(first_time), Some((last_time, _))) if last_time > first_time => {
+                Some((last_time - first_time) as f64)
+            }
+            _ => None,
+        };
+        // can't integrate over a single value
+        let integral = duration.and(self.integral);
+        let units_per_second = self.units_per_second as f64;
+        let average = integral.zip(duration).map(|(e, d)| e / d);
+        let energy_kwh = integral.map(|e| e * (1e-3 / 3600.0 / units_per_second));
+        let std_dev = average.zip(duration).map(|(_, duration)| {
+            let fields: Vec<f64> = self
+                .moments
+                .iter()
+                .map(|moments| (moments.squares / duration).sqrt())
+                .collect();
+            PowerValues::from_fields(&fields)
+        });
+        PowerStatistics {
+            average,
+            mins: self.mins,
+            nonzero_mins: self.nonzero_mins,
+            maxes: self.maxes,
+            // would need all values of the range at once
+            p95: None,
+            std_dev,
+            energy_kwh,
+            kpi: energy_kwh.as_ref().and_then(Kpi::from_energy),
+            excluded_ms: 0,
+            quality: self.quality.into(),
+        }
+    }
+}
+
+/// The time-weighted mean of a field over some intervals and the integral over the square of
+/// its deviation from that mean, which are combined with those of the following intervals
+/// like in Welford's algorithm, so large values don't cancel out like in the mean of the
+/// squares less the square of the mean
+#[derive(Default, Clone, Copy)]
+struct CentredMoments {
+    duration: f64,
+    mean: f64,
+    squares: f64,
+}
+
+impl CentredMoments {
+    /// those of the field over the intervals between consecutive values, taking it to change
+    /// linearly like `integrate` does
+    fn of_intervals(values: &[(u64, PowerValues)], field: usize) -> CentredMoments {
+        let intervals = || {
+            values.windows(2).map(|pair| {
+                let ((t_0, f_0), (t_1, f_1)) = (pair[0], pair[1]);
+                (f_0.field(field), f_1.field(field), (t_1 - t_0) as f64)
+            })
+        };
+        let duration: f64 = intervals().map(|(_, _, dt)| dt).sum();
+        if duration <= 0.0 {
+            return CentredMoments::default();
+        }
+        let mean = intervals()
+            .map(|(f_0, f_1, dt)| (f_0 + f_1) * 0.5 * dt)
+            .sum::<f64>()
+            / duration;
+        let squares = intervals()
+            .map(|(f_0, f_1, dt)| squared_trapezoid(f_0 - mean, f_1 - mean, dt))
+            .sum();
+        CentredMoments {
+            duration,
+            mean,
+            squares,
+        }
+    }
+
+    /// adds those of the following intervals
+    fn merge(&mut self, other: CentredMoments) {
+        let duration = self.duration + other.duration;
+        if duration <= 0.0 {
+            return;
+        }
+        let delta = other.mean - self.mean;
+        self.squares += other.squares + delta * delta * self.duration * other.duration / duration;
+        self.mean += delta * other.duration / duration;
+        self.duration = duration;
+    }
+}
+
+/// combines the values field by field, e.g. the maxes of two chunks into those of both
+fn combine_fields(
+    a: Option<PowerValues>,
+    b: Option<PowerValues>,
+    f: fn(f64, f64) -> f64,
+) -> Option<PowerValues> {
+    match (a, b) {
+        (Some(a), Some(b)) => {
+            let fields: Vec<f64> = (0..PowerValues::COUNT)
+                .map(|i| f(a.field(i), b.field(i)))
+                .collect();
+            Some(PowerValues::from_fields(&fields))
+        }
+        (a, b) => a.or(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute_statistics;
+    use sunny_db::timeseries::TimeSeries;
+
+    #[test]
+    fn test_statistics_in_chunks() {
+        let mut series = TimeSeries::<PowerValues>::new(100);
+        for i in 0..50u64 {
+            let x = i as f64;
+            series.insert_value_at_time(
+                i * 1000 + (i % 3) * 100,
+                PowerValues {
+                    power_pv: (x * 0.3).sin() * 3000.0,
+                    power_to_grid: x * 10.0,
+                    power_from_grid: 500.0 - x,
+                    power_used: (x * 0.1).cos() * 1000.0,
+                },
+            );
+        }
+
+        let mut statistics = StatisticsInChunks::default();
+        let values: Vec<_> = series.iter().map(|(t, v)| (t, *v)).collect();
+        for chunk in values.chunks(7) {
+            let mut part = TimeSeries::<PowerValues>::new(7);
+            for (time, value) in chunk {
+                part.insert_value_at_time(*time, *value);
+            }
+            statistics.add(part.view());
+        }
+        let chunked = serde_json::to_value(statistics.finish()).unwrap();
+        let whole = serde_json::to_value(compute_statistics(series.view())).unwrap();
+        for field in [
+            "average",
+            "mins",
+            "nonzero_mins",
+            "maxes",
+            "std_dev",
+            "energy_kwh",
+        ] {
+            for (name, value) in whole[field].as_object().unwrap() {
+                let chunked = chunked[field][name].as_f64().unwrap();
+                let value = value.as_f64().unwrap();
+                assert!(
+                    (chunked - value).abs() < 1e-9 * value.abs().max(1.0),
+                    "{}",
+                    name
+                );
+            }
+        }
+        assert_eq!(chunked["quality"], whole["quality"]);
+
+        // a small spread of large values doesn't get lost in the squares of the values
+        let mut large = TimeSeries::<PowerValues>::new(100);
+        for i in 0..20u64 {
+            let offset = (i % 2) as f64;
+            let value = PowerValues {
+                power_pv: 1e9 + offset,
+                ..Default::default()
+            };
+            large.insert_value_at_time(i * 1000, value);
+        }
+        let mut statistics = StatisticsInChunks::default();
+        let values: Vec<_> = large.iter().map(|(t, v)| (t, *v)).collect();
+        for chunk in values.chunks(3) {
+            let mut part = TimeSeries::<PowerValues>::new(3);
+            for (time, value) in chunk {
+                part.insert_value_at_time(*time, *value);
+            }
+            statistics.add(part.view());
+        }
+        let chunked = statistics.finish().std_dev.unwrap().power_pv;
+        let whole = compute_statistics(large.view()).std_dev.unwrap().power_pv;
+        assert!(
+            whole > 0.0 && (chunked - whole).abs() < 1e-6 * whole,
+            "{}",
+            chunked
+        );
+
+        let single = StatisticsInChunks::default();
+        let statistics = single.finish();
+        assert!(statistics.average.is_none() && statistics.energy_kwh.is_none());
+    }
+}
@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+
+use crate::PowerValues;
+
+/// How much of the PV production is used on site and how much of the consumption it covers
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Kpi {
+    /// PV energy used directly in kWh, i.e. not fed into the grid
+    pub self_consumed_kwh: f64,
+    /// consumption not drawn from the grid in kWh
+    pub self_supplied_kwh: f64,
+    /// self-consumed share of the PV production; null without production
+    pub self_consumption: Option<f64>,
+    /// self-supplied share of the consumption; null without consumption
+    pub autarky: Option<f64>,
+}
+
+impl Kpi {
+    /// from the energy of each field in kWh; None if any of them is missing
+    pub fn from_energy(energy: &PowerValues) -> Option<Self> {
+        let PowerValues {
+            power_pv: pv,
+            power_to_grid: to_grid,
+            power_from_grid: from_grid,
+            power_used: used,
+        } = *energy;
+        if [pv, to_grid, from_grid, used].iter().any(|e| e.is_nan()) {
+            return None;
+        }
+        let self_consumed = (pv - to_grid).max(0.0);
+        let self_supplied = (used - from_grid).max(0.0);
+        Some(Kpi {
+            self_consumed_kwh: self_consumed,
+            self_supplied_kwh: self_supplied,
+            self_consumption: (pv > 0.0).then(|| (self_consumed / pv).min(1.0)),
+            autarky: (used > 0.0).then(|| (self_supplied / used).min(1.0)),
+        })
+    }
+}
+
+/// Response of `GET /kpi/:start_time/:end_time`
+#[derive(Serialize, Debug, PartialEq)]
+pub struct RangeKpi {
+    pub energy_kwh: Option<PowerValues>,
+    /// null without energy
+    pub kpi: Option<Kpi>,
+}
+
+impl RangeKpi {
+    pub fn new(energy_kwh: Option<PowerValues>) -> Self {
+        RangeKpi {
+            energy_kwh,
+            kpi: energy_kwh.as_ref().and_then(Kpi::from_energy),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kpi() {
+        let energy = PowerValues {
+            power_pv: 10.0,
+            power_to_grid: 6.0,
+            power_from_grid: 2.0,
+            power_used: 6.0,
+        };
+        let kpi = Kpi::from_energy(&energy).unwrap();
+        assert_eq!(kpi.self_consumed_kwh, 4.0);
+        assert_eq!(kpi.self_supplied_kwh, 4.0);
+        assert_eq!(kpi.self_consumption, Some(0.4));
+        assert_eq!(kpi.autarky, Some(4.0 / 6.0));
+
+        let night = PowerValues {
+            power_pv: 0.0,
+            power_to_grid: 0.0,
+            power_from_grid: 1.0,
+            power_used: 1.0,
+        };
+        let kpi = Kpi::from_energy(&night).unwrap();
+        assert_eq!(kpi.self_consumption, None);
+        assert_eq!(kpi.autarky, Some(0.0));
+
+        let missing = PowerValues {
+            power_pv: f64::NAN,
+            ..night
+        };
+        assert_eq!(Kpi::from_energy(&missing), None);
+        assert_eq!(RangeKpi::new(None).kpi, None);
+    }
+}
@@ -0,0 +1,128 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use sunny_db::statistics::Average;
+use sunny_db::timeseries::TimeSeries;
+
+use crate::stream::{self, NewValues};
+use crate::PowerValues;
+
+/// The most recent samples as fetched from the inverter, before they're averaged for storage,
+/// served via `GET /live` so the frontend can display them at the full sampling rate, and
+/// pushed to the subscribers of the `samples` channel of `GET /ws` as they come in
+#[derive(Clone)]
+pub struct LiveBuffer {
+    samples: Arc<Mutex<VecDeque<(u64, PowerValues)>>>,
+    capacity: usize,
+    new_samples: NewValues<PowerValues>,
+}
+
+impl LiveBuffer {
+    pub fn new(capacity: usize) -> Self {
+        LiveBuffer {
+            samples: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+            new_samples: stream::new_values(),
+        }
+    }
+
+    /// adds a sample, dropping the oldest one once the buffer is full
+    pub fn push(&self, time: u64, value: PowerValues) {
+        let mut samples = self.samples.lock().unwrap();
+        if samples.len() >= self.capacity {
+            samples.pop_front();
+        }
+        samples.push_back((time, value));
+        // fails if no client is listening
+        let _ = self.new_samples.send((time, value));
+    }
+
+    /// the channel the samples are pushed to as they're added
+    pub fn new_samples(&self) -> &NewValues<PowerValues> {
+        &self.new_samples
+    }
+
+    /// the bytes held by the buffer, which reserves room for all of its samples up front
+    pub fn memory_usage(&self) -> usize {
+        self.samples.lock().unwrap().capacity() * std::mem::size_of::<(u64, PowerValues)>()
+    }
+
+    /// the buffered samples taken after `after` (in ms), oldest first
+    pub fn samples_after(&self, after: u64) -> Vec<(u64, PowerValues)> {
+        let samples = self.samples.lock().unwrap();
+        let start = samples.partition_point(|(time, _)| *time <= after);
+        samples.range(start..).copied().collect()
+    }
+}
+
+/// Collects the samples fetched from the inverter: every one of them goes to the live buffer
+/// right away, while only their average over `average_over` samples is stored, so the display
+/// rate doesn't depend on how much ends up on disk
+pub struct Decimator {
+    average_over: usize,
+    samples: TimeSeries<PowerValues>,
+    live: LiveBuffer,
+}
+
+impl Decimator {
+    pub fn new(average_over: usize, live: LiveBuffer) -> Self {
+        Decimator {
+            average_over,
+            samples: TimeSeries::<PowerValues>::new(average_over),
+            live,
+        }
+    }
+
+    /// adds a sample taken at `time` (in ms); returns the average to store once
+    /// `average_over` samples have come together
+    pub fn push(&mut self, time: u64, value: PowerValues) -> Option<PowerValues> {
+        self.samples.insert_value_at_time(time, value);
+        self.live.push(time, value);
+        if self.samples.len() < self.average_over {
+            return None;
+        }
+        let average = self.samples.average();
+        self.samples = TimeSeries::<PowerValues>::new(self.average_over);
+        average
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn power(power_pv: f64) -> PowerValues {
+        PowerValues {
+            power_pv,
+            power_to_grid: 0.0,
+            power_from_grid: 0.0,
+            power_used: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_live_buffer() {
+        let live = LiveBuffer::new(3);
+        for time in 1..=4 {
+            live.push(time * 1000, power(time as f64));
+        }
+        // the oldest sample was dropped
+        assert_eq!(live.samples_after(0).len(), 3);
+        assert_eq!(
+            live.samples_after(2000),
+            vec![(3000, power(3.0)), (4000, power(4.0))]
+        );
+        assert!(live.samples_after(4000).is_empty());
+    }
+
+    #[test]
+    fn test_decimator() {
+        let live = LiveBuffer::new(10);
+        let mut decimator = Decimator::new(3, live.clone());
+        assert_eq!(decimator.push(1000, power(100.0)), None);
+        assert_eq!(decimator.push(2000, power(300.0)), None);
+        // every sample is live, only their average is stored
+        assert_eq!(decimator.push(3000, power(100.0)), Some(power(200.0)));
+        assert_eq!(live.samples_after(0).len(), 3);
+        assert_eq!(decimator.push(4000, power(100.0)), None);
+    }
+}
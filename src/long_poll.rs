@@ -0,0 +1,67 @@
+use anyhow::{self, Context};
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// How long `GET /next` waits for a new value unless the client asks for something else
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Upper limit of the timeout clients can ask for, so connections aren't held forever
+pub const MAX_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Time of the newest value in the database, updated whenever a value is written
+pub type LatestSample = watch::Receiver<Option<u64>>;
+
+pub fn latest_sample() -> (watch::Sender<Option<u64>>, LatestSample) {
+    watch::channel(None)
+}
+
+/// parses timeouts like `30s`, `500ms` or `2m`; plain numbers are seconds
+pub fn parse_timeout(timeout: &str) -> anyhow::Result<Duration> {
+    let unit_start = timeout
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(timeout.len());
+    let (amount, unit) = timeout.split_at(unit_start);
+    let amount: u64 = amount
+        .parse()
+        .with_context(|| format!("Timeout '{}' doesn't start with a number", timeout))?;
+    let duration = match unit {
+        "ms" => Duration::from_millis(amount),
+        "" | "s" => Duration::from_secs(amount),
+        "m" => Duration::from_secs(60 * amount),
+        _ => anyhow::bail!("Unknown unit '{}' in timeout '{}'", unit, timeout),
+    };
+    if duration > MAX_TIMEOUT {
+        anyhow::bail!(
+            "Timeout '{}' exceeds the maximum of {} s",
+            timeout,
+            MAX_TIMEOUT.as_secs()
+        );
+    }
+    Ok(duration)
+}
+
+/// waits until a value newer than `after` has been written; false if there was none within
+/// the timeout
+pub async fn wait_for_sample_after(
+    latest: &mut LatestSample,
+    after: u64,
+    timeout: Duration,
+) -> bool {
+    let newer = latest.wait_for(|time| time.is_some_and(|t| t > after));
+    matches!(tokio::time::timeout(timeout, newer).await, Ok(Ok(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_timeout() {
+        assert_eq!(parse_timeout("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_timeout("30").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_timeout("250ms").unwrap(), Duration::from_millis(250));
+        assert_eq!(parse_timeout("2m").unwrap(), Duration::from_secs(120));
+        assert!(parse_timeout("1h").is_err());
+        assert!(parse_timeout("10m").is_err());
+        assert!(parse_timeout("s").is_err());
+    }
+}
@@ -1,34 +1,140 @@
 use anyhow::{self, Context};
 use axum::{
     self,
-    extract::Path,
+    extract::{
+        ws::WebSocketUpgrade, MatchedPath, OriginalUri, Path, Query, RawPathParams, Request, State,
+    },
     http::Method,
     http::StatusCode,
-    response::{IntoResponse, Response},
+    middleware::Next,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    Extension, Json,
 };
 use bitcode::{Decode, Encode};
-use clap::Parser;
-use reqwest;
+use clap::{Parser, Subcommand};
+use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
-use std::ops::{Add, Div, Mul, Sub};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use sunny_db::codec::SegmentEncoding;
+use sunny_db::downsampling::{Downsample, DownsamplingMethod};
+use sunny_db::encryption::EncryptionKey;
+use sunny_db::error::SunnyDbError;
+use sunny_db::fields::FloatFields;
+use sunny_db::remote::{DirectoryStore, ObjectStore};
+use sunny_db::smoothing::MovingAverage;
 use sunny_db::statistics::*;
-use sunny_db::timeseries::TimeSeries;
+use sunny_db::timeseries::{Resolution, TimeSeries, TimeSeriesView, TimestampBounds};
 use sunny_db::timeseries_db::SunnyDB;
 use tokio::signal;
-use tokio::sync::RwLock;
+use tokio::sync::{watch, RwLock};
 use tokio::time::interval;
-use tower_http::{cors::{Any, CorsLayer}, services::ServeDir};
 use tower_http::services::ServeFile;
+use tower_http::{
+    cors::{Any, CorsLayer},
+    services::ServeDir,
+};
+
+mod aggregate;
+mod assets;
+mod audit;
+mod auth;
+mod baseline;
+mod battery;
+mod bench;
+mod config;
+mod correlation;
+mod deprecation;
+mod energy;
+mod flows;
+mod fronius;
+mod health;
+mod histogram;
+mod jobs;
+mod kpi;
+mod live;
+mod long_poll;
+mod metrics;
+mod migrate;
+mod panels;
+mod peak_demand;
+mod peaks;
+mod prices;
+mod profile;
+mod projection;
+mod rollups;
+mod sampling;
+mod scheduler;
+mod server;
+mod shifting;
+mod standby;
+mod status;
+mod storage;
+mod stream;
+mod summary;
+mod sync;
+#[cfg(test)]
+mod tests;
+mod verify;
+mod version;
+mod websocket;
+
+use config::{
+    BillingSettings, Config, EmptyResponse, FrontendSettings, Precision, RemoteSettings,
+    SamplingSettings, SourceSettings,
+};
+use live::{Decimator, LiveBuffer};
+use long_poll::LatestSample;
+use metrics::{log_if_slow, QueryDetails, RouteMetrics, SlowQueryLog, ValueHistogram};
+use rollups::Rollups;
+use sampling::AdaptiveInterval;
+use scheduler::{parse_time, parse_timezone, QuietTime, Scheduler};
+use stream::NewValues;
+use sync::SyncParams;
 
 #[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    args: Option<Args>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Re-encode a sample of existing segments with different codecs, compression levels and
+    /// layouts and print how they compare
+    BenchCompression(bench::BenchArgs),
+    /// Check all segments for corruption, overlaps and gaps and print a JSON report; exits with
+    /// a non-zero status if there are any issues
+    Verify(verify::VerifyArgs),
+    /// Serve read-only queries from a replicated sunny home directory without collecting any
+    /// values, e.g. to run the dashboard on another machine
+    Standby(standby::StandbyArgs),
+    /// Import the energy values of a CSV export of Fronius Solar.web as backfilled average
+    /// powers; sunny mustn't be running while importing
+    ImportFronius(fronius::ImportArgs),
+    /// Import the hourly long-term statistics of Home Assistant's recorder as backfilled
+    /// average powers; sunny mustn't be running while importing
+    ImportHomeAssistant(migrate::HomeAssistantArgs),
+    /// Import points in InfluxDB's line protocol, averaged per interval, as backfilled values;
+    /// sunny mustn't be running while importing
+    ImportInflux(migrate::InfluxArgs),
+}
+
+#[derive(clap::Args, Debug)]
 struct Args {
     // Granularity in seconds at which PowerData is fetched
     #[arg(short, long)]
     granularity: u64,
 
-
     // Number of points collected until the average over those points is written in the DB
     #[arg(long)]
     average_over: usize,
@@ -54,68 +160,46 @@ struct Args {
     // with small segments; set to 0 to always store any data
     #[arg(long, default_value_t = 10)]
     loss_threshold: usize,
-}
 
-#[derive(Copy, Clone, Encode, Decode, PartialEq, Serialize, Deserialize, Debug)]
-struct PowerValues {
-    power_pv: f64,
-    power_to_grid: f64,
-    power_from_grid: f64,
-    power_used: f64,
-}
+    // Maximum number of values kept in memory while segments can't be written (e.g. because the
+    // disk is full); beyond it the oldest values are dropped
+    #[arg(long, default_value_t = 100000)]
+    max_in_memory_points: usize,
 
-// traits required to do statistics
-impl Add for PowerValues {
-    type Output = Self;
+    // Path to an optional TOML config file with additional settings
+    #[arg(short, long)]
+    config: Option<String>,
 
-    fn add(self, other: Self) -> Self {
-        Self {
-            power_pv: self.power_pv + other.power_pv,
-            power_to_grid: self.power_to_grid + other.power_to_grid,
-            power_from_grid: self.power_from_grid + other.power_from_grid,
-            power_used: self.power_used + other.power_used,
-        }
-    }
-}
+    // Optional file holding the 32 byte key segments are encrypted with
+    #[arg(long)]
+    key_file: Option<String>,
 
-impl Sub for PowerValues {
-    type Output = Self;
+    // Encoding of new segments: zstd, or gorilla for delta-of-delta timestamps and XOR
+    // compressed values; segments are read in either one
+    #[arg(long, default_value = "zstd")]
+    segment_encoding: SegmentEncoding,
 
-    fn sub(self, other: Self) -> Self {
-        Self {
-            power_pv: self.power_pv - other.power_pv,
-            power_to_grid: self.power_to_grid - other.power_to_grid,
-            power_from_grid: self.power_from_grid - other.power_from_grid,
-            power_used: self.power_used - other.power_used,
-        }
-    }
+    // Number of decimal places values are rounded to and stored as scaled integers with, e.g. 1
+    // to store watts to 0.1 W; values are stored as they are without it
+    #[arg(long, allow_negative_numbers = true)]
+    quantize_decimals: Option<i8>,
 }
 
-impl Mul<f64> for PowerValues {
-    type Output = Self;
-
-    fn mul(self, rhs: f64) -> Self {
-        Self {
-            power_pv: self.power_pv * rhs,
-            power_to_grid: self.power_to_grid * rhs,
-            power_from_grid: self.power_from_grid * rhs,
-            power_used: self.power_used * rhs,
-        }
-    }
+#[derive(Copy, Clone, Default, Encode, Decode, PartialEq, Serialize, Deserialize, Debug)]
+struct PowerValues {
+    power_pv: f64,
+    power_to_grid: f64,
+    power_from_grid: f64,
+    power_used: f64,
 }
 
-impl Div<f64> for PowerValues {
-    type Output = Self;
-
-    fn div(self, rhs: f64) -> Self {
-        Self {
-            power_pv: self.power_pv / rhs,
-            power_to_grid: self.power_to_grid / rhs,
-            power_from_grid: self.power_from_grid / rhs,
-            power_used: self.power_used / rhs,
-        }
-    }
-}
+// the fields in the order of their declaration, for the gorilla encoding of segments
+sunny_db::float_fields!(PowerValues {
+    power_pv,
+    power_to_grid,
+    power_from_grid,
+    power_used,
+});
 
 /// Simple wrapper around Arc<RwLock> to make it read-only
 /// see also: https://stackoverflow.com/questions/70470631/getting-a-read-only-version-of-an-arcrwlockfoo
@@ -126,7 +210,7 @@ struct DatabaseReadLock {
 
 impl DatabaseReadLock {
     fn new(lock: Arc<RwLock<SunnyDB<PowerValues>>>) -> Self {
-        DatabaseReadLock { lock: lock }
+        DatabaseReadLock { lock }
     }
 
     async fn read(&self) -> tokio::sync::RwLockReadGuard<'_, SunnyDB<PowerValues>> {
@@ -159,9 +243,91 @@ where
     }
 }
 
+/// reads the key to encrypt segments with, if a key file is given
+fn load_encryption_key(key_file: Option<&str>) -> anyhow::Result<Option<EncryptionKey>> {
+    key_file
+        .map(|path| EncryptionKey::from_file(std::path::Path::new(path)))
+        .transpose()
+}
+
+/// the object store old segments are offloaded to, if one is configured
+fn open_remote_store(settings: &RemoteSettings) -> anyhow::Result<Option<Box<dyn ObjectStore>>> {
+    if let Some(dir) = &settings.dir {
+        return Ok(Some(Box::new(DirectoryStore::new(dir))));
+    }
+    let Some(endpoint) = &settings.endpoint else {
+        return Ok(None);
+    };
+    #[cfg(feature = "s3")]
+    {
+        let env = |name| std::env::var(name).with_context(|| format!("{} isn't set", name));
+        Ok(Some(Box::new(sunny_db::remote::S3Store {
+            endpoint: endpoint.clone(),
+            bucket: settings.bucket.clone(),
+            region: settings.region.clone(),
+            prefix: settings.prefix.clone(),
+            access_key_id: env("AWS_ACCESS_KEY_ID")?,
+            secret_access_key: env("AWS_SECRET_ACCESS_KEY")?,
+        })))
+    }
+    #[cfg(not(feature = "s3"))]
+    anyhow::bail!(
+        "Offloading to {} needs sunny to be built with the s3 feature",
+        endpoint
+    )
+}
+
 #[tokio::main]
 async fn main() {
-    let args = Args::parse();
+    let cli = Cli::parse();
+    // initialize tracing first so the database's events while opening it are logged, too
+    tracing_subscriber::fmt::init();
+    let args = match (cli.command, cli.args) {
+        (Some(Command::BenchCompression(bench_args)), _) => {
+            if let Err(e) = bench::run(&bench_args) {
+                panic!("Error while benchmarking compression: {:#}", e)
+            }
+            return;
+        }
+        (Some(Command::Verify(verify_args)), _) => match verify::run(&verify_args) {
+            Ok(true) => return,
+            Ok(false) => std::process::exit(1),
+            Err(e) => panic!("Error while verifying the database: {:#}", e),
+        },
+        (Some(Command::ImportFronius(import_args)), _) => {
+            if let Err(e) = fronius::run(&import_args) {
+                panic!("Error while importing the Solar.web export: {:#}", e)
+            }
+            return;
+        }
+        (Some(Command::ImportHomeAssistant(import_args)), _) => {
+            if let Err(e) = migrate::run_home_assistant(&import_args) {
+                panic!(
+                    "Error while importing the Home Assistant statistics: {:#}",
+                    e
+                )
+            }
+            return;
+        }
+        (Some(Command::ImportInflux(import_args)), _) => {
+            if let Err(e) = migrate::run_influx(&import_args) {
+                panic!("Error while importing the InfluxDB export: {:#}", e)
+            }
+            return;
+        }
+        (Some(Command::Standby(standby_args)), _) => {
+            if let Err(e) = standby::run(standby_args).await {
+                panic!("Error while serving the replica: {:#}", e)
+            }
+            return;
+        }
+        (None, Some(args)) => args,
+        (None, None) => unreachable!("clap requires the server arguments without a subcommand"),
+    };
+    let config = match Config::load(args.config.as_deref()) {
+        Ok(c) => c,
+        Err(e) => panic!("Error while loading config: {:#}", e),
+    };
     let sunny_home = args.sunny_home;
     let sunny_path = if sunny_home.ends_with("/") {
         sunny_home
@@ -169,116 +335,915 @@ async fn main() {
         sunny_home + "/"
     };
     let db_path = sunny_path.to_owned() + "db";
-    let sunny_db =
-        SunnyDB::<PowerValues>::new(args.segment_size, &db_path, 2, args.loss_threshold);
+    let key = match load_encryption_key(args.key_file.as_deref()) {
+        Ok(k) => k,
+        Err(e) => panic!("Error while loading the encryption key: {:#}", e),
+    };
+    let timestamp_bounds = match config.timestamps.bounds() {
+        Ok(b) => b,
+        Err(e) => panic!("Error in the accepted timestamps: {:#}", e),
+    };
+    let timezone = match parse_timezone(config.timezone()) {
+        Ok(t) => t,
+        Err(e) => panic!("Error in the timezone: {:#}", e),
+    };
+    let mut remote_store = match open_remote_store(&config.remote) {
+        Ok(s) => s,
+        Err(e) => panic!("Error while setting up the remote storage tier: {:#}", e),
+    };
+    let open_db = || -> Result<SunnyDB<PowerValues>, SunnyDbError> {
+        let mut sunny_db =
+            SunnyDB::<PowerValues>::new(args.segment_size, &db_path, 2, args.loss_threshold)?
+                .with_max_in_memory_points(args.max_in_memory_points)?
+                .with_segment_encoding(args.segment_encoding)?
+                .with_timestamp_bounds(timestamp_bounds)?;
+        if let Some(decimals) = args.quantize_decimals {
+            sunny_db = sunny_db.with_quantization(decimals)?;
+        }
+        if let Some(key) = key {
+            sunny_db = sunny_db.with_encryption_key(key)?;
+        }
+        if let Some(cold_dir) = &config.archive.cold_dir {
+            sunny_db = sunny_db.with_cold_storage(cold_dir)?;
+        }
+        if let Some(store) = remote_store.take() {
+            sunny_db = sunny_db.with_remote_storage(store)?;
+        }
+        #[cfg(feature = "catalog")]
+        if config.storage.catalog {
+            sunny_db = sunny_db.with_catalog()?;
+        }
+        // the running statistics cover the current local day, see fetch_and_write_values_to_db
+        let today_start = projection::start_of_day(Resolution::Milliseconds.now(), timezone);
+        let today_start = sunny_db.get_resolution().from_millis(today_start);
+        Ok(sunny_db
+            .with_energy_aggregates()?
+            .with_running_statistics(today_start))
+    };
+    let sunny_db = match open_db() {
+        Ok(db) => db,
+        Err(e) => {
+            println!("Error while opening the database at {}: {}", db_path, e);
+            std::process::exit(1);
+        }
+    };
 
     // create an RW lock that locks the entire DB during writes;
     // writes should be pretty fast so that should be fine as we can have multiple readers
     let db_write_lock = Arc::new(RwLock::new(sunny_db));
     let db_shutdown_lock = Arc::clone(&db_write_lock);
-    let db_read_lock_1 = DatabaseReadLock::new(Arc::clone(&db_write_lock));
-    let db_read_lock_2 = db_read_lock_1.clone();
-    let db_read_lock_3 = db_read_lock_1.clone();
+    let db_scheduler_lock = Arc::clone(&db_write_lock);
+    let db_read_lock = DatabaseReadLock::new(Arc::clone(&db_write_lock));
+    let (latest_sample_sender, latest_sample) = long_poll::latest_sample();
+    let new_values = stream::new_values();
+    let fetch_new_values = new_values.clone();
 
     println!("Spawning database writer...");
     let granularity = Duration::from_secs(args.granularity);
+    let stored_interval = config
+        .sampling
+        .stored_interval(granularity, args.average_over);
+    let source = config.source.clone();
+    let sampling = config.sampling.clone();
+    let live = LiveBuffer::new(config.live.buffer_size);
+    let decimator = Decimator::new(args.average_over, live.clone());
+    let fetcher_health = health::FetcherHealth::new(Instant::now());
+    let fetch_health = fetcher_health.clone();
     tokio::spawn(async move {
-        fetch_and_write_values_to_db(&db_write_lock, granularity, args.average_over, args.url).await;
+        fetch_and_write_values_to_db(
+            &db_write_lock,
+            &latest_sample_sender,
+            &fetch_new_values,
+            granularity,
+            decimator,
+            args.url,
+            &source,
+            &sampling,
+            timezone,
+            fetch_health,
+        )
+        .await;
     });
 
+    println!("Scheduling daily jobs...");
+    let summary_dir = PathBuf::from(sunny_path.to_owned() + "summaries");
+    let scheduler = match create_scheduler(&config, db_scheduler_lock, summary_dir, stored_interval)
+    {
+        Ok(s) => s,
+        Err(e) => panic!("Error while setting up scheduled jobs: {:#}", e),
+    };
+    scheduler.start();
+
+    println!("Opening prices...");
+    let prices = match prices::Prices::open(&config.prices, &sunny_path) {
+        Ok(p) => p,
+        Err(e) => panic!("Error while opening the prices: {:#}", e),
+    };
+    prices.start_fetching(timezone);
+
+    println!("Loading rollups...");
+    let rollups = match rollups::parse_rules(&config.rollups) {
+        Ok(r) => Arc::new(std::sync::RwLock::new(r)),
+        Err(e) => panic!("Error while loading rollups: {:#}", e),
+    };
+    tokio::spawn(rollups::reload_on_sighup(
+        Arc::clone(&rollups),
+        args.config.clone(),
+    ));
+
     // launch the server
 
-    // initialize tracing
     println!("Initializing server...");
-    tracing_subscriber::fmt::init();
+
+    let resolution = db_read_lock.read().await.get_resolution();
+    let state = AppState {
+        db_read_lock,
+        resolution,
+        latest_sample,
+        new_values,
+        live,
+        prices,
+        rollups,
+        fetcher_health,
+    };
+    let app = build_router(state, &config, &sunny_path);
+
+    // run our app with hyper, listening globally on port
+    // very useful: https://github.com/tokio-rs/axum/tree/main/examples
+    let listener = tokio::net::TcpListener::bind(&(args.bind)).await.unwrap();
+    println!("Listening on http://{}", args.bind);
+    println!("Starting now! Everything looks fantastic! Enjoy!");
+    server::serve(
+        listener,
+        app,
+        &config.server,
+        shutdown_signal(db_shutdown_lock),
+    )
+    .await;
+}
+
+/// What the routes serve besides the config, see `build_router`
+struct AppState {
+    db_read_lock: DatabaseReadLock,
+    /// the unit of the database's timestamps, which doesn't change while it's open
+    resolution: Resolution,
+    latest_sample: LatestSample,
+    new_values: NewValues<PowerValues>,
+    live: LiveBuffer,
+    prices: prices::Prices,
+    rollups: Rollups,
+    fetcher_health: health::FetcherHealth,
+}
+
+fn build_router(state: AppState, config: &Config, sunny_path: &str) -> axum::Router {
+    let AppState {
+        db_read_lock,
+        resolution,
+        latest_sample,
+        new_values,
+        live,
+        prices,
+        rollups,
+        fetcher_health,
+    } = state;
 
     // cors layer
     let cors = CorsLayer::new()
-        .allow_methods([Method::GET])
+        .allow_methods([Method::GET, Method::POST])
+        .allow_headers([axum::http::header::AUTHORIZATION])
         .allow_origin(Any);
 
     let index_route = sunny_path.to_owned() + "index.html";
-    let assets_route = sunny_path + "assets/";
+    let assets_route = sunny_path.to_owned() + "assets/";
+    let values_read_lock = db_read_lock.clone();
+    let batch_read_lock = db_read_lock.clone();
+    let stats_read_lock = db_read_lock.clone();
+    let flows_read_lock = db_read_lock.clone();
+    let next_read_lock = db_read_lock.clone();
+    let peak_demand_read_lock = db_read_lock.clone();
+    let projection_read_lock = db_read_lock.clone();
+    let today_read_lock = db_read_lock.clone();
+    let energy_read_lock = db_read_lock.clone();
+    let timezone = config.timezone().to_owned();
+    let projection_timezone = timezone.clone();
+    let energy_timezone = timezone.clone();
+    let aggregate_timezone = timezone.clone();
+    let aggregate_read_lock = db_read_lock.clone();
+    let baseline_timezone = timezone.clone();
+    let baseline_read_lock = db_read_lock.clone();
+    let cumulative_read_lock = db_read_lock.clone();
+    let histogram_read_lock = db_read_lock.clone();
+    let correlation_read_lock = db_read_lock.clone();
+    let kpi_read_lock = db_read_lock.clone();
+    let peaks_read_lock = db_read_lock.clone();
+    let battery_read_lock = db_read_lock.clone();
+    let panels_read_lock = db_read_lock.clone();
+    let profile_read_lock = db_read_lock.clone();
+    let profile_timezone = timezone.clone();
+    let costs_read_lock = db_read_lock.clone();
+    let costs_prices = prices.clone();
+    let cheapest_prices = prices.clone();
+    let prices_timezone = timezone.clone();
+    let shifting_read_lock = db_read_lock.clone();
+    let shifting_prices = prices.clone();
+    let shifting_timezone = timezone.clone();
+    let billing = config.billing.clone();
+    let metrics_read_lock = db_read_lock.clone();
+    let sync_read_lock = db_read_lock.clone();
+    let download_read_lock = db_read_lock.clone();
+    let rollup_read_lock = db_read_lock.clone();
+    let rollup_definitions = Arc::clone(&rollups);
+    let summary_read_lock = db_read_lock.clone();
+    let summary_dir = PathBuf::from(sunny_path.to_owned() + "summaries");
+    let frontend_settings = config.frontend.clone();
+    let slow_query_threshold = config.metrics.slow_query_threshold();
+    let empty_response = config.api.empty_response;
+    let values_precision = config.api.precision.clone();
+    let batch_precision = config.api.precision.clone();
+    let range_bounds = (resolution, config.timestamps.bounds().unwrap_or_default());
+    let stats_precision = config.api.precision.clone();
+    let next_precision = config.api.precision.clone();
+    let live_precision = config.api.precision.clone();
+    let stream_precision = config.api.precision.clone();
+    let ws_read_lock = db_read_lock.clone();
+    let ws_live = live.clone();
+    let ws_precision = config.api.precision.clone();
+    let audit_entries = audit::AuditLog::new(sunny_path.to_owned() + "audit.log");
+    let jobs_read_lock = db_read_lock.clone();
+    let jobs = jobs::Jobs::default();
+    let job_states = jobs.clone();
+    let jobs_precision = config.api.precision.clone();
+    let route_metrics = Arc::new(RouteMetrics::default());
+    let latency_metrics = Arc::clone(&route_metrics);
+    let update_check = version::UpdateCheck::start(&config.updates);
+    let metrics_update_check = update_check.clone();
+    let metrics_live = live.clone();
+    let metrics_prices = prices.clone();
+    let deprecation = Arc::new(deprecation::Deprecation::new(
+        config.api.prefix().unwrap_or_default(),
+        config.api.legacy_sunset().unwrap_or_default(),
+    ));
+    let metrics_deprecation = Arc::clone(&deprecation);
+    let info_read_lock = db_read_lock.clone();
+    let info_live = live.clone();
+    let info_prices = prices.clone();
 
-    // build our application with a route
-    let app = axum::Router::new()
-        // `GET /` goes to `root`
-        .route_service(
-            "/",
-            ServeFile::new(index_route),
+    // routes serving data; they're served under the versioned API prefix and, so dashboards
+    // built against older versions keep working, optionally under their old paths as well
+    let data_routes = axum::Router::new()
+        .route(
+            "/values/:start_time/:end_time",
+            axum::routing::get(
+                move |route: MatchedPath,
+                      Path((start_time, end_time)): Path<(u64, u64)>,
+                      Query(downsampling): Query<DownsamplingParams>,
+                      Query(page): Query<PageParams>,
+                      Query(full_precision): Query<PrecisionParams>| {
+                    get_values_in_time_range(
+                        values_read_lock,
+                        Path((start_time, end_time)),
+                        downsampling,
+                        page,
+                        full_precision.precision(values_precision),
+                        SlowQueryLog {
+                            threshold: slow_query_threshold,
+                            route,
+                        },
+                        empty_response,
+                    )
+                },
+            ),
         )
-        .layer(cors.clone())
-        .nest_service("/assets",
-            ServeDir::new(assets_route)
+        .route(
+            "/values-with-stats/:start_time/:end_time",
+            axum::routing::get(
+                move |route: MatchedPath,
+                      Path((start_time, end_time)): Path<(u64, u64)>,
+                      Query(full_precision): Query<PrecisionParams>,
+                      Query(integration): Query<IntegrationParams>,
+                      Query(downsampling): Query<DownsamplingParams>| {
+                    get_values_in_time_range_with_statistics(
+                        stats_read_lock,
+                        Path((start_time, end_time)),
+                        full_precision.precision(stats_precision),
+                        integration,
+                        downsampling,
+                        SlowQueryLog {
+                            threshold: slow_query_threshold,
+                            route,
+                        },
+                        empty_response,
+                    )
+                },
+            ),
         )
-        .layer(cors.clone())
         .route(
-            "/values/:start_time/:end_time",
+            "/jobs/:id",
+            axum::routing::get(
+                move |Path(id): Path<u64>, Query(full_precision): Query<PrecisionParams>| {
+                    get_job(job_states, id, full_precision.precision(jobs_precision))
+                },
+            ),
+        )
+        .route(
+            "/flows/:start_time/:end_time",
             axum::routing::get(move |Path((start_time, end_time)): Path<(u64, u64)>| {
-                get_values_in_time_range(db_read_lock_2, Path((start_time, end_time)))
+                get_energy_flows(
+                    flows_read_lock,
+                    Path((start_time, end_time)),
+                    empty_response,
+                )
             }),
         )
-        .layer(cors.clone())
         .route(
-            "/values-with-stats/:start_time/:end_time",
+            "/live",
+            axum::routing::get(
+                move |Query(params): Query<LiveParams>,
+                      Query(full_precision): Query<PrecisionParams>| {
+                    get_live_samples(live, params, full_precision.precision(live_precision))
+                },
+            ),
+        )
+        .route(
+            "/next",
+            axum::routing::get(
+                move |Query(params): Query<NextParams>,
+                      Query(full_precision): Query<PrecisionParams>| {
+                    get_next_values(
+                        next_read_lock,
+                        latest_sample,
+                        params,
+                        full_precision.precision(next_precision),
+                    )
+                },
+            ),
+        )
+        .route(
+            "/stream",
+            axum::routing::get(move |Query(full_precision): Query<PrecisionParams>| {
+                get_stream(new_values, full_precision.precision(stream_precision))
+            }),
+        )
+        .route(
+            "/ws",
+            axum::routing::get(
+                move |Extension(connection): Extension<server::Connection>,
+                      upgrade: WebSocketUpgrade| async move {
+                    upgrade.on_upgrade(move |socket| {
+                        websocket::serve(socket, connection, ws_read_lock, ws_live, ws_precision)
+                    })
+                },
+            ),
+        )
+        .route(
+            "/peak-demand/:start_time/:end_time",
+            axum::routing::get(move |Path((start_time, end_time)): Path<(u64, u64)>| {
+                get_peak_demand(
+                    peak_demand_read_lock,
+                    Path((start_time, end_time)),
+                    timezone,
+                    billing,
+                    empty_response,
+                )
+            }),
+        )
+        .route(
+            "/energy/:interval/:start_time/:end_time",
+            axum::routing::get(
+                move |Path((interval, start_time, end_time)): Path<(
+                    energy::EnergyInterval,
+                    u64,
+                    u64,
+                )>| {
+                    get_energy(
+                        energy_read_lock,
+                        interval,
+                        Path((start_time, end_time)),
+                        energy_timezone,
+                        empty_response,
+                    )
+                },
+            ),
+        )
+        .route(
+            "/histogram/:start_time/:end_time",
+            axum::routing::get(
+                move |Path((start_time, end_time)): Path<(u64, u64)>,
+                      Query(params): Query<histogram::HistogramParams>| {
+                    get_histogram(
+                        histogram_read_lock,
+                        Path((start_time, end_time)),
+                        params,
+                        empty_response,
+                    )
+                },
+            ),
+        )
+        .route(
+            "/correlation/:start_time/:end_time",
+            axum::routing::get(
+                move |Path((start_time, end_time)): Path<(u64, u64)>,
+                      Query(params): Query<correlation::CorrelationParams>| {
+                    get_correlation(correlation_read_lock, Path((start_time, end_time)), params)
+                },
+            ),
+        )
+        .route(
+            "/kpi/:start_time/:end_time",
+            axum::routing::get(move |Path((start_time, end_time)): Path<(u64, u64)>| {
+                get_kpi(kpi_read_lock, Path((start_time, end_time)))
+            }),
+        )
+        .route(
+            "/peaks/:field/:start_time/:end_time",
+            axum::routing::get(
+                move |Path((field, start_time, end_time)): Path<(
+                    correlation::PowerField,
+                    u64,
+                    u64,
+                )>,
+                      Query(params): Query<peaks::PeaksParams>| {
+                    get_peaks(peaks_read_lock, Path((field, start_time, end_time)), params)
+                },
+            ),
+        )
+        .route(
+            "/battery-simulation/:start_time/:end_time",
+            axum::routing::get(
+                move |Path((start_time, end_time)): Path<(u64, u64)>,
+                      Query(params): Query<battery::BatteryParams>| {
+                    get_battery_simulation(battery_read_lock, Path((start_time, end_time)), params)
+                },
+            ),
+        )
+        .route(
+            "/panel-simulation/:start_time/:end_time",
+            axum::routing::get(
+                move |Path((start_time, end_time)): Path<(u64, u64)>,
+                      Query(params): Query<panels::PanelParams>| {
+                    get_panel_simulation(panels_read_lock, Path((start_time, end_time)), params)
+                },
+            ),
+        )
+        .route(
+            "/profile/daily",
+            axum::routing::get(move |Query(params): Query<profile::DailyProfileParams>| {
+                get_daily_profile(profile_read_lock, params, profile_timezone)
+            }),
+        )
+        .route(
+            "/aggregate/:bucket/:start_time/:end_time",
+            axum::routing::get(
+                move |Path((bucket, start_time, end_time)): Path<(
+                    aggregate::AggregateBucket,
+                    u64,
+                    u64,
+                )>,
+                      Query(params): Query<aggregate::AggregateParams>| {
+                    get_aggregate(
+                        aggregate_read_lock,
+                        bucket,
+                        params.aggregate,
+                        Path((start_time, end_time)),
+                        aggregate_timezone,
+                        empty_response,
+                    )
+                },
+            ),
+        )
+        .route(
+            "/baseline/:start_time/:end_time",
+            axum::routing::get(move |Path((start_time, end_time)): Path<(u64, u64)>| {
+                get_baselines(
+                    baseline_read_lock,
+                    Path((start_time, end_time)),
+                    baseline_timezone,
+                    empty_response,
+                )
+            }),
+        )
+        .route(
+            "/cumulative-energy/:start_time/:end_time",
             axum::routing::get(move |Path((start_time, end_time)): Path<(u64, u64)>| {
-                get_values_in_time_range_with_statistics(
-                    db_read_lock_3,
+                get_cumulative_energy(
+                    cumulative_read_lock,
                     Path((start_time, end_time)),
+                    empty_response,
+                )
+            }),
+        )
+        .route(
+            "/prices/cheapest-hours",
+            axum::routing::get(move |Query(params): Query<CheapestHoursParams>| {
+                get_cheapest_hours(cheapest_prices, params, prices_timezone, empty_response)
+            }),
+        )
+        .route(
+            "/prices/:start_time/:end_time",
+            axum::routing::get(move |Path((start_time, end_time)): Path<(u64, u64)>| {
+                get_prices(prices, Path((start_time, end_time)), empty_response)
+            }),
+        )
+        .route(
+            "/costs/:start_time/:end_time",
+            axum::routing::get(move |Path((start_time, end_time)): Path<(u64, u64)>| {
+                get_costs(costs_read_lock, costs_prices, Path((start_time, end_time)))
+            }),
+        )
+        .route(
+            "/load-shifting",
+            axum::routing::get(move |Query(params): Query<LoadShiftingParams>| {
+                get_load_shifting(
+                    shifting_read_lock,
+                    shifting_prices,
+                    params,
+                    shifting_timezone,
+                )
+            }),
+        )
+        .route(
+            "/projection/today",
+            axum::routing::get(move || {
+                get_today_projection(projection_read_lock, projection_timezone)
+            }),
+        )
+        .route(
+            "/statistics/today",
+            axum::routing::get(move || get_today_so_far(today_read_lock)),
+        )
+        .route(
+            "/sync/segments",
+            axum::routing::get(
+                move |OriginalUri(uri): OriginalUri, Query(params): Query<SyncParams>| {
+                    get_segment_changes(sync_read_lock, uri, params)
+                },
+            ),
+        )
+        .route(
+            "/sync/segments/:name",
+            axum::routing::get(move |Path(name): Path<String>| {
+                get_segment_file(download_read_lock, name)
+            }),
+        )
+        .route(
+            "/config/frontend",
+            axum::routing::get(move || get_frontend_settings(frontend_settings)),
+        )
+        .route(
+            "/metrics",
+            axum::routing::get(move |Query(params): Query<MetricsParams>| {
+                get_metrics(
+                    metrics_read_lock,
+                    route_metrics,
+                    metrics_update_check,
+                    metrics_live,
+                    metrics_prices,
+                    metrics_deprecation,
+                    params,
                 )
             }),
         )
+        .route(
+            "/db/info",
+            axum::routing::get(move || get_db_info(info_read_lock, info_live, info_prices)),
+        )
+        .route(
+            "/version",
+            axum::routing::get(move || get_version(update_check)),
+        )
+        .route(
+            "/rollups",
+            axum::routing::get(move || get_rollup_definitions(rollup_definitions)),
+        )
+        .route(
+            "/rollups/:name/:start_time/:end_time",
+            axum::routing::get(
+                move |Path((name, start_time, end_time)): Path<(String, u64, u64)>| {
+                    get_rollup(
+                        rollup_read_lock,
+                        rollups,
+                        name,
+                        start_time,
+                        end_time,
+                        empty_response,
+                    )
+                },
+            ),
+        )
+        .route(
+            "/summaries/:date",
+            axum::routing::get(move |Path(date): Path<chrono::NaiveDate>| {
+                get_summary(summary_read_lock, summary_dir, date, empty_response)
+            }),
+        )
+        .route(
+            "/admin/audit",
+            axum::routing::get(move |Query(params): Query<AuditParams>| {
+                get_audit_log(audit_entries, params)
+            }),
+        )
+        .route_layer(axum::middleware::from_fn_with_state(
+            range_bounds,
+            reject_implausible_ranges,
+        ))
+        // queries taking their parameters in the body, which don't change anything, so they
+        // aren't audited; the batch checks the timestamps of its ranges itself
+        .route(
+            "/jobs/stats",
+            axum::routing::post(move |Json(request): Json<jobs::StatsJobRequest>| {
+                start_stats_job(jobs_read_lock, jobs, request)
+            }),
+        )
+        .route(
+            "/values/batch",
+            axum::routing::post(
+                move |Query(full_precision): Query<PrecisionParams>,
+                      Json(request): Json<BatchRequest>| {
+                    get_values_in_time_ranges(
+                        batch_read_lock,
+                        request,
+                        range_bounds,
+                        full_precision.precision(batch_precision),
+                    )
+                },
+            ),
+        )
+        .route_layer(axum::middleware::from_fn_with_state(
+            auth::Authenticator::start(&config.auth),
+            auth::require_auth,
+        ))
         .layer(cors.clone());
 
-    // run our app with hyper, listening globally on port
-    // very useful: https://github.com/tokio-rs/axum/tree/main/examples
-    let listener = tokio::net::TcpListener::bind(&(args.bind)).await.unwrap();
-    println!("Listening on http://{}", args.bind);
-    println!("Starting now! Everything looks fantastic! Enjoy!");
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal(db_shutdown_lock))
-        .await
-        .unwrap();
+    let mut index_file = ServeFile::new(index_route);
+    let mut assets_dir = ServeDir::new(assets_route);
+    if config.assets.precompressed {
+        // serve e.g. index.js.br instead of index.js to clients accepting brotli
+        index_file = index_file.precompressed_br().precompressed_gzip();
+        assets_dir = assets_dir.precompressed_br().precompressed_gzip();
+    }
+
+    // build our application with a route
+    let mut app = axum::Router::new()
+        // `GET /` goes to `root`
+        .route_service("/", index_file)
+        .layer(cors.clone())
+        .nest_service("/assets", assets_dir)
+        .layer(cors.clone())
+        .layer(axum::middleware::from_fn(assets::cache_headers));
+
+    match config.api.prefix() {
+        Some(prefix) => {
+            app = app.nest(&prefix, data_routes.clone());
+            if config.api.legacy_routes {
+                app = app.merge(
+                    data_routes.route_layer(axum::middleware::from_fn_with_state(
+                        deprecation,
+                        deprecation::warn_legacy,
+                    )),
+                );
+            }
+        }
+        None => app = app.merge(data_routes),
+    }
+
+    if config.status.enabled {
+        let status_read_lock = db_read_lock.clone();
+        let status_settings = config.status.clone();
+        let requests_per_minute = status_settings.requests_per_minute;
+        let status_limiter = Arc::new(status::RateLimiter::new(requests_per_minute));
+        app = app.route(
+            "/status.json",
+            axum::routing::get(move |Query(params): Query<status::StatusParams>| {
+                get_status(status_read_lock, status_settings, status_limiter, params)
+            })
+            .layer(cors.clone()),
+        );
+    }
+
+    // for monitors, so like /status.json it's neither authorized nor prefixed
+    let health_read_lock = db_read_lock.clone();
+    let max_failing = config.health.max_failing();
+    app = app.route(
+        "/health",
+        axum::routing::get(move || get_health(health_read_lock, fetcher_health, max_failing)),
+    );
+
+    app.route_layer(axum::middleware::from_fn_with_state(
+        latency_metrics,
+        metrics::track_latency,
+    ))
+}
+
+fn create_scheduler(
+    config: &Config,
+    db_lock: Arc<RwLock<SunnyDB<PowerValues>>>,
+    summary_dir: PathBuf,
+    stored_interval: Duration,
+) -> anyhow::Result<Scheduler> {
+    let timezone = parse_timezone(config.timezone())?;
+    let quiet_times = config
+        .schedule
+        .quiet_times
+        .iter()
+        .map(|q| QuietTime::parse(q))
+        .collect::<anyhow::Result<Vec<QuietTime>>>()?;
+    let mut scheduler = Scheduler::new(timezone, quiet_times);
+
+    // start a new segment with every local day so that segments don't span across days
+    let new_segment_lock = Arc::clone(&db_lock);
+    scheduler.daily(
+        "new segment",
+        parse_time(&config.schedule.new_segment_at)?,
+        move || {
+            let db_lock = Arc::clone(&new_segment_lock);
+            async move {
+                let mut sunny_db = db_lock.write().await;
+                if let Err(e) = sunny_db.start_new_segment() {
+                    println!("Error while trying to start a new segment: {}", e);
+                }
+            }
+        },
+    );
+
+    if let Some(months) = config.archive.after_months {
+        let archive_lock = Arc::clone(&db_lock);
+        let compression_level = config.archive.compression_level;
+        scheduler.daily(
+            "archive",
+            parse_time(&config.archive.archive_at)?,
+            move || {
+                let db_lock = Arc::clone(&archive_lock);
+                async move {
+                    let cutoff = chrono::Utc::now() - chrono::Months::new(months);
+                    let older_than = cutoff.timestamp_millis().max(0) as u64;
+                    // re-compressing may take a while, so it's done without holding the lock
+                    // and off the async workers; the segments are only listed under it
+                    let result = tokio::task::spawn_blocking(move || {
+                        let job = db_lock
+                            .blocking_read()
+                            .archive_job(older_than, compression_level)?;
+                        let archived = job.run()?;
+                        db_lock.blocking_read().finish_archive(archived);
+                        anyhow::Ok(())
+                    })
+                    .await;
+                    match result {
+                        Ok(Err(e)) => println!("Error while archiving old segments: {:#}", e),
+                        Err(e) => println!("Error while archiving old segments: {}", e),
+                        Ok(Ok(())) => (),
+                    }
+                }
+            },
+        );
+    }
+
+    if let Some(months) = config.remote.after_months {
+        let offload_lock = Arc::clone(&db_lock);
+        scheduler.daily(
+            "offload",
+            parse_time(&config.remote.offload_at)?,
+            move || {
+                let db_lock = Arc::clone(&offload_lock);
+                async move {
+                    let cutoff = chrono::Utc::now() - chrono::Months::new(months);
+                    let older_than = cutoff.timestamp_millis().max(0) as u64;
+                    // uploading may take a while, so it's done without holding the lock and off
+                    // the async workers; the segments are only listed under it
+                    let result = tokio::task::spawn_blocking(move || {
+                        let job = db_lock.blocking_read().offload_job(older_than)?;
+                        let offloaded = job.run()?;
+                        db_lock.blocking_read().finish_offload(offloaded);
+                        anyhow::Ok(offloaded)
+                    })
+                    .await;
+                    match result {
+                        Ok(Err(e)) => println!("Error while offloading old segments: {:#}", e),
+                        Err(e) => println!("Error while offloading old segments: {}", e),
+                        Ok(Ok(_)) => (),
+                    }
+                }
+            },
+        );
+    }
+
+    if !config.storage.quotas.is_empty() {
+        let storage_lock = Arc::clone(&db_lock);
+        let storage_settings = config.storage.clone();
+        let storage_summary_dir = summary_dir.clone();
+        scheduler.daily(
+            "storage quotas",
+            parse_time(&config.storage.check_at)?,
+            move || {
+                let db_lock = Arc::clone(&storage_lock);
+                let settings = storage_settings.clone();
+                let summary_dir = storage_summary_dir.clone();
+                async move {
+                    // keep queries from reading segments while they're being deleted
+                    let sunny_db = db_lock.write().await;
+                    let storage = storage::Storage {
+                        db: &sunny_db,
+                        summary_dir: &summary_dir,
+                    };
+                    // the summaries are kept in sunny's home directory next to the database
+                    let home = summary_dir.parent().unwrap_or(&summary_dir);
+                    let result =
+                        storage::enforce_quotas(&storage, &settings, || fs2::available_space(home));
+                    if let Err(e) = result {
+                        println!("Error while enforcing storage quotas: {:#}", e);
+                    }
+                }
+            },
+        );
+    }
+
+    scheduler.daily(
+        "daily summary",
+        parse_time(&config.schedule.summary_at)?,
+        move || {
+            let db_lock = Arc::clone(&db_lock);
+            let summary_dir = summary_dir.clone();
+            async move {
+                let today = chrono::Utc::now().with_timezone(&timezone).date_naive();
+                let Some(yesterday) = today.pred_opt() else {
+                    return;
+                };
+                let sunny_db = db_lock.read().await;
+                let summary =
+                    summary::summarize_day(&sunny_db, yesterday, timezone, stored_interval);
+                if let Err(e) = summary::store_summary(&sunny_db, &summary_dir, &summary) {
+                    println!("Error while storing the summary of {}: {:#}", yesterday, e);
+                }
+            }
+        },
+    );
+
+    Ok(scheduler)
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn fetch_and_write_values_to_db(
     db_lock: &RwLock<SunnyDB<PowerValues>>,
+    latest_sample: &watch::Sender<Option<u64>>,
+    new_values: &NewValues<PowerValues>,
     granularity: Duration,
-    average_over: usize,
+    mut decimator: Decimator,
     url: String,
+    source: &SourceSettings,
+    sampling: &SamplingSettings,
+    timezone: chrono_tz::Tz,
+    health: health::FetcherHealth,
 ) {
-    let mut granular_timeseries = TimeSeries::<PowerValues>::new(average_over);
     let mut pause = interval(granularity);
+    let mut adaptive = sampling.adaptive.then(|| AdaptiveInterval::new(sampling));
 
     let full_url = format!(
         "http://{}/status/powerflow",
         url.strip_suffix("/").unwrap_or(&url)
     );
     loop {
-        let values = fetch_power_values(&full_url).await;
-        pause.tick().await;
-        match values {
-            Ok(v) => {
-                granular_timeseries.insert_value_at_current_time(v);
+        let values = fetch_power_values(&full_url, source).await;
+        match &mut adaptive {
+            Some(adaptive) => tokio::time::sleep(adaptive.next(values.as_ref().ok())).await,
+            None => {
+                pause.tick().await;
             }
-            Err(e) => println!("Error encountered while trying to fetch latest data: {}", e),
         }
+        let average = match values {
+            Ok(v) => {
+                health.record_success(Instant::now());
+                decimator.push(Resolution::Milliseconds.now(), v)
+            }
+            Err(e) => {
+                health.record_failure();
+                println!("Error encountered while trying to fetch latest data: {}", e);
+                None
+            }
+        };
 
-        if granular_timeseries.len() >= average_over {
-            let average = granular_timeseries.average();
-            match average {
-                Some(avg) => {
-                    let mut sunny_db = db_lock.write().await;
-                    sunny_db.insert_value_at_current_time(avg);
-                },
-                None => ()
+        if let Some(avg) = average {
+            let mut sunny_db = db_lock.write().await;
+            // start today's statistics over with the first value of a new day
+            let today_start = projection::start_of_day(Resolution::Milliseconds.now(), timezone);
+            let today_start = sunny_db.get_resolution().from_millis(today_start);
+            if sunny_db
+                .running_statistics()
+                .is_some_and(|statistics| statistics.since < today_start)
+            {
+                sunny_db.reset_running_statistics(today_start);
+            }
+            // the series in memory is empty if the value filled the segment, so the time of the
+            // value is taken from the insert
+            if let Some(time) = sunny_db.insert_value_at_current_time(avg) {
+                // wake up clients waiting for new values
+                latest_sample.send_replace(Some(time));
+                // fails if no client is listening
+                let _ = new_values.send((time, avg));
             }
-            granular_timeseries = TimeSeries::<PowerValues>::new(average_over);
         }
     }
 }
 
-async fn fetch_power_values(url: &str) -> anyhow::Result<PowerValues> {
+async fn fetch_power_values(url: &str, source: &SourceSettings) -> anyhow::Result<PowerValues> {
     let current_values = reqwest::get(url).await?.json::<serde_json::Value>().await?;
     let site_data = &current_values["site"];
 
@@ -291,6 +1256,7 @@ async fn fetch_power_values(url: &str) -> anyhow::Result<PowerValues> {
     // the grid power value is negative if we're feeding into to the grid and positive if we're pulling from it
     let grid_power = site_data["P_Grid"]
         .as_f64()
+        .map(|p| source.grid.to_watts(p))
         .context("Couldn't obtain grid power from response")?;
     let (power_to_grid, power_from_grid) = if grid_power < 0.0 {
         (-grid_power, 0.0)
@@ -301,99 +1267,1297 @@ async fn fetch_power_values(url: &str) -> anyhow::Result<PowerValues> {
     // power load can only be negative, but still, let's work with positives only
     let power_load = site_data["P_Load"]
         .as_f64()
+        .map(|p| source.load.to_watts(p))
         .context("Couldn't obtain used power from response")?;
     let power_used = -power_load;
 
     let power_values = PowerValues {
         power_pv: site_data["P_PV"]
             .as_f64()
+            .map(|p| source.pv.to_watts(p))
             .context("Couldn't obtain PV power from response")?,
-        power_from_grid: power_from_grid,
-        power_to_grid: power_to_grid,
-        power_used: power_used,
+        power_from_grid,
+        power_to_grid,
+        power_used,
     };
 
     Ok(power_values)
 }
 
+#[derive(Deserialize, Default, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+enum Downsampling {
+    Lttb,
+    #[default]
+    Average,
+}
+
+/// Optional query parameters to reduce the number of returned values, e.g. `?max_points=500`,
+/// or to smooth them, e.g. `?moving_average_ms=60000` to average each value with those within
+/// half a minute before and after it
+#[derive(Deserialize)]
+struct DownsamplingParams {
+    max_points: Option<usize>,
+    #[serde(default)]
+    downsampling: Downsampling,
+    moving_average_ms: Option<u64>,
+}
+
+impl DownsamplingParams {
+    fn method(&self) -> DownsamplingMethod<PowerValues> {
+        match self.downsampling {
+            // pick the points by the overall power flowing so that peaks of all values are kept
+            Downsampling::Lttb => DownsamplingMethod::Lttb(|v| {
+                v.power_pv + v.power_to_grid + v.power_from_grid + v.power_used
+            }),
+            Downsampling::Average => DownsamplingMethod::BucketAverage,
+        }
+    }
+
+    /// the values smoothed and reduced as requested; None if neither was requested
+    fn apply(&self, series: &TimeSeries<PowerValues>) -> Option<TimeSeries<PowerValues>> {
+        // smoothed before downsampling, so the reduced values are smooth as well
+        let smoothed = self
+            .moving_average_ms
+            .map(|window_ms| series.moving_average(window_ms));
+        let series = smoothed.as_ref().unwrap_or(series);
+        self.max_points
+            .map(|max_points| series.downsample(max_points, &self.method()))
+            .or(smoothed)
+    }
+}
+
+/// Optional query parameters to page through the values, e.g. `?limit=10000`; the cursor of the
+/// next page is returned in the `X-Next-Cursor` header and passed as `?cursor=`
+#[derive(Deserialize)]
+struct PageParams {
+    limit: Option<usize>,
+    cursor: Option<u64>,
+}
+
 async fn get_values_in_time_range(
     db_read_lock: DatabaseReadLock,
     Path((start_time, end_time)): Path<(u64, u64)>,
-) -> Result<String, AppError> {
+    downsampling: DownsamplingParams,
+    page: PageParams,
+    precision: Precision,
+    slow_query: SlowQueryLog,
+    empty: EmptyResponse,
+) -> Result<Response, AppError> {
     let reader = db_read_lock.read().await;
 
-    let read_timeseries = reader.get_values_in_range(start_time, end_time);
+    let query_start = Instant::now();
+    let (read_timeseries, next_cursor) = match page.limit {
+        Some(limit) => {
+            match reader.get_values_in_range_paged(start_time, end_time, limit, page.cursor) {
+                Some(page) => (Some(page.values), page.next),
+                None => (None, None),
+            }
+        }
+        None => (reader.get_values_in_range(start_time, end_time), None),
+    };
+    let read_timeseries =
+        read_timeseries.map(|series| downsampling.apply(&series).unwrap_or(series));
+    log_if_slow(&slow_query, query_start.elapsed(), || {
+        let resolution = match downsampling.max_points {
+            Some(max_points) => format!(
+                "{:?}, at most {} values ({:?})",
+                reader.get_resolution(),
+                max_points,
+                downsampling.downsampling
+            ),
+            None => format!("{:?}", reader.get_resolution()),
+        };
+        QueryDetails {
+            start_time,
+            end_time,
+            resolution,
+            segments: reader.segments_in_range(start_time, end_time),
+        }
+    });
     match read_timeseries {
-        Some(series) => Ok(serde_json::to_string_pretty(&series.get_current_values())?),
-        None => Ok(String::from("{ }")),
+        Some(series) if !series.is_empty() => {
+            let json = serde_json::to_string_pretty(&rounded_json(
+                &series.get_current_values(),
+                &precision,
+            )?)?;
+            match next_cursor {
+                Some(cursor) => Ok(([("x-next-cursor", cursor.to_string())], json).into_response()),
+                None => Ok(json.into_response()),
+            }
+        }
+        _ => empty_response(empty, Vec::<(u64, PowerValues)>::new()),
     }
 }
 
+/// Most ranges `POST /values/batch` answers at once, e.g. the days of a year
+const MAX_BATCH_RANGES: usize = 366;
+
+/// Body of `POST /values/batch`, e.g.
+/// `{"ranges": [{"start_time": 1717200000000, "end_time": 1717286399999}, ...]}`
+#[derive(Deserialize)]
+struct BatchRequest {
+    ranges: Vec<BatchRange>,
+}
+
+#[derive(Deserialize)]
+struct BatchRange {
+    start_time: u64,
+    end_time: u64,
+}
+
+/// the values of each of the ranges, in the same order; ranges sharing segments are read
+/// together instead of decoding the segments once per range. Like the ranges in paths, ranges
+/// starting at or after the latest accepted timestamp are answered with 400
+async fn get_values_in_time_ranges(
+    db_read_lock: DatabaseReadLock,
+    request: BatchRequest,
+    (resolution, bounds): (Resolution, TimestampBounds),
+    precision: Precision,
+) -> Result<Response, AppError> {
+    if request.ranges.len() > MAX_BATCH_RANGES {
+        let message = format!(
+            "At most {} ranges can be requested at once",
+            MAX_BATCH_RANGES
+        );
+        return Ok((StatusCode::BAD_REQUEST, message).into_response());
+    }
+    let implausible = request
+        .ranges
+        .iter()
+        .find_map(|range| implausible_start(resolution, bounds, range.start_time));
+    if let Some(response) = implausible {
+        return Ok(response);
+    }
+    let ranges: Vec<(u64, u64)> = request
+        .ranges
+        .iter()
+        .map(|range| (range.start_time, range.end_time))
+        .collect();
+    let values: Vec<Vec<(u64, PowerValues)>> = db_read_lock
+        .read()
+        .await
+        .get_values_in_ranges(&ranges)
+        .into_iter()
+        .map(|series| series.map(|s| s.get_current_values()).unwrap_or_default())
+        .collect();
+    Ok(serde_json::to_string(&rounded_json(&values, &precision)?)?.into_response())
+}
+
+/// Optional query parameter to get values with full precision regardless of the configured
+/// one, i.e. `?precision=full`
+#[derive(Deserialize)]
+struct PrecisionParams {
+    precision: Option<FullPrecision>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum FullPrecision {
+    Full,
+}
+
+/// Optional query parameters to pick how the energy and averages are integrated, e.g.
+/// `?integration=simpson`, to leave out intervals between values longer than
+/// `?max_gap_ms=` rather than bridging them, and to only use the stored values rather than
+/// interpolating values at the start and end of the range with `?interpolate_boundaries=false`
+#[derive(Deserialize)]
+struct IntegrationParams {
+    #[serde(default)]
+    integration: IntegrationMethod,
+    max_gap_ms: Option<u64>,
+    /// true unless it's given, like for `/kpi` and the daily summaries
+    interpolate_boundaries: Option<bool>,
+}
+
+impl PrecisionParams {
+    /// the precision values are served with
+    fn precision(self, configured: Precision) -> Precision {
+        match self.precision {
+            Some(FullPrecision::Full) => Precision::default(),
+            None => configured,
+        }
+    }
+}
+
+/// the body as JSON with the fields of its values rounded to the given precision
+fn rounded_json(body: &impl Serialize, precision: &Precision) -> anyhow::Result<serde_json::Value> {
+    let mut json = serde_json::to_value(body)?;
+    precision.apply(&mut json);
+    Ok(json)
+}
+
+/// Query parameters of `GET /next`, e.g. `?after=1717200000000&timeout=30s`
+#[derive(Deserialize)]
+struct NextParams {
+    after: Option<u64>,
+    timeout: Option<String>,
+}
+
+/// Query parameters of `GET /admin/audit`, e.g. `?limit=20`
+#[derive(Deserialize)]
+struct AuditParams {
+    limit: Option<usize>,
+}
+
+/// the newest entries of the audit log
+async fn get_audit_log(
+    audit_log: audit::AuditLog,
+    params: AuditParams,
+) -> Result<String, AppError> {
+    let entries = audit_log.read(params.limit.unwrap_or(audit::DEFAULT_LIMIT))?;
+    Ok(serde_json::to_string(&entries)?)
+}
+
+/// Query parameters of `GET /live`, e.g. `?after=1717200000000`
+#[derive(Deserialize)]
+struct LiveParams {
+    after: Option<u64>,
+}
+
+/// the samples fetched after `after`, or all of them that are still buffered, whether they've
+/// been stored yet or not
+async fn get_live_samples(
+    live: LiveBuffer,
+    params: LiveParams,
+    precision: Precision,
+) -> Result<String, AppError> {
+    let samples = live.samples_after(params.after.unwrap_or(0));
+    Ok(serde_json::to_string(&rounded_json(&samples, &precision)?)?)
+}
+
+/// long-polls for the values newer than `after`, or than the newest one if it's missing;
+/// answers with 204 if none arrived within the timeout
+async fn get_next_values(
+    db_read_lock: DatabaseReadLock,
+    mut latest_sample: LatestSample,
+    params: NextParams,
+    precision: Precision,
+) -> Result<Response, AppError> {
+    let timeout = match params.timeout.as_deref().map(long_poll::parse_timeout) {
+        Some(Ok(timeout)) => timeout,
+        Some(Err(e)) => return Ok((StatusCode::BAD_REQUEST, format!("{:#}", e)).into_response()),
+        None => long_poll::DEFAULT_TIMEOUT,
+    };
+    let after = match params.after {
+        Some(after) => after,
+        None => {
+            let reader = db_read_lock.read().await;
+            reader
+                .time_series
+                .get_end_time()
+                .or_else(|| reader.last_persisted_time())
+                .unwrap_or(0)
+        }
+    };
+
+    // the values may have arrived already, otherwise wait for them
+    let mut newer = db_read_lock
+        .read()
+        .await
+        .get_values_in_range(after, u64::MAX);
+    if newer.is_none() && long_poll::wait_for_sample_after(&mut latest_sample, after, timeout).await
+    {
+        newer = db_read_lock
+            .read()
+            .await
+            .get_values_in_range(after, u64::MAX);
+    }
+    match newer {
+        Some(series) if !series.is_empty() => {
+            let json = rounded_json(&series.get_current_values(), &precision)?;
+            Ok(serde_json::to_string(&json)?.into_response())
+        }
+        _ => Ok(StatusCode::NO_CONTENT.into_response()),
+    }
+}
+
+/// pushes every value written from now on as a server-sent event, e.g.
+/// `event: values` with `data: [1717200000000, {"power_pv": ...}]`
+async fn get_stream(
+    new_values: NewValues<PowerValues>,
+    precision: Precision,
+) -> Sse<impl futures_util::Stream<Item = anyhow::Result<Event>>> {
+    let events = stream::subscribe(&new_values).map(move |value| {
+        let json = rounded_json(&value, &precision)?;
+        Ok(Event::default().event("values").data(json.to_string()))
+    });
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+async fn get_peak_demand(
+    db_read_lock: DatabaseReadLock,
+    Path((start_time, end_time)): Path<(u64, u64)>,
+    timezone: String,
+    billing: BillingSettings,
+    empty: EmptyResponse,
+) -> Result<Response, AppError> {
+    let timezone = parse_timezone(&timezone)?;
+    let reader = db_read_lock.read().await;
+    let peaks = peak_demand::peak_demand(&reader, start_time, end_time, timezone, &billing);
+    if peaks.is_empty() {
+        return empty_response(empty, peaks);
+    }
+    Ok(serde_json::to_string(&peaks)?.into_response())
+}
+
+/// the energy flows between PV, grid and load within the range for a Sankey diagram
+async fn get_energy_flows(
+    db_read_lock: DatabaseReadLock,
+    Path((start_time, end_time)): Path<(u64, u64)>,
+    empty: EmptyResponse,
+) -> Result<Response, AppError> {
+    let reader = db_read_lock.read().await;
+    let flows = reader
+        .get_values_in_range(start_time, end_time)
+        .and_then(|series| flows::energy_flows(series.view()));
+    match flows {
+        Some(flows) => Ok(serde_json::to_string(&flows)?.into_response()),
+        None => empty_response(empty, flows::EnergyFlows::default()),
+    }
+}
+
+/// starts computing the statistics of a range too large to be answered right away; the
+/// job's state and eventually its result are polled via `GET /jobs/:id`
+async fn start_stats_job(
+    db_read_lock: DatabaseReadLock,
+    jobs: jobs::Jobs,
+    request: jobs::StatsJobRequest,
+) -> Response {
+    match jobs.start_stats(db_read_lock, request.start_time, request.end_time) {
+        Some(id) => (
+            StatusCode::ACCEPTED,
+            serde_json::json!({ "id": id }).to_string(),
+        )
+            .into_response(),
+        None => (
+            StatusCode::TOO_MANY_REQUESTS,
+            format!("At most {} jobs can run at once", jobs::MAX_RUNNING),
+        )
+            .into_response(),
+    }
+}
+
+async fn get_job(jobs: jobs::Jobs, id: u64, precision: Precision) -> Result<Response, AppError> {
+    let Some(state) = jobs.state(id) else {
+        return Ok((StatusCode::NOT_FOUND, format!("No job {}", id)).into_response());
+    };
+    let mut json = state?;
+    precision.apply(&mut json);
+    Ok(json.to_string().into_response())
+}
+
+/// the energy per hour or local day within the range, read from the continuous aggregates
+async fn get_energy(
+    db_read_lock: DatabaseReadLock,
+    interval: energy::EnergyInterval,
+    Path((start_time, end_time)): Path<(u64, u64)>,
+    timezone: String,
+    empty: EmptyResponse,
+) -> Result<Response, AppError> {
+    let timezone = parse_timezone(&timezone)?;
+    let reader = db_read_lock.read().await;
+    let energies = energy::energy(&reader, interval, start_time, end_time, timezone)?;
+    if energies.is_empty() {
+        return empty_response(empty, energies);
+    }
+    Ok(serde_json::to_string(&energies)?.into_response())
+}
+
+/// the energy, average or maxima of the values per hour, local day, week or month
+async fn get_aggregate(
+    db_read_lock: DatabaseReadLock,
+    bucket: aggregate::AggregateBucket,
+    function: aggregate::Aggregate,
+    Path((start_time, end_time)): Path<(u64, u64)>,
+    timezone: String,
+    empty: EmptyResponse,
+) -> Result<Response, AppError> {
+    let timezone = parse_timezone(&timezone)?;
+    let reader = db_read_lock.read().await;
+    let aggregates = reader
+        .get_values_in_range(start_time, end_time)
+        .map(|series| aggregate::aggregate(series.view(), bucket, function, timezone))
+        .unwrap_or_default();
+    if aggregates.is_empty() {
+        return empty_response(empty, aggregates);
+    }
+    Ok(serde_json::to_string(&aggregates)?.into_response())
+}
+
+/// how many hours each field spent within each power bin in the range
+async fn get_histogram(
+    db_read_lock: DatabaseReadLock,
+    Path((start_time, end_time)): Path<(u64, u64)>,
+    params: histogram::HistogramParams,
+    empty: EmptyResponse,
+) -> Result<Response, AppError> {
+    let bin_width = params.bin_width.unwrap_or(histogram::DEFAULT_BIN_WIDTH);
+    let reader = db_read_lock.read().await;
+    let bins = match reader.get_values_in_range(start_time, end_time) {
+        Some(series) => match histogram::histogram(series.view(), bin_width) {
+            Ok(bins) => bins,
+            Err(e) => return Ok((StatusCode::BAD_REQUEST, format!("{:#}", e)).into_response()),
+        },
+        None => Vec::new(),
+    };
+    if bins.is_empty() {
+        return empty_response(empty, bins);
+    }
+    Ok(serde_json::to_string(&bins)?.into_response())
+}
+
+/// answers requests for ranges starting at or after the latest accepted timestamp with 400,
+/// e.g. ones in µs rather than ms; ranges may well start before the earliest or end after the
+/// latest one though, like `/values/0/99999999999999`
+async fn reject_implausible_ranges(
+    State((resolution, bounds)): State<(Resolution, TimestampBounds)>,
+    params: RawPathParams,
+    request: Request,
+    next: Next,
+) -> Response {
+    let start_time = params
+        .iter()
+        .find(|(name, _)| *name == "start_time")
+        .and_then(|(_, value)| value.parse::<u64>().ok());
+    let implausible =
+        start_time.and_then(|start_time| implausible_start(resolution, bounds, start_time));
+    if let Some(response) = implausible {
+        return response;
+    }
+    next.run(request).await
+}
+
+/// a 400 response if the start time is at or after the latest accepted timestamp
+fn implausible_start(
+    resolution: Resolution,
+    bounds: TimestampBounds,
+    start_time: u64,
+) -> Option<Response> {
+    if resolution.to_millis(start_time) < bounds.max_ms {
+        return None;
+    }
+    let message = format!(
+        "The start time {} is after the latest accepted timestamp, {} ms",
+        start_time, bounds.max_ms
+    );
+    Some((StatusCode::BAD_REQUEST, message).into_response())
+}
+
+/// how two of the fields are correlated within the range
+async fn get_correlation(
+    db_read_lock: DatabaseReadLock,
+    Path((start_time, end_time)): Path<(u64, u64)>,
+    params: correlation::CorrelationParams,
+) -> Result<String, AppError> {
+    let reader = db_read_lock.read().await;
+    let series = reader
+        .get_values_in_range(start_time, end_time)
+        .unwrap_or_else(TimeSeries::empty);
+    let correlation = correlation::correlate(series.view(), params);
+    Ok(serde_json::to_string(&correlation)?)
+}
+
+/// the self-consumption and autarky within the range
+async fn get_kpi(
+    db_read_lock: DatabaseReadLock,
+    Path((start_time, end_time)): Path<(u64, u64)>,
+) -> Result<String, AppError> {
+    let reader = db_read_lock.read().await;
+    let energy_kwh = reader
+        .get_values_in_range_interpolated(start_time, end_time)
+        .and_then(|series| compute_statistics(series.view()).energy_kwh);
+    Ok(serde_json::to_string(&kpi::RangeKpi::new(energy_kwh))?)
+}
+
+/// the highest local maxima of a field within the range, e.g. the biggest consumption spikes
+async fn get_peaks(
+    db_read_lock: DatabaseReadLock,
+    Path((field, start_time, end_time)): Path<(correlation::PowerField, u64, u64)>,
+    params: peaks::PeaksParams,
+) -> Result<String, AppError> {
+    let reader = db_read_lock.read().await;
+    let series = reader
+        .get_values_in_range(start_time, end_time)
+        .unwrap_or_else(TimeSeries::empty);
+    let peaks = peaks::peaks(series.view(), field, params);
+    Ok(serde_json::to_string(&peaks)?)
+}
+
+/// how a battery would have changed the grid exchange within the range
+async fn get_battery_simulation(
+    db_read_lock: DatabaseReadLock,
+    Path((start_time, end_time)): Path<(u64, u64)>,
+    params: battery::BatteryParams,
+) -> Result<Response, AppError> {
+    let reader = db_read_lock.read().await;
+    let series = reader
+        .get_values_in_range(start_time, end_time)
+        .unwrap_or_else(TimeSeries::empty);
+    match battery::simulate(series.view(), params) {
+        Ok(simulation) => Ok(serde_json::to_string(&simulation)?.into_response()),
+        Err(e) => Ok((StatusCode::BAD_REQUEST, format!("{:#}", e)).into_response()),
+    }
+}
+
+/// how a bigger (or smaller) PV array would have changed the grid exchange within the range
+async fn get_panel_simulation(
+    db_read_lock: DatabaseReadLock,
+    Path((start_time, end_time)): Path<(u64, u64)>,
+    params: panels::PanelParams,
+) -> Result<Response, AppError> {
+    let reader = db_read_lock.read().await;
+    let series = reader
+        .get_values_in_range(start_time, end_time)
+        .unwrap_or_else(TimeSeries::empty);
+    match panels::simulate(series.view(), params) {
+        Ok(simulation) => Ok(serde_json::to_string(&simulation)?.into_response()),
+        Err(e) => Ok((StatusCode::BAD_REQUEST, format!("{:#}", e)).into_response()),
+    }
+}
+
+/// the average day of the values within the range
+async fn get_daily_profile(
+    db_read_lock: DatabaseReadLock,
+    params: profile::DailyProfileParams,
+    timezone: String,
+) -> Result<Response, AppError> {
+    let timezone = parse_timezone(&timezone)?;
+    let reader = db_read_lock.read().await;
+    let series = reader
+        .get_values_in_range(params.start, params.end)
+        .unwrap_or_else(TimeSeries::empty);
+    let slot_minutes = params.slot_minutes.unwrap_or(profile::DEFAULT_SLOT_MINUTES);
+    match profile::daily_profile(series.view(), timezone, slot_minutes, params.split_weekends) {
+        Ok(profile) => Ok(serde_json::to_string(&profile)?.into_response()),
+        Err(e) => Ok((StatusCode::BAD_REQUEST, format!("{:#}", e)).into_response()),
+    }
+}
+
+/// the baseline consumption of each local day within the range
+async fn get_baselines(
+    db_read_lock: DatabaseReadLock,
+    Path((start_time, end_time)): Path<(u64, u64)>,
+    timezone: String,
+    empty: EmptyResponse,
+) -> Result<Response, AppError> {
+    let timezone = parse_timezone(&timezone)?;
+    let reader = db_read_lock.read().await;
+    let baselines = reader
+        .get_values_in_range(start_time, end_time)
+        .map(|series| baseline::daily_baselines(series.view(), timezone))
+        .unwrap_or_default();
+    if baselines.is_empty() {
+        return empty_response(empty, baselines);
+    }
+    Ok(serde_json::to_string(&baselines)?.into_response())
+}
+
+/// the energy in kWh of each field from the start of the range up to each value
+async fn get_cumulative_energy(
+    db_read_lock: DatabaseReadLock,
+    Path((start_time, end_time)): Path<(u64, u64)>,
+    empty: EmptyResponse,
+) -> Result<Response, AppError> {
+    let reader = db_read_lock.read().await;
+    let Some(series) = reader.get_values_in_range(start_time, end_time) else {
+        return empty_response(empty, Vec::<(u64, PowerValues)>::new());
+    };
+    let resolution = series.get_resolution();
+    // the integral comes out in W times the timestamp unit
+    let to_kwh = 1e-3 / 3600.0 / resolution.per_second() as f64;
+    let totals: Vec<(u64, PowerValues)> = series
+        .cumulative_integral()
+        .iter()
+        .map(|(time, total)| (resolution.to_millis(time), *total * to_kwh))
+        .collect();
+    Ok(serde_json::to_string(&totals)?.into_response())
+}
+
+/// the spot prices per kWh holding within the range as `[start, price]` pairs
+async fn get_prices(
+    prices: prices::Prices,
+    Path((start_time, end_time)): Path<(u64, u64)>,
+    empty: EmptyResponse,
+) -> Result<Response, AppError> {
+    let prices = prices.in_range(start_time, end_time).await;
+    if prices.is_empty() {
+        return empty_response(empty, prices);
+    }
+    Ok(serde_json::to_string(&prices)?.into_response())
+}
+
+/// Query parameters of `GET /prices/cheapest-hours`, e.g. `?hours=3&contiguous=true`
+#[derive(Deserialize)]
+struct CheapestHoursParams {
+    hours: Option<usize>,
+    #[serde(default)]
+    contiguous: bool,
+    /// local date; tomorrow if it's missing
+    date: Option<chrono::NaiveDate>,
+}
+
+/// the cheapest hours of tomorrow (or the given date) by their spot price
+async fn get_cheapest_hours(
+    prices: prices::Prices,
+    params: CheapestHoursParams,
+    timezone: String,
+    empty: EmptyResponse,
+) -> Result<Response, AppError> {
+    let timezone = parse_timezone(&timezone)?;
+    let date = match params.date {
+        Some(date) => date,
+        None => chrono::Utc::now().with_timezone(&timezone).date_naive() + chrono::Days::new(1),
+    };
+    let (start_time, end_time) = summary::day_range(date, timezone);
+    let day_prices = prices.in_range(start_time, end_time).await;
+    let hours = prices::cheapest_hours(
+        &day_prices,
+        date,
+        timezone,
+        params.hours.unwrap_or(3),
+        params.contiguous,
+    );
+    if hours.is_empty() {
+        return empty_response(empty, hours);
+    }
+    Ok(serde_json::to_string(&hours)?.into_response())
+}
+
+/// what the energy from and to the grid cost and earned at the spot prices
+async fn get_costs(
+    db_read_lock: DatabaseReadLock,
+    prices: prices::Prices,
+    Path((start_time, end_time)): Path<(u64, u64)>,
+) -> Result<String, AppError> {
+    let powers: Vec<(u64, f64, f64)> = {
+        let reader = db_read_lock.read().await;
+        match reader.get_values_in_range(start_time, end_time) {
+            Some(series) => {
+                let resolution = series.get_resolution();
+                series
+                    .iter()
+                    .map(|(time, v)| {
+                        (
+                            resolution.to_millis(time),
+                            v.power_from_grid,
+                            v.power_to_grid,
+                        )
+                    })
+                    .collect()
+            }
+            None => Vec::new(),
+        }
+    };
+    let range_prices = prices.in_range(start_time, end_time).await;
+    let costs = prices::costs(&powers, &range_prices, prices.settings());
+    Ok(serde_json::to_string(&costs)?)
+}
+
+/// Query parameters of `GET /load-shifting`, e.g. `?power_w=2000&hours=2&windows=3`
+#[derive(Deserialize)]
+struct LoadShiftingParams {
+    power_w: Option<f64>,
+    hours: Option<usize>,
+    windows: Option<usize>,
+    /// local date; tomorrow if it's missing
+    date: Option<chrono::NaiveDate>,
+}
+
+/// the best windows of tomorrow (or the given date) to run an appliance in, given the PV
+/// production and consumption of the past days and the spot prices
+async fn get_load_shifting(
+    db_read_lock: DatabaseReadLock,
+    prices: prices::Prices,
+    params: LoadShiftingParams,
+    timezone: String,
+) -> Result<String, AppError> {
+    let timezone = parse_timezone(&timezone)?;
+    let date = match params.date {
+        Some(date) => date,
+        None => chrono::Utc::now().with_timezone(&timezone).date_naive() + chrono::Days::new(1),
+    };
+    let (forecast, history_days) = {
+        let reader = db_read_lock.read().await;
+        shifting::forecast_day(&reader, date, timezone)
+    };
+    let (start_time, end_time) = summary::day_range(date, timezone);
+    let day_prices = prices.in_range(start_time, end_time).await;
+    let power_w = params.power_w.unwrap_or(2000.0).max(0.0);
+    let windows = shifting::recommend_windows(
+        &forecast,
+        &day_prices,
+        prices.settings(),
+        power_w,
+        params.hours.unwrap_or(2),
+        params.windows.unwrap_or(3),
+    );
+    let recommendation = shifting::LoadShifting {
+        date,
+        power_w,
+        history_days,
+        currency: prices::PRICE_CURRENCY,
+        windows,
+    };
+    Ok(serde_json::to_string(&recommendation)?)
+}
+
+/// projects today's total PV production from the production so far and the past days
+async fn get_today_projection(
+    db_read_lock: DatabaseReadLock,
+    timezone: String,
+) -> Result<String, AppError> {
+    let timezone = parse_timezone(&timezone)?;
+    let now = chrono::Utc::now().timestamp_millis() as u64;
+    let reader = db_read_lock.read().await;
+    let projection = projection::project_today(&reader, now, timezone);
+    Ok(serde_json::to_string(&projection)?)
+}
+
+/// today's statistics so far, without reading the day's segments
+async fn get_today_so_far(db_read_lock: DatabaseReadLock) -> Result<String, AppError> {
+    let today = projection::today_so_far(&*db_read_lock.read().await);
+    Ok(serde_json::to_string(&today)?)
+}
+
+/// the current power, today's energy and autarky for widgets on other sites; rate-limited on
+/// its own and cacheable
+async fn get_status(
+    db_read_lock: DatabaseReadLock,
+    settings: config::StatusSettings,
+    limiter: Arc<status::RateLimiter>,
+    params: status::StatusParams,
+) -> Result<Response, AppError> {
+    if !status::is_shared_with(&settings.share_tokens, params.token.as_deref()) {
+        return Ok((StatusCode::UNAUTHORIZED, "Unknown share token").into_response());
+    }
+    if let Err(wait) = limiter.allow(Instant::now()) {
+        let retry_after = wait.as_secs().max(1).to_string();
+        return Ok((
+            StatusCode::TOO_MANY_REQUESTS,
+            [(axum::http::header::RETRY_AFTER, retry_after)],
+            "Too many requests",
+        )
+            .into_response());
+    }
+    let today = projection::today_so_far(&*db_read_lock.read().await);
+    let body = serde_json::to_string(&status::Status::new(today))?;
+    let cache_control = format!("public, max-age={}", settings.max_age_secs);
+    Ok((
+        [
+            (
+                axum::http::header::CONTENT_TYPE,
+                String::from("application/json"),
+            ),
+            (axum::http::header::CACHE_CONTROL, cache_control),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// whether values are being collected, for monitors; 503 once fetching has been failing for
+/// longer than `[health] max_failing_secs`
+async fn get_health(
+    db_read_lock: DatabaseReadLock,
+    fetcher_health: health::FetcherHealth,
+    max_failing: Duration,
+) -> Result<Response, AppError> {
+    let in_memory_points = db_read_lock.read().await.time_series.len();
+    let health = fetcher_health.report(Instant::now(), in_memory_points, max_failing);
+    let status = match health.healthy {
+        true => StatusCode::OK,
+        false => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    Ok((status, serde_json::to_string(&health)?).into_response())
+}
+
+/// lists the segments added or removed since the manifest entry `since`, so archivers can
+/// mirror the database incrementally
+async fn get_segment_changes(
+    db_read_lock: DatabaseReadLock,
+    uri: axum::http::Uri,
+    params: SyncParams,
+) -> Result<String, AppError> {
+    let entries = db_read_lock.read().await.manifest_since(params.since)?;
+    let changes = sync::segment_changes(params.since, entries, uri.path());
+    Ok(serde_json::to_string(&changes)?)
+}
+
+/// the raw (compressed) file of a persisted segment
+async fn get_segment_file(
+    db_read_lock: DatabaseReadLock,
+    name: String,
+) -> Result<Response, AppError> {
+    let not_found = || (StatusCode::NOT_FOUND, "No such segment").into_response();
+    let Some(segment) = sync::parse_segment_name(&name) else {
+        return Ok(not_found());
+    };
+    let Some(path) = db_read_lock.read().await.segment_file(segment) else {
+        return Ok(not_found());
+    };
+    // the segment may have been archived or pruned in the meantime
+    match tokio::fs::read(&path).await {
+        Ok(data) => Ok((
+            [(axum::http::header::CONTENT_TYPE, "application/octet-stream")],
+            data,
+        )
+            .into_response()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(not_found()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// the response to a query of a range without any values; `body` is the result's usual
+/// structure without any values
+fn empty_response(empty: EmptyResponse, body: impl Serialize) -> Result<Response, AppError> {
+    match empty {
+        EmptyResponse::Empty => Ok(serde_json::to_string(&body)?.into_response()),
+        EmptyResponse::NoContent => Ok(StatusCode::NO_CONTENT.into_response()),
+        EmptyResponse::NotFound => {
+            Ok((StatusCode::NOT_FOUND, "No values in the given range").into_response())
+        }
+    }
+}
+
+/// the summary of a local day stored by the daily summary job
+async fn get_summary(
+    db_read_lock: DatabaseReadLock,
+    summary_dir: PathBuf,
+    date: chrono::NaiveDate,
+    empty: EmptyResponse,
+) -> Result<Response, AppError> {
+    let summary = {
+        let reader = db_read_lock.read().await;
+        summary::read_summary(&reader, &summary_dir, date)?
+    };
+    match summary {
+        Some(json) => Ok(json.into_response()),
+        None => empty_response(empty, serde_json::Value::Null),
+    }
+}
+
+async fn get_rollup_definitions(rollups: Rollups) -> Result<String, AppError> {
+    let rules: Vec<_> = rollups
+        .read()
+        .unwrap()
+        .iter()
+        .map(|r| r.rule.clone())
+        .collect();
+    Ok(serde_json::to_string(&rules)?)
+}
+
+async fn get_rollup(
+    db_read_lock: DatabaseReadLock,
+    rollups: Rollups,
+    name: String,
+    start_time: u64,
+    end_time: u64,
+    empty: EmptyResponse,
+) -> Result<Response, AppError> {
+    let Some(rollup) = rollups::find(&rollups, &name) else {
+        return Ok((StatusCode::NOT_FOUND, format!("Unknown rollup '{}'", name)).into_response());
+    };
+
+    let reader = db_read_lock.read().await;
+    let rolled_up = reader
+        .get_values_in_range(start_time, end_time)
+        .map(|series| rollup.compute(&series))
+        .unwrap_or_default();
+    if rolled_up.is_empty() {
+        return empty_response(empty, rolled_up);
+    }
+    Ok(serde_json::to_string(&rolled_up)?.into_response())
+}
+
+async fn get_frontend_settings(settings: FrontendSettings) -> Result<String, AppError> {
+    Ok(serde_json::to_string(&settings)?)
+}
+
+/// Bytes held in memory, see `SunnyDB::memory_usage`
 #[derive(Serialize)]
-struct ValuesAndStats {
-    values: Vec<(u64, PowerValues)>,
+struct MemoryInfo {
+    /// the values that haven't been written to a segment yet
+    series_bytes: usize,
+    /// the list of segments offloaded to an object store
+    remote_index_bytes: usize,
+    /// the running statistics of today and the metrics of the write path
+    statistics_bytes: usize,
+    /// the samples served by `GET /live`
+    live_buffer_bytes: usize,
+    /// the values of the series of spot prices in memory
+    prices_bytes: usize,
+    total_bytes: usize,
+}
+
+async fn memory_info(
+    db: &SunnyDB<PowerValues>,
+    live: &LiveBuffer,
+    prices: &prices::Prices,
+) -> MemoryInfo {
+    let usage = db.memory_usage();
+    let live_buffer_bytes = live.memory_usage();
+    let prices_bytes = prices.memory_usage().await;
+    MemoryInfo {
+        series_bytes: usage.series,
+        remote_index_bytes: usage.remote_index,
+        statistics_bytes: usage.statistics,
+        live_buffer_bytes,
+        prices_bytes,
+        total_bytes: usage.total() + live_buffer_bytes + prices_bytes,
+    }
+}
+
+/// Counters describing the health of the database writer
+#[derive(Serialize)]
+struct Metrics {
+    in_memory_points: usize,
+    dropped_points: u64,
+    failed_exports: u64,
+    /// values rejected because of their timestamps, see `[timestamps]`
+    rejected_points: u64,
+    memory: MemoryInfo,
+    write_path: metrics::WritePathMetrics,
+    /// whether the update check found a newer release, see `GET /version`
+    update_available: bool,
+    /// latency histograms in ms keyed by route
+    latencies: BTreeMap<String, ValueHistogram>,
+    /// requests to the deprecated, unprefixed paths keyed by route, see `[api]`
+    legacy_requests: BTreeMap<String, u64>,
+}
+
+impl Metrics {
+    /// the metrics in the Prometheus text format
+    fn to_prometheus(&self) -> String {
+        let mut text = format!(
+            "# TYPE sunny_in_memory_points gauge\nsunny_in_memory_points {}\n\
+             # TYPE sunny_dropped_points_total counter\nsunny_dropped_points_total {}\n\
+             # TYPE sunny_failed_exports_total counter\nsunny_failed_exports_total {}\n\
+             # TYPE sunny_rejected_points_total counter\nsunny_rejected_points_total {}\n\
+             # TYPE sunny_update_available gauge\nsunny_update_available {}\n\
+             # TYPE sunny_memory_bytes gauge\n",
+            self.in_memory_points,
+            self.dropped_points,
+            self.failed_exports,
+            self.rejected_points,
+            self.update_available as u8,
+        );
+        for (part, bytes) in [
+            ("series", self.memory.series_bytes),
+            ("remote_index", self.memory.remote_index_bytes),
+            ("statistics", self.memory.statistics_bytes),
+            ("live_buffer", self.memory.live_buffer_bytes),
+            ("prices", self.memory.prices_bytes),
+        ] {
+            text += &format!("sunny_memory_bytes{{part=\"{}\"}} {}\n", part, bytes);
+        }
+        text += &self.write_path.to_prometheus();
+        text += "# TYPE sunny_request_duration_ms histogram\n";
+        for (route, histogram) in &self.latencies {
+            let labels = format!("route=\"{}\"", route);
+            text += &histogram.to_prometheus("sunny_request_duration_ms", &labels);
+        }
+        text += "# TYPE sunny_legacy_requests_total counter\n";
+        for (route, count) in &self.legacy_requests {
+            text += &format!(
+                "sunny_legacy_requests_total{{route=\"{}\"}} {}\n",
+                route, count
+            );
+        }
+        text
+    }
+}
+
+/// Optional query parameter to get the metrics in the Prometheus text format rather than as
+/// JSON, i.e. `?format=prometheus`
+#[derive(Deserialize)]
+struct MetricsParams {
+    format: Option<MetricsFormat>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum MetricsFormat {
+    Json,
+    Prometheus,
+}
+
+async fn get_metrics(
+    db_read_lock: DatabaseReadLock,
+    route_metrics: Arc<RouteMetrics>,
+    update_check: version::UpdateCheck,
+    live: LiveBuffer,
+    prices: prices::Prices,
+    deprecation: Arc<deprecation::Deprecation>,
+    params: MetricsParams,
+) -> Result<Response, AppError> {
+    let reader = db_read_lock.read().await;
+    let metrics = Metrics {
+        in_memory_points: reader.time_series.len(),
+        dropped_points: reader.dropped_points(),
+        failed_exports: reader.failed_exports(),
+        rejected_points: reader.rejected_points(),
+        memory: memory_info(&reader, &live, &prices).await,
+        write_path: reader.write_metrics().into(),
+        update_available: update_check.update_available(),
+        latencies: route_metrics.snapshot(),
+        legacy_requests: deprecation.usage(),
+    };
+    match params.format {
+        Some(MetricsFormat::Prometheus) => Ok((
+            [(
+                axum::http::header::CONTENT_TYPE,
+                "text/plain; version=0.0.4",
+            )],
+            metrics.to_prometheus(),
+        )
+            .into_response()),
+        Some(MetricsFormat::Json) | None => Ok(serde_json::to_string(&metrics)?.into_response()),
+    }
+}
+
+/// Response of `GET /db/info`
+#[derive(Serialize)]
+struct DbInfo {
+    resolution: String,
+    in_memory_points: usize,
+    /// mean time between the values in memory, to check whether values are collected as
+    /// often as configured; null with fewer than two values
+    mean_sample_interval_ms: Option<f64>,
+    /// of the segments of all storage tiers; null if they couldn't be listed
+    disk_usage_bytes: Option<u64>,
+    memory: MemoryInfo,
+}
+
+/// what the database holds in memory and on disk, e.g. to tune the segment size on small
+/// devices
+async fn get_db_info(
+    db_read_lock: DatabaseReadLock,
+    live: LiveBuffer,
+    prices: prices::Prices,
+) -> Result<String, AppError> {
+    let reader = db_read_lock.read().await;
+    let info = DbInfo {
+        resolution: format!("{:?}", reader.get_resolution()),
+        in_memory_points: reader.time_series.len(),
+        mean_sample_interval_ms: reader
+            .time_series
+            .mean_sample_interval()
+            .map(|interval| interval * 1e3 / reader.get_resolution().per_second() as f64),
+        disk_usage_bytes: reader.disk_usage().ok(),
+        memory: memory_info(&reader, &live, &prices).await,
+    };
+    Ok(serde_json::to_string(&info)?)
+}
+
+async fn get_version(update_check: version::UpdateCheck) -> Result<String, AppError> {
+    Ok(serde_json::to_string(&update_check.build_info())?)
+}
+
+#[derive(Serialize)]
+struct ValuesAndStats<'a> {
+    #[serde(serialize_with = "serialize_values")]
+    values: TimeSeriesView<'a, PowerValues>,
+    #[serde(flatten)]
+    stats: PowerStatistics,
+    /// when each field first reached its minimum and maximum
+    min_times: Option<FieldTimes>,
+    max_times: Option<FieldTimes>,
+}
+
+/// A time in ms per field, e.g. when it peaked; null for fields without values
+#[derive(Serialize, Debug, PartialEq)]
+struct FieldTimes {
+    power_pv: Option<u64>,
+    power_to_grid: Option<u64>,
+    power_from_grid: Option<u64>,
+    power_used: Option<u64>,
+}
+
+impl FieldTimes {
+    /// from the times of the fields in the order of `PowerValues::field`
+    fn new(times: &[Option<u64>], resolution: Resolution) -> Self {
+        let indices: Vec<f64> = (0..PowerValues::COUNT).map(|i| i as f64).collect();
+        let index = PowerValues::from_fields(&indices);
+        let time = |i: f64| times[i as usize].map(|t| resolution.to_millis(t));
+        FieldTimes {
+            power_pv: time(index.power_pv),
+            power_to_grid: time(index.power_to_grid),
+            power_from_grid: time(index.power_from_grid),
+            power_used: time(index.power_used),
+        }
+    }
+}
+
+/// serializes the values as `[time, values]` pairs straight from the series
+fn serialize_values<S: serde::Serializer>(
+    values: &TimeSeriesView<'_, PowerValues>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(values.iter())
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct PowerStatistics {
     average: Option<PowerValues>,
+    mins: Option<PowerValues>,
+    /// the smallest values that aren't 0, e.g. the baseline load; null for fields that were 0
+    nonzero_mins: Option<PowerValues>,
     maxes: Option<PowerValues>,
+    // the 95th percentile, which unlike the maxes isn't thrown off by short spikes
+    p95: Option<PowerValues>,
+    /// time-weighted standard deviation, i.e. how volatile the values are
+    std_dev: Option<PowerValues>,
     energy_kwh: Option<PowerValues>,
+    /// self-consumption and autarky derived from the energy
+    #[serde(default)]
+    kpi: Option<kpi::Kpi>,
+    /// time in ms between values left out of the average and energy since it's longer than
+    /// `max_gap_ms`, e.g. an outage
+    #[serde(default)]
+    excluded_ms: u64,
+    // how many of the values the statistics are based on weren't actually measured
+    #[serde(default)]
+    quality: QualityCounts,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct QualityCounts {
+    measured: usize,
+    interpolated: usize,
+    backfilled: usize,
+    suspect: usize,
+    measured_fraction: Option<f64>,
+}
+
+impl From<QualitySummary> for QualityCounts {
+    fn from(summary: QualitySummary) -> Self {
+        QualityCounts {
+            measured: summary.measured,
+            interpolated: summary.interpolated,
+            backfilled: summary.backfilled,
+            suspect: summary.suspect,
+            measured_fraction: summary.measured_fraction(),
+        }
+    }
 }
 
 async fn get_values_in_time_range_with_statistics(
     db_read_lock: DatabaseReadLock,
     Path((start_time, end_time)): Path<(u64, u64)>,
-) -> Result<String, AppError> {
+    precision: Precision,
+    integration: IntegrationParams,
+    downsampling: DownsamplingParams,
+    slow_query: SlowQueryLog,
+    empty: EmptyResponse,
+) -> Result<Response, AppError> {
     let reader = db_read_lock.read().await;
-    let read_timeseries = reader.get_values_in_range(start_time, end_time);
+    let query_start = Instant::now();
+    let read_timeseries = if integration.interpolate_boundaries.unwrap_or(true) {
+        reader.get_values_in_range_interpolated(start_time, end_time)
+    } else {
+        reader.get_values_in_range(start_time, end_time)
+    };
+    log_if_slow(&slow_query, query_start.elapsed(), || QueryDetails {
+        start_time,
+        end_time,
+        resolution: format!("{:?}", reader.get_resolution()),
+        segments: reader.segments_in_range(start_time, end_time),
+    });
+
+    let timeseries = read_timeseries.unwrap_or_else(TimeSeries::empty);
 
-    if read_timeseries.is_none() {
-        return Ok(String::from("{ }"));
+    let resolution = timeseries.get_resolution();
+    // found once for both the statistics and the times
+    let (extrema, extrema_times) = timeseries.extrema_with_times().unzip();
+    // only the returned values are reduced, the statistics are those of all of them
+    let reduced = downsampling.apply(&timeseries);
+    let response_data = ValuesAndStats {
+        values: reduced.as_ref().unwrap_or(&timeseries).view(),
+        stats: compute_statistics_with(
+            timeseries.view(),
+            extrema,
+            integration.integration,
+            integration.max_gap_ms,
+        ),
+        min_times: extrema_times
+            .as_ref()
+            .map(|t| FieldTimes::new(&t.min, resolution)),
+        max_times: extrema_times
+            .as_ref()
+            .map(|t| FieldTimes::new(&t.max, resolution)),
+    };
+    if timeseries.is_empty() {
+        return empty_response(empty, response_data);
     }
 
-    let timeseries = read_timeseries.unwrap();
+    let json = serde_json::to_string(&rounded_json(&response_data, &precision)?);
+    Ok(json?.into_response())
+}
+
+fn compute_statistics(timeseries: TimeSeriesView<'_, PowerValues>) -> PowerStatistics {
+    let extrema = timeseries.extrema();
+    compute_statistics_with(timeseries, extrema, IntegrationMethod::Trapezoidal, None)
+}
+
+/// the statistics with the given extrema of the series and the energy and average integrated
+/// by the given method, leaving out intervals longer than `max_gap_ms`
+fn compute_statistics_with(
+    timeseries: TimeSeriesView<'_, PowerValues>,
+    extrema: Option<Extrema<PowerValues>>,
+    integration: IntegrationMethod,
+    max_gap_ms: Option<u64>,
+) -> PowerStatistics {
+    if timeseries.len() < 2 {
+        // can't integrate over a single value
+        return PowerStatistics {
+            average: None,
+            mins: extrema.map(|e| e.min),
+            nonzero_mins: extrema.map(|e| e.nonzero_min),
+            maxes: extrema.map(|e| e.max),
+            p95: timeseries.quantile(0.95),
+            std_dev: None,
+            energy_kwh: None,
+            kpi: None,
+            excluded_ms: 0,
+            quality: timeseries.quality_summary().into(),
+        };
+    }
 
-    // time is in ms so the integral over the series comes out in units of W*ms = mJ
-    let integral = timeseries.integrate();
-    let energy_joule = integral.map(|e| e * 1e-3);
+    // the integral over the series comes out in units of W times the timestamp unit, e.g. W*ms = mJ
+    let integral = timeseries.integrate_skipping_gaps(integration, max_gap_ms);
+    let resolution = timeseries.get_resolution();
+    let units_per_second = resolution.per_second() as f64;
+    // nothing was integrated if every interval was left out
+    let energy_joule = integral.map(|e| e.integral.unwrap_or_default() / units_per_second);
     let energy_kwh = energy_joule.map(|e| e * 1e-3 / 3600.0);
-    let avg = integral.map(|e| {
-        e / (timeseries.get_end_time().unwrap() - timeseries.get_start_time().unwrap()) as f64
-    });
-    let maxes = get_max_powervalues_from_series(&timeseries);
+    let avg = integral.and_then(|e| Some(e.integral? / e.covered as f64));
+    let excluded_ms = integral.map_or(0, |e| resolution.to_millis(e.excluded));
 
-    let response_data = ValuesAndStats {
-        values: timeseries.get_current_values(),
+    PowerStatistics {
         average: avg,
-        maxes: maxes,
-        energy_kwh: energy_kwh,
-    };
-
-    let json = serde_json::to_string(&response_data);
-    Ok(json?)
+        mins: extrema.map(|e| e.min),
+        nonzero_mins: extrema.map(|e| e.nonzero_min),
+        maxes: extrema.map(|e| e.max),
+        p95: timeseries.quantile(0.95),
+        std_dev: timeseries.standard_deviation(),
+        energy_kwh,
+        kpi: energy_kwh.as_ref().and_then(kpi::Kpi::from_energy),
+        excluded_ms,
+        quality: timeseries.quality_summary().into(),
+    }
 }
 
-fn get_max_powervalues_from_series(timeseries: &TimeSeries<PowerValues>) -> Option<PowerValues> {
-    let pv_max = timeseries
-        .max_by(|a, b| a.power_pv.partial_cmp(&b.power_pv).unwrap())?
-        .power_pv;
-    let grid_max = timeseries
-        .max_by(|a, b| a.power_from_grid.partial_cmp(&b.power_from_grid).unwrap())?
-        .power_from_grid;
-    let into_grid_max = timeseries
-        .max_by(|a, b| a.power_to_grid.partial_cmp(&b.power_to_grid).unwrap())?
-        .power_to_grid;
-    let used_max = timeseries
-        .max_by(|a, b| a.power_used.partial_cmp(&b.power_used).unwrap())?
-        .power_used;
+async fn shutdown_signal(db_shutdown_lock: Arc<RwLock<SunnyDB<PowerValues>>>) {
+    termination_signal().await;
 
-    let pv = PowerValues {
-        power_pv: pv_max,
-        power_to_grid: into_grid_max,
-        power_from_grid: grid_max,
-        power_used: used_max,
-    };
-    Some(pv)
+    // flush the database
+    let mut write_lock = db_shutdown_lock.write().await;
+    write_lock.lossy_persist();
 }
 
-async fn shutdown_signal(db_shutdown_lock: Arc<RwLock<SunnyDB<PowerValues>>>) {
+/// completes on Ctrl+C or SIGTERM
+async fn termination_signal() {
     // from https://github.com/tokio-rs/axum/blob/main/examples/graceful-shutdown/src/main.rs <3
 
     let ctrl_c = async {
@@ -414,8 +2578,4 @@ async fn shutdown_signal(db_shutdown_lock: Arc<RwLock<SunnyDB<PowerValues>>>) {
         _ = ctrl_c => {},
         _ = terminate => {},
     }
-
-    // flush the database
-    let mut write_lock = db_shutdown_lock.write().await;
-    write_lock.lossy_persist();
 }
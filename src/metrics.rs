@@ -0,0 +1,246 @@
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use sunny_db::write_metrics::{Histogram, WriteMetrics};
+
+/// Upper bounds (in ms) of the buckets of the latency histograms; slower requests end up in
+/// an additional overflow bucket
+const BUCKET_BOUNDS_MS: [f64; 12] = [
+    1.0, 2.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0,
+];
+
+/// Latency histograms of all routes, keyed by the route's path pattern
+#[derive(Default)]
+pub struct RouteMetrics {
+    histograms: Mutex<BTreeMap<String, Histogram>>,
+}
+
+impl RouteMetrics {
+    pub fn record(&self, route: &str, latency: Duration) {
+        let mut histograms = self.histograms.lock().unwrap();
+        histograms
+            .entry(route.to_owned())
+            .or_insert_with(|| Histogram::new(&BUCKET_BOUNDS_MS))
+            .record(latency.as_secs_f64() * 1e3);
+    }
+
+    /// the latencies in ms
+    pub fn snapshot(&self) -> BTreeMap<String, ValueHistogram> {
+        let histograms = self.histograms.lock().unwrap();
+        histograms
+            .iter()
+            .map(|(route, histogram)| (route.clone(), histogram.into()))
+            .collect()
+    }
+}
+
+/// middleware recording the latency of every request to a route
+pub async fn track_latency(
+    State(metrics): State<Arc<RouteMetrics>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_owned())
+        .unwrap_or_else(|| request.uri().path().to_owned());
+    let start = Instant::now();
+    let response = next.run(request).await;
+    metrics.record(&route, start.elapsed());
+    response
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ValueBucket {
+    /// upper bound of the bucket; none for the overflow bucket
+    pub le: Option<f64>,
+    pub count: u64,
+}
+
+/// Distribution of a measurement, e.g. the latency of a route or one of the write path; the
+/// bucket counts aren't cumulative
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ValueHistogram {
+    pub count: u64,
+    pub sum: f64,
+    pub max: f64,
+    pub buckets: Vec<ValueBucket>,
+}
+
+impl From<&Histogram> for ValueHistogram {
+    fn from(histogram: &Histogram) -> Self {
+        let buckets = histogram
+            .bounds
+            .iter()
+            .map(|b| Some(*b))
+            .chain([None])
+            .zip(&histogram.counts)
+            .map(|(le, count)| ValueBucket { le, count: *count })
+            .collect();
+        ValueHistogram {
+            count: histogram.count,
+            sum: histogram.sum,
+            max: histogram.max,
+            buckets,
+        }
+    }
+}
+
+impl ValueHistogram {
+    /// the histogram in the Prometheus text format, with the cumulative bucket counts it
+    /// expects; the labels are added to every line, e.g. `route="/values"`
+    pub fn to_prometheus(&self, name: &str, labels: &str) -> String {
+        let separator = if labels.is_empty() { "" } else { "," };
+        let mut lines = String::new();
+        let mut cumulative = 0;
+        for bucket in &self.buckets {
+            cumulative += bucket.count;
+            let le = bucket.le.map_or(String::from("+Inf"), |le| le.to_string());
+            lines += &format!(
+                "{}_bucket{{{}{}le=\"{}\"}} {}\n",
+                name, labels, separator, le, cumulative
+            );
+        }
+        let labels = if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", labels)
+        };
+        lines += &format!("{}_sum{} {}\n", name, labels, self.sum);
+        lines += &format!("{}_count{} {}\n", name, labels, self.count);
+        lines
+    }
+}
+
+/// How long inserting values and writing segments took and how big the segments were
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct WritePathMetrics {
+    pub insert_us: ValueHistogram,
+    pub flush_ms: ValueHistogram,
+    pub encode_ms: ValueHistogram,
+    pub segment_bytes: ValueHistogram,
+}
+
+impl From<&WriteMetrics> for WritePathMetrics {
+    fn from(metrics: &WriteMetrics) -> Self {
+        WritePathMetrics {
+            insert_us: (&metrics.insert_us).into(),
+            flush_ms: (&metrics.flush_ms).into(),
+            encode_ms: (&metrics.encode_ms).into(),
+            segment_bytes: (&metrics.segment_bytes).into(),
+        }
+    }
+}
+
+impl WritePathMetrics {
+    pub fn to_prometheus(&self) -> String {
+        [
+            ("sunny_insert_duration_us", &self.insert_us),
+            ("sunny_flush_duration_ms", &self.flush_ms),
+            ("sunny_encode_duration_ms", &self.encode_ms),
+            ("sunny_segment_bytes", &self.segment_bytes),
+        ]
+        .into_iter()
+        .map(|(name, histogram)| {
+            format!("# TYPE {} histogram\n", name) + &histogram.to_prometheus(name, "")
+        })
+        .collect()
+    }
+}
+
+/// Where slow queries of a route are logged, i.e. the threshold and the matched route
+pub struct SlowQueryLog {
+    pub threshold: Option<Duration>,
+    pub route: MatchedPath,
+}
+
+/// What's logged about queries that take longer than the configured threshold
+pub struct QueryDetails {
+    pub start_time: u64,
+    pub end_time: u64,
+    /// the resolution of the result, i.e. the timestamp unit and any downsampling
+    pub resolution: String,
+    /// persisted segments that had to be read
+    pub segments: usize,
+}
+
+/// logs the query if it took longer than the threshold; the details are only computed then
+pub fn log_if_slow(log: &SlowQueryLog, elapsed: Duration, details: impl FnOnce() -> QueryDetails) {
+    let Some(threshold) = log.threshold else {
+        return;
+    };
+    if elapsed < threshold {
+        return;
+    }
+    let details = details();
+    println!(
+        "Slow query: {} from {} to {} at resolution {} took {} ms, touching {} segments",
+        log.route.as_str(),
+        details.start_time,
+        details.end_time,
+        details.resolution,
+        elapsed.as_millis(),
+        details.segments
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latency_histogram() {
+        let metrics = RouteMetrics::default();
+        metrics.record("/values", Duration::from_micros(500));
+        metrics.record("/values", Duration::from_millis(30));
+        metrics.record("/values", Duration::from_secs(10));
+        metrics.record("/metrics", Duration::from_millis(1));
+
+        let snapshot = metrics.snapshot();
+        let values = &snapshot["/values"];
+        assert_eq!(values.count, 3);
+        assert_eq!(values.max, 10000.0);
+        assert_eq!(values.buckets[0].count, 1);
+        assert_eq!(values.buckets[5].le, Some(50.0));
+        assert_eq!(values.buckets[5].count, 1);
+        assert_eq!(values.buckets.last().unwrap().le, None);
+        assert_eq!(values.buckets.last().unwrap().count, 1);
+        // the bounds are inclusive
+        assert_eq!(snapshot["/metrics"].buckets[0].count, 1);
+        assert!(snapshot["/metrics"]
+            .to_prometheus("sunny_test", "route=\"/metrics\"")
+            .starts_with("sunny_test_bucket{route=\"/metrics\",le=\"1\"} 1\n"));
+    }
+
+    #[test]
+    fn test_write_path_metrics() {
+        let mut histogram = Histogram::new(&[1.0, 10.0]);
+        for value in [0.5, 3.0, 20.0] {
+            histogram.record(value);
+        }
+        let histogram = ValueHistogram::from(&histogram);
+        assert_eq!(
+            histogram.buckets[1],
+            ValueBucket {
+                le: Some(10.0),
+                count: 1
+            }
+        );
+        assert_eq!(histogram.buckets[2], ValueBucket { le: None, count: 1 });
+        assert_eq!(
+            histogram.to_prometheus("sunny_test", ""),
+            "sunny_test_bucket{le=\"1\"} 1\n\
+             sunny_test_bucket{le=\"10\"} 2\n\
+             sunny_test_bucket{le=\"+Inf\"} 3\n\
+             sunny_test_sum 23.5\n\
+             sunny_test_count 3\n"
+        );
+    }
+}
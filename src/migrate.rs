@@ -0,0 +1,539 @@
+use anyhow::{self, Context};
+use rusqlite::{params_from_iter, Connection, OpenFlags};
+use std::collections::BTreeMap;
+use std::io::BufRead;
+use sunny_db::codec::SegmentEncoding;
+use sunny_db::timeseries::{Quality, TimeSeries};
+use sunny_db::timeseries_db::SunnyDB;
+
+use crate::audit::AuditLog;
+use crate::config::TimestampSettings;
+use crate::{load_encryption_key, PowerValues};
+
+const HOUR_MS: u64 = 3600 * 1000;
+
+/// The series of another logger holding the values of sunny's fields; the grid is either a
+/// single signed series (positive while purchasing, like P_Grid of Fronius inverters) or one
+/// series per direction. Without a series for the consumption, it's derived from the others
+#[derive(clap::Args, Debug)]
+pub struct SeriesArgs {
+    // Series of the PV production
+    #[arg(long)]
+    pv: String,
+
+    // Series of the grid power, positive while purchasing and negative while feeding in
+    #[arg(long)]
+    grid: Option<String>,
+
+    // Series of the energy or power fed into the grid
+    #[arg(long)]
+    to_grid: Option<String>,
+
+    // Series of the energy or power purchased from the grid
+    #[arg(long)]
+    from_grid: Option<String>,
+
+    // Series of the consumption
+    #[arg(long)]
+    used: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct DatabaseArgs {
+    // Database directory, e.g. <sunny-home>/db; sunny mustn't be running while importing
+    #[arg(long)]
+    data_dir: String,
+
+    // Time series segment size
+    #[arg(long, default_value_t = 100)]
+    segment_size: usize,
+
+    // Optional file holding the 32 byte key segments are encrypted with
+    #[arg(long)]
+    key_file: Option<String>,
+
+    // Encoding of the imported segments: zstd or gorilla
+    #[arg(long, default_value = "zstd")]
+    segment_encoding: SegmentEncoding,
+
+    // Number of decimal places the imported values are rounded to and stored as scaled
+    // integers with
+    #[arg(long, allow_negative_numbers = true)]
+    quantize_decimals: Option<i8>,
+
+    // Imports with values before this UTC day fail, e.g. timestamps of a clock that wasn't set
+    #[arg(long, default_value = "2000-01-01")]
+    earliest: chrono::NaiveDate,
+
+    // Imports with values from this UTC day on fail, e.g. timestamps in the wrong unit
+    #[arg(long, default_value = "2100-01-01")]
+    latest: chrono::NaiveDate,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct HomeAssistantArgs {
+    // The recorder's database, usually home-assistant_v2.db in Home Assistant's config directory
+    #[arg(long)]
+    file: String,
+
+    #[command(flatten)]
+    series: SeriesArgs,
+
+    #[command(flatten)]
+    database: DatabaseArgs,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct InfluxArgs {
+    // File in InfluxDB's line protocol, e.g. written by `influxd inspect export-lp`
+    #[arg(long)]
+    file: String,
+
+    #[command(flatten)]
+    series: SeriesArgs,
+
+    // Precision of the timestamps: ns, us, ms or s
+    #[arg(long, default_value_t = String::from("ns"))]
+    precision: String,
+
+    // Length of the intervals in seconds the values are averaged over
+    #[arg(long, default_value_t = 60)]
+    interval: u64,
+
+    // Factor converting the values to W, e.g. 1000 if they're in kW
+    #[arg(long, default_value_t = 1.0)]
+    scale: f64,
+
+    #[command(flatten)]
+    database: DatabaseArgs,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Field {
+    Pv,
+    Grid,
+    ToGrid,
+    FromGrid,
+    Used,
+}
+
+impl SeriesArgs {
+    fn validate(&self) -> anyhow::Result<()> {
+        match (&self.grid, &self.to_grid, &self.from_grid) {
+            (Some(_), None, None) | (None, Some(_), Some(_)) => Ok(()),
+            _ => anyhow::bail!("Either --grid or both --to-grid and --from-grid are needed"),
+        }
+    }
+
+    /// the series and the field each of them holds
+    fn fields(&self) -> Vec<(&str, Field)> {
+        [
+            (Some(&self.pv), Field::Pv),
+            (self.grid.as_ref(), Field::Grid),
+            (self.to_grid.as_ref(), Field::ToGrid),
+            (self.from_grid.as_ref(), Field::FromGrid),
+            (self.used.as_ref(), Field::Used),
+        ]
+        .into_iter()
+        .filter_map(|(series, field)| Some((series?.as_str(), field)))
+        .collect()
+    }
+}
+
+/// averages the powers (in W) of each field per interval and combines them into values at the
+/// start of each interval, flagged as backfilled; intervals lacking any of the needed fields
+/// are skipped
+fn combine(
+    samples: impl IntoIterator<Item = (Field, u64, f64)>,
+    interval_ms: u64,
+) -> TimeSeries<PowerValues> {
+    let mut intervals: BTreeMap<u64, [(f64, u32); 5]> = BTreeMap::new();
+    for (field, time, watts) in samples {
+        let sums = intervals
+            .entry(time - time % interval_ms)
+            .or_insert([(0.0, 0); 5]);
+        sums[field as usize].0 += watts;
+        sums[field as usize].1 += 1;
+    }
+
+    let mut series = TimeSeries::<PowerValues>::new(intervals.len());
+    for (time, sums) in intervals {
+        let average = |field: Field| {
+            let (sum, count) = sums[field as usize];
+            (count > 0).then(|| sum / count as f64)
+        };
+        let grid = average(Field::Grid);
+        let to_grid = average(Field::ToGrid).or(grid.map(|g| (-g).max(0.0)));
+        let from_grid = average(Field::FromGrid).or(grid.map(|g| g.max(0.0)));
+        let (Some(pv), Some(to_grid), Some(from_grid)) = (average(Field::Pv), to_grid, from_grid)
+        else {
+            continue;
+        };
+        let values = PowerValues {
+            power_pv: pv,
+            power_to_grid: to_grid,
+            power_from_grid: from_grid,
+            power_used: average(Field::Used).unwrap_or(pv - to_grid + from_grid),
+        };
+        series.insert_value_with_quality(time, values, Quality::Backfilled);
+    }
+    series
+}
+
+/// reads the hourly long-term statistics of Home Assistant's recorder (2023.3 or newer): the
+/// means of sensors in W or kW, and the energy per hour of sensors in Wh, kWh or MWh, derived
+/// from the increase of their sum, as average power
+fn read_home_assistant(
+    connection: &Connection,
+    series: &SeriesArgs,
+) -> anyhow::Result<TimeSeries<PowerValues>> {
+    let fields = series.fields();
+    let placeholders = vec!["?"; fields.len()].join(", ");
+    let mut statement = connection.prepare(&format!(
+        "SELECT m.statistic_id, m.unit_of_measurement, s.start_ts, s.mean, s.sum \
+         FROM statistics s JOIN statistics_meta m ON s.metadata_id = m.id \
+         WHERE m.statistic_id IN ({}) ORDER BY m.statistic_id, s.start_ts",
+        placeholders
+    ))?;
+    let mut rows = statement.query(params_from_iter(fields.iter().map(|(id, _)| id)))?;
+
+    let mut samples = Vec::new();
+    let mut found: Vec<String> = Vec::new();
+    // the sum at the end of the previous hour of the same statistic
+    let mut previous: Option<(u64, f64)> = None;
+    while let Some(row) = rows.next()? {
+        let id: String = row.get(0)?;
+        let unit: Option<String> = row.get(1)?;
+        let start = (row.get::<_, f64>(2)? * 1000.0).round() as u64;
+        if found.last() != Some(&id) {
+            found.push(id.clone());
+            previous = None;
+        }
+        let field = fields.iter().find(|(s, _)| *s == id).map(|(_, f)| *f);
+        let Some(field) = field else {
+            continue;
+        };
+
+        let watts = match unit.as_deref() {
+            Some("W") => row.get::<_, Option<f64>>(3)?,
+            Some("kW") => row.get::<_, Option<f64>>(3)?.map(|m| m * 1e3),
+            Some(energy_unit @ ("Wh" | "kWh" | "MWh")) => {
+                let scale = match energy_unit {
+                    "Wh" => 1.0,
+                    "kWh" => 1e3,
+                    _ => 1e6,
+                };
+                let sum: Option<f64> = row.get(4)?;
+                // the row of an hour holds the sum at its end
+                let energy = match (previous, sum) {
+                    (Some((time, before)), Some(sum)) if time + HOUR_MS == start => {
+                        Some((sum - before) * scale)
+                    }
+                    _ => None,
+                };
+                previous = sum.map(|sum| (start, sum));
+                energy
+            }
+            unit => anyhow::bail!("{} has the unsupported unit {:?}", id, unit),
+        };
+        if let Some(watts) = watts {
+            samples.push((field, start, watts));
+        }
+    }
+
+    for (id, _) in &fields {
+        if !found.iter().any(|f| f == id) {
+            anyhow::bail!("There are no statistics of {}", id);
+        }
+    }
+    Ok(combine(samples, HOUR_MS))
+}
+
+/// A series of the line protocol: `<measurement>[,<tag>=<value>...]:<field>`; points match if
+/// they have all the given tags
+#[derive(Debug, PartialEq)]
+struct LineSeries {
+    measurement: String,
+    tags: Vec<(String, String)>,
+    field: String,
+}
+
+impl LineSeries {
+    fn parse(series: &str) -> anyhow::Result<LineSeries> {
+        let (key, field) = series
+            .rsplit_once(':')
+            .with_context(|| format!("Series '{}' lacks the ':<field>'", series))?;
+        let (measurement, tags) = parse_series_key(key)?;
+        Ok(LineSeries {
+            measurement,
+            tags,
+            field: field.to_owned(),
+        })
+    }
+
+    fn matches(&self, measurement: &str, tags: &[(String, String)]) -> bool {
+        self.measurement == measurement && self.tags.iter().all(|tag| tags.contains(tag))
+    }
+}
+
+/// splits a string at the delimiter, except where it's escaped with a backslash or quoted
+fn split_unescaped(s: &str, delimiter: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = s.chars();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    parts.last_mut().unwrap().push(escaped);
+                }
+            }
+            '"' => {
+                quoted = !quoted;
+                parts.last_mut().unwrap().push(c);
+            }
+            c if c == delimiter && !quoted => parts.push(String::new()),
+            c => parts.last_mut().unwrap().push(c),
+        }
+    }
+    parts
+}
+
+fn parse_series_key(key: &str) -> anyhow::Result<(String, Vec<(String, String)>)> {
+    let mut parts = split_unescaped(key, ',').into_iter();
+    let measurement = parts.next().unwrap_or_default();
+    let tags = parts
+        .map(|tag| {
+            tag.split_once('=')
+                .map(|(k, v)| (k.to_owned(), v.to_owned()))
+                .with_context(|| format!("Invalid tag '{}'", tag))
+        })
+        .collect::<anyhow::Result<_>>()?;
+    Ok((measurement, tags))
+}
+
+/// the field values of a line of the line protocol with its timestamp, skipping non-numeric
+/// ones; None for comments, DDL and points without a timestamp
+#[allow(clippy::type_complexity)]
+fn parse_line(
+    line: &str,
+) -> anyhow::Result<Option<(String, Vec<(String, String)>, Vec<(String, f64)>, i64)>> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') || line.starts_with("CREATE ") {
+        return Ok(None);
+    }
+    let parts = split_unescaped(line, ' ');
+    let [key, fields, timestamp] = parts.as_slice() else {
+        return Ok(None);
+    };
+    let (measurement, tags) = parse_series_key(key)?;
+    let fields = split_unescaped(fields, ',')
+        .iter()
+        .filter_map(|field| {
+            let (name, value) = field.split_once('=')?;
+            let value = value.trim_end_matches(['i', 'u']).parse().ok()?;
+            Some((name.to_owned(), value))
+        })
+        .collect();
+    let timestamp = timestamp
+        .parse()
+        .with_context(|| format!("Invalid timestamp '{}'", timestamp))?;
+    Ok(Some((measurement, tags, fields, timestamp)))
+}
+
+/// reads the points of the series from InfluxDB's line protocol and averages them per
+/// interval; `nanos_per_unit` is the length of the unit of the timestamps in ns
+fn read_line_protocol(
+    reader: impl BufRead,
+    series: &SeriesArgs,
+    nanos_per_unit: i64,
+    interval_ms: u64,
+    scale: f64,
+) -> anyhow::Result<TimeSeries<PowerValues>> {
+    let fields = series
+        .fields()
+        .into_iter()
+        .map(|(series, field)| Ok((LineSeries::parse(series)?, field)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut samples = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let point = parse_line(&line).with_context(|| format!("Invalid line {}", i + 1))?;
+        let Some((measurement, tags, values, timestamp)) = point else {
+            continue;
+        };
+        let Ok(time) = u64::try_from(timestamp.saturating_mul(nanos_per_unit) / 1_000_000) else {
+            continue;
+        };
+        for (series, field) in &fields {
+            if !series.matches(&measurement, &tags) {
+                continue;
+            }
+            if let Some((_, value)) = values.iter().find(|(name, _)| *name == series.field) {
+                samples.push((*field, time, value * scale));
+            }
+        }
+    }
+    Ok(combine(samples, interval_ms))
+}
+
+/// writes the imported values into the database; returns the number of imported values
+pub fn write_series(
+    args: &DatabaseArgs,
+    series: &TimeSeries<PowerValues>,
+    source: &str,
+) -> anyhow::Result<usize> {
+    let bounds = TimestampSettings {
+        earliest: args.earliest,
+        latest: args.latest,
+    }
+    .bounds()?;
+    let mut db = SunnyDB::<PowerValues>::new(args.segment_size, &args.data_dir, 2, 0)?
+        .with_segment_encoding(args.segment_encoding)?
+        .with_timestamp_bounds(bounds)?;
+    if let Some(decimals) = args.quantize_decimals {
+        db = db.with_quantization(decimals)?;
+    }
+    if let Some(key) = load_encryption_key(args.key_file.as_deref())? {
+        db = db.with_encryption_key(key)?;
+    }
+    db = db.with_energy_aggregates()?;
+    let segments = db.import_series(series)?;
+    println!(
+        "Imported {} values in {} segments from {}",
+        series.len(),
+        segments,
+        source
+    );
+    Ok(series.len())
+}
+
+/// runs an import of the file and records it in the audit log next to the database
+pub fn audited_import(
+    args: &DatabaseArgs,
+    action: &str,
+    file: &str,
+    import: impl FnOnce() -> anyhow::Result<usize>,
+) -> anyhow::Result<usize> {
+    let result = import();
+    let parameters = serde_json::json!({ "file": file, "data_dir": args.data_dir });
+    AuditLog::next_to(&args.data_dir).record_import(action, parameters, &result);
+    result
+}
+
+/// imports the statistics of Home Assistant's recorder into the database
+pub fn run_home_assistant(args: &HomeAssistantArgs) -> anyhow::Result<usize> {
+    audited_import(&args.database, "import-home-assistant", &args.file, || {
+        args.series.validate()?;
+        let connection = Connection::open_with_flags(&args.file, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .with_context(|| format!("Couldn't open {}", args.file))?;
+        let series = read_home_assistant(&connection, &args.series)?;
+        write_series(&args.database, &series, &args.file)
+    })
+}
+
+/// imports points in InfluxDB's line protocol into the database
+pub fn run_influx(args: &InfluxArgs) -> anyhow::Result<usize> {
+    audited_import(&args.database, "import-influx", &args.file, || {
+        args.series.validate()?;
+        let nanos_per_unit = match args.precision.as_str() {
+            "ns" => 1,
+            "us" => 1_000,
+            "ms" => 1_000_000,
+            "s" => 1_000_000_000,
+            precision => anyhow::bail!("Unknown precision '{}'", precision),
+        };
+        if args.interval == 0 {
+            anyhow::bail!("The interval must not be 0");
+        }
+        let file = std::fs::File::open(&args.file)
+            .with_context(|| format!("Couldn't open {}", args.file))?;
+        let series = read_line_protocol(
+            std::io::BufReader::new(file),
+            &args.series,
+            nanos_per_unit,
+            args.interval * 1000,
+            args.scale,
+        )?;
+        write_series(&args.database, &series, &args.file)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series_args(pv: &str, grid: &str) -> SeriesArgs {
+        SeriesArgs {
+            pv: pv.to_owned(),
+            grid: Some(grid.to_owned()),
+            to_grid: None,
+            from_grid: None,
+            used: None,
+        }
+    }
+
+    #[test]
+    fn test_read_home_assistant() {
+        let connection = Connection::open_in_memory().unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE statistics_meta (id INTEGER PRIMARY KEY, statistic_id TEXT, unit_of_measurement TEXT);
+                 CREATE TABLE statistics (id INTEGER PRIMARY KEY, metadata_id INTEGER, start_ts REAL, mean REAL, sum REAL);
+                 INSERT INTO statistics_meta VALUES (1, 'sensor.pv_energy', 'kWh'), (2, 'sensor.grid_power', 'W');
+                 -- 2024-06-01 10:00 to 13:00 UTC, the hour starting 12:00 is missing
+                 INSERT INTO statistics (metadata_id, start_ts, mean, sum) VALUES
+                     (1, 1717236000.0, NULL, 10.0), (1, 1717239600.0, NULL, 12.5),
+                     (1, 1717243200.0, NULL, 14.0), (1, 1717250400.0, NULL, 20.0),
+                     (2, 1717239600.0, -1000.0, NULL), (2, 1717243200.0, 300.0, NULL);",
+            )
+            .unwrap();
+
+        let args = series_args("sensor.pv_energy", "sensor.grid_power");
+        let series = read_home_assistant(&connection, &args).unwrap();
+        let values = series.get_current_values();
+        assert_eq!(values.len(), 2);
+        assert_eq!(values[0].0, 1717239600000);
+        assert_eq!(values[0].1.power_pv, 2500.0);
+        assert_eq!(values[0].1.power_to_grid, 1000.0);
+        assert_eq!(values[0].1.power_used, 1500.0);
+        assert_eq!(values[1].1.power_from_grid, 300.0);
+        assert_eq!(values[1].1.power_used, 1800.0);
+
+        let missing = series_args("sensor.pv_energy", "sensor.grid");
+        assert!(read_home_assistant(&connection, &missing).is_err());
+    }
+
+    #[test]
+    fn test_read_line_protocol() {
+        let export = "# DDL\n\
+            CREATE DATABASE home\n\
+            # DML\n\
+            W,entity_id=pv_power value=3000 1717236000000000000\n\
+            W,entity_id=pv_power value=2000i 1717236030000000000\n\
+            W,entity_id=grid_power value=-500.5,friendly_name=\"Grid power\" 1717236000000000000\n\
+            W,entity_id=pv_power value=1000 1717236060000000000\n\
+            W,entity_id=other\\ sensor value=1 1717236000000000000\n";
+        let args = series_args("W,entity_id=pv_power:value", "W,entity_id=grid_power:value");
+        let series = read_line_protocol(export.as_bytes(), &args, 1, 60 * 1000, 1.0).unwrap();
+        let values = series.get_current_values();
+        // the second interval lacks the grid
+        assert_eq!(values.len(), 1);
+        assert_eq!(values[0].0, 1717236000000);
+        assert_eq!(values[0].1.power_pv, 2500.0);
+        assert_eq!(values[0].1.power_to_grid, 500.5);
+        assert_eq!(values[0].1.power_from_grid, 0.0);
+
+        assert_eq!(
+            LineSeries::parse("W,entity_id=sensor.pv:value").unwrap(),
+            LineSeries {
+                measurement: String::from("W"),
+                tags: vec![(String::from("entity_id"), String::from("sensor.pv"))],
+                field: String::from("value"),
+            }
+        );
+        assert!(LineSeries::parse("W").is_err());
+        assert!(read_line_protocol("W value=1 x\n".as_bytes(), &args, 1, 1000, 1.0).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+use sunny_db::timeseries::TimeSeriesView;
+
+use crate::battery::{intervals, GridExchange, Interval};
+use crate::PowerValues;
+
+/// Query parameters of `GET /panel-simulation/:start_time/:end_time`, e.g. `?scale=1.4` for
+/// 40% more kWp
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct PanelParams {
+    pub scale: f64,
+}
+
+/// Response of `GET /panel-simulation/:start_time/:end_time`
+#[derive(Serialize, Debug)]
+pub struct PanelSimulation {
+    pub scale: f64,
+    pub used_kwh: f64,
+    pub pv_kwh: f64,
+    /// the PV production of the scaled array
+    pub scaled_pv_kwh: f64,
+    pub current: GridExchange,
+    pub scaled: GridExchange,
+    /// how much more (or less) of the consumption the scaled array would have covered
+    pub additional_self_consumed_kwh: f64,
+    /// how much more (or less) would have been fed into the grid
+    pub additional_to_grid_kwh: f64,
+}
+
+/// energies in Wh of an array
+#[derive(Default)]
+struct Totals {
+    pv: f64,
+    from_grid: f64,
+    to_grid: f64,
+}
+
+impl Totals {
+    fn add(&mut self, interval: &Interval, scale: f64) {
+        self.pv += interval.mean_pv() * scale * interval.hours;
+        for (hours, surplus) in interval.surpluses(scale) {
+            self.from_grid += (-surplus).max(0.0) * hours;
+            self.to_grid += surplus.max(0.0) * hours;
+        }
+    }
+}
+
+/// scales the PV production by `scale`, e.g. 1.4 for panels adding 40% to the peak power, to
+/// see how the self-consumption and the feed-in would have changed. Only an array that's
+/// oriented the same way can be simulated, since the shape of the production doesn't change;
+/// a differently oriented one would need a model of its production. Inverter clipping isn't
+/// taken into account either
+pub fn simulate(
+    series: TimeSeriesView<'_, PowerValues>,
+    params: PanelParams,
+) -> anyhow::Result<PanelSimulation> {
+    if !(params.scale > 0.0 && params.scale.is_finite()) {
+        anyhow::bail!("The scale has to be a positive number");
+    }
+    let (mut current, mut scaled) = (Totals::default(), Totals::default());
+    let mut used = 0.0;
+    for interval in intervals(series) {
+        used += interval.mean_used() * interval.hours;
+        current.add(&interval, 1.0);
+        scaled.add(&interval, params.scale);
+    }
+    let self_consumed = |totals: &Totals| totals.pv - totals.to_grid;
+    let exchange = |totals: &Totals| {
+        GridExchange::from_energies(totals.pv, used, totals.from_grid, totals.to_grid)
+    };
+    Ok(PanelSimulation {
+        scale: params.scale,
+        used_kwh: used / 1000.0,
+        pv_kwh: current.pv / 1000.0,
+        scaled_pv_kwh: scaled.pv / 1000.0,
+        current: exchange(&current),
+        scaled: exchange(&scaled),
+        additional_self_consumed_kwh: (self_consumed(&scaled) - self_consumed(&current)) / 1000.0,
+        additional_to_grid_kwh: (scaled.to_grid - current.to_grid) / 1000.0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sunny_db::timeseries::TimeSeries;
+
+    #[test]
+    fn test_simulate() {
+        let hour = 3_600_000;
+        let mut series = TimeSeries::<PowerValues>::new(11);
+        // 1 kW drawn throughout, PV of 500 W for two hours, then 2 kW for two
+        for h in 0..=4 {
+            let power_pv = if h <= 2 { 500.0 } else { 2000.0 };
+            let values = PowerValues {
+                power_pv,
+                power_to_grid: 0.0,
+                power_from_grid: 0.0,
+                power_used: 1000.0,
+            };
+            series.insert_value_at_time(h * hour, values);
+        }
+        // a value with missing fields isn't integrated
+        let mut missing = *series.iter().next_back().unwrap().1;
+        missing.power_pv = f64::NAN;
+        series.insert_value_at_time(5 * hour, missing);
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+
+        let simulation = simulate(series.view(), PanelParams { scale: 1.4 }).unwrap();
+        assert!(close(simulation.used_kwh, 4.0));
+        // the hour from 2 to 3 has a mean of 1250 W
+        assert!(close(simulation.pv_kwh, 4.25));
+        assert!(close(simulation.scaled_pv_kwh, 4.25 * 1.4));
+        // from 2 to 3 the deficit of 500 W turns into a surplus of 1 kW after 20 minutes
+        let (from_grid, to_grid) = (1.0 + 0.25 / 3.0, 1.0 + 1.0 / 3.0);
+        assert!(close(simulation.current.from_grid_kwh, from_grid));
+        assert!(close(simulation.current.to_grid_kwh, to_grid));
+        // 700 W for two hours, then from a deficit of 300 W to a surplus of 1.8 kW, which
+        // crosses zero after a seventh of the hour, and a surplus of 1.8 kW for the last one
+        let (scaled_from_grid, scaled_to_grid) = (0.6 + 0.15 / 7.0, 1.8 + 0.9 * 6.0 / 7.0);
+        assert!(close(simulation.scaled.from_grid_kwh, scaled_from_grid));
+        assert!(close(simulation.scaled.to_grid_kwh, scaled_to_grid));
+        let additional_to_grid = scaled_to_grid - to_grid;
+        assert!(close(
+            simulation.additional_self_consumed_kwh,
+            4.25 * 0.4 - additional_to_grid
+        ));
+        assert!(close(simulation.additional_to_grid_kwh, additional_to_grid));
+        assert!(close(
+            simulation.scaled.autarky.unwrap(),
+            1.0 - scaled_from_grid / 4.0
+        ));
+
+        let unchanged = simulate(series.view(), PanelParams { scale: 1.0 }).unwrap();
+        assert_eq!(unchanged.current, unchanged.scaled);
+        assert!(simulate(series.view(), PanelParams { scale: 0.0 }).is_err());
+        assert!(simulate(series.view(), PanelParams { scale: f64::NAN }).is_err());
+    }
+}
@@ -0,0 +1,135 @@
+use chrono::{Datelike, Months, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::Serialize;
+use sunny_db::statistics::PeakWindowAverage;
+use sunny_db::timeseries_db::SunnyDB;
+
+use crate::config::BillingSettings;
+use crate::summary::day_range;
+use crate::PowerValues;
+
+/// The highest average grid import within a billing period, which many utilities bill as
+/// (maximum) demand charge
+#[derive(Serialize, Debug, PartialEq)]
+pub struct PeakDemand {
+    /// start of the billing period in ms
+    pub period_start: u64,
+    /// end of the billing period in ms (exclusive)
+    pub period_end: u64,
+    /// null if the values within the period don't even cover a single window
+    pub peak: Option<PeakDemandWindow>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct PeakDemandWindow {
+    pub start: u64,
+    pub end: u64,
+    /// average grid import within the window in W
+    pub power_from_grid: f64,
+}
+
+/// the first day of the billing period containing the date
+fn period_start_date(date: NaiveDate, start_day: u32) -> NaiveDate {
+    let this_month = date
+        .with_day(start_day)
+        .expect("billing periods start on one of the first 28 days");
+    if this_month <= date {
+        this_month
+    } else {
+        this_month - Months::new(1)
+    }
+}
+
+/// the local billing periods [start, end) in ms containing the times from first to last
+pub fn billing_periods(first: u64, last: u64, timezone: Tz, start_day: u32) -> Vec<(u64, u64)> {
+    let Some(first_date) = Utc
+        .timestamp_millis_opt(first as i64)
+        .single()
+        .map(|t| t.with_timezone(&timezone).date_naive())
+    else {
+        return Vec::new();
+    };
+
+    let mut periods = Vec::new();
+    let mut date = period_start_date(first_date, start_day);
+    loop {
+        let next = date + Months::new(1);
+        let period = (day_range(date, timezone).0, day_range(next, timezone).0);
+        periods.push(period);
+        if period.1 > last {
+            return periods;
+        }
+        date = next;
+    }
+}
+
+/// the peak demand of every billing period with values between start_time and end_time
+pub fn peak_demand(
+    db: &SunnyDB<PowerValues>,
+    start_time: u64,
+    end_time: u64,
+    timezone: Tz,
+    settings: &BillingSettings,
+) -> Vec<PeakDemand> {
+    let Some(series) = db.get_values_in_range(start_time, end_time) else {
+        return Vec::new();
+    };
+    let (Some(first), Some(last)) = (series.get_start_time(), series.get_end_time()) else {
+        return Vec::new();
+    };
+
+    let resolution = series.get_resolution();
+    let window = resolution.from_millis(settings.peak_window_minutes * 60 * 1000);
+    let periods = billing_periods(
+        resolution.to_millis(first),
+        resolution.to_millis(last),
+        timezone,
+        settings.period_start_day,
+    );
+    periods
+        .into_iter()
+        .map(|(period_start, period_end)| {
+            // ranges don't include values at their start time
+            let values = series.view_range(
+                resolution.from_millis(period_start).saturating_sub(1),
+                resolution.from_millis(period_end) - 1,
+            );
+            let peak = values.and_then(|v| v.peak_window_average(window, |v| v.power_from_grid));
+            PeakDemand {
+                period_start,
+                period_end,
+                peak: peak.map(|peak| PeakDemandWindow {
+                    start: resolution.to_millis(peak.start),
+                    end: resolution.to_millis(peak.end),
+                    power_from_grid: peak.average,
+                }),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_billing_periods() {
+        let berlin: Tz = "Europe/Berlin".parse().unwrap();
+        // 2024-03-10 12:00 UTC to 2024-05-20 12:00 UTC
+        let periods = billing_periods(1710072000000, 1716206400000, berlin, 15);
+        assert_eq!(
+            periods,
+            vec![
+                // 2024-02-15 00:00 CET to 2024-03-15 00:00 CET
+                (1707951600000, 1710457200000),
+                // until 2024-04-15 00:00 CEST, after the change to summer time
+                (1710457200000, 1713132000000),
+                (1713132000000, 1715724000000),
+                (1715724000000, 1718402400000),
+            ]
+        );
+
+        let utc_periods = billing_periods(1709251200000, 1709251200001, Tz::UTC, 1);
+        assert_eq!(utc_periods, vec![(1709251200000, 1711929600000)]);
+    }
+}
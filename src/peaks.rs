@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+use sunny_db::statistics::Peaks;
+use sunny_db::timeseries::TimeSeriesView;
+
+use crate::correlation::{field_series, PowerField};
+use crate::PowerValues;
+
+/// Number of peaks unless asked for another one
+pub const DEFAULT_PEAKS: usize = 5;
+/// Minimum time between two peaks in ms unless asked for another one, so the values around a
+/// single spike don't show up as several peaks
+pub const DEFAULT_MIN_SEPARATION_MS: u64 = 3_600_000;
+
+/// Query parameters of `GET /peaks/:field/:start_time/:end_time`, e.g.
+/// `?n=5&min_separation_ms=3600000`
+#[derive(Deserialize)]
+pub struct PeaksParams {
+    pub n: Option<usize>,
+    pub min_separation_ms: Option<u64>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct PowerPeak {
+    /// in ms
+    pub time: u64,
+    /// in W
+    pub value: f64,
+}
+
+/// the highest local maxima of the field, highest first
+pub fn peaks(
+    series: TimeSeriesView<'_, PowerValues>,
+    field: PowerField,
+    params: PeaksParams,
+) -> Vec<PowerPeak> {
+    let resolution = series.get_resolution();
+    let min_separation = resolution.from_millis(
+        params
+            .min_separation_ms
+            .unwrap_or(DEFAULT_MIN_SEPARATION_MS),
+    );
+    field_series(series, field)
+        .peaks(params.n.unwrap_or(DEFAULT_PEAKS), min_separation)
+        .into_iter()
+        .map(|peak| PowerPeak {
+            time: resolution.to_millis(peak.time),
+            value: peak.value,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sunny_db::timeseries::TimeSeries;
+
+    #[test]
+    fn test_peaks() {
+        let minute = 60_000;
+        let used = |power_used| PowerValues {
+            power_pv: 0.0,
+            power_to_grid: 0.0,
+            power_from_grid: power_used,
+            power_used,
+        };
+        // a spike of two values at 10:00 and a smaller one at 12:00
+        let mut series = TimeSeries::<PowerValues>::new(200);
+        for i in 0..180 {
+            let power = match i {
+                60 => 3000.0,
+                62 => 2500.0,
+                120 => 2000.0,
+                _ => 200.0 + (i % 2) as f64,
+            };
+            series.insert_value_at_time(i * minute, used(power));
+        }
+        let params = |n, min_separation_ms| PeaksParams {
+            n,
+            min_separation_ms,
+        };
+
+        let found = peaks(series.view(), PowerField::Used, params(Some(2), None));
+        assert_eq!(
+            found,
+            [
+                PowerPeak {
+                    time: 60 * minute,
+                    value: 3000.0
+                },
+                PowerPeak {
+                    time: 120 * minute,
+                    value: 2000.0
+                },
+            ]
+        );
+        let close = peaks(series.view(), PowerField::Used, params(Some(2), Some(0)));
+        assert_eq!(close[1].time, 62 * minute);
+        // only the two spikes are an hour apart
+        assert_eq!(
+            peaks(series.view(), PowerField::Used, params(None, None)).len(),
+            2
+        );
+        let all = peaks(series.view(), PowerField::Used, params(None, Some(minute)));
+        assert_eq!(all.len(), DEFAULT_PEAKS);
+        assert!(peaks(series.view(), PowerField::Pv, params(None, None)).is_empty());
+    }
+}
@@ -0,0 +1,486 @@
+use anyhow::Context;
+use chrono::{Days, NaiveDate, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use sunny_db::statistics::cmp_nan_last;
+use sunny_db::timeseries::TimeSeries;
+use sunny_db::timeseries_db::SunnyDB;
+use tokio::sync::RwLock;
+
+use crate::config::{PriceSettings, PriceSource};
+use crate::summary::day_range;
+
+/// Prices hold until the next one, but for an hour at most, the longest interval of the
+/// supported feeds, so energy isn't priced with stale prices if fetching fails for a while
+const PRICE_VALID_MS: u64 = 3_600_000;
+const HOUR_MS: u64 = 3_600_000;
+/// Values of the series of prices per segment, i.e. a day of 15 minute prices
+const PRICE_SEGMENT_SIZE: usize = 96;
+
+/// The currency of the spot prices and the markups; both aWATTar and ENTSO-E publish their
+/// prices in EUR, whatever currency the frontend shows
+pub const PRICE_CURRENCY: &str = "EUR";
+
+/// The spot prices in EUR per kWh by the start of the interval they hold for, if a
+/// source is configured
+#[derive(Clone)]
+pub struct Prices {
+    db: Option<Arc<RwLock<SunnyDB<f64>>>>,
+    settings: PriceSettings,
+}
+
+impl Prices {
+    pub fn disabled() -> Self {
+        Prices {
+            db: None,
+            settings: PriceSettings::default(),
+        }
+    }
+
+    /// opens the series of prices in `<sunny-home>/prices` if a source is configured
+    pub fn open(settings: &PriceSettings, sunny_path: &str) -> anyhow::Result<Self> {
+        if settings.source == PriceSource::None {
+            return Ok(Prices::disabled());
+        }
+        let path = sunny_path.to_owned() + "prices";
+        let db = SunnyDB::<f64>::new(PRICE_SEGMENT_SIZE, &path, 2, 0)
+            .with_context(|| format!("Couldn't open the prices at {}", path))?;
+        Ok(Prices {
+            db: Some(Arc::new(RwLock::new(db))),
+            settings: settings.clone(),
+        })
+    }
+
+    /// the bytes the series of prices holds in memory
+    pub async fn memory_usage(&self) -> usize {
+        match &self.db {
+            Some(db) => db.read().await.memory_usage().total(),
+            None => 0,
+        }
+    }
+
+    pub fn settings(&self) -> &PriceSettings {
+        &self.settings
+    }
+
+    /// fetches the prices published since the newest stored one every
+    /// `fetch_interval_minutes`, up to the end of tomorrow in the given timezone
+    pub fn start_fetching(&self, timezone: Tz) {
+        let Some(db) = self.db.clone() else {
+            return;
+        };
+        let settings = self.settings.clone();
+        let interval = Duration::from_secs(settings.fetch_interval_minutes * 60);
+        tokio::spawn(async move {
+            let client = reqwest::Client::new();
+            loop {
+                if let Err(e) = fetch_new_prices(&db, &client, &settings, timezone).await {
+                    println!("Error while fetching prices: {:#}", e);
+                }
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+
+    /// the prices holding during [start_time, end_time) in ms, including the one holding at
+    /// `start_time`; empty without a source
+    pub async fn in_range(&self, start_time: u64, end_time: u64) -> Vec<(u64, f64)> {
+        let Some(db) = &self.db else {
+            return Vec::new();
+        };
+        let reader = db.read().await;
+        let Some(series) =
+            reader.get_values_in_range(start_time.saturating_sub(PRICE_VALID_MS), end_time)
+        else {
+            return Vec::new();
+        };
+        let resolution = series.get_resolution();
+        let prices: Vec<(u64, f64)> = series
+            .iter()
+            .map(|(time, price)| (resolution.to_millis(time), *price))
+            .collect();
+        // only the last of the prices starting before the range holds within it
+        let first = prices
+            .partition_point(|(time, _)| *time <= start_time)
+            .saturating_sub(1);
+        prices[first..]
+            .iter()
+            .copied()
+            .filter(|(time, _)| *time < end_time && *time + PRICE_VALID_MS > start_time)
+            .collect()
+    }
+}
+
+async fn fetch_new_prices(
+    db: &RwLock<SunnyDB<f64>>,
+    client: &reqwest::Client,
+    settings: &PriceSettings,
+    timezone: Tz,
+) -> anyhow::Result<()> {
+    let today = Utc::now().with_timezone(&timezone).date_naive();
+    let start = match db.read().await.last_persisted_time() {
+        Some(newest) => newest + 1,
+        None => day_range(today, timezone).0,
+    };
+    let end = day_range(today + Days::new(2), timezone).0;
+    if start >= end {
+        return Ok(());
+    }
+
+    let prices = match settings.source {
+        PriceSource::None => return Ok(()),
+        PriceSource::Awattar => fetch_awattar(client, settings, start, end).await?,
+        PriceSource::Entsoe => fetch_entsoe(client, settings, start, end).await?,
+    };
+    let mut series = TimeSeries::<f64>::new(prices.len());
+    for (time, price) in prices.into_iter().filter(|(time, _)| *time >= start) {
+        series.insert_value_at_time(time, price);
+    }
+    if !series.is_empty() {
+        db.write().await.import_series(&series)?;
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct AwattarResponse {
+    data: Vec<AwattarPrice>,
+}
+
+#[derive(Deserialize)]
+struct AwattarPrice {
+    start_timestamp: u64,
+    /// per MWh
+    marketprice: f64,
+}
+
+async fn fetch_awattar(
+    client: &reqwest::Client,
+    settings: &PriceSettings,
+    start: u64,
+    end: u64,
+) -> anyhow::Result<Vec<(u64, f64)>> {
+    let url = format!("{}?start={}&end={}", settings.awattar_url, start, end);
+    let response = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<AwattarResponse>()
+        .await?;
+    Ok(response
+        .data
+        .iter()
+        .map(|price| (price.start_timestamp, price.marketprice / 1000.0))
+        .collect())
+}
+
+async fn fetch_entsoe(
+    client: &reqwest::Client,
+    settings: &PriceSettings,
+    start: u64,
+    end: u64,
+) -> anyhow::Result<Vec<(u64, f64)>> {
+    let format = |time: u64| {
+        chrono::DateTime::from_timestamp_millis(time as i64)
+            .map(|t| t.format("%Y%m%d%H%M").to_string())
+            .unwrap_or_default()
+    };
+    // day-ahead prices (A44) of the bidding zone
+    let url = format!(
+        "{}?securityToken={}&documentType=A44&in_Domain={}&out_Domain={}&periodStart={}\
+         &periodEnd={}",
+        settings.entsoe_url,
+        settings.entsoe_token,
+        settings.entsoe_area,
+        settings.entsoe_area,
+        format(start),
+        format(end)
+    );
+    let document = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    parse_entsoe(&document)
+}
+
+/// the text of the first element with the given name
+fn element<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{}>", name))? + name.len() + 2;
+    let length = xml[start..].find(&format!("</{}>", name))?;
+    Some(xml[start..start + length].trim())
+}
+
+/// the prices per kWh in an ENTSO-E publication document; positions left out hold the price
+/// of the previous one. Documents without prices, e.g. before tomorrow's are published, are
+/// answered with an acknowledgement, which has none either
+fn parse_entsoe(document: &str) -> anyhow::Result<Vec<(u64, f64)>> {
+    let mut prices = Vec::new();
+    for period in document.split("<Period>").skip(1) {
+        let start = element(period, "start").context("Period without a start")?;
+        let start = NaiveDateTime::parse_from_str(start, "%Y-%m-%dT%H:%MZ")
+            .with_context(|| format!("Invalid start '{}'", start))?
+            .and_utc()
+            .timestamp_millis() as u64;
+        let resolution = match element(period, "resolution") {
+            Some("PT15M") => 15 * 60_000,
+            Some("PT30M") => 30 * 60_000,
+            Some("PT60M") => HOUR_MS,
+            resolution => anyhow::bail!("Unsupported resolution {:?}", resolution),
+        };
+        for point in period.split("<Point>").skip(1) {
+            let position: u64 = element(point, "position")
+                .context("Point without a position")?
+                .parse()?;
+            let price: f64 = element(point, "price.amount")
+                .context("Point without a price")?
+                .parse()?;
+            prices.push((
+                start + position.saturating_sub(1) * resolution,
+                price / 1000.0,
+            ));
+        }
+    }
+    prices.sort_by_key(|(time, _)| *time);
+    prices.dedup_by_key(|(time, _)| *time);
+    Ok(prices)
+}
+
+/// the time-weighted average of the prices during [start, end); None unless there's a price
+/// for all of it
+pub fn average_price(prices: &[(u64, f64)], start: u64, end: u64) -> Option<f64> {
+    let mut covered = 0;
+    let mut sum = 0.0;
+    for (i, (price_start, price)) in prices.iter().enumerate() {
+        let price_end = prices
+            .get(i + 1)
+            .map_or(u64::MAX, |(next, _)| *next)
+            .min(price_start + PRICE_VALID_MS);
+        let (from, to) = ((*price_start).max(start), price_end.min(end));
+        if from < to {
+            covered += to - from;
+            sum += price * (to - from) as f64;
+        }
+    }
+    (covered == end - start && end > start).then(|| sum / covered as f64)
+}
+
+/// the energy in kWh of the powers in W at the given times in ms, and what it's worth at the
+/// prices: the trapezoids between values are split wherever the price changes. Returns the
+/// total energy, its worth and the energy during times without a price
+pub fn priced_energy(powers: &[(u64, f64)], prices: &[(u64, f64)]) -> (f64, f64, f64) {
+    let kwh = |from: u64, to: u64, p_from: f64, p_to: f64| {
+        (p_from + p_to) * 0.5 * (to - from) as f64 / 3.6e9
+    };
+    let (mut energy, mut worth, mut priced) = (0.0, 0.0, 0.0);
+    let mut first_price = 0;
+    for pair in powers.windows(2) {
+        let ((t_0, p_0), (t_1, p_1)) = (pair[0], pair[1]);
+        if t_1 <= t_0 {
+            continue;
+        }
+        let power_at = |t: u64| p_0 + (p_1 - p_0) * (t - t_0) as f64 / (t_1 - t_0) as f64;
+        energy += kwh(t_0, t_1, p_0, p_1);
+
+        while first_price + 1 < prices.len() && prices[first_price + 1].0 <= t_0 {
+            first_price += 1;
+        }
+        for i in first_price..prices.len() {
+            let (price_start, price) = prices[i];
+            if price_start >= t_1 {
+                break;
+            }
+            let price_end = prices
+                .get(i + 1)
+                .map_or(u64::MAX, |(next, _)| *next)
+                .min(price_start + PRICE_VALID_MS);
+            let (from, to) = (price_start.max(t_0), price_end.min(t_1));
+            if from < to {
+                let part = kwh(from, to, power_at(from), power_at(to));
+                priced += part;
+                worth += part * price;
+            }
+        }
+    }
+    (energy, worth, energy - priced)
+}
+
+/// What the energy exchanged with the grid in a range cost and earned at the spot prices
+#[derive(Serialize, Debug)]
+pub struct Costs {
+    pub energy_from_grid_kwh: f64,
+    pub energy_to_grid_kwh: f64,
+    /// of the energy from the grid, including `import_markup_per_kwh`
+    pub cost: f64,
+    /// of the energy fed into the grid, including `export_markup_per_kwh`
+    pub revenue: f64,
+    pub net_cost: f64,
+    /// energy from and to the grid during times without a price, which is left out of the
+    /// cost and the revenue
+    pub unpriced_kwh: f64,
+    pub currency: &'static str,
+}
+
+/// the cost and revenue of the grid powers `(time in ms, from grid, to grid)` at the prices
+pub fn costs(powers: &[(u64, f64, f64)], prices: &[(u64, f64)], settings: &PriceSettings) -> Costs {
+    let from_grid: Vec<(u64, f64)> = powers.iter().map(|(t, from, _)| (*t, *from)).collect();
+    let to_grid: Vec<(u64, f64)> = powers.iter().map(|(t, _, to)| (*t, *to)).collect();
+    let (energy_from_grid_kwh, spot_cost, unpriced_from) = priced_energy(&from_grid, prices);
+    let (energy_to_grid_kwh, spot_revenue, unpriced_to) = priced_energy(&to_grid, prices);
+    let cost = spot_cost + (energy_from_grid_kwh - unpriced_from) * settings.import_markup_per_kwh;
+    let revenue =
+        spot_revenue + (energy_to_grid_kwh - unpriced_to) * settings.export_markup_per_kwh;
+    Costs {
+        energy_from_grid_kwh,
+        energy_to_grid_kwh,
+        cost,
+        revenue,
+        net_cost: cost - revenue,
+        unpriced_kwh: unpriced_from + unpriced_to,
+        currency: PRICE_CURRENCY,
+    }
+}
+
+/// An hour with its average spot price, see `cheapest_hours`
+#[derive(Serialize, Debug, PartialEq)]
+pub struct PricedHour {
+    pub start: u64,
+    pub end: u64,
+    pub price: f64,
+}
+
+/// the `count` hours of a local day with the lowest average spot price in order of time, or
+/// with `contiguous`, the consecutive ones that are cheapest together, e.g. to run the
+/// dishwasher; only hours with a price for all of them count
+pub fn cheapest_hours(
+    prices: &[(u64, f64)],
+    date: NaiveDate,
+    timezone: Tz,
+    count: usize,
+    contiguous: bool,
+) -> Vec<PricedHour> {
+    let (day_start, day_end) = day_range(date, timezone);
+    let mut hours: Vec<PricedHour> = (day_start..day_end)
+        .step_by(HOUR_MS as usize)
+        .filter_map(|start| {
+            let end = (start + HOUR_MS).min(day_end);
+            let price = average_price(prices, start, end)?;
+            Some(PricedHour { start, end, price })
+        })
+        .collect();
+    if count == 0 {
+        return Vec::new();
+    }
+
+    if !contiguous {
+        hours.sort_by(|a, b| cmp_nan_last(&a.price, &b.price));
+        hours.truncate(count);
+        hours.sort_by_key(|hour| hour.start);
+        return hours;
+    }
+    let cheapest_run = hours
+        .windows(count)
+        .enumerate()
+        .filter(|(_, run)| run.windows(2).all(|pair| pair[0].end == pair[1].start))
+        .map(|(i, run)| (i, run.iter().map(|hour| hour.price).sum::<f64>()))
+        .min_by(|a, b| cmp_nan_last(&a.1, &b.1));
+    match cheapest_run {
+        Some((first, _)) => hours.drain(first..first + count).collect(),
+        None => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-06-01 00:00 CEST
+    const MIDNIGHT: u64 = 1717192800000;
+
+    #[test]
+    fn test_priced_energy() {
+        let prices = [(0, 0.1), (HOUR_MS, 0.3), (2 * HOUR_MS, 0.2)];
+        // 1 kW for 4 hours, but the last price only holds for an hour
+        let powers = [(0, 1000.0), (HOUR_MS / 2, 1000.0), (4 * HOUR_MS, 1000.0)];
+        let (energy, worth, unpriced) = priced_energy(&powers, &prices);
+        assert!((energy - 4.0).abs() < 1e-9);
+        assert!((worth - 0.6).abs() < 1e-9);
+        assert!((unpriced - 1.0).abs() < 1e-9);
+
+        // a ramp from 0 to 2 kW is split where the price changes
+        let powers = [(0, 0.0), (2 * HOUR_MS, 2000.0)];
+        let (energy, worth, _) = priced_energy(&powers, &prices);
+        assert!((energy - 2.0).abs() < 1e-9);
+        assert!((worth - (0.5 * 0.1 + 1.5 * 0.3)).abs() < 1e-9);
+
+        let settings = PriceSettings {
+            import_markup_per_kwh: 0.2,
+            ..PriceSettings::default()
+        };
+        let costs = costs(
+            &[(0, 1000.0, 0.0), (HOUR_MS, 1000.0, 0.0)],
+            &prices,
+            &settings,
+        );
+        assert!((costs.cost - 0.3).abs() < 1e-9);
+        assert_eq!(costs.revenue, 0.0);
+    }
+
+    #[test]
+    fn test_cheapest_hours() {
+        let berlin: Tz = "Europe/Berlin".parse().unwrap();
+        let date = NaiveDate::from_ymd_opt(2024, 6, 1).unwrap();
+        // cheap at 3 am and from 12 to 3 pm, in quarter hours
+        let prices: Vec<(u64, f64)> = (0..24 * 4)
+            .map(|quarter| {
+                let price = match quarter / 4 {
+                    3 => 0.05,
+                    12..=14 => 0.08,
+                    _ => 0.3,
+                };
+                (MIDNIGHT + quarter * HOUR_MS / 4, price)
+            })
+            .collect();
+
+        let hours = cheapest_hours(&prices, date, berlin, 2, false);
+        assert_eq!(hours.len(), 2);
+        assert_eq!(hours[0].start, MIDNIGHT + 3 * HOUR_MS);
+        assert_eq!(hours[0].price, 0.05);
+        assert_eq!(hours[1].start, MIDNIGHT + 12 * HOUR_MS);
+
+        let run = cheapest_hours(&prices, date, berlin, 3, true);
+        let starts: Vec<u64> = run.iter().map(|hour| hour.start).collect();
+        assert_eq!(
+            starts,
+            (12..15).map(|h| MIDNIGHT + h * HOUR_MS).collect::<Vec<_>>()
+        );
+
+        // tomorrow's prices aren't known yet
+        assert!(cheapest_hours(&prices, date.succ_opt().unwrap(), berlin, 3, false).is_empty());
+    }
+
+    #[test]
+    fn test_parse_entsoe() {
+        let document = "<Publication_MarketDocument><TimeSeries><Period>\
+            <timeInterval><start>2024-05-31T22:00Z</start><end>2024-06-01T22:00Z</end></timeInterval>\
+            <resolution>PT60M</resolution>\
+            <Point><position>1</position><price.amount>95.5</price.amount></Point>\
+            <Point><position>3</position><price.amount>-10</price.amount></Point>\
+            </Period></TimeSeries></Publication_MarketDocument>";
+        let prices = parse_entsoe(document).unwrap();
+        assert_eq!(
+            prices,
+            vec![(MIDNIGHT, 0.0955), (MIDNIGHT + 2 * HOUR_MS, -0.01)]
+        );
+
+        let acknowledgement = "<Acknowledgement_MarketDocument><Reason><code>999</code>\
+            <text>No matching data found</text></Reason></Acknowledgement_MarketDocument>";
+        assert_eq!(parse_entsoe(acknowledgement).unwrap(), vec![]);
+        assert!(parse_entsoe("<Period><start>yesterday</start></Period>").is_err());
+    }
+}
@@ -0,0 +1,244 @@
+use chrono::{Datelike, NaiveDate, TimeZone, Timelike, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use sunny_db::fields::FloatFields;
+use sunny_db::statistics::time_weights;
+use sunny_db::timeseries::TimeSeriesView;
+
+use crate::PowerValues;
+
+/// Length of the time-of-day slots in minutes unless asked for something else
+pub const DEFAULT_SLOT_MINUTES: u32 = 60;
+const DAY_MINUTES: u32 = 24 * 60;
+
+/// Query parameters of `GET /profile/daily`, e.g. `?start=..&end=..&slot_minutes=15`
+#[derive(Deserialize)]
+pub struct DailyProfileParams {
+    pub start: u64,
+    pub end: u64,
+    pub slot_minutes: Option<u32>,
+    /// whether to add separate profiles of the weekdays and the weekends
+    #[serde(default)]
+    pub split_weekends: bool,
+}
+
+/// The time-weighted mean power during a slot of the day
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ProfileSlot {
+    /// local minutes after midnight the slot starts at
+    pub minute: u32,
+    /// in W; null without values, and fields without values are null
+    pub mean: Option<PowerValues>,
+    pub values: usize,
+}
+
+/// The average day of a number of local days
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Profile {
+    /// number of local days with values
+    pub days: usize,
+    pub slots: Vec<ProfileSlot>,
+}
+
+/// Response of `GET /profile/daily`
+#[derive(Serialize, Debug)]
+pub struct DailyProfile {
+    pub slot_minutes: u32,
+    pub all: Profile,
+    /// only with `split_weekends`
+    pub weekdays: Option<Profile>,
+    pub weekends: Option<Profile>,
+}
+
+#[derive(Clone)]
+struct SlotSums {
+    /// of each field times the time it stood for
+    sums: Vec<f64>,
+    /// the time each field stood for
+    weights: Vec<f64>,
+    values: usize,
+}
+
+impl SlotSums {
+    fn new() -> Self {
+        SlotSums {
+            sums: vec![0.0; PowerValues::COUNT],
+            weights: vec![0.0; PowerValues::COUNT],
+            values: 0,
+        }
+    }
+}
+
+/// sums up the values of each slot weighted by time, counting NaN fields as missing
+struct ProfileSums {
+    slots: Vec<SlotSums>,
+    days: Vec<NaiveDate>,
+}
+
+impl ProfileSums {
+    fn new(slots: usize) -> Self {
+        Self {
+            slots: vec![SlotSums::new(); slots],
+            days: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, slot: usize, date: NaiveDate, value: &PowerValues, weight: f64) {
+        let sums = &mut self.slots[slot];
+        for i in 0..PowerValues::COUNT {
+            let field = value.field(i);
+            if !field.is_nan() {
+                sums.sums[i] += field * weight;
+                sums.weights[i] += weight;
+            }
+        }
+        sums.values += 1;
+        // the values come in order
+        if self.days.last() != Some(&date) {
+            self.days.push(date);
+        }
+    }
+
+    fn profile(self, slot_minutes: u32) -> Profile {
+        let slots = self
+            .slots
+            .iter()
+            .zip((0..).step_by(slot_minutes as usize))
+            .map(|(sums, minute)| {
+                let means: Vec<f64> = sums
+                    .sums
+                    .iter()
+                    .zip(&sums.weights)
+                    .map(|(sum, w)| if *w > 0.0 { sum / w } else { f64::NAN })
+                    .collect();
+                ProfileSlot {
+                    minute,
+                    mean: (sums.values > 0).then(|| PowerValues::from_fields(&means)),
+                    values: sums.values,
+                }
+            })
+            .collect();
+        Profile {
+            days: self.days.len(),
+            slots,
+        }
+    }
+}
+
+/// the typical day of the values, i.e. the mean of each field per local time-of-day slot of
+/// `slot_minutes`, optionally split into weekdays and weekends. Each value counts for the time
+/// it stood for, see `time_weights`
+pub fn daily_profile(
+    series: TimeSeriesView<'_, PowerValues>,
+    timezone: Tz,
+    slot_minutes: u32,
+    split_weekends: bool,
+) -> anyhow::Result<DailyProfile> {
+    if slot_minutes == 0 || !DAY_MINUTES.is_multiple_of(slot_minutes) {
+        anyhow::bail!(
+            "The slots have to split the day evenly, {} minutes don't",
+            slot_minutes
+        );
+    }
+    let slots = (DAY_MINUTES / slot_minutes) as usize;
+    let mut all = ProfileSums::new(slots);
+    let mut weekdays = ProfileSums::new(slots);
+    let mut weekends = ProfileSums::new(slots);
+    let resolution = series.get_resolution();
+    let times: Vec<u64> = series.iter().map(|(time, _)| time).collect();
+    for ((time, value), weight) in series.iter().zip(time_weights(&times)) {
+        let Some(local) = Utc
+            .timestamp_millis_opt(resolution.to_millis(time) as i64)
+            .single()
+            .map(|t| t.with_timezone(&timezone))
+        else {
+            continue;
+        };
+        let slot = ((local.hour() * 60 + local.minute()) / slot_minutes) as usize;
+        let date = local.date_naive();
+        all.add(slot, date, value, weight);
+        if split_weekends {
+            match local.weekday() {
+                Weekday::Sat | Weekday::Sun => weekends.add(slot, date, value, weight),
+                _ => weekdays.add(slot, date, value, weight),
+            }
+        }
+    }
+    Ok(DailyProfile {
+        slot_minutes,
+        all: all.profile(slot_minutes),
+        weekdays: split_weekends.then(|| weekdays.profile(slot_minutes)),
+        weekends: split_weekends.then(|| weekends.profile(slot_minutes)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sunny_db::timeseries::TimeSeries;
+
+    // 2024-05-31 00:00 CEST, a Friday
+    const MIDNIGHT: u64 = 1717106400000;
+    const HOUR_MS: u64 = 3_600_000;
+
+    #[test]
+    fn test_daily_profile() {
+        let berlin: Tz = "Europe/Berlin".parse().unwrap();
+        let used = |power_used| PowerValues {
+            power_pv: 0.0,
+            power_to_grid: 0.0,
+            power_from_grid: power_used,
+            power_used,
+        };
+        // every 30 minutes on Friday and Saturday; 200 W at night and 1 kW from 18:00 to 19:00,
+        // on Saturday 2 kW instead
+        let mut series = TimeSeries::<PowerValues>::new(100);
+        for i in 0..2 * 48 {
+            let h = (i / 2) % 24;
+            let power = match (h, i < 48) {
+                (18, true) => 1000.0,
+                (18, false) => 2000.0,
+                _ => 200.0,
+            };
+            series.insert_value_at_time(MIDNIGHT + i * HOUR_MS / 2, used(power));
+        }
+        let mut missing = used(f64::NAN);
+        missing.power_pv = 0.0;
+        series.insert_value_at_time(MIDNIGHT + 48 * HOUR_MS - 60_000, missing);
+
+        let profile = daily_profile(series.view(), berlin, 60, false).unwrap();
+        assert_eq!(profile.all.days, 2);
+        assert_eq!(profile.all.slots.len(), 24);
+        assert_eq!(profile.all.slots[18].minute, 18 * 60);
+        assert_eq!(profile.all.slots[18].mean.unwrap().power_used, 1500.0);
+        assert_eq!(profile.all.slots[3].mean.unwrap().power_used, 200.0);
+        // the NaN fields count as missing, the others not
+        assert_eq!(profile.all.slots[23].values, 5);
+        assert_eq!(profile.all.slots[23].mean.unwrap().power_used, 200.0);
+        assert!(profile.weekdays.is_none());
+
+        let profile = daily_profile(series.view(), berlin, 30, true).unwrap();
+        assert_eq!(profile.all.slots.len(), 48);
+        let weekdays = profile.weekdays.unwrap();
+        let weekends = profile.weekends.unwrap();
+        assert_eq!((weekdays.days, weekends.days), (1, 1));
+        assert_eq!(weekdays.slots[37].mean.unwrap().power_used, 1000.0);
+        assert_eq!(weekends.slots[37].mean.unwrap().power_used, 2000.0);
+        assert_eq!(weekends.slots[37].minute, 18 * 60 + 30);
+
+        assert!(daily_profile(series.view(), berlin, 7, false).is_err());
+        assert!(daily_profile(series.view(), berlin, 0, false).is_err());
+        let empty = daily_profile(TimeSeries::empty().view(), berlin, 60, true).unwrap();
+        assert_eq!(empty.all.days, 0);
+        assert_eq!(empty.all.slots[0].mean, None);
+
+        // 600 W from 00:01 to 00:30 counts for more than the 0 W at 00:00 and 00:59
+        let mut irregular = TimeSeries::<PowerValues>::new(3);
+        for (minute, power) in [(0, 0.0), (1, 600.0), (59, 0.0)] {
+            irregular.insert_value_at_time(MIDNIGHT + minute * 60_000, used(power));
+        }
+        let profile = daily_profile(irregular.view(), berlin, 60, false).unwrap();
+        assert_eq!(profile.all.slots[0].values, 3);
+        assert_eq!(profile.all.slots[0].mean.unwrap().power_used, 300.0);
+    }
+}
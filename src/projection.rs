@@ -0,0 +1,217 @@
+use chrono::{Days, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::Serialize;
+use sunny_db::timeseries::TimeSeries;
+use sunny_db::timeseries_db::SunnyDB;
+
+use crate::summary::day_range;
+use crate::{compute_statistics, PowerValues};
+
+/// Number of past days making up the average daily production profile
+pub const HISTORY_DAYS: u64 = 14;
+/// Below this share of the typical daily production, scaling up what has been produced so far
+/// is too unreliable, e.g. early in the morning
+const MIN_FRACTION: f64 = 0.1;
+
+/// Projection of today's total PV production, based on the production so far and the share of
+/// the daily production the past days typically had reached by the same time of day
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TodayProjection {
+    pub date: NaiveDate,
+    /// time of the projection in ms
+    pub time: u64,
+    /// PV energy produced today until `time` in kWh; null without values
+    pub produced_kwh: Option<f64>,
+    /// share of the daily production typically produced by this time of day
+    pub typical_fraction: Option<f64>,
+    /// projected PV production of the whole day in kWh; null without history
+    pub projected_kwh: Option<f64>,
+    /// number of past days with values the projection is based on
+    pub history_days: usize,
+}
+
+/// Statistics of today's values so far, maintained while inserting them so they're available
+/// without reading the day's segments
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TodaySoFar {
+    /// start of the local day in ms
+    pub since: u64,
+    pub values: u64,
+    /// time of the latest value in ms
+    pub last_time: Option<u64>,
+    pub last: Option<PowerValues>,
+    pub mean: Option<PowerValues>,
+    pub min: Option<PowerValues>,
+    pub max: Option<PowerValues>,
+    pub energy_kwh: Option<PowerValues>,
+}
+
+/// the start of the local day containing `now`, both in ms
+pub fn start_of_day(now: u64, timezone: Tz) -> u64 {
+    let date = Utc
+        .timestamp_millis_opt(now as i64)
+        .unwrap()
+        .with_timezone(&timezone)
+        .date_naive();
+    day_range(date, timezone).0
+}
+
+/// today's statistics maintained by the database; None if it doesn't maintain them
+pub fn today_so_far(db: &SunnyDB<PowerValues>) -> Option<TodaySoFar> {
+    let statistics = db.running_statistics()?;
+    let resolution = db.get_resolution();
+    Some(TodaySoFar {
+        since: resolution.to_millis(statistics.since),
+        values: statistics.count,
+        last_time: statistics.last.map(|(time, _)| resolution.to_millis(time)),
+        last: statistics.last.map(|(_, value)| value),
+        mean: statistics.mean(),
+        min: statistics.min(),
+        max: statistics.max(),
+        energy_kwh: statistics.integral().map(|wh| wh * 0.001),
+    })
+}
+
+/// PV energy in kWh of the values in (start, end], given in ms
+fn pv_energy(series: &TimeSeries<PowerValues>, start: u64, end: u64) -> Option<f64> {
+    let resolution = series.get_resolution();
+    let view = series.view_range(resolution.from_millis(start), resolution.from_millis(end))?;
+    compute_statistics(view).energy_kwh.map(|e| e.power_pv)
+}
+
+/// projects the day's total from the energy produced so far and the (energy produced by the
+/// same time, total energy) of past days; returns the typical fraction and the projection
+fn project(produced: f64, history: &[(f64, f64)]) -> (Option<f64>, Option<f64>) {
+    if history.is_empty() {
+        return (None, None);
+    }
+    let days = history.len() as f64;
+    let until_now = history.iter().map(|(c, _)| c).sum::<f64>() / days;
+    let total = history.iter().map(|(_, t)| t).sum::<f64>() / days;
+    if total <= 0.0 {
+        return (None, Some(produced));
+    }
+    let fraction = (until_now / total).min(1.0);
+    let projected = if fraction >= MIN_FRACTION {
+        produced / fraction
+    } else {
+        // too early to scale; expect the rest of the day to go as usual
+        produced + total - until_now
+    };
+    (Some(fraction), Some(projected.max(produced)))
+}
+
+/// projects the PV production of the local day containing `now` (in ms)
+pub fn project_today(db: &SunnyDB<PowerValues>, now: u64, timezone: Tz) -> TodayProjection {
+    let local_now = Utc
+        .timestamp_millis_opt(now as i64)
+        .unwrap()
+        .with_timezone(&timezone);
+    let date = local_now.date_naive();
+    let (today_start, _) = day_range(date, timezone);
+    let produced = db
+        .get_values_in_range(today_start, now)
+        .and_then(|s| pv_energy(&s, today_start, now));
+
+    let mut history = Vec::new();
+    for days_ago in 1..=HISTORY_DAYS {
+        let Some(day) = date.checked_sub_days(Days::new(days_ago)) else {
+            break;
+        };
+        let (start, end) = day_range(day, timezone);
+        // the same time of day, or as long after the start of the day if it doesn't exist
+        let same_time = timezone
+            .from_local_datetime(&day.and_time(local_now.time()))
+            .earliest()
+            .map(|t| t.timestamp_millis().max(0) as u64)
+            .unwrap_or(start + (now - today_start))
+            .min(end - 1);
+        let Some(series) = db.get_values_in_range(start, end - 1) else {
+            continue;
+        };
+        if let Some(total) = pv_energy(&series, start, end - 1) {
+            let until_now = pv_energy(&series, start, same_time).unwrap_or(0.0);
+            history.push((until_now, total));
+        }
+    }
+
+    let (typical_fraction, projected_kwh) = match produced {
+        Some(produced) => project(produced, &history),
+        None => project(0.0, &history),
+    };
+    TodayProjection {
+        date,
+        time: now,
+        produced_kwh: produced,
+        typical_fraction,
+        projected_kwh,
+        history_days: history.len(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project() {
+        assert_eq!(project(1.0, &[]), (None, None));
+        // half of the production is usually done by now
+        let (fraction, projected) = project(3.0, &[(4.0, 8.0), (6.0, 12.0)]);
+        assert_eq!(fraction, Some(0.5));
+        assert_eq!(projected, Some(6.0));
+        // early in the morning, the rest of a typical day is added
+        let (fraction, projected) = project(0.5, &[(0.2, 10.0)]);
+        assert!((fraction.unwrap() - 0.02).abs() < 1e-12);
+        assert!((projected.unwrap() - 10.3).abs() < 1e-12);
+        // never less than what has been produced already
+        assert_eq!(project(2.0, &[(0.0, 0.0)]), (None, Some(2.0)));
+    }
+
+    #[test]
+    fn test_project_today() {
+        let path = std::env::temp_dir().join(format!("sunny-projection-{}", std::process::id()));
+        std::fs::remove_dir_all(&path).ok();
+        let mut db = SunnyDB::<PowerValues>::new(100, path.to_str().unwrap(), 2, 0).unwrap();
+        let berlin: Tz = "Europe/Berlin".parse().unwrap();
+        let pv = |power_pv| PowerValues {
+            power_pv,
+            power_used: 0.0,
+            power_to_grid: 0.0,
+            power_from_grid: 0.0,
+        };
+        // 2024-06-01 00:00 CEST; 1 kW from 06:00 to 18:00 on the two days before
+        let day_start = 1717192800000;
+        let hour = 3_600_000;
+        for h in 0..=48 {
+            let power = if (6..18).contains(&(h % 24)) {
+                1000.0
+            } else {
+                0.0
+            };
+            db.insert_value_at_time(day_start + h * hour, pv(power));
+        }
+        // 2 kW until noon today
+        let today = day_start + 2 * 24 * hour;
+        for h in 1..=12 {
+            let power = if h > 6 { 2000.0 } else { 0.0 };
+            db.insert_value_at_time(today + h * hour, pv(power));
+        }
+
+        let projection = project_today(&db, today + 12 * hour, berlin);
+        assert_eq!(
+            projection.date,
+            NaiveDate::from_ymd_opt(2024, 6, 3).unwrap()
+        );
+        assert_eq!(projection.history_days, 2);
+        let fraction = projection.typical_fraction.unwrap();
+        let produced = projection.produced_kwh.unwrap();
+        let projected = projection.projected_kwh.unwrap();
+        assert!(produced > 10.0 && produced < 12.0, "{}", produced);
+        assert!(fraction > 0.4 && fraction < 0.6, "{}", fraction);
+        assert!((projected - produced / fraction).abs() < 1e-9);
+
+        drop(db);
+        std::fs::remove_dir_all(&path).ok();
+    }
+}
@@ -0,0 +1,274 @@
+use anyhow::{self, Context};
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
+use sunny_db::rollup::{rollup, Aggregation};
+use sunny_db::timeseries::TimeSeries;
+use tokio::signal;
+
+use crate::config::{Config, RollupRule};
+use crate::PowerValues;
+
+type Field = fn(&PowerValues) -> f64;
+
+/// The fields of the power values that can be used in rollup expressions
+const FIELDS: [(&str, Field); 4] = [
+    ("power_pv", |v| v.power_pv),
+    ("power_to_grid", |v| v.power_to_grid),
+    ("power_from_grid", |v| v.power_from_grid),
+    ("power_used", |v| v.power_used),
+];
+
+/// A term of a source expression, e.g. `-0.5 * power_used`
+#[derive(Clone, Copy)]
+struct Term {
+    factor: f64,
+    field: Field,
+}
+
+/// A parsed rollup rule
+#[derive(Clone)]
+pub struct Rollup {
+    pub rule: RollupRule,
+    terms: Vec<Term>,
+    interval_ms: u64,
+    aggregation: Aggregation,
+}
+
+impl Rollup {
+    pub fn parse(rule: &RollupRule) -> anyhow::Result<Rollup> {
+        let invalid = || {
+            format!(
+                "Rollup '{}' isn't of the form <aggregation>_over_time(<expression>[<interval>]): '{}'",
+                rule.record, rule.expr
+            )
+        };
+        let expr = rule.expr.trim();
+        let (function, argument) = expr.split_once('(').with_context(invalid)?;
+        let argument = argument.strip_suffix(')').with_context(invalid)?;
+        let (source, interval) = argument.rsplit_once('[').with_context(invalid)?;
+        let interval = interval.trim().strip_suffix(']').with_context(invalid)?;
+
+        let aggregation = match function.trim() {
+            "avg_over_time" => Aggregation::Average,
+            "min_over_time" => Aggregation::Min,
+            "max_over_time" => Aggregation::Max,
+            "sum_over_time" => Aggregation::Sum,
+            "count_over_time" => Aggregation::Count,
+            "last_over_time" => Aggregation::Last,
+            other => anyhow::bail!(
+                "Unknown aggregation '{}' in rollup '{}'",
+                other,
+                rule.record
+            ),
+        };
+
+        Ok(Rollup {
+            rule: rule.clone(),
+            terms: parse_expression(source)
+                .with_context(|| format!("Invalid expression in rollup '{}'", rule.record))?,
+            interval_ms: parse_interval(interval)
+                .with_context(|| format!("Invalid interval in rollup '{}'", rule.record))?,
+            aggregation,
+        })
+    }
+
+    /// the interval of the rollup in the timestamp unit of the series
+    pub fn interval(&self, series: &TimeSeries<PowerValues>) -> u64 {
+        series.get_resolution().from_millis(self.interval_ms).max(1)
+    }
+
+    pub fn evaluate(&self, values: &PowerValues) -> f64 {
+        self.terms
+            .iter()
+            .map(|term| term.factor * (term.field)(values))
+            .sum()
+    }
+
+    pub fn compute(&self, series: &TimeSeries<PowerValues>) -> Vec<(u64, f64)> {
+        rollup(series, self.interval(series), self.aggregation, |v| {
+            self.evaluate(v)
+        })
+    }
+}
+
+/// parses sums of optionally scaled fields, e.g. `power_pv - 0.5 * power_used`
+fn parse_expression(expression: &str) -> anyhow::Result<Vec<Term>> {
+    let mut terms = Vec::new();
+    for (sign, term) in split_terms(expression) {
+        if term.is_empty() {
+            continue;
+        }
+        let (factor, field) = match term.split_once('*') {
+            Some((factor, field)) => (
+                factor
+                    .trim()
+                    .parse::<f64>()
+                    .with_context(|| format!("'{}' isn't a number", factor.trim()))?,
+                field.trim(),
+            ),
+            None => (1.0, term),
+        };
+        let field = FIELDS
+            .iter()
+            .find(|(name, _)| *name == field)
+            .map(|(_, f)| *f)
+            .with_context(|| {
+                let names: Vec<&str> = FIELDS.iter().map(|(name, _)| *name).collect();
+                format!(
+                    "Unknown field '{}', expected one of {}",
+                    field,
+                    names.join(", ")
+                )
+            })?;
+        terms.push(Term {
+            factor: sign * factor,
+            field,
+        });
+    }
+    if terms.is_empty() {
+        anyhow::bail!("The expression is empty");
+    }
+    Ok(terms)
+}
+
+/// splits the expression at its `+` and `-` operators into the terms and their signs; the
+/// sign of an exponent like in `1e-3` belongs to the number
+fn split_terms(expression: &str) -> Vec<(f64, &str)> {
+    let mut terms = Vec::new();
+    let mut sign = 1.0;
+    let mut start = 0;
+    for (i, c) in expression.char_indices() {
+        if c != '+' && c != '-' {
+            continue;
+        }
+        let term = expression[start..i].trim();
+        let is_mantissa = term.strip_suffix(['e', 'E']).is_some_and(|m| {
+            m.starts_with(|c: char| c.is_ascii_digit()) && m.parse::<f64>().is_ok()
+        });
+        if is_mantissa {
+            continue;
+        }
+        terms.push((sign, term));
+        sign = if c == '-' { -1.0 } else { 1.0 };
+        start = i + c.len_utf8();
+    }
+    terms.push((sign, expression[start..].trim()));
+    terms
+}
+
+/// parses intervals like `30s`, `15m`, `1h` or `1d` to ms
+fn parse_interval(interval: &str) -> anyhow::Result<u64> {
+    let unit_start = interval
+        .find(|c: char| !c.is_ascii_digit())
+        .with_context(|| format!("Interval '{}' has no unit", interval))?;
+    let (amount, unit) = interval.split_at(unit_start);
+    let amount: u64 = amount
+        .parse()
+        .with_context(|| format!("Interval '{}' doesn't start with a number", interval))?;
+    let unit_ms = match unit {
+        "s" => 1000,
+        "m" => 60 * 1000,
+        "h" => 3600 * 1000,
+        "d" => 24 * 3600 * 1000,
+        _ => anyhow::bail!("Unknown unit '{}' in interval '{}'", unit, interval),
+    };
+    if amount == 0 {
+        anyhow::bail!("Interval '{}' is empty", interval);
+    }
+    Ok(amount * unit_ms)
+}
+
+pub fn parse_rules(rules: &[RollupRule]) -> anyhow::Result<Vec<Rollup>> {
+    let mut names = HashSet::new();
+    for rule in rules {
+        if !names.insert(&rule.record) {
+            anyhow::bail!("Rollup '{}' is defined more than once", rule.record);
+        }
+    }
+    rules.iter().map(Rollup::parse).collect()
+}
+
+/// The currently defined rollups; they're replaced when the config is reloaded
+pub type Rollups = Arc<RwLock<Vec<Rollup>>>;
+
+pub fn find(rollups: &Rollups, name: &str) -> Option<Rollup> {
+    rollups
+        .read()
+        .unwrap()
+        .iter()
+        .find(|r| r.rule.record == name)
+        .cloned()
+}
+
+/// re-reads the rollups from the config file; on errors the current ones are kept
+pub fn reload(rollups: &Rollups, config_path: Option<&str>) -> anyhow::Result<usize> {
+    let config = Config::load(config_path)?;
+    let reloaded = parse_rules(&config.rollups)?;
+    let count = reloaded.len();
+    *rollups.write().unwrap() = reloaded;
+    Ok(count)
+}
+
+/// reloads the rollups whenever the process receives SIGHUP
+pub async fn reload_on_sighup(rollups: Rollups, config_path: Option<String>) {
+    let mut hangup = signal::unix::signal(signal::unix::SignalKind::hangup())
+        .expect("failed to install signal handler");
+    while hangup.recv().await.is_some() {
+        match reload(&rollups, config_path.as_deref()) {
+            Ok(count) => println!("Reloaded {} rollup definitions", count),
+            Err(e) => println!(
+                "Error while reloading rollups, keeping the old ones: {:#}",
+                e
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(expr: &str) -> RollupRule {
+        RollupRule {
+            record: String::from("test"),
+            expr: String::from(expr),
+        }
+    }
+
+    #[test]
+    fn test_parse_rollup() {
+        let rollup = Rollup::parse(&rule(
+            "avg_over_time(power_pv - 0.5 * power_used + power_from_grid [15m])",
+        ))
+        .unwrap();
+        assert_eq!(rollup.aggregation, Aggregation::Average);
+        assert_eq!(rollup.interval_ms, 900000);
+        let values = PowerValues {
+            power_pv: 1000.0,
+            power_to_grid: 0.0,
+            power_from_grid: 100.0,
+            power_used: 400.0,
+        };
+        assert_eq!(rollup.evaluate(&values), 900.0);
+
+        let scaled = Rollup::parse(&rule(
+            "sum_over_time(1e-3 * power_pv - 2.5E+2 * power_used + -power_from_grid [1h])",
+        ))
+        .unwrap();
+        assert_eq!(scaled.evaluate(&values), 1.0 - 100000.0 - 100.0);
+
+        assert!(Rollup::parse(&rule("max_over_time(power_pv[1d])")).is_ok());
+        assert!(Rollup::parse(&rule("power_pv[1h]")).is_err());
+        assert!(Rollup::parse(&rule("median_over_time(power_pv[1h])")).is_err());
+        assert!(Rollup::parse(&rule("avg_over_time(power_wind[1h])")).is_err());
+        assert!(Rollup::parse(&rule("avg_over_time(power_pv[1w])")).is_err());
+        assert!(Rollup::parse(&rule("avg_over_time(power_pv[0h])")).is_err());
+        assert!(Rollup::parse(&rule("avg_over_time([1h])")).is_err());
+
+        let duplicates = [
+            rule("avg_over_time(power_pv[1h])"),
+            rule("sum_over_time(power_pv[1h])"),
+        ];
+        assert!(parse_rules(&duplicates).is_err());
+    }
+}
@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use crate::config::SamplingSettings;
+use crate::PowerValues;
+
+/// The interval between samples in adaptive mode: it drops to the minimum as soon as any of
+/// the values changes by the threshold, e.g. when clouds pass or appliances switch, and
+/// doubles with every stable sample up to the maximum, so quiet periods take little space
+pub struct AdaptiveInterval {
+    min: Duration,
+    max: Duration,
+    threshold_watts: f64,
+    current: Duration,
+    previous: Option<PowerValues>,
+}
+
+impl AdaptiveInterval {
+    pub fn new(settings: &SamplingSettings) -> Self {
+        AdaptiveInterval {
+            min: Duration::from_millis(settings.min_interval_ms),
+            max: Duration::from_millis(settings.max_interval_ms),
+            threshold_watts: settings.threshold_watts,
+            current: Duration::from_millis(settings.min_interval_ms),
+            previous: None,
+        }
+    }
+
+    /// the time to wait until the next sample after this one; failed fetches (None) are
+    /// retried at the current pace
+    pub fn next(&mut self, sample: Option<&PowerValues>) -> Duration {
+        let Some(sample) = sample else {
+            return self.current;
+        };
+        let active = self.previous.is_none_or(|previous| {
+            let change = *sample - previous;
+            [
+                change.power_pv,
+                change.power_to_grid,
+                change.power_from_grid,
+                change.power_used,
+            ]
+            .iter()
+            .any(|c| c.abs() >= self.threshold_watts)
+        });
+        self.previous = Some(*sample);
+        self.current = match active {
+            true => self.min,
+            false => (self.current * 2).min(self.max),
+        };
+        self.current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(power_pv: f64) -> PowerValues {
+        PowerValues {
+            power_pv,
+            power_to_grid: 0.0,
+            power_from_grid: 200.0,
+            power_used: 200.0 + power_pv,
+        }
+    }
+
+    #[test]
+    fn test_adaptive_interval() {
+        let settings = SamplingSettings {
+            adaptive: true,
+            min_interval_ms: 1000,
+            max_interval_ms: 5000,
+            threshold_watts: 50.0,
+        };
+        let mut interval = AdaptiveInterval::new(&settings);
+        let seconds = |d: Duration| d.as_secs_f64();
+
+        assert_eq!(seconds(interval.next(Some(&sample(1000.0)))), 1.0);
+        // stable values slow down sampling up to the maximum
+        assert_eq!(seconds(interval.next(Some(&sample(1010.0)))), 2.0);
+        assert_eq!(seconds(interval.next(Some(&sample(990.0)))), 4.0);
+        assert_eq!(seconds(interval.next(None)), 4.0);
+        assert_eq!(seconds(interval.next(Some(&sample(1000.0)))), 5.0);
+        assert_eq!(seconds(interval.next(Some(&sample(1000.0)))), 5.0);
+        // a passing cloud speeds it up right away
+        assert_eq!(seconds(interval.next(Some(&sample(400.0)))), 1.0);
+        assert_eq!(seconds(interval.next(Some(&sample(420.0)))), 2.0);
+    }
+}
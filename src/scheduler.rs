@@ -0,0 +1,220 @@
+use anyhow::{self, Context};
+use chrono::{DateTime, Duration, LocalResult, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+type Task = Arc<dyn Fn() -> Pin<Box<dyn Future<Output = ()> + Send>> + Send + Sync>;
+
+/// A window of local time during which no jobs are run; it may wrap around midnight
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QuietTime {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl QuietTime {
+    /// parses windows of the form "HH:MM-HH:MM"
+    pub fn parse(window: &str) -> anyhow::Result<QuietTime> {
+        let (start, end) = window
+            .split_once('-')
+            .with_context(|| format!("Quiet time '{}' isn't of the form HH:MM-HH:MM", window))?;
+        Ok(QuietTime {
+            start: parse_time(start.trim())?,
+            end: parse_time(end.trim())?,
+        })
+    }
+
+    fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
+}
+
+pub fn parse_time(time: &str) -> anyhow::Result<NaiveTime> {
+    NaiveTime::parse_from_str(time, "%H:%M")
+        .with_context(|| format!("'{}' isn't a valid time of the form HH:MM", time))
+}
+
+pub fn parse_timezone(timezone: &str) -> anyhow::Result<Tz> {
+    timezone
+        .parse::<Tz>()
+        .map_err(|e| anyhow::anyhow!("Unknown timezone '{}': {}", timezone, e))
+}
+
+struct Job {
+    name: &'static str,
+    at: NaiveTime,
+    task: Task,
+}
+
+/// Central place for all jobs that need to run at a certain local wall-clock time every day,
+/// e.g. at the start of an energy day (local midnight); jobs due during one of the quiet times
+/// are postponed until the quiet time is over
+pub struct Scheduler {
+    timezone: Tz,
+    quiet_times: Vec<QuietTime>,
+    jobs: Vec<Job>,
+}
+
+impl Scheduler {
+    pub fn new(timezone: Tz, quiet_times: Vec<QuietTime>) -> Self {
+        Scheduler {
+            timezone,
+            quiet_times,
+            jobs: Vec::new(),
+        }
+    }
+
+    /// registers a job that runs every day at the given local time
+    pub fn daily<F, Fut>(&mut self, name: &'static str, at: NaiveTime, task: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.jobs.push(Job {
+            name,
+            at,
+            task: Arc::new(move || Box::pin(task())),
+        });
+    }
+
+    /// spawns a tokio task per job that sleeps until the job is due
+    pub fn start(self) {
+        let scheduler = Arc::new(self);
+        for i in 0..scheduler.jobs.len() {
+            let scheduler = Arc::clone(&scheduler);
+            tokio::spawn(async move {
+                let job = &scheduler.jobs[i];
+                loop {
+                    let now = Utc::now();
+                    let next = scheduler.next_run(job.at, now);
+                    let wait = (next - now).to_std().unwrap_or_default();
+                    println!("Scheduled job '{}' to run at {}", job.name, next);
+                    tokio::time::sleep(wait).await;
+                    (job.task)().await;
+                }
+            });
+        }
+    }
+
+    /// the next point in time strictly after `now` at which a job scheduled for the local
+    /// time `at` should run
+    pub fn next_run(&self, at: NaiveTime, now: DateTime<Utc>) -> DateTime<Utc> {
+        let today = now.with_timezone(&self.timezone).date_naive();
+        let mut day = today - Duration::days(1);
+        loop {
+            let run = self.postpone_during_quiet_times(self.local_time(day, at));
+            if run > now {
+                return run;
+            }
+            day = day
+                .succ_opt()
+                .expect("Ran out of dates while scheduling a job!");
+        }
+    }
+
+    /// converts a local date and time to UTC; times skipped by a DST change are moved to
+    /// right after the change and ambiguous times run at their first occurrence
+    fn local_time(&self, day: NaiveDate, at: NaiveTime) -> DateTime<Utc> {
+        let mut local = day.and_time(at);
+        loop {
+            match self.timezone.from_local_datetime(&local) {
+                LocalResult::Single(t) => return t.with_timezone(&Utc),
+                LocalResult::Ambiguous(t, _) => return t.with_timezone(&Utc),
+                LocalResult::None => local += Duration::minutes(1),
+            }
+        }
+    }
+
+    fn postpone_during_quiet_times(&self, mut run: DateTime<Utc>) -> DateTime<Utc> {
+        // quiet times may overlap each other, so keep going until we're outside of all of them;
+        // if they cover the whole day, the job runs at the end of the last one we hit
+        for _ in 0..self.quiet_times.len() {
+            let Some(quiet) = self
+                .quiet_times
+                .iter()
+                .find(|q| q.contains(run.with_timezone(&self.timezone).time()))
+            else {
+                break;
+            };
+
+            let local = run.with_timezone(&self.timezone);
+            let mut end_day = local.date_naive();
+            if quiet.end <= local.time() {
+                end_day = end_day
+                    .succ_opt()
+                    .expect("Ran out of dates while scheduling a job!");
+            }
+            run = self.local_time(end_day, quiet.end);
+        }
+        run
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_next_run() {
+        let berlin = parse_timezone("Europe/Berlin").unwrap();
+        let scheduler = Scheduler::new(berlin, Vec::new());
+        let midnight = parse_time("00:00").unwrap();
+
+        // local midnight in summer is 22:00 UTC
+        assert_eq!(
+            scheduler.next_run(midnight, utc("2024-06-01T12:00:00Z")),
+            utc("2024-06-01T22:00:00Z")
+        );
+        // ... and 23:00 UTC in winter
+        assert_eq!(
+            scheduler.next_run(midnight, utc("2024-12-01T23:30:00Z")),
+            utc("2024-12-02T23:00:00Z")
+        );
+        // the job doesn't run twice at the same time
+        assert_eq!(
+            scheduler.next_run(midnight, utc("2024-06-01T22:00:00Z")),
+            utc("2024-06-02T22:00:00Z")
+        );
+
+        // 02:30 doesn't exist on the day clocks are moved forward
+        let half_past_two = parse_time("02:30").unwrap();
+        assert_eq!(
+            scheduler.next_run(half_past_two, utc("2024-03-30T12:00:00Z")),
+            utc("2024-03-31T01:00:00Z")
+        );
+    }
+
+    #[test]
+    fn test_quiet_times() {
+        let quiet_times = vec![
+            QuietTime::parse("23:30-01:00").unwrap(),
+            QuietTime::parse("00:30 - 02:00").unwrap(),
+        ];
+        let scheduler = Scheduler::new(parse_timezone("UTC").unwrap(), quiet_times);
+
+        // midnight falls into the first window, whose end falls into the second one
+        assert_eq!(
+            scheduler.next_run(parse_time("00:00").unwrap(), utc("2024-06-01T12:00:00Z")),
+            utc("2024-06-02T02:00:00Z")
+        );
+        // outside of the quiet times nothing changes
+        assert_eq!(
+            scheduler.next_run(parse_time("12:00").unwrap(), utc("2024-06-01T00:00:00Z")),
+            utc("2024-06-01T12:00:00Z")
+        );
+
+        assert!(QuietTime::parse("23:30").is_err());
+        assert!(QuietTime::parse("23:30-25:00").is_err());
+        assert!(parse_timezone("Mars/Olympus_Mons").is_err());
+    }
+}
@@ -0,0 +1,305 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use axum::extract::Request;
+use axum::Router;
+use hyper::body::{Body, Frame, Incoming, SizeHint};
+use hyper_util::rt::{TokioIo, TokioTimer};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpListener;
+use tokio::sync::{watch, OwnedSemaphorePermit, Semaphore};
+use tokio::time::{Instant, Sleep};
+use tower::ServiceExt;
+use tower_http::limit::RequestBodyLimitLayer;
+
+use crate::config::ServerSettings;
+
+/// serves the app on the listener until `shutdown` completes, protecting it from slow clients:
+/// request headers have to arrive within the header read timeout, connections without a
+/// request in flight are closed once they've been idle for the idle timeout, as are
+/// connections whose requests' bodies take longer than it to arrive or whose responses aren't
+/// read for as long, request bodies are limited in size and only so many connections are
+/// served at once
+pub async fn serve(
+    listener: TcpListener,
+    app: Router,
+    settings: &ServerSettings,
+    shutdown: impl Future<Output = ()>,
+) {
+    let app = app.layer(RequestBodyLimitLayer::new(settings.max_body_bytes));
+    let connections = Arc::new(Semaphore::new(settings.max_connections));
+    let mut http = hyper::server::conn::http1::Builder::new();
+    http.timer(TokioTimer::new())
+        .header_read_timeout(settings.header_read_timeout());
+    let idle_timeout = settings.idle_timeout();
+
+    // every connection holds a receiver, so all of them are closed once the sender is
+    let (shutdown_sender, shutdown_receiver) = watch::channel(());
+    tokio::pin!(shutdown);
+    loop {
+        let permit = tokio::select! {
+            permit = Arc::clone(&connections).acquire_owned() => permit.unwrap(),
+            _ = &mut shutdown => break,
+        };
+        let (stream, _) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    // e.g. too many open files; back off instead of spinning
+                    eprintln!("Couldn't accept connection: {}", e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            },
+            _ = &mut shutdown => break,
+        };
+
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let io = TokioIo::new(IdleTimeout::new(
+            stream,
+            idle_timeout,
+            Arc::clone(&in_flight),
+        ));
+        let app = app.clone();
+        let connection = Connection {
+            _permit: Arc::new(permit),
+            shutdown: shutdown_receiver.clone(),
+        };
+        let request_connection = connection.clone();
+        let service = hyper::service::service_fn(move |request: Request<Incoming>| {
+            let app = app.clone();
+            let in_flight = InFlight::new(Arc::clone(&in_flight));
+            let mut request = request.map(|body| DeadlineBody::new(body, idle_timeout));
+            request.extensions_mut().insert(request_connection.clone());
+            async move {
+                let response = app.oneshot(request).await;
+                drop(in_flight);
+                response
+            }
+        });
+        // upgraded connections, i.e. WebSockets, are served by the app until they're closed; it
+        // holds on to the `Connection` for as long
+        let serving = http.serve_connection(io, service).with_upgrades();
+        tokio::spawn(async move {
+            let mut connection = connection;
+            tokio::pin!(serving);
+            tokio::select! {
+                // errors are mostly clients going away or timing out, nothing to act on
+                _ = serving.as_mut() => {}
+                _ = connection.shutdown() => {
+                    serving.as_mut().graceful_shutdown();
+                    serving.await.ok();
+                }
+            }
+        });
+    }
+
+    // let the open connections finish their requests
+    drop(shutdown_receiver);
+    shutdown_sender.send(()).ok();
+    shutdown_sender.closed().await;
+}
+
+/// A connection being served, available to the handlers as request extension; it counts
+/// toward `max_connections` until all of its clones are dropped, so handlers of upgraded
+/// connections keep it until they're done, since hyper is done with them once they're handed
+/// over
+#[derive(Clone)]
+pub struct Connection {
+    _permit: Arc<OwnedSemaphorePermit>,
+    shutdown: watch::Receiver<()>,
+}
+
+impl Connection {
+    /// completes once the server shuts down, which waits for all connections to be dropped
+    pub async fn shutdown(&mut self) {
+        // fails if the sender is gone, which means the same
+        self.shutdown.changed().await.ok();
+    }
+}
+
+/// counts a request as in flight for as long as it's alive
+struct InFlight(Arc<AtomicUsize>);
+
+impl InFlight {
+    fn new(count: Arc<AtomicUsize>) -> Self {
+        count.fetch_add(1, Ordering::SeqCst);
+        InFlight(count)
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A request body failing once it hasn't arrived completely within the timeout, so clients
+/// can't hold a connection by trickling it while its request counts as in flight
+struct DeadlineBody<B> {
+    body: B,
+    /// None without a timeout
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl<B> DeadlineBody<B> {
+    fn new(body: B, timeout: Option<Duration>) -> Self {
+        DeadlineBody {
+            body,
+            sleep: timeout.map(|timeout| Box::pin(tokio::time::sleep(timeout))),
+        }
+    }
+}
+
+impl<B: Body + Unpin> Body for DeadlineBody<B>
+where
+    B::Error: Into<tower::BoxError>,
+{
+    type Data = B::Data;
+    type Error = tower::BoxError;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        if let Poll::Ready(frame) = Pin::new(&mut self.body).poll_frame(cx) {
+            return Poll::Ready(frame.map(|frame| frame.map_err(Into::into)));
+        }
+        match self.sleep.as_mut().map(|sleep| sleep.as_mut().poll(cx)) {
+            Some(Poll::Ready(())) => {
+                let timed_out = io::Error::new(io::ErrorKind::TimedOut, "request body timed out");
+                Poll::Ready(Some(Err(timed_out.into())))
+            }
+            _ => Poll::Pending,
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.body.size_hint()
+    }
+}
+
+/// A connection failing reads once nothing has been read or written for the timeout while
+/// no request is in flight, so long polls aren't cut off while they wait for a value, and
+/// failing writes the client doesn't read for as long
+struct IdleTimeout<S> {
+    stream: S,
+    timeout: Option<Duration>,
+    sleep: Pin<Box<Sleep>>,
+    in_flight: Arc<AtomicUsize>,
+    /// when a write that hasn't made progress since times out; None while writes progress
+    write_sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl<S> IdleTimeout<S> {
+    fn new(stream: S, timeout: Option<Duration>, in_flight: Arc<AtomicUsize>) -> Self {
+        // without a timeout, the sleep is never polled
+        let deadline = Instant::now() + timeout.unwrap_or_default();
+        IdleTimeout {
+            stream,
+            timeout,
+            sleep: Box::pin(tokio::time::sleep_until(deadline)),
+            in_flight,
+            write_sleep: None,
+        }
+    }
+
+    fn reset(&mut self) {
+        if let Some(timeout) = self.timeout {
+            self.sleep.as_mut().reset(Instant::now() + timeout);
+        }
+    }
+
+    /// tracks the result of a write, failing it if it has been pending for the timeout
+    fn check_write<T>(
+        &mut self,
+        cx: &mut Context<'_>,
+        written: Poll<io::Result<T>>,
+    ) -> Poll<io::Result<T>> {
+        if written.is_ready() {
+            self.write_sleep = None;
+            self.reset();
+            return written;
+        }
+        let Some(timeout) = self.timeout else {
+            return written;
+        };
+        let sleep = self
+            .write_sleep
+            .get_or_insert_with(|| Box::pin(tokio::time::sleep(timeout)));
+        match sleep.as_mut().poll(cx) {
+            Poll::Ready(()) => Poll::Ready(Err(io::ErrorKind::TimedOut.into())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for IdleTimeout<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match Pin::new(&mut self.stream).poll_read(cx, buf) {
+            Poll::Ready(result) => {
+                self.reset();
+                Poll::Ready(result)
+            }
+            Poll::Pending => {
+                if self.timeout.is_none() {
+                    return Poll::Pending;
+                }
+                if self.in_flight.load(Ordering::SeqCst) > 0 {
+                    self.reset();
+                    return Poll::Pending;
+                }
+                match self.sleep.as_mut().poll(cx) {
+                    Poll::Ready(()) => Poll::Ready(Err(io::ErrorKind::TimedOut.into())),
+                    Poll::Pending => Poll::Pending,
+                }
+            }
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for IdleTimeout<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let written = Pin::new(&mut self.stream).poll_write(cx, buf);
+        self.check_write(cx, written)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let flushed = Pin::new(&mut self.stream).poll_flush(cx);
+        self.check_write(cx, flushed)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let written = Pin::new(&mut self.stream).poll_write_vectored(cx, bufs);
+        self.check_write(cx, written)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+}
@@ -0,0 +1,296 @@
+use chrono::{Days, NaiveDate, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use serde::Serialize;
+use std::cmp::Ordering;
+use sunny_db::statistics::cmp_nan_last;
+use sunny_db::timeseries::TimeSeries;
+use sunny_db::timeseries_db::SunnyDB;
+
+use crate::config::PriceSettings;
+use crate::prices::average_price;
+use crate::profile::daily_profile;
+use crate::projection::HISTORY_DAYS;
+use crate::summary::day_range;
+use crate::PowerValues;
+
+const HOUR_MS: u64 = 3_600_000;
+
+/// The expected average PV production and consumption in W during an hour, i.e. how they
+/// typically were at the same time of day during the past days
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ForecastHour {
+    pub start: u64,
+    pub end: u64,
+    pub power_pv: f64,
+    pub power_used: f64,
+}
+
+/// A time window to run an appliance in, see `recommend_windows`
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Window {
+    pub start: u64,
+    pub end: u64,
+    /// the appliance's energy expected to be covered by the PV surplus in kWh
+    pub self_consumed_kwh: f64,
+    /// the share of the appliance's energy covered by the PV surplus
+    pub self_consumption: f64,
+    pub from_grid_kwh: f64,
+    /// of the energy from the grid plus the feed-in revenue missed by using the surplus, with
+    /// the markups; null unless there's a spot price for all of the window
+    pub cost: Option<f64>,
+}
+
+/// Response of `GET /load-shifting`
+#[derive(Serialize, Debug)]
+pub struct LoadShifting {
+    pub date: NaiveDate,
+    pub power_w: f64,
+    /// number of past days with values the forecast is based on
+    pub history_days: usize,
+    pub currency: &'static str,
+    /// best first
+    pub windows: Vec<Window>,
+}
+
+/// the average PV production and consumption per local hour of the day during the
+/// `HISTORY_DAYS` days before `date`, see `profile::daily_profile`, and the number of those
+/// days with values
+fn hourly_profile(
+    db: &SunnyDB<PowerValues>,
+    date: NaiveDate,
+    timezone: Tz,
+) -> ([Option<(f64, f64)>; 24], usize) {
+    let (end, _) = day_range(date, timezone);
+    let start = date
+        .checked_sub_days(Days::new(HISTORY_DAYS))
+        .map_or(0, |day| day_range(day, timezone).0);
+    let series = db
+        .get_values_in_range(start, end.saturating_sub(1))
+        .unwrap_or_else(TimeSeries::empty);
+    let Ok(profile) = daily_profile(series.view(), timezone, 60, false) else {
+        return ([None; 24], 0);
+    };
+    let mut hours = [None; 24];
+    for (hour, slot) in hours.iter_mut().zip(&profile.all.slots) {
+        *hour = slot
+            .mean
+            .map(|mean| (mean.power_pv, mean.power_used))
+            .filter(|(pv, used)| !pv.is_nan() && !used.is_nan());
+    }
+    (hours, profile.all.days)
+}
+
+/// the forecast of the hours of the local `date` from the past days, leaving out hours of
+/// the day without history; and the number of past days it's based on
+pub fn forecast_day(
+    db: &SunnyDB<PowerValues>,
+    date: NaiveDate,
+    timezone: Tz,
+) -> (Vec<ForecastHour>, usize) {
+    let (profile, history_days) = hourly_profile(db, date, timezone);
+    let (day_start, day_end) = day_range(date, timezone);
+    let hours = (day_start..day_end)
+        .step_by(HOUR_MS as usize)
+        .filter_map(|start| {
+            let hour = Utc
+                .timestamp_millis_opt(start as i64)
+                .single()?
+                .with_timezone(&timezone)
+                .hour();
+            let (power_pv, power_used) = profile[hour as usize]?;
+            Some(ForecastHour {
+                start,
+                end: (start + HOUR_MS).min(day_end),
+                power_pv,
+                power_used,
+            })
+        })
+        .collect();
+    (hours, history_days)
+}
+
+/// the expected self-consumption and cost of drawing `power_w` throughout the given hours
+fn window(
+    run: &[ForecastHour],
+    prices: &[(u64, f64)],
+    settings: &PriceSettings,
+    power_w: f64,
+) -> Window {
+    let (mut self_consumed_kwh, mut from_grid_kwh, mut cost) = (0.0, 0.0, Some(0.0));
+    for hour in run {
+        let hours = (hour.end - hour.start) as f64 / HOUR_MS as f64;
+        let surplus = (hour.power_pv - hour.power_used).max(0.0);
+        let self_consumed = surplus.min(power_w) * hours / 1000.0;
+        let from_grid = power_w * hours / 1000.0 - self_consumed;
+        self_consumed_kwh += self_consumed;
+        from_grid_kwh += from_grid;
+        cost = match (cost, average_price(prices, hour.start, hour.end)) {
+            (Some(cost), Some(price)) => Some(
+                cost + from_grid * (price + settings.import_markup_per_kwh)
+                    + self_consumed * (price + settings.export_markup_per_kwh),
+            ),
+            _ => None,
+        };
+    }
+    let energy = self_consumed_kwh + from_grid_kwh;
+    Window {
+        start: run[0].start,
+        end: run[run.len() - 1].end,
+        self_consumed_kwh,
+        self_consumption: if energy > 0.0 {
+            self_consumed_kwh / energy
+        } else {
+            0.0
+        },
+        from_grid_kwh,
+        cost,
+    }
+}
+
+/// up to `count` non-overlapping windows of `duration` consecutive hours to run an appliance
+/// drawing `power_w` in, e.g. the dishwasher or charging the car. The cheapest come first,
+/// those without prices after them; equally expensive ones by the most self-consumption
+pub fn recommend_windows(
+    hours: &[ForecastHour],
+    prices: &[(u64, f64)],
+    settings: &PriceSettings,
+    power_w: f64,
+    duration: usize,
+    count: usize,
+) -> Vec<Window> {
+    if duration == 0 || count == 0 {
+        return Vec::new();
+    }
+    let mut candidates: Vec<Window> = hours
+        .windows(duration)
+        .filter(|run| run.windows(2).all(|pair| pair[0].end == pair[1].start))
+        .map(|run| window(run, prices, settings, power_w))
+        .collect();
+    candidates.sort_by(|a, b| {
+        let by_cost = match (a.cost, b.cost) {
+            (Some(a), Some(b)) => cmp_nan_last(&a, &b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        };
+        by_cost.then(cmp_nan_last(&b.self_consumed_kwh, &a.self_consumed_kwh))
+    });
+
+    let mut best: Vec<Window> = Vec::new();
+    for candidate in candidates {
+        if best.len() == count {
+            break;
+        }
+        if best
+            .iter()
+            .all(|window| candidate.end <= window.start || candidate.start >= window.end)
+        {
+            best.push(candidate);
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2024-06-01 00:00 CEST
+    const MIDNIGHT: u64 = 1717192800000;
+
+    fn hour(h: u64, power_pv: f64, power_used: f64) -> ForecastHour {
+        ForecastHour {
+            start: MIDNIGHT + h * HOUR_MS,
+            end: MIDNIGHT + (h + 1) * HOUR_MS,
+            power_pv,
+            power_used,
+        }
+    }
+
+    #[test]
+    fn test_recommend_windows() {
+        let settings = PriceSettings {
+            import_markup_per_kwh: 0.2,
+            ..PriceSettings::default()
+        };
+        // 3 kW PV surplus around noon, but negative spot prices at night
+        let hours: Vec<ForecastHour> = (0..24)
+            .map(|h| match h {
+                11..=13 => hour(h, 3500.0, 500.0),
+                _ => hour(h, 0.0, 300.0),
+            })
+            .collect();
+        let prices: Vec<(u64, f64)> = (0..24)
+            .map(|h| {
+                let price = match h {
+                    2..=3 => -0.05,
+                    _ => 0.1,
+                };
+                (MIDNIGHT + h * HOUR_MS, price)
+            })
+            .collect();
+
+        let windows = recommend_windows(&hours, &prices, &settings, 2000.0, 2, 3);
+        assert_eq!(windows.len(), 3);
+        // the surplus only costs the missed feed-in
+        assert_eq!(windows[0].start, hours[11].start);
+        assert_eq!(windows[0].self_consumed_kwh, 4.0);
+        assert_eq!(windows[0].self_consumption, 1.0);
+        assert!((windows[0].cost.unwrap() - 0.4).abs() < 1e-9);
+        // the windows don't overlap; cheap night power beats half of the surplus
+        assert_eq!(windows[1].start, hours[2].start);
+        assert!((windows[1].cost.unwrap() - 0.6).abs() < 1e-9);
+        assert_eq!(windows[1].from_grid_kwh, 4.0);
+        // either half of the surplus
+        assert!([hours[10].start, hours[13].start].contains(&windows[2].start));
+        assert!((windows[2].cost.unwrap() - 0.8).abs() < 1e-9);
+        assert_eq!(windows[2].self_consumption, 0.5);
+
+        // without prices, the most self-consumption wins
+        let windows = recommend_windows(&hours, &[], &settings, 2000.0, 3, 1);
+        assert_eq!(windows[0].start, hours[11].start);
+        assert_eq!(windows[0].cost, None);
+        assert_eq!(windows[0].end, hours[13].end);
+
+        // hours without a forecast split the day
+        let gappy = [hour(1, 0.0, 0.0), hour(3, 0.0, 0.0)];
+        assert!(recommend_windows(&gappy, &prices, &settings, 1000.0, 2, 3).is_empty());
+        assert!(recommend_windows(&hours, &prices, &settings, 1000.0, 0, 3).is_empty());
+    }
+
+    #[test]
+    fn test_forecast_day() {
+        let path = std::env::temp_dir().join(format!("sunny-shifting-{}", std::process::id()));
+        std::fs::remove_dir_all(&path).ok();
+        let mut db = SunnyDB::<PowerValues>::new(100, path.to_str().unwrap(), 2, 0).unwrap();
+        let berlin: Tz = "Europe/Berlin".parse().unwrap();
+        let power = |power_pv, power_used| PowerValues {
+            power_pv,
+            power_used,
+            power_to_grid: 0.0,
+            power_from_grid: 0.0,
+        };
+        // 1 kW and then 2 kW PV from 10:00 to 11:00 on the two days before, every 30 minutes
+        for i in 0..2 * 48 {
+            let h = (i / 2) % 24;
+            let pv = match (h, i < 48) {
+                (10, true) => 1000.0,
+                (10, false) => 2000.0,
+                _ => 0.0,
+            };
+            db.insert_value_at_time(MIDNIGHT + i * HOUR_MS / 2, power(pv, 100.0));
+        }
+
+        let date = NaiveDate::from_ymd_opt(2024, 6, 3).unwrap();
+        let (forecast, history_days) = forecast_day(&db, date, berlin);
+        assert_eq!(history_days, 2);
+        assert_eq!(forecast.len(), 24);
+        let today = MIDNIGHT + 48 * HOUR_MS;
+        assert_eq!(forecast[10], hour(58, 1500.0, 100.0));
+        assert_eq!(forecast[10].start, today + 10 * HOUR_MS);
+        assert_eq!(forecast[9].power_pv, 0.0);
+
+        drop(db);
+        std::fs::remove_dir_all(&path).ok();
+    }
+}
@@ -0,0 +1,148 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use sunny_db::codec::SegmentEncoding;
+use sunny_db::timeseries_db::SunnyDB;
+use tokio::net::TcpListener;
+use tokio::sync::{watch, RwLock};
+use tokio::time::interval;
+
+use crate::config::Config;
+use crate::health::FetcherHealth;
+use crate::live::LiveBuffer;
+use crate::prices::Prices;
+use crate::{build_router, load_encryption_key, termination_signal, AppState, DatabaseReadLock};
+use crate::{long_poll, rollups, server, stream, PowerValues};
+
+/// Number of scans without new segments after which the replica is reported as stale
+const STALE_AFTER_SCANS: u32 = 10;
+
+#[derive(clap::Args, Debug)]
+pub struct StandbyArgs {
+    // Replicated sunny home directory (e.g. synced via rsync or mounted via NFS), containing
+    // the database and the frontend
+    #[arg(long)]
+    replica_home: String,
+
+    // Address to which the server is bound
+    #[arg(short, long, default_value_t = String::from("0.0.0.0:3000"))]
+    bind: String,
+
+    // Path to an optional TOML config file with additional settings
+    #[arg(short, long)]
+    config: Option<String>,
+
+    // Seconds between scans of the replica for new segments
+    #[arg(long, default_value_t = 60, value_parser = clap::value_parser!(u64).range(1..))]
+    rescan_interval: u64,
+
+    // Optional file holding the 32 byte key segments are encrypted with
+    #[arg(long)]
+    key_file: Option<String>,
+}
+
+/// serves read-only queries from a replicated data directory without collecting any values;
+/// segments are listed on every query, so new ones show up as soon as they've been replicated.
+/// It's protected from slow clients like the primary, see `[server]`
+pub async fn run(args: StandbyArgs) -> anyhow::Result<()> {
+    let listener = tokio::net::TcpListener::bind(&args.bind).await?;
+    serve(args, listener, termination_signal()).await
+}
+
+/// serves the replica on the listener until `shutdown` completes
+pub async fn serve(
+    args: StandbyArgs,
+    listener: TcpListener,
+    shutdown: impl Future<Output = ()>,
+) -> anyhow::Result<()> {
+    let config = Config::load(args.config.as_deref())?;
+    let replica_path = if args.replica_home.ends_with('/') {
+        args.replica_home
+    } else {
+        args.replica_home + "/"
+    };
+
+    let mut sunny_db = SunnyDB::<PowerValues>::open_read_only(&(replica_path.to_owned() + "db"))?
+        .with_segment_encoding(SegmentEncoding::default())?;
+    if let Some(key) = load_encryption_key(args.key_file.as_deref())? {
+        sunny_db = sunny_db.with_encryption_key(key)?;
+    }
+    if let Some(cold_dir) = &config.archive.cold_dir {
+        sunny_db = sunny_db.with_cold_storage(cold_dir)?;
+    }
+    let db_lock = Arc::new(RwLock::new(sunny_db));
+
+    let scan_lock = Arc::clone(&db_lock);
+    let rescan_interval = Duration::from_secs(args.rescan_interval);
+    let (latest_sample_sender, latest_sample) = long_poll::latest_sample();
+    tokio::spawn(
+        async move { watch_replica(&scan_lock, &latest_sample_sender, rescan_interval).await },
+    );
+
+    let rollups = Arc::new(std::sync::RwLock::new(rollups::parse_rules(
+        &config.rollups,
+    )?));
+    tokio::spawn(rollups::reload_on_sighup(
+        Arc::clone(&rollups),
+        args.config.clone(),
+    ));
+
+    let resolution = db_lock.read().await.get_resolution();
+    // nothing is fetched on a standby, so the live buffer stays empty
+    let state = AppState {
+        db_read_lock: DatabaseReadLock::new(db_lock),
+        resolution,
+        latest_sample,
+        // the replicated values are only written as segments, so there's nothing to push
+        new_values: stream::new_values(),
+        live: LiveBuffer::new(config.live.buffer_size),
+        // prices are fetched by the primary into a series of their own that isn't replicated
+        prices: Prices::disabled(),
+        rollups,
+        // nor are there any fetches to fail
+        fetcher_health: FetcherHealth::new(Instant::now()),
+    };
+    let app = build_router(state, &config, &replica_path);
+    println!(
+        "Serving replica {} read-only on http://{}",
+        replica_path,
+        listener.local_addr()?
+    );
+    server::serve(listener, app, &config.server, shutdown).await;
+    Ok(())
+}
+
+/// periodically re-scans the replica and logs how far it has caught up, warning if no new
+/// segments show up for a while; clients long-polling for new values are woken up whenever
+/// new segments arrive
+async fn watch_replica(
+    db_lock: &RwLock<SunnyDB<PowerValues>>,
+    latest_sample: &watch::Sender<Option<u64>>,
+    rescan_interval: Duration,
+) {
+    let mut pause = interval(rescan_interval);
+    let mut last_seen = None;
+    let mut scans_without_news = 0;
+    loop {
+        pause.tick().await;
+        let last_persisted = db_lock.read().await.last_persisted_time();
+        if last_persisted > last_seen {
+            println!(
+                "Replica contains values up to {}",
+                last_persisted.unwrap_or_default()
+            );
+            last_seen = last_persisted;
+            latest_sample.send_replace(last_persisted);
+            scans_without_news = 0;
+            continue;
+        }
+
+        scans_without_news += 1;
+        if scans_without_news == STALE_AFTER_SCANS {
+            println!(
+                "Warning: no new segments have been replicated in the last {} s",
+                rescan_interval.as_secs() * STALE_AFTER_SCANS as u64
+            );
+        }
+    }
+}
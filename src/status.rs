@@ -0,0 +1,142 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::auth::tokens_match;
+use crate::kpi::Kpi;
+use crate::projection::TodaySoFar;
+use crate::PowerValues;
+
+/// Query parameters of `GET /status.json`
+#[derive(Deserialize)]
+pub struct StatusParams {
+    pub token: Option<String>,
+}
+
+/// Response of `GET /status.json`, small enough to be polled by widgets on other sites
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Status {
+    /// time of the latest value in ms; null without values today
+    pub time: Option<u64>,
+    /// the latest values in W
+    pub power: Option<PowerValues>,
+    /// energy of today so far in kWh
+    pub today_kwh: Option<PowerValues>,
+    /// share of today's consumption not drawn from the grid; null without consumption
+    pub autarky_today: Option<f64>,
+}
+
+impl Status {
+    pub fn new(today: Option<TodaySoFar>) -> Self {
+        let Some(today) = today else {
+            return Status {
+                time: None,
+                power: None,
+                today_kwh: None,
+                autarky_today: None,
+            };
+        };
+        let autarky_today = today
+            .energy_kwh
+            .as_ref()
+            .and_then(Kpi::from_energy)
+            .and_then(|kpi| kpi.autarky);
+        Status {
+            time: today.last_time,
+            power: today.last,
+            today_kwh: today.energy_kwh,
+            autarky_today,
+        }
+    }
+}
+
+/// whether the token is one of the share tokens; any is if there are none
+pub fn is_shared_with(share_tokens: &[String], token: Option<&str>) -> bool {
+    if share_tokens.is_empty() {
+        return true;
+    }
+    let Some(token) = token else {
+        return false;
+    };
+    share_tokens
+        .iter()
+        .any(|share_token| tokens_match(share_token, token))
+}
+
+/// Allows a number of requests per minute across all clients, independently of any other
+/// route, so a popular embedding can't starve the dashboard
+pub struct RateLimiter {
+    per_minute: u32,
+    /// start of the current minute and the requests allowed within it
+    window: Mutex<(Instant, u32)>,
+}
+
+impl RateLimiter {
+    pub fn new(per_minute: u32) -> Self {
+        RateLimiter {
+            per_minute,
+            window: Mutex::new((Instant::now(), 0)),
+        }
+    }
+
+    /// whether another request may be answered at `now`; otherwise how long until the next
+    /// one may
+    pub fn allow(&self, now: Instant) -> Result<(), Duration> {
+        let mut window = self.window.lock().unwrap();
+        let elapsed = now.saturating_duration_since(window.0);
+        if elapsed >= Duration::from_secs(60) {
+            *window = (now, 0);
+        }
+        if window.1 >= self.per_minute {
+            return Err(Duration::from_secs(60).saturating_sub(elapsed));
+        }
+        window.1 += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(2);
+        let start = Instant::now();
+        assert!(limiter.allow(start).is_ok());
+        assert!(limiter.allow(start + Duration::from_secs(10)).is_ok());
+        let wait = limiter.allow(start + Duration::from_secs(20)).unwrap_err();
+        assert!(wait <= Duration::from_secs(40));
+        assert!(limiter.allow(start + Duration::from_secs(61)).is_ok());
+    }
+
+    #[test]
+    fn test_status() {
+        let tokens = vec![String::from("secret")];
+        assert!(is_shared_with(&[], None));
+        assert!(is_shared_with(&tokens, Some("secret")));
+        assert!(!is_shared_with(&tokens, Some("secreT")));
+        assert!(!is_shared_with(&tokens, None));
+
+        let energy = PowerValues {
+            power_pv: 3.0,
+            power_to_grid: 1.0,
+            power_from_grid: 0.5,
+            power_used: 2.5,
+        };
+        let today = TodaySoFar {
+            since: 0,
+            values: 2,
+            last_time: Some(1000),
+            last: Some(energy * 1000.0),
+            mean: None,
+            min: None,
+            max: None,
+            energy_kwh: Some(energy),
+        };
+        let status = Status::new(Some(today));
+        assert_eq!(status.time, Some(1000));
+        assert_eq!(status.autarky_today, Some(0.8));
+        assert_eq!(Status::new(None).power, None);
+    }
+}
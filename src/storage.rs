@@ -0,0 +1,173 @@
+use anyhow::{self, Context};
+use std::fs;
+use std::path::{Path, PathBuf};
+use sunny_db::timeseries_db::SunnyDB;
+
+use crate::config::{SeriesQuota, StorageSettings, StoredSeries};
+use crate::PowerValues;
+
+const MB: u64 = 1024 * 1024;
+
+/// Where the series sunny keeps on disk are stored; summaries are in the catalog of the
+/// database if it maintains one and in the summary directory if they were written before
+pub struct Storage<'a> {
+    pub db: &'a SunnyDB<PowerValues>,
+    pub summary_dir: &'a Path,
+}
+
+impl Storage<'_> {
+    fn usage(&self, series: StoredSeries) -> anyhow::Result<u64> {
+        match series {
+            StoredSeries::Values => self.db.disk_usage(),
+            StoredSeries::Summaries => {
+                let files = summary_files(self.summary_dir)?;
+                let usage: u64 = files.iter().map(|(_, size)| size).sum();
+                #[cfg(feature = "catalog")]
+                if let Some(catalog) = self.db.catalog() {
+                    return Ok(usage + catalog.summaries_size()?);
+                }
+                Ok(usage)
+            }
+        }
+    }
+
+    /// deletes the oldest data of the series until at least `bytes` have been freed or
+    /// nothing is left; returns the number of bytes freed
+    fn prune(&self, series: StoredSeries, bytes: u64) -> anyhow::Result<u64> {
+        match series {
+            StoredSeries::Values => self.db.prune_oldest(bytes),
+            StoredSeries::Summaries => {
+                // the files are older than the summaries in the catalog
+                let freed = prune_summaries(self.summary_dir, bytes)?;
+                #[cfg(feature = "catalog")]
+                if let Some(catalog) = self.db.catalog() {
+                    if freed < bytes {
+                        return Ok(freed + catalog.remove_oldest_summaries(bytes - freed)?);
+                    }
+                }
+                Ok(freed)
+            }
+        }
+    }
+}
+
+/// the summary files with their size, oldest first
+fn summary_files(summary_dir: &Path) -> anyhow::Result<Vec<(PathBuf, u64)>> {
+    if !summary_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut files = Vec::new();
+    for entry in fs::read_dir(summary_dir)? {
+        let entry = entry?;
+        if entry.path().extension().is_some_and(|e| e == "json") {
+            files.push((entry.path(), entry.metadata()?.len()));
+        }
+    }
+    // the files are named by their date
+    files.sort();
+    Ok(files)
+}
+
+fn prune_summaries(summary_dir: &Path, bytes: u64) -> anyhow::Result<u64> {
+    let mut freed = 0;
+    for (path, size) in summary_files(summary_dir)? {
+        if freed >= bytes {
+            break;
+        }
+        fs::remove_file(&path).with_context(|| format!("Couldn't remove {}", path.display()))?;
+        freed += size;
+    }
+    Ok(freed)
+}
+
+/// prunes the series exceeding their quota and, if less than the configured space is
+/// available (as reported by `available_space`), the series with quotas in the order of
+/// their priority; returns the number of bytes freed
+pub fn enforce_quotas(
+    storage: &Storage,
+    settings: &StorageSettings,
+    available_space: impl Fn() -> std::io::Result<u64>,
+) -> anyhow::Result<u64> {
+    let mut freed = 0;
+    for quota in &settings.quotas {
+        let Some(max_mb) = quota.max_mb else {
+            continue;
+        };
+        let usage = storage.usage(quota.series)?;
+        if usage > max_mb * MB {
+            freed += storage.prune(quota.series, usage - max_mb * MB)?;
+        }
+    }
+
+    if settings.min_free_mb == 0 {
+        return Ok(freed);
+    }
+    let available = available_space().context("Couldn't determine the available disk space")?;
+    let mut missing = (settings.min_free_mb * MB).saturating_sub(available);
+    let mut by_priority: Vec<&SeriesQuota> = settings.quotas.iter().collect();
+    by_priority.sort_by_key(|quota| quota.priority);
+    for quota in by_priority {
+        if missing == 0 {
+            break;
+        }
+        let pruned = storage.prune(quota.series, missing)?;
+        missing = missing.saturating_sub(pruned);
+        freed += pruned;
+    }
+    if missing > 0 {
+        println!(
+            "Warning: less than {} MB are available on disk even after pruning all series with quotas",
+            settings.min_free_mb
+        );
+    }
+    Ok(freed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_enforce_quotas() {
+        let home = std::env::temp_dir().join(format!("sunny-storage-{}", std::process::id()));
+        fs::remove_dir_all(&home).ok();
+        let summary_dir = home.join("summaries");
+        fs::create_dir_all(&summary_dir).unwrap();
+        for day in ["2024-06-01", "2024-06-02", "2024-06-03"] {
+            fs::write(
+                summary_dir.join(format!("{}.json", day)),
+                vec![b' '; 600 * 1024],
+            )
+            .unwrap();
+        }
+        let db = SunnyDB::<PowerValues>::new(10, home.join("db").to_str().unwrap(), 2, 0).unwrap();
+        let storage = Storage {
+            db: &db,
+            summary_dir: &summary_dir,
+        };
+
+        let quota = |series, max_mb, priority| SeriesQuota {
+            series,
+            max_mb,
+            priority,
+        };
+        let mut settings = StorageSettings {
+            quotas: vec![
+                quota(StoredSeries::Values, None, 10),
+                quota(StoredSeries::Summaries, Some(2), 1),
+            ],
+            ..StorageSettings::default()
+        };
+        // within the quota and no minimum of free space
+        assert_eq!(enforce_quotas(&storage, &settings, || Ok(0)).unwrap(), 0);
+
+        // the series with the lowest priority are pruned first, oldest first
+        settings.min_free_mb = 1;
+        let freed = enforce_quotas(&storage, &settings, || Ok(0)).unwrap();
+        assert_eq!(freed, 2 * 600 * 1024);
+        assert!(summary_dir.join("2024-06-03.json").exists());
+        assert!(!summary_dir.join("2024-06-02.json").exists());
+
+        fs::remove_dir_all(&home).ok();
+    }
+}
@@ -0,0 +1,50 @@
+use futures_util::stream::{self, Stream};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// Number of values a client of `GET /stream` may fall behind before it misses some
+const CAPACITY: usize = 64;
+
+/// Values with their time as they're written to the database, pushed to the clients of
+/// `GET /stream`
+pub type NewValues<T> = broadcast::Sender<(u64, T)>;
+
+pub fn new_values<T: Clone>() -> NewValues<T> {
+    broadcast::channel(CAPACITY).0
+}
+
+/// the values written from now on; clients that fell behind skip the ones they missed
+pub fn subscribe<T: Clone + Send + 'static>(
+    new_values: &NewValues<T>,
+) -> impl Stream<Item = (u64, T)> {
+    stream::unfold(new_values.subscribe(), |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(value) => return Some((value, receiver)),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        let new_values = new_values::<f64>();
+        // nobody is listening yet
+        assert!(new_values.send((1, 1.0)).is_err());
+
+        let mut values = Box::pin(subscribe(&new_values));
+        for i in 2..(2 + CAPACITY as u64 + 10) {
+            new_values.send((i, i as f64)).unwrap();
+        }
+        // the oldest values were missed
+        assert_eq!(values.next().await, Some((12, 12.0)));
+        drop(new_values);
+        assert_eq!(values.count().await, CAPACITY - 1);
+    }
+}
@@ -0,0 +1,221 @@
+use anyhow::{self, Context};
+use chrono::{NaiveDate, NaiveTime, TimeZone};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, create_dir_all};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use sunny_db::timeseries_db::SunnyDB;
+
+use crate::baseline::baseline;
+use crate::{compute_statistics, PowerStatistics, PowerValues};
+
+/// Statistics over a single local day, written to `<sunny-home>/summaries/YYYY-MM-DD.json`
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DailySummary {
+    pub date: NaiveDate,
+    pub timezone: String,
+    pub start_time: u64,
+    pub end_time: u64,
+    #[serde(flatten)]
+    pub stats: PowerStatistics,
+    // summaries written before they were added lack these
+    #[serde(default)]
+    pub completeness: Completeness,
+    /// the always-on consumption in W, see baseline.rs; None without values at night
+    #[serde(default)]
+    pub baseline_w: Option<f64>,
+}
+
+/// How complete the values of a day are, so patchy days can be told apart from trustworthy ones
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct Completeness {
+    /// share of the values expected at the sampling interval that are present, at most 1
+    pub score: f64,
+    pub expected_values: u64,
+    /// longest time in ms without any values, counting from the start and to the end of the day
+    pub longest_gap_ms: u64,
+}
+
+/// the completeness of the values at the given times within [start_time, end_time), which are
+/// expected every `expected_interval`
+fn completeness(
+    times: impl Iterator<Item = u64>,
+    start_time: u64,
+    end_time: u64,
+    expected_interval: Duration,
+) -> Completeness {
+    let mut present = 0;
+    let mut longest_gap_ms = 0;
+    let mut last_time = start_time;
+    for time in times {
+        present += 1;
+        longest_gap_ms = longest_gap_ms.max(time.saturating_sub(last_time));
+        last_time = time;
+    }
+    longest_gap_ms = longest_gap_ms.max(end_time.saturating_sub(last_time));
+
+    let interval_ms = (expected_interval.as_millis() as u64).max(1);
+    let expected_values = (end_time.saturating_sub(start_time) / interval_ms).max(1);
+    Completeness {
+        score: (present as f64 / expected_values as f64).min(1.0),
+        expected_values,
+        longest_gap_ms,
+    }
+}
+
+/// the range [start, end) of a local day in ms since the epoch
+pub fn day_range(date: NaiveDate, timezone: Tz) -> (u64, u64) {
+    let start_of = |d: NaiveDate| {
+        timezone
+            .from_local_datetime(&d.and_time(NaiveTime::MIN))
+            .earliest()
+            // no timezone skips midnight for long, but just in case, start at 1 am
+            .or(timezone
+                .from_local_datetime(&d.and_hms_opt(1, 0, 0).unwrap())
+                .earliest())
+            .map(|t| t.timestamp_millis().max(0) as u64)
+            .unwrap_or(0)
+    };
+
+    let next_day = date.succ_opt().unwrap_or(date);
+    (start_of(date), start_of(next_day))
+}
+
+/// the statistics of the values of the given local day; values are expected to be stored every
+/// `expected_interval` to rate how complete they are
+pub fn summarize_day(
+    db: &SunnyDB<PowerValues>,
+    date: NaiveDate,
+    timezone: Tz,
+    expected_interval: Duration,
+) -> DailySummary {
+    let (start_time, end_time) = day_range(date, timezone);
+    // interpolated at midnight so the energy covers the whole day, but only the stored values
+    // within the day count for the completeness and the baseline
+    let series = db.get_values_in_range_interpolated(start_time, end_time);
+    let stored = series
+        .as_ref()
+        .and_then(|series| series.view_range(start_time, end_time - 1));
+    let times = stored
+        .iter()
+        .flat_map(|stored| stored.iter().map(|(time, _)| time));
+    let completeness = completeness(times, start_time, end_time, expected_interval);
+    let baseline_w = stored
+        .as_ref()
+        .and_then(|stored| baseline(stored.iter(), stored.get_resolution()))
+        .map(|(baseline_w, _)| baseline_w);
+    let stats = match series {
+        Some(series) => compute_statistics(series.view()),
+        None => PowerStatistics {
+            average: None,
+            mins: None,
+            nonzero_mins: None,
+            maxes: None,
+            p95: None,
+            std_dev: None,
+            energy_kwh: None,
+            kpi: None,
+            excluded_ms: 0,
+            quality: Default::default(),
+        },
+    };
+
+    DailySummary {
+        date,
+        timezone: timezone.name().to_owned(),
+        start_time,
+        end_time,
+        stats,
+        completeness,
+        baseline_w,
+    }
+}
+
+pub fn summary_path(summary_dir: &Path, date: NaiveDate) -> PathBuf {
+    summary_dir.join(format!("{}.json", date.format("%Y-%m-%d")))
+}
+
+/// stores the summary in the catalog of the database if it maintains one, otherwise in
+/// `<summary_dir>/YYYY-MM-DD.json`
+pub fn store_summary(
+    db: &SunnyDB<PowerValues>,
+    summary_dir: &Path,
+    summary: &DailySummary,
+) -> anyhow::Result<()> {
+    #[cfg(feature = "catalog")]
+    if let Some(catalog) = db.catalog() {
+        return catalog.put_summary(summary.date, &serde_json::to_string(summary)?);
+    }
+    #[cfg(not(feature = "catalog"))]
+    let _ = db;
+    write_summary(summary_dir, summary)
+}
+
+/// the stored summary of the day as JSON, see `store_summary`; summaries written to files
+/// before the catalog was maintained are still found
+pub fn read_summary(
+    db: &SunnyDB<PowerValues>,
+    summary_dir: &Path,
+    date: NaiveDate,
+) -> anyhow::Result<Option<String>> {
+    #[cfg(feature = "catalog")]
+    if let Some(summary) = db.catalog().map(|c| c.summary(date)).transpose()?.flatten() {
+        return Ok(Some(summary));
+    }
+    #[cfg(not(feature = "catalog"))]
+    let _ = db;
+    let path = summary_path(summary_dir, date);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let json =
+        fs::read_to_string(&path).with_context(|| format!("Couldn't read {}", path.display()))?;
+    Ok(Some(json))
+}
+
+pub fn write_summary(summary_dir: &Path, summary: &DailySummary) -> anyhow::Result<()> {
+    create_dir_all(summary_dir).with_context(|| {
+        format!(
+            "Couldn't create summary directory {}",
+            summary_dir.display()
+        )
+    })?;
+    let path = summary_path(summary_dir, summary.date);
+    let json = serde_json::to_string_pretty(summary)?;
+    fs::write(&path, json).with_context(|| format!("Couldn't write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_completeness() {
+        let minute = Duration::from_secs(60);
+        let hour = 3_600_000;
+        let day = 24 * hour;
+
+        // a value every minute, except for two hours in the afternoon
+        let times = (0..day / 60_000)
+            .map(|i| i * 60_000)
+            .filter(|time| !(14 * hour..16 * hour).contains(time));
+        let patchy = completeness(times, 0, day, minute);
+        assert_eq!(patchy.expected_values, 1440);
+        assert_eq!(patchy.score, 1320.0 / 1440.0);
+        assert_eq!(patchy.longest_gap_ms, 2 * hour + 60_000);
+
+        // values stop early in the evening
+        let times = (0..20 * 60).map(|i| i * 60_000);
+        let evening = completeness(times, 0, day, minute);
+        assert_eq!(evening.longest_gap_ms, 4 * hour + 60_000);
+
+        // faster sampling than expected doesn't score more than complete
+        let times = (0..day / 1000).map(|i| i * 1000);
+        assert_eq!(completeness(times, 0, day, minute).score, 1.0);
+
+        let empty = completeness(std::iter::empty(), 0, day, minute);
+        assert_eq!(empty.score, 0.0);
+        assert_eq!(empty.longest_gap_ms, day);
+    }
+}
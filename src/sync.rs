@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+use sunny_db::manifest::{Change, ManifestEntry};
+
+/// Query parameters of `GET /sync/segments`, e.g. `?since=42`
+#[derive(Deserialize)]
+pub struct SyncParams {
+    /// sequence number of the last manifest entry the client has seen; all entries if missing
+    #[serde(default)]
+    pub since: u64,
+}
+
+/// The changes to the persisted segments after the client's last sync
+#[derive(Serialize, Debug, PartialEq)]
+pub struct SegmentChanges {
+    /// sequence number of the newest entry, i.e. where to continue from next time
+    pub seq: u64,
+    pub segments: Vec<SegmentChange>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct SegmentChange {
+    pub seq: u64,
+    pub change: SegmentChangeKind,
+    /// name of the segment file, which is also its path below the download URL
+    pub name: String,
+    /// time of the first value in the segment in ms
+    pub start: u64,
+    /// time of the last value in the segment in ms
+    pub end: u64,
+    pub size: u64,
+    /// where to download the segment file; null for removed segments
+    pub url: Option<String>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SegmentChangeKind {
+    Added,
+    Removed,
+}
+
+/// lists the manifest entries with download URLs below `base_url`, the path the listing
+/// itself was requested at
+pub fn segment_changes(since: u64, entries: Vec<ManifestEntry>, base_url: &str) -> SegmentChanges {
+    let base_url = base_url.trim_end_matches('/');
+    SegmentChanges {
+        seq: entries.last().map_or(since, |e| e.seq),
+        segments: entries
+            .into_iter()
+            .map(|entry| {
+                let (start, end) = entry.segment;
+                let name = format!("{}-{}", start, end);
+                let (change, url) = match entry.change {
+                    Change::Added => (
+                        SegmentChangeKind::Added,
+                        Some(format!("{}/{}", base_url, name)),
+                    ),
+                    Change::Removed => (SegmentChangeKind::Removed, None),
+                };
+                SegmentChange {
+                    seq: entry.seq,
+                    change,
+                    name,
+                    start,
+                    end,
+                    size: entry.size,
+                    url,
+                }
+            })
+            .collect(),
+    }
+}
+
+/// parses segment names like `1717200000000-1717203600000`
+pub fn parse_segment_name(name: &str) -> Option<(u64, u64)> {
+    let (start, end) = name.split_once('-')?;
+    Some((start.parse().ok()?, end.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segment_changes() {
+        let entries = vec![
+            ManifestEntry {
+                seq: 3,
+                change: Change::Added,
+                segment: (1000, 2000),
+                size: 120,
+            },
+            ManifestEntry {
+                seq: 4,
+                change: Change::Removed,
+                segment: (0, 900),
+                size: 0,
+            },
+        ];
+        let changes = segment_changes(2, entries, "/api/v1/sync/segments/");
+        assert_eq!(changes.seq, 4);
+        assert_eq!(
+            changes.segments[0].url.as_deref(),
+            Some("/api/v1/sync/segments/1000-2000")
+        );
+        assert_eq!(changes.segments[1].url, None);
+        assert_eq!(segment_changes(7, Vec::new(), "/").seq, 7);
+
+        assert_eq!(
+            parse_segment_name(&changes.segments[0].name),
+            Some((1000, 2000))
+        );
+        assert_eq!(parse_segment_name("1000"), None);
+        assert_eq!(parse_segment_name("..-2000"), None);
+    }
+}
@@ -0,0 +1,1390 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use super::harness::{last_time, TestInstance, TestOptions};
+use super::mock_inverter::MockPowerFlow;
+use crate::config::{Config, PriceSource};
+use crate::PowerValues;
+
+const FLOW: MockPowerFlow = MockPowerFlow {
+    p_pv: 3000.0,
+    p_grid: -1000.0,
+    p_load: -2000.0,
+};
+
+fn assert_close(a: f64, b: f64) {
+    assert!((a - b).abs() < 1e-6, "{} != {}", a, b);
+}
+
+fn assert_expected_values(values: &serde_json::Value) {
+    let values: PowerValues = serde_json::from_value(values.clone()).unwrap();
+    assert_close(values.power_pv, 3000.0);
+    assert_close(values.power_to_grid, 1000.0);
+    assert_close(values.power_from_grid, 0.0);
+    assert_close(values.power_used, 2000.0);
+}
+
+#[tokio::test]
+async fn collects_and_serves_values() {
+    let sunny = TestInstance::start("e2e", FLOW, TestOptions::default()).await;
+
+    // more than a segment's worth, so some values are read from disk
+    sunny.wait_for_values(8).await;
+    assert!(sunny.inverter.requests() >= 16);
+
+    let values = sunny.get_json("/values/0/99999999999999").await;
+    let values = values.as_array().unwrap();
+    assert!(values.len() >= 8);
+    let mut last_time = 0;
+    for value in values {
+        let time = value[0].as_u64().unwrap();
+        assert!(time > last_time);
+        last_time = time;
+        assert_expected_values(&value[1]);
+    }
+
+    let with_stats = sunny.get_json("/values-with-stats/0/99999999999999").await;
+    assert!(with_stats["values"].as_array().unwrap().len() >= 8);
+    assert_expected_values(&with_stats["average"]);
+    assert_expected_values(&with_stats["mins"]);
+    // nothing's drawn from the grid, so there's no minimum while drawing
+    assert_close(
+        with_stats["nonzero_mins"]["power_pv"].as_f64().unwrap(),
+        3000.0,
+    );
+    assert!(with_stats["nonzero_mins"]["power_from_grid"].is_null());
+    assert_expected_values(&with_stats["maxes"]);
+    assert_expected_values(&with_stats["p95"]);
+    // the power is constant, so the first value is both the minimum and the maximum
+    let first = with_stats["values"][0][0].as_u64().unwrap();
+    assert_eq!(with_stats["max_times"]["power_pv"], first);
+    assert_eq!(with_stats["min_times"]["power_used"], first);
+
+    // constant power, so the energy is just power * duration
+    let start = with_stats["values"][0][0].as_u64().unwrap();
+    let values = with_stats["values"].as_array().unwrap();
+    let end = values[values.len() - 1][0].as_u64().unwrap();
+    let hours = (end - start) as f64 / 3600e3;
+    let energy_kwh = with_stats["energy_kwh"]["power_pv"].as_f64().unwrap();
+    assert_close(energy_kwh, 3.0 * hours);
+    // a third of the production is fed in, none of the consumption is drawn from the grid
+    let kpi = &with_stats["kpi"];
+    assert_close(kpi["self_consumption"].as_f64().unwrap(), 2.0 / 3.0);
+    assert_close(kpi["autarky"].as_f64().unwrap(), 1.0);
+    let range_kpi = sunny.get_json(&format!("/kpi/{}/{}", start - 1, end)).await;
+    assert_close(
+        range_kpi["kpi"]["self_supplied_kwh"].as_f64().unwrap(),
+        2.0 * hours,
+    );
+
+    let downsampled = sunny
+        .get_json("/values/0/99999999999999?max_points=3&downsampling=lttb")
+        .await;
+    assert_eq!(downsampled.as_array().unwrap().len(), 3);
+    // only the values are reduced, the statistics are still those of all of them
+    let reduced_with_stats = sunny
+        .get_json(&format!("/values-with-stats/0/{}?max_points=3", end))
+        .await;
+    assert_eq!(reduced_with_stats["values"].as_array().unwrap().len(), 3);
+    let all_with_stats = sunny
+        .get_json(&format!("/values-with-stats/0/{}", end))
+        .await;
+    assert_eq!(
+        reduced_with_stats["energy_kwh"],
+        all_with_stats["energy_kwh"]
+    );
+    assert_eq!(reduced_with_stats["maxes"], all_with_stats["maxes"]);
+
+    // the same values when asking for several ranges at once
+    let ranges = serde_json::json!({"ranges": [
+        {"start_time": 0, "end_time": end},
+        {"start_time": start, "end_time": end},
+        {"start_time": 0, "end_time": 1},
+    ]});
+    let batch: serde_json::Value = reqwest::Client::new()
+        .post(sunny.url("/values/batch"))
+        .json(&ranges)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(batch[0], with_stats["values"]);
+    assert_eq!(batch[1].as_array().unwrap().len(), values.len() - 1);
+    assert!(batch[2].as_array().unwrap().is_empty());
+    // a range in µs is rejected like in a path
+    let in_micros = serde_json::json!({"ranges": [
+        {"start_time": 0, "end_time": end},
+        {"start_time": start * 1000, "end_time": end * 1000},
+    ]});
+    let rejected = reqwest::Client::new()
+        .post(sunny.url("/values/batch"))
+        .json(&in_micros)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(rejected.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    // the power is constant, so there are no spikes
+    let peaks = sunny
+        .get_json("/peaks/power_used/0/99999999999999?n=3")
+        .await;
+    assert!(peaks.as_array().unwrap().is_empty());
+    let unknown = sunny.get("/peaks/power_wind/0/99999999999999").await;
+    assert_eq!(unknown.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    let settings = sunny.get_json("/config/frontend").await;
+    assert_eq!(settings["site_name"], "Sunny");
+
+    // the data routes are also served under the API prefix
+    let prefixed = sunny.get_json("/api/v1/values/0/99999999999999").await;
+    assert!(prefixed.as_array().unwrap().len() >= 8);
+
+    let metrics = sunny.get_json("/metrics").await;
+    assert_eq!(metrics["dropped_points"], 0);
+    assert_eq!(metrics["failed_exports"], 0);
+    let values_latency = &metrics["latencies"]["/values/:start_time/:end_time"];
+    assert_eq!(values_latency["count"], 2);
+    let bucket_counts: u64 = values_latency["buckets"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|b| b["count"].as_u64().unwrap())
+        .sum();
+    assert_eq!(bucket_counts, 2);
+    // every collected value was inserted
+    assert!(
+        metrics["write_path"]["insert_us"]["count"]
+            .as_u64()
+            .unwrap()
+            >= 8
+    );
+}
+
+#[tokio::test]
+async fn reports_memory_usage() {
+    let sunny = TestInstance::start("e2e-memory", FLOW, TestOptions::default()).await;
+    sunny.wait_for_values(2).await;
+
+    let info = sunny.get_json("/db/info").await;
+    assert!(info["in_memory_points"].as_u64().unwrap() >= 2);
+    assert!(info["mean_sample_interval_ms"].as_f64().unwrap() > 0.0);
+    let memory = &info["memory"];
+    assert!(memory["series_bytes"].as_u64().unwrap() > 0);
+    assert!(memory["live_buffer_bytes"].as_u64().unwrap() > 0);
+    assert!(memory["statistics_bytes"].as_u64().unwrap() > 0);
+    let parts: u64 = [
+        "series_bytes",
+        "remote_index_bytes",
+        "statistics_bytes",
+        "live_buffer_bytes",
+        "prices_bytes",
+    ]
+    .iter()
+    .map(|part| memory[part].as_u64().unwrap())
+    .sum();
+    assert_eq!(memory["total_bytes"].as_u64().unwrap(), parts);
+
+    let response = sunny.get("/metrics?format=prometheus").await;
+    assert_eq!(response.status(), reqwest::StatusCode::OK);
+    let text = response.text().await.unwrap();
+    let series_bytes = memory["series_bytes"].as_u64().unwrap();
+    assert!(text.contains(&format!(
+        "sunny_memory_bytes{{part=\"series\"}} {}",
+        series_bytes
+    )));
+    assert!(text.contains("sunny_request_duration_ms_bucket{route=\"/db/info\",le=\"+Inf\"} 1"));
+    assert!(text.contains("# TYPE sunny_flush_duration_ms histogram"));
+    let json = sunny.get_json("/metrics").await;
+    assert_eq!(json["memory"]["series_bytes"], series_bytes);
+}
+
+#[tokio::test]
+async fn rejects_ranges_in_the_wrong_unit() {
+    let sunny = TestInstance::start("e2e-timestamps", FLOW, TestOptions::default()).await;
+    sunny.wait_for_values(2).await;
+
+    // µs rather than ms
+    let micros = sunny.get("/values/1717200000000000/1717200060000000").await;
+    assert_eq!(micros.status(), reqwest::StatusCode::BAD_REQUEST);
+    let stats = sunny
+        .get("/api/v1/values-with-stats/1717200000000000/1")
+        .await;
+    assert_eq!(stats.status(), reqwest::StatusCode::BAD_REQUEST);
+    // open-ended ranges are fine
+    let all = sunny.get("/values/0/99999999999999").await;
+    assert_eq!(all.status(), reqwest::StatusCode::OK);
+
+    let metrics = sunny.get_json("/metrics").await;
+    assert_eq!(metrics["rejected_points"], 0);
+}
+
+#[tokio::test]
+async fn keeps_collecting_after_inverter_errors() {
+    let sunny = TestInstance::start("e2e-errors", FLOW, TestOptions::default()).await;
+    sunny.wait_for_values(2).await;
+
+    sunny.inverter.set_flow(None);
+    let requests = sunny.inverter.requests();
+    while sunny.inverter.requests() < requests + 5 {
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+
+    sunny.inverter.set_flow(Some(FLOW));
+    sunny.wait_for_values(6).await;
+
+    // only valid values have been stored
+    let values = sunny.get_json("/values/0/99999999999999").await;
+    for value in values.as_array().unwrap() {
+        assert_expected_values(&value[1]);
+    }
+}
+
+#[tokio::test]
+async fn reports_failing_fetches_via_health() {
+    let options = TestOptions {
+        config: Config::from_toml("[health]\nmax_failing_secs = 1").unwrap(),
+        ..TestOptions::default()
+    };
+    let sunny = TestInstance::start("e2e-health", FLOW, options).await;
+    sunny.wait_for_values(2).await;
+
+    let health = sunny.get_json("/health").await;
+    assert_eq!(health["healthy"], true);
+    assert_eq!(health["consecutive_fetch_errors"], 0);
+    assert!(health["secs_since_last_fetch"].is_u64());
+
+    sunny.inverter.set_flow(None);
+    let requests = sunny.inverter.requests();
+    // without a successful fetch for more than a second
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    while sunny.inverter.requests() < requests + 3 {
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    let failing = sunny.get("/health").await;
+    assert_eq!(failing.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+    let failing: serde_json::Value = failing.json().await.unwrap();
+    assert_eq!(failing["healthy"], false);
+    assert!(failing["consecutive_fetch_errors"].as_u64().unwrap() >= 2);
+
+    sunny.inverter.set_flow(Some(FLOW));
+    let values = sunny.get_json("/values/0/99999999999999").await;
+    sunny
+        .wait_for_values(values.as_array().unwrap().len() + 2)
+        .await;
+    assert_eq!(sunny.get_json("/health").await["healthy"], true);
+}
+
+#[tokio::test]
+async fn serves_data_routes_under_configured_prefix() {
+    let config = Config::from_toml(
+        r#"
+        [api]
+        prefix = "/sunny/v2/"
+        legacy_routes = false
+        "#,
+    )
+    .unwrap();
+    let options = TestOptions {
+        config,
+        ..TestOptions::default()
+    };
+    let sunny = TestInstance::start("e2e-prefix", FLOW, options).await;
+    sunny.wait_for_values(2).await;
+
+    let values = sunny.get_json("/sunny/v2/values/0/99999999999999").await;
+    assert!(!values.as_array().unwrap().is_empty());
+    let settings = sunny.get_json("/sunny/v2/config/frontend").await;
+    assert_eq!(settings["site_name"], "Sunny");
+
+    // without the legacy routes, the old paths are gone
+    let old_path = sunny.get("/values/0/99999999999999").await;
+    assert_eq!(old_path.status(), reqwest::StatusCode::NOT_FOUND);
+    let metrics = sunny.get_json("/sunny/v2/metrics").await;
+    assert_eq!(
+        metrics["latencies"]["/sunny/v2/values/:start_time/:end_time"]["count"],
+        1
+    );
+}
+
+#[tokio::test]
+async fn marks_legacy_routes_as_deprecated() {
+    let options = TestOptions {
+        config: Config::from_toml("[api]\nlegacy_sunset = \"2027-01-01\"").unwrap(),
+        ..TestOptions::default()
+    };
+    let sunny = TestInstance::start("e2e-deprecation", FLOW, options).await;
+    sunny.wait_for_values(2).await;
+
+    let legacy = sunny.get("/values/0/99999999999999").await;
+    assert!(legacy.status().is_success());
+    assert_eq!(
+        legacy.headers()["warning"],
+        "299 - \"Deprecated path, use /api/v1/values/0/99999999999999 instead\""
+    );
+    assert_eq!(legacy.headers()["sunset"], "Fri, 01 Jan 2027 00:00:00 GMT");
+    let prefixed = sunny.get("/api/v1/values/0/99999999999999").await;
+    assert!(!prefixed.headers().contains_key("warning"));
+
+    let metrics = sunny.get_json("/api/v1/metrics").await;
+    assert_eq!(
+        metrics["legacy_requests"]["/values/:start_time/:end_time"],
+        1
+    );
+    assert_eq!(metrics["legacy_requests"].as_object().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn serves_frontend_with_cache_headers() {
+    let options = TestOptions {
+        config: Config::from_toml("[assets]\nprecompressed = true").unwrap(),
+        ..TestOptions::default()
+    };
+    let sunny = TestInstance::start("e2e-assets", FLOW, options).await;
+    let assets = sunny.sunny_home.join("assets");
+    std::fs::create_dir_all(&assets).unwrap();
+    std::fs::write(sunny.sunny_home.join("index.html"), "<html></html>").unwrap();
+    std::fs::write(assets.join("index-BlLwO1xV.js"), "console.log(1)").unwrap();
+    std::fs::write(assets.join("index-BlLwO1xV.js.gz"), "gzipped").unwrap();
+
+    let index = sunny.get("/").await;
+    assert!(index.status().is_success());
+    assert_eq!(index.headers()["cache-control"], "no-cache");
+
+    let asset = reqwest::Client::new()
+        .get(sunny.url("/assets/index-BlLwO1xV.js"))
+        .header("accept-encoding", "gzip")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        asset.headers()["cache-control"],
+        "public, max-age=31536000, immutable"
+    );
+    assert_eq!(asset.headers()["content-encoding"], "gzip");
+    assert_eq!(asset.text().await.unwrap(), "gzipped");
+
+    // the API isn't cached
+    let values = sunny.get("/api/v1/values/0/1").await;
+    assert!(values.headers().get("cache-control").is_none());
+}
+
+#[tokio::test]
+async fn serves_stored_summaries() {
+    let sunny = TestInstance::start("e2e-summaries", FLOW, TestOptions::default()).await;
+    let summaries = sunny.sunny_home.join("summaries");
+    std::fs::create_dir_all(&summaries).unwrap();
+    std::fs::write(
+        summaries.join("2024-06-01.json"),
+        r#"{"date": "2024-06-01", "baseline_w": 120.0}"#,
+    )
+    .unwrap();
+
+    let summary = sunny.get_json("/api/v1/summaries/2024-06-01").await;
+    assert_eq!(summary["date"], "2024-06-01");
+    assert_eq!(summary["baseline_w"], 120.0);
+    let missing = sunny.get_json("/api/v1/summaries/2024-06-02").await;
+    assert!(missing.is_null());
+    let invalid = sunny.get("/api/v1/summaries/yesterday").await;
+    assert_eq!(invalid.status(), reqwest::StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn serves_rollups() {
+    let config = Config::from_toml(
+        r#"
+        [[rollups]]
+        record = "surplus_hourly"
+        expr = "avg_over_time(power_pv - power_used [1h])"
+        "#,
+    )
+    .unwrap();
+    let options = TestOptions {
+        config,
+        ..TestOptions::default()
+    };
+    let sunny = TestInstance::start("e2e-rollups", FLOW, options).await;
+    sunny.wait_for_values(2).await;
+
+    let definitions = sunny.get_json("/api/v1/rollups").await;
+    assert_eq!(definitions[0]["record"], "surplus_hourly");
+
+    let rolled_up = sunny
+        .get_json("/api/v1/rollups/surplus_hourly/0/99999999999999")
+        .await;
+    let rolled_up = rolled_up.as_array().unwrap();
+    assert!(!rolled_up.is_empty());
+    for bucket in rolled_up {
+        assert_eq!(bucket[0].as_u64().unwrap() % 3600000, 0);
+        assert_close(bucket[1].as_f64().unwrap(), 1000.0);
+    }
+
+    let unknown = sunny.get("/api/v1/rollups/unknown/0/1").await;
+    assert_eq!(unknown.status(), reqwest::StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn pages_through_values() {
+    let sunny = TestInstance::start("e2e-paging", FLOW, TestOptions::default()).await;
+    sunny.wait_for_values(8).await;
+
+    // values keep coming in, so page through a fixed range
+    let all = sunny.all_values().await;
+    let end = last_time(&all);
+
+    let mut paged = Vec::new();
+    let mut cursor = None;
+    loop {
+        let path = match cursor {
+            Some(cursor) => format!("/api/v1/values/0/{}?limit=3&cursor={}", end, cursor),
+            None => format!("/api/v1/values/0/{}?limit=3", end),
+        };
+        let response = sunny.get(&path).await;
+        cursor = response
+            .headers()
+            .get("x-next-cursor")
+            .map(|c| c.to_str().unwrap().to_owned());
+        let page: serde_json::Value = response.json().await.unwrap();
+        let page = page.as_array().unwrap().clone();
+        assert!(page.len() <= 3);
+        paged.extend(page);
+        if cursor.is_none() {
+            break;
+        }
+    }
+    assert_eq!(paged, all);
+}
+
+#[tokio::test]
+async fn smooths_values_with_a_moving_average() {
+    let sunny = TestInstance::start("e2e-smoothing", FLOW, TestOptions::default()).await;
+    sunny.wait_for_values(4).await;
+    sunny.inverter.set_flow(Some(MockPowerFlow {
+        p_pv: 500.0,
+        ..FLOW
+    }));
+    sunny.wait_for_values(8).await;
+
+    let end = sunny.last_time().await;
+    let raw = sunny.get_json(&format!("/api/v1/values/0/{}", end)).await;
+    let raw = raw.as_array().unwrap();
+    let pv_average = raw
+        .iter()
+        .map(|value| value[1]["power_pv"].as_f64().unwrap())
+        .sum::<f64>()
+        / raw.len() as f64;
+
+    // a window spanning all values averages each one over all of them
+    let smoothed = sunny
+        .get_json(&format!(
+            "/api/v1/values/0/{}?moving_average_ms=1000000000",
+            end
+        ))
+        .await;
+    let smoothed = smoothed.as_array().unwrap();
+    assert_eq!(smoothed.len(), raw.len());
+    for (value, raw) in smoothed.iter().zip(raw) {
+        assert_eq!(value[0], raw[0]);
+        assert_close(value[1]["power_pv"].as_f64().unwrap(), pv_average);
+    }
+}
+
+#[tokio::test]
+async fn serves_energy_aggregates() {
+    let sunny = TestInstance::start("e2e-energy", FLOW, TestOptions::default()).await;
+    // the aggregates are updated once the first segment of 5 values has been written
+    sunny.wait_for_values(6).await;
+
+    for interval in ["hourly", "daily"] {
+        let energy = sunny
+            .get_json(&format!("/api/v1/energy/{}/0/99999999999999", interval))
+            .await;
+        let energy = energy.as_array().unwrap();
+        assert!(!energy.is_empty());
+        let produced: f64 = energy
+            .iter()
+            .map(|e| e[1]["power_pv"].as_f64().unwrap())
+            .sum();
+        assert!(produced > 0.0);
+    }
+    let unknown = sunny.get("/api/v1/energy/weekly/0/1").await;
+    assert_eq!(unknown.status(), reqwest::StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn aggregates_values_per_calendar_bucket() {
+    let sunny = TestInstance::start("e2e-aggregate", FLOW, TestOptions::default()).await;
+    sunny.wait_for_values(4).await;
+
+    let end = sunny.last_time().await;
+    let stats = sunny
+        .get_json(&format!("/api/v1/values-with-stats/0/{}", end))
+        .await;
+
+    let daily = sunny
+        .get_json(&format!("/api/v1/aggregate/day/0/{}", end))
+        .await;
+    let energy: f64 = daily
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|day| day[1]["power_pv"].as_f64().unwrap())
+        .sum();
+    assert_close(energy, stats["energy_kwh"]["power_pv"].as_f64().unwrap());
+
+    let maxes = sunny
+        .get_json(&format!("/api/v1/aggregate/month/0/{}?aggregate=max", end))
+        .await;
+    assert_expected_values(&maxes[0][1]);
+    let invalid = sunny.get("/api/v1/aggregate/year/0/1").await;
+    assert_eq!(invalid.status(), reqwest::StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn integrates_with_the_selected_method() {
+    let sunny = TestInstance::start("e2e-integration", FLOW, TestOptions::default()).await;
+    sunny.wait_for_values(4).await;
+
+    let all = sunny.all_values().await;
+    let end = last_time(&all);
+    let trapezoidal = sunny
+        .get_json(&format!("/api/v1/values-with-stats/0/{}", end))
+        .await;
+    // the fake inverter reports the same values throughout, so every method agrees
+    for method in ["simpson", "left_riemann", "right_riemann"] {
+        let stats = sunny
+            .get_json(&format!(
+                "/api/v1/values-with-stats/0/{}?integration={}",
+                end, method
+            ))
+            .await;
+        assert_close(
+            stats["energy_kwh"]["power_pv"].as_f64().unwrap(),
+            trapezoidal["energy_kwh"]["power_pv"].as_f64().unwrap(),
+        );
+    }
+    assert_eq!(trapezoidal["excluded_ms"], 0);
+    // the values are sampled more often than every minute, but not every ms
+    let within = sunny
+        .get_json(&format!(
+            "/api/v1/values-with-stats/0/{}?max_gap_ms=60000",
+            end
+        ))
+        .await;
+    assert_eq!(within["energy_kwh"], trapezoidal["energy_kwh"]);
+    let skipped = sunny
+        .get_json(&format!("/api/v1/values-with-stats/0/{}?max_gap_ms=0", end))
+        .await;
+    assert_eq!(skipped["energy_kwh"]["power_pv"], 0.0);
+    assert_eq!(skipped["average"], serde_json::Value::Null);
+    let first = all[0][0].as_u64().unwrap();
+    assert_eq!(skipped["excluded_ms"].as_u64().unwrap(), end - first);
+    // starting between two values, the part of the interval within the range counts as well
+    let middle = (first + all[1][0].as_u64().unwrap()) / 2;
+    let interpolated = sunny
+        .get_json(&format!("/api/v1/values-with-stats/{}/{}", middle, end))
+        .await;
+    assert_eq!(interpolated["values"][0][0], middle);
+    let stored = sunny
+        .get_json(&format!(
+            "/api/v1/values-with-stats/{}/{}?interpolate_boundaries=false",
+            middle, end
+        ))
+        .await;
+    assert_eq!(stored["values"][0], all[1]);
+    assert_close(
+        interpolated["energy_kwh"]["power_pv"].as_f64().unwrap(),
+        trapezoidal["energy_kwh"]["power_pv"].as_f64().unwrap() * (end - middle) as f64
+            / (end - first) as f64,
+    );
+    let unknown = sunny
+        .get("/api/v1/values-with-stats/0/1?integration=midpoint")
+        .await;
+    assert_eq!(unknown.status(), reqwest::StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn serves_the_energy_produced_so_far() {
+    let sunny = TestInstance::start("e2e-cumulative", FLOW, TestOptions::default()).await;
+    sunny.wait_for_values(4).await;
+
+    let all = sunny.all_values().await;
+    let end = last_time(&all);
+    let stats = sunny
+        .get_json(&format!("/api/v1/values-with-stats/0/{}", end))
+        .await;
+
+    let cumulative = sunny
+        .get_json(&format!("/api/v1/cumulative-energy/0/{}", end))
+        .await;
+    let cumulative = cumulative.as_array().unwrap();
+    assert_eq!(cumulative.len(), all.len());
+    assert_eq!(cumulative[0][1]["power_pv"].as_f64().unwrap(), 0.0);
+    let totals: Vec<f64> = cumulative
+        .iter()
+        .map(|value| value[1]["power_pv"].as_f64().unwrap())
+        .collect();
+    assert!(totals.windows(2).all(|pair| pair[0] <= pair[1]));
+    assert_close(
+        totals[totals.len() - 1],
+        stats["energy_kwh"]["power_pv"].as_f64().unwrap(),
+    );
+
+    // maintained while inserting, and more values may have come in since
+    let today = sunny.get_json("/api/v1/statistics/today").await;
+    assert!(today["values"].as_u64().unwrap() >= all.len() as u64);
+    assert_expected_values(&today["mean"]);
+    assert_expected_values(&today["max"]);
+    assert!(today["last_time"].as_u64().unwrap() >= end);
+    assert!(
+        today["energy_kwh"]["power_pv"].as_f64().unwrap()
+            >= stats["energy_kwh"]["power_pv"].as_f64().unwrap() - 1e-9
+    );
+}
+
+#[tokio::test]
+async fn estimates_the_baseline_load_at_night() {
+    let night = MockPowerFlow {
+        p_pv: 0.0,
+        p_grid: 180.0,
+        p_load: -180.0,
+    };
+    let sunny = TestInstance::start("e2e-baseline", night, TestOptions::default()).await;
+    sunny.wait_for_values(4).await;
+
+    let baselines = sunny.get_json("/api/v1/baseline/0/99999999999999").await;
+    let today = &baselines.as_array().unwrap()[0];
+    assert_close(today["baseline_w"].as_f64().unwrap(), 180.0);
+    assert!(today["night_values"].as_u64().unwrap() >= 4);
+}
+
+#[tokio::test]
+async fn answers_queries_without_values() {
+    let sunny = TestInstance::start("e2e-empty", FLOW, TestOptions::default()).await;
+
+    let values = sunny.get_json("/api/v1/values/0/1").await;
+    assert_eq!(values, serde_json::json!([]));
+    let with_stats = sunny.get_json("/api/v1/values-with-stats/0/1").await;
+    assert_eq!(with_stats["values"], serde_json::json!([]));
+    assert!(with_stats["average"].is_null());
+    assert!(with_stats["mins"].is_null());
+    assert!(with_stats["maxes"].is_null());
+    assert!(with_stats["p95"].is_null());
+    assert!(with_stats["std_dev"].is_null());
+    assert!(with_stats["energy_kwh"].is_null());
+    assert_eq!(with_stats["quality"]["measured"], 0);
+
+    let options = TestOptions {
+        config: Config::from_toml("[api]\nempty_response = \"no_content\"").unwrap(),
+        ..TestOptions::default()
+    };
+    let sunny = TestInstance::start("e2e-no-content", FLOW, options).await;
+    for path in ["/api/v1/values/0/1", "/api/v1/values-with-stats/0/1"] {
+        let response = sunny.get(path).await;
+        assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+    }
+}
+
+#[tokio::test]
+async fn serves_live_samples_at_the_fetch_rate() {
+    let options = TestOptions {
+        average_over: 5,
+        ..TestOptions::default()
+    };
+    let sunny = TestInstance::start("e2e-live", FLOW, options).await;
+    sunny.wait_for_values(2).await;
+
+    // every sample fetched is live, five of them make a stored value
+    let stored = sunny.all_values().await;
+    let live = sunny.get_json("/api/v1/live").await;
+    let live = live.as_array().unwrap();
+    assert!(live.len() >= 5 * stored.len());
+    assert_expected_values(&live[0][1]);
+
+    let last = live[live.len() - 1][0].as_u64().unwrap();
+    let newer = sunny
+        .get_json(&format!("/api/v1/live?after={}", last))
+        .await;
+    assert!(newer
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|sample| sample[0].as_u64().unwrap() > last));
+}
+
+#[tokio::test]
+async fn long_polls_for_next_values() {
+    let sunny = TestInstance::start("e2e-next", FLOW, TestOptions::default()).await;
+    sunny.wait_for_values(1).await;
+
+    // blocks until the next value arrives
+    let next = sunny.get_json("/api/v1/next?timeout=5s").await;
+    let next = next.as_array().unwrap();
+    assert!(!next.is_empty());
+    assert_expected_values(&next[0][1]);
+
+    // values newer than `after` that are there already are returned right away
+    let after = next[0][0].as_u64().unwrap() - 1;
+    let already_there = sunny
+        .get_json(&format!("/api/v1/next?after={}", after))
+        .await;
+    assert!(!already_there.as_array().unwrap().is_empty());
+
+    let timed_out = sunny
+        .get("/api/v1/next?after=99999999999999&timeout=50ms")
+        .await;
+    assert_eq!(timed_out.status(), reqwest::StatusCode::NO_CONTENT);
+    let invalid = sunny.get("/api/v1/next?timeout=1h").await;
+    assert_eq!(invalid.status(), reqwest::StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn streams_new_values_as_server_sent_events() {
+    let sunny = TestInstance::start("e2e-stream", FLOW, TestOptions::default()).await;
+    let mut stream = sunny.get("/api/v1/stream").await;
+    assert_eq!(stream.headers()["content-type"], "text/event-stream");
+
+    // the events are pushed as the values are fetched, without polling
+    let mut received = String::new();
+    let read = async {
+        while received.matches("event: values").count() < 2 {
+            let chunk = stream.chunk().await.unwrap().unwrap();
+            received += std::str::from_utf8(&chunk).unwrap();
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(10), read)
+        .await
+        .unwrap();
+    let data = received
+        .lines()
+        .find_map(|line| line.strip_prefix("data: "))
+        .unwrap();
+    let value: serde_json::Value = serde_json::from_str(data).unwrap();
+    assert_expected_values(&value[1]);
+    let stored = sunny.get_json("/values/0/99999999999999").await;
+    assert!(stored
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|stored| stored[0] == value[0]));
+}
+
+type WebSocket =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// the JSON messages received over the WebSocket up to the first one of the given type
+async fn receive_until(socket: &mut WebSocket, kind: &str) -> Vec<serde_json::Value> {
+    use futures_util::StreamExt;
+    use tokio_tungstenite::tungstenite::Message;
+
+    let mut messages = Vec::new();
+    while !messages
+        .last()
+        .is_some_and(|m: &serde_json::Value| m["type"] == kind)
+    {
+        let message = tokio::time::timeout(Duration::from_secs(10), socket.next());
+        if let Message::Text(text) = message.await.unwrap().unwrap().unwrap() {
+            messages.push(serde_json::from_str(&text).unwrap());
+        }
+    }
+    messages
+}
+
+#[tokio::test]
+async fn pushes_subscribed_channels_over_websocket() {
+    use futures_util::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::Message;
+
+    let sunny = TestInstance::start("e2e-websocket", FLOW, TestOptions::default()).await;
+    let url = sunny.url("/api/v1/ws").replacen("http", "ws", 1);
+    let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+
+    let subscribe = r#"{"type": "subscribe", "channels": ["samples", "stats"]}"#;
+    socket.send(Message::Text(subscribe.into())).await.unwrap();
+    let subscribed = receive_until(&mut socket, "subscribed").await;
+    assert_eq!(subscribed.len(), 1);
+    assert_eq!(
+        subscribed[0]["channels"],
+        serde_json::json!(["samples", "stats"])
+    );
+    assert_eq!(subscribed[0]["stats_interval_secs"], 60);
+    // the stats are sent right away, the samples as they're fetched
+    let stats = receive_until(&mut socket, "stats").await;
+    assert!(stats.last().unwrap()["today"]["values"].as_u64().is_some());
+    let samples = receive_until(&mut socket, "sample").await;
+    let sample = samples.last().unwrap();
+    assert_expected_values(&sample["values"]);
+    // they're the samples as fetched, not the averages written to the database
+    let live = sunny.get_json("/api/v1/live").await;
+    assert!(live
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|live_sample| live_sample[0] == sample["time"]));
+
+    let unsubscribe = r#"{"type": "unsubscribe", "channels": ["samples"]}"#;
+    socket
+        .send(Message::Text(unsubscribe.into()))
+        .await
+        .unwrap();
+    receive_until(&mut socket, "subscribed").await;
+    let invalid = r#"{"type": "subscribe", "channels": ["prices"]}"#;
+    socket.send(Message::Text(invalid.into())).await.unwrap();
+    let error = receive_until(&mut socket, "error").await;
+    assert!(error.last().unwrap()["message"]
+        .as_str()
+        .unwrap()
+        .contains("prices"));
+    // the stats keep their interval instead of being sent again after every message
+    let next = tokio::time::timeout(Duration::from_secs(1), socket.next()).await;
+    assert!(next.is_err(), "unexpected message {:?}", next);
+}
+
+#[tokio::test]
+async fn websockets_count_toward_the_connection_limit() {
+    let options = TestOptions {
+        config: Config::from_toml("[server]\nmax_connections = 1").unwrap(),
+        ..TestOptions::default()
+    };
+    let sunny = TestInstance::start("e2e-websocket-limit", FLOW, options).await;
+    let url = sunny.url("/api/v1/ws").replacen("http", "ws", 1);
+    let (socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_millis(500))
+        .build()
+        .unwrap();
+    let blocked = client.get(sunny.url("/api/v1/version")).send().await;
+    assert!(blocked.is_err());
+
+    drop(socket);
+    let version = reqwest::Client::new()
+        .get(sunny.url("/api/v1/version"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(version.status(), reqwest::StatusCode::OK);
+}
+
+#[tokio::test]
+async fn serves_websockets_on_a_standby() {
+    use clap::Parser;
+    use futures_util::SinkExt;
+    use tokio_tungstenite::tungstenite::Message;
+
+    let sunny = TestInstance::start("e2e-standby", FLOW, TestOptions::default()).await;
+    // the segments hold 5 values each
+    sunny.wait_for_values(10).await;
+
+    let replica_home = sunny.sunny_home.to_str().unwrap();
+    let cli = crate::Cli::parse_from(["sunny", "standby", "--replica-home", replica_home]);
+    let Some(crate::Command::Standby(args)) = cli.command else {
+        panic!("not parsed as standby: {:?}", cli);
+    };
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(crate::standby::serve(
+        args,
+        listener,
+        std::future::pending(),
+    ));
+
+    let url = format!("ws://{}/api/v1/ws", address);
+    let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+    let subscribe = r#"{"type": "subscribe", "channels": ["stats"]}"#;
+    socket.send(Message::Text(subscribe.into())).await.unwrap();
+    let subscribed = receive_until(&mut socket, "subscribed").await;
+    assert_eq!(
+        subscribed.last().unwrap()["channels"],
+        serde_json::json!(["stats"])
+    );
+    receive_until(&mut socket, "stats").await;
+
+    // re-scanning would stop right away
+    let never_rescanned = crate::Cli::try_parse_from([
+        "sunny",
+        "standby",
+        "--replica-home",
+        replica_home,
+        "--rescan-interval",
+        "0",
+    ]);
+    assert!(never_rescanned.is_err());
+}
+
+#[tokio::test]
+async fn mirrors_segments_incrementally() {
+    let sunny = TestInstance::start("e2e-sync", FLOW, TestOptions::default()).await;
+    // the segments hold 5 values each
+    sunny.wait_for_values(10).await;
+
+    let changes = sunny.get_json("/api/v1/sync/segments").await;
+    let segments = changes["segments"].as_array().unwrap();
+    assert!(!segments.is_empty());
+    let first = &segments[0];
+    assert_eq!(first["change"], "added");
+    let download = sunny.get(first["url"].as_str().unwrap()).await;
+    assert_eq!(download.status(), reqwest::StatusCode::OK);
+    let data = download.bytes().await.unwrap();
+    assert_eq!(data.len() as u64, first["size"].as_u64().unwrap());
+
+    // asking for the changes after the newest entry only returns later ones
+    let seq = changes["seq"].as_u64().unwrap();
+    let later = sunny
+        .get_json(&format!("/api/v1/sync/segments?since={}", seq))
+        .await;
+    let later = later["segments"].as_array().unwrap();
+    assert!(later.iter().all(|s| s["seq"].as_u64().unwrap() > seq));
+
+    let missing = sunny.get("/api/v1/sync/segments/1-2").await;
+    assert_eq!(missing.status(), reqwest::StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn converts_source_units() {
+    let config = Config::from_toml(
+        r#"
+        [source]
+        pv = "kW"
+        grid = { unit = "A", voltage = 200 }
+        "#,
+    )
+    .unwrap();
+    let options = TestOptions {
+        config,
+        ..TestOptions::default()
+    };
+    let sunny = TestInstance::start("e2e-units", FLOW, options).await;
+    sunny.wait_for_values(1).await;
+
+    let values = sunny.get_json("/api/v1/values/0/99999999999999").await;
+    let values: PowerValues = serde_json::from_value(values[0][1].clone()).unwrap();
+    assert_close(values.power_pv, 3e6);
+    assert_close(values.power_to_grid, 2e5);
+    assert_close(values.power_used, 2000.0);
+}
+
+#[tokio::test]
+async fn rounds_values_to_configured_precision() {
+    let flow = MockPowerFlow {
+        p_pv: 3000.123,
+        ..FLOW
+    };
+    let options = TestOptions {
+        config: Config::from_toml("[api.precision]\npower_pv = 1").unwrap(),
+        ..TestOptions::default()
+    };
+    let sunny = TestInstance::start("e2e-precision", flow, options).await;
+    sunny.wait_for_values(2).await;
+
+    let values = sunny.get_json("/api/v1/values/0/99999999999999").await;
+    assert_eq!(values[0][1]["power_pv"].as_f64(), Some(3000.1));
+    // other fields keep their precision
+    assert_close(values[0][1]["power_used"].as_f64().unwrap(), 2000.0);
+    let with_stats = sunny
+        .get_json("/api/v1/values-with-stats/0/99999999999999")
+        .await;
+    assert_eq!(with_stats["maxes"]["power_pv"].as_f64(), Some(3000.1));
+
+    let full = sunny
+        .get_json("/api/v1/values/0/99999999999999?precision=full")
+        .await;
+    assert_eq!(full[0][1]["power_pv"].as_f64(), Some(3000.123));
+}
+
+#[tokio::test]
+async fn disconnects_slow_and_idle_clients() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let config =
+        "[server]\nheader_read_timeout_secs = 1\nidle_timeout_secs = 1\nmax_body_bytes = 16";
+    let options = TestOptions {
+        config: Config::from_toml(config).unwrap(),
+        ..TestOptions::default()
+    };
+    let sunny = TestInstance::start("e2e-slow-clients", FLOW, options).await;
+    sunny.wait_for_values(1).await;
+
+    // the server hangs up on connections that stay silent or never finish their headers
+    let closed = |mut stream: tokio::net::TcpStream| async move {
+        let mut buffer = Vec::new();
+        tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut buffer))
+            .await
+            .is_ok()
+    };
+    let idle = tokio::net::TcpStream::connect(sunny.address).await.unwrap();
+    assert!(closed(idle).await);
+    let mut slow = tokio::net::TcpStream::connect(sunny.address).await.unwrap();
+    slow.write_all(b"GET /api/v1/values/0/1 HTTP/1.1\r\nHost: sunny\r\n")
+        .await
+        .unwrap();
+    assert!(closed(slow).await);
+
+    // nor do they wait for bodies that are trickled in, which would take 8s here
+    let trickling = tokio::net::TcpStream::connect(sunny.address).await.unwrap();
+    let (mut reader, mut writer) = trickling.into_split();
+    writer
+        .write_all(
+            b"POST /api/v1/values/batch HTTP/1.1\r\nHost: sunny\r\n\
+              Content-Type: application/json\r\nContent-Length: 16\r\n\r\n",
+        )
+        .await
+        .unwrap();
+    let trickle = tokio::spawn(async move {
+        for _ in 0..16 {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            if writer.write_all(b" ").await.is_err() {
+                break;
+            }
+        }
+    });
+    let mut buffer = Vec::new();
+    let answered = tokio::time::timeout(Duration::from_secs(5), reader.read_to_end(&mut buffer));
+    assert!(answered.await.is_ok());
+    trickle.abort();
+
+    // long polls outlast the idle timeout
+    let next = sunny
+        .get("/api/v1/next?after=99999999999999&timeout=2s")
+        .await;
+    assert_eq!(next.status(), reqwest::StatusCode::NO_CONTENT);
+
+    let too_large = reqwest::Client::new()
+        .post(sunny.url("/api/v1/values/0/1"))
+        .body(vec![0; 1024])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(too_large.status(), reqwest::StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn computes_statistics_in_background_jobs() {
+    let sunny = TestInstance::start("e2e-jobs", FLOW, TestOptions::default()).await;
+    sunny.wait_for_values(8).await;
+    let end_time = {
+        let db = sunny.db_lock.read().await;
+        db.get_all_values().unwrap().get_end_time().unwrap()
+    };
+
+    let started = reqwest::Client::new()
+        .post(sunny.url("/api/v1/jobs/stats"))
+        .json(&serde_json::json!({"start_time": 0, "end_time": end_time}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(started.status(), reqwest::StatusCode::ACCEPTED);
+    let id = started.json::<serde_json::Value>().await.unwrap()["id"]
+        .as_u64()
+        .unwrap();
+
+    let job = loop {
+        let job = sunny.get_json(&format!("/api/v1/jobs/{}", id)).await;
+        if job["state"] == "done" {
+            break job;
+        }
+        assert_eq!(job["state"], "running");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+    let stats = sunny
+        .get_json(&format!("/api/v1/values-with-stats/0/{}", end_time))
+        .await;
+    for field in ["average", "mins", "maxes", "std_dev", "energy_kwh"] {
+        for (name, value) in stats[field].as_object().unwrap() {
+            let computed = job["result"][field][name].as_f64().unwrap();
+            assert!(
+                (computed - value.as_f64().unwrap()).abs() < 1e-12,
+                "{}.{}",
+                field,
+                name
+            );
+        }
+    }
+    assert_eq!(job["result"]["quality"], stats["quality"]);
+    assert_expected_values(&job["result"]["average"]);
+
+    let unknown = sunny.get("/api/v1/jobs/12345").await;
+    assert_eq!(unknown.status(), reqwest::StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn samples_less_often_while_values_are_stable() {
+    let mut config = Config::default();
+    config.sampling.adaptive = true;
+    config.sampling.min_interval_ms = 10;
+    config.sampling.max_interval_ms = 160;
+    let options = TestOptions {
+        config,
+        ..TestOptions::default()
+    };
+    let sunny = TestInstance::start("e2e-adaptive", FLOW, options).await;
+
+    // 10, 20, 40, 80 and then 160 ms between samples of the constant flow
+    tokio::time::sleep(Duration::from_millis(1000)).await;
+    let stable = sunny.inverter.requests();
+    assert!((4..=15).contains(&stable), "{} requests", stable);
+
+    // a change brings the pace back to the minimum
+    sunny.inverter.set_flow(Some(MockPowerFlow {
+        p_pv: 500.0,
+        ..FLOW
+    }));
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let values = sunny.get_json("/values/0/99999999999999").await;
+    let times: Vec<u64> = values
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|value| value[0].as_u64().unwrap())
+        .collect();
+    let gaps: Vec<u64> = times.windows(2).map(|pair| pair[1] - pair[0]).collect();
+    // every two samples are averaged into a value, so stable values are at least 320 ms apart
+    assert!(gaps.iter().any(|gap| *gap >= 300), "{:?}", gaps);
+    assert!(
+        gaps.iter().rev().take(3).any(|gap| *gap < 200),
+        "{:?}",
+        gaps
+    );
+}
+
+#[tokio::test]
+async fn requires_an_api_key_if_configured() {
+    let options = TestOptions {
+        config: Config::from_toml("[auth]\nmode = \"api_key\"\napi_keys = [\"secret\"]").unwrap(),
+        ..TestOptions::default()
+    };
+    let sunny = TestInstance::start("e2e-api-key", FLOW, options).await;
+    sunny.wait_for_values(1).await;
+
+    let anonymous = sunny.get("/api/v1/values/0/99999999999999").await;
+    assert_eq!(anonymous.status(), reqwest::StatusCode::UNAUTHORIZED);
+    assert_eq!(anonymous.headers()["www-authenticate"], "Bearer");
+
+    let client = reqwest::Client::new();
+    let request = |token: &str| {
+        client
+            .get(sunny.url("/api/v1/values/0/99999999999999"))
+            .bearer_auth(token)
+            .send()
+    };
+    assert_eq!(
+        request("guess").await.unwrap().status(),
+        reqwest::StatusCode::UNAUTHORIZED
+    );
+    let authorized = request("secret").await.unwrap();
+    assert!(authorized.status().is_success());
+    let values: serde_json::Value = authorized.json().await.unwrap();
+    assert!(!values.as_array().unwrap().is_empty());
+
+    assert!(Config::from_toml("[auth]\nmode = \"jwt\"").is_err());
+}
+
+#[tokio::test]
+async fn serves_the_status_to_holders_of_share_tokens() {
+    let config = "[auth]\nmode = \"api_key\"\napi_keys = [\"secret\"]\n\
+                  [status]\nenabled = true\nshare_tokens = [\"share\"]\nrequests_per_minute = 2";
+    let options = TestOptions {
+        config: Config::from_toml(config).unwrap(),
+        ..TestOptions::default()
+    };
+    let sunny = TestInstance::start("e2e-status", FLOW, options).await;
+    sunny.wait_for_values(2).await;
+
+    let anonymous = sunny.get("/status.json").await;
+    assert_eq!(anonymous.status(), reqwest::StatusCode::UNAUTHORIZED);
+
+    // the API key isn't needed
+    let shared = sunny.get("/status.json?token=share").await;
+    assert_eq!(shared.status(), reqwest::StatusCode::OK);
+    assert_eq!(shared.headers()["cache-control"], "public, max-age=30");
+    let status: serde_json::Value = shared.json().await.unwrap();
+    assert_expected_values(&status["power"]);
+    assert!(status["today_kwh"]["power_pv"].as_f64().unwrap() > 0.0);
+    // all of the consumption is covered by the PV production
+    assert_close(status["autarky_today"].as_f64().unwrap(), 1.0);
+
+    // rate-limited on its own
+    assert!(sunny
+        .get("/status.json?token=share")
+        .await
+        .status()
+        .is_success());
+    let limited = sunny.get("/status.json?token=share").await;
+    assert_eq!(limited.status(), reqwest::StatusCode::TOO_MANY_REQUESTS);
+    assert!(limited.headers().contains_key("retry-after"));
+
+    assert!(Config::from_toml("[status]\nrequests_per_minute = 0").is_err());
+    // without share tokens, anyone could get around the authorization
+    let unshared = "[auth]\nmode = \"api_key\"\napi_keys = [\"secret\"]\n[status]\nenabled = true";
+    assert!(Config::from_toml(unshared).is_err());
+}
+
+#[tokio::test]
+async fn records_changes_in_the_audit_log() {
+    let options = TestOptions {
+        config: Config::from_toml("[auth]\nmode = \"api_key\"\napi_keys = [\"secret\"]").unwrap(),
+        ..TestOptions::default()
+    };
+    let sunny = TestInstance::start("e2e-audit", FLOW, options).await;
+    let client = reqwest::Client::new();
+
+    let started = client
+        .post(sunny.url("/api/v1/jobs/stats"))
+        .bearer_auth("secret")
+        .json(&serde_json::json!({"start_time": 0, "end_time": 1}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(started.status(), reqwest::StatusCode::ACCEPTED);
+    let batch = client
+        .post(sunny.url("/api/v1/values/batch"))
+        .bearer_auth("secret")
+        .json(&serde_json::json!({"ranges": [{"start_time": 0, "end_time": 1}]}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(batch.status(), reqwest::StatusCode::OK);
+    // an import via the command line, which writes to the same log
+    let audit_log = crate::audit::AuditLog::next_to(sunny.sunny_home.join("db").to_str().unwrap());
+    let parameters = serde_json::json!({ "file": "export.csv" });
+    audit_log.record_import("import-fronius", parameters, &anyhow::Ok(()));
+
+    let audit: serde_json::Value = client
+        .get(sunny.url("/api/v1/admin/audit"))
+        .bearer_auth("secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    // requests to the API aren't recorded, even if they're POSTed
+    let entries = audit.as_array().unwrap();
+    assert_eq!(entries.len(), 1);
+    assert!(entries[0]["actor"].as_str().unwrap().starts_with("cli:"));
+    assert_eq!(entries[0]["action"], "import-fronius");
+    assert_eq!(entries[0]["parameters"]["file"], "export.csv");
+    assert_eq!(entries[0]["outcome"], "ok");
+
+    let anonymous = sunny.get("/api/v1/admin/audit").await;
+    assert_eq!(anonymous.status(), reqwest::StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn prices_energy_at_spot_prices() {
+    // a fake aWATTar API: 100 EUR/MWh, except for 20 EUR/MWh at 3 am
+    let market_data = axum::Router::new().route(
+        "/marketdata",
+        axum::routing::get(
+            |axum::extract::Query(range): axum::extract::Query<HashMap<String, u64>>| async move {
+                let hour = 3_600_000;
+                let data: Vec<serde_json::Value> = (range["start"] / hour..range["end"] / hour)
+                    .map(|h| {
+                        let price = if h % 24 == 3 { 20.0 } else { 100.0 };
+                        serde_json::json!({
+                            "start_timestamp": h * hour,
+                            "end_timestamp": (h + 1) * hour,
+                            "marketprice": price,
+                            "unit": "Eur/MWh",
+                        })
+                    })
+                    .collect();
+                axum::Json(serde_json::json!({"object": "list", "data": data}))
+            },
+        ),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, market_data).await.unwrap();
+    });
+
+    let mut config = Config::default();
+    config.prices.source = PriceSource::Awattar;
+    config.prices.awattar_url = format!("http://{}/marketdata", address);
+    // the costs are in the currency of the prices, not the one shown by the frontend
+    config.frontend.currency = String::from("CHF");
+    let options = TestOptions {
+        config,
+        ..TestOptions::default()
+    };
+    let sunny = TestInstance::start("e2e-prices", FLOW, options).await;
+    sunny.wait_for_values(4).await;
+
+    let fetched = async {
+        loop {
+            let response = sunny.get("/api/v1/prices/0/99999999999999").await;
+            let prices: serde_json::Value = response.json().await.unwrap();
+            if prices.as_array().is_some_and(|prices| !prices.is_empty()) {
+                return prices;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    };
+    let prices = tokio::time::timeout(Duration::from_secs(10), fetched)
+        .await
+        .expect("Timed out waiting for the prices");
+    assert_close(prices[0][1].as_f64().unwrap(), 0.1);
+
+    let cheapest = sunny
+        .get_json("/api/v1/prices/cheapest-hours?hours=1")
+        .await;
+    let cheapest = &cheapest.as_array().unwrap()[0];
+    assert_eq!(
+        cheapest["start"].as_u64().unwrap() % (24 * 3_600_000),
+        3 * 3_600_000
+    );
+    assert_close(cheapest["price"].as_f64().unwrap(), 0.02);
+
+    // feeding in 1 kW, all of it at 0.1 EUR/kWh
+    let costs = sunny.get_json("/api/v1/costs/0/99999999999999").await;
+    let fed_in = costs["energy_to_grid_kwh"].as_f64().unwrap();
+    assert!(fed_in > 0.0);
+    assert_close(costs["revenue"].as_f64().unwrap(), fed_in * 0.1);
+    assert_close(costs["cost"].as_f64().unwrap(), 0.0);
+    assert_eq!(costs["unpriced_kwh"], 0.0);
+    assert_eq!(costs["currency"], "EUR");
+}
+
+#[tokio::test]
+async fn reports_version_and_available_updates() {
+    // a fake GitHub releases API
+    let releases = axum::Router::new().route(
+        "/releases/latest",
+        axum::routing::get(|| async { axum::Json(serde_json::json!({"tag_name": "v99.0.0"})) }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, releases).await.unwrap();
+    });
+
+    let mut config = Config::default();
+    config.updates.check = true;
+    config.updates.url = format!("http://{}/releases/latest", address);
+    let options = TestOptions {
+        config,
+        ..TestOptions::default()
+    };
+    let sunny = TestInstance::start("e2e-version", FLOW, options).await;
+
+    let version = loop {
+        let version = sunny.get_json("/version").await;
+        if !version["latest_release"].is_null() {
+            break version;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+    assert_eq!(version["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(version["latest_release"], "v99.0.0");
+    assert_eq!(version["update_available"], true);
+    let metrics = sunny.get_json("/metrics").await;
+    assert_eq!(metrics["update_available"], true);
+
+    // without the check, nothing is known about releases
+    let sunny = TestInstance::start("e2e-no-version-check", FLOW, TestOptions::default()).await;
+    let version = sunny.get_json("/version").await;
+    assert!(version["latest_release"].is_null());
+    assert_eq!(version["update_available"], false);
+}
@@ -0,0 +1,185 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use sunny_db::timeseries::Resolution;
+use sunny_db::timeseries_db::SunnyDB;
+use tokio::sync::RwLock;
+
+use super::mock_inverter::{MockInverter, MockPowerFlow};
+use crate::config::Config;
+use crate::health::FetcherHealth;
+use crate::live::{Decimator, LiveBuffer};
+use crate::prices::Prices;
+use crate::scheduler::parse_timezone;
+use crate::{build_router, fetch_and_write_values_to_db, AppState, DatabaseReadLock, PowerValues};
+use crate::{long_poll, projection, rollups, server, stream};
+
+/// A complete sunny instance running in-process: a mock inverter, the collector fetching
+/// from it into a fresh database, and the HTTP server on a random local port
+pub struct TestInstance {
+    pub inverter: MockInverter,
+    pub db_lock: Arc<RwLock<SunnyDB<PowerValues>>>,
+    pub address: SocketAddr,
+    pub sunny_home: PathBuf,
+}
+
+pub struct TestOptions {
+    pub granularity: Duration,
+    pub average_over: usize,
+    pub segment_size: usize,
+    pub config: Config,
+}
+
+impl Default for TestOptions {
+    fn default() -> Self {
+        TestOptions {
+            granularity: Duration::from_millis(10),
+            average_over: 2,
+            segment_size: 5,
+            config: Config::default(),
+        }
+    }
+}
+
+impl TestInstance {
+    pub async fn start(name: &str, flow: MockPowerFlow, options: TestOptions) -> Self {
+        let inverter = MockInverter::start(flow).await;
+
+        let sunny_home =
+            std::env::temp_dir().join(format!("sunny-test-{}-{}", name, std::process::id()));
+        std::fs::remove_dir_all(&sunny_home).ok();
+        let sunny_path = sunny_home.to_str().unwrap().to_owned() + "/";
+        let sunny_db =
+            SunnyDB::<PowerValues>::new(options.segment_size, &(sunny_path.clone() + "db"), 2, 0)
+                .unwrap()
+                .with_energy_aggregates()
+                .unwrap();
+        let timezone = parse_timezone(options.config.timezone()).unwrap();
+        let today_start = projection::start_of_day(Resolution::Milliseconds.now(), timezone);
+        let sunny_db = sunny_db.with_running_statistics(today_start);
+        let db_lock = Arc::new(RwLock::new(sunny_db));
+
+        let fetch_lock = Arc::clone(&db_lock);
+        let url = inverter.url();
+        let source = options.config.source.clone();
+        let sampling = options.config.sampling.clone();
+        let (latest_sample_sender, latest_sample) = long_poll::latest_sample();
+        let new_values = stream::new_values();
+        let fetch_new_values = new_values.clone();
+        let live = LiveBuffer::new(options.config.live.buffer_size);
+        let decimator = Decimator::new(options.average_over, live.clone());
+        let fetcher_health = FetcherHealth::new(Instant::now());
+        let fetch_health = fetcher_health.clone();
+        tokio::spawn(async move {
+            fetch_and_write_values_to_db(
+                &fetch_lock,
+                &latest_sample_sender,
+                &fetch_new_values,
+                options.granularity,
+                decimator,
+                url,
+                &source,
+                &sampling,
+                timezone,
+                fetch_health,
+            )
+            .await;
+        });
+
+        let prices = Prices::open(&options.config.prices, &sunny_path).unwrap();
+        prices.start_fetching(timezone);
+
+        let rollups = rollups::parse_rules(&options.config.rollups).unwrap();
+        let state = AppState {
+            db_read_lock: DatabaseReadLock::new(Arc::clone(&db_lock)),
+            resolution: db_lock.read().await.get_resolution(),
+            latest_sample,
+            new_values,
+            live,
+            prices,
+            rollups: Arc::new(std::sync::RwLock::new(rollups)),
+            fetcher_health,
+        };
+        let app = build_router(state, &options.config, &sunny_path);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server_settings = options.config.server.clone();
+        tokio::spawn(async move {
+            server::serve(listener, app, &server_settings, std::future::pending()).await;
+        });
+
+        TestInstance {
+            inverter,
+            db_lock,
+            address,
+            sunny_home,
+        }
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.address, path)
+    }
+
+    pub async fn get(&self, path: &str) -> reqwest::Response {
+        reqwest::get(self.url(path)).await.unwrap()
+    }
+
+    pub async fn get_json(&self, path: &str) -> serde_json::Value {
+        let response = self.get(path).await;
+        assert!(
+            response.status().is_success(),
+            "GET {} failed with {}",
+            path,
+            response.status()
+        );
+        response.json().await.unwrap()
+    }
+
+    /// all the values served so far, as `[time, values]` pairs
+    pub async fn all_values(&self) -> Vec<serde_json::Value> {
+        match self.get_json("/api/v1/values/0/99999999999999").await {
+            serde_json::Value::Array(values) => values,
+            other => panic!("Expected an array of values, got {}", other),
+        }
+    }
+
+    /// the time of the latest value served so far
+    pub async fn last_time(&self) -> u64 {
+        last_time(&self.all_values().await)
+    }
+
+    /// waits until the database holds at least `n` values (in memory and on disk)
+    pub async fn wait_for_values(&self, n: usize) {
+        let wait = async {
+            loop {
+                let count = self
+                    .db_lock
+                    .read()
+                    .await
+                    .get_all_values()
+                    .map(|ts| ts.len())
+                    .unwrap_or(0);
+                if count >= n {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(10), wait)
+            .await
+            .unwrap_or_else(|_| panic!("Timed out waiting for {} values", n));
+    }
+}
+
+impl Drop for TestInstance {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.sunny_home).ok();
+    }
+}
+
+/// the time of the last of the `[time, values]` pairs
+pub fn last_time(values: &[serde_json::Value]) -> u64 {
+    let last = values.last().expect("no values served yet");
+    last[0].as_u64().unwrap()
+}
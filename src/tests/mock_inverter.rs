@@ -0,0 +1,74 @@
+use axum::{extract::State, Json};
+use serde_json::json;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+/// The power flow reported by the mock inverter, using the inverter's sign conventions:
+/// `p_grid` is negative when feeding into the grid and `p_load` is always negative
+#[derive(Clone, Copy, Debug)]
+pub struct MockPowerFlow {
+    pub p_pv: f64,
+    pub p_grid: f64,
+    pub p_load: f64,
+}
+
+#[derive(Default)]
+struct MockState {
+    flow: Option<MockPowerFlow>,
+    requests: usize,
+}
+
+/// A fake inverter serving `GET /status/powerflow` on a random local port
+pub struct MockInverter {
+    address: SocketAddr,
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockInverter {
+    pub async fn start(flow: MockPowerFlow) -> Self {
+        let state = Arc::new(Mutex::new(MockState {
+            flow: Some(flow),
+            requests: 0,
+        }));
+
+        let app = axum::Router::new()
+            .route("/status/powerflow", axum::routing::get(get_power_flow))
+            .with_state(Arc::clone(&state));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        MockInverter { address, state }
+    }
+
+    /// the address as expected by the `--url` argument
+    pub fn url(&self) -> String {
+        self.address.to_string()
+    }
+
+    /// changes the reported power flow; `None` makes the inverter respond with garbage
+    pub fn set_flow(&self, flow: Option<MockPowerFlow>) {
+        self.state.lock().unwrap().flow = flow;
+    }
+
+    pub fn requests(&self) -> usize {
+        self.state.lock().unwrap().requests
+    }
+}
+
+async fn get_power_flow(State(state): State<Arc<Mutex<MockState>>>) -> Json<serde_json::Value> {
+    let mut state = state.lock().unwrap();
+    state.requests += 1;
+    match state.flow {
+        Some(flow) => Json(json!({
+            "site": {
+                "P_PV": flow.p_pv,
+                "P_Grid": flow.p_grid,
+                "P_Load": flow.p_load,
+            }
+        })),
+        None => Json(json!({ "site": {} })),
+    }
+}
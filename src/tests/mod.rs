@@ -0,0 +1,3 @@
+mod e2e;
+pub mod harness;
+pub mod mock_inverter;
@@ -0,0 +1,49 @@
+use serde_json::json;
+use sunny_db::codec::SegmentEncoding;
+use sunny_db::timeseries_db::SunnyDB;
+
+use crate::{load_encryption_key, PowerValues};
+
+#[derive(clap::Args, Debug)]
+pub struct VerifyArgs {
+    // Database directory, e.g. <sunny-home>/db
+    #[arg(long)]
+    data_dir: String,
+
+    // Cold storage directory, if archived segments are moved to one
+    #[arg(long)]
+    cold_dir: Option<String>,
+
+    // Optional file holding the 32 byte key segments are encrypted with
+    #[arg(long)]
+    key_file: Option<String>,
+}
+
+/// checks the database and prints a JSON report; returns whether everything is fine
+pub fn run(args: &VerifyArgs) -> anyhow::Result<bool> {
+    // opening read-only works while sunny is running and doesn't repair anything on the way
+    let mut db = SunnyDB::<PowerValues>::open_read_only(&args.data_dir)?
+        .with_segment_encoding(SegmentEncoding::default())?;
+    if let Some(key) = load_encryption_key(args.key_file.as_deref())? {
+        db = db.with_encryption_key(key)?;
+    }
+    if let Some(cold_dir) = &args.cold_dir {
+        db = db.with_cold_storage(cold_dir)?;
+    }
+
+    let report = db.verify();
+    let issues: Vec<_> = report
+        .issues
+        .iter()
+        .map(|issue| json!({ "kind": issue.kind(), "message": issue.to_string() }))
+        .collect();
+    let json = json!({
+        "ok": report.is_ok(),
+        "segments": report.segments,
+        "values": report.values,
+        "segments_without_checksum": report.segments_without_checksum,
+        "issues": issues,
+    });
+    println!("{}", serde_json::to_string_pretty(&json)?);
+    Ok(report.is_ok())
+}
@@ -0,0 +1,135 @@
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::config::UpdateSettings;
+
+/// Version of this build as set in Cargo.toml
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Commit this build was made from, set by the build scripts via the `SUNNY_GIT_HASH`
+/// environment variable
+pub const GIT_HASH: Option<&str> = option_env!("SUNNY_GIT_HASH");
+
+/// What's served via `GET /version`
+#[derive(Serialize, Debug)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub git_hash: Option<&'static str>,
+    pub features: Vec<&'static str>,
+    /// newest release found by the update check; None if it's disabled or hasn't succeeded yet
+    pub latest_release: Option<String>,
+    pub update_available: bool,
+}
+
+/// cargo features this build was compiled with
+fn enabled_features() -> Vec<&'static str> {
+    let mut features = Vec::new();
+    if cfg!(feature = "s3") {
+        features.push("s3");
+    }
+    if cfg!(feature = "catalog") {
+        features.push("catalog");
+    }
+    features
+}
+
+/// The newest release found by the periodic check against the releases of the repository;
+/// nothing is downloaded or installed, it's only reported
+#[derive(Clone, Default)]
+pub struct UpdateCheck {
+    latest_release: Arc<Mutex<Option<String>>>,
+}
+
+impl UpdateCheck {
+    /// starts checking for new releases every `interval_hours` if enabled in the settings
+    pub fn start(settings: &UpdateSettings) -> Self {
+        let check = UpdateCheck::default();
+        if !settings.check {
+            return check;
+        }
+
+        let latest_release = Arc::clone(&check.latest_release);
+        let url = settings.url.clone();
+        let interval = Duration::from_secs(settings.interval_hours * 3600);
+        tokio::spawn(async move {
+            let client = reqwest::Client::new();
+            loop {
+                match fetch_latest_release(&client, &url).await {
+                    Ok(release) => *latest_release.lock().unwrap() = Some(release),
+                    Err(e) => println!("Error while checking for a new release: {:#}", e),
+                }
+                tokio::time::sleep(interval).await;
+            }
+        });
+        check
+    }
+
+    pub fn latest_release(&self) -> Option<String> {
+        self.latest_release.lock().unwrap().clone()
+    }
+
+    pub fn update_available(&self) -> bool {
+        self.latest_release()
+            .is_some_and(|release| is_newer(&release, VERSION))
+    }
+
+    pub fn build_info(&self) -> BuildInfo {
+        BuildInfo {
+            version: VERSION,
+            git_hash: GIT_HASH,
+            features: enabled_features(),
+            latest_release: self.latest_release(),
+            update_available: self.update_available(),
+        }
+    }
+}
+
+/// the tag of the latest release as returned by the GitHub releases API
+async fn fetch_latest_release(client: &reqwest::Client, url: &str) -> anyhow::Result<String> {
+    let release = client
+        .get(url)
+        // GitHub rejects requests without a user agent
+        .header(reqwest::header::USER_AGENT, format!("sunny/{}", VERSION))
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<serde_json::Value>()
+        .await?;
+    let tag = release["tag_name"]
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("Release without a tag_name"))?;
+    Ok(tag.to_owned())
+}
+
+/// the numeric parts of a version like `v1.2.3`; anything after them like `-rc1` is ignored
+fn version_parts(version: &str) -> Vec<u64> {
+    version
+        .trim_start_matches('v')
+        .split('.')
+        .map_while(|part| {
+            let digits: String = part.chars().take_while(char::is_ascii_digit).collect();
+            digits.parse().ok()
+        })
+        .collect()
+}
+
+/// whether the release has a higher version than the current one
+fn is_newer(release: &str, current: &str) -> bool {
+    version_parts(release) > version_parts(current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_newer() {
+        assert!(is_newer("v0.2.0", "0.1.0"));
+        assert!(is_newer("0.1.1", "0.1.0"));
+        assert!(is_newer("v1.0", "0.9.12"));
+        assert!(!is_newer("v0.1.0", "0.1.0"));
+        assert!(!is_newer("v0.0.9", "0.1.0"));
+        assert!(!is_newer("nightly", "0.1.0"));
+        assert_eq!(version_parts("v1.2.3-rc1"), vec![1, 2, 3]);
+    }
+}
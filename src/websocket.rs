@@ -0,0 +1,236 @@
+use axum::extract::ws::{Message, WebSocket};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::time::{interval, Interval, MissedTickBehavior};
+
+use crate::config::Precision;
+use crate::live::LiveBuffer;
+use crate::projection::{self, TodaySoFar};
+use crate::server::Connection;
+use crate::stream;
+use crate::{rounded_json, DatabaseReadLock, PowerValues};
+
+/// How often today's statistics are sent unless the client asks for something else
+const DEFAULT_STATS_INTERVAL_SECS: u64 = 60;
+const MAX_STATS_INTERVAL_SECS: u64 = 3600;
+
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    /// every sample as soon as it has been fetched, before it is averaged for storage
+    Samples,
+    /// today's statistics so far, see `GET /statistics/today`
+    Stats,
+}
+
+/// Messages clients send over `/ws`, e.g.
+/// `{"type": "subscribe", "channels": ["samples", "stats"], "stats_interval_secs": 10}`
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum ClientMessage {
+    Subscribe {
+        channels: Vec<Channel>,
+        stats_interval_secs: Option<u64>,
+    },
+    Unsubscribe {
+        channels: Vec<Channel>,
+    },
+}
+
+/// Messages sent to the clients
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ServerMessage {
+    /// the channels subscribed to after a client's message
+    Subscribed {
+        channels: Vec<Channel>,
+        stats_interval_secs: u64,
+    },
+    Sample {
+        time: u64,
+        values: PowerValues,
+    },
+    Stats {
+        today: Option<TodaySoFar>,
+    },
+    /// a message of the client that couldn't be applied
+    Error {
+        message: String,
+    },
+}
+
+/// What a client is subscribed to; nothing until it sends a message
+#[derive(Clone, Debug, PartialEq)]
+pub struct Subscription {
+    pub samples: bool,
+    pub stats: bool,
+    pub stats_interval: Duration,
+}
+
+impl Default for Subscription {
+    fn default() -> Self {
+        Subscription {
+            samples: false,
+            stats: false,
+            stats_interval: Duration::from_secs(DEFAULT_STATS_INTERVAL_SECS),
+        }
+    }
+}
+
+impl Subscription {
+    /// applies a message of the client, leaving the subscription as it was if it's invalid
+    pub fn apply(&mut self, message: &str) -> anyhow::Result<()> {
+        let message: ClientMessage = serde_json::from_str(message)?;
+        let (channels, subscribe) = match message {
+            ClientMessage::Subscribe {
+                channels,
+                stats_interval_secs,
+            } => {
+                if let Some(secs) = stats_interval_secs {
+                    if !(1..=MAX_STATS_INTERVAL_SECS).contains(&secs) {
+                        anyhow::bail!(
+                            "stats_interval_secs has to be between 1 and {}",
+                            MAX_STATS_INTERVAL_SECS
+                        );
+                    }
+                    self.stats_interval = Duration::from_secs(secs);
+                }
+                (channels, true)
+            }
+            ClientMessage::Unsubscribe { channels } => (channels, false),
+        };
+        for channel in channels {
+            match channel {
+                Channel::Samples => self.samples = subscribe,
+                Channel::Stats => self.stats = subscribe,
+            }
+        }
+        Ok(())
+    }
+
+    fn channels(&self) -> Vec<Channel> {
+        [
+            (Channel::Samples, self.samples),
+            (Channel::Stats, self.stats),
+        ]
+        .into_iter()
+        .filter_map(|(channel, subscribed)| subscribed.then_some(channel))
+        .collect()
+    }
+}
+
+/// pushes the channels the client subscribes to until it goes away or the server shuts down;
+/// the connection counts toward `max_connections` until then
+pub async fn serve(
+    mut socket: WebSocket,
+    mut connection: Connection,
+    db_read_lock: DatabaseReadLock,
+    live: LiveBuffer,
+    precision: Precision,
+) {
+    let mut subscription = Subscription::default();
+    let mut samples = Box::pin(stream::subscribe(live.new_samples()));
+    let mut stats: Option<Interval> = None;
+    loop {
+        let message = tokio::select! {
+            received = socket.recv() => match received {
+                Some(Ok(Message::Text(text))) => {
+                    let previous = subscription.clone();
+                    let message = match subscription.apply(&text) {
+                        Ok(()) => ServerMessage::Subscribed {
+                            channels: subscription.channels(),
+                            stats_interval_secs: subscription.stats_interval.as_secs(),
+                        },
+                        Err(e) => ServerMessage::Error {
+                            message: format!("{:#}", e),
+                        },
+                    };
+                    // the first tick comes right away, so new subscribers get the stats at once;
+                    // the interval is kept unless the subscription to them changed
+                    let stats_changed = (subscription.stats, subscription.stats_interval)
+                        != (previous.stats, previous.stats_interval);
+                    if stats_changed {
+                        stats = subscription.stats.then(|| {
+                            let mut stats = interval(subscription.stats_interval);
+                            stats.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                            stats
+                        });
+                    }
+                    Some(message)
+                }
+                // pings are answered by axum
+                Some(Ok(Message::Binary(_) | Message::Ping(_) | Message::Pong(_))) => None,
+                Some(Ok(Message::Close(_)) | Err(_)) | None => return,
+            },
+            _ = connection.shutdown() => {
+                socket.send(Message::Close(None)).await.ok();
+                return;
+            }
+            Some((time, values)) = samples.next() => {
+                subscription.samples.then_some(ServerMessage::Sample { time, values })
+            }
+            _ = async { stats.as_mut().unwrap().tick().await }, if stats.is_some() => {
+                let today = projection::today_so_far(&*db_read_lock.read().await);
+                Some(ServerMessage::Stats { today })
+            }
+        };
+        let Some(message) = message else {
+            continue;
+        };
+        let text = match rounded_json(&message, &precision) {
+            Ok(json) => json.to_string(),
+            Err(e) => {
+                println!("Couldn't serialize WebSocket message: {:#}", e);
+                continue;
+            }
+        };
+        if socket.send(Message::Text(text)).await.is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subscription() {
+        let mut subscription = Subscription::default();
+        subscription
+            .apply(r#"{"type": "subscribe", "channels": ["samples", "stats"]}"#)
+            .unwrap();
+        assert_eq!(
+            subscription.channels(),
+            vec![Channel::Samples, Channel::Stats]
+        );
+        assert_eq!(subscription.stats_interval, Duration::from_secs(60));
+
+        subscription
+            .apply(r#"{"type": "unsubscribe", "channels": ["samples"]}"#)
+            .unwrap();
+        subscription
+            .apply(r#"{"type": "subscribe", "channels": [], "stats_interval_secs": 5}"#)
+            .unwrap();
+        assert_eq!(
+            subscription,
+            Subscription {
+                samples: false,
+                stats: true,
+                stats_interval: Duration::from_secs(5),
+            }
+        );
+
+        // invalid messages don't change anything
+        for invalid in [
+            r#"{"type": "subscribe", "channels": ["prices"]}"#,
+            r#"{"type": "subscribe", "channels": [], "stats_interval_secs": 0}"#,
+            r#"{"type": "listen"}"#,
+            "samples",
+        ] {
+            assert!(subscription.apply(invalid).is_err(), "{}", invalid);
+        }
+        assert_eq!(subscription.channels(), vec![Channel::Stats]);
+    }
+}
@@ -0,0 +1,187 @@
+use crate::codec::Codec;
+use crate::rollup::interval_start;
+use crate::timeseries::TimeSeries;
+use chrono::{DateTime, Datelike, NaiveDate};
+use std::collections::BTreeMap;
+use std::ops::{Add, Mul};
+use std::path::{Path, PathBuf};
+
+/// Name of the directory next to the data directory holding the aggregates
+pub const AGGREGATES_DIR: &str = "aggregates";
+
+/// Pauses between values longer than this (in ms) are treated as gaps and not integrated, so
+/// updating the aggregates of a range only needs the values up to this much around it
+pub const MAX_GAP_MS: u64 = 3600 * 1000;
+
+const MS_PER_HOUR: f64 = 3600.0 * 1000.0;
+
+/// The length of the intervals energy aggregates are maintained for
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AggregateInterval {
+    Hour,
+    /// UTC days
+    Day,
+}
+
+impl AggregateInterval {
+    pub fn millis(self) -> u64 {
+        match self {
+            AggregateInterval::Hour => 3600 * 1000,
+            AggregateInterval::Day => 24 * 3600 * 1000,
+        }
+    }
+
+    /// the file holding the aggregate of the interval starting at `time` (in ms) relative to
+    /// the aggregates directory, together with the range [start, end) it covers; hourly ones
+    /// are stored in a file per month, daily ones in a file per year
+    pub(crate) fn file_of(self, time: u64) -> (PathBuf, u64, u64) {
+        let date = i64::try_from(time)
+            .ok()
+            .and_then(DateTime::from_timestamp_millis)
+            .map(|t| t.date_naive())
+            .unwrap_or(NaiveDate::MAX);
+        let (name, start, next) = match self {
+            AggregateInterval::Hour => {
+                let start = NaiveDate::from_ymd_opt(date.year(), date.month(), 1);
+                let next = match date.month() {
+                    12 => NaiveDate::from_ymd_opt(date.year() + 1, 1, 1),
+                    month => NaiveDate::from_ymd_opt(date.year(), month + 1, 1),
+                };
+                (format!("{}-{:02}", date.year(), date.month()), start, next)
+            }
+            AggregateInterval::Day => (
+                format!("{}", date.year()),
+                NaiveDate::from_ymd_opt(date.year(), 1, 1),
+                NaiveDate::from_ymd_opt(date.year() + 1, 1, 1),
+            ),
+        };
+        let millis = |d: Option<NaiveDate>| {
+            d.and_then(|d| d.and_hms_opt(0, 0, 0))
+                .map(|t| t.and_utc().timestamp_millis().max(0) as u64)
+                .unwrap_or(u64::MAX)
+        };
+        (
+            Path::new(self.directory()).join(name),
+            millis(start),
+            millis(next),
+        )
+    }
+
+    /// the start (in ms) of the range covered by a file named like the ones of `file_of`
+    pub(crate) fn file_start(self, name: &str) -> Option<u64> {
+        let date = match self {
+            AggregateInterval::Hour => {
+                NaiveDate::parse_from_str(&format!("{}-01", name), "%Y-%m-%d")
+            }
+            AggregateInterval::Day => {
+                NaiveDate::parse_from_str(&format!("{}-01-01", name), "%Y-%m-%d")
+            }
+        };
+        let start = date
+            .ok()?
+            .and_hms_opt(0, 0, 0)?
+            .and_utc()
+            .timestamp_millis();
+        u64::try_from(start).ok()
+    }
+
+    /// the directory of the files within the aggregates directory
+    pub(crate) fn directory(self) -> &'static str {
+        match self {
+            AggregateInterval::Hour => "hourly",
+            AggregateInterval::Day => "daily",
+        }
+    }
+}
+
+/// integrates the values of the series into intervals of `interval` ms using the trapezoidal
+/// rule, splitting the trapezoids between two values at the interval boundaries; returns the
+/// energy (in hours times the unit of the values, e.g. Wh for W) of the intervals starting
+/// within [from, to) (in ms) that any values contributed to
+pub(crate) fn energy_per_interval<T>(
+    series: &TimeSeries<T>,
+    interval: u64,
+    from: u64,
+    to: u64,
+) -> BTreeMap<u64, T>
+where
+    T: Codec + Add<Output = T> + Mul<f64, Output = T>,
+{
+    let resolution = series.get_resolution();
+    let mut energy: BTreeMap<u64, T> = BTreeMap::new();
+    let values = || series.iter().map(|(t, v)| (resolution.to_millis(t), *v));
+    for ((t0, v0), (t1, v1)) in values().zip(values().skip(1)) {
+        if t1 <= t0 || t1 - t0 > MAX_GAP_MS {
+            continue;
+        }
+        let at = |t: u64| {
+            let f = (t - t0) as f64 / (t1 - t0) as f64;
+            v0 * (1.0 - f) + v1 * f
+        };
+        let mut a = t0;
+        while a < t1 {
+            let start = interval_start(a, interval);
+            let b = (start + interval).min(t1);
+            if (from..to).contains(&start) {
+                let part = (at(a) + at(b)) * ((b - a) as f64 / 2.0 / MS_PER_HOUR);
+                energy
+                    .entry(start)
+                    .and_modify(|e| *e = *e + part)
+                    .or_insert(part);
+            }
+            a = b;
+        }
+    }
+    energy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_energy_per_interval() {
+        let hour = 3600 * 1000;
+        let mut series = TimeSeries::<f64>::new(5);
+        // 1 kW from 00:30 to 01:30, rising to 2 kW at 02:00; then a gap until 04:00
+        for (time, value) in [
+            (hour / 2, 1000.0),
+            (3 * hour / 2, 1000.0),
+            (2 * hour, 2000.0),
+            (4 * hour + 1, 0.0),
+            (5 * hour, 0.0),
+        ] {
+            series.insert_value_at_time(time, value);
+        }
+
+        let energy = energy_per_interval(&series, hour, 0, 10 * hour);
+        assert_eq!(
+            energy.into_iter().collect::<Vec<_>>(),
+            vec![(0, 500.0), (hour, 500.0 + 750.0), (4 * hour, 0.0)]
+        );
+        // only the intervals within the range
+        let energy = energy_per_interval(&series, hour, hour, 2 * hour);
+        assert_eq!(energy.into_iter().collect::<Vec<_>>(), vec![(hour, 1250.0)]);
+    }
+
+    #[test]
+    fn test_aggregate_files() {
+        // 2024-12-31 23:00 UTC
+        let time = 1735686000000;
+        let (path, start, end) = AggregateInterval::Hour.file_of(time);
+        assert_eq!(path, PathBuf::from("hourly/2024-12"));
+        assert_eq!((start, end), (1733011200000, 1735689600000));
+        let (path, start, end) = AggregateInterval::Day.file_of(time);
+        assert_eq!(path, PathBuf::from("daily/2024"));
+        assert_eq!((start, end), (1704067200000, 1735689600000));
+        assert_eq!(
+            AggregateInterval::Day.file_start("2024"),
+            Some(1704067200000)
+        );
+        assert_eq!(
+            AggregateInterval::Hour.file_start("2024-12"),
+            Some(1733011200000)
+        );
+        assert_eq!(AggregateInterval::Hour.file_start("2024-12.tmp"), None);
+    }
+}
@@ -0,0 +1,292 @@
+use crate::codec::Codec;
+use std::ops::{Add, Mul};
+
+use crate::timeseries::{Quality, TimeSeries};
+
+/// How to obtain values at points of the grid that don't coincide with a measurement
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fill {
+    /// the last value measured at or before the grid point
+    Previous,
+    /// the value measured closest to the grid point; ties go to the earlier one
+    Nearest,
+    /// linear interpolation between the values measured right before and after the grid point
+    Linear,
+}
+
+/// resamples two series onto a common grid of multiples of `interval` (in the timestamp
+/// resolution of `series_a`, to which `series_b` is converted if needed) covering the time
+/// range both series have values in, so they can be combined value by value; the series are
+/// empty if they don't overlap; values that had to be filled in are marked as interpolated
+/// (unless the values they're based on are even less reliable). None for an interval of 0
+pub fn align<A, B>(
+    series_a: &TimeSeries<A>,
+    series_b: &TimeSeries<B>,
+    interval: u64,
+    fill: Fill,
+) -> Option<(TimeSeries<A>, TimeSeries<B>)>
+where
+    A: Codec + Add<Output = A> + Mul<f64, Output = A>,
+    B: Codec + Add<Output = B> + Mul<f64, Output = B>,
+{
+    if interval == 0 {
+        return None;
+    }
+    let converted_b;
+    let series_b = if series_b.get_resolution() != series_a.get_resolution() {
+        converted_b = series_b.to_resolution(series_a.get_resolution());
+        &converted_b
+    } else {
+        series_b
+    };
+
+    let grid = match (
+        series_a.get_start_time().zip(series_a.get_end_time()),
+        series_b.get_start_time().zip(series_b.get_end_time()),
+    ) {
+        (Some((start_a, end_a)), Some((start_b, end_b))) => {
+            grid(start_a.max(start_b), end_a.min(end_b), interval)
+        }
+        _ => Vec::new(),
+    };
+
+    Some((
+        resample_onto(series_a, &grid, fill),
+        resample_onto(series_b, &grid, fill),
+    ))
+}
+
+pub trait Resample<T> {
+    /// resamples the series onto an evenly spaced grid of multiples of `interval_ms` covering
+    /// its time range, e.g. for charting; values are filled in like by `align`. None if the
+    /// interval is shorter than the resolution of the timestamps
+    fn resample(&self, interval_ms: u64, fill: Fill) -> Option<TimeSeries<T>>;
+}
+
+impl<T> Resample<T> for TimeSeries<T>
+where
+    T: Codec + Add<Output = T> + Mul<f64, Output = T>,
+{
+    fn resample(&self, interval_ms: u64, fill: Fill) -> Option<TimeSeries<T>> {
+        let interval = self.get_resolution().from_millis(interval_ms);
+        if interval == 0 {
+            return None;
+        }
+        let grid = match self.get_start_time().zip(self.get_end_time()) {
+            Some((start, end)) => grid(start, end, interval),
+            None => Vec::new(),
+        };
+        Some(resample_onto(self, &grid, fill))
+    }
+}
+
+/// the values of the series within [start, end], with values at exactly `start` and `end`
+/// interpolated linearly from the values around them unless there are values at these times
+/// already, so integrating the result covers the whole range no matter when the values were
+/// sampled. A boundary isn't interpolated without values on both sides of it or if they're
+/// more than `max_gap` apart; the interpolated values are marked as such
+pub fn clip_interpolated<T>(ts: &TimeSeries<T>, start: u64, end: u64, max_gap: u64) -> TimeSeries<T>
+where
+    T: Codec + Add<Output = T> + Mul<f64, Output = T>,
+{
+    let entries = ts.get_current_values_with_quality();
+    let at = |time: u64| {
+        let next = entries.partition_point(|(t, _, _)| *t < time);
+        match (next.checked_sub(1).map(|i| entries[i]), entries.get(next)) {
+            (_, Some(&(t, v, q))) if t == time => Some((v, q)),
+            (Some((t_prev, v_prev, q_prev)), Some(&(t_next, v_next, q_next)))
+                if t_next - t_prev <= max_gap =>
+            {
+                let w = (time - t_prev) as f64 / (t_next - t_prev) as f64;
+                let quality = q_prev.max(q_next).max(Quality::Interpolated);
+                Some((v_prev * (1.0 - w) + v_next * w, quality))
+            }
+            _ => None,
+        }
+    };
+    let inner = entries
+        .iter()
+        .filter(|(time, _, _)| start < *time && *time < end)
+        .copied();
+    let boundary = |time: u64| at(time).map(|(value, quality)| (time, value, quality));
+    let values: Vec<(u64, T, Quality)> = boundary(start)
+        .into_iter()
+        .chain(inner)
+        .chain(boundary(end).filter(|_| end > start))
+        .collect();
+
+    let mut clipped = TimeSeries::<T>::with_resolution(values.len(), ts.get_resolution());
+    for (time, value, quality) in values {
+        clipped.insert_value_with_quality(time, value, quality);
+    }
+    clipped
+}
+
+fn grid(start: u64, end: u64, interval: u64) -> Vec<u64> {
+    let first = start.div_ceil(interval) * interval;
+    (first..=end).step_by(interval as usize).collect()
+}
+
+fn resample_onto<T>(ts: &TimeSeries<T>, grid: &[u64], fill: Fill) -> TimeSeries<T>
+where
+    T: Codec + Add<Output = T> + Mul<f64, Output = T>,
+{
+    let entries = ts.get_current_values_with_quality();
+    let mut resampled = TimeSeries::<T>::with_resolution(grid.len(), ts.get_resolution());
+
+    // index of the first entry after the current grid point
+    let mut next = 0;
+    for time in grid {
+        while next < entries.len() && entries[next].0 <= *time {
+            next += 1;
+        }
+        // the grid only covers the range of the series, so there's always a previous entry
+        let (t_prev, v_prev, q_prev) = entries[next - 1];
+        let filled = |q: Quality| q.max(Quality::Interpolated);
+        let (value, quality) = match entries.get(next) {
+            _ if t_prev == *time => (v_prev, q_prev),
+            None => (v_prev, filled(q_prev)),
+            Some(&(t_next, v_next, q_next)) => match fill {
+                Fill::Previous => (v_prev, filled(q_prev)),
+                Fill::Nearest if time - t_prev <= t_next - time => (v_prev, filled(q_prev)),
+                Fill::Nearest => (v_next, filled(q_next)),
+                Fill::Linear => {
+                    let w = (time - t_prev) as f64 / (t_next - t_prev) as f64;
+                    (v_prev * (1.0 - w) + v_next * w, filled(q_prev.max(q_next)))
+                }
+            },
+        };
+        resampled.insert_value_with_quality(*time, value, quality);
+    }
+    resampled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timeseries::Resolution;
+
+    #[test]
+    fn test_align() {
+        // measured every 10 s and every 25 s
+        let mut a = TimeSeries::<f64>::new(10);
+        for i in 0..10u64 {
+            a.insert_value_at_time(1000 + i * 10000, i as f64);
+        }
+        let mut b = TimeSeries::<f64>::new(5);
+        for i in 0..5u64 {
+            b.insert_value_at_time(20000 + i * 25000, 10.0 * i as f64);
+        }
+
+        // both cover 20 s to 91 s
+        let (a_prev, b_prev) = align(&a, &b, 20000, Fill::Previous).unwrap();
+        assert_eq!(
+            a_prev.get_current_values(),
+            vec![(20000, 1.0), (40000, 3.0), (60000, 5.0), (80000, 7.0)]
+        );
+        assert_eq!(
+            b_prev.get_current_values(),
+            vec![(20000, 0.0), (40000, 0.0), (60000, 10.0), (80000, 20.0)]
+        );
+
+        let (a_linear, b_linear) = align(&a, &b, 20000, Fill::Linear).unwrap();
+        let (t, v) = a_linear.get_current_values()[1];
+        assert_eq!(t, 40000);
+        assert!((v - 3.9).abs() < 1e-9);
+        assert_eq!(b_linear.get_current_values()[1], (40000, 8.0));
+
+        // only values that coincide with the grid count as measured
+        let qualities: Vec<Quality> = b_linear
+            .get_current_values_with_quality()
+            .into_iter()
+            .map(|(_, _, q)| q)
+            .collect();
+        assert_eq!(
+            qualities,
+            vec![
+                Quality::Measured,
+                Quality::Interpolated,
+                Quality::Interpolated,
+                Quality::Interpolated
+            ]
+        );
+
+        let (_, b_nearest) = align(&a, &b, 20000, Fill::Nearest).unwrap();
+        assert_eq!(
+            b_nearest.get_current_values(),
+            vec![(20000, 0.0), (40000, 10.0), (60000, 20.0), (80000, 20.0)]
+        );
+
+        // no overlap, no values
+        let mut c = TimeSeries::<f64>::new(1);
+        c.insert_value_at_time(1000000, 1.0);
+        let (a_none, c_none) = align(&a, &c, 1000, Fill::Linear).unwrap();
+        assert!(a_none.is_empty() && c_none.is_empty());
+        assert!(align(&a, &b, 0, Fill::Linear).is_none());
+    }
+
+    #[test]
+    fn test_clip_interpolated() {
+        let mut ts = TimeSeries::<f64>::with_resolution(5, Resolution::Seconds);
+        for (t, v) in [(5, 0.0), (15, 10.0), (20, 20.0), (30, 0.0), (4000, 1.0)] {
+            ts.insert_value_at_time(t, v);
+        }
+
+        let clipped = clip_interpolated(&ts, 10, 25, 3600);
+        assert_eq!(
+            clipped.get_current_values(),
+            vec![(10, 5.0), (15, 10.0), (20, 20.0), (25, 10.0)]
+        );
+        let qualities: Vec<Quality> = clipped
+            .view()
+            .iter_with_quality()
+            .map(|(_, _, q)| q)
+            .collect();
+        assert_eq!(qualities[0], Quality::Interpolated);
+        assert_eq!(qualities[1], Quality::Measured);
+
+        // values at the boundaries are kept as they are
+        let exact = clip_interpolated(&ts, 15, 20, 3600);
+        assert_eq!(exact.get_current_values(), vec![(15, 10.0), (20, 20.0)]);
+        assert!(exact
+            .view()
+            .iter_with_quality()
+            .all(|(_, _, q)| q == Quality::Measured));
+
+        // nothing to interpolate from before the first value or across a long pause
+        let outside = clip_interpolated(&ts, 0, 100, 3600);
+        assert_eq!(
+            outside.get_current_values(),
+            vec![(5, 0.0), (15, 10.0), (20, 20.0), (30, 0.0)]
+        );
+        assert!(clip_interpolated(&ts, 100, 200, 3600).is_empty());
+    }
+
+    #[test]
+    fn test_resample() {
+        let mut ts = TimeSeries::<f64>::with_resolution(4, Resolution::Seconds);
+        for (t, v) in [(5, 0.0), (20, 3.0), (40, 1.0), (45, 0.0)] {
+            ts.insert_value_at_time(t, v);
+        }
+
+        let locf = ts.resample(10000, Fill::Previous).unwrap();
+        assert_eq!(
+            locf.get_current_values(),
+            vec![(10, 0.0), (20, 3.0), (30, 3.0), (40, 1.0)]
+        );
+        assert_eq!(locf.get_resolution(), Resolution::Seconds);
+
+        let linear = ts.resample(10000, Fill::Linear).unwrap();
+        assert_eq!(
+            linear.get_current_values(),
+            vec![(10, 1.0), (20, 3.0), (30, 2.0), (40, 1.0)]
+        );
+
+        assert!(TimeSeries::<f64>::empty()
+            .resample(1000, Fill::Linear)
+            .unwrap()
+            .is_empty());
+        // the timestamps are in seconds
+        assert!(ts.resample(500, Fill::Linear).is_none());
+    }
+}
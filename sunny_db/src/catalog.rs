@@ -0,0 +1,226 @@
+use anyhow::{self, Context};
+use chrono::NaiveDate;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::manifest::{Change, ManifestEntry};
+
+/// Name of the catalog next to the data directory
+pub const CATALOG_FILE: &str = "catalog.sqlite";
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS segments (
+        start_ms INTEGER NOT NULL,
+        end_ms INTEGER NOT NULL,
+        size INTEGER NOT NULL,
+        PRIMARY KEY (start_ms, end_ms)
+    );
+    CREATE INDEX IF NOT EXISTS segments_by_end ON segments (end_ms);
+    CREATE TABLE IF NOT EXISTS manifest (
+        seq INTEGER PRIMARY KEY,
+        change TEXT NOT NULL,
+        start_ms INTEGER NOT NULL,
+        end_ms INTEGER NOT NULL,
+        size INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS summaries (
+        date TEXT PRIMARY KEY,
+        summary TEXT NOT NULL
+    );
+";
+
+/// An SQLite database mirroring the manifest and the segments it describes, plus the daily
+/// summaries of the application, for databases too large to list and parse their files
+/// whenever the segments of a range or the changes since a sync are needed; it can also be
+/// inspected with any SQLite client. The manifest entries keep their sequence numbers, and
+/// each batch of them is applied in a single transaction
+pub struct Catalog {
+    connection: Mutex<Connection>,
+}
+
+impl Catalog {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let connection = Connection::open(path)
+            .with_context(|| format!("Couldn't open catalog {}", path.display()))?;
+        connection
+            .execute_batch(SCHEMA)
+            .with_context(|| format!("Couldn't create the tables of catalog {}", path.display()))?;
+        Ok(Catalog {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// opens an existing catalog without ever writing to it, e.g. of a database another
+    /// process is writing to
+    pub fn open_read_only(path: &Path) -> anyhow::Result<Self> {
+        let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .with_context(|| format!("Couldn't open catalog {}", path.display()))?;
+        Ok(Catalog {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    /// the sequence number of the last manifest entry; 0 if there are none
+    pub fn last_seq(&self) -> anyhow::Result<u64> {
+        let connection = self.connection.lock().unwrap();
+        let seq: Option<i64> =
+            connection.query_row("SELECT MAX(seq) FROM manifest", [], |row| row.get(0))?;
+        Ok(seq.unwrap_or(0) as u64)
+    }
+
+    /// whether it holds all manifest entries up to the last one; entries that couldn't be
+    /// recorded by older versions leave gaps
+    pub fn is_contiguous(&self) -> anyhow::Result<bool> {
+        let connection = self.connection.lock().unwrap();
+        let (count, last): (i64, Option<i64>) =
+            connection.query_row("SELECT COUNT(*), MAX(seq) FROM manifest", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?;
+        Ok(count == last.unwrap_or(0))
+    }
+
+    /// removes the manifest entries and the segments, e.g. to record them again; the summaries
+    /// are kept
+    pub(crate) fn clear(&self) -> anyhow::Result<()> {
+        let connection = self.connection.lock().unwrap();
+        connection.execute_batch("DELETE FROM manifest; DELETE FROM segments;")?;
+        Ok(())
+    }
+
+    /// appends the manifest entries and applies them to the segments
+    pub(crate) fn record(&self, entries: &[ManifestEntry]) -> anyhow::Result<()> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        for entry in entries {
+            let (start, end) = (entry.segment.0 as i64, entry.segment.1 as i64);
+            let change = match entry.change {
+                Change::Added => "+",
+                Change::Removed => "-",
+            };
+            transaction.execute(
+                "INSERT OR REPLACE INTO manifest (seq, change, start_ms, end_ms, size)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![entry.seq as i64, change, start, end, entry.size as i64],
+            )?;
+            match entry.change {
+                Change::Added => transaction.execute(
+                    "INSERT OR REPLACE INTO segments (start_ms, end_ms, size) VALUES (?1, ?2, ?3)",
+                    params![start, end, entry.size as i64],
+                )?,
+                Change::Removed => transaction.execute(
+                    "DELETE FROM segments WHERE start_ms = ?1 AND end_ms = ?2",
+                    params![start, end],
+                )?,
+            };
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// the segments holding values between start_ms and end_ms with their size in bytes,
+    /// sorted by time
+    pub fn segments_in(
+        &self,
+        start_ms: u64,
+        end_ms: u64,
+    ) -> anyhow::Result<Vec<((u64, u64), u64)>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare_cached(
+            "SELECT start_ms, end_ms, size FROM segments
+             WHERE end_ms >= ?1 AND start_ms <= ?2 ORDER BY start_ms, end_ms",
+        )?;
+        let rows = statement.query_map(
+            params![
+                start_ms.min(i64::MAX as u64) as i64,
+                end_ms.min(i64::MAX as u64) as i64
+            ],
+            |row| {
+                let (start, end, size): (i64, i64, i64) = (row.get(0)?, row.get(1)?, row.get(2)?);
+                Ok(((start as u64, end as u64), size as u64))
+            },
+        )?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// the manifest entries after the one with sequence number `seq`
+    pub fn manifest_since(&self, seq: u64) -> anyhow::Result<Vec<ManifestEntry>> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement = connection.prepare_cached(
+            "SELECT seq, change, start_ms, end_ms, size FROM manifest WHERE seq > ?1 ORDER BY seq",
+        )?;
+        let rows = statement.query_map(params![seq as i64], |row| {
+            let change: String = row.get(1)?;
+            let (start, end): (i64, i64) = (row.get(2)?, row.get(3)?);
+            Ok(ManifestEntry {
+                seq: row.get::<_, i64>(0)? as u64,
+                change: if change == "-" {
+                    Change::Removed
+                } else {
+                    Change::Added
+                },
+                segment: (start as u64, end as u64),
+                size: row.get::<_, i64>(4)? as u64,
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// stores the summary of a day, e.g. as JSON, replacing the one stored before
+    pub fn put_summary(&self, date: NaiveDate, summary: &str) -> anyhow::Result<()> {
+        let connection = self.connection.lock().unwrap();
+        connection.execute(
+            "INSERT OR REPLACE INTO summaries (date, summary) VALUES (?1, ?2)",
+            params![date.format("%Y-%m-%d").to_string(), summary],
+        )?;
+        Ok(())
+    }
+
+    pub fn summary(&self, date: NaiveDate) -> anyhow::Result<Option<String>> {
+        let connection = self.connection.lock().unwrap();
+        let summary = connection
+            .query_row(
+                "SELECT summary FROM summaries WHERE date = ?1",
+                params![date.format("%Y-%m-%d").to_string()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(summary)
+    }
+
+    /// the size of the stored summaries in bytes; the file itself only shrinks once it's
+    /// vacuumed
+    pub fn summaries_size(&self) -> anyhow::Result<u64> {
+        let connection = self.connection.lock().unwrap();
+        let size: i64 = connection.query_row(
+            "SELECT COALESCE(SUM(LENGTH(CAST(summary AS BLOB))), 0) FROM summaries",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(size as u64)
+    }
+
+    /// removes the summaries of the oldest days until at least `bytes` have been freed or none
+    /// are left; returns the number of bytes freed
+    pub fn remove_oldest_summaries(&self, bytes: u64) -> anyhow::Result<u64> {
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        let mut freed = 0;
+        {
+            let mut statement = transaction.prepare(
+                "SELECT date, LENGTH(CAST(summary AS BLOB)) FROM summaries ORDER BY date",
+            )?;
+            let mut rows = statement.query([])?;
+            while freed < bytes {
+                let Some(row) = rows.next()? else {
+                    break;
+                };
+                let date: String = row.get(0)?;
+                transaction.execute("DELETE FROM summaries WHERE date = ?1", params![date])?;
+                freed += row.get::<_, i64>(1)? as u64;
+            }
+        }
+        transaction.commit()?;
+        Ok(freed)
+    }
+}
@@ -0,0 +1,128 @@
+use crate::timeseries::{LegacySegmentBody, SegmentBody};
+use bitcode::{DecodeOwned, Encode};
+
+/// Codec of segments written by bitcode; also used by all segments written before the codec
+/// was recorded in their header
+pub const BITCODE: u8 = 0;
+/// Codec of segments written by postcard via serde
+pub const POSTCARD: u8 = 1;
+/// Segments in the gorilla encoding, see gorilla.rs; they aren't compressed with zstd
+pub const GORILLA: u8 = 2;
+/// Segments with values quantized to integers, see quantization.rs
+pub const QUANTIZED: u8 = 3;
+
+#[doc(hidden)]
+pub type DecodeResult<T> = anyhow::Result<SegmentBody<T>>;
+
+/// How the values of a series are encoded when persisting segments.
+///
+/// Types deriving bitcode's `Encode` and `Decode` use bitcode automatically. With the `serde`
+/// feature, types that only implement serde's `Serialize` and `Deserialize` can be stored
+/// using postcard by invoking [`serde_codec!`](crate::serde_codec) for them.
+pub trait Codec: Copy {
+    /// recorded in the segment header so segments are never decoded with another codec
+    const ID: u8;
+
+    #[doc(hidden)]
+    fn encode_segment(body: &SegmentBody<Self>) -> std::io::Result<Vec<u8>>;
+
+    #[doc(hidden)]
+    fn decode_segment(bytes: &[u8]) -> DecodeResult<Self>;
+
+    /// segments written before quality flags were introduced; they always use bitcode
+    #[doc(hidden)]
+    fn decode_legacy_segment(_bytes: &[u8]) -> anyhow::Result<LegacySegmentBody<Self>> {
+        anyhow::bail!("Segments without a header can only be decoded with bitcode")
+    }
+}
+
+impl<T: Copy + Encode + DecodeOwned> Codec for T {
+    const ID: u8 = BITCODE;
+
+    fn encode_segment(body: &SegmentBody<Self>) -> std::io::Result<Vec<u8>> {
+        Ok(bitcode::encode(body))
+    }
+
+    fn decode_segment(bytes: &[u8]) -> DecodeResult<Self> {
+        Ok(bitcode::decode(bytes)?)
+    }
+
+    fn decode_legacy_segment(bytes: &[u8]) -> anyhow::Result<LegacySegmentBody<Self>> {
+        Ok(bitcode::decode(bytes)?)
+    }
+}
+
+pub fn codec_name(id: u8) -> &'static str {
+    match id {
+        BITCODE => "bitcode",
+        POSTCARD => "postcard",
+        GORILLA => "gorilla",
+        QUANTIZED => "quantized",
+        _ => "unknown",
+    }
+}
+
+#[cfg(feature = "serde")]
+#[doc(hidden)]
+pub fn postcard_encode<T: serde::Serialize>(body: &SegmentBody<T>) -> std::io::Result<Vec<u8>> {
+    postcard::to_stdvec(body).map_err(|e| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Couldn't encode segment with postcard: {}", e),
+        )
+    })
+}
+
+#[cfg(feature = "serde")]
+#[doc(hidden)]
+pub fn postcard_decode<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> DecodeResult<T> {
+    Ok(postcard::from_bytes(bytes)?)
+}
+
+/// Stores values of the given types using postcard instead of bitcode, e.g.
+/// `sunny_db::serde_codec!(PowerValues);` for a type deriving `Serialize` and `Deserialize`
+/// but not bitcode's `Encode` and `Decode`
+#[cfg(feature = "serde")]
+#[macro_export]
+macro_rules! serde_codec {
+    ($($t:ty),+ $(,)?) => {
+        $(
+            impl $crate::codec::Codec for $t {
+                const ID: u8 = $crate::codec::POSTCARD;
+
+                fn encode_segment(
+                    body: &$crate::timeseries::SegmentBody<Self>,
+                ) -> ::std::io::Result<Vec<u8>> {
+                    $crate::codec::postcard_encode(body)
+                }
+
+                fn decode_segment(bytes: &[u8]) -> $crate::codec::DecodeResult<Self> {
+                    $crate::codec::postcard_decode(bytes)
+                }
+            }
+        )+
+    };
+}
+
+/// How a database encodes new segments; segments are always decoded the way their header says
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum SegmentEncoding {
+    /// the codec of the values, compressed with zstd
+    #[default]
+    Zstd,
+    /// delta-of-delta timestamps and XOR compressed floats, see gorilla.rs; only for values
+    /// implementing `FloatFields`
+    Gorilla,
+}
+
+impl std::str::FromStr for SegmentEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "zstd" => Ok(SegmentEncoding::Zstd),
+            "gorilla" => Ok(SegmentEncoding::Gorilla),
+            _ => anyhow::bail!("Unknown segment encoding {}, use zstd or gorilla", s),
+        }
+    }
+}
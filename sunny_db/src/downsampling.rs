@@ -0,0 +1,204 @@
+use crate::codec::Codec;
+use std::ops::{Add, Div, Mul};
+
+use crate::timeseries::{Quality, Resolution, TimeSeries};
+
+/// Methods available to reduce a time series to a maximum number of points
+pub enum DownsamplingMethod<T> {
+    /// Largest-Triangle-Three-Buckets; keeps the points that preserve the visual shape of the
+    /// series best; the function maps a value to the scalar used to compute triangle areas
+    Lttb(fn(&T) -> f64),
+    /// splits the covered time range into equally sized buckets and averages all values in each one;
+    /// the average gets the quality of the least reliable value in the bucket
+    BucketAverage,
+}
+
+pub trait Downsample<T> {
+    /// returns a series with at most `max_points` values; series that are already small enough
+    /// are returned unchanged
+    fn downsample(&self, max_points: usize, method: &DownsamplingMethod<T>) -> TimeSeries<T>;
+}
+
+impl<T> Downsample<T> for TimeSeries<T>
+where
+    T: Codec + Add<Output = T> + Mul<f64, Output = T> + Div<f64, Output = T>,
+{
+    fn downsample(&self, max_points: usize, method: &DownsamplingMethod<T>) -> TimeSeries<T> {
+        let entries = self.get_current_values_with_quality();
+        if entries.len() <= max_points {
+            return series_from_entries(&entries, self.get_resolution());
+        }
+
+        let sampled = match method {
+            DownsamplingMethod::Lttb(f) => lttb(&entries, max_points, *f),
+            DownsamplingMethod::BucketAverage => bucket_average(&entries, max_points),
+        };
+        series_from_entries(&sampled, self.get_resolution())
+    }
+}
+
+type Entry<T> = (u64, T, Quality);
+
+fn series_from_entries<T: Codec>(entries: &[Entry<T>], resolution: Resolution) -> TimeSeries<T> {
+    let mut ts = TimeSeries::<T>::with_resolution(entries.len(), resolution);
+    for (time, value, quality) in entries {
+        ts.insert_value_with_quality(*time, *value, *quality);
+    }
+    ts
+}
+
+/// see Sveinn Steinarsson, "Downsampling Time Series for Visual Representation" (2013)
+fn lttb<T: Copy>(entries: &[Entry<T>], max_points: usize, f: fn(&T) -> f64) -> Vec<Entry<T>> {
+    if max_points == 0 {
+        return Vec::new();
+    }
+
+    let n = entries.len();
+    if max_points < 3 {
+        // there's no bucket in between the first and last point; just keep the boundaries
+        let mut sampled = vec![entries[0]];
+        if max_points == 2 {
+            sampled.push(entries[n - 1]);
+        }
+        return sampled;
+    }
+
+    let mut sampled = Vec::with_capacity(max_points);
+    sampled.push(entries[0]);
+
+    // the first and last point are always kept, the rest is split into equally sized buckets
+    let bucket_size = (n - 2) as f64 / (max_points - 2) as f64;
+    let mut previous = 0;
+
+    for i in 0..(max_points - 2) {
+        let bucket_start = (i as f64 * bucket_size) as usize + 1;
+        let bucket_end = ((i + 1) as f64 * bucket_size) as usize + 1;
+
+        // average point of the next bucket, which is the third corner of the triangle
+        let next_start = bucket_end;
+        let next_end = (((i + 2) as f64 * bucket_size) as usize + 1).min(n);
+        let next = &entries[next_start..next_end.max(next_start + 1)];
+        let avg_t = next.iter().map(|(t, _, _)| *t as f64).sum::<f64>() / next.len() as f64;
+        let avg_v = next.iter().map(|(_, v, _)| f(v)).sum::<f64>() / next.len() as f64;
+
+        let (t_a, v_a) = (entries[previous].0 as f64, f(&entries[previous].1));
+        let mut max_area = -1.0;
+        let mut max_index = bucket_start;
+        for (j, (t, v, _)) in entries
+            .iter()
+            .enumerate()
+            .take(bucket_end)
+            .skip(bucket_start)
+        {
+            let area = ((t_a - avg_t) * (f(v) - v_a) - (t_a - *t as f64) * (avg_v - v_a)).abs();
+            if area > max_area {
+                max_area = area;
+                max_index = j;
+            }
+        }
+
+        sampled.push(entries[max_index]);
+        previous = max_index;
+    }
+
+    sampled.push(entries[n - 1]);
+    sampled
+}
+
+fn bucket_average<T>(entries: &[Entry<T>], max_points: usize) -> Vec<Entry<T>>
+where
+    T: Copy + Add<Output = T> + Mul<f64, Output = T> + Div<f64, Output = T>,
+{
+    if max_points == 0 {
+        return Vec::new();
+    }
+
+    let start = entries[0].0;
+    let end = entries[entries.len() - 1].0;
+    // +1 so that the last value ends up in the last bucket rather than in a bucket of its own
+    let bucket_width = (end - start) / max_points as u64 + 1;
+
+    let mut sampled = Vec::with_capacity(max_points);
+    let mut bucket: Vec<Entry<T>> = Vec::new();
+    let mut bucket_index = 0;
+    for entry in entries {
+        let index = (entry.0 - start) / bucket_width;
+        if index != bucket_index && !bucket.is_empty() {
+            sampled.push(average_of_bucket(&bucket));
+            bucket.clear();
+        }
+        bucket_index = index;
+        bucket.push(*entry);
+    }
+    if !bucket.is_empty() {
+        sampled.push(average_of_bucket(&bucket));
+    }
+
+    sampled
+}
+
+fn average_of_bucket<T>(bucket: &[Entry<T>]) -> Entry<T>
+where
+    T: Copy + Add<Output = T> + Mul<f64, Output = T> + Div<f64, Output = T>,
+{
+    let n = bucket.len();
+    let time = bucket.iter().map(|(t, _, _)| *t as u128).sum::<u128>() / n as u128;
+    let mut sum = bucket[0].1;
+    for (_, v, _) in &bucket[1..] {
+        sum = sum + *v;
+    }
+    let quality = bucket.iter().map(|(_, _, q)| *q).max().unwrap_or_default();
+    (time as u64, sum / n as f64, quality)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_downsampling() {
+        let mut ts = TimeSeries::<f64>::new(1000);
+        for i in 0..1000u64 {
+            let value = if i == 500 { 100.0 } else { (i % 10) as f64 };
+            ts.insert_value_at_time(i * 10, value);
+        }
+
+        // small enough series are kept as they are
+        let unchanged = ts.downsample(1000, &DownsamplingMethod::BucketAverage);
+        assert_eq!(unchanged, ts);
+
+        // LTTB keeps the boundaries and the spike
+        let lttb = ts.downsample(50, &DownsamplingMethod::Lttb(|v| *v));
+        assert_eq!(lttb.len(), 50);
+        assert_eq!(lttb.get_start_time(), ts.get_start_time());
+        assert_eq!(lttb.get_end_time(), ts.get_end_time());
+        assert!(lttb.get_current_values().contains(&(5000, 100.0)));
+
+        // averaging buckets of 10 values of 0..9 results in 4.5 everywhere except for the spike
+        let averaged = ts.downsample(100, &DownsamplingMethod::BucketAverage);
+        assert_eq!(averaged.len(), 100);
+        for (t, v) in averaged.get_current_values() {
+            if t == 5045 {
+                assert_eq!(v, 14.5);
+            } else {
+                assert_eq!(v, 4.5);
+            }
+        }
+
+        assert!(ts
+            .downsample(0, &DownsamplingMethod::BucketAverage)
+            .is_empty());
+
+        // an interpolated value taints the average of its bucket
+        ts.insert_value_with_quality(5005, 0.0, Quality::Interpolated);
+        let averaged = ts.downsample(100, &DownsamplingMethod::BucketAverage);
+        let interpolated: Vec<u64> = averaged
+            .get_current_values_with_quality()
+            .into_iter()
+            .filter(|(_, _, q)| *q == Quality::Interpolated)
+            .map(|(t, _, _)| t)
+            .collect();
+        assert_eq!(interpolated.len(), 1);
+        assert_eq!(ts.downsample(1, &DownsamplingMethod::Lttb(|v| *v)).len(), 1);
+    }
+}
@@ -0,0 +1,100 @@
+use anyhow::{self, Context};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use std::fmt;
+use std::path::Path;
+
+/// Cipher recorded in the header of unencrypted segments
+pub const NO_CIPHER: u8 = 0;
+/// Cipher recorded in the header of segments encrypted with ChaCha20-Poly1305
+pub const CHACHA20_POLY1305: u8 = 1;
+
+/// Length of keys in bytes
+pub const KEY_LEN: usize = 32;
+/// Length of the random nonce preceding the encrypted data of a segment
+const NONCE_LEN: usize = 12;
+
+/// Key for encrypting the data of segments at rest using ChaCha20-Poly1305; the segment header
+/// is authenticated as well, so it can't be altered unnoticed either
+#[derive(Clone)]
+pub struct EncryptionKey(Key);
+
+impl EncryptionKey {
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<EncryptionKey> {
+        if bytes.len() != KEY_LEN {
+            anyhow::bail!(
+                "Encryption keys must be {} bytes long, got {}",
+                KEY_LEN,
+                bytes.len()
+            );
+        }
+        Ok(EncryptionKey(*Key::from_slice(bytes)))
+    }
+
+    /// reads a key file holding the raw key, e.g. created with `head -c 32 /dev/urandom`
+    pub fn from_file(path: &Path) -> anyhow::Result<EncryptionKey> {
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("Couldn't read key file {}", path.display()))?;
+        EncryptionKey::from_bytes(&bytes)
+            .with_context(|| format!("Invalid key file {}", path.display()))
+    }
+
+    /// encrypts the data using a random nonce, which is prepended to the result
+    pub(crate) fn encrypt(&self, data: &[u8], header: &[u8]) -> std::io::Result<Vec<u8>> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: data,
+            aad: header,
+        };
+        let encrypted = ChaCha20Poly1305::new(&self.0)
+            .encrypt(&nonce, payload)
+            .map_err(|_| std::io::Error::other("Couldn't encrypt segment"))?;
+        let mut result = nonce.to_vec();
+        result.extend(encrypted);
+        Ok(result)
+    }
+
+    pub(crate) fn decrypt(&self, data: &[u8], header: &[u8]) -> anyhow::Result<Vec<u8>> {
+        if data.len() < NONCE_LEN {
+            anyhow::bail!("Encrypted segment is truncated");
+        }
+        let (nonce, encrypted) = data.split_at(NONCE_LEN);
+        let payload = Payload {
+            msg: encrypted,
+            aad: header,
+        };
+        ChaCha20Poly1305::new(&self.0)
+            .decrypt(Nonce::from_slice(nonce), payload)
+            .map_err(|_| {
+                anyhow::anyhow!("Couldn't decrypt segment; was it encrypted with another key?")
+            })
+    }
+}
+
+impl fmt::Debug for EncryptionKey {
+    // never log the key itself
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encryption() {
+        let key = EncryptionKey::from_bytes(&[7; KEY_LEN]).unwrap();
+        let encrypted = key.encrypt(b"values", b"header").unwrap();
+        assert_eq!(encrypted.len(), NONCE_LEN + 6 + 16);
+        assert_eq!(key.decrypt(&encrypted, b"header").unwrap(), b"values");
+        // nonces are random
+        assert_ne!(key.encrypt(b"values", b"header").unwrap(), encrypted);
+
+        assert!(key.decrypt(&encrypted, b"altered header").is_err());
+        let other = EncryptionKey::from_bytes(&[8; KEY_LEN]).unwrap();
+        assert!(other.decrypt(&encrypted, b"header").is_err());
+        assert!(EncryptionKey::from_bytes(&[7; 16]).is_err());
+        assert_eq!(format!("{:?}", key), "EncryptionKey(..)");
+    }
+}
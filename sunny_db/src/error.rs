@@ -0,0 +1,44 @@
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Errors opening, writing to or combining series of a database; unlike a panic, they leave it
+/// to the caller whether to log them, retry or give up
+#[derive(Error, Debug)]
+pub enum SunnyDbError {
+    #[error("couldn't set up the database directory {path}: {source}")]
+    Directory {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("couldn't lock the database at {path}; is it opened by another process? {source}")]
+    Locked {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("there's no data directory at {0}")]
+    NoDataDirectory(PathBuf),
+    #[error("the database has been opened read-only")]
+    ReadOnly,
+    #[error("the maximum number of values in memory ({max}) must not be smaller than the segment size ({segment_size})")]
+    InMemoryCapTooSmall { max: usize, segment_size: usize },
+    #[error("tried to write a segment without any values")]
+    EmptySegment,
+    #[error("tried to append a series that doesn't follow the values of this one")]
+    AppendOutOfOrder,
+    #[error("tried to append a series with a different timestamp resolution")]
+    AppendResolutionMismatch,
+    #[error("values can't be quantized to {0} decimal places, use -9 to 9")]
+    QuantizationOutOfRange(i8),
+    #[error("the clock is set to a time before the unix epoch")]
+    BeforeEpoch,
+    #[error("the timestamp {time} is outside of the accepted range from {min} up to {max} ms")]
+    TimestampOutOfBounds { time: u64, min: u64, max: u64 },
+    #[error("the earliest accepted timestamp ({min}) is after the latest ({max})")]
+    InvalidTimestampBounds { min: u64, max: u64 },
+    #[error("couldn't open the catalog at {path}: {reason}")]
+    Catalog { path: PathBuf, reason: String },
+    #[error("couldn't decode the aggregates in {path}: {reason}")]
+    CorruptAggregates { path: PathBuf, reason: String },
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
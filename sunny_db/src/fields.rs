@@ -0,0 +1,135 @@
+/// Values made up of a fixed number of floats, which can be stored with the gorilla encoding
+/// and are aggregated field by field
+pub trait FloatFields: Copy {
+    /// the number of floats of every value
+    const COUNT: usize;
+
+    /// the float at the index, which is below `COUNT`
+    fn field(&self, index: usize) -> f64;
+
+    /// the value made up of `COUNT` floats
+    fn from_fields(fields: &[f64]) -> Self;
+}
+
+impl FloatFields for f64 {
+    const COUNT: usize = 1;
+
+    fn field(&self, _index: usize) -> f64 {
+        *self
+    }
+
+    fn from_fields(fields: &[f64]) -> Self {
+        fields[0]
+    }
+}
+
+impl<const N: usize> FloatFields for [f64; N] {
+    const COUNT: usize = N;
+
+    fn field(&self, index: usize) -> f64 {
+        self[index]
+    }
+
+    fn from_fields(fields: &[f64]) -> Self {
+        let mut value = [0.0; N];
+        value.copy_from_slice(fields);
+        value
+    }
+}
+
+/// the value with the function applied to each of its fields
+pub(crate) fn map_fields<T: FloatFields>(value: &T, f: impl Fn(f64) -> f64) -> T {
+    let fields: Vec<f64> = (0..T::COUNT).map(|i| f(value.field(i))).collect();
+    T::from_fields(&fields)
+}
+
+/// Implements `FloatFields` and the field-wise `Add`, `Sub`, `Mul<f64>` and `Div<f64>` needed
+/// for statistics for a struct of floats, e.g.
+/// `sunny_db::float_fields!(PowerValues { power_pv, power_used });`; the fields are listed in
+/// the order they're stored in
+#[macro_export]
+macro_rules! float_fields {
+    ($t:ident { $($field:ident),+ $(,)? }) => {
+        impl $crate::fields::FloatFields for $t {
+            const COUNT: usize = [$(stringify!($field)),+].len();
+
+            fn field(&self, index: usize) -> f64 {
+                [$(self.$field),+][index]
+            }
+
+            fn from_fields(fields: &[f64]) -> Self {
+                let mut fields = fields.iter().copied();
+                $t {
+                    $($field: fields.next().expect("Too few fields")),+
+                }
+            }
+        }
+
+        impl ::std::ops::Add for $t {
+            type Output = Self;
+
+            fn add(self, other: Self) -> Self {
+                $t { $($field: self.$field + other.$field),+ }
+            }
+        }
+
+        impl ::std::ops::Sub for $t {
+            type Output = Self;
+
+            fn sub(self, other: Self) -> Self {
+                $t { $($field: self.$field - other.$field),+ }
+            }
+        }
+
+        impl ::std::ops::Mul<f64> for $t {
+            type Output = Self;
+
+            fn mul(self, rhs: f64) -> Self {
+                $t { $($field: self.$field * rhs),+ }
+            }
+        }
+
+        impl ::std::ops::Div<f64> for $t {
+            type Output = Self;
+
+            fn div(self, rhs: f64) -> Self {
+                $t { $($field: self.$field / rhs),+ }
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Copy, Clone, PartialEq, Debug)]
+    struct Flow {
+        power_in: f64,
+        power_out: f64,
+    }
+
+    crate::float_fields!(Flow {
+        power_in,
+        power_out
+    });
+
+    #[test]
+    fn test_float_fields_macro() {
+        let flow = Flow {
+            power_in: 1.0,
+            power_out: 4.0,
+        };
+        assert_eq!(Flow::COUNT, 2);
+        assert_eq!([flow.field(0), flow.field(1)], [1.0, 4.0]);
+        assert_eq!(Flow::from_fields(&[1.0, 4.0]), flow);
+        let doubled = Flow {
+            power_in: 2.0,
+            power_out: 8.0,
+        };
+        assert_eq!(flow + flow, doubled);
+        assert_eq!(doubled - flow, flow);
+        assert_eq!(flow * 2.0, doubled);
+        assert_eq!(doubled / 2.0, flow);
+    }
+}
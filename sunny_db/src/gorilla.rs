@@ -0,0 +1,392 @@
+use crate::fields::FloatFields;
+use crate::timeseries::{Quality, TimeSeriesEntry};
+
+/// encodes the entries of a segment, which have to be sorted by time, as in Facebook's Gorilla
+/// paper: timestamps as the difference between consecutive deltas, which is mostly 0 for
+/// periodic samples, and each float as the XOR with its previous value, of which only the bits
+/// in between the leading and trailing zeros are kept
+pub(crate) fn encode<T: FloatFields>(init_size: usize, entries: &[TimeSeriesEntry<T>]) -> Vec<u8> {
+    let mut writer = BitWriter::default();
+    write_varint(&mut writer.bytes, init_size as u64);
+    write_varint(&mut writer.bytes, entries.len() as u64);
+
+    let Some(first) = entries.first() else {
+        return writer.bytes;
+    };
+    writer.write(first.time, 64);
+    writer.write(quality_bits(first.quality), 2);
+    let mut fields: Vec<FloatState> = (0..T::COUNT)
+        .map(|i| FloatState::new(first.value.field(i).to_bits()))
+        .collect();
+    for field in &fields {
+        writer.write(field.previous, 64);
+    }
+
+    let (mut previous_time, mut previous_delta) = (first.time, 0u64);
+    let mut previous_quality = first.quality;
+    for entry in &entries[1..] {
+        let delta = entry.time.wrapping_sub(previous_time);
+        write_delta_of_delta(&mut writer, delta.wrapping_sub(previous_delta) as i64);
+        (previous_time, previous_delta) = (entry.time, delta);
+
+        if entry.quality == previous_quality {
+            writer.write(0, 1);
+        } else {
+            writer.write(1, 1);
+            writer.write(quality_bits(entry.quality), 2);
+            previous_quality = entry.quality;
+        }
+
+        for (i, field) in fields.iter_mut().enumerate() {
+            field.encode(&mut writer, entry.value.field(i).to_bits());
+        }
+    }
+    writer.bytes
+}
+
+/// decodes the initial size and the entries of a segment written by `encode`
+pub(crate) fn decode<T: FloatFields>(
+    bytes: &[u8],
+) -> anyhow::Result<(usize, Vec<TimeSeriesEntry<T>>)> {
+    let mut offset = 0;
+    let init_size = read_varint(bytes, &mut offset)? as usize;
+    let count = read_varint(bytes, &mut offset)? as usize;
+    let mut reader = BitReader {
+        bytes: &bytes[offset..],
+        position: 0,
+    };
+    // every value takes at least a few bits, so larger counts can't be right
+    if count > reader.bytes.len().saturating_mul(8) + 1 {
+        anyhow::bail!("Gorilla segment claims to hold {} values", count);
+    }
+    let mut entries = Vec::with_capacity(count);
+    if count == 0 {
+        return Ok((init_size, entries));
+    }
+
+    let mut time = reader.read(64)?;
+    let mut quality = quality_from_bits(reader.read(2)?);
+    let mut fields = Vec::with_capacity(T::COUNT);
+    for _ in 0..T::COUNT {
+        fields.push(FloatState::new(reader.read(64)?));
+    }
+    let mut floats: Vec<f64> = fields.iter().map(|f| f64::from_bits(f.previous)).collect();
+    entries.push(TimeSeriesEntry {
+        time,
+        value: T::from_fields(&floats),
+        quality,
+    });
+
+    let mut delta = 0u64;
+    for _ in 1..count {
+        delta = delta.wrapping_add(read_delta_of_delta(&mut reader)? as u64);
+        time = time.wrapping_add(delta);
+        if reader.read(1)? == 1 {
+            quality = quality_from_bits(reader.read(2)?);
+        }
+        for (field, float) in fields.iter_mut().zip(floats.iter_mut()) {
+            *float = f64::from_bits(field.decode(&mut reader)?);
+        }
+        entries.push(TimeSeriesEntry {
+            time,
+            value: T::from_fields(&floats),
+            quality,
+        });
+    }
+    if entries.windows(2).any(|pair| pair[0].time > pair[1].time) {
+        anyhow::bail!("Gorilla segment holds values that aren't sorted by time");
+    }
+    Ok((init_size, entries))
+}
+
+/// bit lengths of the delta-of-delta buckets after their prefixes 10, 110 and 1110; larger
+/// ones follow 1111 in full
+const DELTA_OF_DELTA_BITS: [u32; 3] = [7, 9, 12];
+
+fn write_delta_of_delta(writer: &mut BitWriter, delta_of_delta: i64) {
+    if delta_of_delta == 0 {
+        writer.write(0, 1);
+        return;
+    }
+    for (i, bits) in DELTA_OF_DELTA_BITS.iter().enumerate() {
+        let bound = 1i64 << (bits - 1);
+        if (-bound..bound).contains(&delta_of_delta) {
+            // i + 1 ones and a zero
+            writer.write((1 << (i + 2)) - 2, i as u32 + 2);
+            writer.write(delta_of_delta as u64 & ((1 << bits) - 1), *bits);
+            return;
+        }
+    }
+    writer.write(0b1111, 4);
+    writer.write(delta_of_delta as u64, 64);
+}
+
+fn read_delta_of_delta(reader: &mut BitReader<'_>) -> anyhow::Result<i64> {
+    let mut ones = 0;
+    while ones < 4 && reader.read(1)? == 1 {
+        ones += 1;
+    }
+    match ones {
+        0 => Ok(0),
+        1..=3 => read_signed(reader, DELTA_OF_DELTA_BITS[ones - 1]),
+        _ => Ok(reader.read(64)? as i64),
+    }
+}
+
+/// reads a two's complement number of the given length
+fn read_signed(reader: &mut BitReader<'_>, bits: u32) -> anyhow::Result<i64> {
+    let value = reader.read(bits)?;
+    Ok(((value << (64 - bits)) as i64) >> (64 - bits))
+}
+
+fn quality_bits(quality: Quality) -> u64 {
+    match quality {
+        Quality::Measured => 0,
+        Quality::Interpolated => 1,
+        Quality::Backfilled => 2,
+        Quality::Suspect => 3,
+    }
+}
+
+fn quality_from_bits(bits: u64) -> Quality {
+    match bits {
+        0 => Quality::Measured,
+        1 => Quality::Interpolated,
+        2 => Quality::Backfilled,
+        _ => Quality::Suspect,
+    }
+}
+
+/// The previous bits of a float and the window of meaningful bits of the last XOR stored
+struct FloatState {
+    previous: u64,
+    /// leading and trailing zeros of the window
+    window: Option<(u32, u32)>,
+}
+
+impl FloatState {
+    fn new(bits: u64) -> Self {
+        FloatState {
+            previous: bits,
+            window: None,
+        }
+    }
+
+    fn encode(&mut self, writer: &mut BitWriter, bits: u64) {
+        let xor = bits ^ self.previous;
+        self.previous = bits;
+        if xor == 0 {
+            writer.write(0, 1);
+            return;
+        }
+        writer.write(1, 1);
+
+        // the number of leading zeros is stored in 5 bits
+        let leading = xor.leading_zeros().min(31);
+        let trailing = xor.trailing_zeros();
+        match self.window {
+            Some((window_leading, window_trailing))
+                if leading >= window_leading && trailing >= window_trailing =>
+            {
+                writer.write(0, 1);
+                writer.write(
+                    xor >> window_trailing,
+                    64 - window_leading - window_trailing,
+                );
+            }
+            _ => {
+                let meaningful = 64 - leading - trailing;
+                writer.write(1, 1);
+                writer.write(leading as u64, 5);
+                writer.write(meaningful as u64 - 1, 6);
+                writer.write(xor >> trailing, meaningful);
+                self.window = Some((leading, trailing));
+            }
+        }
+    }
+
+    fn decode(&mut self, reader: &mut BitReader<'_>) -> anyhow::Result<u64> {
+        if reader.read(1)? == 0 {
+            return Ok(self.previous);
+        }
+        let (leading, trailing) = match (reader.read(1)?, self.window) {
+            (0, Some(window)) => window,
+            (0, None) => anyhow::bail!("Gorilla segment reuses a window it never set"),
+            _ => {
+                let leading = reader.read(5)? as u32;
+                let meaningful = reader.read(6)? as u32 + 1;
+                if leading + meaningful > 64 {
+                    anyhow::bail!("Gorilla segment holds a window beyond 64 bits");
+                }
+                let window = (leading, 64 - leading - meaningful);
+                self.window = Some(window);
+                window
+            }
+        };
+        let xor = reader.read(64 - leading - trailing)? << trailing;
+        self.previous ^= xor;
+        Ok(self.previous)
+    }
+}
+
+/// Writes bits most significant first
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    /// bits of the last byte that haven't been written yet
+    free: u32,
+}
+
+impl BitWriter {
+    /// writes the lowest `count` bits of the value
+    fn write(&mut self, value: u64, count: u32) {
+        let mut remaining = count;
+        while remaining > 0 {
+            if self.free == 0 {
+                self.bytes.push(0);
+                self.free = 8;
+            }
+            let take = remaining.min(self.free);
+            let chunk = (value >> (remaining - take)) & ((1 << take) - 1);
+            *self.bytes.last_mut().unwrap() |= (chunk as u8) << (self.free - take);
+            self.free -= take;
+            remaining -= take;
+        }
+    }
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    /// in bits
+    position: usize,
+}
+
+impl BitReader<'_> {
+    fn read(&mut self, count: u32) -> anyhow::Result<u64> {
+        let mut value = 0u64;
+        let mut remaining = count;
+        while remaining > 0 {
+            let Some(byte) = self.bytes.get(self.position / 8) else {
+                anyhow::bail!("Gorilla segment is truncated");
+            };
+            let available = 8 - (self.position % 8) as u32;
+            let take = remaining.min(available);
+            let chunk = (*byte as u64 >> (available - take)) & ((1 << take) - 1);
+            value = (value << take) | chunk;
+            self.position += take as usize;
+            remaining -= take;
+        }
+        Ok(value)
+    }
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+fn read_varint(bytes: &[u8], offset: &mut usize) -> anyhow::Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let Some(byte) = bytes.get(*offset) else {
+            anyhow::bail!("Gorilla segment is truncated");
+        };
+        *offset += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    anyhow::bail!("Gorilla segment holds an overlong number")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(time: u64, value: [f64; 2], quality: Quality) -> TimeSeriesEntry<[f64; 2]> {
+        TimeSeriesEntry {
+            time,
+            value,
+            quality,
+        }
+    }
+
+    #[test]
+    fn test_bits() {
+        let mut writer = BitWriter::default();
+        writer.write(0b101, 3);
+        writer.write(u64::MAX, 64);
+        writer.write(0, 0);
+        writer.write(0x1234, 13);
+        let mut reader = BitReader {
+            bytes: &writer.bytes,
+            position: 0,
+        };
+        assert_eq!(reader.read(3).unwrap(), 0b101);
+        assert_eq!(reader.read(64).unwrap(), u64::MAX);
+        assert_eq!(reader.read(13).unwrap(), 0x1234 & 0x1fff);
+        assert!(reader.read(8).is_err());
+    }
+
+    #[test]
+    fn test_delta_of_delta_buckets() {
+        let values = [
+            0,
+            1,
+            -1,
+            63,
+            -64,
+            64,
+            255,
+            -256,
+            256,
+            2047,
+            -2048,
+            2048,
+            i64::MIN,
+            i64::MAX,
+        ];
+        let mut writer = BitWriter::default();
+        for value in values {
+            write_delta_of_delta(&mut writer, value);
+        }
+        let mut reader = BitReader {
+            bytes: &writer.bytes,
+            position: 0,
+        };
+        for value in values {
+            assert_eq!(read_delta_of_delta(&mut reader).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let entries = vec![
+            entry(1_000, [0.0, -0.0], Quality::Measured),
+            entry(2_000, [1500.25, f64::NAN], Quality::Measured),
+            entry(3_001, [1500.25, f64::INFINITY], Quality::Interpolated),
+            entry(3_001, [f64::MIN_POSITIVE, f64::MAX], Quality::Suspect),
+            entry(u64::MAX - 5, [-3.5e-300, 42.0], Quality::Backfilled),
+            entry(u64::MAX, [1e300, 42.0], Quality::Backfilled),
+        ];
+        let bytes = encode(17, &entries);
+        let (init_size, decoded) = decode::<[f64; 2]>(&bytes).unwrap();
+        assert_eq!(init_size, 17);
+        assert_eq!(decoded.len(), entries.len());
+        for (decoded, entry) in decoded.iter().zip(&entries) {
+            assert_eq!(decoded.time, entry.time);
+            assert_eq!(decoded.quality, entry.quality);
+            for i in 0..2 {
+                assert_eq!(decoded.value[i].to_bits(), entry.value[i].to_bits());
+            }
+        }
+
+        let (_, empty) = decode::<f64>(&encode::<f64>(3, &[])).unwrap();
+        assert!(empty.is_empty());
+        assert!(decode::<[f64; 2]>(&bytes[..bytes.len() - 3]).is_err());
+    }
+}
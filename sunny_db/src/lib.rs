@@ -1,3 +1,22 @@
+pub mod aggregates;
+pub mod alignment;
+#[cfg(feature = "catalog")]
+pub mod catalog;
+pub mod codec;
+pub mod downsampling;
+pub mod encryption;
+pub mod error;
+pub mod fields;
+pub mod gorilla;
+pub mod manifest;
+pub mod quantization;
+pub mod raw_segments;
+pub mod remote;
+pub mod rollup;
+pub mod running_statistics;
+pub mod smoothing;
 pub mod statistics;
 pub mod timeseries;
 pub mod timeseries_db;
+pub mod verify;
+pub mod write_metrics;
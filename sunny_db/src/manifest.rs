@@ -0,0 +1,198 @@
+use anyhow::{self, Context};
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Name of the manifest file next to the data directory
+pub const MANIFEST_FILE: &str = ".manifest";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Change {
+    Added,
+    Removed,
+}
+
+/// An entry of the manifest, the numbered log of the segments that were added to or removed
+/// from a database; mirrors can catch up incrementally by asking for the entries after the
+/// last one they've seen. Segments that were rewritten (e.g. when archiving them) are added
+/// again with their new size
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ManifestEntry {
+    pub seq: u64,
+    pub change: Change,
+    /// start and end of the segment in ms, as in its file name
+    pub segment: (u64, u64),
+    /// size of the segment file in bytes; 0 for removed segments
+    pub size: u64,
+}
+
+impl ManifestEntry {
+    fn to_line(self) -> String {
+        let change = match self.change {
+            Change::Added => '+',
+            Change::Removed => '-',
+        };
+        format!(
+            "{} {} {}-{} {}\n",
+            self.seq, change, self.segment.0, self.segment.1, self.size
+        )
+    }
+
+    fn parse(line: &str) -> Option<ManifestEntry> {
+        let mut fields = line.split_whitespace();
+        let seq = fields.next()?.parse().ok()?;
+        let change = match fields.next()? {
+            "+" => Change::Added,
+            "-" => Change::Removed,
+            _ => return None,
+        };
+        let (start, end) = fields.next()?.split_once('-')?;
+        let size = fields.next()?.parse().ok()?;
+        Some(ManifestEntry {
+            seq,
+            change,
+            segment: (start.parse().ok()?, end.parse().ok()?),
+            size,
+        })
+    }
+}
+
+/// reads all entries of the manifest; a missing manifest is empty
+pub fn read(path: &Path) -> anyhow::Result<Vec<ManifestEntry>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Couldn't read manifest {}", path.display()))?;
+    contents
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            ManifestEntry::parse(line)
+                .with_context(|| format!("Invalid line {} in manifest {}", i + 1, path.display()))
+        })
+        .collect()
+}
+
+/// the sequence number of the last entry, found without reading the whole manifest
+pub fn last_seq(path: &Path) -> anyhow::Result<u64> {
+    if !path.exists() {
+        return Ok(0);
+    }
+    // entries are far shorter than this
+    const TAIL: u64 = 256;
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(TAIL)))?;
+    let mut tail = String::new();
+    file.read_to_string(&mut tail)?;
+    match tail.lines().last() {
+        Some(line) => ManifestEntry::parse(line)
+            .map(|e| e.seq)
+            .with_context(|| format!("Invalid last line in manifest {}", path.display())),
+        None => Ok(0),
+    }
+}
+
+/// appends the changes to the manifest, numbering them after the last entry; returns the
+/// entries appended
+pub fn record(
+    path: &Path,
+    changes: &[(Change, (u64, u64), u64)],
+) -> anyhow::Result<Vec<ManifestEntry>> {
+    let last = last_seq(path)?;
+    let entries: Vec<ManifestEntry> = changes
+        .iter()
+        .zip(last + 1..)
+        .map(|(&(change, segment, size), seq)| ManifestEntry {
+            seq,
+            change,
+            segment,
+            size,
+        })
+        .collect();
+    append(path, &entries)?;
+    Ok(entries)
+}
+
+pub fn append(path: &Path, entries: &[ManifestEntry]) -> std::io::Result<()> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let lines: String = entries.iter().map(|e| e.to_line()).collect();
+    file.write_all(lines.as_bytes())?;
+    file.sync_all()
+}
+
+/// the entries to append to the manifest so it describes the `current` segments and their
+/// sizes
+pub fn changes(
+    entries: &[ManifestEntry],
+    current: &BTreeMap<(u64, u64), u64>,
+) -> Vec<ManifestEntry> {
+    let mut known = BTreeMap::new();
+    for entry in entries {
+        match entry.change {
+            Change::Added => known.insert(entry.segment, entry.size),
+            Change::Removed => known.remove(&entry.segment),
+        };
+    }
+
+    let mut seq = entries.last().map_or(0, |e| e.seq);
+    let mut next = |change, segment, size| {
+        seq += 1;
+        ManifestEntry {
+            seq,
+            change,
+            segment,
+            size,
+        }
+    };
+    let mut changes = Vec::new();
+    for (segment, _) in known.iter().filter(|(s, _)| !current.contains_key(s)) {
+        changes.push(next(Change::Removed, *segment, 0));
+    }
+    for (segment, size) in current {
+        if known.get(segment) != Some(size) {
+            changes.push(next(Change::Added, *segment, *size));
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changes() {
+        let mut current = BTreeMap::from([((0, 10), 100), ((20, 30), 200)]);
+        let mut entries = changes(&[], &current);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].seq, 2);
+        assert!(changes(&entries, &current).is_empty());
+
+        // removed, rewritten and new segments
+        current.remove(&(0, 10));
+        current.insert((20, 30), 150);
+        current.insert((40, 50), 300);
+        let new = changes(&entries, &current);
+        let summary: Vec<(u64, Change, (u64, u64))> =
+            new.iter().map(|e| (e.seq, e.change, e.segment)).collect();
+        assert_eq!(
+            summary,
+            vec![
+                (3, Change::Removed, (0, 10)),
+                (4, Change::Added, (20, 30)),
+                (5, Change::Added, (40, 50)),
+            ]
+        );
+
+        entries.extend(new);
+        let lines: String = entries.iter().map(|e| e.to_line()).collect();
+        let parsed: Vec<ManifestEntry> = lines.lines().flat_map(ManifestEntry::parse).collect();
+        assert_eq!(parsed, entries);
+    }
+}
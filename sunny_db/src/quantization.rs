@@ -0,0 +1,164 @@
+use crate::fields::{map_fields, FloatFields};
+use crate::timeseries::{Quality, TimeSeriesEntry};
+use bitcode::{Decode, Encode};
+
+/// Values can be rounded to at most this many decimal places, or to multiples of up to this
+/// power of ten with negative ones
+pub const MAX_DECIMALS: i8 = 9;
+
+/// Values rounded to a number of decimal places and stored column by column as integers
+/// scaled by the corresponding power of ten, e.g. 1234 for 123.4 W with one decimal place
+#[derive(Encode, Decode)]
+struct QuantizedBody {
+    init_size: u64,
+    times: Vec<u64>,
+    qualities: Vec<Quality>,
+    fields: Vec<Vec<i32>>,
+}
+
+/// the value scaled to an integer of the given precision, but still as float
+fn scale(value: f64, decimals: i8) -> f64 {
+    let factor = 10f64.powi(decimals.unsigned_abs() as i32);
+    // dividing by 0.1 isn't exact, dividing by 10 is
+    match decimals >= 0 {
+        true => (value * factor).round(),
+        false => (value / factor).round(),
+    }
+}
+
+fn unscale(scaled: f64, decimals: i8) -> f64 {
+    let factor = 10f64.powi(decimals.unsigned_abs() as i32);
+    match decimals >= 0 {
+        true => scaled / factor,
+        false => scaled * factor,
+    }
+}
+
+/// the entries with their values scaled to integers of the given precision, e.g. to store
+/// them in the gorilla encoding, in which integral floats take fewer bits
+pub(crate) fn scale_entries<T: FloatFields>(
+    entries: &[TimeSeriesEntry<T>],
+    decimals: i8,
+) -> Vec<TimeSeriesEntry<T>> {
+    entries
+        .iter()
+        .map(|entry| TimeSeriesEntry {
+            value: map_fields(&entry.value, |v| scale(v, decimals)),
+            ..*entry
+        })
+        .collect()
+}
+
+/// undoes `scale_entries`
+pub(crate) fn unscale_entries<T: FloatFields>(entries: &mut [TimeSeriesEntry<T>], decimals: i8) {
+    for entry in entries {
+        entry.value = map_fields(&entry.value, |v| unscale(v, decimals));
+    }
+}
+
+/// encodes the entries of a segment with their values as scaled integers; None if a value
+/// isn't a number or doesn't fit into an i32 at that precision
+pub(crate) fn encode<T: FloatFields>(
+    init_size: usize,
+    entries: &[TimeSeriesEntry<T>],
+    decimals: i8,
+) -> Option<Vec<u8>> {
+    let mut fields = vec![Vec::with_capacity(entries.len()); T::COUNT];
+    for entry in entries {
+        for (i, column) in fields.iter_mut().enumerate() {
+            let scaled = scale(entry.value.field(i), decimals);
+            if !(i32::MIN as f64..=i32::MAX as f64).contains(&scaled) {
+                return None;
+            }
+            column.push(scaled as i32);
+        }
+    }
+    Some(bitcode::encode(&QuantizedBody {
+        init_size: init_size as u64,
+        times: entries.iter().map(|entry| entry.time).collect(),
+        qualities: entries.iter().map(|entry| entry.quality).collect(),
+        fields,
+    }))
+}
+
+/// decodes the initial size and the entries of a segment written by `encode`
+pub(crate) fn decode<T: FloatFields>(
+    bytes: &[u8],
+    decimals: i8,
+) -> anyhow::Result<(usize, Vec<TimeSeriesEntry<T>>)> {
+    let body: QuantizedBody = bitcode::decode(bytes)?;
+    let count = body.times.len();
+    if body.qualities.len() != count
+        || body.fields.len() != T::COUNT
+        || body.fields.iter().any(|column| column.len() != count)
+    {
+        anyhow::bail!("Quantized segment doesn't hold as many fields as the values have");
+    }
+    if body.times.windows(2).any(|pair| pair[0] > pair[1]) {
+        anyhow::bail!("Quantized segment holds values that aren't sorted by time");
+    }
+
+    let mut fields = vec![0.0; T::COUNT];
+    let entries = (0..count)
+        .map(|i| {
+            for (field, column) in fields.iter_mut().zip(&body.fields) {
+                *field = unscale(column[i] as f64, decimals);
+            }
+            TimeSeriesEntry {
+                time: body.times[i],
+                value: T::from_fields(&fields),
+                quality: body.qualities[i],
+            }
+        })
+        .collect();
+    Ok((body.init_size as usize, entries))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(values: &[f64]) -> Vec<TimeSeriesEntry<f64>> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| TimeSeriesEntry {
+                time: i as u64 * 1000,
+                value: *value,
+                quality: Quality::Measured,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_quantization() {
+        let values = [0.0, 1234.56, -17.04, 0.05, 99999.99];
+        let bytes = encode(5, &entries(&values), 1).unwrap();
+        let (init_size, decoded) = decode::<f64>(&bytes, 1).unwrap();
+        assert_eq!(init_size, 5);
+        let decoded: Vec<f64> = decoded.iter().map(|entry| entry.value).collect();
+        assert_eq!(decoded, [0.0, 1234.6, -17.0, 0.1, 100000.0]);
+
+        // multiples of 100
+        let bytes = encode(5, &entries(&[1234.5, -60.0]), -2).unwrap();
+        let (_, decoded) = decode::<f64>(&bytes, -2).unwrap();
+        assert_eq!(decoded[0].value, 1200.0);
+        assert_eq!(decoded[1].value, -100.0);
+
+        // values that don't fit are left to the caller
+        assert!(encode(5, &entries(&[3e9]), 0).is_none());
+        assert!(encode(5, &entries(&[3e8]), 1).is_none());
+        assert!(encode(5, &entries(&[f64::NAN]), 1).is_none());
+        assert!(decode::<[f64; 2]>(&bytes, -2).is_err());
+    }
+
+    #[test]
+    fn test_scale_entries() {
+        let mut scaled = scale_entries(&entries(&[1.25, -3.125]), 2);
+        assert_eq!(scaled[0].value, 125.0);
+        assert_eq!(scaled[1].value, -313.0);
+        unscale_entries(&mut scaled, 2);
+        assert_eq!(scaled[0].value, 1.25);
+        assert_eq!(scaled[1].value, -3.13);
+    }
+}
@@ -0,0 +1,120 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::codec::Codec;
+use crate::timeseries::SegmentInfo;
+use crate::timeseries_db::SunnyDB;
+
+/// Where a persisted segment is stored
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StorageTier {
+    Hot,
+    /// archived to the cold data directory
+    Cold,
+    /// offloaded to an object store
+    Remote,
+}
+
+/// A persisted segment as it's stored, i.e. still compressed (and encrypted, if the database
+/// is), so it can be copied without decoding it
+#[derive(Debug)]
+pub struct RawSegment {
+    /// time of the first and the last value in ms, as in its file name
+    pub segment: (u64, u64),
+    pub tier: StorageTier,
+    /// what the header says about the encoding; None for segments without a readable one
+    pub info: Option<SegmentInfo>,
+    pub bytes: Vec<u8>,
+}
+
+impl RawSegment {
+    /// the file name of the segment, e.g. `1717200000000-1717203600000`
+    pub fn name(&self) -> String {
+        format!("{}-{}", self.segment.0, self.segment.1)
+    }
+}
+
+/// Where to continue iterating the segments, e.g. of a backup tool between runs: the sequence
+/// number of the last manifest entry seen, so segments added later are found even if they're
+/// older than the ones seen, like backfilled or compacted ones. It's written and parsed as the
+/// number
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SegmentCursor {
+    pub seq: u64,
+}
+
+impl fmt::Display for SegmentCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.seq)
+    }
+}
+
+impl FromStr for SegmentCursor {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse() {
+            Ok(seq) => Ok(SegmentCursor { seq }),
+            Err(_) => anyhow::bail!("Invalid segment cursor {}, expected a sequence number", s),
+        }
+    }
+}
+
+/// Iterates the persisted segments in time order, see `SunnyDB::raw_segments`
+pub struct RawSegments<'a, T> {
+    db: &'a SunnyDB<T>,
+    segments: std::vec::IntoIter<(u64, u64)>,
+    cursor: SegmentCursor,
+}
+
+impl<'a, T> RawSegments<'a, T> {
+    pub(crate) fn new(
+        db: &'a SunnyDB<T>,
+        segments: Vec<(u64, u64)>,
+        cursor: SegmentCursor,
+    ) -> Self {
+        RawSegments {
+            db,
+            segments: segments.into_iter(),
+            cursor,
+        }
+    }
+
+    /// where the next run continues from once all segments have been iterated; segments
+    /// added while iterating may be returned again then
+    pub fn cursor(&self) -> SegmentCursor {
+        self.cursor
+    }
+}
+
+impl<T: Codec> Iterator for RawSegments<'_, T> {
+    type Item = anyhow::Result<RawSegment>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for segment in self.segments.by_ref() {
+            match self.db.read_raw_segment(segment) {
+                Ok(Some(raw)) => return Some(Ok(raw)),
+                // replaced by compacting since the segments were listed
+                Ok(None) => continue,
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor() {
+        let cursor: SegmentCursor = "42".parse().unwrap();
+        assert_eq!(cursor.seq, 42);
+        assert_eq!(cursor.to_string(), "42");
+        assert!("1717200000000-1717203600000"
+            .parse::<SegmentCursor>()
+            .is_err());
+        assert!("a".parse::<SegmentCursor>().is_err());
+    }
+}
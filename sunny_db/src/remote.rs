@@ -0,0 +1,359 @@
+use anyhow::Context;
+use chrono::{DateTime, Datelike, NaiveDate};
+use std::collections::BTreeSet;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::RwLock;
+use std::time::SystemTime;
+
+use crate::error::SunnyDbError;
+
+/// Name of the file next to the data directory listing the segments that have been offloaded
+/// to the remote tier, so they can be found without asking the object store
+pub const REMOTE_SEGMENTS_FILE: &str = ".remote-segments";
+
+/// Storage for the segments offloaded from the local tiers, e.g. an S3 bucket; objects are
+/// addressed by keys like `2024/06/01/1717200000000-1717203600000`
+pub trait ObjectStore: Send + Sync {
+    fn put(&self, key: &str, data: &[u8]) -> anyhow::Result<()>;
+    fn get(&self, key: &str) -> anyhow::Result<Vec<u8>>;
+}
+
+/// An object store in a local directory, e.g. a network share mounted on the Pi
+pub struct DirectoryStore {
+    root: PathBuf,
+}
+
+impl DirectoryStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        DirectoryStore { root: root.into() }
+    }
+}
+
+impl ObjectStore for DirectoryStore {
+    fn put(&self, key: &str, data: &[u8]) -> anyhow::Result<()> {
+        let path = self.root.join(key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // write to a temporary file first, so no partial object ever shows up under the key
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, data)?;
+        fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> anyhow::Result<Vec<u8>> {
+        let path = self.root.join(key);
+        fs::read(&path).with_context(|| format!("Couldn't read {}", path.display()))
+    }
+}
+
+/// The remote tier of a database: the object store and the list of segments offloaded to it,
+/// which is kept locally and re-read whenever another instance changed it
+pub(crate) struct RemoteTier {
+    store: Box<dyn ObjectStore>,
+    segments_path: PathBuf,
+    segments: RwLock<OffloadedSegments>,
+}
+
+/// The offloaded segments as of the time the list was last modified
+#[derive(Default)]
+struct OffloadedSegments {
+    modified: Option<SystemTime>,
+    segments: BTreeSet<(u64, u64)>,
+}
+
+impl RemoteTier {
+    pub(crate) fn open(
+        store: Box<dyn ObjectStore>,
+        segments_path: PathBuf,
+    ) -> Result<Self, SunnyDbError> {
+        let tier = RemoteTier {
+            store,
+            segments_path,
+            segments: RwLock::new(OffloadedSegments::default()),
+        };
+        tier.reload()?;
+        Ok(tier)
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        fs::metadata(&self.segments_path)
+            .and_then(|m| m.modified())
+            .ok()
+    }
+
+    fn reload(&self) -> Result<(), SunnyDbError> {
+        let modified = self.modified();
+        let segments = match fs::read_to_string(&self.segments_path) {
+            Ok(contents) => contents.lines().filter_map(parse_segment).collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeSet::new(),
+            Err(e) => return Err(e.into()),
+        };
+        *self.segments.write().unwrap() = OffloadedSegments { modified, segments };
+        Ok(())
+    }
+
+    /// the bytes held by the list of offloaded segments, leaving out the overhead of the tree
+    pub(crate) fn memory_usage(&self) -> usize {
+        self.segments.read().unwrap().segments.len() * std::mem::size_of::<(u64, u64)>()
+    }
+
+    /// the offloaded segments overlapping [start_time, end_time] (in ms)
+    pub(crate) fn segments_in(&self, start_time: u64, end_time: u64) -> Vec<(u64, u64)> {
+        let outdated = self.segments.read().unwrap().modified != self.modified();
+        if outdated {
+            if let Err(e) = self.reload() {
+                tracing::warn!(error = %e, "Couldn't read the list of offloaded segments");
+            }
+        }
+        let offloaded = self.segments.read().unwrap();
+        offloaded
+            .segments
+            .iter()
+            .filter(|(start, end)| *start <= end_time && *end >= start_time)
+            .copied()
+            .collect()
+    }
+
+    pub(crate) fn contains(&self, segment: &(u64, u64)) -> bool {
+        self.segments.read().unwrap().segments.contains(segment)
+    }
+
+    /// uploads the segment and records it as offloaded; the local file may be removed after
+    pub(crate) fn offload(
+        &self,
+        key: &str,
+        segment: (u64, u64),
+        data: &[u8],
+    ) -> anyhow::Result<()> {
+        self.store.put(key, data)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.segments_path)?;
+        file.write_all(format!("{}-{}\n", segment.0, segment.1).as_bytes())?;
+        file.sync_all()?;
+        let mut offloaded = self.segments.write().unwrap();
+        offloaded.segments.insert(segment);
+        offloaded.modified = self.modified();
+        Ok(())
+    }
+
+    pub(crate) fn fetch(&self, key: &str) -> anyhow::Result<Vec<u8>> {
+        self.store.get(key)
+    }
+}
+
+fn parse_segment(line: &str) -> Option<(u64, u64)> {
+    let (start, end) = line.trim().split_once('-')?;
+    Some((start.parse().ok()?, end.parse().ok()?))
+}
+
+/// the key of a segment in the object store; the date-partitioned layout of the data
+/// directory is kept, so the objects can be copied back into one as they are
+pub(crate) fn segment_key(segment: (u64, u64)) -> String {
+    let day = i64::try_from(segment.0)
+        .ok()
+        .and_then(DateTime::from_timestamp_millis)
+        .map(|t| t.date_naive())
+        .unwrap_or(NaiveDate::MAX);
+    format!(
+        "{:04}/{:02}/{:02}/{}-{}",
+        day.year(),
+        day.month(),
+        day.day(),
+        segment.0,
+        segment.1
+    )
+}
+
+#[cfg(feature = "s3")]
+pub use s3::S3Store;
+
+#[cfg(feature = "s3")]
+mod s3 {
+    use super::ObjectStore;
+    use anyhow::Context;
+    use hmac_sha256::{Hash, HMAC};
+    use std::time::Duration;
+
+    const TIMEOUT: Duration = Duration::from_secs(60);
+
+    /// An S3-compatible object store (AWS, MinIO, Garage, ...) addressed with path-style URLs
+    /// like `<endpoint>/<bucket>/<prefix><key>`; requests are signed with AWS Signature V4
+    pub struct S3Store {
+        pub endpoint: String,
+        pub bucket: String,
+        pub region: String,
+        /// prepended to all keys, e.g. `sunny/`
+        pub prefix: String,
+        pub access_key_id: String,
+        pub secret_access_key: String,
+    }
+
+    impl S3Store {
+        /// the host (with a non-default port) the endpoint points to
+        fn host(&self) -> &str {
+            let authority = self
+                .endpoint
+                .split_once("://")
+                .map_or(self.endpoint.as_str(), |(_, rest)| rest);
+            let authority = authority.split('/').next().unwrap_or(authority);
+            let default_port = match self.endpoint.starts_with("https://") {
+                true => ":443",
+                false => ":80",
+            };
+            authority.strip_suffix(default_port).unwrap_or(authority)
+        }
+
+        fn path(&self, key: &str) -> String {
+            format!("/{}/{}{}", self.bucket, self.prefix, key)
+        }
+
+        /// the headers authorizing a request, see
+        /// https://docs.aws.amazon.com/AmazonS3/latest/API/sig-v4-header-based-auth.html
+        fn authorization(
+            &self,
+            method: &str,
+            path: &str,
+            payload: &[u8],
+            now: chrono::DateTime<chrono::Utc>,
+        ) -> [(&'static str, String); 3] {
+            let date = now.format("%Y%m%d").to_string();
+            let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+            let payload_hash = hex(&Hash::hash(payload));
+            let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+            let canonical_request = format!(
+                "{}\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+                method,
+                path,
+                self.host(),
+                payload_hash,
+                timestamp,
+                signed_headers,
+                payload_hash
+            );
+            let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+            let string_to_sign = format!(
+                "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+                timestamp,
+                scope,
+                hex(&Hash::hash(canonical_request.as_bytes()))
+            );
+            let key = signing_key(&self.secret_access_key, &date, &self.region, "s3");
+            let signature = hex(&HMAC::mac(string_to_sign.as_bytes(), key));
+            let authorization = format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                self.access_key_id, scope, signed_headers, signature
+            );
+            [
+                ("authorization", authorization),
+                ("x-amz-content-sha256", payload_hash),
+                ("x-amz-date", timestamp),
+            ]
+        }
+
+        fn request(&self, method: &str, key: &str, payload: &[u8]) -> anyhow::Result<Vec<u8>> {
+            let path = self.path(key);
+            let url = format!("{}{}", self.endpoint.trim_end_matches('/'), path);
+            let mut request = match method {
+                "PUT" => attohttpc::put(&url),
+                _ => attohttpc::get(&url),
+            }
+            .timeout(TIMEOUT);
+            for (name, value) in self.authorization(method, &path, payload, chrono::Utc::now()) {
+                request = request.header(name, value);
+            }
+            let response = request
+                .bytes(payload)
+                .send()
+                .with_context(|| format!("{} {} failed", method, url))?;
+            let status = response.status();
+            let body = response.bytes()?;
+            if !status.is_success() {
+                anyhow::bail!(
+                    "{} {} failed with {}: {}",
+                    method,
+                    url,
+                    status,
+                    String::from_utf8_lossy(&body)
+                );
+            }
+            Ok(body)
+        }
+    }
+
+    impl ObjectStore for S3Store {
+        fn put(&self, key: &str, data: &[u8]) -> anyhow::Result<()> {
+            self.request("PUT", key, data).map(|_| ())
+        }
+
+        fn get(&self, key: &str) -> anyhow::Result<Vec<u8>> {
+            self.request("GET", key, &[])
+        }
+    }
+
+    fn signing_key(secret: &str, date: &str, region: &str, service: &str) -> [u8; 32] {
+        let date_key = HMAC::mac(date.as_bytes(), format!("AWS4{}", secret).as_bytes());
+        let region_key = HMAC::mac(region.as_bytes(), date_key);
+        let service_key = HMAC::mac(service.as_bytes(), region_key);
+        HMAC::mac(b"aws4_request", service_key)
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_signing_key() {
+            // the example of the AWS documentation on deriving signing keys
+            let key = signing_key(
+                "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+                "20120215",
+                "us-east-1",
+                "iam",
+            );
+            assert_eq!(
+                hex(&key),
+                "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+            );
+        }
+
+        #[test]
+        fn test_host() {
+            let store = |endpoint: &str| S3Store {
+                endpoint: endpoint.to_owned(),
+                bucket: String::from("bucket"),
+                region: String::from("us-east-1"),
+                prefix: String::new(),
+                access_key_id: String::new(),
+                secret_access_key: String::new(),
+            };
+            assert_eq!(store("https://s3.amazonaws.com").host(), "s3.amazonaws.com");
+            assert_eq!(store("https://minio.local:443/").host(), "minio.local");
+            assert_eq!(store("http://192.168.1.2:9000").host(), "192.168.1.2:9000");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segment_key() {
+        assert_eq!(
+            segment_key((1717200000000, 1717203600000)),
+            "2024/06/01/1717200000000-1717203600000"
+        );
+        assert_eq!(parse_segment("1-2\n"), Some((1, 2)));
+        assert_eq!(parse_segment("1-x"), None);
+    }
+}
@@ -0,0 +1,123 @@
+use crate::codec::Codec;
+use crate::timeseries::TimeSeries;
+
+/// How the values within an interval are combined into a single one
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Aggregation {
+    Average,
+    Min,
+    Max,
+    Sum,
+    Count,
+    Last,
+}
+
+/// Running aggregate of the values in one interval; values can be added one at a time, so
+/// the state can be kept up to date while values come in
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub struct AggregateState {
+    count: usize,
+    sum: f64,
+    min: f64,
+    max: f64,
+    last: f64,
+}
+
+impl AggregateState {
+    pub fn push(&mut self, value: f64) {
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.count += 1;
+        self.sum += value;
+        self.last = value;
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// the aggregated value; None if no values have been added
+    pub fn value(&self, aggregation: Aggregation) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        let value = match aggregation {
+            Aggregation::Average => self.sum / self.count as f64,
+            Aggregation::Min => self.min,
+            Aggregation::Max => self.max,
+            Aggregation::Sum => self.sum,
+            Aggregation::Count => self.count as f64,
+            Aggregation::Last => self.last,
+        };
+        Some(value)
+    }
+}
+
+/// the start of the interval of the given length containing time; intervals are aligned to
+/// multiples of their length
+pub fn interval_start(time: u64, interval: u64) -> u64 {
+    time - time % interval
+}
+
+/// aggregates the values derived from the series via `value` per interval; returns the start
+/// of each interval containing any values together with the aggregated value
+pub fn rollup<T: Codec>(
+    series: &TimeSeries<T>,
+    interval: u64,
+    aggregation: Aggregation,
+    value: impl Fn(&T) -> f64,
+) -> Vec<(u64, f64)> {
+    assert!(interval > 0, "Rollup intervals must not be empty!");
+    let mut rolled_up = Vec::new();
+    let mut current: Option<(u64, AggregateState)> = None;
+    for (time, v) in series {
+        let start = interval_start(time, interval);
+        match &mut current {
+            Some((current_start, state)) if *current_start == start => state.push(value(v)),
+            _ => {
+                if let Some((current_start, state)) = current.take() {
+                    rolled_up.extend(state.value(aggregation).map(|v| (current_start, v)));
+                }
+                let mut state = AggregateState::default();
+                state.push(value(v));
+                current = Some((start, state));
+            }
+        }
+    }
+    if let Some((current_start, state)) = current {
+        rolled_up.extend(state.value(aggregation).map(|v| (current_start, v)));
+    }
+    rolled_up
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rollup() {
+        let mut series = TimeSeries::<f64>::new(6);
+        for (time, value) in [(0, 1.0), (500, 3.0), (999, 2.0), (1000, 10.0), (3500, -1.0)] {
+            series.insert_value_at_time(time, value);
+        }
+
+        let average = rollup(&series, 1000, Aggregation::Average, |v| *v);
+        assert_eq!(average, vec![(0, 2.0), (1000, 10.0), (3000, -1.0)]);
+        let max = rollup(&series, 1000, Aggregation::Max, |v| *v);
+        assert_eq!(max, vec![(0, 3.0), (1000, 10.0), (3000, -1.0)]);
+        let last = rollup(&series, 2000, Aggregation::Last, |v| *v);
+        assert_eq!(last, vec![(0, 10.0), (2000, -1.0)]);
+        let count = rollup(&series, 2000, Aggregation::Count, |v| *v);
+        assert_eq!(count, vec![(0, 4.0), (2000, 1.0)]);
+        let doubled_sum = rollup(&series, 10000, Aggregation::Sum, |v| 2.0 * v);
+        assert_eq!(doubled_sum, vec![(0, 30.0)]);
+
+        assert!(rollup(&TimeSeries::<f64>::empty(), 1000, Aggregation::Min, |v| *v).is_empty());
+        assert_eq!(AggregateState::default().value(Aggregation::Sum), None);
+    }
+}
@@ -0,0 +1,181 @@
+use crate::fields::FloatFields;
+use crate::timeseries::Resolution;
+
+/// Statistics of the values inserted since some point in time, updated with every value so
+/// e.g. the numbers of the current day can be read in O(1) instead of reading and integrating
+/// its segments again. Fields that aren't a number are skipped
+#[derive(Clone, Debug)]
+pub struct RunningStatistics<T> {
+    /// in the unit of the database; earlier values are ignored
+    pub since: u64,
+    /// number of values since then
+    pub count: u64,
+    /// the latest value and its time
+    pub last: Option<(u64, T)>,
+    sums: Vec<f64>,
+    counts: Vec<u64>,
+    /// trapezoidal integral of each field over hours
+    integral: Vec<f64>,
+    /// hours integrated for each field
+    hours: Vec<f64>,
+    min: Vec<f64>,
+    max: Vec<f64>,
+    units_per_hour: f64,
+}
+
+impl<T: FloatFields + Copy> RunningStatistics<T> {
+    pub fn new(since: u64, resolution: Resolution) -> Self {
+        RunningStatistics {
+            since,
+            count: 0,
+            last: None,
+            sums: vec![0.0; T::COUNT],
+            counts: vec![0; T::COUNT],
+            integral: vec![0.0; T::COUNT],
+            hours: vec![0.0; T::COUNT],
+            min: vec![f64::NAN; T::COUNT],
+            max: vec![f64::NAN; T::COUNT],
+            units_per_hour: 3600.0 * resolution.per_second() as f64,
+        }
+    }
+
+    /// the sum of each field
+    pub fn sum(&self) -> Option<T> {
+        (self.count > 0).then(|| T::from_fields(&self.sums))
+    }
+
+    /// the mean of each field over time, i.e. its integral divided by the hours integrated,
+    /// so irregular sampling doesn't skew it. Fields that haven't been integrated yet, e.g.
+    /// with a single value, fall back to the mean of their values; ones without values are NaN
+    pub fn mean(&self) -> Option<T> {
+        let means: Vec<f64> = (0..T::COUNT)
+            .map(|i| match (self.hours[i], self.counts[i]) {
+                (hours, _) if hours > 0.0 => self.integral[i] / hours,
+                (_, n) if n > 0 => self.sums[i] / n as f64,
+                _ => f64::NAN,
+            })
+            .collect();
+        (self.count > 0).then(|| T::from_fields(&means))
+    }
+
+    /// the integral of each field over time in hours, e.g. in Wh for values in W
+    pub fn integral(&self) -> Option<T> {
+        (self.count > 0).then(|| T::from_fields(&self.integral))
+    }
+
+    pub fn min(&self) -> Option<T> {
+        (self.count > 0).then(|| T::from_fields(&self.min))
+    }
+
+    pub fn max(&self) -> Option<T> {
+        (self.count > 0).then(|| T::from_fields(&self.max))
+    }
+
+    /// adds a value inserted at the time. Values that come out of order are counted but not
+    /// integrated, and neither are pauses of more than an hour, like in the energy aggregates
+    pub fn add(&mut self, time: u64, value: &T) {
+        if time < self.since {
+            return;
+        }
+        self.count += 1;
+        for i in 0..T::COUNT {
+            let field = value.field(i);
+            if field.is_nan() {
+                continue;
+            }
+            self.sums[i] += field;
+            self.counts[i] += 1;
+            // f64::min and f64::max return the other argument if one is NaN
+            self.min[i] = self.min[i].min(field);
+            self.max[i] = self.max[i].max(field);
+        }
+        match self.last {
+            Some((last_time, _)) if time <= last_time => return,
+            Some((last_time, last)) => {
+                let hours = (time - last_time) as f64 / self.units_per_hour;
+                if hours <= 1.0 {
+                    for i in 0..T::COUNT {
+                        let mean = (last.field(i) + value.field(i)) / 2.0;
+                        if !mean.is_nan() {
+                            self.integral[i] += mean * hours;
+                            self.hours[i] += hours;
+                        }
+                    }
+                }
+            }
+            None => {}
+        }
+        self.last = Some((time, *value));
+    }
+}
+
+impl<T> RunningStatistics<T> {
+    /// starts over with the values from `since` on
+    pub fn reset(&mut self, since: u64) {
+        self.since = since;
+        self.count = 0;
+        self.last = None;
+        self.sums.fill(0.0);
+        self.counts.fill(0);
+        self.integral.fill(0.0);
+        self.hours.fill(0.0);
+        self.min.fill(f64::NAN);
+        self.max.fill(f64::NAN);
+    }
+
+    /// the bytes held by the statistics
+    pub fn memory_usage(&self) -> usize {
+        let fields = [
+            &self.sums,
+            &self.integral,
+            &self.hours,
+            &self.min,
+            &self.max,
+        ];
+        std::mem::size_of::<Self>()
+            + fields.iter().map(|field| field.capacity()).sum::<usize>()
+                * std::mem::size_of::<f64>()
+            + self.counts.capacity() * std::mem::size_of::<u64>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_running_statistics() {
+        let hour = 3_600_000;
+        let mut stats = RunningStatistics::<f64>::new(hour, Resolution::Milliseconds);
+        assert_eq!(stats.sum(), None);
+        stats.add(0, &100.0);
+        assert_eq!(stats.count, 0);
+        for (time, value) in [(hour, 1000.0), (2 * hour, 3000.0), (3 * hour, f64::NAN)] {
+            stats.add(time, &value);
+        }
+        stats.add(hour + 1, &5000.0);
+        assert_eq!(stats.count, 4);
+        assert_eq!(stats.sum(), Some(9000.0));
+        // over the hour from 1000 to 3000 W
+        assert_eq!(stats.mean(), Some(2000.0));
+        assert_eq!(stats.min(), Some(1000.0));
+        assert_eq!(stats.max(), Some(5000.0));
+        // the value out of order and the one that isn't a number aren't integrated
+        assert_eq!(stats.integral(), Some(2000.0));
+        assert!(stats.last.unwrap().1.is_nan());
+        // nor is the pause of more than an hour
+        stats.add(5 * hour, &1000.0);
+        stats.add(6 * hour, &1000.0);
+        assert_eq!(stats.integral(), Some(3000.0));
+        assert_eq!(stats.mean(), Some(1500.0));
+
+        stats.reset(10 * hour);
+        assert_eq!(stats.count, 0);
+        assert_eq!(stats.last, None);
+        assert_eq!(stats.integral(), None);
+        stats.add(10 * hour, &2.0);
+        assert_eq!(stats.max(), Some(2.0));
+        // a single value isn't integrated yet
+        assert_eq!(stats.mean(), Some(2.0));
+    }
+}
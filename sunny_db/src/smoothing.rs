@@ -0,0 +1,134 @@
+use crate::codec::Codec;
+use crate::fields::FloatFields;
+
+use crate::timeseries::{Quality, TimeSeries};
+
+pub trait MovingAverage<T> {
+    /// the series with every value replaced by the average of the values at most half the
+    /// window (in ms) before or after it, e.g. to smooth over passing clouds; the average gets
+    /// the quality of the least reliable value in its window. Fields that aren't a number are
+    /// left out of the averages
+    fn moving_average(&self, window_ms: u64) -> TimeSeries<T>;
+}
+
+impl<T> MovingAverage<T> for TimeSeries<T>
+where
+    T: Codec + FloatFields,
+{
+    fn moving_average(&self, window_ms: u64) -> TimeSeries<T> {
+        let resolution = self.get_resolution();
+        let entries = self.get_current_values_with_quality();
+        let mut smoothed = TimeSeries::<T>::with_resolution(entries.len(), resolution);
+        let half_window = window_ms.saturating_mul(resolution.per_second()) / 2000;
+
+        // the sum and the number of the numbers of each field in [start, end) of the window and
+        // how many values of each quality there are, updated as the window slides along
+        let mut sums = vec![0.0; T::COUNT];
+        let mut counts = vec![0usize; T::COUNT];
+        let mut qualities = [0usize; 4];
+        let (mut start, mut end) = (0, 0);
+        for (time, _, _) in &entries {
+            while end < entries.len() && entries[end].0 <= time.saturating_add(half_window) {
+                for i in 0..T::COUNT {
+                    let field = entries[end].1.field(i);
+                    if !field.is_nan() {
+                        sums[i] += field;
+                        counts[i] += 1;
+                    }
+                }
+                qualities[entries[end].2 as usize] += 1;
+                end += 1;
+            }
+            while entries[start].0 < time.saturating_sub(half_window) {
+                for i in 0..T::COUNT {
+                    let field = entries[start].1.field(i);
+                    if !field.is_nan() {
+                        sums[i] -= field;
+                        counts[i] -= 1;
+                    }
+                }
+                qualities[entries[start].2 as usize] -= 1;
+                start += 1;
+            }
+
+            // NaN if the whole window is
+            let averages: Vec<f64> = sums
+                .iter()
+                .zip(&counts)
+                .map(|(sum, n)| if *n > 0 { sum / *n as f64 } else { f64::NAN })
+                .collect();
+            let quality = [
+                Quality::Suspect,
+                Quality::Backfilled,
+                Quality::Interpolated,
+                Quality::Measured,
+            ]
+            .into_iter()
+            .find(|quality| qualities[*quality as usize] > 0)
+            .unwrap_or_default();
+            smoothed.insert_value_with_quality(*time, T::from_fields(&averages), quality);
+        }
+        smoothed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timeseries::Resolution;
+
+    #[test]
+    fn test_moving_average() {
+        let mut ts = TimeSeries::<f64>::new(10);
+        for (i, value) in [1.0, 5.0, 1.0, 5.0, 1.0, 5.0].iter().enumerate() {
+            ts.insert_value_at_time(i as u64 * 1000, *value);
+        }
+        ts.insert_value_with_quality(6000, 1.0, Quality::Backfilled);
+
+        // each value averaged with its neighbours
+        let smoothed = ts.moving_average(2000);
+        let values: Vec<f64> = smoothed.iter().map(|(_, v)| *v).collect();
+        assert_eq!(
+            values,
+            [
+                3.0,
+                7.0 / 3.0,
+                11.0 / 3.0,
+                7.0 / 3.0,
+                11.0 / 3.0,
+                7.0 / 3.0,
+                3.0
+            ]
+        );
+        let qualities: Vec<Quality> = smoothed
+            .get_current_values_with_quality()
+            .iter()
+            .map(|(_, _, quality)| *quality)
+            .collect();
+        assert_eq!(qualities[4], Quality::Measured);
+        assert_eq!(qualities[5], Quality::Backfilled);
+
+        // windows shorter than the sampling interval leave the values as they are
+        assert_eq!(
+            ts.moving_average(500).get_current_values_with_quality(),
+            ts.get_current_values_with_quality()
+        );
+
+        // the window is in ms regardless of the timestamps' unit
+        let mut seconds = TimeSeries::<f64>::with_resolution(10, Resolution::Seconds);
+        seconds.insert_value_at_time(0, 0.0);
+        seconds.insert_value_at_time(1, 2.0);
+        assert_eq!(seconds.moving_average(2000).get_current_values()[0].1, 1.0);
+        assert!(TimeSeries::<f64>::empty().moving_average(1000).is_empty());
+
+        // a missing field is left out of the averages of its neighbours, but not the others
+        let mut gaps = TimeSeries::<[f64; 2]>::new(10);
+        gaps.insert_value_at_time(0, [1.0, 1.0]);
+        gaps.insert_value_at_time(1000, [f64::NAN, 3.0]);
+        gaps.insert_value_at_time(2000, [3.0, 5.0]);
+        let smoothed = gaps.moving_average(2000).get_current_values();
+        assert_eq!(smoothed[0].1, [1.0, 2.0]);
+        assert_eq!(smoothed[1].1, [2.0, 3.0]);
+        assert_eq!(smoothed[2].1, [3.0, 4.0]);
+    }
+}
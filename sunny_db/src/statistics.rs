@@ -1,44 +1,226 @@
-use bitcode::{DecodeOwned, Encode};
+use crate::alignment::{align, Fill};
+use crate::codec::Codec;
+use crate::fields::{map_fields, FloatFields};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, TimeZone, Timelike};
+#[cfg(feature = "serde")]
+use serde::Deserialize;
 use std::{
     cmp::Ordering,
+    collections::BTreeMap,
     ops::{Add, Div, Mul, Sub},
 };
 
-use crate::timeseries::TimeSeries;
+use crate::timeseries::{Quality, TimeSeries, TimeSeriesView};
+
+/// How the integral between the sampled values is approximated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum IntegrationMethod {
+    /// linear between neighbouring values; robust, but underestimates curved stretches, e.g.
+    /// the PV production around noon when sampled sparsely
+    #[default]
+    Trapezoidal,
+    /// a parabola through each pair of intervals, also for uneven intervals; exact for
+    /// quadratic functions
+    Simpson,
+    /// each value holds until the next one
+    LeftRiemann,
+    /// each value holds since the previous one
+    RightRiemann,
+}
+
+/// An integral leaving out the intervals longer than a threshold, see
+/// `TrapezoidalIntegral::integrate_skipping_gaps`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GapAwareIntegral<T> {
+    /// None if every interval was left out
+    pub integral: Option<T>,
+    /// the time spanned by the integrated intervals, in the unit of the timestamps
+    pub covered: u64,
+    /// the time spanned by the left out intervals, in the unit of the timestamps
+    pub excluded: u64,
+}
 
 pub trait TrapezoidalIntegral<T> {
     fn integrate(&self) -> Option<T>;
+
+    /// the integral with the given method; None for fewer than two values like `integrate()`
+    fn integrate_with(&self, method: IntegrationMethod) -> Option<T>;
+
+    /// the integral with the given method, leaving out intervals longer than `max_gap_ms`, e.g.
+    /// outages that would otherwise be bridged by a giant trapezoid; without a threshold,
+    /// nothing is left out. None for fewer than two values
+    fn integrate_skipping_gaps(
+        &self,
+        method: IntegrationMethod,
+        max_gap_ms: Option<u64>,
+    ) -> Option<GapAwareIntegral<T>>;
+
+    /// the integral from the first value up to each value, e.g. the energy produced so far
+    /// today at any time; the totals get the quality of the least reliable value up to them
+    fn cumulative_integral(&self) -> TimeSeries<T>;
 }
 
-impl<T> TrapezoidalIntegral<T> for TimeSeries<T>
+impl<T> TrapezoidalIntegral<T> for TimeSeriesView<'_, T>
 where
-    T: Copy
-        + Clone
-        + Encode
-        + DecodeOwned
-        + Add<Output = T>
-        + Sub<Output = T>
-        + Mul<f64, Output = T>,
+    T: Copy + Clone + Codec + Add<Output = T> + Sub<Output = T> + Mul<f64, Output = T>,
 {
     fn integrate(&self) -> Option<T> {
-        if self.is_empty() {
-            return None;
-        }
-        let n = self.len();
-        let entries = self.get_current_values();
+        let mut intervals = self.iter().zip(self.iter().skip(1));
 
-        let (t_0, f_0) = entries[0];
-        let (t_1, f_1) = entries[1];
-        let mut s = (f_1 + f_0) * ((t_1 - t_0) as f64);
+        let ((t_0, f_0), (t_1, f_1)) = intervals.next()?;
+        let mut s = (*f_1 + *f_0) * ((t_1 - t_0) as f64);
 
-        for i in 1..(n - 1) {
-            let (t_i, f_i) = entries[i];
-            let (t_ip1, f_ip1) = entries[i + 1];
-            s = s + (f_ip1 + f_i) * ((t_ip1 - t_i) as f64);
+        for ((t_i, f_i), (t_ip1, f_ip1)) in intervals {
+            s = s + (*f_ip1 + *f_i) * ((t_ip1 - t_i) as f64);
         }
 
         Some(s * 0.5)
     }
+
+    fn integrate_with(&self, method: IntegrationMethod) -> Option<T> {
+        let intervals = self.iter().zip(self.iter().skip(1));
+        match method {
+            IntegrationMethod::Trapezoidal => self.integrate(),
+            IntegrationMethod::Simpson => {
+                let points: Vec<(u64, T)> = self.iter().map(|(t, f)| (t, *f)).collect();
+                simpson(&points)
+            }
+            IntegrationMethod::LeftRiemann => intervals
+                .map(|((t_i, f_i), (t_ip1, _))| *f_i * ((t_ip1 - t_i) as f64))
+                .reduce(|a, b| a + b),
+            IntegrationMethod::RightRiemann => intervals
+                .map(|((t_i, _), (t_ip1, f_ip1))| *f_ip1 * ((t_ip1 - t_i) as f64))
+                .reduce(|a, b| a + b),
+        }
+    }
+
+    fn integrate_skipping_gaps(
+        &self,
+        method: IntegrationMethod,
+        max_gap_ms: Option<u64>,
+    ) -> Option<GapAwareIntegral<T>> {
+        if self.len() < 2 {
+            return None;
+        }
+        let max_gap = max_gap_ms.map_or(u64::MAX, |ms| self.get_resolution().from_millis(ms));
+        let mut integral = None;
+        let mut covered = 0;
+        // stretches of a single value have no integral
+        for run in self.split_at_gaps(max_gap) {
+            if let (Some(part), Some(start), Some(end)) = (
+                run.integrate_with(method),
+                run.get_start_time(),
+                run.get_end_time(),
+            ) {
+                integral = Some(integral.map_or(part, |sum| sum + part));
+                covered += end - start;
+            }
+        }
+        let (start, end) = (self.get_start_time()?, self.get_end_time()?);
+        Some(GapAwareIntegral {
+            integral,
+            covered,
+            excluded: end - start - covered,
+        })
+    }
+
+    fn cumulative_integral(&self) -> TimeSeries<T> {
+        let mut cumulative = TimeSeries::<T>::with_resolution(self.len(), self.get_resolution());
+        let mut previous: Option<(u64, T, T, Quality)> = None;
+        for (time, value, quality) in self.iter_with_quality() {
+            let (total, quality) = match previous {
+                None => (*value * 0.0, quality),
+                Some((t_0, f_0, total, worst)) => (
+                    total + (f_0 + *value) * ((time - t_0) as f64 * 0.5),
+                    worst.max(quality),
+                ),
+            };
+            cumulative.insert_value_with_quality(time, total, quality);
+            previous = Some((time, *value, total, quality));
+        }
+        cumulative
+    }
+}
+
+impl<T> TrapezoidalIntegral<T> for TimeSeries<T>
+where
+    T: Copy + Clone + Codec + Add<Output = T> + Sub<Output = T> + Mul<f64, Output = T>,
+{
+    fn integrate(&self) -> Option<T> {
+        self.view().integrate()
+    }
+
+    fn integrate_with(&self, method: IntegrationMethod) -> Option<T> {
+        self.view().integrate_with(method)
+    }
+
+    fn integrate_skipping_gaps(
+        &self,
+        method: IntegrationMethod,
+        max_gap_ms: Option<u64>,
+    ) -> Option<GapAwareIntegral<T>> {
+        self.view().integrate_skipping_gaps(method, max_gap_ms)
+    }
+
+    fn cumulative_integral(&self) -> TimeSeries<T> {
+        self.view().cumulative_integral()
+    }
+}
+
+/// composite Simpson's rule for unevenly spaced points; with an odd number of intervals the
+/// last one gets the parabola through the last three points
+fn simpson<T>(points: &[(u64, T)]) -> Option<T>
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<f64, Output = T>,
+{
+    let n = points.len();
+    let dt = |i: usize| (points[i + 1].0 - points[i].0) as f64;
+    let f = |i: usize| points[i].1;
+    match n {
+        0 | 1 => return None,
+        // not enough for a parabola
+        2 => return Some((f(0) + f(1)) * (dt(0) * 0.5)),
+        _ => {}
+    }
+
+    let mut s = (0..n - 2)
+        .step_by(2)
+        .map(|i| {
+            let (h_0, h_1) = (dt(i), dt(i + 1));
+            (f(i) * (2.0 - h_1 / h_0)
+                + f(i + 1) * ((h_0 + h_1).powi(2) / (h_0 * h_1))
+                + f(i + 2) * (2.0 - h_0 / h_1))
+                * ((h_0 + h_1) / 6.0)
+        })
+        .reduce(|a, b| a + b)?;
+
+    if n.is_multiple_of(2) {
+        let (h_0, h_1) = (dt(n - 3), dt(n - 2));
+        let alpha = (2.0 * h_1 * h_1 + 3.0 * h_0 * h_1) / (6.0 * (h_0 + h_1));
+        let beta = (h_1 * h_1 + 3.0 * h_0 * h_1) / (6.0 * h_0);
+        let eta = h_1.powi(3) / (6.0 * h_0 * (h_0 + h_1));
+        s = s + f(n - 1) * alpha + f(n - 2) * beta - f(n - 3) * eta;
+    }
+    Some(s)
+}
+
+/// orders numbers like `f64::total_cmp`, but puts NaN of either sign after all of them, so a bad
+/// sample sorts last and `min_by` only returns it if there's nothing else
+pub fn cmp_nan_last(a: &f64, b: &f64) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (false, false) => a.total_cmp(b),
+        (a_nan, b_nan) => a_nan.cmp(&b_nan),
+    }
+}
+
+/// like `cmp_nan_last`, but puts NaN before all numbers, so `max_by` skips it
+pub fn cmp_nan_first(a: &f64, b: &f64) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (false, false) => a.total_cmp(b),
+        (a_nan, b_nan) => b_nan.cmp(&a_nan),
+    }
 }
 
 pub trait MinMaxOfSeries<T> {
@@ -51,37 +233,232 @@ pub trait MinMaxOfSeries<T> {
         F: FnMut(&T, &T) -> Ordering;
 }
 
+impl<T> MinMaxOfSeries<T> for TimeSeriesView<'_, T>
+where
+    T: Codec,
+{
+    fn min_by<F>(&self, f: F) -> Option<T>
+    where
+        F: FnMut(&T, &T) -> Ordering,
+    {
+        self.iter().map(|(_, v)| *v).min_by(f)
+    }
+
+    fn max_by<F>(&self, f: F) -> Option<T>
+    where
+        F: FnMut(&T, &T) -> Ordering,
+    {
+        self.iter().map(|(_, v)| *v).max_by(f)
+    }
+}
+
 impl<T> MinMaxOfSeries<T> for TimeSeries<T>
 where
-    T: Copy + Clone + Encode + DecodeOwned,
+    T: Codec,
 {
     fn min_by<F>(&self, f: F) -> Option<T>
     where
         F: FnMut(&T, &T) -> Ordering,
     {
-        let values = self.get_current_values_without_time();
-        values.into_iter().min_by(f)
+        self.view().min_by(f)
     }
 
     fn max_by<F>(&self, f: F) -> Option<T>
     where
         F: FnMut(&T, &T) -> Ordering,
     {
-        let values = self.get_current_values_without_time();
-        values.into_iter().max_by(f)
+        self.view().max_by(f)
+    }
+}
+
+/// The smallest and largest value of each field on its own, and the smallest one that isn't 0,
+/// e.g. the baseline load drawn even at night; a field that's always 0 has a NaN `nonzero_min`
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Extrema<T> {
+    pub min: T,
+    pub max: T,
+    pub nonzero_min: T,
+}
+
+/// When the extrema of each field were first reached, in the unit of the series; None for
+/// fields that aren't a number throughout
+#[derive(Clone, PartialEq, Debug)]
+pub struct ExtremaTimes {
+    pub min: Vec<Option<u64>>,
+    pub max: Vec<Option<u64>>,
+}
+
+pub trait ComponentwiseMinMax<T> {
+    /// the extrema of each field and when they were reached, all found in a single pass over
+    /// the values
+    fn extrema_with_times(&self) -> Option<(Extrema<T>, ExtremaTimes)>;
+
+    /// the extrema of each field
+    fn extrema(&self) -> Option<Extrema<T>> {
+        self.extrema_with_times().map(|(extrema, _)| extrema)
+    }
+
+    /// the smallest value of each field on its own, e.g. the lowest grid draw and the lowest
+    /// consumption, which usually weren't measured at the same time
+    fn componentwise_min(&self) -> Option<T> {
+        self.extrema().map(|extrema| extrema.min)
+    }
+
+    /// the largest value of each field on its own
+    fn componentwise_max(&self) -> Option<T> {
+        self.extrema().map(|extrema| extrema.max)
+    }
+
+    /// the smallest value of each field that isn't 0
+    fn componentwise_nonzero_min(&self) -> Option<T> {
+        self.extrema().map(|extrema| extrema.nonzero_min)
+    }
+}
+
+/// the extrema of each field of the values and when they were first reached, skipping fields
+/// that aren't a number
+fn fold_extrema<'a, T>(
+    values: impl Iterator<Item = (u64, &'a T)>,
+) -> Option<(Extrema<T>, ExtremaTimes)>
+where
+    T: FloatFields + 'a,
+{
+    let mut values = values.peekable();
+    values.peek()?;
+    // f64::min and f64::max return the other argument if one is NaN
+    let mut min = vec![f64::NAN; T::COUNT];
+    let mut max = vec![f64::NAN; T::COUNT];
+    let mut nonzero_min = vec![f64::NAN; T::COUNT];
+    let mut times = ExtremaTimes {
+        min: vec![None; T::COUNT],
+        max: vec![None; T::COUNT],
+    };
+    for (time, value) in values {
+        for i in 0..T::COUNT {
+            let field = value.field(i);
+            if field.is_nan() {
+                continue;
+            }
+            if min[i].is_nan() || field < min[i] {
+                min[i] = field;
+                times.min[i] = Some(time);
+            }
+            if max[i].is_nan() || field > max[i] {
+                max[i] = field;
+                times.max[i] = Some(time);
+            }
+            if field != 0.0 {
+                nonzero_min[i] = nonzero_min[i].min(field);
+            }
+        }
+    }
+    let extrema = Extrema {
+        min: T::from_fields(&min),
+        max: T::from_fields(&max),
+        nonzero_min: T::from_fields(&nonzero_min),
+    };
+    Some((extrema, times))
+}
+
+impl<T> ComponentwiseMinMax<T> for TimeSeriesView<'_, T>
+where
+    T: Codec + FloatFields,
+{
+    fn extrema_with_times(&self) -> Option<(Extrema<T>, ExtremaTimes)> {
+        fold_extrema(self.iter())
+    }
+}
+
+impl<T> ComponentwiseMinMax<T> for TimeSeries<T>
+where
+    T: Codec + FloatFields,
+{
+    fn extrema_with_times(&self) -> Option<(Extrema<T>, ExtremaTimes)> {
+        self.view().extrema_with_times()
+    }
+}
+
+pub trait Quantiles<T> {
+    /// the value of each field below which it stayed for the share `q` (between 0 and 1) of the
+    /// time, e.g. the 95th percentile of consumption for `q = 0.95`; each value counts for half
+    /// the time to its neighbours, so irregular sampling doesn't skew the result
+    fn quantile(&self, q: f64) -> Option<T>;
+}
+
+/// how long each value of the series stood for: half the interval to each of its neighbours,
+/// or 1 each if they all share the same time
+pub fn time_weights(times: &[u64]) -> Vec<f64> {
+    let weights: Vec<f64> = (0..times.len())
+        .map(|i| {
+            let before = i.checked_sub(1).map_or(0, |j| times[i] - times[j]);
+            let after = times.get(i + 1).map_or(0, |next| next - times[i]);
+            (before + after) as f64 * 0.5
+        })
+        .collect();
+    match weights.iter().sum::<f64>() > 0.0 {
+        true => weights,
+        false => vec![1.0; times.len()],
+    }
+}
+
+/// the weighted quantile of the values, skipping those that aren't a number
+fn weighted_quantile(mut values: Vec<(f64, f64)>, q: f64) -> f64 {
+    values.retain(|(value, _)| !value.is_nan());
+    values.sort_by(|a, b| cmp_nan_last(&a.0, &b.0));
+    let total: f64 = values.iter().map(|(_, weight)| weight).sum();
+    let mut cumulative = 0.0;
+    for (value, weight) in &values {
+        cumulative += weight;
+        if cumulative >= q * total {
+            return *value;
+        }
+    }
+    values.last().map_or(f64::NAN, |(value, _)| *value)
+}
+
+impl<T> Quantiles<T> for TimeSeriesView<'_, T>
+where
+    T: Codec + FloatFields,
+{
+    fn quantile(&self, q: f64) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        let q = q.clamp(0.0, 1.0);
+        let times: Vec<u64> = self.iter().map(|(t, _)| t).collect();
+        let weights = time_weights(&times);
+        let fields: Vec<f64> = (0..T::COUNT)
+            .map(|i| {
+                let values = self.iter().map(|(_, v)| v.field(i));
+                weighted_quantile(values.zip(weights.iter().copied()).collect(), q)
+            })
+            .collect();
+        Some(T::from_fields(&fields))
+    }
+}
+
+impl<T> Quantiles<T> for TimeSeries<T>
+where
+    T: Codec + FloatFields,
+{
+    fn quantile(&self, q: f64) -> Option<T> {
+        self.view().quantile(q)
     }
 }
 
 pub trait Average<T> {
     fn average(&self) -> Option<T>;
+
+    /// the average over the intervals no longer than `max_gap_ms`, see
+    /// `TrapezoidalIntegral::integrate_skipping_gaps`; None if all of them are longer
+    fn average_skipping_gaps(&self, max_gap_ms: Option<u64>) -> Option<T>;
 }
 
-impl<T> Average<T> for TimeSeries<T>
+impl<T> Average<T> for TimeSeriesView<'_, T>
 where
     T: Copy
         + Clone
-        + Encode
-        + DecodeOwned
+        + Codec
         + Add<Output = T>
         + Sub<Output = T>
         + Mul<f64, Output = T>
@@ -97,16 +474,774 @@ where
         let avg = self.integrate()? / ((b - a) as f64);
         Some(avg)
     }
+
+    fn average_skipping_gaps(&self, max_gap_ms: Option<u64>) -> Option<T> {
+        let integral = self.integrate_skipping_gaps(IntegrationMethod::Trapezoidal, max_gap_ms)?;
+        Some(integral.integral? / integral.covered as f64)
+    }
+}
+
+impl<T> Average<T> for TimeSeries<T>
+where
+    T: Copy
+        + Clone
+        + Codec
+        + Add<Output = T>
+        + Sub<Output = T>
+        + Mul<f64, Output = T>
+        + Div<f64, Output = T>,
+{
+    fn average(&self) -> Option<T> {
+        self.view().average()
+    }
+
+    fn average_skipping_gaps(&self, max_gap_ms: Option<u64>) -> Option<T> {
+        self.view().average_skipping_gaps(max_gap_ms)
+    }
+}
+
+pub trait Aggregate<T> {
+    /// the sum of each field, skipping NaN like the extrema; None without any values
+    fn sum(&self) -> Option<T>;
+
+    /// the number of values
+    fn count(&self) -> usize;
+
+    /// the mean time between consecutive values in the unit of the series, e.g. to check
+    /// whether values are collected as often as configured; None with fewer than two values
+    fn mean_sample_interval(&self) -> Option<f64>;
+}
+
+impl<T> Aggregate<T> for TimeSeriesView<'_, T>
+where
+    T: Codec + FloatFields,
+{
+    fn sum(&self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        let mut sums = vec![0.0; T::COUNT];
+        for (_, value) in self.iter() {
+            for (i, sum) in sums.iter_mut().enumerate() {
+                let field = value.field(i);
+                if !field.is_nan() {
+                    *sum += field;
+                }
+            }
+        }
+        Some(T::from_fields(&sums))
+    }
+
+    fn count(&self) -> usize {
+        self.len()
+    }
+
+    fn mean_sample_interval(&self) -> Option<f64> {
+        if self.len() < 2 {
+            return None;
+        }
+        let duration = self.get_end_time()? - self.get_start_time()?;
+        Some(duration as f64 / (self.len() - 1) as f64)
+    }
+}
+
+impl<T> Aggregate<T> for TimeSeries<T>
+where
+    T: Codec + FloatFields,
+{
+    fn sum(&self) -> Option<T> {
+        self.view().sum()
+    }
+
+    fn count(&self) -> usize {
+        self.view().count()
+    }
+
+    fn mean_sample_interval(&self) -> Option<f64> {
+        self.view().mean_sample_interval()
+    }
+}
+
+pub trait Spread<T> {
+    /// time-weighted variance of each field, taking the values to change linearly between
+    /// samples like `integrate` does; None for series spanning no time
+    fn variance(&self) -> Option<T>;
+
+    /// square root of the variance of each field, in the unit of the values
+    fn standard_deviation(&self) -> Option<T>;
+}
+
+/// integral over the square of a value changing linearly from `a` to `b` within `dt`
+pub fn squared_trapezoid(a: f64, b: f64, dt: f64) -> f64 {
+    (a * a + a * b + b * b) * dt / 3.0
+}
+
+impl<T> Spread<T> for TimeSeriesView<'_, T>
+where
+    T: Codec + FloatFields,
+{
+    fn variance(&self) -> Option<T> {
+        let duration = (self.get_end_time()? - self.get_start_time()?) as f64;
+        if duration <= 0.0 {
+            return None;
+        }
+        let pairs = || self.iter().zip(self.iter().skip(1));
+        let fields: Vec<f64> = (0..T::COUNT)
+            .map(|i| {
+                let mean = pairs()
+                    .map(|((t_0, f_0), (t_1, f_1))| {
+                        (f_0.field(i) + f_1.field(i)) * 0.5 * (t_1 - t_0) as f64
+                    })
+                    .sum::<f64>()
+                    / duration;
+                // centered on the mean so large values don't cancel out
+                let squares: f64 = pairs()
+                    .map(|((t_0, f_0), (t_1, f_1))| {
+                        let (a, b) = (f_0.field(i) - mean, f_1.field(i) - mean);
+                        squared_trapezoid(a, b, (t_1 - t_0) as f64)
+                    })
+                    .sum();
+                squares / duration
+            })
+            .collect();
+        Some(T::from_fields(&fields))
+    }
+
+    fn standard_deviation(&self) -> Option<T> {
+        Some(map_fields(&self.variance()?, f64::sqrt))
+    }
+}
+
+impl<T> Spread<T> for TimeSeries<T>
+where
+    T: Codec + FloatFields,
+{
+    fn variance(&self) -> Option<T> {
+        self.view().variance()
+    }
+
+    fn standard_deviation(&self) -> Option<T> {
+        self.view().standard_deviation()
+    }
+}
+
+/// The window of a fixed length with the highest time-weighted average of a value, e.g. the
+/// 15 minutes with the highest grid import that utilities bill as peak demand
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PeakWindow {
+    pub start: u64,
+    pub end: u64,
+    pub average: f64,
+}
+
+pub trait PeakWindowAverage<T> {
+    /// the rolling window of the given length (in the timestamp unit of the series) with the
+    /// highest average of the values derived via `value`, skipping those that aren't a number;
+    /// None if the series is shorter than the window
+    fn peak_window_average<F>(&self, window: u64, value: F) -> Option<PeakWindow>
+    where
+        F: Fn(&T) -> f64;
+}
+
+impl<T> PeakWindowAverage<T> for TimeSeriesView<'_, T>
+where
+    T: Codec,
+{
+    fn peak_window_average<F>(&self, window: u64, value: F) -> Option<PeakWindow>
+    where
+        F: Fn(&T) -> f64,
+    {
+        let (times, values): (Vec<u64>, Vec<f64>) = self
+            .iter()
+            .map(|(t, v)| (t, value(v)))
+            .filter(|(_, v)| !v.is_nan())
+            .unzip();
+        let (first, last) = (*times.first()?, *times.last()?);
+        if window == 0 || last - first < window {
+            return None;
+        }
+
+        // trapezoidal integral from the first value up to each value
+        let mut integrals = vec![0.0; times.len()];
+        for i in 1..times.len() {
+            let area = (values[i] + values[i - 1]) * 0.5 * (times[i] - times[i - 1]) as f64;
+            integrals[i] = integrals[i - 1] + area;
+        }
+        // index of the last value at or before time and the interpolated value at time
+        let value_at = |time: u64| {
+            let i = times.partition_point(|t| *t <= time) - 1;
+            if time == times[i] {
+                return (i, values[i]);
+            }
+            let fraction = (time - times[i]) as f64 / (times[i + 1] - times[i]) as f64;
+            (i, values[i] + (values[i + 1] - values[i]) * fraction)
+        };
+        let average_from = |start: u64| {
+            let integral_up_to = |time: u64| {
+                let (i, value) = value_at(time);
+                integrals[i] + (values[i] + value) * 0.5 * (time - times[i]) as f64
+            };
+            (integral_up_to(start + window) - integral_up_to(start)) / window as f64
+        };
+        // how the average changes when moving the window
+        let slope = |start: u64| value_at(start + window).1 - value_at(start).1;
+
+        // between windows starting or ending at one of the values, the values at both ends of
+        // the window change linearly, so the average peaks where they're equal
+        let mut starts: Vec<u64> = times
+            .iter()
+            .filter(|t| **t + window <= last)
+            .copied()
+            .chain(
+                times
+                    .iter()
+                    .filter(|t| **t >= first + window)
+                    .map(|t| t - window),
+            )
+            .collect();
+        starts.sort_unstable();
+        starts.dedup();
+        let turning_points: Vec<u64> = starts
+            .windows(2)
+            .filter_map(|pair| {
+                let (a, b) = (pair[0], pair[1]);
+                let (slope_a, slope_b) = (slope(a), slope(b));
+                if slope_a <= 0.0 || slope_b >= 0.0 {
+                    return None;
+                }
+                let offset = (b - a) as f64 * slope_a / (slope_a - slope_b);
+                Some(a + offset.round() as u64)
+            })
+            .collect();
+        starts.extend(turning_points);
+        starts.sort_unstable();
+
+        starts
+            .into_iter()
+            .map(|start| PeakWindow {
+                start,
+                end: start + window,
+                average: average_from(start),
+            })
+            .reduce(|peak, w| match cmp_nan_first(&w.average, &peak.average) {
+                Ordering::Greater => w,
+                _ => peak,
+            })
+    }
+}
+
+impl<T> PeakWindowAverage<T> for TimeSeries<T>
+where
+    T: Codec,
+{
+    fn peak_window_average<F>(&self, window: u64, value: F) -> Option<PeakWindow>
+    where
+        F: Fn(&T) -> f64,
+    {
+        self.view().peak_window_average(window, value)
+    }
+}
+
+/// Calendar intervals in a timezone a series can be split into, see `Bucketize`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BucketInterval {
+    Hour,
+    Day,
+    /// starting on Mondays
+    Week,
+    Month,
+}
+
+/// What's computed from the values within each bucket
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BucketAggregator {
+    /// like `integrate`, in the unit of the values times the unit of the timestamps
+    Integral,
+    Average,
+    Max,
+}
+
+/// The aggregate of the values within [start, end) of a series
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Bucket<T> {
+    pub start: u64,
+    pub end: u64,
+    pub value: T,
+}
+
+pub trait Bucketize<T> {
+    /// splits the series into the calendar intervals of the timezone they fall into, e.g. local
+    /// days for daily energies, and aggregates the values of each; the values are taken to
+    /// change linearly between samples, so the trapezoids between two values are split at the
+    /// bucket boundaries. Buckets without any values (or, except for `Max`, without any time
+    /// covered by them) are left out
+    fn bucketize<Z: TimeZone>(
+        &self,
+        interval: BucketInterval,
+        timezone: &Z,
+        aggregator: BucketAggregator,
+    ) -> Vec<Bucket<T>>;
+}
+
+/// the UTC time in ms of the local time, or of the first time after it if it's skipped by a
+/// change of the offset
+fn local_to_millis<Z: TimeZone>(timezone: &Z, local: NaiveDateTime) -> Option<i64> {
+    let time = timezone
+        .from_local_datetime(&local)
+        .earliest()
+        .or_else(|| {
+            timezone
+                .from_local_datetime(&(local + Duration::hours(1)))
+                .earliest()
+        })?;
+    Some(time.timestamp_millis())
+}
+
+/// the range [start, end) in ms of the bucket holding the time in ms
+fn bucket_of<Z: TimeZone>(time: u64, interval: BucketInterval, timezone: &Z) -> Option<(u64, u64)> {
+    let utc = timezone
+        .timestamp_millis_opt(i64::try_from(time).ok()?)
+        .single()?;
+    let local = utc.naive_local();
+    let days = |first: NaiveDate, next: NaiveDate| {
+        let start = local_to_millis(timezone, first.and_hms_opt(0, 0, 0)?)?;
+        let end = local_to_millis(timezone, next.and_hms_opt(0, 0, 0)?)?;
+        Some((start, end))
+    };
+    let (start, end) = match interval {
+        // offsets only change by whole hours (or halves of them), so hours are cut off relative
+        // to the offset at the time, which keeps the hour repeated when clocks go back apart
+        BucketInterval::Hour => {
+            let into_hour = local.minute() as i64 * 60_000
+                + local.second() as i64 * 1000
+                + utc.timestamp_subsec_millis() as i64;
+            let start = utc.timestamp_millis() - into_hour;
+            (start, start + 3_600_000)
+        }
+        BucketInterval::Day => days(local.date(), local.date().succ_opt()?)?,
+        BucketInterval::Week => {
+            let into_week = local.weekday().num_days_from_monday() as i64;
+            let monday = local.date() - Duration::days(into_week);
+            days(monday, monday + Duration::days(7))?
+        }
+        BucketInterval::Month => {
+            let first = local.date().with_day(1)?;
+            let next = match first.month() {
+                12 => NaiveDate::from_ymd_opt(first.year() + 1, 1, 1)?,
+                month => NaiveDate::from_ymd_opt(first.year(), month + 1, 1)?,
+            };
+            days(first, next)?
+        }
+    };
+    Some((u64::try_from(start).ok()?, u64::try_from(end).ok()?))
+}
+
+/// what's collected about the values within a bucket
+struct BucketState {
+    end: u64,
+    integral: Vec<f64>,
+    duration: u64,
+    max: Vec<f64>,
+}
+
+impl BucketState {
+    fn new(end: u64, fields: usize) -> Self {
+        BucketState {
+            end,
+            integral: vec![0.0; fields],
+            duration: 0,
+            max: vec![f64::NAN; fields],
+        }
+    }
+
+    /// adds the piece of a trapezoid from `a` to `b` at the times `t_a` and `t_b`
+    fn add(&mut self, t_a: u64, a: &[f64], t_b: u64, b: &[f64]) {
+        let dt = t_b - t_a;
+        self.duration += dt;
+        for i in 0..a.len() {
+            self.integral[i] += (a[i] + b[i]) * 0.5 * dt as f64;
+            self.max[i] = self.max[i].max(a[i]).max(b[i]);
+        }
+    }
+}
+
+impl<T> Bucketize<T> for TimeSeriesView<'_, T>
+where
+    T: Codec + FloatFields,
+{
+    fn bucketize<Z: TimeZone>(
+        &self,
+        interval: BucketInterval,
+        timezone: &Z,
+        aggregator: BucketAggregator,
+    ) -> Vec<Bucket<T>> {
+        let resolution = self.get_resolution();
+        // the bucket of a time as a range of timestamps in the unit of the series
+        let bucket = |time: u64| {
+            let (start, end) = bucket_of(resolution.to_millis(time), interval, timezone)?;
+            Some((resolution.from_millis(start), resolution.from_millis(end)))
+        };
+        let fields = |value: &T| (0..T::COUNT).map(|i| value.field(i)).collect::<Vec<f64>>();
+
+        let mut buckets: BTreeMap<u64, BucketState> = BTreeMap::new();
+        let mut previous: Option<(u64, Vec<f64>)> = None;
+        for (time, value) in self.iter() {
+            let value = fields(value);
+            let Some((start, end)) = bucket(time) else {
+                continue;
+            };
+            let state = buckets
+                .entry(start)
+                .or_insert_with(|| BucketState::new(end, T::COUNT));
+            state.add(time, &value, time, &value);
+
+            // the trapezoid from the previous value, split at the boundaries it crosses
+            if let Some((t_0, f_0)) = previous.take() {
+                let at = |t: u64| -> Vec<f64> {
+                    let f = (t - t_0) as f64 / (time - t_0) as f64;
+                    (0..T::COUNT)
+                        .map(|i| f_0[i] * (1.0 - f) + value[i] * f)
+                        .collect()
+                };
+                let (mut t_a, mut a) = (t_0, f_0.clone());
+                while let Some((_, boundary)) = bucket(t_a).filter(|(_, end)| *end <= time) {
+                    let b = at(boundary);
+                    if let Some((start, end)) = bucket(t_a) {
+                        buckets
+                            .entry(start)
+                            .or_insert_with(|| BucketState::new(end, T::COUNT))
+                            .add(t_a, &a, boundary, &b);
+                    }
+                    (t_a, a) = (boundary, b);
+                }
+                buckets
+                    .entry(start)
+                    .or_insert_with(|| BucketState::new(end, T::COUNT))
+                    .add(t_a.max(start), &a, time, &value);
+            }
+            previous = Some((time, value));
+        }
+
+        buckets
+            .into_iter()
+            .filter_map(|(start, state)| {
+                let fields = match aggregator {
+                    BucketAggregator::Integral if state.duration > 0 => state.integral,
+                    BucketAggregator::Average if state.duration > 0 => state
+                        .integral
+                        .iter()
+                        .map(|integral| integral / state.duration as f64)
+                        .collect(),
+                    BucketAggregator::Max => state.max,
+                    _ => return None,
+                };
+                Some(Bucket {
+                    start,
+                    end: state.end,
+                    value: T::from_fields(&fields),
+                })
+            })
+            .collect()
+    }
+}
+
+impl<T> Bucketize<T> for TimeSeries<T>
+where
+    T: Codec + FloatFields,
+{
+    fn bucketize<Z: TimeZone>(
+        &self,
+        interval: BucketInterval,
+        timezone: &Z,
+        aggregator: BucketAggregator,
+    ) -> Vec<Bucket<T>> {
+        self.view().bucketize(interval, timezone, aggregator)
+    }
+}
+
+/// A range [low, high) of values and how long each field was within it, see `Histogram`
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct HistogramBin<T> {
+    pub low: f64,
+    pub high: f64,
+    /// in the unit of the timestamps
+    pub duration: T,
+}
+
+pub trait Histogram<T> {
+    /// how long each field was within the ranges between consecutive `edges` (ascending), e.g.
+    /// for how many hours the house drew 0-500 W, 500-1000 W, ...; the values are taken to
+    /// change linearly between samples like `integrate` does, and the last bin includes its
+    /// upper edge. Time outside of the edges or next to values that aren't a number isn't
+    /// counted; empty for fewer than two edges
+    fn histogram(&self, edges: &[f64]) -> Vec<HistogramBin<T>>;
+}
+
+/// adds the time `dt` a value changing linearly from `a` to `b` spends within each bin
+fn add_to_bins(durations: &mut [f64], edges: &[f64], a: f64, b: f64, dt: f64) {
+    if a.is_nan() || b.is_nan() {
+        return;
+    }
+    let last = edges.len() - 1;
+    let (low, high) = (a.min(b), a.max(b));
+    if low == high {
+        let bin = match edges.partition_point(|edge| *edge <= low) {
+            0 => return,
+            i if i <= last => i - 1,
+            _ if low == edges[last] => last - 1,
+            _ => return,
+        };
+        durations[bin] += dt;
+        return;
+    }
+    let first = edges.partition_point(|edge| *edge <= low).saturating_sub(1);
+    for bin in first..last {
+        if edges[bin] >= high {
+            break;
+        }
+        let overlap = high.min(edges[bin + 1]) - low.max(edges[bin]);
+        if overlap > 0.0 {
+            durations[bin] += dt * overlap / (high - low);
+        }
+    }
+}
+
+impl<T> Histogram<T> for TimeSeriesView<'_, T>
+where
+    T: Codec + FloatFields,
+{
+    fn histogram(&self, edges: &[f64]) -> Vec<HistogramBin<T>> {
+        if edges.len() < 2 {
+            return Vec::new();
+        }
+        let mut durations = vec![vec![0.0; edges.len() - 1]; T::COUNT];
+        for ((t_0, f_0), (t_1, f_1)) in self.iter().zip(self.iter().skip(1)) {
+            for (i, bins) in durations.iter_mut().enumerate() {
+                add_to_bins(bins, edges, f_0.field(i), f_1.field(i), (t_1 - t_0) as f64);
+            }
+        }
+        edges
+            .windows(2)
+            .enumerate()
+            .map(|(bin, pair)| {
+                let fields: Vec<f64> = durations.iter().map(|bins| bins[bin]).collect();
+                HistogramBin {
+                    low: pair[0],
+                    high: pair[1],
+                    duration: T::from_fields(&fields),
+                }
+            })
+            .collect()
+    }
+}
+
+impl<T> Histogram<T> for TimeSeries<T>
+where
+    T: Codec + FloatFields,
+{
+    fn histogram(&self, edges: &[f64]) -> Vec<HistogramBin<T>> {
+        self.view().histogram(edges)
+    }
+}
+
+pub trait Correlation {
+    /// the Pearson correlation coefficient of this series and another one, e.g. of the PV
+    /// production and the consumption, after resampling both linearly onto a common grid (see
+    /// `align`) as fine as the typical interval between the values of the sparser one. Pairs
+    /// with a NaN are skipped; None if fewer than two pairs are left or either side is constant
+    fn correlate(&self, other: &TimeSeries<f64>) -> Option<f64>;
+}
+
+/// the median interval between the values of a series in ms; None with fewer than two values
+fn median_interval_ms(series: &TimeSeries<f64>) -> Option<u64> {
+    let times: Vec<u64> = series.iter().map(|(t, _)| t).collect();
+    let mut intervals: Vec<u64> = times.windows(2).map(|pair| pair[1] - pair[0]).collect();
+    if intervals.is_empty() {
+        return None;
+    }
+    intervals.sort_unstable();
+    Some(
+        series
+            .get_resolution()
+            .to_millis(intervals[intervals.len() / 2]),
+    )
+}
+
+/// the Pearson correlation coefficient of the pairs that don't contain a NaN
+fn pearson(pairs: impl Iterator<Item = (f64, f64)>) -> Option<f64> {
+    let pairs: Vec<(f64, f64)> = pairs.filter(|(x, y)| !x.is_nan() && !y.is_nan()).collect();
+    if pairs.len() < 2 {
+        return None;
+    }
+    let n = pairs.len() as f64;
+    let mean_x = pairs.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = pairs.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let (mut covariance, mut variance_x, mut variance_y) = (0.0, 0.0, 0.0);
+    for (x, y) in &pairs {
+        let (dx, dy) = (x - mean_x, y - mean_y);
+        covariance += dx * dy;
+        variance_x += dx * dx;
+        variance_y += dy * dy;
+    }
+    if variance_x <= 0.0 || variance_y <= 0.0 {
+        return None;
+    }
+    Some((covariance / (variance_x * variance_y).sqrt()).clamp(-1.0, 1.0))
+}
+
+impl Correlation for TimeSeries<f64> {
+    fn correlate(&self, other: &TimeSeries<f64>) -> Option<f64> {
+        let interval_ms = median_interval_ms(self)?.max(median_interval_ms(other)?);
+        let interval = self.get_resolution().from_millis(interval_ms).max(1);
+        let (a, b) = align(self, other, interval, Fill::Linear)?;
+        pearson(a.iter().zip(b.iter()).map(|((_, x), (_, y))| (*x, *y)))
+    }
+}
+
+/// A local maximum of a series
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Peak {
+    pub time: u64,
+    pub value: f64,
+}
+
+pub trait Peaks {
+    /// the `n` highest local maxima, highest first, that are at least `min_separation` (in the
+    /// unit of the series) apart, so a single spike isn't reported several times. A plateau
+    /// counts once, at its first value, unless it spans the whole series; values that aren't a
+    /// number are never peaks
+    fn peaks(&self, n: usize, min_separation: u64) -> Vec<Peak>;
+}
+
+/// whether a neighbour of a value is lower; NaN counts as lower than anything
+fn lower(neighbour: f64, value: f64) -> bool {
+    neighbour.is_nan() || neighbour < value
+}
+
+impl Peaks for TimeSeriesView<'_, f64> {
+    fn peaks(&self, n: usize, min_separation: u64) -> Vec<Peak> {
+        let values: Vec<(u64, f64)> = self.iter().map(|(t, v)| (t, *v)).collect();
+        let mut candidates = Vec::new();
+        let mut i = 0;
+        while i < values.len() {
+            let value = values[i].1;
+            // the end of the plateau
+            let mut j = i;
+            while j + 1 < values.len() && values[j + 1].1 == value {
+                j += 1;
+            }
+            let rises = i == 0 || lower(values[i - 1].1, value);
+            let falls = j + 1 == values.len() || lower(values[j + 1].1, value);
+            let whole_series = i == 0 && j + 1 == values.len();
+            if !value.is_nan() && rises && falls && !whole_series {
+                candidates.push(Peak {
+                    time: values[i].0,
+                    value,
+                });
+            }
+            i = j + 1;
+        }
+
+        candidates.sort_by(|a, b| b.value.total_cmp(&a.value).then(a.time.cmp(&b.time)));
+        let mut peaks: Vec<Peak> = Vec::new();
+        for candidate in candidates {
+            if peaks.len() >= n {
+                break;
+            }
+            if peaks
+                .iter()
+                .all(|peak| peak.time.abs_diff(candidate.time) >= min_separation)
+            {
+                peaks.push(candidate);
+            }
+        }
+        peaks
+    }
+}
+
+impl Peaks for TimeSeries<f64> {
+    fn peaks(&self, n: usize, min_separation: u64) -> Vec<Peak> {
+        self.view().peaks(n, min_separation)
+    }
+}
+
+/// Number of values of each quality in a series, so figures computed from it can state how
+/// much of them rests on values that weren't actually measured
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct QualitySummary {
+    pub measured: usize,
+    pub interpolated: usize,
+    pub backfilled: usize,
+    pub suspect: usize,
+}
+
+impl QualitySummary {
+    pub fn total(&self) -> usize {
+        self.measured + self.interpolated + self.backfilled + self.suspect
+    }
+
+    /// share of values that were measured; None for an empty series
+    pub fn measured_fraction(&self) -> Option<f64> {
+        match self.total() {
+            0 => None,
+            total => Some(self.measured as f64 / total as f64),
+        }
+    }
+}
+
+pub trait QualityOfSeries {
+    fn quality_summary(&self) -> QualitySummary;
+}
+
+impl<T> QualityOfSeries for TimeSeriesView<'_, T>
+where
+    T: Codec,
+{
+    fn quality_summary(&self) -> QualitySummary {
+        let mut summary = QualitySummary::default();
+        for (_, _, quality) in self.iter_with_quality() {
+            match quality {
+                Quality::Measured => summary.measured += 1,
+                Quality::Interpolated => summary.interpolated += 1,
+                Quality::Backfilled => summary.backfilled += 1,
+                Quality::Suspect => summary.suspect += 1,
+            }
+        }
+        summary
+    }
+}
+
+impl<T> QualityOfSeries for TimeSeries<T>
+where
+    T: Codec,
+{
+    fn quality_summary(&self) -> QualitySummary {
+        self.view().quality_summary()
+    }
 }
 
 // short-hand composite trait
-pub trait Statistics<T>: TrapezoidalIntegral<T> + MinMaxOfSeries<T> + Average<T> {}
+pub trait Statistics<T>:
+    TrapezoidalIntegral<T> + MinMaxOfSeries<T> + Average<T> + QualityOfSeries
+{
+}
 
 impl<T> Statistics<T> for TimeSeries<T> where
     T: Copy
         + Clone
-        + Encode
-        + DecodeOwned
+        + Codec
+        + Ord
+        + Add<Output = T>
+        + Sub<Output = T>
+        + Mul<f64, Output = T>
+        + Div<f64, Output = T>
+{
+}
+
+impl<T> Statistics<T> for TimeSeriesView<'_, T> where
+    T: Copy
+        + Clone
+        + Codec
         + Ord
         + Add<Output = T>
         + Sub<Output = T>
@@ -120,13 +1255,13 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_statistics() -> () {
+    fn test_statistics() {
         let times: Vec<u64> = vec![0, 10, 20, 30, 40];
 
         // simplest case: linear with slope 1
         let mut ts = TimeSeries::<f64>::new(10);
-        for i in 0..times.len() {
-            ts.insert_value_at_time(times[i], i as f64);
+        for (i, t) in times.iter().enumerate() {
+            ts.insert_value_at_time(*t, i as f64);
         }
         let integral = ts.integrate().unwrap();
         assert_eq!(
@@ -136,15 +1271,15 @@ mod tests {
                 * 0.5
         );
 
-        let m = ts.max_by(|a, b| a.partial_cmp(b).unwrap()).unwrap();
+        let m = ts.max_by(cmp_nan_first).unwrap();
         assert_eq!(m, (times.len() - 1) as f64);
 
         // linear with slope != 1 and offset != 0
         let times: Vec<u64> = (2..100).collect();
         let mut ts = TimeSeries::<f64>::new(times.len());
         let k = 0.23;
-        for i in 0..times.len() {
-            ts.insert_value_at_time(times[i], k * times[i] as f64);
+        for t in &times {
+            ts.insert_value_at_time(*t, k * *t as f64);
         }
 
         let integral = ts.integrate().unwrap();
@@ -166,8 +1301,8 @@ mod tests {
         }
 
         let mut ts = TimeSeries::<f64>::new(times.len());
-        for i in 0..times.len() {
-            ts.insert_value_at_time(times[i], f_nl(times[i] as f64));
+        for t in &times {
+            ts.insert_value_at_time(*t, f_nl(*t as f64));
         }
 
         let integral = ts.integrate().unwrap();
@@ -179,10 +1314,10 @@ mod tests {
         let d_abs = if d < 0.0 { -d } else { d };
         assert!(d_abs < 0.0001);
 
-        assert_eq!(ts.max_by(|a, b| a.partial_cmp(b).unwrap()).unwrap(), 1.0);
+        assert_eq!(ts.max_by(cmp_nan_first).unwrap(), 1.0);
 
         assert_eq!(
-            ts.min_by(|a, b| a.partial_cmp(b).unwrap()).unwrap(),
+            ts.min_by(cmp_nan_last).unwrap(),
             ts.get_current_values().last().unwrap().1
         );
 
@@ -193,4 +1328,472 @@ mod tests {
         let d_abs = if d < 0.0 { -d } else { d };
         assert!(d_abs < 0.0001);
     }
+
+    #[test]
+    fn test_peak_window_average() {
+        // 1 kW, except for a spike up to 5 kW between minutes 30 and 50
+        let minute = 60000;
+        let mut ts = TimeSeries::<f64>::new(5);
+        for (t, v) in [(0, 1.0), (30, 1.0), (40, 5.0), (50, 1.0), (90, 1.0)] {
+            ts.insert_value_at_time(t * minute, v * 1000.0);
+        }
+        let assert_peak = |window: u64, start: u64, average: f64| {
+            let peak = ts.peak_window_average(window * minute, |v| *v).unwrap();
+            assert_eq!((peak.start, peak.end), (start, start + window * minute));
+            assert!((peak.average - average).abs() < 1e-6, "{:?}", peak);
+        };
+
+        // the spike adds 40 kW*min to any window covering it
+        assert_peak(20, 30 * minute, 3000.0);
+        assert_peak(30, 20 * minute, 1000.0 + 40000.0 / 30.0);
+        // shorter windows peak between the values, 2.5 minutes into the spike
+        assert_peak(15, 32 * minute + 30000, 3500.0);
+
+        assert!(ts.peak_window_average(100 * minute, |v| *v).is_none());
+
+        // a bad sample is skipped rather than spoiling every window after it
+        ts.insert_value_at_time(60 * minute, f64::NAN);
+        let peak = ts.peak_window_average(20 * minute, |v| *v).unwrap();
+        assert_eq!((peak.start, peak.average), (30 * minute, 3000.0));
+        assert!(TimeSeries::<f64>::empty()
+            .peak_window_average(minute, |v| *v)
+            .is_none());
+    }
+
+    #[test]
+    fn test_nan_safe_comparisons() {
+        let mut values = [2.0, f64::NAN, -1.0, -f64::NAN, f64::INFINITY];
+        values.sort_by(cmp_nan_last);
+        assert_eq!(values[..3], [-1.0, 2.0, f64::INFINITY]);
+        assert!(values[3..].iter().all(|v| v.is_nan()));
+        values.sort_by(cmp_nan_first);
+        assert!(values[..2].iter().all(|v| v.is_nan()));
+        assert_eq!(values[2..], [-1.0, 2.0, f64::INFINITY]);
+
+        let mut ts = TimeSeries::<f64>::new(4);
+        for (t, v) in [(0, 1.0), (1, f64::NAN), (2, 3.0), (3, -f64::NAN)] {
+            ts.insert_value_at_time(t, v);
+        }
+        assert_eq!(ts.max_by(cmp_nan_first), Some(3.0));
+        assert_eq!(ts.min_by(cmp_nan_last), Some(1.0));
+        let all_nan = [f64::NAN];
+        assert!(all_nan
+            .iter()
+            .copied()
+            .max_by(cmp_nan_first)
+            .unwrap()
+            .is_nan());
+    }
+
+    #[test]
+    fn test_componentwise_min_max() {
+        let mut ts = TimeSeries::<[f64; 2]>::new(5);
+        ts.insert_value_at_time(0, [3.0, -1.0]);
+        ts.insert_value_at_time(10, [1.0, f64::NAN]);
+        ts.insert_value_at_time(20, [2.0, 4.0]);
+        assert_eq!(ts.componentwise_min(), Some([1.0, -1.0]));
+        assert_eq!(ts.componentwise_max(), Some([3.0, 4.0]));
+        let view = ts.view_range(5, 25).unwrap();
+        assert_eq!(view.componentwise_min(), Some([1.0, 4.0]));
+        assert!(TimeSeries::<[f64; 2]>::empty()
+            .componentwise_min()
+            .is_none());
+        let (_, times) = ts.extrema_with_times().unwrap();
+        assert_eq!(times.min, [Some(10), Some(0)]);
+        assert_eq!(times.max, [Some(0), Some(20)]);
+
+        // no load at all at night, a baseline of 80 W during the day
+        let mut ts = TimeSeries::<[f64; 2]>::new(5);
+        ts.insert_value_at_time(0, [0.0, 0.0]);
+        ts.insert_value_at_time(10, [80.0, 0.0]);
+        ts.insert_value_at_time(20, [450.0, 0.0]);
+        let extrema = ts.extrema().unwrap();
+        assert_eq!(extrema.min, [0.0, 0.0]);
+        assert_eq!(extrema.nonzero_min[0], 80.0);
+        assert!(extrema.nonzero_min[1].is_nan());
+        assert_eq!(ts.componentwise_nonzero_min().unwrap()[0], 80.0);
+        // the first time an extremum is reached counts
+        let (_, times) = ts.extrema_with_times().unwrap();
+        assert_eq!(times.min, [Some(0), Some(0)]);
+        assert_eq!(times.max, [Some(20), Some(0)]);
+        let mut missing = TimeSeries::<[f64; 2]>::new(5);
+        missing.insert_value_at_time(0, [1.0, f64::NAN]);
+        assert_eq!(missing.extrema_with_times().unwrap().1.max, [Some(0), None]);
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let mut ts = TimeSeries::<f64>::new(5);
+        assert_eq!(ts.sum(), None);
+        assert_eq!(ts.mean_sample_interval(), None);
+        for (t, v) in [(0, 1.0), (10, 2.0), (30, 4.0)] {
+            ts.insert_value_at_time(t, v);
+        }
+        assert_eq!(ts.sum(), Some(7.0));
+        assert_eq!(ts.count(), 3);
+        assert_eq!(ts.mean_sample_interval(), Some(15.0));
+        let view = ts.view_range(5, 30).unwrap();
+        assert_eq!(view.sum(), Some(6.0));
+        assert_eq!(view.mean_sample_interval(), Some(20.0));
+
+        let mut ts = TimeSeries::<[f64; 2]>::new(5);
+        for (t, v) in [(0, [1.0, 1.0]), (10, [f64::NAN, 2.0]), (20, [3.0, 4.0])] {
+            ts.insert_value_at_time(t, v);
+        }
+        assert_eq!(ts.sum(), Some([4.0, 7.0]));
+    }
+
+    #[test]
+    fn test_quantiles() {
+        let mut ts = TimeSeries::<f64>::new(200);
+        for i in 0..100u64 {
+            ts.insert_value_at_time(i * 10, i as f64);
+        }
+        assert_eq!(ts.quantile(0.0), Some(0.0));
+        assert_eq!(ts.quantile(0.5), Some(49.0));
+        assert_eq!(ts.quantile(0.95), Some(94.0));
+        assert_eq!(ts.quantile(1.0), Some(99.0));
+
+        // a spike sampled often only counts for as long as it lasted
+        let mut ts = TimeSeries::<f64>::new(200);
+        ts.insert_value_at_time(0, 100.0);
+        ts.insert_value_at_time(1000, 100.0);
+        for i in 0..50u64 {
+            ts.insert_value_at_time(1001 + i, 5000.0);
+        }
+        ts.insert_value_at_time(1100, 100.0);
+        ts.insert_value_at_time(2000, 100.0);
+        assert_eq!(ts.quantile(0.5), Some(100.0));
+        assert_eq!(ts.quantile(0.9), Some(100.0));
+        assert_eq!(ts.quantile(0.99), Some(5000.0));
+
+        let mut ts = TimeSeries::<[f64; 2]>::new(5);
+        ts.insert_value_at_time(0, [1.0, f64::NAN]);
+        assert_eq!(ts.quantile(0.95).map(|v| v[0]), Some(1.0));
+        assert!(TimeSeries::<f64>::empty().quantile(0.5).is_none());
+    }
+
+    #[test]
+    fn test_spread() {
+        // alternating between 0 and 2 with steps in between: half the time at the extremes
+        let mut ts = TimeSeries::<f64>::new(10);
+        for (time, value) in [
+            (0, 0.0),
+            (10, 0.0),
+            (10, 2.0),
+            (20, 2.0),
+            (20, 0.0),
+            (30, 0.0),
+        ] {
+            ts.insert_value_at_time(time, value);
+        }
+        assert_eq!(ts.average(), Some(2.0 / 3.0));
+        assert!((ts.variance().unwrap() - 8.0 / 9.0).abs() < 1e-12);
+
+        // a linear ramp from 0 to 1 has the variance 1/12 of the uniform distribution
+        let mut ts = TimeSeries::<[f64; 2]>::new(10);
+        ts.insert_value_at_time(0, [0.0, 1000.0]);
+        ts.insert_value_at_time(10, [1.0, 1000.0]);
+        let variance = ts.variance().unwrap();
+        assert!((variance[0] - 1.0 / 12.0).abs() < 1e-12);
+        assert_eq!(variance[1], 0.0);
+        let deviation = ts.standard_deviation().unwrap();
+        assert!((deviation[0] - (1.0f64 / 12.0).sqrt()).abs() < 1e-12);
+
+        let mut single = TimeSeries::<f64>::new(1);
+        single.insert_value_at_time(0, 1.0);
+        assert!(single.variance().is_none());
+        assert!(TimeSeries::<f64>::empty().standard_deviation().is_none());
+    }
+
+    #[test]
+    fn test_histogram() {
+        let mut ts = TimeSeries::<f64>::new(10);
+        // 200 W for 10 s, a ramp up to 1200 W within 10 s and 1200 W for 5 s
+        for (t, v) in [(0, 200.0), (10, 200.0), (20, 1200.0), (25, 1200.0)] {
+            ts.insert_value_at_time(t, v);
+        }
+        let bins = ts.histogram(&[0.0, 500.0, 1000.0, 1200.0]);
+        let durations: Vec<f64> = bins.iter().map(|bin| bin.duration).collect();
+        assert_eq!(durations, vec![13.0, 5.0, 7.0]);
+        assert_eq!((bins[1].low, bins[1].high), (500.0, 1000.0));
+        // the whole time is accounted for if the edges span all values
+        assert_eq!(durations.iter().sum::<f64>(), 25.0);
+
+        // values outside the edges and around NaN don't count
+        ts.insert_value_at_time(30, f64::NAN);
+        ts.insert_value_at_time(40, 100.0);
+        let bins = ts.histogram(&[500.0, 1000.0]);
+        assert_eq!(bins.len(), 1);
+        assert_eq!(bins[0].duration, 5.0);
+        assert!(ts.histogram(&[0.0]).is_empty());
+        assert_eq!(
+            TimeSeries::<f64>::empty().histogram(&[0.0, 1.0])[0].duration,
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_correlate() {
+        // a sine every 10 s and its negation, shifted by 5 s, every 30 s
+        let minute = 60_000;
+        let wave = |t: u64| (t as f64 / minute as f64).sin();
+        let a: TimeSeries<f64> = (0..600).map(|i| (i * 10_000, wave(i * 10_000))).collect();
+        let b: TimeSeries<f64> = (0..200)
+            .map(|i| (5_000 + i * 30_000, -wave(5_000 + i * 30_000)))
+            .collect();
+        assert!(a.correlate(&b).unwrap() < -0.99);
+        assert!(b.correlate(&a).unwrap() < -0.99);
+        assert!((a.correlate(&a).unwrap() - 1.0).abs() < 1e-12);
+
+        // a cosine is uncorrelated to the sine over whole periods
+        let period = (2.0 * std::f64::consts::PI * minute as f64) as u64;
+        let cosine: TimeSeries<f64> = (0..=1000)
+            .map(|i| {
+                let t = i * 10 * period / 1000;
+                (t, (t as f64 / minute as f64).cos())
+            })
+            .collect();
+        let sine: TimeSeries<f64> = cosine.iter().map(|(t, _)| (t, wave(t))).collect();
+        assert!(sine.correlate(&cosine).unwrap().abs() < 0.01);
+
+        // other resolutions are converted; NaNs are skipped
+        let mut b_seconds = b.to_resolution(crate::timeseries::Resolution::Seconds);
+        b_seconds.insert_value_at_time(5, f64::NAN);
+        assert!(a.correlate(&b_seconds).unwrap() < -0.99);
+
+        // constant or non-overlapping series have no correlation
+        let constant: TimeSeries<f64> = (0..10).map(|i| (i * 10_000, 1.0)).collect();
+        assert_eq!(a.correlate(&constant), None);
+        let later: TimeSeries<f64> = (0..10).map(|i| (10_000_000 + i * 1000, i as f64)).collect();
+        assert_eq!(a.correlate(&later), None);
+        assert_eq!(a.correlate(&TimeSeries::empty()), None);
+    }
+
+    #[test]
+    fn test_peaks() {
+        let values = [
+            1.0,
+            5.0,
+            2.0,
+            4.0,
+            4.0,
+            1.0,
+            f64::NAN,
+            3.0,
+            2.0,
+            6.0,
+            6.5,
+            7.0,
+        ];
+        let series: TimeSeries<f64> = values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (i as u64 * 10, *v))
+            .collect();
+        let peaks = series.peaks(10, 0);
+        let times: Vec<u64> = peaks.iter().map(|peak| peak.time).collect();
+        // the plateau at 30 counts once, the last value is a peak, the rising 6.0 isn't
+        assert_eq!(times, [110, 10, 30, 70]);
+        assert_eq!(peaks[0].value, 7.0);
+
+        assert_eq!(series.peaks(2, 0).len(), 2);
+        // 30 is too close to 10
+        let separated: Vec<u64> = series.peaks(10, 30).iter().map(|p| p.time).collect();
+        assert_eq!(separated, [110, 10, 70]);
+        assert!(TimeSeries::<f64>::empty().peaks(5, 0).is_empty());
+        let constant: TimeSeries<f64> = (0..10).map(|i| (i * 10, 1.0)).collect();
+        assert!(constant.peaks(5, 0).is_empty());
+    }
+
+    #[test]
+    fn test_bucketize() {
+        let hour = 3_600_000;
+        let cest = chrono::FixedOffset::east_opt(2 * 3600).unwrap();
+        // 2024-06-01 22:00 to 2024-06-02 02:00 CEST, ramping up from 0 to 400 W
+        let start = 1717272000000;
+        let mut ts = TimeSeries::<f64>::new(10);
+        for i in 0..5 {
+            ts.insert_value_at_time(start + i * hour, i as f64 * 100.0);
+        }
+
+        let days = ts.bucketize(BucketInterval::Day, &cest, BucketAggregator::Integral);
+        assert_eq!(days.len(), 2);
+        // 2024-06-02 00:00 CEST
+        assert_eq!(days[0].end, start + 2 * hour);
+        assert_eq!(days[1].start, start + 2 * hour);
+        assert_eq!(days[0].value, 200.0 * hour as f64);
+        assert_eq!(days[1].value, 600.0 * hour as f64);
+        let maxes = ts.bucketize(BucketInterval::Day, &cest, BucketAggregator::Max);
+        assert_eq!(maxes[0].value, 200.0);
+        let averages = ts.bucketize(BucketInterval::Day, &cest, BucketAggregator::Average);
+        assert_eq!(averages[1].value, 300.0);
+
+        // trapezoids are split at the boundaries they cross
+        let mut sparse = TimeSeries::<f64>::new(2);
+        sparse.insert_value_at_time(start + hour / 2, 100.0);
+        sparse.insert_value_at_time(start + 3 * hour + hour / 2, 100.0);
+        let hours = sparse.bucketize(BucketInterval::Hour, &cest, BucketAggregator::Integral);
+        let starts: Vec<u64> = hours.iter().map(|bucket| bucket.start).collect();
+        assert_eq!(
+            starts,
+            (0..4).map(|i| start + i * hour).collect::<Vec<u64>>()
+        );
+        assert_eq!(hours[1].value, 100.0 * hour as f64);
+        let total: f64 = hours.iter().map(|bucket| bucket.value).sum();
+        assert_eq!(total, sparse.integrate().unwrap());
+
+        // weeks start on Mondays, 2024-05-27, and months on the first
+        let weeks = ts.bucketize(BucketInterval::Week, &cest, BucketAggregator::Max);
+        assert_eq!(weeks.len(), 1);
+        assert_eq!(weeks[0].start, 1716760800000);
+        assert_eq!(weeks[0].end - weeks[0].start, 7 * 24 * hour);
+        let months = ts.bucketize(BucketInterval::Month, &cest, BucketAggregator::Max);
+        // 2024-06-01 00:00 to 2024-07-01 00:00 CEST
+        assert_eq!(
+            (months[0].start, months[0].end),
+            (1717192800000, 1719784800000)
+        );
+
+        // a single value only has a maximum
+        let mut single = TimeSeries::<f64>::new(1);
+        single.insert_value_at_time(start, 5.0);
+        assert_eq!(
+            single
+                .bucketize(BucketInterval::Day, &cest, BucketAggregator::Max)
+                .len(),
+            1
+        );
+        assert!(single
+            .bucketize(BucketInterval::Day, &cest, BucketAggregator::Average)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_cumulative_integral() {
+        let mut ts = TimeSeries::<f64>::new(10);
+        ts.insert_value_at_time(0, 1.0);
+        ts.insert_value_at_time(10, 3.0);
+        ts.insert_value_with_quality(20, 3.0, Quality::Interpolated);
+        ts.insert_value_at_time(40, 1.0);
+
+        let cumulative = ts.cumulative_integral();
+        assert_eq!(
+            cumulative.get_current_values_with_quality(),
+            vec![
+                (0, 0.0, Quality::Measured),
+                (10, 20.0, Quality::Measured),
+                (20, 50.0, Quality::Interpolated),
+                (40, 90.0, Quality::Interpolated),
+            ]
+        );
+        assert_eq!(
+            cumulative.get_current_values()[3].1,
+            ts.integrate().unwrap()
+        );
+        assert!(TimeSeries::<f64>::empty().cumulative_integral().is_empty());
+    }
+
+    #[test]
+    fn test_integration_methods() {
+        let sampled = |times: &[u64], f: fn(f64) -> f64| {
+            let mut ts = TimeSeries::<f64>::new(times.len());
+            for t in times {
+                ts.insert_value_at_time(*t, f(*t as f64));
+            }
+            ts
+        };
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9 * b.abs().max(1.0);
+        let even: Vec<u64> = (0..=10).collect();
+        let uneven: Vec<u64> = vec![0, 1, 3, 4, 7, 10];
+
+        // the integral of x^2 from 0 to 10 is 1000 / 3, exact for Simpson with an even as
+        // well as an odd number of uneven intervals
+        for times in [&even[..], &uneven, &uneven[..5]] {
+            let ts = sampled(times, |x| x * x);
+            let end = *times.last().unwrap() as f64;
+            let simpson = ts.integrate_with(IntegrationMethod::Simpson).unwrap();
+            assert!(
+                close(simpson, end.powi(3) / 3.0),
+                "{:?}: {}",
+                times,
+                simpson
+            );
+            assert!(ts.integrate().unwrap() > end.powi(3) / 3.0);
+        }
+        // x^3 too on even intervals
+        let ts = sampled(&even, |x| x.powi(3));
+        let simpson = ts.integrate_with(IntegrationMethod::Simpson).unwrap();
+        assert!(close(simpson, 2500.0));
+
+        // sin from 0 to pi is 2; Simpson is far closer than the trapezoids
+        let pi_times: Vec<u64> = (0..=8).map(|i| i * 1000).collect();
+        let ts = sampled(&pi_times, |x| (x / 8000.0 * std::f64::consts::PI).sin());
+        let scale = 8000.0 / std::f64::consts::PI;
+        let simpson = ts.integrate_with(IntegrationMethod::Simpson).unwrap() / scale;
+        let trapezoid = ts.integrate().unwrap() / scale;
+        assert!((simpson - 2.0).abs() < 1e-3);
+        assert!((trapezoid - 2.0).abs() > 10.0 * (simpson - 2.0).abs());
+
+        // the Riemann sums under- and overestimate a rising line by half a step per interval
+        let ts = sampled(&even, |x| x);
+        assert_eq!(
+            ts.integrate_with(IntegrationMethod::LeftRiemann),
+            Some(45.0)
+        );
+        assert_eq!(
+            ts.integrate_with(IntegrationMethod::RightRiemann),
+            Some(55.0)
+        );
+        assert_eq!(
+            ts.integrate_with(IntegrationMethod::Trapezoidal),
+            Some(50.0)
+        );
+
+        // two values are just a trapezoid, a single one isn't enough
+        let ts = sampled(&[0, 2], |x| x * x);
+        assert_eq!(ts.integrate_with(IntegrationMethod::Simpson), Some(4.0));
+        let ts = sampled(&[5], |x| x);
+        for method in [IntegrationMethod::Simpson, IntegrationMethod::LeftRiemann] {
+            assert_eq!(ts.integrate_with(method), None);
+        }
+    }
+
+    #[test]
+    fn test_integrate_skipping_gaps() {
+        let hour = 3_600_000;
+        let mut ts = TimeSeries::<f64>::new(10);
+        // 1 kW for an hour, a 6 hour outage, 3 kW for an hour, a lone value after another gap
+        for (time, value) in [(0, 1.0), (hour, 1.0), (7 * hour, 3.0), (8 * hour, 3.0)] {
+            ts.insert_value_at_time(time, value);
+        }
+        ts.insert_value_at_time(10 * hour, 5.0);
+        let trapezoidal = IntegrationMethod::Trapezoidal;
+
+        let integral = ts.integrate_skipping_gaps(trapezoidal, Some(hour)).unwrap();
+        assert_eq!(integral.integral, Some(4.0 * hour as f64));
+        assert_eq!((integral.covered, integral.excluded), (2 * hour, 8 * hour));
+        assert_eq!(ts.average_skipping_gaps(Some(hour)), Some(2.0));
+        // without a threshold, the outage is bridged
+        let bridged = ts.integrate_skipping_gaps(trapezoidal, None).unwrap();
+        assert_eq!(bridged.integral, ts.integrate());
+        assert_eq!((bridged.covered, bridged.excluded), (10 * hour, 0));
+        assert_eq!(ts.average_skipping_gaps(None), ts.average());
+        // the threshold is in ms regardless of the resolution
+        let seconds = ts.to_resolution(crate::timeseries::Resolution::Seconds);
+        let integral = seconds
+            .integrate_skipping_gaps(trapezoidal, Some(hour))
+            .unwrap();
+        assert_eq!(integral.excluded, 8 * 3600);
+        // everything left out
+        let integral = ts.integrate_skipping_gaps(trapezoidal, Some(0)).unwrap();
+        assert_eq!((integral.integral, integral.covered), (None, 0));
+        assert_eq!(ts.average_skipping_gaps(Some(0)), None);
+        // rather than 0 times the first value, which is NaN if it is
+        let mut ts = TimeSeries::<f64>::new(10);
+        ts.insert_value_at_time(0, f64::NAN);
+        ts.insert_value_at_time(10 * hour, 1.0);
+        let integral = ts.integrate_skipping_gaps(trapezoidal, Some(hour)).unwrap();
+        assert_eq!(integral.integral, None);
+    }
 }
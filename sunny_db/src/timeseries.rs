@@ -1,40 +1,390 @@
-use bitcode::{Decode, DecodeOwned, Encode};
+use crate::codec::{codec_name, Codec, SegmentEncoding, BITCODE, GORILLA, QUANTIZED};
+use crate::encryption::{EncryptionKey, CHACHA20_POLY1305, NO_CIPHER};
+use crate::error::SunnyDbError;
+use crate::fields::FloatFields;
+use crate::gorilla;
+use crate::quantization::{self, MAX_DECIMALS};
+use bitcode::{Decode, Encode};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use std::time::{SystemTime, UNIX_EPOCH};
 
+/// Segments start with these bytes followed by the version of their format; segments written
+/// by older versions only consist of the compressed data
+const SEGMENT_MAGIC: &[u8; 4] = b"SNYS";
+/// Version 2 added a CRC32 checksum of the compressed data right after the version, version 3
+/// the resolution of the timestamps in between the two, version 4 the codec after the
+/// resolution, version 5 the cipher the data is encrypted with after the codec and version 6
+/// the number of decimal places values are quantized to after the cipher
+const SEGMENT_VERSION: u8 = 6;
+/// Header byte of segments whose values aren't quantized
+const NOT_QUANTIZED: u8 = i8::MIN as u8;
+
+/// How a value came about; ordered from most to least reliable, so values derived from several
+/// others get the quality of the least reliable one
+#[derive(Copy, Clone, Encode, Decode, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Quality {
+    #[default]
+    Measured,
+    Interpolated,
+    Backfilled,
+    Suspect,
+}
+
+/// Unit of the timestamps of a series; low-rate series can use seconds to save space
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Resolution {
+    Seconds,
+    #[default]
+    Milliseconds,
+    Microseconds,
+}
+
+impl Resolution {
+    pub fn per_second(&self) -> u64 {
+        match self {
+            Resolution::Seconds => 1,
+            Resolution::Milliseconds => 1_000,
+            Resolution::Microseconds => 1_000_000,
+        }
+    }
+
+    /// converts a timestamp of this resolution to another one, rounding down
+    pub fn convert(&self, time: u64, to: Resolution) -> u64 {
+        let (from, to) = (self.per_second(), to.per_second());
+        if from >= to {
+            time / (from / to)
+        } else {
+            time.saturating_mul(to / from)
+        }
+    }
+
+    pub fn to_millis(&self, time: u64) -> u64 {
+        self.convert(time, Resolution::Milliseconds)
+    }
+
+    pub fn from_millis(&self, millis: u64) -> u64 {
+        Resolution::Milliseconds.convert(millis, *self)
+    }
+
+    /// the current time as a timestamp of this resolution; 0 if the clock is set before the
+    /// epoch, which `TimestampBounds` reject
+    pub fn now(&self) -> u64 {
+        let micros = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_micros() as u64);
+        Resolution::Microseconds.convert(micros, *self)
+    }
+
+    fn to_header_byte(self) -> u8 {
+        match self {
+            Resolution::Seconds => 0,
+            Resolution::Milliseconds => 1,
+            Resolution::Microseconds => 2,
+        }
+    }
+
+    fn from_header_byte(byte: u8) -> Option<Resolution> {
+        match byte {
+            0 => Some(Resolution::Seconds),
+            1 => Some(Resolution::Milliseconds),
+            2 => Some(Resolution::Microseconds),
+            _ => None,
+        }
+    }
+}
+
+/// Which value is kept when merging two series with values at the same time
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DuplicatePolicy {
+    /// the value of the series merged into
+    #[default]
+    KeepExisting,
+    /// the value of the series being merged
+    Replace,
+    /// the value of better quality; the existing one if both are equally reliable
+    BestQuality,
+}
+
+/// Bounds of a series kept as a rolling window, e.g. for a live view; the oldest values are
+/// evicted whenever new ones push the series beyond them
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Window {
+    /// maximum number of values
+    pub max_len: Option<usize>,
+    /// maximum age of values relative to the newest one, in the resolution of the series
+    pub max_age: Option<u64>,
+}
+
 #[derive(Copy, Clone, Encode, Decode, PartialEq, Debug)]
-struct TimeSeriesEntry<T> {
-    time: u64,
-    value: T,
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub(crate) struct TimeSeriesEntry<T> {
+    pub(crate) time: u64,
+    pub(crate) value: T,
+    pub(crate) quality: Quality,
 }
 
-#[derive(Encode, Decode, PartialEq, Debug)]
+#[derive(PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct TimeSeries<T> {
     init_size: usize,
     data: Vec<TimeSeriesEntry<T>>,
     start_time: Option<u64>,
     end_time: Option<u64>,
+    resolution: Resolution,
+    duplicate_policy: DuplicatePolicy,
+    #[cfg_attr(feature = "serde", serde(default))]
+    window: Window,
+}
+
+/// What's stored of a series in a segment; the resolution is part of the segment header
+#[doc(hidden)]
+#[derive(Encode, Decode)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SegmentBody<T> {
+    init_size: usize,
+    data: Vec<TimeSeriesEntry<T>>,
+    start_time: Option<u64>,
+    end_time: Option<u64>,
+}
+
+/// Segments written before quality flags were introduced
+#[derive(Decode)]
+struct LegacyTimeSeriesEntry<T> {
+    time: u64,
+    value: T,
+}
+
+#[doc(hidden)]
+#[derive(Decode)]
+pub struct LegacySegmentBody<T> {
+    init_size: usize,
+    data: Vec<LegacyTimeSeriesEntry<T>>,
+    start_time: Option<u64>,
+    end_time: Option<u64>,
+}
+
+impl<T> From<LegacySegmentBody<T>> for TimeSeries<T> {
+    fn from(legacy: LegacySegmentBody<T>) -> Self {
+        let data = legacy
+            .data
+            .into_iter()
+            .map(|entry| TimeSeriesEntry {
+                time: entry.time,
+                value: entry.value,
+                quality: Quality::Measured,
+            })
+            .collect();
+        TimeSeries {
+            init_size: legacy.init_size,
+            data,
+            start_time: legacy.start_time,
+            end_time: legacy.end_time,
+            resolution: Resolution::Milliseconds,
+            duplicate_policy: DuplicatePolicy::default(),
+            window: Window::default(),
+        }
+    }
+}
+
+/// Decodes the data of a segment written using the given codec that only values implementing
+/// `FloatFields` can be stored with, undoing the quantization to the given decimal places
+type FloatDecoder<T> = fn(u8, &[u8], Option<i8>) -> anyhow::Result<DecodedEntries<T>>;
+
+/// The initial size and the entries of a segment
+type DecodedEntries<T> = (usize, Vec<TimeSeriesEntry<T>>);
+
+struct SegmentHeader {
+    resolution: Resolution,
+    codec: u8,
+    cipher: u8,
+    /// decimal places the values are quantized to
+    decimals: Option<i8>,
+    checksum: Option<[u8; 4]>,
+    /// where the compressed data starts
+    data_offset: usize,
+}
+
+/// parses the header of an encoded segment; None for segments without one
+fn parse_header(segment: &[u8]) -> anyhow::Result<Option<SegmentHeader>> {
+    let Some(rest) = segment.strip_prefix(SEGMENT_MAGIC) else {
+        return Ok(None);
+    };
+
+    let header = match rest {
+        [1, ..] => SegmentHeader {
+            resolution: Resolution::Milliseconds,
+            codec: BITCODE,
+            cipher: NO_CIPHER,
+            decimals: None,
+            checksum: None,
+            data_offset: 5,
+        },
+        [2, c0, c1, c2, c3, ..] => SegmentHeader {
+            resolution: Resolution::Milliseconds,
+            codec: BITCODE,
+            cipher: NO_CIPHER,
+            decimals: None,
+            checksum: Some([*c0, *c1, *c2, *c3]),
+            data_offset: 9,
+        },
+        [3, resolution, c0, c1, c2, c3, ..] => SegmentHeader {
+            resolution: Resolution::from_header_byte(*resolution).ok_or_else(|| {
+                anyhow::anyhow!("Unknown timestamp resolution {} in segment", resolution)
+            })?,
+            codec: BITCODE,
+            cipher: NO_CIPHER,
+            decimals: None,
+            checksum: Some([*c0, *c1, *c2, *c3]),
+            data_offset: 10,
+        },
+        [4, resolution, codec, c0, c1, c2, c3, ..] => SegmentHeader {
+            resolution: Resolution::from_header_byte(*resolution).ok_or_else(|| {
+                anyhow::anyhow!("Unknown timestamp resolution {} in segment", resolution)
+            })?,
+            codec: *codec,
+            cipher: NO_CIPHER,
+            decimals: None,
+            checksum: Some([*c0, *c1, *c2, *c3]),
+            data_offset: 11,
+        },
+        [5, resolution, codec, cipher, c0, c1, c2, c3, ..] => SegmentHeader {
+            resolution: Resolution::from_header_byte(*resolution).ok_or_else(|| {
+                anyhow::anyhow!("Unknown timestamp resolution {} in segment", resolution)
+            })?,
+            codec: *codec,
+            cipher: *cipher,
+            decimals: None,
+            checksum: Some([*c0, *c1, *c2, *c3]),
+            data_offset: 12,
+        },
+        [6, resolution, codec, cipher, decimals, c0, c1, c2, c3, ..] => SegmentHeader {
+            resolution: Resolution::from_header_byte(*resolution).ok_or_else(|| {
+                anyhow::anyhow!("Unknown timestamp resolution {} in segment", resolution)
+            })?,
+            codec: *codec,
+            cipher: *cipher,
+            decimals: match *decimals {
+                NOT_QUANTIZED => None,
+                decimals => Some(decimals as i8),
+            },
+            checksum: Some([*c0, *c1, *c2, *c3]),
+            data_offset: 13,
+        },
+        [version, ..] if *version > SEGMENT_VERSION => {
+            anyhow::bail!("Unsupported segment format version {}", version)
+        }
+        _ => anyhow::bail!("Segment header is truncated"),
+    };
+    Ok(Some(header))
+}
+
+/// checks the checksum of an encoded segment; None if the segment doesn't have one
+pub fn checksum_matches(segment: &[u8]) -> Option<bool> {
+    match parse_header(segment) {
+        Ok(Some(SegmentHeader {
+            checksum: Some(checksum),
+            data_offset,
+            ..
+        })) => Some(checksum == crc32fast::hash(&segment[data_offset..]).to_le_bytes()),
+        Ok(_) => None,
+        Err(_) => Some(false),
+    }
+}
+
+/// What the header of an encoded segment says about how it's encoded
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SegmentInfo {
+    pub resolution: Resolution,
+    /// one of the codecs in `codec`, e.g. `codec::GORILLA`
+    pub codec: u8,
+    /// one of the ciphers in `encryption`; `encryption::NO_CIPHER` if it isn't encrypted
+    pub cipher: u8,
+    /// decimal places the values are quantized to
+    pub decimals: Option<i8>,
+}
+
+/// reads the header of an encoded segment without decoding it; None for segments written
+/// before there were headers, which hold bitcode with timestamps in ms
+pub fn segment_info(segment: &[u8]) -> anyhow::Result<Option<SegmentInfo>> {
+    Ok(parse_header(segment)?.map(|header| SegmentInfo {
+        resolution: header.resolution,
+        codec: header.codec,
+        cipher: header.cipher,
+        decimals: header.decimals,
+    }))
 }
 
 pub trait UnixTimestamp {
-    fn timestamp(&self) -> u64;
+    /// the ms since the epoch; an error for earlier times, e.g. of a clock that isn't set
+    fn timestamp(&self) -> Result<u64, SunnyDbError>;
 }
 
 impl UnixTimestamp for SystemTime {
-    fn timestamp(&self) -> u64 {
+    fn timestamp(&self) -> Result<u64, SunnyDbError> {
+        // we're not going beyond 500 Mio years
         self.duration_since(UNIX_EPOCH)
-            .expect("Time precedes unix epoch!")
-            .as_millis() as u64 // we're not going beyond 500 Mio years
+            .map(|since_epoch| since_epoch.as_millis() as u64)
+            .map_err(|_| SunnyDbError::BeforeEpoch)
     }
 }
 
-impl<T: Copy + Encode + DecodeOwned> TimeSeries<T> {
+/// The range of plausible timestamps in ms, from `min_ms` up to but excluding `max_ms`, to
+/// reject values of a clock that isn't set or of imports in the wrong unit, e.g. in the year
+/// 3000
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimestampBounds {
+    pub min_ms: u64,
+    pub max_ms: u64,
+}
+
+impl Default for TimestampBounds {
+    /// from 2000-01-01 up to 2100-01-01
+    fn default() -> Self {
+        TimestampBounds {
+            min_ms: 946_684_800_000,
+            max_ms: 4_102_444_800_000,
+        }
+    }
+}
+
+impl TimestampBounds {
+    pub fn contains(&self, time_ms: u64) -> bool {
+        (self.min_ms..self.max_ms).contains(&time_ms)
+    }
+
+    /// an error unless the time in ms is within the bounds
+    pub fn check(&self, time_ms: u64) -> Result<(), SunnyDbError> {
+        if self.contains(time_ms) {
+            return Ok(());
+        }
+        Err(SunnyDbError::TimestampOutOfBounds {
+            time: time_ms,
+            min: self.min_ms,
+            max: self.max_ms,
+        })
+    }
+}
+
+impl<T: Codec> TimeSeries<T> {
     pub fn new(init_size: usize) -> Self {
+        TimeSeries::<T>::with_resolution(init_size, Resolution::default())
+    }
+
+    pub fn with_resolution(init_size: usize, resolution: Resolution) -> Self {
         let data = Vec::<TimeSeriesEntry<T>>::with_capacity(init_size);
         TimeSeries {
-            init_size: init_size,
-            data: data,
+            init_size,
+            data,
             start_time: None,
             end_time: None,
+            resolution,
+            duplicate_policy: DuplicatePolicy::default(),
+            window: Window::default(),
         }
     }
 
@@ -48,6 +398,12 @@ impl<T: Copy + Encode + DecodeOwned> TimeSeries<T> {
         self.data.len()
     }
 
+    /// the bytes held by the series, including the capacity reserved for more values
+    pub fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.data.capacity() * std::mem::size_of::<TimeSeriesEntry<T>>()
+    }
+
     pub fn is_empty(&self) -> bool {
         self.start_time.is_none() || self.data.is_empty()
     }
@@ -60,6 +416,74 @@ impl<T: Copy + Encode + DecodeOwned> TimeSeries<T> {
         self.end_time
     }
 
+    pub fn get_resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    /// sets which value is kept when merging series with values at the same time
+    pub fn with_duplicate_policy(mut self, duplicate_policy: DuplicatePolicy) -> Self {
+        self.duplicate_policy = duplicate_policy;
+        self
+    }
+
+    pub fn get_duplicate_policy(&self) -> DuplicatePolicy {
+        self.duplicate_policy
+    }
+
+    /// keeps at most the `max_len` newest values
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.window.max_len = Some(max_len);
+        self.evict();
+        self
+    }
+
+    /// keeps only the values at most `max_age` (in the resolution of the series) older than
+    /// the newest one
+    pub fn with_max_age(mut self, max_age: u64) -> Self {
+        self.window.max_age = Some(max_age);
+        self.evict();
+        self
+    }
+
+    pub fn get_window(&self) -> Window {
+        self.window
+    }
+
+    /// the same series with timestamps of another resolution; when reducing the resolution,
+    /// only the first of several values ending up at the same time is kept
+    pub fn to_resolution(&self, resolution: Resolution) -> TimeSeries<T> {
+        let mut data: Vec<TimeSeriesEntry<T>> = self
+            .data
+            .iter()
+            .map(|entry| TimeSeriesEntry {
+                time: self.resolution.convert(entry.time, resolution),
+                ..*entry
+            })
+            .collect();
+        data.dedup_by_key(|entry| entry.time);
+
+        TimeSeries {
+            init_size: data.len(),
+            start_time: data.first().map(|d| d.time),
+            end_time: data.last().map(|d| d.time),
+            data,
+            resolution,
+            duplicate_policy: self.duplicate_policy,
+            window: Window {
+                max_age: self
+                    .window
+                    .max_age
+                    .map(|age| self.resolution.convert(age, resolution)),
+                ..self.window
+            },
+        }
+    }
+
+    /// iterates over the times and values without copying them
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter(self.data.iter())
+    }
+
     pub fn get_current_values(&self) -> Vec<(u64, T)> {
         self.data
             .iter()
@@ -67,6 +491,13 @@ impl<T: Copy + Encode + DecodeOwned> TimeSeries<T> {
             .collect()
     }
 
+    pub fn get_current_values_with_quality(&self) -> Vec<(u64, T, Quality)> {
+        self.data
+            .iter()
+            .map(|entry| (entry.time, entry.value, entry.quality))
+            .collect()
+    }
+
     pub fn get_current_values_without_time(&self) -> Vec<T> {
         self.data.iter().map(|entry| entry.value).collect()
     }
@@ -93,6 +524,17 @@ impl<T: Copy + Encode + DecodeOwned> TimeSeries<T> {
     }
 
     pub fn get_values_in_range(&self, start_time: u64, end_time: u64) -> Option<TimeSeries<T>> {
+        let series = self.view_range(start_time, end_time)?.to_series();
+        Some(series.with_duplicate_policy(self.duplicate_policy))
+    }
+
+    /// borrowed view of all values of the series
+    pub fn view(&self) -> TimeSeriesView<'_, T> {
+        TimeSeriesView::new(&self.data, self.resolution)
+    }
+
+    /// same as get_values_in_range, but borrows the values instead of copying them
+    pub fn view_range(&self, start_time: u64, end_time: u64) -> Option<TimeSeriesView<'_, T>> {
         if self.data.is_empty() {
             return None;
         }
@@ -102,54 +544,124 @@ impl<T: Copy + Encode + DecodeOwned> TimeSeries<T> {
             return None;
         }
 
+        if start_time >= self.end_time.unwrap() {
+            // after all data points; values at the start time itself aren't included
+            return None;
+        }
+
         let start_index = self.find_last_index_after_time(start_time).unwrap_or(0);
         let end_index = self
             .find_last_index_after_time(end_time)
             .unwrap_or(self.data.len());
 
-        let data = self.data[start_index..end_index].to_vec();
-
+        let data = &self.data[start_index..end_index];
         if data.is_empty() {
             return None;
         }
-
-        let new_series_start_time = data.first().map(|d| d.time);
-        let new_series_end_time = data.last().map(|d| d.time);
-        let tts = TimeSeries {
-            init_size: data.len(),
-            data: data,
-            start_time: new_series_start_time,
-            end_time: new_series_end_time,
-        };
-
-        Some(tts)
+        Some(TimeSeriesView::new(data, self.resolution))
     }
 
     // adding values to the series
     pub fn insert_value_at_current_time(&mut self, value: T) {
-        let now = SystemTime::now().timestamp();
-        let entry = TimeSeriesEntry {
-            time: now,
-            value: value,
-        };
-        self.insert_entry(entry);
+        let now = self.resolution.now();
+        self.insert_value_with_quality(now, value, Quality::Measured);
     }
 
     pub fn insert_value_at_time(&mut self, time: u64, value: T) {
+        self.insert_value_with_quality(time, value, Quality::Measured);
+    }
+
+    pub fn insert_value_with_quality(&mut self, time: u64, value: T, quality: Quality) {
         let entry = TimeSeriesEntry {
-            time: time,
-            value: value,
+            time,
+            value,
+            quality,
         };
         self.insert_entry(entry);
     }
 
+    /// inserts many values at once; as long as their timestamps increase and follow all
+    /// existing values they're appended directly, anything else is inserted at its place
+    pub fn insert_many_sorted(&mut self, values: impl IntoIterator<Item = (u64, T)>) {
+        let values = values.into_iter();
+        self.data.reserve(values.size_hint().0);
+        for (time, value) in values {
+            let entry = TimeSeriesEntry {
+                time,
+                value,
+                quality: Quality::Measured,
+            };
+            if self.end_time.is_none_or(|end| end <= time) {
+                self.data.push(entry);
+                self.update_start_and_end(time);
+            } else {
+                self.insert_entry(entry);
+            }
+        }
+        self.evict();
+    }
+
     fn insert_entry(&mut self, entry: TimeSeriesEntry<T>) {
-        let index = self.find_last_index_after_time(entry.time);
-        match index {
-            Some(idx) => self.data.insert(idx, entry),
-            None => self.data.push(entry),
-        };
+        // after all values at the same time, so values are kept in the order they were inserted
+        let index = self.data.partition_point(|e| e.time <= entry.time);
+        self.data.insert(index, entry);
         self.update_start_and_end(entry.time);
+        self.evict();
+    }
+
+    /// removes the values outside of the window, if the series has one
+    fn evict(&mut self) {
+        if let Some(max_len) = self.window.max_len {
+            self.drop_oldest(self.data.len().saturating_sub(max_len));
+        }
+        if let (Some(max_age), Some(end)) = (self.window.max_age, self.end_time) {
+            self.truncate_before(end.saturating_sub(max_age));
+        }
+    }
+
+    /// removes the `count` oldest values and returns how many were actually removed
+    pub fn drop_oldest(&mut self, count: usize) -> usize {
+        let count = count.min(self.data.len());
+        self.data.drain(..count);
+        self.update_bounds();
+        count
+    }
+
+    /// splits the series into the values before `time` and the ones at or after it
+    pub fn split_at_time(mut self, time: u64) -> (TimeSeries<T>, TimeSeries<T>) {
+        let index = self.data.partition_point(|e| e.time < time);
+        let mut later = TimeSeries {
+            init_size: self.init_size,
+            data: self.data.split_off(index),
+            start_time: None,
+            end_time: None,
+            resolution: self.resolution,
+            duplicate_policy: self.duplicate_policy,
+            window: self.window,
+        };
+        self.update_bounds();
+        later.update_bounds();
+        (self, later)
+    }
+
+    /// removes all values before `time`
+    pub fn truncate_before(&mut self, time: u64) {
+        let index = self.data.partition_point(|e| e.time < time);
+        self.data.drain(..index);
+        self.update_bounds();
+    }
+
+    /// removes all values after `time`
+    pub fn truncate_after(&mut self, time: u64) {
+        let index = self.data.partition_point(|e| e.time <= time);
+        self.data.truncate(index);
+        self.update_bounds();
+    }
+
+    /// sets start and end time after values have been removed
+    fn update_bounds(&mut self) {
+        self.start_time = self.data.first().map(|d| d.time);
+        self.end_time = self.data.last().map(|d| d.time);
     }
 
     fn find_last_index_after_time(&self, time: u64) -> Option<usize> {
@@ -157,46 +669,483 @@ impl<T: Copy + Encode + DecodeOwned> TimeSeries<T> {
             return None;
         }
 
-        self.data
-            .iter()
-            .rposition(|entries| entries.time <= time)
-            .map(|idx| idx + 1)
+        // there's at least one value at or before time, so the index is never 0
+        Some(self.data.partition_point(|entry| entry.time <= time))
+    }
+
+    /// the uncompressed data of a segment, without the resolution
+    pub fn to_bytes(&self) -> std::io::Result<Vec<u8>> {
+        T::encode_segment(&SegmentBody {
+            init_size: self.init_size,
+            data: self.data.clone(),
+            start_time: self.start_time,
+            end_time: self.end_time,
+        })
+    }
+
+    pub fn from_bytes(bytes: &[u8], resolution: Resolution) -> anyhow::Result<TimeSeries<T>> {
+        let body = T::decode_segment(bytes)?;
+        Ok(TimeSeries {
+            init_size: body.init_size,
+            data: body.data,
+            start_time: body.start_time,
+            end_time: body.end_time,
+            resolution,
+            duplicate_policy: DuplicatePolicy::default(),
+            window: Window::default(),
+        })
     }
 
     pub fn to_compressed_json(&self, level: i32) -> std::io::Result<Vec<u8>> {
-        let bytes: &[u8] = &bitcode::encode(self);
-        let output = zstd::stream::encode_all(bytes, level);
-        output
+        self.to_segment(level, None)
+    }
+
+    /// the series in the gorilla encoding, without the resolution
+    pub fn to_gorilla_bytes(&self) -> Vec<u8>
+    where
+        T: FloatFields,
+    {
+        gorilla::encode(self.init_size, &self.data)
+    }
+
+    pub fn from_gorilla_bytes(bytes: &[u8], resolution: Resolution) -> anyhow::Result<TimeSeries<T>>
+    where
+        T: FloatFields,
+    {
+        let (init_size, data) = gorilla::decode(bytes)?;
+        let mut series = TimeSeries::with_resolution(init_size, resolution);
+        series.data = data;
+        series.update_bounds();
+        Ok(series)
+    }
+
+    /// encodes the series as a segment, compressed with the given zstd level and encrypted if
+    /// a key is given
+    pub fn to_segment(&self, level: i32, key: Option<&EncryptionKey>) -> std::io::Result<Vec<u8>> {
+        let bytes: &[u8] = &self.to_bytes()?;
+        let compressed = zstd::stream::encode_all(bytes, level)?;
+        self.seal_segment(T::ID, None, compressed, key)
+    }
+
+    /// encodes the series as a segment in the gorilla encoding instead of the codec of the
+    /// values and zstd, encrypted if a key is given
+    pub fn to_gorilla_segment(&self, key: Option<&EncryptionKey>) -> std::io::Result<Vec<u8>>
+    where
+        T: FloatFields,
+    {
+        self.seal_segment(GORILLA, None, self.to_gorilla_bytes(), key)
+    }
+
+    /// encodes the series as a segment in the given encoding; with `decimals`, the values are
+    /// rounded to that many decimal places and stored as integers scaled by the corresponding
+    /// power of ten, unless one of them doesn't fit into an i32 that way
+    pub fn to_segment_with(
+        &self,
+        encoding: SegmentEncoding,
+        decimals: Option<i8>,
+        level: i32,
+        key: Option<&EncryptionKey>,
+    ) -> std::io::Result<Vec<u8>>
+    where
+        T: FloatFields,
+    {
+        if decimals.is_some_and(|d| d.abs() > MAX_DECIMALS) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Values can't be quantized to that many decimal places",
+            ));
+        }
+        match (encoding, decimals) {
+            (SegmentEncoding::Zstd, None) => self.to_segment(level, key),
+            (SegmentEncoding::Gorilla, None) => self.to_gorilla_segment(key),
+            (SegmentEncoding::Zstd, Some(decimals)) => {
+                match quantization::encode(self.init_size, &self.data, decimals) {
+                    Some(bytes) => {
+                        let compressed = zstd::stream::encode_all(bytes.as_slice(), level)?;
+                        self.seal_segment(QUANTIZED, Some(decimals), compressed, key)
+                    }
+                    None => self.to_segment(level, key),
+                }
+            }
+            (SegmentEncoding::Gorilla, Some(decimals)) => {
+                let scaled = quantization::scale_entries(&self.data, decimals);
+                let bytes = gorilla::encode(self.init_size, &scaled);
+                self.seal_segment(GORILLA, Some(decimals), bytes, key)
+            }
+        }
+    }
+
+    /// puts the header in front of the encoded data
+    fn seal_segment(
+        &self,
+        codec: u8,
+        decimals: Option<i8>,
+        encoded: Vec<u8>,
+        key: Option<&EncryptionKey>,
+    ) -> std::io::Result<Vec<u8>> {
+        let mut segment = SEGMENT_MAGIC.to_vec();
+        segment.push(SEGMENT_VERSION);
+        segment.push(self.resolution.to_header_byte());
+        segment.push(codec);
+        segment.push(match key {
+            Some(_) => CHACHA20_POLY1305,
+            None => NO_CIPHER,
+        });
+        segment.push(decimals.map_or(NOT_QUANTIZED, |d| d as u8));
+        let mut data = match key {
+            Some(key) => key.encrypt(&encoded, &segment)?,
+            None => encoded,
+        };
+        // the checksum covers the encrypted data, so segments can be verified without the key
+        segment.extend_from_slice(&crc32fast::hash(&data).to_le_bytes());
+        segment.append(&mut data);
+        Ok(segment)
     }
 
     pub fn from_compressed_json(compressed_json_bytes: &[u8]) -> anyhow::Result<TimeSeries<T>> {
-        let bytes: &[u8] = &zstd::stream::decode_all(compressed_json_bytes)?;
-        let ts = bitcode::decode(bytes)?;
-        Ok(ts)
+        TimeSeries::<T>::from_segment(compressed_json_bytes, None)
     }
 
-    /// Appends one time series to another mutating the original time series
-    /// **NOTE**: The timeseries to which you append *must* precede the timeseries
-    /// that you are trying to append. Otherwise, this will cause a panic!
-    pub fn append(&mut self, t: &TimeSeries<T>) -> &Self {
-        if t.is_empty() {
+    /// decodes a segment; encrypted segments need the key they were encrypted with, while
+    /// unencrypted ones are read regardless of the key. Segments in the gorilla encoding or
+    /// with quantized values need `from_float_segment`
+    pub fn from_segment(
+        compressed_json_bytes: &[u8],
+        key: Option<&EncryptionKey>,
+    ) -> anyhow::Result<TimeSeries<T>> {
+        TimeSeries::<T>::decode_segment(compressed_json_bytes, key, None)
+    }
+
+    /// decodes a segment like `from_segment`, including segments in the gorilla encoding and
+    /// segments with quantized values
+    pub fn from_float_segment(
+        segment: &[u8],
+        key: Option<&EncryptionKey>,
+    ) -> anyhow::Result<TimeSeries<T>>
+    where
+        T: FloatFields,
+    {
+        TimeSeries::<T>::decode_segment(segment, key, Some(TimeSeries::<T>::decode_float_bytes))
+    }
+
+    fn decode_float_bytes(
+        codec: u8,
+        bytes: &[u8],
+        decimals: Option<i8>,
+    ) -> anyhow::Result<DecodedEntries<T>>
+    where
+        T: FloatFields,
+    {
+        match (codec, decimals) {
+            (GORILLA, decimals) => {
+                let (init_size, mut data) = gorilla::decode(bytes)?;
+                if let Some(decimals) = decimals {
+                    quantization::unscale_entries(&mut data, decimals);
+                }
+                Ok((init_size, data))
+            }
+            (_, Some(decimals)) => {
+                quantization::decode(&zstd::stream::decode_all(bytes)?, decimals)
+            }
+            (_, None) => anyhow::bail!("Quantized segment doesn't record its precision"),
+        }
+    }
+
+    fn decode_segment(
+        segment: &[u8],
+        key: Option<&EncryptionKey>,
+        decode_float_bytes: Option<FloatDecoder<T>>,
+    ) -> anyhow::Result<TimeSeries<T>> {
+        let Some(header) = parse_header(segment)? else {
+            // segments without a header don't have quality flags
+            let bytes: &[u8] = &zstd::stream::decode_all(segment)?;
+            return Ok(T::decode_legacy_segment(bytes)?.into());
+        };
+
+        let decode_float_bytes = match (header.codec, decode_float_bytes) {
+            (GORILLA | QUANTIZED, Some(decode)) => Some(decode),
+            (GORILLA | QUANTIZED, None) => anyhow::bail!(
+                "Segment was written using {}, which needs values implementing FloatFields",
+                codec_name(header.codec)
+            ),
+            (codec, _) if codec != T::ID => anyhow::bail!(
+                "Segment was written using {} but the values are stored using {}",
+                codec_name(codec),
+                codec_name(T::ID)
+            ),
+            _ => None,
+        };
+
+        if checksum_matches(segment) == Some(false) {
+            anyhow::bail!("Segment checksum doesn't match its data");
+        }
+        let data = &segment[header.data_offset..];
+        let decrypted;
+        let data = match (header.cipher, key) {
+            (NO_CIPHER, _) => data,
+            (CHACHA20_POLY1305, Some(key)) => {
+                // everything in front of the checksum is authenticated
+                let authenticated = &segment[..header.data_offset - 4];
+                decrypted = key.decrypt(data, authenticated)?;
+                &decrypted
+            }
+            (CHACHA20_POLY1305, None) => anyhow::bail!("Segment is encrypted but no key was given"),
+            (cipher, _) => anyhow::bail!("Unknown cipher {} in segment", cipher),
+        };
+        if let Some(decode_float_bytes) = decode_float_bytes {
+            let (init_size, data) = decode_float_bytes(header.codec, data, header.decimals)?;
+            let mut series = TimeSeries::with_resolution(init_size, header.resolution);
+            series.data = data;
+            series.update_bounds();
+            return Ok(series);
+        }
+        let bytes: &[u8] = &zstd::stream::decode_all(data)?;
+        TimeSeries::<T>::from_bytes(bytes, header.resolution)
+    }
+
+    /// Merges another series into this one, interleaving the values by time; values at the same
+    /// time are resolved using the duplicate policy of this series. Unlike `append`, the series
+    /// may overlap in any way and use different resolutions; the other series is converted to
+    /// the resolution of this one unless this one is empty
+    pub fn merge(&mut self, other: &TimeSeries<T>) -> &Self {
+        if other.is_empty() {
             return self;
         }
+        if self.is_empty() {
+            self.resolution = other.resolution;
+        }
+        let converted;
+        let other = if other.resolution == self.resolution {
+            other
+        } else {
+            converted = other.to_resolution(self.resolution);
+            &converted
+        };
+
+        let existing = std::mem::take(&mut self.data);
+        let mut merged = Vec::with_capacity(existing.len() + other.data.len());
+        let (mut i, mut j) = (0, 0);
+        while i < existing.len() && j < other.data.len() {
+            let (a, b) = (existing[i], other.data[j]);
+            if a.time < b.time {
+                merged.push(a);
+                i += 1;
+            } else if b.time < a.time {
+                merged.push(b);
+                j += 1;
+            } else {
+                let keep_other = match self.duplicate_policy {
+                    DuplicatePolicy::KeepExisting => false,
+                    DuplicatePolicy::Replace => true,
+                    DuplicatePolicy::BestQuality => b.quality < a.quality,
+                };
+                merged.push(if keep_other { b } else { a });
+                i += 1;
+                j += 1;
+            }
+        }
+        merged.extend_from_slice(&existing[i..]);
+        merged.extend_from_slice(&other.data[j..]);
 
-        if self.start_time > t.start_time {
-            panic!("Tried to append to timeseries in wrong order!")
+        self.init_size = self.init_size.max(merged.len());
+        self.start_time = merged.first().map(|d| d.time);
+        self.end_time = merged.last().map(|d| d.time);
+        self.data = merged;
+        self.evict();
+        self
+    }
+
+    /// Appends one time series to another mutating the original time series
+    /// **NOTE**: The timeseries to which you append *must* follow the timeseries you append
+    /// it to and use the same resolution, otherwise nothing is appended and an error is
+    /// returned; use `merge` for series that may overlap
+    pub fn append(&mut self, t: &TimeSeries<T>) -> Result<&Self, SunnyDbError> {
+        if t.is_empty() {
+            return Ok(self);
         }
 
-        if self.end_time > t.end_time {
-            panic!("Tried to append to timeseries in wrong order!")
+        if self.start_time > t.start_time || self.end_time > t.end_time {
+            return Err(SunnyDbError::AppendOutOfOrder);
         }
 
-        // update end time
-        self.end_time = Some(t.end_time.unwrap());
+        if !self.is_empty() && self.resolution != t.resolution {
+            return Err(SunnyDbError::AppendResolutionMismatch);
+        }
+        self.resolution = t.resolution;
 
-        self.init_size = self.init_size + t.init_size;
+        self.init_size += t.init_size;
         let mut data_to_append = t.data.clone();
         self.data.append(&mut data_to_append);
-        self
+        self.update_bounds();
+        self.evict();
+        Ok(self)
+    }
+}
+
+/// Borrowed, contiguous range of the values of a series, see `TimeSeries::view_range`
+#[derive(Debug)]
+pub struct TimeSeriesView<'a, T> {
+    data: &'a [TimeSeriesEntry<T>],
+    start_time: Option<u64>,
+    end_time: Option<u64>,
+    resolution: Resolution,
+}
+
+// derived Clone and Copy would require T: Copy
+impl<T> Clone for TimeSeriesView<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for TimeSeriesView<'_, T> {}
+
+impl<'a, T: Codec> TimeSeriesView<'a, T> {
+    fn new(data: &'a [TimeSeriesEntry<T>], resolution: Resolution) -> Self {
+        TimeSeriesView {
+            data,
+            start_time: data.first().map(|entry| entry.time),
+            end_time: data.last().map(|entry| entry.time),
+            resolution,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn get_start_time(&self) -> Option<u64> {
+        self.start_time
+    }
+
+    pub fn get_end_time(&self) -> Option<u64> {
+        self.end_time
+    }
+
+    pub fn get_resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    pub fn iter(&self) -> Iter<'a, T> {
+        Iter(self.data.iter())
+    }
+
+    pub fn iter_with_quality(&self) -> impl Iterator<Item = (u64, &'a T, Quality)> {
+        self.data
+            .iter()
+            .map(|entry| (entry.time, &entry.value, entry.quality))
+    }
+
+    pub fn get_current_values(&self) -> Vec<(u64, T)> {
+        self.iter().map(|(time, value)| (time, *value)).collect()
+    }
+
+    /// the stretches of the view between intervals longer than `max_gap`, in the unit of the
+    /// timestamps
+    pub fn split_at_gaps(&self, max_gap: u64) -> impl Iterator<Item = TimeSeriesView<'a, T>> {
+        let resolution = self.resolution;
+        self.data
+            .chunk_by(move |a, b| b.time - a.time <= max_gap)
+            .map(move |run| TimeSeriesView::new(run, resolution))
+    }
+
+    /// copies the values into a new series
+    pub fn to_series(&self) -> TimeSeries<T> {
+        TimeSeries {
+            init_size: self.data.len(),
+            data: self.data.to_vec(),
+            start_time: self.start_time,
+            end_time: self.end_time,
+            resolution: self.resolution,
+            duplicate_policy: DuplicatePolicy::default(),
+            window: Window::default(),
+        }
+    }
+}
+
+impl<'a, T: Codec> IntoIterator for TimeSeriesView<'a, T> {
+    type Item = (u64, &'a T);
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the times and values of a series, see `TimeSeries::iter`
+pub struct Iter<'a, T>(std::slice::Iter<'a, TimeSeriesEntry<T>>);
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = (u64, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|entry| (entry.time, &entry.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<T> DoubleEndedIterator for Iter<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back().map(|entry| (entry.time, &entry.value))
+    }
+}
+
+impl<T> ExactSizeIterator for Iter<'_, T> {}
+
+impl<'a, T: Codec> IntoIterator for &'a TimeSeries<T> {
+    type Item = (u64, &'a T);
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator moving the times and values out of a series
+pub struct IntoIter<T>(std::vec::IntoIter<TimeSeriesEntry<T>>);
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = (u64, T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|entry| (entry.time, entry.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.0.next_back().map(|entry| (entry.time, entry.value))
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+impl<T> IntoIterator for TimeSeries<T> {
+    type Item = (u64, T);
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter(self.data.into_iter())
+    }
+}
+
+/// collects measured values with millisecond timestamps; they don't need to be sorted
+impl<T: Codec> FromIterator<(u64, T)> for TimeSeries<T> {
+    fn from_iter<I: IntoIterator<Item = (u64, T)>>(iter: I) -> Self {
+        let mut ts = TimeSeries::<T>::empty();
+        ts.insert_many_sorted(iter);
+        ts.init_size = ts.len();
+        ts
     }
 }
@@ -1,9 +1,74 @@
-use crate::timeseries::{TimeSeries, UnixTimestamp};
-use bitcode::{DecodeOwned, Encode};
-use std::fs::{self, create_dir_all, remove_file, File};
+use crate::aggregates::{self, AggregateInterval, AGGREGATES_DIR, MAX_GAP_MS};
+use crate::alignment::clip_interpolated;
+#[cfg(feature = "catalog")]
+use crate::catalog::{Catalog, CATALOG_FILE};
+use crate::codec::{Codec, SegmentEncoding};
+use crate::downsampling::{Downsample, DownsamplingMethod};
+use crate::encryption::EncryptionKey;
+use crate::error::SunnyDbError;
+use crate::fields::FloatFields;
+use crate::manifest::{self, Change, ManifestEntry, MANIFEST_FILE};
+use crate::quantization::MAX_DECIMALS;
+use crate::raw_segments::{RawSegment, RawSegments, SegmentCursor, StorageTier};
+use crate::remote::{self, ObjectStore, RemoteTier, REMOTE_SEGMENTS_FILE};
+use crate::rollup::interval_start;
+use crate::running_statistics::RunningStatistics;
+use crate::timeseries::{
+    checksum_matches, segment_info, DuplicatePolicy, Resolution, TimeSeries, TimestampBounds,
+};
+use crate::verify::{Issue, VerifyReport};
+use crate::write_metrics::WriteMetrics;
+use anyhow::Context;
+use chrono::{DateTime, Datelike, NaiveDate};
+use fs2::FileExt;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, create_dir_all, remove_file, rename, File, OpenOptions};
 use std::io::prelude::*;
-use std::path::Path;
-use std::time::SystemTime;
+use std::ops::{Add, Deref, DerefMut, Div, Mul};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime};
+use tracing::{debug, error, info, trace, warn};
+
+/// Name of the file in the data directory recording up to which time segments have been
+/// archived in place
+const ARCHIVE_WATERMARK_FILE: &str = ".archived-until";
+
+/// Extension of segments that are still being written
+const TMP_EXTENSION: &str = "tmp";
+
+/// Pauses between segments longer than this many times the usual interval between values are
+/// reported as gaps when verifying the database
+const GAP_FACTOR: u64 = 10;
+
+/// Name of the lock file next to the data directory that's held by the writable instance
+const LOCK_FILE: &str = ".sunny.lock";
+
+/// How often a query lists and reads the segments again if one of them was removed by a
+/// concurrent compaction before it could be read
+const MAX_READ_ATTEMPTS: usize = 5;
+
+/// A segment with its file and the file's size in bytes
+type SegmentFile = ((u64, u64), PathBuf, u64);
+
+/// Encodes a series as a segment in the given encoding, quantized to the given decimal places,
+/// with the given zstd level and optional key
+type EncodeSegment<T> = fn(
+    &TimeSeries<T>,
+    SegmentEncoding,
+    Option<i8>,
+    i32,
+    Option<&EncryptionKey>,
+) -> std::io::Result<Vec<u8>>;
+
+/// Decodes a segment with the optional key
+type DecodeSegment<T> = fn(&[u8], Option<&EncryptionKey>) -> anyhow::Result<TimeSeries<T>>;
+
+/// Updates the aggregates of the range between two times in ms
+type AggregatesUpdate<T> = fn(&SunnyDB<T>, u64, u64) -> Result<(), SunnyDbError>;
+
+/// Adds a value inserted at a time to the running statistics
+type StatisticsUpdate<T> = fn(&mut RunningStatistics<T>, u64, &T);
 
 pub struct SunnyDB<T> {
     pub time_series: TimeSeries<T>,
@@ -13,109 +78,1579 @@ pub struct SunnyDB<T> {
     compression_level: i32,
     /// Specify at which point a time series segment should be written to disk when the database is closed
     data_loss_threshold: usize,
+    /// Optional directory to which old segments are moved when archiving them
+    cold_data_path: Option<String>,
+    /// The segment last written by flushing the values that are still in memory; it's
+    /// replaced whenever these values are written again so no data ends up on disk twice
+    flushed_segment: Option<PathBuf>,
+    /// The lock file of a writable instance; the lock is held for as long as the file is open.
+    /// Instances opened read-only don't have one and refuse to write anything
+    lock_file: Option<File>,
+    /// Upper bound for the number of values kept in memory while segments can't be written,
+    /// e.g. because the disk is full; beyond it the oldest values are dropped
+    max_in_memory_points: usize,
+    /// Number of values dropped because of the cap above
+    dropped_points: u64,
+    /// Range of timestamps values are accepted at, if it's restricted
+    timestamp_bounds: Option<TimestampBounds>,
+    /// Number of values rejected because of their timestamps
+    rejected_points: u64,
+    /// Number of failed attempts to write a full segment
+    failed_exports: u64,
+    /// Key to encrypt new segments with and decrypt encrypted ones
+    encryption_key: Option<EncryptionKey>,
+    /// Keeps the energy aggregates up to date whenever segments are written, if enabled
+    energy_aggregates: Option<AggregatesUpdate<T>>,
+    /// Optional object store old segments are offloaded to
+    remote: Option<Arc<RemoteTier>>,
+    /// How segments are written and read, see `with_segment_encoding`
+    segment_codec: SegmentCodec<T>,
+    /// Durations of inserts and writes and the sizes of the written segments
+    write_metrics: WriteMetrics,
+    /// Statistics of the values inserted since some point in time, if they're maintained
+    running_statistics: Option<(RunningStatistics<T>, StatisticsUpdate<T>)>,
+    /// Mirror of the manifest and the segments, if it's maintained
+    #[cfg(feature = "catalog")]
+    catalog: Option<Catalog>,
 }
 
-impl<T: Copy + DecodeOwned + Encode> SunnyDB<T> {
+/// Writes and reads segments; the values have to implement `FloatFields` for segments in the
+/// gorilla encoding or with quantized values to be read, which is only known once
+/// `with_segment_encoding` or `with_quantization` is called
+struct SegmentCodec<T> {
+    encoding: SegmentEncoding,
+    decimals: Option<i8>,
+    encode: EncodeSegment<T>,
+    decode: DecodeSegment<T>,
+}
+
+impl<T> Clone for SegmentCodec<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for SegmentCodec<T> {}
+
+impl<T: Codec> SegmentCodec<T> {
+    fn zstd() -> Self {
+        SegmentCodec {
+            encoding: SegmentEncoding::Zstd,
+            decimals: None,
+            encode: |series, _, _, level, key| series.to_segment(level, key),
+            decode: TimeSeries::<T>::from_segment,
+        }
+    }
+
+    fn floats(encoding: SegmentEncoding, decimals: Option<i8>) -> Self
+    where
+        T: FloatFields,
+    {
+        SegmentCodec {
+            encoding,
+            decimals,
+            encode: TimeSeries::<T>::to_segment_with,
+            decode: TimeSeries::<T>::from_float_segment,
+        }
+    }
+
+    fn encode(
+        &self,
+        series: &TimeSeries<T>,
+        level: i32,
+        key: Option<&EncryptionKey>,
+    ) -> std::io::Result<Vec<u8>> {
+        (self.encode)(series, self.encoding, self.decimals, level, key)
+    }
+}
+
+impl<T: Codec> SunnyDB<T> {
     pub fn new(
         time_series_cache_size: usize,
         dir_path: &str,
         compression_level: i32,
         data_loss_threshold: usize,
-    ) -> Self {
-        let data_dir_path = Self::init_directory(dir_path);
+    ) -> Result<Self, SunnyDbError> {
+        let data_dir_path = Self::init_directory(dir_path)?;
+        let lock_file = Self::lock_directory(dir_path)?;
+        Self::remove_stale_temp_files(Path::new(&data_dir_path));
+        Self::migrate_flat_segments(&data_dir_path)?;
+        Self::merge_overlapping_segments(
+            &data_dir_path,
+            compression_level,
+            None,
+            SegmentCodec::zstd(),
+        )?;
 
         let time_series = TimeSeries::<T>::new(time_series_cache_size);
-        SunnyDB {
-            time_series: time_series,
-            time_series_cache_size: time_series_cache_size,
+        let db = SunnyDB {
+            time_series,
+            time_series_cache_size,
             data_path: data_dir_path,
-            compression_level: compression_level,
-            data_loss_threshold: data_loss_threshold,
+            compression_level,
+            data_loss_threshold,
+            cold_data_path: None,
+            flushed_segment: None,
+            lock_file: Some(lock_file),
+            max_in_memory_points: usize::MAX,
+            dropped_points: 0,
+            timestamp_bounds: None,
+            rejected_points: 0,
+            failed_exports: 0,
+            encryption_key: None,
+            energy_aggregates: None,
+            remote: None,
+            segment_codec: SegmentCodec::zstd(),
+            write_metrics: WriteMetrics::default(),
+            running_statistics: None,
+            #[cfg(feature = "catalog")]
+            catalog: None,
+        };
+        db.update_manifest();
+        Ok(db)
+    }
+
+    /// opens an existing database without writing to it, e.g. to analyse the data while
+    /// another process is writing to it; values in the writer's memory aren't visible and
+    /// anything that would modify the data directory returns an error
+    pub fn open_read_only(dir_path: &str) -> Result<Self, SunnyDbError> {
+        let data_dir_path = Path::new(dir_path).join("data/");
+        if !data_dir_path.is_dir() {
+            return Err(SunnyDbError::NoDataDirectory(data_dir_path));
+        }
+
+        Ok(SunnyDB {
+            time_series: TimeSeries::<T>::new(0),
+            time_series_cache_size: 0,
+            data_path: data_dir_path.to_string_lossy().into_owned(),
+            compression_level: 0,
+            data_loss_threshold: 0,
+            cold_data_path: None,
+            flushed_segment: None,
+            lock_file: None,
+            max_in_memory_points: usize::MAX,
+            dropped_points: 0,
+            timestamp_bounds: None,
+            rejected_points: 0,
+            failed_exports: 0,
+            encryption_key: None,
+            energy_aggregates: None,
+            remote: None,
+            segment_codec: SegmentCodec::zstd(),
+            write_metrics: WriteMetrics::default(),
+            running_statistics: None,
+            #[cfg(feature = "catalog")]
+            catalog: None,
+        })
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.lock_file.is_none()
+    }
+
+    /// sets the resolution of the timestamps of new values (and of all values read, which are
+    /// converted if they were stored with another one); milliseconds by default. Segment file
+    /// names always use milliseconds
+    pub fn with_resolution(mut self, resolution: Resolution) -> Self {
+        self.time_series = self.time_series.to_resolution(resolution);
+        self
+    }
+
+    pub fn get_resolution(&self) -> Resolution {
+        self.time_series.get_resolution()
+    }
+
+    /// caps the number of values kept in memory when full segments can't be written; values
+    /// are retained (and writing them is retried with every new value) until the cap is hit,
+    /// then the oldest ones are dropped. Unbounded by default
+    pub fn with_max_in_memory_points(
+        mut self,
+        max_in_memory_points: usize,
+    ) -> Result<Self, SunnyDbError> {
+        if max_in_memory_points < self.time_series_cache_size {
+            return Err(SunnyDbError::InMemoryCapTooSmall {
+                max: max_in_memory_points,
+                segment_size: self.time_series_cache_size,
+            });
+        }
+        self.max_in_memory_points = max_in_memory_points;
+        Ok(self)
+    }
+
+    /// rejects inserted values with timestamps outside of the bounds, e.g. of a clock that
+    /// isn't set, and fails imports of such values
+    pub fn with_timestamp_bounds(mut self, bounds: TimestampBounds) -> Result<Self, SunnyDbError> {
+        if bounds.min_ms > bounds.max_ms {
+            return Err(SunnyDbError::InvalidTimestampBounds {
+                min: bounds.min_ms,
+                max: bounds.max_ms,
+            });
+        }
+        self.timestamp_bounds = Some(bounds);
+        Ok(self)
+    }
+
+    /// how many inserted values have been rejected so far because of their timestamps
+    pub fn rejected_points(&self) -> u64 {
+        self.rejected_points
+    }
+
+    /// an error if the time in the unit of the database is out of the bounds
+    fn check_timestamp(&self, time: u64) -> Result<(), SunnyDbError> {
+        match self.timestamp_bounds {
+            Some(bounds) => bounds.check(self.get_resolution().to_millis(time)),
+            None => Ok(()),
+        }
+    }
+
+    /// whether a value may be inserted at the time; counts and logs it otherwise
+    fn accepts(&mut self, time: u64) -> bool {
+        let Err(e) = self.check_timestamp(time) else {
+            return true;
+        };
+        self.rejected_points += 1;
+        warn!(
+            time,
+            rejected_total = self.rejected_points,
+            error = %e,
+            "Rejected a value because of its timestamp"
+        );
+        false
+    }
+
+    /// how many values have been dropped so far because they couldn't be written to disk
+    pub fn dropped_points(&self) -> u64 {
+        self.dropped_points
+    }
+
+    /// how many attempts to write a full segment have failed so far
+    pub fn failed_exports(&self) -> u64 {
+        self.failed_exports
+    }
+
+    /// how long inserting values and writing segments took so far, and how big the segments
+    /// were, to notice regressions of the write path
+    pub fn write_metrics(&self) -> &WriteMetrics {
+        &self.write_metrics
+    }
+
+    /// maintains the SQLite catalog next to the data directory (see `Catalog`), creating it if
+    /// it doesn't exist yet and adding the manifest entries it's missing (or recording all of
+    /// them again if it has gaps); read-only databases only read it
+    #[cfg(feature = "catalog")]
+    pub fn with_catalog(mut self) -> Result<Self, SunnyDbError> {
+        let data_path = Path::new(&self.data_path);
+        let path = data_path.parent().unwrap_or(data_path).join(CATALOG_FILE);
+        let open = || -> anyhow::Result<Catalog> {
+            if self.is_read_only() {
+                return Catalog::open_read_only(&path);
+            }
+            let catalog = Catalog::open(&path)?;
+            if !catalog.is_contiguous()? {
+                warn!("The catalog is missing manifest entries, recording all of them again");
+                catalog.clear()?;
+            }
+            catalog.record(&self.manifest_since(catalog.last_seq()?)?)?;
+            Ok(catalog)
+        };
+        let catalog = open().map_err(|e| SunnyDbError::Catalog {
+            path: path.clone(),
+            reason: format!("{:#}", e),
+        })?;
+        info!(path = %path.display(), "Opened the catalog");
+        self.catalog = Some(catalog);
+        Ok(self)
+    }
+
+    #[cfg(feature = "catalog")]
+    pub fn catalog(&self) -> Option<&Catalog> {
+        self.catalog.as_ref()
+    }
+
+    /// maintains statistics of the values inserted from `since` on, which are read in O(1) by
+    /// `running_statistics`; the values stored since then are added right away
+    pub fn with_running_statistics(mut self, since: u64) -> Self
+    where
+        T: FloatFields,
+    {
+        self.running_statistics = Some((
+            RunningStatistics::new(since, self.get_resolution()),
+            RunningStatistics::add,
+        ));
+        self.add_stored_values_to_running_statistics();
+        self
+    }
+
+    pub fn running_statistics(&self) -> Option<&RunningStatistics<T>> {
+        self.running_statistics
+            .as_ref()
+            .map(|(statistics, _)| statistics)
+    }
+
+    /// starts the running statistics over from `since`, e.g. with every new day, and adds the
+    /// values stored since then; does nothing if they aren't maintained
+    pub fn reset_running_statistics(&mut self, since: u64) {
+        let Some((statistics, _)) = &mut self.running_statistics else {
+            return;
+        };
+        statistics.reset(since);
+        self.add_stored_values_to_running_statistics();
+    }
+
+    fn add_stored_values_to_running_statistics(&mut self) {
+        let Some(since) = self.running_statistics().map(|s| s.since) else {
+            return;
+        };
+        let end_time = self
+            .time_series
+            .get_end_time()
+            .unwrap_or(self.get_resolution().now())
+            .max(since);
+        // the range doesn't include its start
+        let stored = self.get_values_in_range(since.saturating_sub(1), end_time);
+        if let (Some((statistics, add)), Some(stored)) = (&mut self.running_statistics, stored) {
+            for (time, value) in stored.iter() {
+                add(statistics, time, value);
+            }
+        }
+    }
+
+    /// the bytes the database holds in memory, e.g. to pick a segment size that fits a small
+    /// device; segments are only read while answering queries, so they don't count
+    pub fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            series: self.time_series.memory_usage(),
+            remote_index: self
+                .remote
+                .as_ref()
+                .map_or(0, |remote| remote.memory_usage()),
+            statistics: self
+                .running_statistics()
+                .map_or(0, |statistics| statistics.memory_usage())
+                + self.write_metrics.memory_usage(),
+        }
+    }
+
+    fn new_time_series(&self) -> TimeSeries<T> {
+        TimeSeries::<T>::with_resolution(self.time_series_cache_size, self.get_resolution())
+    }
+
+    /// takes an advisory lock so no two writable instances use the same directory; the lock
+    /// is released by the OS when the process exits, so it can't go stale
+    fn lock_directory(dir_path: &str) -> Result<File, SunnyDbError> {
+        let lock_path = Path::new(dir_path).join(LOCK_FILE);
+        let locked = |source| SunnyDbError::Locked {
+            path: PathBuf::from(dir_path),
+            source,
+        };
+        let lock_file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .map_err(locked)?;
+        lock_file.try_lock_exclusive().map_err(locked)?;
+        Ok(lock_file)
+    }
+
+    fn ensure_writable(&self) -> Result<(), SunnyDbError> {
+        if self.is_read_only() {
+            return Err(SunnyDbError::ReadOnly);
+        }
+        Ok(())
+    }
+
+    /// encrypts all segments written from now on with the key and decrypts encrypted ones when
+    /// reading them; existing unencrypted segments stay readable and are encrypted when they're
+    /// rewritten, e.g. when archiving them. Set it before the cold storage tier
+    pub fn with_encryption_key(mut self, key: EncryptionKey) -> Result<Self, SunnyDbError> {
+        self.encryption_key = Some(key);
+        if !self.is_read_only() {
+            // opening couldn't merge overlapping encrypted segments without the key
+            Self::merge_overlapping_segments(
+                &self.data_path,
+                self.compression_level,
+                self.encryption_key.as_ref(),
+                self.segment_codec,
+            )?;
+            self.update_manifest();
+        }
+        Ok(self)
+    }
+
+    /// writes new segments in the given encoding and reads segments in the gorilla encoding or
+    /// with quantized values, whichever way they were written; segments written before aren't
+    /// converted until they're rewritten. Set it before any other storage tiers
+    pub fn with_segment_encoding(self, encoding: SegmentEncoding) -> Result<Self, SunnyDbError>
+    where
+        T: FloatFields,
+    {
+        let decimals = self.segment_codec.decimals;
+        self.with_segment_codec(SegmentCodec::floats(encoding, decimals))
+    }
+
+    /// rounds the values of new segments to the given number of decimal places (negative ones
+    /// round to multiples of powers of ten) and stores them as scaled integers, e.g. 1 for
+    /// watts to 0.1 W; segments with values too large for that are stored as they are. Like
+    /// `with_segment_encoding`, segments written either way are read
+    pub fn with_quantization(self, decimals: i8) -> Result<Self, SunnyDbError>
+    where
+        T: FloatFields,
+    {
+        if decimals.abs() > MAX_DECIMALS {
+            return Err(SunnyDbError::QuantizationOutOfRange(decimals));
+        }
+        let encoding = self.segment_codec.encoding;
+        self.with_segment_codec(SegmentCodec::floats(encoding, Some(decimals)))
+    }
+
+    fn with_segment_codec(mut self, codec: SegmentCodec<T>) -> Result<Self, SunnyDbError> {
+        self.segment_codec = codec;
+        if !self.is_read_only() {
+            // opening couldn't merge overlapping segments only values implementing FloatFields
+            // can be stored in
+            Self::merge_overlapping_segments(
+                &self.data_path,
+                self.compression_level,
+                self.encryption_key.as_ref(),
+                self.segment_codec,
+            )?;
+            self.update_manifest();
+        }
+        Ok(self)
+    }
+
+    /// sets up a cold storage tier (e.g. on a slower, larger disk); archived segments are
+    /// moved there and are still considered when reading data
+    pub fn with_cold_storage(mut self, dir_path: &str) -> Result<Self, SunnyDbError> {
+        if self.is_read_only() {
+            let cold_data_path = Path::new(dir_path).join("data/");
+            self.cold_data_path = Some(cold_data_path.to_string_lossy().into_owned());
+            return Ok(self);
+        }
+
+        let cold_data_path = Self::init_directory(dir_path)?;
+        Self::remove_stale_temp_files(Path::new(&cold_data_path));
+        Self::merge_overlapping_segments(
+            &cold_data_path,
+            self.compression_level,
+            self.encryption_key.as_ref(),
+            self.segment_codec,
+        )?;
+        self.cold_data_path = Some(cold_data_path);
+        self.update_manifest();
+        Ok(self)
+    }
+
+    /// sets up a remote storage tier, e.g. an S3 bucket, that segments are moved to by
+    /// `offload`; the list of offloaded segments is kept next to the data directory and they're
+    /// fetched from the store whenever a query needs them
+    pub fn with_remote_storage(
+        mut self,
+        store: Box<dyn ObjectStore>,
+    ) -> Result<Self, SunnyDbError> {
+        let data_path = Path::new(&self.data_path);
+        let segments_path = data_path
+            .parent()
+            .unwrap_or(data_path)
+            .join(REMOTE_SEGMENTS_FILE);
+        self.remote = Some(Arc::new(RemoteTier::open(store, segments_path)?));
+        Ok(self)
+    }
+
+    fn init_directory(dir_path: &str) -> Result<String, SunnyDbError> {
+        let data_dir_path = if dir_path.ends_with('/') {
+            dir_path.to_owned() + "data/"
+        } else {
+            dir_path.to_owned() + "/data/"
+        };
+        let directory_error = |source| SunnyDbError::Directory {
+            path: PathBuf::from(&data_dir_path),
+            source,
+        };
+        create_dir_all(&data_dir_path).map_err(directory_error)?;
+
+        // make sure we can write to the directory right away instead of on the first export
+        let permission_file_path = data_dir_path.to_owned() + ".permission-check.tiny.db";
+        File::create(&permission_file_path).map_err(directory_error)?;
+        remove_file(permission_file_path).map_err(directory_error)?;
+
+        Ok(data_dir_path)
+    }
+
+    /// older versions stored all segments directly in the data directory; move them
+    /// into the date-partitioned layout
+    fn migrate_flat_segments(data_dir_path: &str) -> Result<(), SunnyDbError> {
+        let files: Vec<fs::DirEntry> = fs::read_dir(data_dir_path)?
+            .flatten()
+            .filter(|entry| entry.file_type().is_ok_and(|t| t.is_file()))
+            .collect();
+
+        let mut migrated = 0;
+        for file in files {
+            let Some(segment) = Self::parse_filename_to_times(&file) else {
+                continue;
+            };
+
+            let partition = Self::partition_path(data_dir_path, segment.0);
+            create_dir_all(&partition)?;
+            rename(file.path(), partition.join(file.file_name()))?;
+            migrated += 1;
+        }
+
+        if migrated > 0 {
+            info!(
+                segments = migrated,
+                data_dir = data_dir_path,
+                "Migrated segments to the date-partitioned layout"
+            );
+        }
+        Ok(())
+    }
+
+    /// segments whose ranges intersect (e.g. when values persisted on shutdown were written
+    /// again as part of a full segment) can't be read back in order; merge them into a single
+    /// segment without duplicated values
+    fn merge_overlapping_segments(
+        data_dir_path: &str,
+        compression_level: i32,
+        key: Option<&EncryptionKey>,
+        codec: SegmentCodec<T>,
+    ) -> Result<(), SunnyDbError> {
+        let segments = Self::list_segments_in(data_dir_path, 0, u64::MAX);
+
+        let mut groups: Vec<Vec<(u64, u64)>> = Vec::new();
+        for segment in segments {
+            match groups.last_mut() {
+                Some(group) if segment.0 <= group.iter().map(|s| s.1).max().unwrap_or(0) => {
+                    group.push(segment)
+                }
+                _ => groups.push(vec![segment]),
+            }
+        }
+
+        for group in groups.into_iter().filter(|g| g.len() > 1) {
+            let paths: Vec<PathBuf> = group
+                .iter()
+                .map(|seg| {
+                    Self::partition_path(data_dir_path, seg.0).join(format!("{}-{}", seg.0, seg.1))
+                })
+                .collect();
+
+            let mut series = Vec::new();
+            for path in &paths {
+                match Self::read_segment_file(path, key, codec) {
+                    Ok(ts) => series.push(ts),
+                    Err(e) => {
+                        warn!(
+                            segment = %path.display(),
+                            error = %e,
+                            "Couldn't merge overlapping segment"
+                        );
+                        series.clear();
+                        break;
+                    }
+                }
+            }
+            if series.is_empty() {
+                continue;
+            }
+            let merged = Self::merge_series(series);
+
+            // write the merged segment before removing anything, so a crash in between only
+            // means we'll have to merge again next time
+            let merged_path =
+                Self::write_segment(data_dir_path, &merged, compression_level, key, codec)?;
+            for path in paths.iter().filter(|p| **p != merged_path) {
+                remove_file(path)?;
+            }
+            info!(
+                segments = paths.len(),
+                values = merged.len(),
+                merged = %merged_path.display(),
+                "Merged overlapping segments"
+            );
+        }
+        Ok(())
+    }
+
+    /// combines the values of several series into one without duplicated times; for values at
+    /// the same time, the more reliable one or, if they're equally reliable, the one of the
+    /// earlier series in the list is kept, so e.g. backfilled values never replace measured
+    /// ones. The merged series has the finest resolution of all of them
+    fn merge_series(series: Vec<TimeSeries<T>>) -> TimeSeries<T> {
+        let resolution = series
+            .iter()
+            .map(|ts| ts.get_resolution())
+            .max_by_key(|r| r.per_second())
+            .unwrap_or_default();
+        let mut merged = TimeSeries::<T>::with_resolution(0, resolution)
+            .with_duplicate_policy(DuplicatePolicy::BestQuality);
+        for ts in &series {
+            merged.merge(&ts.to_resolution(resolution));
+        }
+        merged
+    }
+
+    /// ingests the segments of another sunny database (in either the flat or the date-partitioned
+    /// layout) into this one; segments overlapping existing ones are merged without duplicating
+    /// any values; returns the number of imported segments
+    #[tracing::instrument(skip(self))]
+    pub fn import_from(&mut self, dir_path: &str) -> anyhow::Result<usize> {
+        self.ensure_writable()?;
+        let source = Path::new(dir_path).join("data");
+        let source = if source.is_dir() {
+            source
+        } else {
+            PathBuf::from(dir_path)
+        };
+        if fs::canonicalize(&source)? == fs::canonicalize(&self.data_path)? {
+            anyhow::bail!("Can't import a database into itself");
+        }
+
+        let files = Self::segment_files_in(&source)?;
+        if let Some(bounds) = self.timestamp_bounds {
+            for ((start, end), path) in &files {
+                bounds
+                    .check(*start)
+                    .and_then(|_| bounds.check(*end))
+                    .with_context(|| format!("Won't import segment {}", path.display()))?;
+            }
+        }
+        for (_, path) in &files {
+            let key = self.encryption_key.as_ref();
+            let imported = Self::read_segment_file(path, key, self.segment_codec)
+                .with_context(|| format!("Couldn't read segment {}", path.display()))?;
+            self.write_imported_segment(imported)?;
+        }
+
+        Self::merge_overlapping_segments(
+            &self.data_path,
+            self.compression_level,
+            self.encryption_key.as_ref(),
+            self.segment_codec,
+        )?;
+        self.update_manifest();
+        let imported_range = files
+            .iter()
+            .map(|((start, _), _)| *start)
+            .min()
+            .zip(files.iter().map(|((_, end), _)| *end).max());
+        if let Some((start, end)) = imported_range {
+            self.maintain_aggregates(start, end);
+        }
+        info!(
+            segments = files.len(),
+            source = %source.display(),
+            "Imported segments"
+        );
+        Ok(files.len())
+    }
+
+    /// writes values from another source, e.g. historical ones exported by another system,
+    /// as segments of the usual size, keeping their quality flags; values overlapping existing
+    /// segments are merged without duplicating any. Returns the number of segments written
+    #[tracing::instrument(skip_all, fields(values = series.len()))]
+    pub fn import_series(&mut self, series: &TimeSeries<T>) -> anyhow::Result<usize> {
+        self.ensure_writable()?;
+        if let Some(bounds) = self.timestamp_bounds {
+            let resolution = series.get_resolution();
+            for time in [series.get_start_time(), series.get_end_time()]
+                .into_iter()
+                .flatten()
+            {
+                bounds
+                    .check(resolution.to_millis(time))
+                    .context("Won't import values outside of the accepted timestamps")?;
+            }
+        }
+        let values: Vec<_> = series.view().iter_with_quality().collect();
+        let chunks = values.chunks(self.time_series_cache_size.max(1));
+        let segments = chunks.len();
+        for chunk in chunks {
+            let mut ts = TimeSeries::<T>::with_resolution(chunk.len(), series.get_resolution());
+            for (time, value, quality) in chunk {
+                ts.insert_value_with_quality(*time, **value, *quality);
+            }
+            self.write_imported_segment(ts)?;
+        }
+
+        Self::merge_overlapping_segments(
+            &self.data_path,
+            self.compression_level,
+            self.encryption_key.as_ref(),
+            self.segment_codec,
+        )?;
+        self.update_manifest();
+        let resolution = series.get_resolution();
+        if let (Some(start), Some(end)) = (series.get_start_time(), series.get_end_time()) {
+            self.maintain_aggregates(resolution.to_millis(start), resolution.to_millis(end));
+        }
+        Ok(segments)
+    }
+
+    /// writes an imported segment, merging it with an existing one of the same name
+    fn write_imported_segment(&self, imported: TimeSeries<T>) -> anyhow::Result<()> {
+        let resolution = imported.get_resolution();
+        let (Some(start), Some(end)) = (imported.get_start_time(), imported.get_end_time()) else {
+            return Ok(());
+        };
+        let (start, end) = (resolution.to_millis(start), resolution.to_millis(end));
+        let existing_path =
+            Self::partition_path(&self.data_path, start).join(format!("{}-{}", start, end));
+        let ts = if existing_path.exists() {
+            let existing = Self::read_segment_file(
+                &existing_path,
+                self.encryption_key.as_ref(),
+                self.segment_codec,
+            )?;
+            Self::merge_series(vec![existing, imported])
+        } else {
+            imported
+        };
+        Self::write_segment(
+            &self.data_path,
+            &ts,
+            self.compression_level,
+            self.encryption_key.as_ref(),
+            self.segment_codec,
+        )?;
+        Ok(())
+    }
+
+    /// checks the segments of all storage tiers for corruption, names that don't match their
+    /// content, overlaps and gaps, e.g. after an unclean shutdown; nothing is modified
+    pub fn verify(&self) -> VerifyReport {
+        let mut report = VerifyReport::default();
+        let mut roots = vec![self.data_path.as_str()];
+        if let Some(cold_data_path) = &self.cold_data_path {
+            roots.push(cold_data_path);
+        }
+
+        let mut segments: Vec<((u64, u64), PathBuf)> = Vec::new();
+        let mut intervals: Vec<u64> = Vec::new();
+        for root in roots {
+            let mut files = match Self::segment_files_in(Path::new(root)) {
+                Ok(files) => files,
+                Err(e) => {
+                    report.issues.push(Issue::Unreadable {
+                        path: PathBuf::from(root),
+                        error: e.to_string(),
+                    });
+                    continue;
+                }
+            };
+            files.sort();
+
+            for (segment, path) in files {
+                report.segments += 1;
+                if path.parent() != Some(Self::partition_path(root, segment.0).as_path()) {
+                    report
+                        .issues
+                        .push(Issue::WrongPartition { path: path.clone() });
+                }
+
+                let bytes = match fs::read(&path) {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        report.issues.push(Issue::Unreadable {
+                            path,
+                            error: e.to_string(),
+                        });
+                        continue;
+                    }
+                };
+                match checksum_matches(&bytes) {
+                    Some(true) => (),
+                    Some(false) => {
+                        report.issues.push(Issue::ChecksumMismatch { path });
+                        continue;
+                    }
+                    None => report.segments_without_checksum += 1,
+                }
+                let ts = match (self.segment_codec.decode)(&bytes, self.encryption_key.as_ref()) {
+                    Ok(ts) => ts,
+                    Err(e) => {
+                        report.issues.push(Issue::Unreadable {
+                            path,
+                            error: e.to_string(),
+                        });
+                        continue;
+                    }
+                };
+
+                // names and gaps are checked in ms, whatever the resolution of the segment is
+                let resolution = ts.get_resolution();
+                let times: Vec<u64> = ts.iter().map(|(t, _)| resolution.to_millis(t)).collect();
+                report.values += times.len();
+                if times.windows(2).any(|w| w[0] >= w[1]) {
+                    report.issues.push(Issue::Unsorted { path: path.clone() });
+                }
+                intervals.extend(
+                    times
+                        .windows(2)
+                        .filter(|w| w[1] > w[0])
+                        .map(|w| w[1] - w[0]),
+                );
+
+                let content = times.first().copied().zip(times.last().copied());
+                if content != Some(segment) {
+                    report.issues.push(Issue::RangeMismatch {
+                        path: path.clone(),
+                        name: segment,
+                        content,
+                    });
+                }
+                segments.push((segment, path));
+            }
+        }
+
+        intervals.sort_unstable();
+        let typical_interval = intervals.get(intervals.len() / 2).copied();
+
+        segments.sort();
+        // the segment reaching furthest so far, so segments spanning several others are caught
+        let mut furthest: Option<(&(u64, u64), &PathBuf)> = None;
+        for (segment, path) in &segments {
+            if let Some((previous, previous_path)) = furthest {
+                if segment.0 <= previous.1 {
+                    report.issues.push(Issue::Overlap {
+                        first: previous_path.clone(),
+                        second: path.clone(),
+                    });
+                } else if typical_interval
+                    .is_some_and(|interval| segment.0 - previous.1 > GAP_FACTOR * interval)
+                {
+                    report.issues.push(Issue::Gap {
+                        start: previous.1,
+                        end: segment.0,
+                    });
+                }
+            }
+            if furthest.is_none_or(|(previous, _)| segment.1 > previous.1) {
+                furthest = Some((segment, path));
+            }
+        }
+
+        report
+    }
+
+    /// all segment files below the given directory, regardless of the layout
+    fn segment_files_in(path: &Path) -> std::io::Result<Vec<((u64, u64), PathBuf)>> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                files.append(&mut Self::segment_files_in(&entry.path())?);
+            } else if let Some(segment) = Self::parse_filename_to_times(&entry) {
+                files.push((segment, entry.path()));
+            }
+        }
+        Ok(files)
+    }
+
+    /// segments are stored in data/YYYY/MM/DD/ directories according to the (UTC) day they start on
+    fn partition_path(data_dir_path: &str, start_time: u64) -> PathBuf {
+        let day = Self::day_of(start_time);
+        Path::new(data_dir_path).join(format!(
+            "{:04}/{:02}/{:02}",
+            day.year(),
+            day.month(),
+            day.day()
+        ))
+    }
+
+    fn day_of(time: u64) -> NaiveDate {
+        i64::try_from(time)
+            .ok()
+            .and_then(DateTime::from_timestamp_millis)
+            .map(|t| t.date_naive())
+            .unwrap_or(NaiveDate::MAX)
+    }
+
+    /// inserts the value at the current time, which is returned; None if it's outside of the
+    /// timestamp bounds and the value was rejected
+    pub fn insert_value_at_current_time(&mut self, value: T) -> Option<u64> {
+        let started = Instant::now();
+        let now = self.get_resolution().now();
+        if !self.accepts(now) {
+            return None;
+        }
+        self.time_series.insert_value_at_time(now, value);
+        if let Some((statistics, add)) = &mut self.running_statistics {
+            add(statistics, now, &value);
+        }
+        self.dump_time_series_if_full();
+        self.write_metrics.record_insert(started.elapsed());
+        Some(now)
+    }
+
+    pub fn insert_value_at_time(&mut self, time: u64, value: T) {
+        trace!(time, "Inserting value");
+        let started = Instant::now();
+        if !self.accepts(time) {
+            return;
+        }
+        self.time_series.insert_value_at_time(time, value);
+        if let Some((statistics, add)) = &mut self.running_statistics {
+            add(statistics, time, &value);
+        }
+        self.dump_time_series_if_full();
+        self.write_metrics.record_insert(started.elapsed());
+    }
+
+    /// inserts all values in one pass and checks whether the series in memory is full only
+    /// afterwards, so at most one (possibly oversized) segment is written; much faster than
+    /// inserting the values one by one when backfilling. Returns the number of inserted values,
+    /// which leaves out those rejected because of their timestamps
+    pub fn insert_many(&mut self, values: impl IntoIterator<Item = (u64, T)>) -> usize {
+        let started = Instant::now();
+        let before = self.time_series.len();
+        let values: Vec<(u64, T)> = values
+            .into_iter()
+            .filter(|(time, _)| self.accepts(*time))
+            .collect();
+        if let Some((statistics, add)) = &mut self.running_statistics {
+            for (time, value) in &values {
+                add(statistics, *time, value);
+            }
+        }
+        self.time_series.insert_many_sorted(values);
+        let inserted = self.time_series.len() - before;
+        debug!(inserted, "Inserted values");
+        self.dump_time_series_if_full();
+        self.write_metrics.record_insert(started.elapsed());
+        inserted
+    }
+
+    fn dump_time_series_if_full(&mut self) {
+        if self.time_series.len() >= self.time_series_cache_size {
+            match self.export_time_series_to_file() {
+                Ok(_) => self.time_series = self.new_time_series(),
+                Err(e) => {
+                    self.failed_exports += 1;
+                    error!(
+                        values = self.time_series.len(),
+                        failed_exports = self.failed_exports,
+                        error = %e,
+                        "Couldn't write the full segment; keeping its values in memory and retrying with the next value"
+                    );
+                    self.drop_values_over_cap();
+                }
+            }
+        }
+    }
+
+    fn drop_values_over_cap(&mut self) {
+        let excess = self
+            .time_series
+            .len()
+            .saturating_sub(self.max_in_memory_points);
+        if excess == 0 {
+            return;
+        }
+        let dropped = self.time_series.drop_oldest(excess);
+        self.dropped_points += dropped as u64;
+        warn!(
+            dropped,
+            max_in_memory_points = self.max_in_memory_points,
+            dropped_total = self.dropped_points,
+            "Dropped the oldest values in memory since there are more than can be kept while they can't be written to disk"
+        );
+    }
+
+    /// persists the values currently in memory as a segment of their own (regardless of the
+    /// data loss threshold) and starts a new one; use this to cut segments at meaningful
+    /// boundaries, e.g. at the start of a day
+    pub fn start_new_segment(&mut self) -> Result<(), SunnyDbError> {
+        if self.time_series.is_empty() {
+            return Ok(());
+        }
+        self.export_time_series_to_file()?;
+        self.time_series = self.new_time_series();
+        Ok(())
+    }
+
+    /// persists the values currently in memory regardless of the data loss threshold, but
+    /// keeps them in memory so the segment keeps growing; flushing again replaces the
+    /// previously flushed segment
+    pub fn flush(&mut self) -> Result<(), SunnyDbError> {
+        if self.time_series.is_empty() {
+            return Ok(());
+        }
+        self.flushed_segment = Some(self.export_time_series_to_file()?);
+        Ok(())
+    }
+
+    /// wraps the database in a guard that flushes it when it's dropped
+    pub fn flush_on_drop(self) -> FlushOnDrop<T> {
+        FlushOnDrop { db: self }
+    }
+
+    /// persists the values currently in the time series without emptying the time series
+    /// to prevent cluttering the DB with many small files, a threshold for the segment
+    /// size is respected; this can be defined using the data_loss_threshold attribute
+    pub fn lossy_persist(&mut self) {
+        if self.data_loss_threshold < self.time_series.len() {
+            if let Err(e) = self.export_time_series_to_file() {
+                error!(
+                    values = self.time_series.len(),
+                    error = %e,
+                    "Couldn't persist the values in memory"
+                );
+            }
+        } else {
+            warn!(
+                values = self.time_series.len(),
+                data_loss_threshold = self.data_loss_threshold,
+                "Deliberately losing the values in memory on closing the database since there are too few of them"
+            );
+        }
+    }
+
+    fn export_time_series_to_file(&mut self) -> Result<PathBuf, SunnyDbError> {
+        self.ensure_writable()?;
+        let started = Instant::now();
+        let data = self.segment_codec.encode(
+            &self.time_series,
+            self.compression_level,
+            self.encryption_key.as_ref(),
+        )?;
+        let encode_time = started.elapsed();
+        let path = Self::write_encoded_segment(&self.data_path, &self.time_series, &data)?;
+        let mut changes = Vec::new();
+        // the new segment contains everything a previously flushed one did; it may have been
+        // archived in the meantime though
+        if let Some(flushed) = self.flushed_segment.take() {
+            if flushed != path && flushed.exists() {
+                remove_file(&flushed)?;
+                changes.extend(Self::segment_of(&flushed).map(|s| (Change::Removed, s, 0)));
+            }
+        }
+        if let Some(segment) = Self::segment_of(&path) {
+            changes.push((Change::Added, segment, fs::metadata(&path)?.len()));
+        }
+        self.record_in_manifest(&changes);
+        let flush_time = started.elapsed();
+        self.write_metrics
+            .record_flush(flush_time, encode_time, data.len());
+        debug!(
+            segment = %path.display(),
+            values = self.time_series.len(),
+            bytes = data.len(),
+            encode_ms = encode_time.as_secs_f64() * 1e3,
+            flush_ms = flush_time.as_secs_f64() * 1e3,
+            "Wrote segment"
+        );
+        if let Some((start, end)) = Self::segment_of(&path) {
+            self.maintain_aggregates(start, end);
+        }
+        Ok(path)
+    }
+
+    fn write_segment(
+        data_dir_path: &str,
+        time_series: &TimeSeries<T>,
+        compression_level: i32,
+        key: Option<&EncryptionKey>,
+        codec: SegmentCodec<T>,
+    ) -> Result<PathBuf, SunnyDbError> {
+        let data = codec.encode(time_series, compression_level, key)?;
+        Self::write_encoded_segment(data_dir_path, time_series, &data)
+    }
+
+    /// writes the already encoded time series as a segment named after its first and last time
+    fn write_encoded_segment(
+        data_dir_path: &str,
+        time_series: &TimeSeries<T>,
+        data: &[u8],
+    ) -> Result<PathBuf, SunnyDbError> {
+        let (Some(start), Some(end)) = (time_series.get_start_time(), time_series.get_end_time())
+        else {
+            return Err(SunnyDbError::EmptySegment);
+        };
+        // names are in ms regardless of the resolution of the segment's timestamps
+        let resolution = time_series.get_resolution();
+        let (start, end) = (resolution.to_millis(start), resolution.to_millis(end));
+        let file_name = format!("{}-{}", start, end);
+        let partition = Self::partition_path(data_dir_path, start);
+        create_dir_all(&partition)?;
+
+        // write to a temporary file first and move it into place once it's complete, so a crash
+        // mid-write can't leave a truncated segment with a valid name behind
+        let tmp_path = partition.join(format!("{}.{}", file_name, TMP_EXTENSION));
+        let mut file = File::create(&tmp_path)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        let path = partition.join(file_name);
+        rename(&tmp_path, &path)?;
+
+        // make sure the rename itself is persisted, too
+        File::open(&partition)?.sync_all()?;
+        Ok(path)
+    }
+
+    /// removes temporary files left behind by writes that didn't complete
+    fn remove_stale_temp_files(path: &Path) {
+        let Ok(entries) = fs::read_dir(path) else {
+            return;
+        };
+
+        for entry in entries.flatten() {
+            let entry_path = entry.path();
+            if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                Self::remove_stale_temp_files(&entry_path);
+            } else if entry_path.extension().is_some_and(|e| e == TMP_EXTENSION) {
+                info!(
+                    segment = %entry_path.display(),
+                    "Removing incomplete segment left behind by an interrupted write"
+                );
+                if let Err(e) = remove_file(&entry_path) {
+                    warn!(
+                        segment = %entry_path.display(),
+                        error = %e,
+                        "Couldn't remove incomplete segment"
+                    );
+                }
+            }
+        }
+    }
+
+    /// re-compresses all segments that end before `older_than` with the given compression
+    /// level (use 22, zstd's maximum, to reclaim as much space as possible); if a cold storage
+    /// tier is set up, the segments are moved there; returns the number of archived segments
+    pub fn archive(&self, older_than: u64, compression_level: i32) -> anyhow::Result<usize> {
+        let archived = self.archive_job(older_than, compression_level)?.run()?;
+        self.finish_archive(archived);
+        Ok(archived)
+    }
+
+    /// collects the segments `archive` re-compresses, so `ArchiveJob::run` can do the work
+    /// without holding a lock on the database; `finish_archive` has to be called with its
+    /// result afterwards
+    #[tracing::instrument(skip(self))]
+    pub fn archive_job(
+        &self,
+        older_than: u64,
+        compression_level: i32,
+    ) -> anyhow::Result<ArchiveJob<T>> {
+        self.ensure_writable()?;
+        let older_than = self.get_resolution().to_millis(older_than);
+        let watermark_path = Path::new(&self.data_path).join(ARCHIVE_WATERMARK_FILE);
+        // when re-compressing in place, segments up to the watermark have been archived already
+        let watermark = match self.cold_data_path {
+            Some(_) => 0,
+            None => fs::read_to_string(&watermark_path)
+                .ok()
+                .and_then(|w| w.trim().parse::<u64>().ok())
+                .unwrap_or(0),
+        };
+        let target_path = self.cold_data_path.as_deref().unwrap_or(&self.data_path);
+
+        let segments = Self::list_segments_in(&self.data_path, 0, older_than)
+            .into_iter()
+            .filter(|seg| seg.1 < older_than && seg.1 > watermark)
+            .map(|segment| {
+                let path = self.segment_path(&segment);
+                let modified = modified_time(&path);
+                (segment, path, modified)
+            })
+            .collect();
+
+        Ok(ArchiveJob {
+            segments,
+            target_path: target_path.to_owned(),
+            move_segments: self.cold_data_path.is_some(),
+            watermark_path: self.cold_data_path.is_none().then_some(watermark_path),
+            watermark,
+            compression_level,
+            key: self.encryption_key.clone(),
+            codec: self.segment_codec,
+        })
+    }
+
+    /// records the segments archived by `ArchiveJob::run` in the manifest
+    pub fn finish_archive(&self, archived: usize) {
+        if archived > 0 {
+            self.update_manifest();
+        }
+    }
+
+    /// moves all segments of the local tiers that end before `older_than` to the remote tier;
+    /// returns the number of offloaded segments
+    pub fn offload(&self, older_than: u64) -> anyhow::Result<usize> {
+        let offloaded = self.offload_job(older_than)?.run()?;
+        self.finish_offload(offloaded);
+        Ok(offloaded)
+    }
+
+    /// collects the segments `offload` moves to the remote tier, so `OffloadJob::run` can
+    /// upload them without holding a lock on the database; `finish_offload` has to be called
+    /// with its result afterwards
+    #[tracing::instrument(skip(self))]
+    pub fn offload_job(&self, older_than: u64) -> anyhow::Result<OffloadJob> {
+        self.ensure_writable()?;
+        let remote = self
+            .remote
+            .as_ref()
+            .context("No remote storage tier has been set up")?;
+        let older_than = self.get_resolution().to_millis(older_than);
+
+        let segments = self
+            .segment_files_with_size()?
+            .into_iter()
+            .filter(|(segment, _, _)| segment.1 < older_than)
+            .map(|(segment, file, _)| {
+                let modified = modified_time(&file);
+                (segment, file, modified)
+            })
+            .collect();
+        Ok(OffloadJob {
+            remote: Arc::clone(remote),
+            segments,
+        })
+    }
+
+    /// records the segments offloaded by `OffloadJob::run` in the manifest
+    pub fn finish_offload(&self, offloaded: usize) {
+        if offloaded > 0 {
+            self.update_manifest();
+        }
+    }
+
+    /// all segment files of all storage tiers with their size in bytes, oldest first
+    fn segment_files_with_size(&self) -> anyhow::Result<Vec<SegmentFile>> {
+        let mut files = Vec::new();
+        for path in std::iter::once(&self.data_path).chain(&self.cold_data_path) {
+            if !Path::new(path).is_dir() {
+                continue;
+            }
+            for (segment, file) in Self::segment_files_in(Path::new(path))? {
+                let size = fs::metadata(&file)?.len();
+                files.push((segment, file, size));
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    /// the size of all persisted segments of all storage tiers in bytes
+    pub fn disk_usage(&self) -> anyhow::Result<u64> {
+        let files = self.segment_files_with_size()?;
+        Ok(files.iter().map(|(_, _, size)| size).sum())
+    }
+
+    /// deletes the oldest segments of all storage tiers until at least `bytes` have been
+    /// freed or there are no segments left; returns the number of bytes freed
+    #[tracing::instrument(skip(self))]
+    pub fn prune_oldest(&self, bytes: u64) -> anyhow::Result<u64> {
+        self.ensure_writable()?;
+        let mut freed = 0;
+        let mut removed = 0;
+        for (_, file, size) in self.segment_files_with_size()? {
+            if freed >= bytes {
+                break;
+            }
+            remove_file(&file)?;
+            // drop the partition if it's empty now; fails harmlessly otherwise
+            if let Some(partition) = file.parent() {
+                fs::remove_dir(partition).ok();
+            }
+            freed += size;
+            removed += 1;
+        }
+        if removed > 0 {
+            self.update_manifest();
+            info!(segments = removed, bytes = freed, "Pruned segments");
         }
+        Ok(freed)
     }
 
-    fn init_directory(dir_path: &str) -> String {
-        let data_dir_path = if dir_path.ends_with('/') {
-            dir_path.to_owned() + "data/"
-        } else {
-            dir_path.to_owned() + "/data/"
+    /// the manifest lives next to the data directory, like the lock file
+    fn manifest_path(&self) -> PathBuf {
+        let data_path = Path::new(&self.data_path);
+        data_path.parent().unwrap_or(data_path).join(MANIFEST_FILE)
+    }
+
+    /// records the segments added, rewritten or removed since the last update in the
+    /// manifest by comparing it with the segment files; failing to do so doesn't affect the
+    /// data, so it's only logged
+    fn update_manifest(&self) {
+        if self.is_read_only() {
+            return;
+        }
+        let update = || -> anyhow::Result<()> {
+            let path = self.manifest_path();
+            let entries = manifest::read(&path)?;
+            let current: BTreeMap<(u64, u64), u64> = self
+                .segment_files_with_size()?
+                .into_iter()
+                .map(|(segment, _, size)| (segment, size))
+                .collect();
+            let changes = manifest::changes(&entries, &current);
+            manifest::append(&path, &changes)?;
+            self.record_in_catalog(&changes)
         };
-        let dir = create_dir_all(&data_dir_path);
+        if let Err(e) = update() {
+            warn!(error = format!("{:#}", e), "Couldn't update the manifest");
+        }
+    }
 
-        match dir {
-            Err(e) => panic!(
-                "Error while trying to create database directory at {}. The error was: {}",
-                dir_path, e
-            ),
-            _ => (),
+    /// appends changes made while writing values to the manifest, without the full scan of
+    /// `update_manifest`
+    fn record_in_manifest(&self, changes: &[(Change, (u64, u64), u64)]) {
+        let record = || self.record_in_catalog(&manifest::record(&self.manifest_path(), changes)?);
+        if let Err(e) = record() {
+            warn!(error = format!("{:#}", e), "Couldn't update the manifest");
         }
+    }
 
-        let permission_file_path = data_dir_path.to_owned() + ".permission-check.tiny.db";
+    /// applies manifest entries to the catalog, if it's maintained; if earlier entries couldn't
+    /// be recorded, they're read from the manifest first so the catalog has no gaps
+    fn record_in_catalog(&self, entries: &[ManifestEntry]) -> anyhow::Result<()> {
+        #[cfg(feature = "catalog")]
+        if let Some(catalog) = &self.catalog {
+            let last_seq = catalog.last_seq()?;
+            match entries.first() {
+                Some(first) if first.seq != last_seq + 1 => {
+                    let missing: Vec<ManifestEntry> = manifest::read(&self.manifest_path())?
+                        .into_iter()
+                        .filter(|e| e.seq > last_seq)
+                        .collect();
+                    catalog.record(&missing)?;
+                }
+                _ => catalog.record(entries)?,
+            }
+        }
+        #[cfg(not(feature = "catalog"))]
+        let _ = entries;
+        Ok(())
+    }
 
-        let file = File::create(&permission_file_path);
-        match file {
-            Err(e) => panic!(
-                "Error while trying to create a database file at {}. The error was: {}",
-                data_dir_path, e
-            ),
-            Ok(_) => (),
+    /// the manifest entries after the one with sequence number `seq` (0 for all of them); they
+    /// come from the catalog if it's up to date, so the manifest file isn't parsed
+    pub fn manifest_since(&self, seq: u64) -> anyhow::Result<Vec<ManifestEntry>> {
+        #[cfg(feature = "catalog")]
+        if let Some(catalog) = self.current_catalog() {
+            return catalog.manifest_since(seq);
         }
+        let entries = manifest::read(&self.manifest_path())?;
+        Ok(entries.into_iter().filter(|e| e.seq > seq).collect())
+    }
 
-        // delete file again
-        let delete_file = remove_file(permission_file_path);
-        match delete_file {
-            Err(e) => panic!(
-                "Error while trying to delete database test file at {}. The error was: {}",
-                data_dir_path, e
-            ), //TODO: warn here? Could use tracing crate (already used by anyhow)
-            _ => (),
+    /// the catalog if it's maintained and has all entries of the manifest; it falls behind if
+    /// recording them failed or, for read-only databases, until the writer has recorded them
+    #[cfg(feature = "catalog")]
+    fn current_catalog(&self) -> Option<&Catalog> {
+        let catalog = self.catalog.as_ref()?;
+        let current = || -> anyhow::Result<bool> {
+            Ok(catalog.last_seq()? == manifest::last_seq(&self.manifest_path())?)
+        };
+        match current() {
+            Ok(current) => current.then_some(catalog),
+            Err(e) => {
+                warn!(error = format!("{:#}", e), "Couldn't read the catalog");
+                None
+            }
         }
+    }
 
-        return data_dir_path;
+    /// the aggregates live next to the data directory, like the manifest
+    fn aggregates_path(&self) -> PathBuf {
+        let data_path = Path::new(&self.data_path);
+        data_path.parent().unwrap_or(data_path).join(AGGREGATES_DIR)
     }
 
-    pub fn insert_value_at_current_time(&mut self, value: T) {
-        self.time_series.insert_value_at_current_time(value);
-        self.dump_time_series_if_full();
+    /// updates the aggregates of a range that has been written, if they're enabled; failing to
+    /// do so doesn't affect the data, so it's only logged
+    fn maintain_aggregates(&self, start_time: u64, end_time: u64) {
+        let Some(update) = self.energy_aggregates else {
+            return;
+        };
+        if let Err(e) = update(self, start_time, end_time) {
+            warn!(error = %e, "Couldn't update the energy aggregates");
+        }
     }
 
-    fn dump_time_series_if_full(&mut self) {
-        if self.time_series.len() >= self.time_series_cache_size {
-            match self.export_time_series_to_file() {
-                Err(e) => panic!("Error while trying to dump time series: {}", e),
-                Ok(()) => (), // TODO: log
+    fn read_aggregate_file(&self, path: &Path) -> Result<BTreeMap<u64, T>, SunnyDbError> {
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let series =
+            TimeSeries::<T>::from_segment(&bytes, self.encryption_key.as_ref()).map_err(|e| {
+                SunnyDbError::CorruptAggregates {
+                    path: path.to_owned(),
+                    reason: format!("{:#}", e),
+                }
+            })?;
+        Ok(series.iter().map(|(time, value)| (time, *value)).collect())
+    }
+
+    /// the stored aggregates of the intervals starting within [start_time, end_time) in ms
+    fn read_aggregates(
+        &self,
+        interval: AggregateInterval,
+        start_time: u64,
+        end_time: u64,
+    ) -> Result<BTreeMap<u64, T>, SunnyDbError> {
+        let directory = self.aggregates_path().join(interval.directory());
+        let mut aggregates = BTreeMap::new();
+        let Ok(entries) = fs::read_dir(&directory) else {
+            return Ok(aggregates);
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let Some(file_start) = name.to_str().and_then(|n| interval.file_start(n)) else {
+                continue;
             };
-            self.time_series = TimeSeries::<T>::new(self.time_series_cache_size);
+            let (_, _, file_end) = interval.file_of(file_start);
+            if file_start >= end_time || file_end <= start_time {
+                continue;
+            }
+            let stored = self.read_aggregate_file(&entry.path())?;
+            aggregates.extend(stored.range(start_time..end_time).map(|(t, v)| (*t, *v)));
         }
+        Ok(aggregates)
     }
 
-    /// persists the values currently in the time series without emptying the time series
-    /// to prevent cluttering the DB with many small files, a threshold for the segment
-    /// size is respected; this can be defined using the data_loss_threshold attribute
-    pub fn lossy_persist(&mut self) {
-        if self.data_loss_threshold < self.time_series.len() {
-            self.export_time_series_to_file().ok();
-        } else {
-            println!("Warning: deliberately losing data on closing DB since there were only {} values in the time series and the threshold is set to {}", self.time_series.len(), self.data_loss_threshold);
+    /// replaces the stored aggregates of the intervals starting within [start_time, end_time)
+    /// in ms with the given ones
+    fn write_aggregates(
+        &self,
+        interval: AggregateInterval,
+        start_time: u64,
+        end_time: u64,
+        aggregates: &BTreeMap<u64, T>,
+    ) -> Result<(), SunnyDbError> {
+        let mut time = start_time;
+        while time < end_time {
+            let (file, file_start, file_end) = interval.file_of(time);
+            let path = self.aggregates_path().join(file);
+            let mut stored = self.read_aggregate_file(&path)?;
+            stored.retain(|t, _| !(start_time..end_time).contains(t));
+            stored.extend(
+                aggregates
+                    .range(file_start..file_end)
+                    .map(|(t, v)| (*t, *v)),
+            );
+            time = file_end;
+
+            if stored.is_empty() {
+                if path.exists() {
+                    remove_file(&path)?;
+                }
+                continue;
+            }
+            let mut series = TimeSeries::<T>::new(stored.len());
+            series.insert_many_sorted(stored);
+            let data = series.to_segment(self.compression_level, self.encryption_key.as_ref())?;
+            if let Some(parent) = path.parent() {
+                create_dir_all(parent)?;
+            }
+            // like segments, written to a temporary file first so they're never truncated
+            let tmp_path = path.with_extension(TMP_EXTENSION);
+            let mut file = File::create(&tmp_path)?;
+            file.write_all(&data)?;
+            file.sync_all()?;
+            rename(&tmp_path, &path)?;
         }
+        Ok(())
     }
 
-    fn export_time_series_to_file(&self) -> Result<(), std::io::Error> {
-        let start = self
-            .time_series
-            .get_start_time()
-            .expect("Error: tried to export time series that has no start time set!");
-        let end = self
-            .time_series
-            .get_end_time()
-            .expect("Error: tried to export time series that has no end time set!");
-        let file_name = format!("{}-{}", start, end);
-        let mut file = File::create(self.data_path.to_owned() + &file_name)?;
+    /// the energy per hour or per (UTC) day of the intervals starting within
+    /// [start_time, end_time), together with their start; energies are given in hours times
+    /// the unit of the values, e.g. in Wh for values in W. Only available if the aggregates
+    /// are maintained, see `with_energy_aggregates`
+    pub fn get_energy(
+        &self,
+        interval: AggregateInterval,
+        start_time: u64,
+        end_time: u64,
+    ) -> Result<Vec<(u64, T)>, SunnyDbError> {
+        let resolution = self.get_resolution();
+        let aggregates = self.read_aggregates(
+            interval,
+            resolution.to_millis(start_time),
+            resolution.to_millis(end_time),
+        )?;
+        Ok(aggregates
+            .into_iter()
+            .map(|(time, energy)| (resolution.from_millis(time), energy))
+            .collect())
+    }
 
-        let data = self
-            .time_series
-            .to_compressed_json(self.compression_level)?;
-        file.write_all(&data)
+    /// the file of a persisted segment in any storage tier, e.g. to copy it elsewhere
+    pub fn segment_file(&self, segment: (u64, u64)) -> Option<PathBuf> {
+        let path = self.segment_path(&segment);
+        path.is_file().then_some(path)
+    }
+
+    /// iterates the persisted segments of all storage tiers in time order as they're stored,
+    /// without decoding them, e.g. for backup and sync tools to stream the database: all of
+    /// them without a cursor, otherwise only the ones added to the manifest after it, e.g. the
+    /// one of a previous run (see `RawSegments::cursor`). Those include segments rewritten by
+    /// compacting or archiving and backfilled ones older than the ones seen before; removed
+    /// segments aren't returned, see `manifest_since` for them. The segments are listed when
+    /// the iteration starts and read one at a time; ones removed meanwhile are skipped
+    pub fn raw_segments(
+        &self,
+        cursor: Option<SegmentCursor>,
+    ) -> anyhow::Result<RawSegments<'_, T>> {
+        let seq = cursor.map_or(0, |cursor| cursor.seq);
+        // read before listing the segments, so none written meanwhile is missed by the next run
+        let entries = self.manifest_since(seq)?;
+        let next = SegmentCursor {
+            seq: entries.last().map_or(seq, |entry| entry.seq),
+        };
+        let segments = match cursor {
+            None => self.list_segments(0, u64::MAX),
+            Some(_) => entries
+                .iter()
+                .filter(|entry| entry.change == Change::Added)
+                .map(|entry| entry.segment)
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect(),
+        };
+        Ok(RawSegments::new(self, segments, next))
+    }
+
+    /// the stored bytes of a segment and where it's stored; None if it doesn't exist (anymore)
+    pub(crate) fn read_raw_segment(
+        &self,
+        segment: (u64, u64),
+    ) -> anyhow::Result<Option<RawSegment>> {
+        let file_name = format!("{}-{}", segment.0, segment.1);
+        let local = std::iter::once((&self.data_path, StorageTier::Hot)).chain(
+            self.cold_data_path
+                .iter()
+                .map(|path| (path, StorageTier::Cold)),
+        );
+        let mut found = None;
+        for (data_path, tier) in local {
+            match fs::read(Self::partition_path(data_path, segment.0).join(&file_name)) {
+                Ok(bytes) => {
+                    found = Some((tier, bytes));
+                    break;
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if found.is_none() {
+            if let Some(remote) = self.remote.as_ref().filter(|r| r.contains(&segment)) {
+                let bytes = remote.fetch(&remote::segment_key(segment))?;
+                found = Some((StorageTier::Remote, bytes));
+            }
+        }
+        Ok(found.map(|(tier, bytes)| RawSegment {
+            segment,
+            tier,
+            info: segment_info(&bytes).ok().flatten(),
+            bytes,
+        }))
     }
 
     // getting values
@@ -124,23 +1659,113 @@ impl<T: Copy + DecodeOwned + Encode> SunnyDB<T> {
         let end_time = self
             .time_series
             .get_end_time()
-            .unwrap_or(SystemTime::now().timestamp());
+            .unwrap_or(self.get_resolution().now());
         self.get_values_in_range(0, end_time)
     }
 
+    #[tracing::instrument(level = "debug", skip(self))]
     pub fn get_values_in_range(&self, start_time: u64, end_time: u64) -> Option<TimeSeries<T>> {
         if end_time < start_time {
             // someone accidentally switched start & end
             return self.get_values_in_range(end_time, start_time);
         }
+        self.read_values_in_range(start_time, end_time, usize::MAX)
+    }
+
+    /// returns at most `limit` values of the range, starting after the `cursor` of the previous
+    /// page if given; segments are only decoded until the limit is reached, so even huge
+    /// ranges can be read without materializing all of their values. Values sharing a
+    /// timestamp are never split across pages, so a page may exceed the limit by them
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn get_values_in_range_paged(
+        &self,
+        start_time: u64,
+        end_time: u64,
+        limit: usize,
+        cursor: Option<u64>,
+    ) -> Option<Page<T>> {
+        let (start_time, end_time) = (start_time.min(end_time), start_time.max(end_time));
+        let start_time = cursor.map_or(start_time, |c| c.max(start_time));
+        let limit = limit.max(1);
+        // one more value than needed tells whether there's another page
+        let values = self.read_values_in_range(start_time, end_time, limit + 1)?;
+        if values.len() <= limit {
+            return Some(Page { values, next: None });
+        }
+        let last_time = values.iter().nth(limit - 1).map(|(t, _)| t)?;
+        let page = values.get_values_in_range(start_time, last_time)?;
+        let next = (page.len() < values.len()).then_some(last_time);
+        Some(Page { values: page, next })
+    }
+
+    /// the values of each of the ranges, in the same order, like get_values_in_range. Ranges
+    /// that overlap or share a segment are read together, so e.g. the days of a month are read
+    /// with each segment decoded once rather than once per day
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub fn get_values_in_ranges(&self, ranges: &[(u64, u64)]) -> Vec<Option<TimeSeries<T>>> {
+        let ranges: Vec<(u64, u64)> = ranges
+            .iter()
+            .map(|&(start, end)| (start.min(end), start.max(end)))
+            .collect();
+        let mut order: Vec<usize> = (0..ranges.len()).collect();
+        order.sort_by_key(|&i| ranges[i]);
+        let (Some(&first), Some(end)) = (order.first(), ranges.iter().map(|r| r.1).max()) else {
+            return Vec::new();
+        };
+        let resolution = self.get_resolution();
+        let segments = self.segments_covering(ranges[first].0, end);
+        let shares_segment = |end: u64, start: u64| {
+            let (end, start) = (resolution.to_millis(end), resolution.to_millis(start));
+            segments.iter().any(|&(s, e)| s <= end && e >= start)
+        };
+
+        // consecutive ranges (in the sorted order) that are read at once
+        let mut groups: Vec<((u64, u64), Vec<usize>)> = Vec::new();
+        for i in order {
+            let (start, end) = ranges[i];
+            match groups.last_mut() {
+                Some(((_, group_end), members))
+                    if start <= *group_end || shares_segment(*group_end, start) =>
+                {
+                    *group_end = end.max(*group_end);
+                    members.push(i);
+                }
+                _ => groups.push(((start, end), vec![i])),
+            }
+        }
+        debug!(
+            ranges = ranges.len(),
+            reads = groups.len(),
+            "Coalesced the ranges"
+        );
 
+        let mut results: Vec<Option<TimeSeries<T>>> = ranges.iter().map(|_| None).collect();
+        for ((start, end), members) in groups {
+            let Some(values) = self.get_values_in_range(start, end) else {
+                continue;
+            };
+            for i in members {
+                results[i] = values.get_values_in_range(ranges[i].0, ranges[i].1);
+            }
+        }
+        results
+    }
+
+    /// the values between start_time and end_time; reading persisted segments stops once they
+    /// hold at least `limit` values of the range
+    fn read_values_in_range(
+        &self,
+        start_time: u64,
+        end_time: u64,
+        limit: usize,
+    ) -> Option<TimeSeries<T>> {
         let ts_start_time = self.time_series.get_start_time();
         if ts_start_time.is_some() && ts_start_time.unwrap() <= start_time {
             // shortcut if all data is currently in memory anyway
             return self.time_series.get_values_in_range(start_time, end_time);
         }
 
-        let read_data = self.read_persisted_data(start_time, end_time);
+        let read_data = self.read_persisted_data(start_time, end_time, limit);
 
         if self.time_series.get_start_time() > Some(end_time) {
             // everything's been covered by reading the persisted data
@@ -153,118 +1778,282 @@ impl<T: Copy + DecodeOwned + Encode> SunnyDB<T> {
             .get_values_in_range(start_time, end_time)
             .unwrap_or(TimeSeries::<T>::empty());
 
-        return match read_data {
+        match read_data {
             None => Some(ts),
             Some(mut d) => {
-                d.append(&ts);
+                // persisting without emptying the series, like lossy_persist does, leaves the
+                // values both in memory and in the newest segment; merging reads them once
+                if ts.get_start_time() <= d.get_end_time() || d.append(&ts).is_err() {
+                    d.merge(&ts);
+                }
                 Some(d)
             }
-        };
+        }
     }
 
-    fn read_persisted_data(&self, start_time: u64, end_time: u64) -> Option<TimeSeries<T>> {
-        let mut files: Vec<fs::DirEntry> = fs::read_dir(&self.data_path)
-            .expect("Couldn't read data directory!")
-            .flatten()
-            .collect();
-        files.sort_by(|f1, f2| f1.path().cmp(&(f2.path())));
+    fn read_persisted_data(
+        &self,
+        start_time: u64,
+        end_time: u64,
+        limit: usize,
+    ) -> Option<TimeSeries<T>> {
+        // compacting segments (merging, archiving, replacing flushed ones) always writes the new
+        // segments before removing the ones they replace, so if a segment has vanished by the
+        // time it's read, listing the segments again yields the complete new set; values read
+        // from both sets are identical and merged without duplicates
+        let mut attempts = 1;
+        let ts = loop {
+            match self.read_segments(start_time, end_time, limit) {
+                Ok(ts) => break ts,
+                Err(ts) if attempts >= MAX_READ_ATTEMPTS => {
+                    warn!(
+                        attempts,
+                        "Segments kept vanishing while reading them; returning the values read"
+                    );
+                    break ts;
+                }
+                Err(_) => {
+                    debug!(
+                        attempts,
+                        "A segment vanished while reading it; listing them again"
+                    );
+                    attempts += 1;
+                }
+            }
+        };
+        debug!(
+            segments = ts.len(),
+            values = ts.iter().map(|t| t.len()).sum::<usize>(),
+            "Read persisted segments"
+        );
 
-        let segments: Vec<(u64, u64)> = files
-            .iter()
-            .map(|file| SunnyDB::<T>::parse_filename_to_times(file))
-            .flatten()
-            .collect();
+        let mut ts = ts.into_iter().filter_map(|t| {
+            // like get_values_in_range, the range doesn't include the start time itself
+            let within_range =
+                t.get_start_time() > Some(start_time) && t.get_end_time() <= Some(end_time);
+            if within_range {
+                Some(t)
+            } else {
+                t.get_values_in_range(start_time, end_time)
+            }
+        });
+        let mut t0 = ts.next()?;
+        for t in ts {
+            // segments replacing others during a compaction overlap them
+            if t.get_start_time() <= t0.get_end_time() || t0.append(&t).is_err() {
+                t0.merge(&t);
+            }
+        }
+        Some(t0)
+    }
 
-        let (start_index, end_index) =
-            self.find_persisted_segment_index(&segments, start_time, end_time);
+    /// reads the persisted segments that may hold values between start_time and end_time,
+    /// stopping once they hold `limit` values of the range and the next segment doesn't overlap
+    /// the ones read; unreadable segments are skipped. If a segment was removed after listing
+    /// them, the segments that could be read are returned as error
+    fn read_segments(
+        &self,
+        start_time: u64,
+        end_time: u64,
+        limit: usize,
+    ) -> Result<Vec<TimeSeries<T>>, Vec<TimeSeries<T>>> {
+        let segments = self.segments_covering(start_time, end_time);
 
-        if start_index.is_none() && end_index.is_none() {
-            // no data found
-            return None;
+        let mut ts = Vec::new();
+        let mut vanished = false;
+        let mut values = 0;
+        let mut read_until = 0;
+        for segment in &segments {
+            if values >= limit && segment.0 > read_until {
+                break;
+            }
+            read_until = read_until.max(segment.1);
+            match self.parse_segment_to_timeseries(segment) {
+                Ok(t) => {
+                    values += t.view_range(start_time, end_time).map_or(0, |v| v.len());
+                    ts.push(t);
+                }
+                Err(e) => {
+                    let not_found = e
+                        .downcast_ref::<std::io::Error>()
+                        .is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound);
+                    if !not_found {
+                        warn!(segment = ?segment, error = %e, "Skipping unreadable segment");
+                    }
+                    vanished |= not_found;
+                }
+            }
         }
+        if vanished {
+            Err(ts)
+        } else {
+            Ok(ts)
+        }
+    }
 
-        // at least one entry was found in the files, so let's do what we can here
-        let actual_start_index = start_index.unwrap_or(0);
-        let actual_end_index = end_index.unwrap_or(files.len() - 1) + 1;
-
-        let ts: Vec<TimeSeries<T>> = segments[actual_start_index..actual_end_index]
+    /// the persisted segments (of all storage tiers) that may hold values between start_time and
+    /// end_time, i.e. the ones a query for this range reads, as the range of their values in ms
+    /// sorted by time. Segments whose first or last value is at one of the ends of the range
+    /// count, as do segments starting in an earlier day partition; while segments are being
+    /// compacted, the old and the new ones may overlap
+    pub fn segments_covering(&self, start_time: u64, end_time: u64) -> Vec<(u64, u64)> {
+        // segments are named by their range in ms
+        let resolution = self.get_resolution();
+        let (start_millis, end_millis) = (
+            resolution.to_millis(start_time.min(end_time)),
+            resolution.to_millis(start_time.max(end_time)),
+        );
+        self.list_segments(start_millis, end_millis)
             .into_iter()
-            .map(|seg| self.parse_segment_to_timeseries(seg))
-            .flatten()
-            .collect();
+            .filter(|(start, end)| *start <= end_millis && *end >= start_millis)
+            .collect()
+    }
+
+    /// the number of persisted segments (of all storage tiers) holding values between start_time
+    /// and end_time, i.e. the segments a query for this range has to read
+    pub fn segments_in_range(&self, start_time: u64, end_time: u64) -> usize {
+        self.segments_covering(start_time, end_time).len()
+    }
 
-        // no data found apparently
-        if ts.is_empty() {
+    /// the end of the newest persisted segment of all storage tiers, e.g. to see how far a
+    /// replicated data directory has caught up
+    pub fn last_persisted_time(&self) -> Option<u64> {
+        let newest_day = std::iter::once(&self.data_path)
+            .chain(&self.cold_data_path)
+            .filter_map(|path| Self::newest_partition(Path::new(path)))
+            .max()?;
+        let start_millis = newest_day
+            .and_hms_opt(0, 0, 0)?
+            .and_utc()
+            .timestamp_millis() as u64;
+        let end_millis = self
+            .list_segments(start_millis, u64::MAX)
+            .iter()
+            .map(|(_, end)| *end)
+            .max()?;
+        Some(self.get_resolution().from_millis(end_millis))
+    }
+
+    /// the newest day partition containing any files
+    fn newest_partition(data_dir_path: &Path) -> Option<NaiveDate> {
+        if !data_dir_path.is_dir() {
             return None;
         }
-
-        // only a single entry, which makes for a bit of a special case
-        if ts.len() == 1 {
-            return ts[0].get_values_in_range(start_time, end_time);
+        let newest_first = |path: &Path| {
+            let mut dirs = Self::numeric_subdirectories(path);
+            dirs.sort_by(|a, b| b.cmp(a));
+            dirs
+        };
+        for (year, year_path) in newest_first(data_dir_path) {
+            for (month, month_path) in newest_first(&year_path) {
+                for (day, day_path) in newest_first(&month_path) {
+                    let has_files = fs::read_dir(&day_path)
+                        .into_iter()
+                        .flatten()
+                        .flatten()
+                        .any(|file| Self::parse_filename_to_times(&file).is_some());
+                    if has_files {
+                        return NaiveDate::from_ymd_opt(year as i32, month, day);
+                    }
+                }
+            }
         }
+        None
+    }
 
-        // multiple entries
-        let mut t0 = ts[0]
-            .get_values_in_range(start_time, end_time)
-            .unwrap_or(TimeSeries::<T>::empty());
-
-        if ts.len() > 2 {
-            for t in &ts[1..(ts.len() - 1)] {
-                t0.append(t);
+    /// lists the persisted segments of all storage tiers sorted by time, only looking into the
+    /// partitions that may hold data between start_time and end_time; the local tiers are looked
+    /// up in the catalog if it's maintained
+    fn list_segments(&self, start_time: u64, end_time: u64) -> Vec<(u64, u64)> {
+        let mut segments = match self.catalogued_segments(start_time, end_time) {
+            Some(segments) => segments,
+            None => {
+                let mut segments = Self::list_segments_in(&self.data_path, start_time, end_time);
+                if let Some(cold_data_path) = &self.cold_data_path {
+                    segments.append(&mut Self::list_segments_in(
+                        cold_data_path,
+                        start_time,
+                        end_time,
+                    ));
+                }
+                segments
             }
+        };
+        if let Some(remote) = &self.remote {
+            segments.append(&mut remote.segments_in(start_time, end_time));
         }
-
-        let t_n = ts[ts.len() - 1]
-            .get_values_in_range(start_time, end_time)
-            .unwrap_or(TimeSeries::<T>::empty());
-        t0.append(&t_n);
-
-        Some(t0)
+        if self.cold_data_path.is_some() || self.remote.is_some() {
+            segments.sort();
+            segments.dedup();
+        }
+        segments
     }
 
-    fn find_persisted_segment_index(
-        &self,
-        segments: &Vec<(u64,u64)>,
-        start_time: u64,
-        end_time: u64,
-    ) -> (Option<usize>, Option<usize>) {
+    /// the segments of the local storage tiers holding values between start_time and end_time
+    /// according to the catalog; None without an up to date catalog or if it can't be read
+    fn catalogued_segments(&self, start_time: u64, end_time: u64) -> Option<Vec<(u64, u64)>> {
+        #[cfg(feature = "catalog")]
+        if let Some(catalog) = self.current_catalog() {
+            match catalog.segments_in(start_time, end_time) {
+                Ok(segments) => return Some(segments.into_iter().map(|(s, _)| s).collect()),
+                Err(e) => warn!(error = format!("{:#}", e), "Couldn't read the catalog"),
+            }
+        }
+        #[cfg(not(feature = "catalog"))]
+        let _ = (start_time, end_time);
+        None
+    }
 
+    fn list_segments_in(data_dir_path: &str, start_time: u64, end_time: u64) -> Vec<(u64, u64)> {
+        let start_day = Self::day_of(start_time);
+        let end_day = Self::day_of(end_time);
 
-        // check if we're getting all the segments
-        let first_segment = segments.first();
-        let last_segment = segments.last();
-        if first_segment.is_none() && last_segment.is_none() {
-            // no persisted data
-            return (None, None);
+        let mut days: Vec<(NaiveDate, PathBuf)> = Vec::new();
+        for (year, year_path) in Self::numeric_subdirectories(Path::new(data_dir_path)) {
+            if year > end_day.year() as u32 {
+                continue;
+            }
+            for (month, month_path) in Self::numeric_subdirectories(&year_path) {
+                if (year, month) > (end_day.year() as u32, end_day.month()) {
+                    continue;
+                }
+                for (day, day_path) in Self::numeric_subdirectories(&month_path) {
+                    let date = NaiveDate::from_ymd_opt(year as i32, month, day);
+                    match date {
+                        Some(d) if d <= end_day => days.push((d, day_path)),
+                        _ => (),
+                    }
+                }
+            }
         }
+        days.sort();
 
-        if end_time < first_segment.unwrap().0 || start_time > last_segment.unwrap().1 {
-            return (None, None);
-        }
-        
-        let start_segment_index = if start_time < first_segment.unwrap().0 {
-            // starting from the very beginning
-            Some(0)
-        } else {
-            // we need to check two consecutive segments here in order to cover times that may be in between segments
-            segments
-                .iter()
-                .zip(segments.iter().skip(1))
-                .position(|(seg1, seg2)| seg1.1 <= start_time && start_time <= seg2.1)
-                .map(|i| i + 1)
-        };
+        // a segment may reach into the following days, so the last partition before
+        // the start day needs to be considered as well
+        let first_day = days
+            .partition_point(|(d, _)| *d < start_day)
+            .saturating_sub(1);
 
-        let end_segment_index = if end_time > last_segment.unwrap().1 {
-            Some(segments.len() - 1)
-        } else {
-            segments
-                .iter()
-                .zip(segments.iter().skip(1))
-                .position(|(seg1, seg2)| seg1.1 <= end_time && end_time <= seg2.1)
-                .map(|i| i + 1)
-        };
+        let mut segments: Vec<(u64, u64)> = days[first_day..]
+            .iter()
+            .flat_map(|(_, path)| fs::read_dir(path).into_iter().flatten().flatten())
+            .filter_map(|file| SunnyDB::<T>::parse_filename_to_times(&file))
+            .collect();
+        segments.sort();
+        segments
+    }
 
-        (start_segment_index, end_segment_index)
+    fn numeric_subdirectories(path: &Path) -> Vec<(u32, PathBuf)> {
+        fs::read_dir(path)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+            .filter_map(|entry| {
+                let number = entry.file_name().to_str()?.parse::<u32>().ok()?;
+                Some((number, entry.path()))
+            })
+            .collect()
     }
 
     fn parse_filename_to_times(file: &fs::DirEntry) -> Option<(u64, u64)> {
@@ -287,13 +2076,349 @@ impl<T: Copy + DecodeOwned + Encode> SunnyDB<T> {
         Some((start_timestamp, end_timestamp))
     }
 
-    fn parse_segment_to_timeseries(&self, segment: &(u64, u64)) -> anyhow::Result<TimeSeries<T>> {
+    fn segment_of(path: &Path) -> Option<(u64, u64)> {
+        let (start, end) = path.file_name()?.to_str()?.split_once('-')?;
+        Some((start.parse().ok()?, end.parse().ok()?))
+    }
+
+    /// the path of a segment in the hot tier or, if it has been archived, in the cold tier
+    fn segment_path(&self, segment: &(u64, u64)) -> PathBuf {
         let file_name = format!("{}-{}", segment.0, segment.1);
-        let path = Path::new(&self.data_path);
-        let path = path.join(file_name);
+        let path = Self::partition_path(&self.data_path, segment.0).join(&file_name);
+        match &self.cold_data_path {
+            Some(cold_data_path) if !path.exists() => {
+                Self::partition_path(cold_data_path, segment.0).join(&file_name)
+            }
+            _ => path,
+        }
+    }
+
+    fn parse_segment_to_timeseries(&self, segment: &(u64, u64)) -> anyhow::Result<TimeSeries<T>> {
+        let path = self.segment_path(segment);
+        let ts = match &self.remote {
+            Some(remote) if !path.exists() && remote.contains(segment) => {
+                let data = remote.fetch(&remote::segment_key(*segment))?;
+                (self.segment_codec.decode)(&data, self.encryption_key.as_ref())?
+            }
+            _ => {
+                let key = self.encryption_key.as_ref();
+                Self::read_segment_file(&path, key, self.segment_codec)?
+            }
+        };
+        if ts.get_resolution() == self.get_resolution() {
+            return Ok(ts);
+        }
+        Ok(ts.to_resolution(self.get_resolution()))
+    }
+
+    fn read_segment_file(
+        path: &Path,
+        key: Option<&EncryptionKey>,
+        codec: SegmentCodec<T>,
+    ) -> anyhow::Result<TimeSeries<T>> {
         let opened_file = File::open(path)?;
         let mut buf: Vec<u8> = vec![0; opened_file.metadata()?.len() as usize];
         let _ = (&opened_file).read(&mut buf);
-        TimeSeries::<T>::from_compressed_json(&buf)
+        (codec.decode)(&buf, key)
+    }
+}
+
+impl<T> SunnyDB<T>
+where
+    T: Codec + Add<Output = T> + Mul<f64, Output = T>,
+{
+    /// same as get_values_in_range, but including start_time, with values interpolated at
+    /// exactly start_time and end_time from the values around them (see `clip_interpolated`),
+    /// so e.g. the energy of a day from midnight to midnight doesn't lose the parts of the
+    /// first and last interval that fall into it. Values further away than the largest pause
+    /// that's integrated aren't interpolated from
+    pub fn get_values_in_range_interpolated(
+        &self,
+        start_time: u64,
+        end_time: u64,
+    ) -> Option<TimeSeries<T>> {
+        let (start_time, end_time) = (start_time.min(end_time), start_time.max(end_time));
+        let max_gap = self.get_resolution().from_millis(MAX_GAP_MS);
+        let around = self.get_values_in_range(
+            start_time.saturating_sub(max_gap),
+            end_time.saturating_add(max_gap),
+        )?;
+        let clipped = clip_interpolated(&around, start_time, end_time, max_gap);
+        (!clipped.is_empty()).then_some(clipped)
+    }
+
+    /// maintains the energy per hour and per (UTC) day whenever segments are written, so e.g.
+    /// the daily production of a month can be served without integrating all of its values;
+    /// they're persisted next to the data directory and built from all persisted values if
+    /// they don't exist yet. Pauses between values of more than an hour aren't integrated
+    pub fn with_energy_aggregates(mut self) -> Result<Self, SunnyDbError> {
+        self.energy_aggregates = Some(Self::update_energy_aggregates);
+        if !self.is_read_only() && !self.aggregates_path().exists() {
+            self.rebuild_energy_aggregates()?;
+        }
+        Ok(self)
+    }
+
+    /// builds the energy aggregates from all persisted values again, e.g. after values have
+    /// been written while they weren't maintained
+    #[tracing::instrument(skip(self))]
+    pub fn rebuild_energy_aggregates(&self) -> Result<(), SunnyDbError> {
+        self.ensure_writable()?;
+        let path = self.aggregates_path();
+        if path.exists() {
+            fs::remove_dir_all(&path)?;
+        }
+        create_dir_all(&path)?;
+
+        let segments = self.list_segments(0, u64::MAX);
+        let (Some(first), Some(last)) = (
+            segments.iter().map(|s| s.0).min(),
+            segments.iter().map(|s| s.1).max(),
+        ) else {
+            return Ok(());
+        };
+        // a day at a time, so not all values have to be in memory at once
+        let day = AggregateInterval::Day.millis();
+        let mut start = interval_start(first, day);
+        while start <= last {
+            self.update_energy_aggregates(start, (start + day - 1).min(last))?;
+            start += day;
+        }
+        info!(first, last, "Built the energy aggregates");
+        Ok(())
+    }
+
+    /// recomputes the hourly aggregates of all hours the values between start_time and
+    /// end_time (in ms) contribute to from the persisted values, and the daily ones from them
+    fn update_energy_aggregates(&self, start_time: u64, end_time: u64) -> Result<(), SunnyDbError> {
+        let hour = AggregateInterval::Hour.millis();
+        let day = AggregateInterval::Day.millis();
+        // the values before start_time contribute to the hour it's in as well, unless they're
+        // further away than the largest pause that's integrated
+        let from = interval_start(start_time.saturating_sub(MAX_GAP_MS), hour);
+        let to = interval_start(end_time, hour) + hour;
+
+        let resolution = self.get_resolution();
+        let values = self.read_persisted_data(
+            resolution
+                .from_millis(from.saturating_sub(MAX_GAP_MS))
+                .saturating_sub(1),
+            resolution.from_millis(to + MAX_GAP_MS),
+            usize::MAX,
+        );
+        let hourly = match values {
+            Some(values) => aggregates::energy_per_interval(&values, hour, from, to),
+            None => BTreeMap::new(),
+        };
+        self.write_aggregates(AggregateInterval::Hour, from, to, &hourly)?;
+
+        let (day_from, day_to) = (interval_start(from, day), interval_start(to - 1, day) + day);
+        let mut daily: BTreeMap<u64, T> = BTreeMap::new();
+        for (time, energy) in self.read_aggregates(AggregateInterval::Hour, day_from, day_to)? {
+            daily
+                .entry(interval_start(time, day))
+                .and_modify(|e| *e = *e + energy)
+                .or_insert(energy);
+        }
+        self.write_aggregates(AggregateInterval::Day, day_from, day_to, &daily)
+    }
+}
+
+/// Bytes held in memory by a database, see `SunnyDB::memory_usage`
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub struct MemoryUsage {
+    /// the values that haven't been written to a segment yet, including the capacity reserved
+    /// for the rest of the segment
+    pub series: usize,
+    /// the list of segments offloaded to an object store
+    pub remote_index: usize,
+    /// the running statistics and the metrics of the write path
+    pub statistics: usize,
+}
+
+impl MemoryUsage {
+    pub fn total(&self) -> usize {
+        self.series + self.remote_index + self.statistics
+    }
+}
+
+/// A page of the values of a range; `next` is the cursor to pass to get the following page, or
+/// None if this is the last one
+#[derive(Debug)]
+pub struct Page<T> {
+    pub values: TimeSeries<T>,
+    pub next: Option<u64>,
+}
+
+impl<T> SunnyDB<T>
+where
+    T: Codec + Add<Output = T> + Mul<f64, Output = T> + Div<f64, Output = T>,
+{
+    /// same as get_values_in_range, but the result is reduced to at most max_points values
+    /// using the given downsampling method
+    pub fn get_values_in_range_downsampled(
+        &self,
+        start_time: u64,
+        end_time: u64,
+        max_points: usize,
+        method: &DownsamplingMethod<T>,
+    ) -> Option<TimeSeries<T>> {
+        let ts = self.get_values_in_range(start_time, end_time)?;
+        Some(ts.downsample(max_points, method))
+    }
+}
+
+/// The segments to archive, collected by `SunnyDB::archive_job`; it only needs the files, so
+/// it can run while the database is being written to
+pub struct ArchiveJob<T> {
+    /// the segments with their file and when it was last modified
+    segments: Vec<((u64, u64), PathBuf, Option<SystemTime>)>,
+    target_path: String,
+    /// whether the segments are moved to the cold tier rather than re-compressed in place
+    move_segments: bool,
+    /// where the end of the segments re-compressed in place so far is kept
+    watermark_path: Option<PathBuf>,
+    watermark: u64,
+    compression_level: i32,
+    key: Option<EncryptionKey>,
+    codec: SegmentCodec<T>,
+}
+
+impl<T: Codec> ArchiveJob<T> {
+    /// re-compresses the segments and returns how many were archived; segments changed since
+    /// the job was created, e.g. by an import merging values into them, are skipped and
+    /// archived the next time
+    #[tracing::instrument(skip(self), fields(segments = self.segments.len()))]
+    pub fn run(self) -> anyhow::Result<usize> {
+        let key = self.key.as_ref();
+        let mut archived = 0;
+        let mut archived_until = Some(self.watermark);
+        for (segment, source, modified) in &self.segments {
+            // checked again right before the segment is replaced or removed, so values merged
+            // into it while it was read and re-compressed aren't lost
+            let unchanged = || modified.is_some() && modified_time(source) == *modified;
+            let mut skip = || {
+                debug!(segment = ?segment, "Skipping segment changed since it was listed");
+                // the watermark can't pass it, or it would never be archived
+                archived_until = None;
+            };
+            if !unchanged() {
+                skip();
+                continue;
+            }
+            let ts = SunnyDB::<T>::read_segment_file(source, key, self.codec)?;
+            let data = self.codec.encode(&ts, self.compression_level, key)?;
+            if !unchanged() {
+                skip();
+                continue;
+            }
+            SunnyDB::<T>::write_encoded_segment(&self.target_path, &ts, &data)?;
+            if self.move_segments {
+                // the copy in the cold tier is replaced when it's archived the next time; until
+                // then, the local one is read
+                if !unchanged() {
+                    skip();
+                    continue;
+                }
+                remove_file(source)?;
+            }
+            archived_until = archived_until.map(|until| until.max(segment.1));
+            archived += 1;
+        }
+
+        if let (Some(watermark_path), Some(until)) = (&self.watermark_path, archived_until) {
+            if until > self.watermark {
+                fs::write(watermark_path, until.to_string())?;
+            }
+        }
+
+        if archived > 0 {
+            info!(
+                segments = archived,
+                target = self.target_path,
+                "Archived segments"
+            );
+        }
+        Ok(archived)
+    }
+}
+
+/// The segments to offload, collected by `SunnyDB::offload_job`; it only needs the files and
+/// the remote tier, so the uploads don't hold up writing to the database
+pub struct OffloadJob {
+    remote: Arc<RemoteTier>,
+    /// the segments with their file and when it was last modified
+    segments: Vec<((u64, u64), PathBuf, Option<SystemTime>)>,
+}
+
+impl OffloadJob {
+    /// uploads the segments and removes them locally; returns how many were offloaded.
+    /// Segments changed since the job was created are skipped and offloaded the next time
+    #[tracing::instrument(skip(self), fields(segments = self.segments.len()))]
+    pub fn run(self) -> anyhow::Result<usize> {
+        let mut offloaded = 0;
+        for (segment, file, modified) in &self.segments {
+            let unchanged = || modified.is_some() && modified_time(file) == *modified;
+            if !unchanged() {
+                debug!(segment = ?segment, "Skipping segment changed since it was listed");
+                continue;
+            }
+            // the segment is uploaded and recorded as offloaded before the local file is
+            // removed, so a crash in between only leaves it in both tiers, and so does a
+            // change while it's being uploaded, as the local file is read first
+            let data = fs::read(file)?;
+            self.remote
+                .offload(&remote::segment_key(*segment), *segment, &data)?;
+            if !unchanged() {
+                continue;
+            }
+            remove_file(file)?;
+            // drop the partition if it's empty now; fails harmlessly otherwise
+            if let Some(partition) = file.parent() {
+                fs::remove_dir(partition).ok();
+            }
+            offloaded += 1;
+        }
+
+        if offloaded > 0 {
+            info!(
+                segments = offloaded,
+                "Offloaded segments to the remote tier"
+            );
+        }
+        Ok(offloaded)
+    }
+}
+
+/// when the file was last modified, to notice changes made by others while working on it
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Flushes the wrapped database when dropped, so applications embedding it don't need to
+/// take care of persisting the values that are still in memory on shutdown
+pub struct FlushOnDrop<T: Codec> {
+    db: SunnyDB<T>,
+}
+
+impl<T: Codec> Deref for FlushOnDrop<T> {
+    type Target = SunnyDB<T>;
+
+    fn deref(&self) -> &SunnyDB<T> {
+        &self.db
+    }
+}
+
+impl<T: Codec> DerefMut for FlushOnDrop<T> {
+    fn deref_mut(&mut self) -> &mut SunnyDB<T> {
+        &mut self.db
+    }
+}
+
+impl<T: Codec> Drop for FlushOnDrop<T> {
+    fn drop(&mut self) {
+        if let Err(e) = self.db.flush() {
+            error!(error = %e, "Couldn't flush the database on drop");
+        }
     }
 }
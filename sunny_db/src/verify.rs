@@ -0,0 +1,97 @@
+use std::fmt;
+use std::path::PathBuf;
+
+/// Result of checking all segments of a database, see SunnyDB::verify
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    pub segments: usize,
+    pub values: usize,
+    /// segments written by older versions, which can't be checked for corruption
+    pub segments_without_checksum: usize,
+    pub issues: Vec<Issue>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Issue {
+    /// the segment couldn't be read or decoded
+    Unreadable { path: PathBuf, error: String },
+    /// the data of the segment doesn't match its checksum
+    ChecksumMismatch { path: PathBuf },
+    /// the time range in the file name differs from the one of the values in it
+    RangeMismatch {
+        path: PathBuf,
+        name: (u64, u64),
+        content: Option<(u64, u64)>,
+    },
+    /// the segment isn't in the partition of the day it starts on
+    WrongPartition { path: PathBuf },
+    /// the values in the segment aren't sorted by time or times occur more than once
+    Unsorted { path: PathBuf },
+    /// the time ranges of two segments intersect
+    Overlap { first: PathBuf, second: PathBuf },
+    /// there's no data between the two times, which is much longer than the usual interval
+    Gap { start: u64, end: u64 },
+}
+
+impl Issue {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Issue::Unreadable { .. } => "unreadable",
+            Issue::ChecksumMismatch { .. } => "checksum_mismatch",
+            Issue::RangeMismatch { .. } => "range_mismatch",
+            Issue::WrongPartition { .. } => "wrong_partition",
+            Issue::Unsorted { .. } => "unsorted",
+            Issue::Overlap { .. } => "overlap",
+            Issue::Gap { .. } => "gap",
+        }
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Issue::Unreadable { path, error } => {
+                write!(f, "couldn't read {}: {}", path.display(), error)
+            }
+            Issue::ChecksumMismatch { path } => {
+                write!(f, "checksum of {} doesn't match", path.display())
+            }
+            Issue::RangeMismatch {
+                path,
+                name,
+                content: Some(content),
+            } => write!(
+                f,
+                "{} is named {}-{} but contains values from {} to {}",
+                path.display(),
+                name.0,
+                name.1,
+                content.0,
+                content.1
+            ),
+            Issue::RangeMismatch {
+                path,
+                content: None,
+                ..
+            } => write!(f, "{} doesn't contain any values", path.display()),
+            Issue::WrongPartition { path } => write!(
+                f,
+                "{} isn't in the partition of the day it starts on",
+                path.display()
+            ),
+            Issue::Unsorted { path } => {
+                write!(f, "values in {} aren't sorted by time", path.display())
+            }
+            Issue::Overlap { first, second } => {
+                write!(f, "{} and {} overlap", first.display(), second.display())
+            }
+            Issue::Gap { start, end } => write!(f, "no data from {} to {}", start, end),
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use std::time::Duration;
+
+/// Upper bounds of the buckets of the insert latencies in µs
+const INSERT_BOUNDS_US: [f64; 10] = [
+    1.0, 2.0, 5.0, 10.0, 25.0, 50.0, 100.0, 1_000.0, 10_000.0, 100_000.0,
+];
+/// Upper bounds of the buckets of the flush and encoding durations in ms
+const DURATION_BOUNDS_MS: [f64; 10] = [
+    1.0, 2.0, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 1_000.0, 5_000.0,
+];
+/// Upper bounds of the buckets of the segment sizes in bytes
+const SIZE_BOUNDS_BYTES: [f64; 7] = [1e3, 1e4, 1e5, 1e6, 1e7, 1e8, 1e9];
+
+/// Distribution of measurements in buckets with fixed upper bounds; larger ones end up in an
+/// additional overflow bucket. The bucket counts aren't cumulative
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    pub bounds: &'static [f64],
+    /// one more than there are bounds, the last one is the overflow bucket
+    pub counts: Vec<u64>,
+    pub count: u64,
+    pub sum: f64,
+    pub max: f64,
+}
+
+impl Histogram {
+    pub fn new(bounds: &'static [f64]) -> Self {
+        Histogram {
+            bounds,
+            counts: vec![0; bounds.len() + 1],
+            count: 0,
+            sum: 0.0,
+            max: 0.0,
+        }
+    }
+
+    pub fn record(&mut self, value: f64) {
+        self.count += 1;
+        self.sum += value;
+        self.max = self.max.max(value);
+        let bucket = self
+            .bounds
+            .iter()
+            .position(|b| value <= *b)
+            .unwrap_or(self.bounds.len());
+        self.counts[bucket] += 1;
+    }
+
+    /// the bytes held by the histogram, leaving out the static bounds
+    pub fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>() + self.counts.capacity() * std::mem::size_of::<u64>()
+    }
+}
+
+/// How long inserting values and writing segments takes and how big the segments are, so
+/// regressions of the write path show up before values go missing
+#[derive(Clone, Debug, PartialEq)]
+pub struct WriteMetrics {
+    /// of each call inserting values in µs, including writing the segment once it's full
+    pub insert_us: Histogram,
+    /// of writing a segment in ms, from encoding it to recording it in the manifest
+    pub flush_ms: Histogram,
+    /// of serializing, compressing and possibly encrypting a segment in ms
+    pub encode_ms: Histogram,
+    /// of the written segments
+    pub segment_bytes: Histogram,
+}
+
+impl Default for WriteMetrics {
+    fn default() -> Self {
+        WriteMetrics {
+            insert_us: Histogram::new(&INSERT_BOUNDS_US),
+            flush_ms: Histogram::new(&DURATION_BOUNDS_MS),
+            encode_ms: Histogram::new(&DURATION_BOUNDS_MS),
+            segment_bytes: Histogram::new(&SIZE_BOUNDS_BYTES),
+        }
+    }
+}
+
+impl WriteMetrics {
+    pub fn record_insert(&mut self, duration: Duration) {
+        self.insert_us.record(duration.as_secs_f64() * 1e6);
+    }
+
+    pub fn record_flush(&mut self, duration: Duration, encode_duration: Duration, bytes: usize) {
+        self.flush_ms.record(duration.as_secs_f64() * 1e3);
+        self.encode_ms.record(encode_duration.as_secs_f64() * 1e3);
+        self.segment_bytes.record(bytes as f64);
+    }
+
+    /// the bytes held by the histograms
+    pub fn memory_usage(&self) -> usize {
+        [
+            &self.insert_us,
+            &self.flush_ms,
+            &self.encode_ms,
+            &self.segment_bytes,
+        ]
+        .iter()
+        .map(|histogram| histogram.memory_usage())
+        .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = Histogram::new(&[1.0, 10.0]);
+        for value in [0.5, 1.0, 3.0, 20.0, 100.0] {
+            histogram.record(value);
+        }
+        assert_eq!(histogram.counts, vec![2, 1, 2]);
+        assert_eq!(histogram.count, 5);
+        assert_eq!(histogram.sum, 124.5);
+        assert_eq!(histogram.max, 100.0);
+
+        let mut metrics = WriteMetrics::default();
+        metrics.record_flush(Duration::from_millis(30), Duration::from_millis(3), 20_000);
+        assert_eq!(metrics.flush_ms.counts[5], 1);
+        assert_eq!(metrics.encode_ms.counts[2], 1);
+        assert_eq!(metrics.segment_bytes.counts[2], 1);
+    }
+}
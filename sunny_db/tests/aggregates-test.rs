@@ -0,0 +1,88 @@
+use std::path::Path;
+use sunny_db::aggregates::AggregateInterval;
+use sunny_db::timeseries_db::SunnyDB;
+
+// 2024-06-01 00:00 UTC
+const START: u64 = 1717200000000;
+const HOUR: u64 = 3600 * 1000;
+const DAY: u64 = 24 * HOUR;
+
+fn assert_energies(energies: &[(u64, f64)], expected: &[(u64, f64)]) {
+    assert_eq!(energies.len(), expected.len(), "{:?}", energies);
+    for ((time, energy), (expected_time, expected_energy)) in energies.iter().zip(expected) {
+        assert_eq!(time, expected_time);
+        assert!(
+            (energy - expected_energy).abs() < 1e-6,
+            "{} != {} at {}",
+            energy,
+            expected_energy,
+            time
+        );
+    }
+}
+
+#[test]
+fn energy_aggregates_are_maintained_on_write() {
+    let db_path = "./tests/test-energy-aggregates";
+    std::fs::remove_dir_all(db_path).ok();
+    let mut db = SunnyDB::<f64>::new(10, db_path, 2, 0)
+        .unwrap()
+        .with_energy_aggregates()
+        .unwrap();
+
+    // 1 kW for a day, a value every 10 minutes
+    for i in 0..=144 {
+        db.insert_value_at_time(START + i * 600 * 1000, 1000.0);
+    }
+    // the last hour is only covered by persisted values up to 23:10
+    let hourly = db
+        .get_energy(AggregateInterval::Hour, START, START + DAY)
+        .unwrap();
+    assert_eq!(hourly.len(), 24);
+    assert!((hourly[23].1 - 1000.0 / 6.0).abs() < 1e-6);
+
+    db.start_new_segment().unwrap();
+    let hourly = db
+        .get_energy(AggregateInterval::Hour, START, START + DAY)
+        .unwrap();
+    let expected: Vec<(u64, f64)> = (0..24).map(|h| (START + h * HOUR, 1000.0)).collect();
+    assert_energies(&hourly, &expected);
+    let daily = db
+        .get_energy(AggregateInterval::Day, START - DAY, START + 7 * DAY)
+        .unwrap();
+    assert_energies(&daily, &[(START, 24000.0)]);
+
+    // a value after a pause of more than an hour doesn't add anything
+    db.insert_value_at_time(START + DAY + 2 * HOUR, 1000.0);
+    db.start_new_segment().unwrap();
+    let daily = db
+        .get_energy(AggregateInterval::Day, START, START + 7 * DAY)
+        .unwrap();
+    assert_energies(&daily, &[(START, 24000.0)]);
+
+    // readers see them as well
+    let reader = SunnyDB::<f64>::open_read_only(db_path).unwrap();
+    let hourly = reader
+        .get_energy(AggregateInterval::Hour, START + 5 * HOUR, START + 7 * HOUR)
+        .unwrap();
+    assert_energies(&hourly, &expected[5..7]);
+
+    // they're built from the persisted values if they're missing
+    drop(db);
+    std::fs::remove_dir_all(Path::new(db_path).join("aggregates")).unwrap();
+    let db = SunnyDB::<f64>::new(10, db_path, 2, 0)
+        .unwrap()
+        .with_energy_aggregates()
+        .unwrap();
+    let hourly = db
+        .get_energy(AggregateInterval::Hour, START, START + 7 * DAY)
+        .unwrap();
+    assert_energies(&hourly, &expected);
+    let daily = db
+        .get_energy(AggregateInterval::Day, START, START + 7 * DAY)
+        .unwrap();
+    assert_energies(&daily, &[(START, 24000.0)]);
+
+    drop(db);
+    std::fs::remove_dir_all(db_path).ok();
+}
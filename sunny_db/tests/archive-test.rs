@@ -0,0 +1,241 @@
+use bitcode::{Decode, Encode};
+use std::path::{Path, PathBuf};
+use sunny_db::remote::DirectoryStore;
+use sunny_db::timeseries::TimeSeries;
+use sunny_db::timeseries_db;
+
+#[derive(Copy, Clone, Encode, Decode, PartialEq, Debug)]
+struct PowerValues {
+    power_pv: f64,
+    power_used: f64,
+}
+
+// 2020-01-01 00:00 UTC
+const OLD_TIME: u64 = 1577836800000;
+// 2024-01-01 00:00 UTC
+const RECENT_TIME: u64 = 1704067200000;
+
+fn write_segment(db: &mut timeseries_db::SunnyDB<PowerValues>, start_time: u64) {
+    for i in 0..100 {
+        db.time_series.insert_value_at_time(
+            start_time + i * 1000,
+            PowerValues {
+                power_pv: i as f64,
+                power_used: 100.0 - i as f64,
+            },
+        );
+    }
+    db.start_new_segment().unwrap();
+}
+
+fn segment_files(path: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(path).expect("Couldn't read data directory!") {
+        let entry = entry.unwrap();
+        if entry.file_type().unwrap().is_dir() {
+            files.append(&mut segment_files(&entry.path()));
+        } else if !entry.file_name().to_str().unwrap().starts_with('.') {
+            files.push(entry.path());
+        }
+    }
+    files
+}
+
+#[test]
+fn archive_to_cold_storage() {
+    let db_path = "./tests/test-archive-cold";
+    let cold_path = "./tests/test-archive-cold-tier";
+    let mut db = timeseries_db::SunnyDB::<PowerValues>::new(1000, db_path, 2, 5)
+        .unwrap()
+        .with_cold_storage(cold_path)
+        .unwrap();
+
+    write_segment(&mut db, OLD_TIME);
+    write_segment(&mut db, OLD_TIME + 86400000);
+    write_segment(&mut db, RECENT_TIME);
+    let all_values = db.get_all_values().unwrap();
+    assert_eq!(all_values.len(), 300);
+
+    let archived = db.archive(RECENT_TIME, 22).unwrap();
+    assert_eq!(archived, 2);
+
+    let hot_files = segment_files(Path::new(&format!("{}/data", db_path)));
+    let cold_files = segment_files(Path::new(&format!("{}/data", cold_path)));
+    assert_eq!(hot_files.len(), 1);
+    assert_eq!(cold_files.len(), 2);
+    assert!(cold_files
+        .iter()
+        .any(|f| f.ends_with("2020/01/02/1577923200000-1577923299000")));
+
+    // archived data is still read transparently
+    assert_eq!(db.get_all_values().unwrap(), all_values);
+    let old_values = db
+        .get_values_in_range(OLD_TIME - 1000, OLD_TIME + 86400000 + 50000)
+        .unwrap();
+    assert_eq!(old_values.len(), 151);
+
+    // nothing left to archive
+    assert_eq!(db.archive(RECENT_TIME, 22).unwrap(), 0);
+
+    std::fs::remove_dir_all(db_path).ok();
+    std::fs::remove_dir_all(cold_path).ok();
+}
+
+#[test]
+fn archive_in_place() {
+    let db_path = "./tests/test-archive-in-place";
+    let mut db = timeseries_db::SunnyDB::<PowerValues>::new(1000, db_path, 2, 5).unwrap();
+
+    write_segment(&mut db, OLD_TIME);
+    write_segment(&mut db, RECENT_TIME);
+    let all_values = db.get_all_values().unwrap();
+
+    assert_eq!(db.archive(RECENT_TIME, 22).unwrap(), 1);
+    assert_eq!(
+        segment_files(Path::new(&format!("{}/data", db_path))).len(),
+        2
+    );
+    assert_eq!(db.get_all_values().unwrap(), all_values);
+
+    // segments that have been archived already aren't touched again
+    assert_eq!(db.archive(RECENT_TIME, 22).unwrap(), 0);
+    assert_eq!(db.archive(RECENT_TIME + 1000000, 22).unwrap(), 1);
+
+    std::fs::remove_dir_all(db_path).ok();
+}
+
+#[test]
+fn archive_jobs_skip_segments_changed_meanwhile() {
+    let db_path = "./tests/test-archive-job";
+    let cold_path = "./tests/test-archive-job-cold-tier";
+    let mut db = timeseries_db::SunnyDB::<PowerValues>::new(1000, db_path, 2, 5)
+        .unwrap()
+        .with_cold_storage(cold_path)
+        .unwrap();
+    write_segment(&mut db, OLD_TIME);
+    write_segment(&mut db, OLD_TIME + 86400000);
+
+    // the segments are listed under the lock, the lock is released while they're archived
+    let job = db.archive_job(RECENT_TIME, 22).unwrap();
+    let mut import = TimeSeries::<PowerValues>::new(1);
+    import.insert_value_at_time(
+        OLD_TIME + 500,
+        PowerValues {
+            power_pv: 1.0,
+            power_used: 2.0,
+        },
+    );
+    db.import_series(&import).unwrap();
+    let all_values = db.get_all_values().unwrap();
+    assert_eq!(all_values.len(), 201);
+
+    let archived = job.run().unwrap();
+    db.finish_archive(archived);
+    assert_eq!(archived, 1);
+    assert_eq!(db.get_all_values().unwrap(), all_values);
+    // the changed one is archived the next time
+    assert_eq!(db.archive(RECENT_TIME, 22).unwrap(), 1);
+    assert_eq!(db.get_all_values().unwrap(), all_values);
+    assert!(segment_files(Path::new(&format!("{}/data", db_path))).is_empty());
+
+    std::fs::remove_dir_all(db_path).ok();
+    std::fs::remove_dir_all(cold_path).ok();
+}
+
+#[test]
+fn archive_jobs_keep_segments_changed_meanwhile_in_place() {
+    let db_path = "./tests/test-archive-job-in-place";
+    std::fs::remove_dir_all(db_path).ok();
+    let mut db = timeseries_db::SunnyDB::<PowerValues>::new(1000, db_path, 2, 5).unwrap();
+    write_segment(&mut db, OLD_TIME);
+    write_segment(&mut db, OLD_TIME + 86400000);
+
+    // the first segment is touched after it was listed
+    let job = db.archive_job(RECENT_TIME, 22).unwrap();
+    let mut import = TimeSeries::<PowerValues>::new(1);
+    import.insert_value_at_time(
+        OLD_TIME + 500,
+        PowerValues {
+            power_pv: 1.0,
+            power_used: 2.0,
+        },
+    );
+    db.import_series(&import).unwrap();
+    let all_values = db.get_all_values().unwrap();
+    assert_eq!(all_values.len(), 201);
+
+    let archived = job.run().unwrap();
+    db.finish_archive(archived);
+    assert_eq!(archived, 1);
+    // it survives with the imported value, and the watermark doesn't pass it
+    assert_eq!(db.get_all_values().unwrap(), all_values);
+    assert_eq!(db.archive(RECENT_TIME, 22).unwrap(), 2);
+    assert_eq!(db.get_all_values().unwrap(), all_values);
+
+    std::fs::remove_dir_all(db_path).ok();
+}
+
+#[test]
+fn offload_to_remote_storage() {
+    let db_path = "./tests/test-offload";
+    let remote_path = "./tests/test-offload-remote";
+    std::fs::remove_dir_all(db_path).ok();
+    std::fs::remove_dir_all(remote_path).ok();
+    let mut db = timeseries_db::SunnyDB::<PowerValues>::new(1000, db_path, 2, 5)
+        .unwrap()
+        .with_remote_storage(Box::new(DirectoryStore::new(remote_path)))
+        .unwrap();
+
+    write_segment(&mut db, OLD_TIME);
+    write_segment(&mut db, OLD_TIME + 86400000);
+    write_segment(&mut db, RECENT_TIME);
+
+    // segments changed after they were listed are left for the next time
+    let job = db.offload_job(RECENT_TIME).unwrap();
+    let mut import = TimeSeries::<PowerValues>::new(1);
+    import.insert_value_at_time(
+        OLD_TIME + 500,
+        PowerValues {
+            power_pv: 1.0,
+            power_used: 2.0,
+        },
+    );
+    db.import_series(&import).unwrap();
+    let all_values = db.get_all_values().unwrap();
+    let offloaded = job.run().unwrap();
+    db.finish_offload(offloaded);
+    assert_eq!(offloaded, 1);
+    assert_eq!(db.offload(RECENT_TIME).unwrap(), 1);
+    assert_eq!(
+        segment_files(Path::new(&format!("{}/data", db_path))).len(),
+        1
+    );
+    let remote_files = segment_files(Path::new(remote_path));
+    assert_eq!(remote_files.len(), 2);
+    assert!(remote_files
+        .iter()
+        .any(|f| f.ends_with("2020/01/02/1577923200000-1577923299000")));
+
+    // offloaded data is fetched transparently, also by readers
+    assert_eq!(db.get_all_values().unwrap(), all_values);
+    let old_values = db
+        .get_values_in_range(OLD_TIME - 1000, OLD_TIME + 86400000 + 50000)
+        .unwrap();
+    assert_eq!(old_values.len(), 152);
+    let reader = timeseries_db::SunnyDB::<PowerValues>::open_read_only(db_path)
+        .unwrap()
+        .with_remote_storage(Box::new(DirectoryStore::new(remote_path)))
+        .unwrap();
+    assert_eq!(reader.get_all_values().unwrap(), all_values);
+
+    // nothing left to offload; without the store, only the local values are left
+    assert_eq!(db.offload(RECENT_TIME).unwrap(), 0);
+    drop(db);
+    let db = timeseries_db::SunnyDB::<PowerValues>::new(1000, db_path, 2, 5).unwrap();
+    assert_eq!(db.get_all_values().unwrap().len(), 100);
+    assert!(db.offload(RECENT_TIME).is_err());
+
+    drop(db);
+    std::fs::remove_dir_all(db_path).ok();
+    std::fs::remove_dir_all(remote_path).ok();
+}
@@ -0,0 +1,51 @@
+use sunny_db::timeseries::Quality;
+use sunny_db::timeseries_db::SunnyDB;
+
+const HOUR: u64 = 3_600_000;
+/// 2024-06-01 00:00 UTC
+const MIDNIGHT: u64 = 1717200000000;
+
+#[test]
+fn ranges_between_values_are_interpolated_at_the_boundaries() {
+    let db_path = "./tests/test-boundary-interpolation";
+    std::fs::remove_dir_all(db_path).ok();
+    let mut db = SunnyDB::<f64>::new(3, db_path, 2, 0).unwrap();
+    // sampled every 40 minutes, out of phase with the hours
+    let interval = 2 * HOUR / 3;
+    for i in 0..10 {
+        db.insert_value_at_time(MIDNIGHT - HOUR + i * interval, 1000.0);
+    }
+
+    let series = db
+        .get_values_in_range_interpolated(MIDNIGHT, MIDNIGHT + 2 * HOUR)
+        .unwrap();
+    let values = series.get_current_values();
+    assert_eq!(values.first(), Some(&(MIDNIGHT, 1000.0)));
+    assert_eq!(values.last(), Some(&(MIDNIGHT + 2 * HOUR, 1000.0)));
+    let qualities: Vec<Quality> = series
+        .view()
+        .iter_with_quality()
+        .map(|(_, _, quality)| quality)
+        .collect();
+    assert_eq!(qualities.first(), Some(&Quality::Interpolated));
+    assert!(qualities[1..qualities.len() - 1]
+        .iter()
+        .all(|quality| *quality == Quality::Measured));
+    let without = db
+        .get_values_in_range(MIDNIGHT, MIDNIGHT + 2 * HOUR)
+        .unwrap();
+    assert_eq!(without.get_current_values().len() + 2, values.len());
+
+    // the boundaries aren't extrapolated beyond the values
+    let after = db
+        .get_values_in_range_interpolated(MIDNIGHT + 5 * HOUR, MIDNIGHT + 10 * HOUR)
+        .unwrap();
+    assert_eq!(after.get_current_values()[0].0, MIDNIGHT + 5 * HOUR);
+    assert_eq!(after.get_end_time(), Some(MIDNIGHT - HOUR + 9 * interval));
+    assert!(db
+        .get_values_in_range_interpolated(MIDNIGHT + 10 * HOUR, MIDNIGHT + 12 * HOUR)
+        .is_none());
+
+    drop(db);
+    std::fs::remove_dir_all(db_path).ok();
+}
@@ -0,0 +1,99 @@
+#![cfg(feature = "catalog")]
+
+use chrono::NaiveDate;
+use sunny_db::manifest::Change;
+use sunny_db::timeseries_db::SunnyDB;
+
+/// 2024-06-01 00:00 UTC
+const MIDNIGHT: u64 = 1717200000000;
+const DAY: u64 = 86_400_000;
+
+fn write_segment(db: &mut SunnyDB<f64>, start_time: u64) {
+    for i in 0..10 {
+        db.time_series
+            .insert_value_at_time(start_time + i * 1000, i as f64);
+    }
+    db.start_new_segment().unwrap();
+}
+
+#[test]
+fn the_catalog_mirrors_the_manifest() {
+    let db_path = "./tests/test-catalog";
+    std::fs::remove_dir_all(db_path).ok();
+    let mut db = SunnyDB::<f64>::new(100, db_path, 2, 0).unwrap();
+    write_segment(&mut db, MIDNIGHT);
+    // segments written before the catalog existed are added when it's created
+    let mut db = db.with_catalog().unwrap();
+    write_segment(&mut db, MIDNIGHT + DAY);
+    write_segment(&mut db, MIDNIGHT + 2 * DAY);
+
+    let catalog = db.catalog().unwrap();
+    let in_range: Vec<(u64, u64)> = catalog
+        .segments_in(MIDNIGHT + DAY, MIDNIGHT + 3 * DAY)
+        .unwrap()
+        .into_iter()
+        .map(|(segment, _)| segment)
+        .collect();
+    assert_eq!(
+        in_range,
+        db.segments_covering(MIDNIGHT + DAY, MIDNIGHT + 3 * DAY)
+    );
+    assert_eq!(in_range.len(), 2);
+
+    let entries = catalog.manifest_since(0).unwrap();
+    assert_eq!(entries.len(), 3);
+    assert!(entries
+        .iter()
+        .all(|e| e.change == Change::Added && e.size > 0));
+    assert_eq!(catalog.last_seq().unwrap(), entries[2].seq);
+    assert_eq!(db.manifest_since(entries[0].seq).unwrap(), entries[1..]);
+
+    let date = NaiveDate::from_ymd_opt(2024, 6, 1).unwrap();
+    catalog.put_summary(date, "{\"score\": 1.0}").unwrap();
+    assert_eq!(catalog.summary(date).unwrap().unwrap(), "{\"score\": 1.0}");
+    assert_eq!(catalog.summary(date.succ_opt().unwrap()).unwrap(), None);
+    catalog
+        .put_summary(date.succ_opt().unwrap(), "{\"score\": 2.0}")
+        .unwrap();
+    assert_eq!(catalog.summaries_size().unwrap(), 28);
+    // the oldest summaries are removed first
+    assert_eq!(catalog.remove_oldest_summaries(1).unwrap(), 14);
+    assert_eq!(catalog.summary(date).unwrap(), None);
+    assert!(catalog.summary(date.succ_opt().unwrap()).unwrap().is_some());
+    drop(db);
+
+    // other processes can read it, e.g. while sunny is running
+    let reader = SunnyDB::<f64>::open_read_only(db_path)
+        .unwrap()
+        .with_catalog()
+        .unwrap();
+    assert_eq!(reader.manifest_since(0).unwrap(), entries);
+    // the segments of a range are looked up in it
+    assert_eq!(
+        reader.segments_covering(MIDNIGHT, MIDNIGHT + 3 * DAY).len(),
+        3
+    );
+
+    // while the catalog is behind the manifest, e.g. because a writer without it added
+    // segments, the manifest and the data directory are read instead
+    let mut writer = SunnyDB::<f64>::new(100, db_path, 2, 0).unwrap();
+    write_segment(&mut writer, MIDNIGHT + 3 * DAY);
+    assert_eq!(reader.manifest_since(0).unwrap().len(), 4);
+    assert_eq!(
+        reader.segments_covering(MIDNIGHT, MIDNIGHT + 4 * DAY).len(),
+        4
+    );
+    drop(writer);
+
+    // the missing entries are added once it's maintained again
+    let db = SunnyDB::<f64>::new(100, db_path, 2, 0)
+        .unwrap()
+        .with_catalog()
+        .unwrap();
+    assert_eq!(db.catalog().unwrap().manifest_since(0).unwrap().len(), 4);
+    assert!(db.catalog().unwrap().is_contiguous().unwrap());
+
+    drop(db);
+    drop(reader);
+    std::fs::remove_dir_all(db_path).ok();
+}
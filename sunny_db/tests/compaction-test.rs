@@ -0,0 +1,87 @@
+use bitcode::{Decode, Encode};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use sunny_db::timeseries::TimeSeries;
+use sunny_db::timeseries_db;
+
+#[derive(Copy, Clone, Encode, Decode, PartialEq, Debug)]
+struct PowerValues {
+    power_pv: f64,
+    power_used: f64,
+}
+
+const START: u64 = 1717200000000;
+const VALUES: u64 = 400;
+
+fn value(i: u64) -> PowerValues {
+    PowerValues {
+        power_pv: i as f64,
+        power_used: 1.0,
+    }
+}
+
+#[test]
+fn queries_run_concurrently_with_compaction() {
+    let db_path = "./tests/test-concurrent-compaction";
+    let cold_path = "./tests/test-concurrent-compaction-cold";
+    std::fs::remove_dir_all(db_path).ok();
+    std::fs::remove_dir_all(cold_path).ok();
+
+    let mut db = timeseries_db::SunnyDB::<PowerValues>::new(10, db_path, 2, 0)
+        .unwrap()
+        .with_cold_storage(cold_path)
+        .unwrap();
+    for i in 0..VALUES {
+        db.insert_value_at_time(START + i * 1000, value(i));
+    }
+    let expected: Vec<(u64, PowerValues)> =
+        (0..VALUES).map(|i| (START + i * 1000, value(i))).collect();
+
+    let done = AtomicBool::new(false);
+    let reads = AtomicUsize::new(0);
+    std::thread::scope(|scope| {
+        for _ in 0..2 {
+            scope.spawn(|| {
+                let reader = timeseries_db::SunnyDB::<PowerValues>::open_read_only(db_path)
+                    .unwrap()
+                    .with_cold_storage(cold_path)
+                    .unwrap();
+                while !done.load(Ordering::Relaxed) {
+                    let values = reader
+                        .get_values_in_range(0, START + VALUES * 1000)
+                        .unwrap();
+                    assert_eq!(values.get_current_values(), expected);
+                    let partial = reader
+                        .get_values_in_range(START + 95000, START + 205000)
+                        .unwrap();
+                    assert_eq!(partial.get_current_values(), expected[96..=205]);
+                    reads.fetch_add(1, Ordering::Relaxed);
+                }
+            });
+        }
+
+        for round in 0..20 {
+            // re-importing shifted copies of the values merges the overlapping segments,
+            // archiving moves the merged ones to the cold tier
+            let shift = round % 9 + 1;
+            let mut copy = TimeSeries::<PowerValues>::new(VALUES as usize);
+            for i in shift..VALUES {
+                copy.insert_value_at_time(START + i * 1000, value(i));
+            }
+            db.import_series(&copy).unwrap();
+            db.archive(u64::MAX, 2).unwrap();
+        }
+        done.store(true, Ordering::Relaxed);
+    });
+
+    assert!(reads.load(Ordering::Relaxed) > 0);
+    assert_eq!(
+        db.get_values_in_range(0, START + VALUES * 1000)
+            .unwrap()
+            .get_current_values(),
+        expected
+    );
+
+    drop(db);
+    std::fs::remove_dir_all(db_path).ok();
+    std::fs::remove_dir_all(cold_path).ok();
+}
@@ -0,0 +1,125 @@
+use bitcode::{Decode, Encode};
+use sunny_db::codec::SegmentEncoding;
+use sunny_db::fields::FloatFields;
+use sunny_db::timeseries::{Quality, Resolution, TimeSeries};
+use sunny_db::timeseries_db;
+
+#[derive(Copy, Clone, Encode, Decode, PartialEq, Debug)]
+struct PowerValues {
+    power_pv: f64,
+    power_used: f64,
+}
+
+impl FloatFields for PowerValues {
+    const COUNT: usize = 2;
+
+    fn field(&self, index: usize) -> f64 {
+        [self.power_pv, self.power_used][index]
+    }
+
+    fn from_fields(fields: &[f64]) -> Self {
+        PowerValues {
+            power_pv: fields[0],
+            power_used: fields[1],
+        }
+    }
+}
+
+const START_TIME: u64 = 1717200000000;
+
+/// a day of readings every 5 seconds with a little jitter, like the collector writes them
+fn readings(count: u64) -> TimeSeries<PowerValues> {
+    let mut series = TimeSeries::<PowerValues>::new(count as usize);
+    for i in 0..count {
+        let jitter = [0, 0, 0, 1, 0, 2, 0, 0][i as usize % 8];
+        let x = i as f64 / count as f64 * std::f64::consts::PI;
+        series.insert_value_at_time(
+            START_TIME + i * 5000 + jitter,
+            PowerValues {
+                power_pv: (x.sin() * 4200.0).round(),
+                power_used: 350.0 + (i % 40) as f64 * 2.5,
+            },
+        );
+    }
+    series
+}
+
+#[test]
+fn gorilla_segments_are_smaller_than_zstd() {
+    let series = readings(17280);
+    let zstd = series.to_segment(19, None).unwrap();
+    let gorilla = series.to_gorilla_segment(None).unwrap();
+    assert!(
+        gorilla.len() < zstd.len(),
+        "gorilla: {} bytes, zstd: {} bytes",
+        gorilla.len(),
+        zstd.len()
+    );
+
+    let decoded = TimeSeries::<PowerValues>::from_float_segment(&gorilla, None).unwrap();
+    assert_eq!(decoded, series);
+    assert_eq!(
+        TimeSeries::<PowerValues>::from_float_segment(&zstd, None).unwrap(),
+        series
+    );
+    // decoding gorilla segments needs values implementing FloatFields
+    assert!(TimeSeries::<PowerValues>::from_segment(&gorilla, None).is_err());
+}
+
+#[test]
+fn qualities_and_resolutions_are_kept() {
+    let mut series = TimeSeries::<PowerValues>::with_resolution(4, Resolution::Seconds);
+    let value = PowerValues {
+        power_pv: 1.5,
+        power_used: 2.5,
+    };
+    series.insert_value_with_quality(10, value, Quality::Backfilled);
+    series.insert_value_with_quality(20, value, Quality::Measured);
+    series.insert_value_with_quality(25, value, Quality::Suspect);
+
+    let segment = series.to_gorilla_segment(None).unwrap();
+    let decoded = TimeSeries::<PowerValues>::from_float_segment(&segment, None).unwrap();
+    assert_eq!(decoded, series);
+    assert_eq!(
+        decoded.get_current_values_with_quality()[2].2,
+        Quality::Suspect
+    );
+}
+
+#[test]
+fn databases_read_segments_in_either_encoding() {
+    let db_path = "./tests/test-gorilla-encoding";
+    std::fs::remove_dir_all(db_path).ok();
+    let series = readings(300);
+    let values = series.get_current_values();
+
+    let mut db = timeseries_db::SunnyDB::<PowerValues>::new(100, db_path, 2, 0).unwrap();
+    for (time, value) in &values[..100] {
+        db.insert_value_at_time(*time, *value);
+    }
+    drop(db);
+
+    let mut db = timeseries_db::SunnyDB::<PowerValues>::new(100, db_path, 2, 0)
+        .unwrap()
+        .with_segment_encoding(SegmentEncoding::Gorilla)
+        .unwrap();
+    for (time, value) in &values[100..] {
+        db.insert_value_at_time(*time, *value);
+    }
+    assert_eq!(db.get_all_values().unwrap().get_current_values(), values);
+    drop(db);
+
+    // switching back keeps the segments written in the gorilla encoding readable
+    let db = timeseries_db::SunnyDB::<PowerValues>::open_read_only(db_path)
+        .unwrap()
+        .with_segment_encoding(SegmentEncoding::Zstd)
+        .unwrap();
+    assert!(db.verify().is_ok());
+    assert_eq!(db.get_all_values().unwrap().get_current_values(), values);
+
+    // without knowing the values support it, they can't be decoded
+    let db = timeseries_db::SunnyDB::<PowerValues>::open_read_only(db_path).unwrap();
+    assert!(!db.verify().is_ok());
+
+    std::fs::remove_dir_all(db_path).ok();
+}
@@ -0,0 +1,81 @@
+use bitcode::{Decode, Encode};
+use std::path::{Path, PathBuf};
+use sunny_db::timeseries_db;
+
+#[derive(Copy, Clone, Encode, Decode, PartialEq, Debug)]
+struct PowerValues {
+    power_pv: f64,
+    power_used: f64,
+}
+
+// 2024-06-01 00:00 UTC
+const START_TIME: u64 = 1717200000000;
+
+fn write_segment(db: &mut timeseries_db::SunnyDB<PowerValues>, from: u64, to: u64) {
+    for i in from..to {
+        db.insert_value_at_time(
+            START_TIME + i * 60000,
+            PowerValues {
+                power_pv: i as f64,
+                power_used: 1.0,
+            },
+        );
+    }
+    db.start_new_segment().unwrap();
+}
+
+fn segment_files(path: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(path).expect("Couldn't read data directory!") {
+        let entry = entry.unwrap();
+        if entry.file_type().unwrap().is_dir() {
+            files.append(&mut segment_files(&entry.path()));
+        } else {
+            files.push(entry.path());
+        }
+    }
+    files
+}
+
+#[test]
+fn import_from_other_database() {
+    let db_path = "./tests/test-import";
+    let other_path = "./tests/test-import-other";
+
+    // the old hardware collected the first day and a bit of the second one
+    let mut other = timeseries_db::SunnyDB::<PowerValues>::new(10000, other_path, 2, 0).unwrap();
+    write_segment(&mut other, 0, 1440);
+    write_segment(&mut other, 1440, 1500);
+    drop(other);
+
+    // the new one overlaps with the old one for a while
+    let mut db = timeseries_db::SunnyDB::<PowerValues>::new(10000, db_path, 2, 0).unwrap();
+    write_segment(&mut db, 1470, 2880);
+    write_segment(&mut db, 2880, 3000);
+
+    assert_eq!(db.import_from(other_path).unwrap(), 2);
+
+    // the overlapping segments have been merged
+    let mut files = segment_files(Path::new(&format!("{}/data", db_path)));
+    files.sort();
+    assert_eq!(files.len(), 3);
+    assert!(files[0].ends_with("2024/06/01/1717200000000-1717286340000"));
+    assert!(files[1].ends_with("2024/06/02/1717286400000-1717372740000"));
+
+    let values = db.get_all_values().unwrap().get_current_values();
+    assert_eq!(values.len(), 3000);
+    for (i, (time, value)) in values.iter().enumerate() {
+        assert_eq!(*time, START_TIME + i as u64 * 60000);
+        assert_eq!(value.power_pv, i as f64);
+    }
+
+    // the source isn't touched
+    assert_eq!(
+        segment_files(Path::new(&format!("{}/data", other_path))).len(),
+        2
+    );
+    assert!(db.import_from(db_path).is_err());
+
+    std::fs::remove_dir_all(db_path).ok();
+    std::fs::remove_dir_all(other_path).ok();
+}
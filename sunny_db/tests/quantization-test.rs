@@ -0,0 +1,124 @@
+use bitcode::{Decode, Encode};
+use sunny_db::codec::SegmentEncoding;
+use sunny_db::encryption::EncryptionKey;
+use sunny_db::error::SunnyDbError;
+use sunny_db::fields::FloatFields;
+use sunny_db::timeseries::TimeSeries;
+use sunny_db::timeseries_db;
+
+#[derive(Copy, Clone, Encode, Decode, PartialEq, Debug)]
+struct PowerValues {
+    power_pv: f64,
+    power_used: f64,
+}
+
+impl FloatFields for PowerValues {
+    const COUNT: usize = 2;
+
+    fn field(&self, index: usize) -> f64 {
+        [self.power_pv, self.power_used][index]
+    }
+
+    fn from_fields(fields: &[f64]) -> Self {
+        PowerValues {
+            power_pv: fields[0],
+            power_used: fields[1],
+        }
+    }
+}
+
+const START_TIME: u64 = 1717200000000;
+
+fn readings() -> TimeSeries<PowerValues> {
+    let mut series = TimeSeries::<PowerValues>::new(100);
+    for i in 0..100 {
+        series.insert_value_at_time(
+            START_TIME + i * 1000,
+            PowerValues {
+                power_pv: 1000.0 + i as f64 * 1.234,
+                power_used: 300.06 - i as f64,
+            },
+        );
+    }
+    series
+}
+
+#[test]
+fn quantized_values_are_rounded() {
+    let series = readings();
+    for encoding in [SegmentEncoding::Zstd, SegmentEncoding::Gorilla] {
+        let segment = series.to_segment_with(encoding, Some(1), 3, None).unwrap();
+        let decoded = TimeSeries::<PowerValues>::from_float_segment(&segment, None).unwrap();
+        assert_eq!(decoded.len(), series.len());
+        for ((time, value), (expected_time, expected)) in decoded.iter().zip(series.iter()) {
+            assert_eq!(time, expected_time);
+            assert_eq!(value.power_pv, (expected.power_pv * 10.0).round() / 10.0);
+            assert_eq!(
+                value.power_used,
+                (expected.power_used * 10.0).round() / 10.0
+            );
+        }
+        // undoing the quantization needs the values to implement FloatFields
+        assert!(TimeSeries::<PowerValues>::from_segment(&segment, None).is_err());
+    }
+
+    // quantized segments are smaller than those with the floats as they are
+    let zstd = series.to_segment(3, None).unwrap();
+    let quantized = series
+        .to_segment_with(SegmentEncoding::Zstd, Some(1), 3, None)
+        .unwrap();
+    assert!(quantized.len() < zstd.len());
+
+    // values that don't fit into an i32 are stored as they are
+    let mut large = TimeSeries::<PowerValues>::new(1);
+    let value = PowerValues {
+        power_pv: 1e12 + 0.25,
+        power_used: 0.0,
+    };
+    large.insert_value_at_time(START_TIME, value);
+    let segment = large
+        .to_segment_with(SegmentEncoding::Zstd, Some(1), 3, None)
+        .unwrap();
+    let decoded = TimeSeries::<PowerValues>::from_segment(&segment, None).unwrap();
+    assert_eq!(decoded.get_current_values(), vec![(START_TIME, value)]);
+}
+
+#[test]
+fn databases_quantize_new_segments() {
+    let db_path = "./tests/test-quantization";
+    std::fs::remove_dir_all(db_path).ok();
+    let key = EncryptionKey::from_bytes(&[7; 32]).unwrap();
+    let series = readings();
+
+    let mut db = timeseries_db::SunnyDB::<PowerValues>::new(50, db_path, 2, 0)
+        .unwrap()
+        .with_quantization(-1)
+        .unwrap()
+        .with_encryption_key(key.clone())
+        .unwrap();
+    for (time, value) in series.iter() {
+        db.insert_value_at_time(time, *value);
+    }
+    drop(db);
+
+    let db = timeseries_db::SunnyDB::<PowerValues>::open_read_only(db_path)
+        .unwrap()
+        .with_segment_encoding(SegmentEncoding::Gorilla)
+        .unwrap()
+        .with_encryption_key(key)
+        .unwrap();
+    assert!(db.verify().is_ok());
+    let values = db.get_all_values().unwrap().get_current_values();
+    assert_eq!(values.len(), 100);
+    assert_eq!(values[0].1.power_pv, 1000.0);
+    assert_eq!(values[99].1.power_used, 200.0);
+    drop(db);
+
+    let db = timeseries_db::SunnyDB::<PowerValues>::open_read_only(db_path).unwrap();
+    assert!(matches!(
+        db.with_quantization(10),
+        Err(SunnyDbError::QuantizationOutOfRange(10))
+    ));
+
+    std::fs::remove_dir_all(db_path).ok();
+}
@@ -0,0 +1,82 @@
+use sunny_db::codec::BITCODE;
+use sunny_db::encryption::NO_CIPHER;
+use sunny_db::raw_segments::{SegmentCursor, StorageTier};
+use sunny_db::timeseries::{Resolution, TimeSeries};
+use sunny_db::timeseries_db::SunnyDB;
+
+/// 2024-06-01 00:00 UTC
+const MIDNIGHT: u64 = 1717200000000;
+const DAY: u64 = 86_400_000;
+
+fn write_segment(db: &mut SunnyDB<f64>, start_time: u64) {
+    for i in 0..10 {
+        db.time_series
+            .insert_value_at_time(start_time + i * 1000, i as f64);
+    }
+    db.start_new_segment().unwrap();
+}
+
+#[test]
+fn segments_are_streamed_without_decoding_them() {
+    // in a directory of its own, so the manifest next to it isn't shared with other tests
+    let home = "./tests/test-raw-segments";
+    let db_path = "./tests/test-raw-segments/db";
+    let cold_path = "./tests/test-raw-segments-cold";
+    std::fs::remove_dir_all(home).ok();
+    std::fs::remove_dir_all(cold_path).ok();
+    let mut db = SunnyDB::<f64>::new(100, db_path, 2, 0)
+        .unwrap()
+        .with_cold_storage(cold_path)
+        .unwrap();
+    for day in [2, 0, 1] {
+        write_segment(&mut db, MIDNIGHT + day * DAY);
+    }
+    assert_eq!(db.archive(MIDNIGHT + DAY, 3).unwrap(), 1);
+
+    let mut all = db.raw_segments(None).unwrap();
+    let segments: Vec<_> = all.by_ref().map(|s| s.unwrap()).collect();
+    let starts: Vec<u64> = segments.iter().map(|s| s.segment.0).collect();
+    assert_eq!(starts, [MIDNIGHT, MIDNIGHT + DAY, MIDNIGHT + 2 * DAY]);
+    assert_eq!(segments[0].tier, StorageTier::Cold);
+    assert_eq!(segments[1].tier, StorageTier::Hot);
+    assert_eq!(
+        segments[1].name(),
+        format!("{}-{}", MIDNIGHT + DAY, MIDNIGHT + DAY + 9000)
+    );
+    let info = segments[1].info.unwrap();
+    assert_eq!(info.resolution, Resolution::Milliseconds);
+    assert_eq!((info.codec, info.cipher), (BITCODE, NO_CIPHER));
+    // the bytes are the segment as stored
+    let decoded = TimeSeries::<f64>::from_segment(&segments[1].bytes, None).unwrap();
+    assert_eq!(decoded.len(), 10);
+    assert_eq!(decoded.get_start_time(), Some(MIDNIGHT + DAY));
+
+    // continuing with only the segments added since, also once the cursor has been stored
+    let cursor: SegmentCursor = all.cursor().to_string().parse().unwrap();
+    assert_eq!(db.raw_segments(Some(cursor)).unwrap().count(), 0);
+    write_segment(&mut db, MIDNIGHT + 3 * DAY);
+    // including a backfilled one older than all others and a rewritten one, archived along
+    // with it
+    write_segment(&mut db, MIDNIGHT - DAY);
+    assert_eq!(db.archive(MIDNIGHT + 2 * DAY, 3).unwrap(), 2);
+    let mut added = db.raw_segments(Some(cursor)).unwrap();
+    let rest: Vec<(u64, StorageTier)> = added
+        .by_ref()
+        .map(|s| s.unwrap())
+        .map(|s| (s.segment.0, s.tier))
+        .collect();
+    assert_eq!(
+        rest,
+        [
+            (MIDNIGHT - DAY, StorageTier::Cold),
+            (MIDNIGHT + DAY, StorageTier::Cold),
+            (MIDNIGHT + 3 * DAY, StorageTier::Hot),
+        ]
+    );
+    assert!(added.cursor().seq > cursor.seq);
+    assert_eq!(db.raw_segments(Some(added.cursor())).unwrap().count(), 0);
+
+    drop(db);
+    std::fs::remove_dir_all(home).ok();
+    std::fs::remove_dir_all(cold_path).ok();
+}
@@ -14,7 +14,7 @@ fn read_test() {
     // generate some data beforehand and put them in the right directory!
     let test_db_path = "./tests/stress-test-data";
 
-    let tiny_db = timeseries_db::SunnyDB::<PowerValues>::new(200, &test_db_path, 2, 20);
+    let tiny_db = timeseries_db::SunnyDB::<PowerValues>::new(200, test_db_path, 2, 20).unwrap();
 
     for _ in 0..2 {
         tiny_db.get_all_values();
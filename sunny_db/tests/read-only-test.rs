@@ -0,0 +1,92 @@
+use bitcode::{Decode, Encode};
+use sunny_db::error::SunnyDbError;
+use sunny_db::timeseries_db;
+
+#[derive(Copy, Clone, Encode, Decode, PartialEq, Debug)]
+struct PowerValues {
+    power_pv: f64,
+    power_used: f64,
+}
+
+#[test]
+fn read_only_alongside_writer() {
+    let db_path = "./tests/test-read-only";
+    let mut writer = timeseries_db::SunnyDB::<PowerValues>::new(10, db_path, 2, 0).unwrap();
+    for i in 0..25 {
+        writer.insert_value_at_time(
+            1717200000000 + i * 1000,
+            PowerValues {
+                power_pv: i as f64,
+                power_used: 1.0,
+            },
+        );
+    }
+
+    // a second writer can't open the directory
+    let second_writer = timeseries_db::SunnyDB::<PowerValues>::new(10, db_path, 2, 0);
+    assert!(matches!(second_writer, Err(SunnyDbError::Locked { .. })));
+
+    // but readers can, and they see everything that has been persisted
+    let mut reader = timeseries_db::SunnyDB::<PowerValues>::open_read_only(db_path).unwrap();
+    assert!(reader.is_read_only());
+    assert_eq!(reader.get_all_values().unwrap().len(), 20);
+    assert_eq!(
+        reader
+            .get_values_in_range(1717200004000, 1717200014000)
+            .unwrap()
+            .len(),
+        10
+    );
+
+    // writing is refused
+    reader.time_series.insert_value_at_time(
+        1717200100000,
+        PowerValues {
+            power_pv: 0.0,
+            power_used: 0.0,
+        },
+    );
+    assert!(matches!(reader.flush(), Err(SunnyDbError::ReadOnly)));
+    assert!(reader.archive(u64::MAX, 22).is_err());
+
+    // once the writer is gone, the directory can be opened for writing again
+    drop(writer);
+    let writer = timeseries_db::SunnyDB::<PowerValues>::new(10, db_path, 2, 0).unwrap();
+    assert!(!writer.is_read_only());
+
+    std::fs::remove_dir_all(db_path).ok();
+}
+
+#[test]
+fn reader_picks_up_new_segments() {
+    let db_path = "./tests/test-read-only-replica";
+    let mut writer = timeseries_db::SunnyDB::<PowerValues>::new(10, db_path, 2, 0).unwrap();
+    let reader = timeseries_db::SunnyDB::<PowerValues>::open_read_only(db_path).unwrap();
+    assert_eq!(reader.last_persisted_time(), None);
+
+    for i in 0..10 {
+        writer.insert_value_at_time(
+            1717200000000 + i * 1000,
+            PowerValues {
+                power_pv: i as f64,
+                power_used: 1.0,
+            },
+        );
+    }
+    assert_eq!(reader.last_persisted_time(), Some(1717200009000));
+
+    // a segment on the next day
+    for i in 0..10 {
+        writer.insert_value_at_time(
+            1717286400000 + i * 1000,
+            PowerValues {
+                power_pv: i as f64,
+                power_used: 1.0,
+            },
+        );
+    }
+    assert_eq!(reader.last_persisted_time(), Some(1717286409000));
+    assert_eq!(reader.get_all_values().unwrap().len(), 20);
+
+    std::fs::remove_dir_all(db_path).ok();
+}
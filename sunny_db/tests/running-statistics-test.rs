@@ -0,0 +1,54 @@
+use sunny_db::timeseries_db::SunnyDB;
+
+const HOUR: u64 = 3_600_000;
+/// 2024-06-01 00:00 UTC
+const MIDNIGHT: u64 = 1717200000000;
+
+#[test]
+fn running_statistics_follow_the_inserts() {
+    let db_path = "./tests/test-running-statistics";
+    std::fs::remove_dir_all(db_path).ok();
+    let mut db = SunnyDB::<f64>::new(3, db_path, 2, 0)
+        .unwrap()
+        .with_running_statistics(MIDNIGHT);
+    db.insert_value_at_time(MIDNIGHT - HOUR, 500.0);
+    for h in 0..4 {
+        db.insert_value_at_time(MIDNIGHT + h * HOUR, 1000.0 * (h + 1) as f64);
+    }
+    db.insert_many([(MIDNIGHT + 5 * HOUR, 0.0), (MIDNIGHT + 6 * HOUR, 0.0)]);
+    let statistics = db.running_statistics().unwrap();
+    assert_eq!(statistics.count, 6);
+    assert_eq!(statistics.sum(), Some(10000.0));
+    assert_eq!(statistics.max(), Some(4000.0));
+    assert_eq!(statistics.min(), Some(0.0));
+    // the pause between 03:00 and 05:00 isn't integrated
+    assert_eq!(statistics.integral(), Some(7500.0));
+    assert_eq!(statistics.last, Some((MIDNIGHT + 6 * HOUR, 0.0)));
+
+    // the values stored since then count when starting over, whether in segments or not
+    db.reset_running_statistics(MIDNIGHT + 2 * HOUR);
+    let statistics = db.running_statistics().unwrap();
+    assert_eq!(statistics.count, 4);
+    assert_eq!(statistics.sum(), Some(7000.0));
+    drop(db);
+
+    let db = SunnyDB::<f64>::new(3, db_path, 2, 0)
+        .unwrap()
+        .with_running_statistics(MIDNIGHT);
+    assert_eq!(db.running_statistics().unwrap().sum(), Some(10000.0));
+
+    drop(db);
+    std::fs::remove_dir_all(db_path).ok();
+}
+
+#[test]
+fn running_statistics_are_optional() {
+    let db_path = "./tests/test-running-statistics-disabled";
+    std::fs::remove_dir_all(db_path).ok();
+    let mut db = SunnyDB::<f64>::new(3, db_path, 2, 0).unwrap();
+    db.insert_value_at_time(MIDNIGHT, 1.0);
+    db.reset_running_statistics(MIDNIGHT);
+    assert!(db.running_statistics().is_none());
+    drop(db);
+    std::fs::remove_dir_all(db_path).ok();
+}
@@ -0,0 +1,631 @@
+use bitcode::{Decode, Encode};
+use std::path::{Path, PathBuf};
+use sunny_db::encryption::EncryptionKey;
+use sunny_db::statistics::QualityOfSeries;
+use sunny_db::timeseries::{checksum_matches, Quality, Resolution};
+use sunny_db::timeseries_db;
+
+#[derive(Copy, Clone, Encode, Decode, PartialEq, Debug)]
+struct PowerValues {
+    power_pv: f64,
+    power_used: f64,
+}
+
+fn all_files(path: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(path).expect("Couldn't read data directory!") {
+        let entry = entry.unwrap();
+        if entry.file_type().unwrap().is_dir() {
+            files.append(&mut all_files(&entry.path()));
+        } else {
+            files.push(entry.path());
+        }
+    }
+    files
+}
+
+#[test]
+fn stale_temp_files_are_removed() {
+    let db_path = "./tests/test-stale-temp-files";
+    let partition = format!("{}/data/2024/06/01", db_path);
+    std::fs::create_dir_all(&partition).unwrap();
+
+    // a segment that was interrupted while being written
+    let stale_file = format!("{}/1717200000000-1717200100000.tmp", partition);
+    std::fs::write(&stale_file, [40, 181, 47]).unwrap();
+
+    let mut db = timeseries_db::SunnyDB::<PowerValues>::new(10, db_path, 2, 0).unwrap();
+    assert!(!Path::new(&stale_file).exists());
+
+    for i in 0..10 {
+        db.time_series.insert_value_at_time(
+            1717200000000 + i * 1000,
+            PowerValues {
+                power_pv: i as f64,
+                power_used: 1.0,
+            },
+        );
+    }
+    db.start_new_segment().unwrap();
+
+    // only the completed segment is left
+    let files = all_files(Path::new(&format!("{}/data", db_path)));
+    assert_eq!(files.len(), 1);
+    assert!(files[0].ends_with("2024/06/01/1717200000000-1717200009000"));
+    assert_eq!(db.get_all_values().unwrap().len(), 10);
+
+    std::fs::remove_dir_all(db_path).ok();
+}
+
+#[test]
+fn flush_replaces_previously_flushed_segment() {
+    let db_path = "./tests/test-flush";
+    let data_path = format!("{}/data", db_path);
+    let mut db = timeseries_db::SunnyDB::<PowerValues>::new(10, db_path, 2, 5).unwrap();
+    let insert = |db: &mut timeseries_db::SunnyDB<PowerValues>, i: u64| {
+        db.insert_value_at_time(
+            1717200000000 + i * 1000,
+            PowerValues {
+                power_pv: i as f64,
+                power_used: 1.0,
+            },
+        )
+    };
+
+    // flushing ignores the data loss threshold and keeps the values in memory
+    for i in 0..3 {
+        insert(&mut db, i);
+    }
+    db.flush().unwrap();
+    assert_eq!(db.time_series.len(), 3);
+    assert_eq!(all_files(Path::new(&data_path)).len(), 1);
+
+    // flushing again replaces the previous segment
+    for i in 3..6 {
+        insert(&mut db, i);
+    }
+    db.flush().unwrap();
+    let files = all_files(Path::new(&data_path));
+    assert_eq!(files.len(), 1);
+    assert!(files[0].ends_with("1717200000000-1717200005000"));
+
+    // ... and so does dumping a full segment
+    for i in 6..10 {
+        insert(&mut db, i);
+    }
+    assert!(db.time_series.is_empty());
+    let files = all_files(Path::new(&data_path));
+    assert_eq!(files.len(), 1);
+    assert!(files[0].ends_with("1717200000000-1717200009000"));
+    assert_eq!(db.get_all_values().unwrap().len(), 10);
+
+    // the guard flushes on drop
+    {
+        let mut guarded = db.flush_on_drop();
+        insert(&mut guarded, 10);
+    }
+    let db = timeseries_db::SunnyDB::<PowerValues>::new(10, db_path, 2, 5).unwrap();
+    assert_eq!(db.get_all_values().unwrap().len(), 11);
+
+    std::fs::remove_dir_all(db_path).ok();
+}
+
+#[test]
+fn overlapping_segments_are_merged_on_open() {
+    let db_path = "./tests/test-overlapping-segments";
+    let data_path = format!("{}/data", db_path);
+    let mut db = timeseries_db::SunnyDB::<PowerValues>::new(10, db_path, 2, 5).unwrap();
+    let insert = |db: &mut timeseries_db::SunnyDB<PowerValues>, i: u64| {
+        db.insert_value_at_time(
+            1717200000000 + i * 1000,
+            PowerValues {
+                power_pv: i as f64,
+                power_used: 1.0,
+            },
+        )
+    };
+
+    // persisting keeps the values in memory, so they're written again with the full segment
+    for i in 0..8 {
+        insert(&mut db, i);
+    }
+    db.lossy_persist();
+    for i in 8..16 {
+        insert(&mut db, i);
+    }
+    db.lossy_persist();
+    drop(db);
+    assert_eq!(all_files(Path::new(&data_path)).len(), 3);
+
+    let db = timeseries_db::SunnyDB::<PowerValues>::new(10, db_path, 2, 5).unwrap();
+    let mut files = all_files(Path::new(&data_path));
+    files.sort();
+    assert_eq!(files.len(), 2);
+    assert!(files[0].ends_with("1717200000000-1717200009000"));
+    assert!(files[1].ends_with("1717200010000-1717200015000"));
+
+    let values = db.get_all_values().unwrap().get_current_values();
+    assert_eq!(values.len(), 16);
+    for (i, (time, value)) in values.iter().enumerate() {
+        assert_eq!(*time, 1717200000000 + i as u64 * 1000);
+        assert_eq!(value.power_pv, i as f64);
+    }
+
+    std::fs::remove_dir_all(db_path).ok();
+}
+
+#[test]
+fn quality_flags_are_persisted() {
+    let db_path = "./tests/test-quality-flags";
+    let mut db = timeseries_db::SunnyDB::<PowerValues>::new(10, db_path, 2, 0).unwrap();
+    let qualities = [
+        Quality::Measured,
+        Quality::Interpolated,
+        Quality::Backfilled,
+        Quality::Suspect,
+    ];
+    for i in 0..10 {
+        db.time_series.insert_value_with_quality(
+            1717200000000 + i * 1000,
+            PowerValues {
+                power_pv: i as f64,
+                power_used: 1.0,
+            },
+            qualities[i as usize % 4],
+        );
+    }
+    db.start_new_segment().unwrap();
+
+    let values = db.get_all_values().unwrap();
+    let read_qualities: Vec<Quality> = values
+        .get_current_values_with_quality()
+        .into_iter()
+        .map(|(_, _, q)| q)
+        .collect();
+    assert_eq!(read_qualities[..4], qualities);
+
+    let summary = values.quality_summary();
+    assert_eq!(summary.measured, 3);
+    assert_eq!(summary.suspect, 2);
+    assert_eq!(summary.measured_fraction(), Some(0.3));
+
+    std::fs::remove_dir_all(db_path).ok();
+}
+
+#[test]
+fn timestamp_resolution() {
+    let db_path = "./tests/test-resolution";
+    let mut db = timeseries_db::SunnyDB::<PowerValues>::new(10, db_path, 2, 0)
+        .unwrap()
+        .with_resolution(Resolution::Seconds);
+    for i in 0..10 {
+        db.insert_value_at_time(
+            1717200000 + i * 60,
+            PowerValues {
+                power_pv: i as f64,
+                power_used: 1.0,
+            },
+        );
+    }
+
+    // file names are always in ms
+    let files = all_files(Path::new(&format!("{}/data", db_path)));
+    assert_eq!(files.len(), 1);
+    assert!(files[0].ends_with("2024/06/01/1717200000000-1717200540000"));
+
+    let values = db.get_values_in_range(1717199999, 1717200300).unwrap();
+    assert_eq!(values.get_resolution(), Resolution::Seconds);
+    assert_eq!(values.len(), 6);
+    assert_eq!(values.get_end_time(), Some(1717200300));
+    drop(db);
+
+    // databases using another resolution convert the values when reading them
+    let db = timeseries_db::SunnyDB::<PowerValues>::open_read_only(db_path)
+        .unwrap()
+        .with_resolution(Resolution::Microseconds);
+    let values = db
+        .get_values_in_range(1717199999000000, 1717200300000000)
+        .unwrap();
+    assert_eq!(values.len(), 6);
+    assert_eq!(values.get_end_time(), Some(1717200300000000));
+
+    assert_eq!(
+        Resolution::Microseconds.to_millis(1717200000123456),
+        1717200000123
+    );
+    assert_eq!(Resolution::Seconds.from_millis(1717200000999), 1717200000);
+
+    std::fs::remove_dir_all(db_path).ok();
+}
+
+#[test]
+fn oldest_values_are_dropped_when_segments_cannot_be_written() {
+    let db_path = "./tests/test-in-memory-cap";
+    let mut db = timeseries_db::SunnyDB::<PowerValues>::new(5, db_path, 2, 0)
+        .unwrap()
+        .with_max_in_memory_points(8)
+        .unwrap();
+
+    // the partition can't be created as there's a file in its place
+    std::fs::write(format!("{}/data/2024", db_path), []).unwrap();
+    let insert = |db: &mut timeseries_db::SunnyDB<PowerValues>, i: u64| {
+        db.insert_value_at_time(
+            1717200000000 + i * 1000,
+            PowerValues {
+                power_pv: i as f64,
+                power_used: 1.0,
+            },
+        )
+    };
+    for i in 0..12 {
+        insert(&mut db, i);
+    }
+    assert_eq!(db.time_series.len(), 8);
+    assert_eq!(db.time_series.get_start_time(), Some(1717200004000));
+    assert_eq!(db.dropped_points(), 4);
+    assert_eq!(db.failed_exports(), 8);
+
+    // once writing works again, everything left in memory ends up in a single segment
+    std::fs::remove_file(format!("{}/data/2024", db_path)).unwrap();
+    insert(&mut db, 12);
+    assert!(db.time_series.is_empty());
+    let files = all_files(Path::new(&format!("{}/data", db_path)));
+    assert_eq!(files.len(), 1);
+    assert!(files[0].ends_with("1717200004000-1717200012000"));
+
+    std::fs::remove_dir_all(db_path).ok();
+}
+
+#[test]
+fn oldest_segments_are_pruned() {
+    let db_path = "./tests/test-prune-oldest";
+    std::fs::remove_dir_all(db_path).ok();
+    let mut db = timeseries_db::SunnyDB::<PowerValues>::new(10, db_path, 2, 0).unwrap();
+    // four segments on two days
+    for i in 0..40 {
+        let day = i / 20 * 86400000;
+        db.insert_value_at_time(
+            1717200000000 + day + i * 1000,
+            PowerValues {
+                power_pv: i as f64,
+                power_used: 1.0,
+            },
+        );
+    }
+    let segments = all_files(&Path::new(db_path).join("data"));
+    assert_eq!(segments.len(), 4);
+    let sizes: Vec<u64> = segments
+        .iter()
+        .map(|s| std::fs::metadata(s).unwrap().len())
+        .collect();
+    assert_eq!(db.disk_usage().unwrap(), sizes.iter().sum::<u64>());
+
+    // every segment frees at least a byte, so one is deleted at a time, the oldest first
+    let usage = db.disk_usage().unwrap();
+    let freed = db.prune_oldest(1).unwrap();
+    assert_eq!(db.disk_usage().unwrap(), usage - freed);
+    db.prune_oldest(1).unwrap();
+    // the emptied partition goes with its segments
+    assert!(!Path::new(db_path).join("data/2024/06/01").exists());
+    db.prune_oldest(1).unwrap();
+    assert_eq!(all_files(&Path::new(db_path).join("data")).len(), 1);
+    let values = db.get_all_values().unwrap();
+    assert_eq!(
+        values.get_start_time(),
+        Some(1717200000000 + 86400000 + 30000)
+    );
+
+    std::fs::remove_dir_all(db_path).ok();
+}
+
+#[test]
+fn manifest_records_segment_changes() {
+    use sunny_db::manifest::Change;
+
+    let db_path = "./tests/test-manifest";
+    std::fs::remove_dir_all(db_path).ok();
+    let mut db = timeseries_db::SunnyDB::<PowerValues>::new(10, db_path, 2, 0).unwrap();
+    assert!(db.manifest_since(0).unwrap().is_empty());
+    for i in 0..30 {
+        db.insert_value_at_time(
+            1717200000000 + i * 1000,
+            PowerValues {
+                power_pv: i as f64,
+                power_used: 1.0,
+            },
+        );
+    }
+    let added = db.manifest_since(0).unwrap();
+    assert_eq!(added.len(), 3);
+    assert!(added.iter().all(|e| e.change == Change::Added));
+    let file = db.segment_file(added[0].segment).unwrap();
+    assert_eq!(std::fs::metadata(file).unwrap().len(), added[0].size);
+
+    // only the changes after the given entry are returned
+    db.prune_oldest(1).unwrap();
+    let changes = db.manifest_since(added[2].seq).unwrap();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0].change, Change::Removed);
+    assert_eq!(changes[0].segment, added[0].segment);
+    assert!(db.segment_file(added[0].segment).is_none());
+
+    // reopening doesn't record anything new and readers see the same manifest
+    drop(db);
+    let db = timeseries_db::SunnyDB::<PowerValues>::new(10, db_path, 2, 0).unwrap();
+    assert_eq!(db.manifest_since(0).unwrap().len(), 4);
+    let reader = timeseries_db::SunnyDB::<PowerValues>::open_read_only(db_path).unwrap();
+    assert_eq!(reader.manifest_since(3).unwrap(), changes);
+
+    std::fs::remove_dir_all(db_path).ok();
+}
+
+#[test]
+fn imported_series_are_merged_with_existing_segments() {
+    let db_path = "./tests/test-import-series";
+    std::fs::remove_dir_all(db_path).ok();
+    let mut db = timeseries_db::SunnyDB::<PowerValues>::new(10, db_path, 2, 0).unwrap();
+    let value = |i: u64| PowerValues {
+        power_pv: i as f64,
+        power_used: 1.0,
+    };
+    for i in 0..10 {
+        db.insert_value_at_time(1717200000000 + i * 1000, value(i));
+    }
+
+    // backfilled values before and among the measured ones
+    let mut backfill = sunny_db::timeseries::TimeSeries::<PowerValues>::new(0);
+    for i in 0..25 {
+        let time = 1717200000000 - 15000 + i * 1000;
+        backfill.insert_value_with_quality(time, value(i), Quality::Backfilled);
+    }
+    assert_eq!(db.import_series(&backfill).unwrap(), 3);
+
+    let values = db.get_all_values().unwrap();
+    assert_eq!(values.len(), 25);
+    // values at the same time as existing ones don't replace them
+    let summary = values.quality_summary();
+    assert_eq!(summary.measured, 10);
+    assert_eq!(summary.backfilled, 15);
+
+    std::fs::remove_dir_all(db_path).ok();
+}
+
+#[test]
+fn segments_are_encrypted_with_a_key() {
+    let db_path = "./tests/test-encryption";
+    let key = || EncryptionKey::from_bytes(&[42; 32]).unwrap();
+    let insert = |db: &mut timeseries_db::SunnyDB<PowerValues>, i: u64| {
+        db.insert_value_at_time(
+            1717200000000 + i * 1000,
+            PowerValues {
+                power_pv: i as f64,
+                power_used: 1.0,
+            },
+        )
+    };
+    // a segment written before encryption was enabled
+    let mut db = timeseries_db::SunnyDB::<PowerValues>::new(10, db_path, 2, 0).unwrap();
+    (0..10).for_each(|i| insert(&mut db, i));
+    drop(db);
+
+    let mut db = timeseries_db::SunnyDB::<PowerValues>::new(10, db_path, 2, 0)
+        .unwrap()
+        .with_encryption_key(key())
+        .unwrap();
+    (10..30).for_each(|i| insert(&mut db, i));
+    let mut files = all_files(Path::new(&format!("{}/data", db_path)));
+    files.sort();
+    // the cipher is recorded in the header after the magic bytes, version, resolution and codec
+    let ciphers: Vec<u8> = files.iter().map(|f| std::fs::read(f).unwrap()[7]).collect();
+    assert_eq!(ciphers, vec![0, 1, 1]);
+    assert_eq!(db.get_all_values().unwrap().len(), 30);
+
+    // without the key, only the unencrypted segment can be read, but checksums can be verified
+    let reader = timeseries_db::SunnyDB::<PowerValues>::open_read_only(db_path).unwrap();
+    assert_eq!(reader.get_all_values().unwrap().len(), 10);
+    assert!(files
+        .iter()
+        .all(|f| checksum_matches(&std::fs::read(f).unwrap()) == Some(true)));
+    assert!(!reader.verify().is_ok());
+    let wrong_key = EncryptionKey::from_bytes(&[7; 32]).unwrap();
+    let reader = reader.with_encryption_key(wrong_key).unwrap();
+    assert_eq!(reader.get_all_values().unwrap().len(), 10);
+    let reader = timeseries_db::SunnyDB::<PowerValues>::open_read_only(db_path)
+        .unwrap()
+        .with_encryption_key(key())
+        .unwrap();
+    assert!(reader.verify().is_ok());
+
+    // rewriting segments encrypts them
+    assert_eq!(db.archive(u64::MAX, 2).unwrap(), 3);
+    let ciphers: Vec<u8> = files.iter().map(|f| std::fs::read(f).unwrap()[7]).collect();
+    assert_eq!(ciphers, vec![1, 1, 1]);
+    assert_eq!(reader.get_all_values().unwrap().len(), 30);
+
+    drop(db);
+    std::fs::remove_dir_all(db_path).ok();
+}
+
+#[test]
+fn insert_many_writes_at_most_one_segment() {
+    let db_path = "./tests/test-insert-many";
+    std::fs::remove_dir_all(db_path).ok();
+    let mut db = timeseries_db::SunnyDB::<PowerValues>::new(10, db_path, 2, 0).unwrap();
+    let values = |from: u64, to: u64| {
+        (from..to).map(|i| {
+            (
+                1717200000000 + i * 1000,
+                PowerValues {
+                    power_pv: i as f64,
+                    power_used: 1.0,
+                },
+            )
+        })
+    };
+
+    // fewer values than a segment holds stay in memory
+    assert_eq!(db.insert_many(values(0, 5)), 5);
+    assert_eq!(db.time_series.len(), 5);
+    assert!(all_files(Path::new(&format!("{}/data", db_path))).is_empty());
+
+    // all values in memory end up in a single segment, even if it's larger than usual
+    assert_eq!(db.insert_many(values(5, 35)), 30);
+    assert!(db.time_series.is_empty());
+    let files = all_files(Path::new(&format!("{}/data", db_path)));
+    assert_eq!(files.len(), 1);
+    assert!(files[0].ends_with("2024/06/01/1717200000000-1717200034000"));
+    let all = db.get_all_values().unwrap();
+    assert_eq!(all.len(), 35);
+    assert_eq!(all.get_current_values()[34].1.power_pv, 34.0);
+
+    std::fs::remove_dir_all(db_path).ok();
+}
+
+#[test]
+fn values_are_read_in_pages() {
+    let db_path = "./tests/test-paged-reads";
+    std::fs::remove_dir_all(db_path).ok();
+    let mut db = timeseries_db::SunnyDB::<PowerValues>::new(10, db_path, 2, 0).unwrap();
+    // three segments and five values in memory
+    for i in 0..35 {
+        db.insert_value_at_time(
+            1717200000000 + i * 1000,
+            PowerValues {
+                power_pv: i as f64,
+                power_used: 1.0,
+            },
+        );
+    }
+    let all = db.get_all_values().unwrap().get_current_values();
+
+    let mut paged = Vec::new();
+    let mut cursor = None;
+    let mut pages = 0;
+    loop {
+        let page = db
+            .get_values_in_range_paged(0, u64::MAX, 8, cursor)
+            .unwrap();
+        assert!(page.values.len() <= 8);
+        paged.extend(page.values.get_current_values());
+        pages += 1;
+        cursor = page.next;
+        if cursor.is_none() {
+            break;
+        }
+    }
+    assert_eq!(pages, 5);
+    assert_eq!(paged, all);
+
+    // segments after the page aren't read, so a broken one doesn't matter
+    let later_segment = all_files(Path::new(&format!("{}/data", db_path)))
+        .into_iter()
+        .find(|f| f.ends_with("1717200020000-1717200029000"))
+        .unwrap();
+    std::fs::write(&later_segment, [0; 16]).unwrap();
+    let first = db.get_values_in_range_paged(0, u64::MAX, 8, None).unwrap();
+    assert_eq!(first.values.get_current_values(), all[..8]);
+    assert_eq!(first.next, Some(1717200007000));
+    assert_eq!(db.get_all_values().unwrap().len(), 25);
+
+    std::fs::remove_dir_all(db_path).ok();
+}
+
+#[test]
+fn values_in_memory_and_persisted_are_read_once() {
+    let db_path = "./tests/test-memory-disk-overlap";
+    std::fs::remove_dir_all(db_path).ok();
+    let mut db = timeseries_db::SunnyDB::<PowerValues>::new(100, db_path, 2, 5).unwrap();
+    for i in 0..12 {
+        db.insert_value_at_time(
+            1717200000000 + i * 1000,
+            PowerValues {
+                power_pv: i as f64,
+                power_used: 1.0,
+            },
+        );
+        if i == 7 {
+            // the persisted segment holds the first values, which stay in memory as well
+            db.lossy_persist();
+        }
+    }
+
+    let values = db.get_values_in_range(0, 1717200011000).unwrap();
+    assert_eq!(values.len(), 12);
+    let times: Vec<u64> = values.iter().map(|(time, _)| time).collect();
+    assert!(
+        times.windows(2).all(|pair| pair[0] < pair[1]),
+        "{:?}",
+        times
+    );
+    assert_eq!(db.get_all_values().unwrap().len(), 12);
+    let page = db
+        .get_values_in_range_paged(0, 1717200011000, 100, None)
+        .unwrap();
+    assert_eq!(page.values.len(), 12);
+
+    std::fs::remove_dir_all(db_path).ok();
+}
+
+#[test]
+fn memory_usage_follows_the_segment_size() {
+    let db_path = "./tests/test-memory-usage";
+    std::fs::remove_dir_all(db_path).ok();
+    let small = timeseries_db::SunnyDB::<PowerValues>::new(10, db_path, 2, 0).unwrap();
+    let usage = small.memory_usage();
+    assert_eq!(usage.remote_index, 0);
+    // the metrics of the write path, without running statistics
+    assert!(usage.statistics > 0);
+    assert_eq!(usage.total(), usage.series + usage.statistics);
+    drop(small);
+
+    // the capacity for a whole segment is reserved up front
+    let mut db = timeseries_db::SunnyDB::<PowerValues>::new(1000, db_path, 2, 0).unwrap();
+    let reserved = db.memory_usage().series;
+    assert!(reserved >= usage.series + 990 * std::mem::size_of::<PowerValues>());
+    for i in 0..10 {
+        db.insert_value_at_time(
+            1717200000000 + i * 1000,
+            PowerValues {
+                power_pv: i as f64,
+                power_used: 1.0,
+            },
+        );
+    }
+    assert_eq!(db.memory_usage().series, reserved);
+    assert_eq!(db.memory_usage().series, db.time_series.memory_usage());
+
+    std::fs::remove_dir_all(db_path).ok();
+}
+
+#[test]
+fn write_metrics_cover_inserts_and_segments() {
+    let db_path = "./tests/test-write-metrics";
+    std::fs::remove_dir_all(db_path).ok();
+    let mut db = timeseries_db::SunnyDB::<PowerValues>::new(10, db_path, 2, 0).unwrap();
+    for i in 0..25 {
+        db.insert_value_at_time(
+            1717200000000 + i * 1000,
+            PowerValues {
+                power_pv: i as f64,
+                power_used: 1.0,
+            },
+        );
+    }
+    db.flush().unwrap();
+
+    let metrics = db.write_metrics();
+    assert_eq!(metrics.insert_us.count, 25);
+    assert_eq!(metrics.insert_us.counts.iter().sum::<u64>(), 25);
+    // two full segments and the flushed rest
+    assert_eq!(metrics.flush_ms.count, 3);
+    assert_eq!(metrics.encode_ms.count, 3);
+    assert!(metrics.encode_ms.sum <= metrics.flush_ms.sum);
+    let on_disk: u64 = all_files(&Path::new(db_path).join("data"))
+        .iter()
+        .map(|segment| segment.metadata().unwrap().len())
+        .sum();
+    assert_eq!(metrics.segment_bytes.sum, on_disk as f64);
+
+    drop(db);
+    std::fs::remove_dir_all(db_path).ok();
+}
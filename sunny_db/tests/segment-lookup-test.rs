@@ -0,0 +1,173 @@
+use sunny_db::timeseries::Resolution;
+use sunny_db::timeseries_db::SunnyDB;
+
+/// 2024-06-01 00:00 UTC
+const MIDNIGHT: u64 = 1717200000000;
+
+/// a database at the path holding a segment per range of times in ms, each with a value every
+/// second from its start to its end
+fn db_with_segments(db_path: &str, segments: &[(u64, u64)]) -> SunnyDB<f64> {
+    std::fs::remove_dir_all(db_path).ok();
+    let mut db = SunnyDB::<f64>::new(10_000, db_path, 2, 0).unwrap();
+    for (start, end) in segments {
+        for time in (*start..=*end).step_by(1000) {
+            db.time_series.insert_value_at_time(time, 1.0);
+        }
+        db.start_new_segment().unwrap();
+    }
+    db
+}
+
+#[test]
+fn single_segment_boundaries() {
+    let db_path = "./tests/test-lookup-single";
+    let segment = (MIDNIGHT + 10_000, MIDNIGHT + 20_000);
+    let db = db_with_segments(db_path, &[segment]);
+
+    let covering = |start: u64, end: u64| db.segments_covering(start, end);
+    // before and after the segment
+    assert!(covering(MIDNIGHT, MIDNIGHT + 9_999).is_empty());
+    assert!(covering(MIDNIGHT + 20_001, MIDNIGHT + 30_000).is_empty());
+    // ranges ending at its first or starting at its last value hit it
+    assert_eq!(covering(MIDNIGHT, MIDNIGHT + 10_000), vec![segment]);
+    assert_eq!(
+        covering(MIDNIGHT + 20_000, MIDNIGHT + 30_000),
+        vec![segment]
+    );
+    // within it, around it and a single point
+    assert_eq!(
+        covering(MIDNIGHT + 12_000, MIDNIGHT + 13_000),
+        vec![segment]
+    );
+    assert_eq!(covering(0, u64::MAX / 2), vec![segment]);
+    assert_eq!(
+        covering(MIDNIGHT + 15_000, MIDNIGHT + 15_000),
+        vec![segment]
+    );
+    // switched ends are taken as the same range
+    assert_eq!(covering(MIDNIGHT + 30_000, MIDNIGHT), vec![segment]);
+    assert_eq!(db.segments_in_range(MIDNIGHT + 30_000, MIDNIGHT), 1);
+
+    drop(db);
+    std::fs::remove_dir_all(db_path).ok();
+}
+
+#[test]
+fn queries_between_segments() {
+    let db_path = "./tests/test-lookup-between";
+    let first = (MIDNIGHT + 10_000, MIDNIGHT + 20_000);
+    let second = (MIDNIGHT + 40_000, MIDNIGHT + 50_000);
+    let db = db_with_segments(db_path, &[first, second]);
+
+    let covering = |start: u64, end: u64| db.segments_covering(start, end);
+    // the gap between them
+    assert!(covering(MIDNIGHT + 20_001, MIDNIGHT + 39_999).is_empty());
+    assert!(db
+        .get_values_in_range(MIDNIGHT + 20_001, MIDNIGHT + 39_999)
+        .is_none_or(|values| values.is_empty()));
+    // from the end of one to the start of the other
+    assert_eq!(
+        covering(MIDNIGHT + 20_000, MIDNIGHT + 40_000),
+        vec![first, second]
+    );
+    assert_eq!(covering(MIDNIGHT + 30_000, MIDNIGHT + 40_000), vec![second]);
+    assert_eq!(covering(MIDNIGHT + 20_000, MIDNIGHT + 30_000), vec![first]);
+    assert_eq!(covering(MIDNIGHT, MIDNIGHT + 60_000), vec![first, second]);
+    assert_eq!(db.segments_in_range(MIDNIGHT, MIDNIGHT + 60_000), 2);
+
+    // what's read for the boundary hits is exactly what's within the range
+    let values = db
+        .get_values_in_range(MIDNIGHT + 19_000, MIDNIGHT + 40_000)
+        .unwrap();
+    assert_eq!(values.get_start_time(), Some(MIDNIGHT + 20_000));
+    assert_eq!(values.get_end_time(), Some(MIDNIGHT + 40_000));
+    assert_eq!(values.len(), 2);
+
+    drop(db);
+    std::fs::remove_dir_all(db_path).ok();
+}
+
+#[test]
+fn several_ranges_at_once() {
+    let db_path = "./tests/test-lookup-ranges";
+    let first = (MIDNIGHT + 10_000, MIDNIGHT + 20_000);
+    let second = (MIDNIGHT + 40_000, MIDNIGHT + 50_000);
+    let db = db_with_segments(db_path, &[first, second]);
+
+    // within the same segment, overlapping, switched, in the gap and in another segment
+    let ranges = [
+        (MIDNIGHT + 15_000, MIDNIGHT + 17_000),
+        (MIDNIGHT + 10_000, MIDNIGHT + 12_000),
+        (MIDNIGHT + 11_000, MIDNIGHT + 13_000),
+        (MIDNIGHT + 49_000, MIDNIGHT + 45_000),
+        (MIDNIGHT + 25_000, MIDNIGHT + 30_000),
+        (MIDNIGHT + 45_000, MIDNIGHT + 50_000),
+    ];
+    let batch = db.get_values_in_ranges(&ranges);
+    assert_eq!(batch.len(), ranges.len());
+    for ((start, end), values) in ranges.iter().zip(&batch) {
+        let single = db
+            .get_values_in_range(*start, *end)
+            .filter(|v| !v.is_empty());
+        assert_eq!(
+            values.as_ref().map(|v| v.get_current_values()),
+            single.map(|v| v.get_current_values())
+        );
+    }
+    assert_eq!(batch[0].as_ref().unwrap().len(), 2);
+    assert!(batch[4].is_none());
+    assert_eq!(batch[5].as_ref().unwrap().len(), 5);
+    assert!(db.get_values_in_ranges(&[]).is_empty());
+
+    drop(db);
+    std::fs::remove_dir_all(db_path).ok();
+}
+
+#[test]
+fn segments_reaching_into_later_days() {
+    let db_path = "./tests/test-lookup-days";
+    // stored in the partition of the day before, but holding values of the next one
+    let overnight = (MIDNIGHT - 5_000, MIDNIGHT + 5_000);
+    let next_day = (MIDNIGHT + 86_400_000, MIDNIGHT + 86_410_000);
+    let db = db_with_segments(db_path, &[overnight, next_day]);
+
+    let covering = |start: u64, end: u64| db.segments_covering(start, end);
+    assert_eq!(
+        covering(MIDNIGHT + 1_000, MIDNIGHT + 2_000),
+        vec![overnight]
+    );
+    assert_eq!(
+        covering(MIDNIGHT + 5_000, MIDNIGHT + 86_400_000),
+        vec![overnight, next_day]
+    );
+    assert!(covering(MIDNIGHT + 5_001, MIDNIGHT + 86_399_999).is_empty());
+    assert_eq!(
+        covering(MIDNIGHT - 10_000, MIDNIGHT - 5_000),
+        vec![overnight]
+    );
+
+    drop(db);
+    std::fs::remove_dir_all(db_path).ok();
+}
+
+#[test]
+fn stores_without_segments_and_other_resolutions() {
+    let db_path = "./tests/test-lookup-empty";
+    let db = db_with_segments(db_path, &[]);
+    assert!(db.segments_covering(0, u64::MAX / 2).is_empty());
+    assert_eq!(db.segments_in_range(0, u64::MAX / 2), 0);
+    drop(db);
+
+    // the range is given in the database's unit, the segments are in ms like their names
+    let segment = (MIDNIGHT, MIDNIGHT + 10_000);
+    let db = db_with_segments(db_path, &[segment]).with_resolution(Resolution::Seconds);
+    let seconds = MIDNIGHT / 1000;
+    assert_eq!(
+        db.segments_covering(seconds + 10, seconds + 20),
+        vec![segment]
+    );
+    assert!(db.segments_covering(seconds + 11, seconds + 20).is_empty());
+
+    drop(db);
+    std::fs::remove_dir_all(db_path).ok();
+}
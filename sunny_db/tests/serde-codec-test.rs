@@ -0,0 +1,95 @@
+#![cfg(feature = "serde")]
+
+use serde::{Deserialize, Serialize};
+use sunny_db::timeseries::{DuplicatePolicy, Quality, Resolution, TimeSeries};
+use sunny_db::timeseries_db;
+
+#[derive(Copy, Clone, Serialize, Deserialize, PartialEq, Debug)]
+struct PowerValues {
+    power_pv: f64,
+    power_used: f64,
+}
+
+sunny_db::serde_codec!(PowerValues);
+
+#[derive(Copy, Clone, bitcode::Encode, bitcode::Decode, PartialEq, Debug)]
+struct BitcodeValues {
+    power_pv: f64,
+    power_used: f64,
+}
+
+#[test]
+fn values_are_stored_via_serde() {
+    let db_path = "./tests/test-serde-codec";
+    let mut db = timeseries_db::SunnyDB::<PowerValues>::new(10, db_path, 2, 0).unwrap();
+    for i in 0..25 {
+        db.insert_value_at_time(
+            1717200000000 + i * 1000,
+            PowerValues {
+                power_pv: i as f64,
+                power_used: 1.0,
+            },
+        );
+    }
+    drop(db);
+
+    let db = timeseries_db::SunnyDB::<PowerValues>::new(10, db_path, 2, 0).unwrap();
+    let values = db.get_all_values().unwrap().get_current_values();
+    assert_eq!(values.len(), 20);
+    assert_eq!(values[19].1.power_pv, 19.0);
+    drop(db);
+
+    // segments aren't decoded with another codec
+    let bitcode_db = timeseries_db::SunnyDB::<BitcodeValues>::open_read_only(db_path).unwrap();
+    assert!(!bitcode_db.verify().is_ok());
+
+    std::fs::remove_dir_all(db_path).ok();
+}
+
+#[test]
+fn segments_record_their_codec() {
+    let mut series = TimeSeries::<PowerValues>::new(2);
+    series.insert_value_at_time(
+        1,
+        PowerValues {
+            power_pv: 1.0,
+            power_used: 2.0,
+        },
+    );
+    let segment = series.to_compressed_json(2).unwrap();
+    assert_eq!(
+        TimeSeries::<PowerValues>::from_compressed_json(&segment).unwrap(),
+        series
+    );
+
+    let error = TimeSeries::<BitcodeValues>::from_compressed_json(&segment).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Segment was written using postcard but the values are stored using bitcode"
+    );
+}
+
+#[test]
+fn series_round_trip_as_json() {
+    let mut series = TimeSeries::<PowerValues>::with_resolution(2, Resolution::Seconds)
+        .with_duplicate_policy(DuplicatePolicy::BestQuality);
+    for (time, quality) in [
+        (1717200000, Quality::Measured),
+        (1717200010, Quality::Suspect),
+    ] {
+        let values = PowerValues {
+            power_pv: time as f64,
+            power_used: 1.0,
+        };
+        series.insert_value_with_quality(time, values, quality);
+    }
+
+    let json = serde_json::to_string(&series).unwrap();
+    let deserialized: TimeSeries<PowerValues> = serde_json::from_str(&json).unwrap();
+    assert_eq!(deserialized, series);
+    assert_eq!(deserialized.get_resolution(), Resolution::Seconds);
+    assert_eq!(
+        deserialized.get_duplicate_policy(),
+        DuplicatePolicy::BestQuality
+    );
+}
@@ -1,5 +1,6 @@
 use bitcode::{Decode, Encode};
 use rand::{thread_rng, Rng};
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 use sunny_db::timeseries_db;
 
@@ -18,7 +19,7 @@ fn stress_test() {
     let test_db_path = "./tests/stress-test-data";
 
     let mut tiny_db =
-        timeseries_db::SunnyDB::<PowerValues>::new(segment_size, &test_db_path, 2, 20);
+        timeseries_db::SunnyDB::<PowerValues>::new(segment_size, test_db_path, 2, 20).unwrap();
     let mut rng = thread_rng();
 
     let now = Instant::now();
@@ -71,7 +72,7 @@ fn stress_test() {
     let read_few_elapsed = now.elapsed().as_millis();
 
     assert!(few_values.is_some());
-    assert!(few_values.as_ref().unwrap().len() >= 1);
+    assert!(!few_values.as_ref().unwrap().is_empty());
 
     println!(
         "Elapsed time for reading {} values out of {}: {} ms",
@@ -114,7 +115,7 @@ fn stress_test() {
     );
 
     // clean up
-    std::fs::remove_dir_all(&test_db_path).ok();
+    std::fs::remove_dir_all(test_db_path).ok();
 }
 
 #[test]
@@ -122,7 +123,8 @@ fn test_data_loss() {
     let data_loss_path = "./tests/test-data-loss";
     let mut full_db_path = data_loss_path.to_owned();
     full_db_path.push_str("/data");
-    let mut tiny_db = timeseries_db::SunnyDB::<PowerValues>::new(10, &data_loss_path, 10, 5);
+    let mut tiny_db =
+        timeseries_db::SunnyDB::<PowerValues>::new(10, data_loss_path, 10, 5).unwrap();
 
     // write some values below loss threshold
     let mut rng = thread_rng();
@@ -140,10 +142,7 @@ fn test_data_loss() {
     tiny_db.lossy_persist();
     assert_eq!(tiny_db.time_series.len(), 4);
 
-    let files: Vec<std::fs::DirEntry> = std::fs::read_dir(&full_db_path)
-        .expect("Couldn't read data directory!")
-        .map(|entry| entry.unwrap())
-        .collect();
+    let files = segment_files(Path::new(&full_db_path));
 
     assert!(files.is_empty());
 
@@ -162,10 +161,7 @@ fn test_data_loss() {
     assert_eq!(tiny_db.time_series.len(), 8);
 
     // should have a single file now
-    let files: Vec<std::fs::DirEntry> = std::fs::read_dir(&full_db_path)
-        .expect("Couldn't read data directory!")
-        .map(|entry| entry.unwrap())
-        .collect();
+    let files = segment_files(Path::new(&full_db_path));
     assert_eq!(files.len(), 1);
 
     // write another set of values, should be dumped automatically now resulting in two files in total
@@ -178,21 +174,29 @@ fn test_data_loss() {
         std::thread::sleep(Duration::from_millis(10));
     }
 
-    let files: Vec<std::fs::DirEntry> = std::fs::read_dir(&full_db_path)
-        .expect("Couldn't read data directory!")
-        .map(|entry| entry.unwrap())
-        .collect();
+    let files = segment_files(Path::new(&full_db_path));
     assert_eq!(files.len(), 2);
 
     assert_eq!(tiny_db.time_series.len(), 2);
     tiny_db.lossy_persist();
     assert_eq!(tiny_db.time_series.len(), 2);
 
-    let files: Vec<std::fs::DirEntry> = std::fs::read_dir(&full_db_path)
-        .expect("Couldn't read data directory!")
-        .map(|entry| entry.unwrap())
-        .collect();
+    let files = segment_files(Path::new(&full_db_path));
     assert_eq!(files.len(), 2);
 
-    std::fs::remove_dir_all(&data_loss_path).ok();
+    std::fs::remove_dir_all(data_loss_path).ok();
+}
+
+/// segments are stored in a date-partitioned directory tree, so collect them recursively
+fn segment_files(path: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(path).expect("Couldn't read data directory!") {
+        let entry = entry.unwrap();
+        if entry.file_type().unwrap().is_dir() {
+            files.append(&mut segment_files(&entry.path()));
+        } else {
+            files.push(entry.path());
+        }
+    }
+    files
 }
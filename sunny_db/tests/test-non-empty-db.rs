@@ -1,4 +1,5 @@
 use bitcode::{Decode, Encode};
+use std::path::Path;
 use sunny_db::timeseries_db;
 
 #[derive(Copy, Clone, Encode, Decode, PartialEq, Debug)]
@@ -11,10 +12,20 @@ struct PowerValues {
 
 #[test]
 fn read_in_range_test() {
-    let test_db_path = "./tests/db-test";
+    // the fixture uses the old flat layout, which gets migrated on opening, so work on a copy
+    let test_db_path = "./tests/db-test-copy";
+    copy_dir(Path::new("./tests/db-test"), Path::new(test_db_path));
 
-    let tiny_db = timeseries_db::SunnyDB::<PowerValues>::new(200, &test_db_path, 2, 20);
+    let tiny_db = timeseries_db::SunnyDB::<PowerValues>::new(200, test_db_path, 2, 20).unwrap();
 
+    // all segments have been moved into their day's partition
+    let flat_segments = std::fs::read_dir("./tests/db-test-copy/data")
+        .unwrap()
+        .filter(|entry| entry.as_ref().unwrap().file_type().unwrap().is_file())
+        .count();
+    assert_eq!(flat_segments, 0);
+    assert!(Path::new("./tests/db-test-copy/data/2024/05/31/1717138113556-1717138608557").exists());
+    assert!(Path::new("./tests/db-test-copy/data/2024/05/24/1716560570868-1716560740859").exists());
 
     // case 1: start time in series, end time large than max time
     let start_time = 1717113600000;
@@ -81,4 +92,25 @@ fn read_in_range_test() {
 
     let end_time_series = read_values.get_end_time().unwrap();
     assert!(end_time_series <= end_time);
+
+    // case 7: range within a single day that has no segment starting on it
+    let start_time = 1716681600000; // 2024-05-26 00:00 UTC
+    let end_time = 1716768000000;
+
+    let read_values = tiny_db.get_values_in_range(start_time, end_time).unwrap();
+    assert!(read_values.is_empty());
+
+    std::fs::remove_dir_all(test_db_path).ok();
+}
+
+fn copy_dir(from: &Path, to: &Path) {
+    std::fs::create_dir_all(to).unwrap();
+    for entry in std::fs::read_dir(from).unwrap() {
+        let entry = entry.unwrap();
+        if entry.file_type().unwrap().is_dir() {
+            copy_dir(&entry.path(), &to.join(entry.file_name()));
+        } else {
+            std::fs::copy(entry.path(), to.join(entry.file_name())).unwrap();
+        }
+    }
 }
@@ -0,0 +1,227 @@
+use sunny_db::error::SunnyDbError;
+use sunny_db::statistics::*;
+use sunny_db::timeseries::{DuplicatePolicy, Quality, Resolution, TimeSeries};
+
+fn series(values: &[(u64, f64, Quality)]) -> TimeSeries<f64> {
+    let mut ts = TimeSeries::<f64>::new(values.len());
+    for (time, value, quality) in values {
+        ts.insert_value_with_quality(*time, *value, *quality);
+    }
+    ts
+}
+
+#[test]
+fn merge_interleaved_series() {
+    let measured = || {
+        series(&[
+            (1000, 1.0, Quality::Suspect),
+            (3000, 3.0, Quality::Measured),
+            (5000, 5.0, Quality::Measured),
+        ])
+    };
+    let backfilled = series(&[
+        (0, 0.0, Quality::Backfilled),
+        (1000, 10.0, Quality::Backfilled),
+        (2000, 20.0, Quality::Backfilled),
+        (5000, 50.0, Quality::Backfilled),
+        (6000, 60.0, Quality::Backfilled),
+    ]);
+
+    let mut merged = measured();
+    merged.merge(&backfilled);
+    assert_eq!(
+        merged.get_current_values(),
+        vec![
+            (0, 0.0),
+            (1000, 1.0),
+            (2000, 20.0),
+            (3000, 3.0),
+            (5000, 5.0),
+            (6000, 60.0)
+        ]
+    );
+    assert_eq!(merged.get_start_time(), Some(0));
+    assert_eq!(merged.get_end_time(), Some(6000));
+
+    let mut merged = measured().with_duplicate_policy(DuplicatePolicy::Replace);
+    merged.merge(&backfilled);
+    let values = merged.get_current_values_without_time();
+    assert_eq!(values, vec![0.0, 10.0, 20.0, 3.0, 50.0, 60.0]);
+
+    let mut merged = measured().with_duplicate_policy(DuplicatePolicy::BestQuality);
+    merged.merge(&backfilled);
+    let values = merged.get_current_values_without_time();
+    assert_eq!(values, vec![0.0, 10.0, 20.0, 3.0, 5.0, 60.0]);
+}
+
+#[test]
+fn merge_never_panics() {
+    // merging into an empty series takes over the other one's resolution
+    let mut seconds = TimeSeries::<f64>::with_resolution(2, Resolution::Seconds);
+    seconds.insert_value_at_time(2, 2.0);
+    let mut merged = TimeSeries::<f64>::empty();
+    merged.merge(&seconds);
+    assert_eq!(merged.get_resolution(), Resolution::Seconds);
+
+    // ... otherwise the other series is converted, regardless of the order of the series
+    let mut millis = series(&[
+        (1500, 1.5, Quality::Measured),
+        (9000, 9.0, Quality::Measured),
+    ]);
+    millis.merge(&seconds);
+    millis.merge(&TimeSeries::<f64>::empty());
+    assert_eq!(
+        millis.get_current_values(),
+        vec![(1500, 1.5), (2000, 2.0), (9000, 9.0)]
+    );
+}
+
+#[test]
+fn append_rejects_series_out_of_order() {
+    let mut ts = series(&[
+        (1000, 1.0, Quality::Measured),
+        (2000, 2.0, Quality::Measured),
+    ]);
+    let earlier = series(&[(500, 0.5, Quality::Measured)]);
+    assert!(matches!(
+        ts.append(&earlier),
+        Err(SunnyDbError::AppendOutOfOrder)
+    ));
+    let mut later_seconds = TimeSeries::<f64>::with_resolution(1, Resolution::Seconds);
+    later_seconds.insert_value_at_time(3000, 3.0);
+    assert!(matches!(
+        ts.append(&later_seconds),
+        Err(SunnyDbError::AppendResolutionMismatch)
+    ));
+    assert_eq!(ts.len(), 2);
+
+    ts.append(&series(&[(3000, 3.0, Quality::Measured)]))
+        .unwrap();
+    assert_eq!(ts.get_current_values_without_time(), vec![1.0, 2.0, 3.0]);
+    assert_eq!(ts.get_end_time(), Some(3000));
+}
+
+#[test]
+fn insertion_keeps_values_sorted() {
+    let mut ts = TimeSeries::<f64>::new(8);
+    for time in [5000, 1000, 3000, 0, 3000, 7000, 2000] {
+        ts.insert_value_at_time(time, time as f64);
+    }
+    let times: Vec<u64> = ts.get_current_values().iter().map(|(t, _)| *t).collect();
+    assert_eq!(times, vec![0, 1000, 2000, 3000, 3000, 5000, 7000]);
+    assert_eq!(ts.get_start_time(), Some(0));
+    assert_eq!(ts.get_end_time(), Some(7000));
+
+    // appended directly as long as the values follow the existing ones, inserted otherwise
+    ts.insert_many_sorted([(8000, 8.0), (9000, 9.0), (4000, 4.0), (10000, 10.0)]);
+    let times: Vec<u64> = ts.get_current_values().iter().map(|(t, _)| *t).collect();
+    assert_eq!(
+        times,
+        vec![0, 1000, 2000, 3000, 3000, 4000, 5000, 7000, 8000, 9000, 10000]
+    );
+    assert_eq!(ts.get_end_time(), Some(10000));
+    assert_eq!(ts.get_values_in_range(2000, 5000).unwrap().len(), 4);
+}
+
+#[test]
+fn iterate_and_collect() {
+    let ts: TimeSeries<f64> = [(3000, 3.0), (1000, 1.0), (2000, 2.0)]
+        .into_iter()
+        .collect();
+    assert_eq!(ts.len(), 3);
+    assert_eq!(ts.get_start_time(), Some(1000));
+
+    let times: Vec<u64> = ts.iter().map(|(time, _)| time).collect();
+    assert_eq!(times, vec![1000, 2000, 3000]);
+    let sum: f64 = (&ts).into_iter().map(|(_, v)| *v).sum();
+    assert_eq!(sum, 6.0);
+    assert_eq!(ts.iter().next_back(), Some((3000, &3.0)));
+    assert_eq!(ts.iter().len(), 3);
+
+    let doubled: TimeSeries<f64> = ts.into_iter().map(|(t, v)| (t, 2.0 * v)).collect();
+    assert_eq!(
+        doubled.get_current_values(),
+        vec![(1000, 2.0), (2000, 4.0), (3000, 6.0)]
+    );
+}
+
+#[test]
+fn views_match_copied_ranges() {
+    let ts: TimeSeries<f64> = (0..100).map(|i| (i * 1000, (i % 7) as f64)).collect();
+
+    let view = ts.view_range(10000, 50000).unwrap();
+    let copy = ts.get_values_in_range(10000, 50000).unwrap();
+    assert_eq!(view.len(), copy.len());
+    assert_eq!(view.get_start_time(), copy.get_start_time());
+    assert_eq!(view.get_end_time(), copy.get_end_time());
+    assert_eq!(view.get_current_values(), copy.get_current_values());
+    assert_eq!(view.to_series(), copy);
+
+    assert_eq!(view.integrate(), copy.integrate());
+    assert_eq!(view.average(), copy.average());
+    assert_eq!(view.max_by(cmp_nan_first), copy.max_by(cmp_nan_first));
+    assert_eq!(view.quality_summary(), copy.quality_summary());
+
+    assert_eq!(ts.view().len(), ts.len());
+    assert!(TimeSeries::<f64>::empty().view_range(0, 1000).is_none());
+    assert_eq!(TimeSeries::<f64>::empty().view().integrate(), None);
+}
+
+#[test]
+fn ranges_after_all_values_are_empty() {
+    let ts: TimeSeries<f64> = (1..=5).map(|i| (i * 1000, i as f64)).collect();
+    assert!(ts.get_values_in_range(5000, 9000).is_none());
+    assert!(ts.get_values_in_range(6000, 9000).is_none());
+    assert_eq!(ts.get_values_in_range(4999, 9000).unwrap().len(), 1);
+}
+
+#[test]
+fn split_and_truncate() {
+    let ts = || -> TimeSeries<f64> { (1..=5).map(|i| (i * 1000, i as f64)).collect() };
+    let (earlier, later) = ts().split_at_time(3000);
+    assert_eq!(earlier.get_current_values(), vec![(1000, 1.0), (2000, 2.0)]);
+    assert_eq!(earlier.get_end_time(), Some(2000));
+    assert_eq!(later.get_start_time(), Some(3000));
+    assert_eq!(later.len(), 3);
+
+    let (all, none) = ts().split_at_time(9000);
+    assert_eq!(all, ts());
+    assert!(none.is_empty());
+    assert_eq!(none.get_start_time(), None);
+
+    let mut truncated = ts();
+    truncated.truncate_before(2500);
+    truncated.truncate_after(4000);
+    assert_eq!(
+        truncated.get_current_values(),
+        vec![(3000, 3.0), (4000, 4.0)]
+    );
+    truncated.truncate_after(0);
+    assert!(truncated.is_empty());
+    assert_eq!(truncated.get_end_time(), None);
+}
+
+#[test]
+fn rolling_windows_evict_old_values() {
+    let mut last_three = TimeSeries::<f64>::new(3).with_max_len(3);
+    for i in 1..=5 {
+        last_three.insert_value_at_time(i * 1000, i as f64);
+    }
+    assert_eq!(
+        last_three.get_current_values(),
+        vec![(3000, 3.0), (4000, 4.0), (5000, 5.0)]
+    );
+    assert_eq!(last_three.get_start_time(), Some(3000));
+
+    // values up to a minute older than the newest one are kept
+    let mut last_minute = TimeSeries::<f64>::new(0).with_max_age(60_000);
+    last_minute.insert_many_sorted((0..10).map(|i| (i * 20_000, i as f64)));
+    assert_eq!(last_minute.len(), 4);
+    assert_eq!(last_minute.get_start_time(), Some(120_000));
+    // late values older than the window are dropped right away
+    last_minute.insert_value_at_time(100_000, -1.0);
+    assert_eq!(last_minute.len(), 4);
+
+    let in_seconds = last_minute.to_resolution(Resolution::Seconds);
+    assert_eq!(in_seconds.get_window().max_age, Some(60));
+}
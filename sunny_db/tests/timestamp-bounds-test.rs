@@ -0,0 +1,122 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use sunny_db::error::SunnyDbError;
+use sunny_db::timeseries::{Resolution, TimeSeries, TimestampBounds, UnixTimestamp};
+use sunny_db::timeseries_db::SunnyDB;
+
+/// 2024-06-01 00:00 UTC
+const MIDNIGHT: u64 = 1717200000000;
+/// 3000-01-01 00:00 UTC
+const YEAR_3000: u64 = 32503680000000;
+
+fn bounded_db(db_path: &str) -> SunnyDB<f64> {
+    std::fs::remove_dir_all(db_path).ok();
+    SunnyDB::<f64>::new(10, db_path, 2, 0)
+        .unwrap()
+        .with_timestamp_bounds(TimestampBounds::default())
+        .unwrap()
+}
+
+#[test]
+fn inserts_outside_of_the_bounds_are_rejected() {
+    let db_path = "./tests/test-bounds-inserts";
+    let mut db = bounded_db(db_path);
+    db.insert_value_at_time(MIDNIGHT, 1.0);
+    db.insert_value_at_time(0, 2.0);
+    db.insert_value_at_time(YEAR_3000, 3.0);
+    assert!(db.insert_value_at_current_time(4.0).is_some());
+    assert_eq!(db.time_series.len(), 2);
+    assert_eq!(db.rejected_points(), 2);
+
+    let inserted = db.insert_many([(5, 5.0), (MIDNIGHT + 1000, 6.0), (u64::MAX, 7.0)]);
+    assert_eq!(inserted, 1);
+    assert_eq!(db.rejected_points(), 4);
+    let times: Vec<u64> = db.time_series.iter().map(|(time, _)| time).collect();
+    assert_eq!(times.len(), 3);
+    assert_eq!(times[..2], [MIDNIGHT, MIDNIGHT + 1000]);
+
+    drop(db);
+    std::fs::remove_dir_all(db_path).ok();
+}
+
+#[test]
+fn inserts_at_the_current_time_return_it() {
+    let db_path = "./tests/test-bounds-current-time";
+    let mut db = bounded_db(db_path);
+    // the last one fills the segment, which leaves the series in memory empty
+    for i in 0..10 {
+        let time = db.insert_value_at_current_time(i as f64);
+        assert!(time.is_some_and(|time| time >= MIDNIGHT));
+    }
+    assert!(db.time_series.is_empty());
+
+    drop(db);
+    std::fs::remove_dir_all(db_path).ok();
+}
+
+#[test]
+fn imports_outside_of_the_bounds_fail() {
+    let db_path = "./tests/test-bounds-import";
+    let mut db = bounded_db(db_path);
+    let mut series = TimeSeries::<f64>::new(2);
+    series.insert_value_at_time(MIDNIGHT, 1.0);
+    series.insert_value_at_time(YEAR_3000, 2.0);
+    let error = db.import_series(&series).unwrap_err();
+    assert!(matches!(
+        error.downcast_ref::<SunnyDbError>(),
+        Some(SunnyDbError::TimestampOutOfBounds {
+            time: YEAR_3000,
+            ..
+        })
+    ));
+    assert!(db.segments_covering(0, u64::MAX / 2).is_empty());
+
+    // the bounds are in ms, whatever the unit of the imported values
+    let mut seconds = TimeSeries::<f64>::with_resolution(2, Resolution::Seconds);
+    seconds.insert_value_at_time(MIDNIGHT / 1000, 1.0);
+    assert_eq!(db.import_series(&seconds).unwrap(), 1);
+
+    // segments of another database
+    let source_path = "./tests/test-bounds-import-source";
+    std::fs::remove_dir_all(source_path).ok();
+    let mut source = SunnyDB::<f64>::new(10, source_path, 2, 0).unwrap();
+    source.insert_value_at_time(YEAR_3000, 1.0);
+    source.start_new_segment().unwrap();
+    drop(source);
+    assert!(db.import_from(source_path).is_err());
+
+    drop(db);
+    std::fs::remove_dir_all(db_path).ok();
+    std::fs::remove_dir_all(source_path).ok();
+}
+
+#[test]
+fn bounds_and_clocks_before_the_epoch() {
+    let bounds = TimestampBounds::default();
+    assert!(bounds.contains(MIDNIGHT));
+    assert!(!bounds.contains(YEAR_3000));
+    // values are accepted up to the start of the latest day
+    assert!(bounds.contains(bounds.min_ms));
+    assert!(bounds.contains(bounds.max_ms - 1));
+    assert!(!bounds.contains(bounds.max_ms));
+    assert!(bounds.check(0).is_err());
+
+    let db_path = "./tests/test-bounds-invalid";
+    std::fs::remove_dir_all(db_path).ok();
+    let inverted = TimestampBounds {
+        min_ms: MIDNIGHT,
+        max_ms: 0,
+    };
+    let db = SunnyDB::<f64>::new(10, db_path, 2, 0).unwrap();
+    assert!(matches!(
+        db.with_timestamp_bounds(inverted),
+        Err(SunnyDbError::InvalidTimestampBounds { .. })
+    ));
+    std::fs::remove_dir_all(db_path).ok();
+
+    let before_epoch = UNIX_EPOCH - Duration::from_secs(1);
+    assert!(matches!(
+        before_epoch.timestamp(),
+        Err(SunnyDbError::BeforeEpoch)
+    ));
+    assert!(SystemTime::now().timestamp().unwrap() > MIDNIGHT);
+}
@@ -0,0 +1,92 @@
+use bitcode::{Decode, Encode};
+use std::fs;
+use std::path::Path;
+use sunny_db::timeseries_db;
+use sunny_db::verify::Issue;
+
+#[derive(Copy, Clone, Encode, Decode, PartialEq, Debug)]
+struct PowerValues {
+    power_pv: f64,
+    power_used: f64,
+}
+
+// 2024-06-01 00:00 UTC
+const START_TIME: u64 = 1717200000000;
+
+fn write_segment(db: &mut timeseries_db::SunnyDB<PowerValues>, from: u64, to: u64) {
+    for i in from..to {
+        db.insert_value_at_time(
+            START_TIME + i * 1000,
+            PowerValues {
+                power_pv: i as f64,
+                power_used: 1.0,
+            },
+        );
+    }
+    db.start_new_segment().unwrap();
+}
+
+#[test]
+fn verify_finds_issues() {
+    let db_path = "./tests/test-verify";
+    let partition = format!("{}/data/2024/06/01", db_path);
+    let mut db = timeseries_db::SunnyDB::<PowerValues>::new(1000, db_path, 2, 0).unwrap();
+    write_segment(&mut db, 0, 100);
+    write_segment(&mut db, 100, 200);
+    // the inverter was offline for a while
+    write_segment(&mut db, 500, 600);
+
+    let report = db.verify();
+    assert_eq!(report.segments, 3);
+    assert_eq!(report.values, 300);
+    assert_eq!(report.segments_without_checksum, 0);
+    assert_eq!(
+        report.issues,
+        vec![Issue::Gap {
+            start: START_TIME + 199000,
+            end: START_TIME + 500000
+        }]
+    );
+
+    // flip a bit in the first segment
+    let first = format!("{}/1717200000000-1717200099000", partition);
+    let mut bytes = fs::read(&first).unwrap();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 1;
+    fs::write(&first, bytes).unwrap();
+
+    // a segment whose name doesn't match its content, which also overlaps with the next one
+    fs::rename(
+        format!("{}/1717200100000-1717200199000", partition),
+        format!("{}/1717200100000-1717200550000", partition),
+    )
+    .unwrap();
+
+    // and one that ended up in the wrong partition
+    fs::create_dir_all(format!("{}/data/2024/06/02", db_path)).unwrap();
+    fs::rename(
+        format!("{}/1717200500000-1717200599000", partition),
+        format!("{}/data/2024/06/02/1717200500000-1717200599000", db_path),
+    )
+    .unwrap();
+
+    let reader = timeseries_db::SunnyDB::<PowerValues>::open_read_only(db_path).unwrap();
+    let report = reader.verify();
+    assert!(!report.is_ok());
+    let kinds: Vec<&str> = report.issues.iter().map(|i| i.kind()).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            "checksum_mismatch",
+            "range_mismatch",
+            "wrong_partition",
+            "overlap"
+        ]
+    );
+    assert!(report.issues[0]
+        .to_string()
+        .contains("1717200000000-1717200099000"));
+    assert!(Path::new(&first).exists());
+
+    std::fs::remove_dir_all(db_path).ok();
+}